@@ -0,0 +1,120 @@
+//! Benchmarks for the lexer and parser, run with `cargo bench --bench
+//! frontend`. Lexing is measured in tokens per second, and parsing in bytes
+//! of source per second, over the same inputs.
+//!
+//! Like the [VM benchmarks](vm.rs), these report the change since the last
+//! run, and can be compared against a saved baseline.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use meow::{lex, lexer::token::TokenKind, parse};
+use std::fmt::Write;
+
+const SCRIPT: &str = "
+class Point {
+    fun init(x, y) {
+        self.x = x;
+        self.y = y;
+    }
+
+    fun dist(other) {
+        let dx = self.x - other.x;
+        let dy = self.y - other.y;
+        dx * dx + dy * dy
+    }
+}
+
+fun closest(points, target) {
+    let mut best = points[0];
+    for point in points {
+        if point.dist(target) < best.dist(target) {
+            best = point;
+        }
+    }
+    best
+}
+
+let points = [Point(1, 2), Point(3, 4), Point(-1, 0)];
+print(closest(points, Point(0, 0)).x);
+";
+
+/// Return a program of about `lines` lines of functions, loops and calls,
+/// like a large module.
+fn generated(lines: usize) -> String {
+    let mut source = String::new();
+    for i in 0..lines / 10 {
+        write!(
+            source,
+            "fun step_{i}(n) {{
+    let mut total = 0;
+    for i in 0..n {{
+        if i / 2 * 2 == i {{
+            total += i * {i};
+        }} else {{
+            total -= 1;
+        }}
+    }}
+    total
+}}
+"
+        )
+        .unwrap();
+    }
+    source
+}
+
+/// Return a program that is mostly string literals.
+fn strings(lines: usize) -> String {
+    (0..lines)
+        .map(|i| {
+            format!("let s{i} = \"the quick brown fox jumps over the lazy dog {i}\" + \"!\";\n")
+        })
+        .collect()
+}
+
+/// Return a program that is mostly int and float literals.
+fn numbers(lines: usize) -> String {
+    (0..lines)
+        .map(|i| format!("let n{i} = [{i}, 1234567890, 3.14159, {i}.5, 0.000001, 987654321];\n"))
+        .collect()
+}
+
+/// Return how many tokens `source` is lexed into.
+fn count_tokens(source: &str) -> u64 {
+    let mut lexer = lex(source);
+    let mut count = 0;
+    while lexer.next_token().kind != TokenKind::Eof {
+        count += 1;
+    }
+    count
+}
+
+fn inputs() -> Vec<(&'static str, String)> {
+    vec![
+        ("script", SCRIPT.to_string()),
+        ("generated", generated(10_000)),
+        ("strings", strings(2_000)),
+        ("numbers", numbers(2_000)),
+    ]
+}
+
+fn lexer(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lexer");
+    for (name, source) in inputs() {
+        group.throughput(Throughput::Elements(count_tokens(&source)));
+        group.bench_function(name, |b| b.iter(|| count_tokens(&source)));
+    }
+    group.finish();
+}
+
+fn parser(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parser");
+    group.sample_size(20);
+    for (name, source) in inputs() {
+        group.throughput(Throughput::Bytes(source.len() as u64));
+        group.bench_function(name, |b| b.iter(|| parse(&source).unwrap()));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, lexer, parser);
+criterion_main!(benches);
@@ -0,0 +1,59 @@
+//! Benchmarks for the virtual machine, run with `cargo bench`. Each program
+//! is run on both backends.
+//!
+//! Criterion reports the change since the last run, so the effect of a
+//! change can be measured by running the benchmarks before and after it. To
+//! compare against a specific commit instead, run `cargo bench --
+//! --save-baseline before` there, and `cargo bench -- --baseline before`
+//! with the change.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use meow::{
+    compile,
+    vm::{Backend, Vm},
+};
+
+const PROGRAMS: &[(&str, &str)] = &[
+    (
+        "fib",
+        "fun fib(n) { if n < 2 { n } else { fib(n - 1) + fib(n - 2) } } fib(25);",
+    ),
+    (
+        "loop",
+        "let mut total = 0; let mut i = 0; while i < 1000000 { total += i * 2; i += 1; }",
+    ),
+    (
+        "for",
+        "fun sum(n) { let mut total = 0; for i in 0..n { total += i; } total } sum(1000000);",
+    ),
+    (
+        "lists",
+        "let mut xs = [0, 0, 0, 0]; for i in 0..200000 { xs[i - i / 4 * 4] = [i]; }",
+    ),
+];
+
+fn programs(c: &mut Criterion) {
+    for (name, source) in PROGRAMS {
+        let script = compile(source).unwrap();
+        let mut group = c.benchmark_group(*name);
+        group.sample_size(20);
+        for backend in [Backend::Stack, Backend::Register] {
+            let id = format!("{:?}", backend).to_lowercase();
+            group.bench_function(id, |b| {
+                b.iter_batched(
+                    || {
+                        let mut vm = Vm::new();
+                        vm.set_backend(backend);
+                        (vm, script.clone())
+                    },
+                    |(mut vm, script)| vm.run(script).unwrap(),
+                    BatchSize::SmallInput,
+                )
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, programs);
+criterion_main!(benches);
@@ -0,0 +1,11 @@
+//! Load arbitrary bytes as a compiled program. Corrupt files should be
+//! rejected with a `LoadError`, never a panic.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use meow::bytecode::serialize::decode;
+
+fuzz_target!(|bytes: &[u8]| {
+    let _ = decode(bytes);
+});
@@ -0,0 +1,12 @@
+//! Lex arbitrary source to the end. Malformed source should become error
+//! tokens, never a panic.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use meow::{lex, lexer::token::TokenKind};
+
+fuzz_target!(|source: &str| {
+    let mut lexer = lex(source);
+    while lexer.next_token().kind != TokenKind::Eof {}
+});
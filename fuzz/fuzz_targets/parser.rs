@@ -0,0 +1,10 @@
+//! Parse, resolve and compile arbitrary source. Malformed programs should
+//! become diagnostics, never a panic.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|source: &str| {
+    meow::check(source);
+});
@@ -0,0 +1,50 @@
+/*
+ * The C interface to the Meow interpreter, built as a shared library with
+ * `cargo build --release --features cdylib`.
+ *
+ * Every string passed in must be valid UTF-8 and end in a nul byte.
+ */
+
+#ifndef MEOW_H
+#define MEOW_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* A virtual machine, holding the globals of the programs run on it. */
+typedef struct MeowVm MeowVm;
+
+/* Create a VM, which must be freed with `meow_free`. */
+MeowVm *meow_new(void);
+
+/* Free a VM created by `meow_new`. Does nothing if `vm` is null. */
+void meow_free(MeowVm *vm);
+
+/*
+ * Run `source` on `vm`, returning 0 if it succeeds. Otherwise returns -1, and
+ * the error can be read with `meow_last_error`.
+ */
+int meow_eval(MeowVm *vm, const char *source);
+
+/*
+ * Return the error of the last call to `meow_eval` on `vm`, or null if it
+ * succeeded. The string belongs to `vm`, and is only valid until the next
+ * call to `meow_eval` or `meow_free`.
+ */
+const char *meow_last_error(const MeowVm *vm);
+
+/*
+ * Return the value of the global `name` of `vm` as it would be printed, or
+ * null if it isn't defined. The string must be freed with `meow_string_free`.
+ */
+char *meow_get_string(const MeowVm *vm, const char *name);
+
+/* Free a string returned by `meow_get_string`. Does nothing if it is null. */
+void meow_string_free(char *string);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
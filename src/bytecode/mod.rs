@@ -0,0 +1,239 @@
+//! Meow programs are executed as bytecode. A [`Chunk`] holds a sequence of
+//! encoded instructions, the constants they refer to, and the source span
+//! each instruction was compiled from, so that runtime errors can point back
+//! at the code that caused them.
+//!
+//! Instructions are encoded as an [`OpCode`] byte followed by its operands.
+//! The human readable form produced by [`Chunk::disassemble`] is only meant
+//! for debugging, while [`serialize`] defines the binary `.mwc` format
+//! compiled programs are saved in.
+
+pub mod opcode;
+pub mod optimize;
+pub mod serialize;
+pub mod spans;
+pub mod verify;
+
+pub use opcode::OpCode;
+pub use spans::SpanTable;
+
+use crate::{span::Span, value::Value};
+use std::{
+    fmt::{self, Write},
+    rc::Rc,
+};
+
+/// A compiled function. The top level of a program is compiled into a
+/// function as well, named `<script>`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub arity: u8,
+    /// Whether the function contains a `yield`. Calling a generator returns
+    /// a generator that runs the function as values are taken from it.
+    pub generator: bool,
+    pub chunk: Chunk,
+}
+
+impl Function {
+    /// Remove the span tables from this function and every function defined
+    /// in it. Errors in stripped functions can't point at the source.
+    pub fn strip_debug_info(&mut self) {
+        self.chunk.spans.strip();
+        for constant in &mut self.chunk.constants {
+            if let Value::Function(function) = constant {
+                Rc::make_mut(function).strip_debug_info();
+            }
+        }
+    }
+
+    /// Run the optimizations that aren't always run on this function and
+    /// every function defined in it. See [`optimize`] for what they do.
+    pub fn optimize(&mut self) {
+        optimize::fold_constants(&mut self.chunk);
+        for constant in &mut self.chunk.constants {
+            if let Value::Function(function) = constant {
+                Rc::make_mut(function).optimize();
+            }
+        }
+    }
+
+    /// Disassemble the function's chunk, followed by every function defined
+    /// in its constant table.
+    pub fn disassemble(&self) -> String {
+        let mut out = self.chunk.disassemble(&self.name);
+        for constant in &self.chunk.constants {
+            if let Value::Function(function) = constant {
+                out.push('\n');
+                out.push_str(&function.disassemble());
+            }
+        }
+        out
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<fun {}>", self.name)
+    }
+}
+
+/// A `Chunk` is a unit of compiled bytecode, such as the body of a function.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<Value>,
+    /// The source span every byte in `code` was compiled from.
+    pub spans: SpanTable,
+}
+
+impl Chunk {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a single byte to the chunk.
+    pub fn write(&mut self, byte: u8, span: Span) {
+        self.spans.push(self.code.len(), span);
+        self.code.push(byte);
+    }
+
+    /// Append an instruction without operands.
+    pub fn write_op(&mut self, op: OpCode, span: Span) {
+        self.write(op as u8, span);
+    }
+
+    /// Append a big endian `u16` operand.
+    pub fn write_u16(&mut self, value: u16, span: Span) {
+        for byte in value.to_be_bytes() {
+            self.write(byte, span);
+        }
+    }
+
+    /// Add a value to the constant table, returning its index. Returns `None`
+    /// if the table is full. Equal constants are only stored once.
+    pub fn add_constant(&mut self, value: Value) -> Option<u16> {
+        let same = |constant: &Value| match (constant, &value) {
+            // Compare bits so that `0.0` and `-0.0` stay distinct
+            (Value::Float(a), Value::Float(b)) => a.to_bits() == b.to_bits(),
+            (a, b) => a == b,
+        };
+        if let Some(index) = self.constants.iter().position(same) {
+            return Some(index as u16);
+        }
+
+        let index = u16::try_from(self.constants.len()).ok()?;
+        self.constants.push(value);
+        Some(index)
+    }
+
+    /// Append an instruction loading `value`, returning `None` if the
+    /// constant table is full.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use meow::{bytecode::{Chunk, OpCode}, span::Span, value::Value};
+    ///
+    /// let mut chunk = Chunk::new();
+    /// chunk.write_constant(Value::Int(42), Span::default()).unwrap();
+    /// chunk.write_op(OpCode::Return, Span::default());
+    /// assert_eq!(chunk.code, vec![OpCode::Constant as u8, 0, 0, OpCode::Return as u8]);
+    /// ```
+    pub fn write_constant(&mut self, value: Value, span: Span) -> Option<u16> {
+        let index = self.add_constant(value)?;
+        self.write_op(OpCode::Constant, span);
+        self.write_u16(index, span);
+        Some(index)
+    }
+
+    /// Read the big endian `u16` at `offset`.
+    pub fn read_u16(&self, offset: usize) -> u16 {
+        u16::from_be_bytes([self.code[offset], self.code[offset + 1]])
+    }
+
+    /// Return the source span of the byte at `offset`, or `None` if debug
+    /// information was stripped.
+    pub fn span_at(&self, offset: usize) -> Option<Span> {
+        self.spans.get(offset)
+    }
+
+    /// Render every instruction in the chunk, one per line, under a header
+    /// containing `name`.
+    pub fn disassemble(&self, name: &str) -> String {
+        let mut out = format!("== {} ==\n", name);
+        let mut offset = 0;
+        while offset < self.code.len() {
+            let (line, next) = self.disassemble_instruction(offset);
+            out.push_str(&line);
+            out.push('\n');
+            offset = next;
+        }
+        out
+    }
+
+    /// Render the instruction at `offset`, returning it along with the offset
+    /// of the next instruction.
+    pub fn disassemble_instruction(&self, offset: usize) -> (String, usize) {
+        let mut out = format!("{:04} ", offset);
+
+        let line = self.span_at(offset).map(|span| span.line);
+        match line {
+            None => out.push_str("   ? "),
+            Some(_) if offset > 0 && self.span_at(offset - 1).map(|s| s.line) == line => {
+                out.push_str("   | ")
+            }
+            Some(line) => {
+                let _ = write!(out, "{:4} ", line);
+            }
+        }
+
+        let op = match OpCode::from_byte(self.code[offset]) {
+            Some(op) => op,
+            None => {
+                let _ = write!(out, "<invalid opcode {}>", self.code[offset]);
+                return (out, offset + 1);
+            }
+        };
+
+        match op.operand_len() {
+            _ if op.is_jump() => {
+                let jump = self.read_u16(offset + 1) as usize;
+                let next = offset + 3;
+                let target = if op == OpCode::Loop {
+                    next.wrapping_sub(jump)
+                } else {
+                    next + jump
+                };
+                let _ = write!(out, "{:<16} {:4} -> {}", op.to_string(), offset, target);
+            }
+            4 => {
+                let index = self.read_u16(offset + 1);
+                let _ = write!(
+                    out,
+                    "{:<16} {:4} '{}' {}",
+                    op.to_string(),
+                    index,
+                    self.constants[index as usize],
+                    self.read_u16(offset + 3)
+                );
+            }
+            2 => {
+                let index = self.read_u16(offset + 1);
+                let _ = write!(
+                    out,
+                    "{:<16} {:4} '{}'",
+                    op.to_string(),
+                    index,
+                    self.constants[index as usize]
+                );
+            }
+            1 => {
+                let _ = write!(out, "{:<16} {:4}", op.to_string(), self.code[offset + 1]);
+            }
+            _ => out.push_str(&op.to_string()),
+        }
+
+        (out, offset + 1 + op.operand_len())
+    }
+}
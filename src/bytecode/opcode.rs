@@ -0,0 +1,289 @@
+use std::fmt;
+
+/// The `OpCode` enum contains every instruction of the Meow virtual machine.
+/// Instructions are stored in a [`Chunk`](super::Chunk) as a single byte,
+/// followed by their operands, if any. Multi-byte operands are big endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum OpCode {
+    /// Push the constant at the `u16` index operand.
+    Constant,
+    Unit,
+    True,
+    False,
+    Pop,
+    /// Pop the `u8` operand number of values.
+    PopN,
+    /// Push a copy of the top of the stack.
+    Dup,
+
+    // variables
+    /// Read the local in the `u8` slot operand of the current call frame.
+    GetLocal,
+    SetLocal,
+    /// Define the global named by the `u16` constant index operand.
+    DefineGlobal,
+    GetGlobal,
+    SetGlobal,
+
+    // arithmetic
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    Negate,
+
+    // comparison
+    Not,
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+
+    // control flow
+    /// Jump forward by the `u16` operand.
+    Jump,
+    /// Jump forward by the `u16` operand if the top of the stack is false,
+    /// without popping it.
+    JumpIfFalse,
+    /// Jump backward by the `u16` operand.
+    Loop,
+
+    // lists
+    /// Pop the `u8` operand number of values into a new list.
+    BuildList,
+    /// Pop an index and a list, and push the item at that index.
+    GetIndex,
+    /// Pop a value, an index and a list, store the value at that index, and
+    /// push it back.
+    SetIndex,
+
+    // classes
+    /// Pop the `u8` operand number of methods and the class name below them,
+    /// and push a new class.
+    Class,
+    /// Pop an instance and push its field, or method, named by the `u16`
+    /// constant index operand.
+    GetField,
+    /// Pop a value and an instance, store the value in the field named by
+    /// the `u16` constant index operand, and push it back.
+    SetField,
+
+    /// Call the value below the `u8` operand number of arguments.
+    Call,
+    Return,
+
+    // tasks
+    /// Pop the `u8` operand number of arguments and the value below them,
+    /// and start a new task calling it.
+    Spawn,
+
+    // generators
+    /// Resume the iterator in the local slot selected by the `u8` operand.
+    /// Pushes the next value and true, or unit and false once the iterator
+    /// is exhausted. A range is replaced by the rest of it in its slot.
+    Next,
+    /// Pop a value and suspend the running generator, passing the value to
+    /// the instruction that resumed it.
+    Yield,
+
+    /// Pop a value and push it converted to the
+    /// [`CastType`](crate::value::CastType) selected by the `u8` operand.
+    Cast,
+    /// Pop the `u8` operand number of values and the template string below
+    /// them, and push the template formatted with the values, as `format`
+    /// does. Interpolated strings are compiled to it.
+    Format,
+    /// Pop the end and start of a range and the list or string below them,
+    /// and push the items between them. The range includes its end if the
+    /// `u8` operand is 1.
+    Slice,
+    /// Pop the end and start of a range, and push the range. It includes
+    /// its end if the `u8` operand is 1.
+    Range,
+    /// Fail unless the end and start of a range on top of the stack are
+    /// ints, leaving them in place.
+    RangeBounds,
+
+    // match
+    /// Pop a range and the value below it, and push whether the value is an
+    /// int in the range, or a float equal to one.
+    InRange,
+    /// Pop a value, and run the entry of the jump table following this
+    /// instruction at the value minus the int constant at the first `u16`
+    /// operand. The table has the second `u16` operand number of `Jump`
+    /// entries, and one more for values outside of it.
+    Switch,
+    /// Pop a value and fail, since no arm of a match matched it.
+    Unmatched,
+}
+
+impl OpCode {
+    /// Every opcode, ordered by its byte value.
+    pub const ALL: [OpCode; 47] = [
+        OpCode::Constant,
+        OpCode::Unit,
+        OpCode::True,
+        OpCode::False,
+        OpCode::Pop,
+        OpCode::PopN,
+        OpCode::Dup,
+        OpCode::GetLocal,
+        OpCode::SetLocal,
+        OpCode::DefineGlobal,
+        OpCode::GetGlobal,
+        OpCode::SetGlobal,
+        OpCode::Add,
+        OpCode::Subtract,
+        OpCode::Multiply,
+        OpCode::Divide,
+        OpCode::Remainder,
+        OpCode::Negate,
+        OpCode::Not,
+        OpCode::Equal,
+        OpCode::NotEqual,
+        OpCode::Greater,
+        OpCode::GreaterEqual,
+        OpCode::Less,
+        OpCode::LessEqual,
+        OpCode::Jump,
+        OpCode::JumpIfFalse,
+        OpCode::Loop,
+        OpCode::BuildList,
+        OpCode::GetIndex,
+        OpCode::SetIndex,
+        OpCode::Class,
+        OpCode::GetField,
+        OpCode::SetField,
+        OpCode::Call,
+        OpCode::Return,
+        OpCode::Spawn,
+        OpCode::Next,
+        OpCode::Yield,
+        OpCode::Cast,
+        OpCode::Format,
+        OpCode::Slice,
+        OpCode::Range,
+        OpCode::RangeBounds,
+        OpCode::InRange,
+        OpCode::Switch,
+        OpCode::Unmatched,
+    ];
+
+    /// Decode a byte into an opcode, returning `None` for bytes that don't
+    /// correspond to any instruction.
+    #[inline]
+    pub fn from_byte(byte: u8) -> Option<OpCode> {
+        Self::ALL.get(byte as usize).copied()
+    }
+
+    /// Returns true for instructions whose operand is a jump offset.
+    pub fn is_jump(self) -> bool {
+        matches!(self, OpCode::Jump | OpCode::JumpIfFalse | OpCode::Loop)
+    }
+
+    /// Returns true for instructions whose operand is an index into the
+    /// constant table.
+    pub fn has_constant(self) -> bool {
+        matches!(
+            self,
+            OpCode::Constant
+                | OpCode::DefineGlobal
+                | OpCode::GetGlobal
+                | OpCode::SetGlobal
+                | OpCode::GetField
+                | OpCode::SetField
+                | OpCode::Switch
+        )
+    }
+
+    /// Return the change in the depth of the stack caused by running the
+    /// instruction, whose first operand byte is `operand`. Instructions that
+    /// leave the frame count the value they pop.
+    pub fn stack_effect(self, operand: u8) -> i32 {
+        let operand = operand as i32;
+        match self {
+            OpCode::Constant
+            | OpCode::Unit
+            | OpCode::True
+            | OpCode::False
+            | OpCode::Dup
+            | OpCode::GetLocal
+            | OpCode::GetGlobal => 1,
+            OpCode::Pop | OpCode::DefineGlobal => -1,
+            OpCode::PopN => -operand,
+            OpCode::SetLocal
+            | OpCode::SetGlobal
+            | OpCode::Negate
+            | OpCode::Not
+            | OpCode::Cast
+            | OpCode::RangeBounds
+            | OpCode::Jump
+            | OpCode::JumpIfFalse
+            | OpCode::Loop
+            | OpCode::GetField => 0,
+            OpCode::Add
+            | OpCode::Subtract
+            | OpCode::Multiply
+            | OpCode::Divide
+            | OpCode::Remainder
+            | OpCode::Equal
+            | OpCode::NotEqual
+            | OpCode::Greater
+            | OpCode::GreaterEqual
+            | OpCode::Less
+            | OpCode::LessEqual
+            | OpCode::GetIndex
+            | OpCode::SetField
+            | OpCode::Range
+            | OpCode::InRange
+            | OpCode::Switch
+            | OpCode::Yield
+            | OpCode::Return
+            | OpCode::Unmatched => -1,
+            OpCode::SetIndex | OpCode::Slice => -2,
+            OpCode::BuildList => 1 - operand,
+            OpCode::Class | OpCode::Call | OpCode::Format => -operand,
+            OpCode::Spawn => -operand - 1,
+            OpCode::Next => 2,
+        }
+    }
+
+    /// Return the number of operand bytes following the opcode.
+    pub fn operand_len(self) -> usize {
+        match self {
+            OpCode::Switch => 4,
+            OpCode::Constant
+            | OpCode::DefineGlobal
+            | OpCode::GetGlobal
+            | OpCode::SetGlobal
+            | OpCode::GetField
+            | OpCode::SetField
+            | OpCode::Jump
+            | OpCode::JumpIfFalse
+            | OpCode::Loop => 2,
+            OpCode::PopN
+            | OpCode::GetLocal
+            | OpCode::SetLocal
+            | OpCode::BuildList
+            | OpCode::Class
+            | OpCode::Call
+            | OpCode::Spawn
+            | OpCode::Next
+            | OpCode::Cast
+            | OpCode::Format
+            | OpCode::Slice
+            | OpCode::Range => 1,
+            _ => 0,
+        }
+    }
+}
+
+impl fmt::Display for OpCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
@@ -0,0 +1,300 @@
+//! Passes run over a chunk once it has been fully compiled. Dead code is
+//! always eliminated, while constants are only folded when optimizations are
+//! asked for, with [`Function::optimize`](super::Function::optimize).
+
+use super::{verify::switch_table, Chunk, OpCode, SpanTable};
+use crate::value::Value;
+
+/// Remove every instruction that can never execute, such as code following
+/// a `return`, along with constants that are no longer used. Jump offsets,
+/// constant indices and the span table are rewritten to match.
+///
+/// Reachability is found by following every path through the chunk from its
+/// first instruction, so a jump's target is always kept if the jump is.
+///
+/// # Examples
+///
+/// ```
+/// use meow::{bytecode::{optimize::eliminate_dead_code, Chunk, OpCode}, span::Span, value::Value};
+///
+/// let mut chunk = Chunk::new();
+/// chunk.write_op(OpCode::Unit, Span::default());
+/// chunk.write_op(OpCode::Return, Span::default());
+/// chunk.write_constant(Value::Int(1), Span::default());
+/// chunk.write_op(OpCode::Return, Span::default());
+///
+/// eliminate_dead_code(&mut chunk);
+/// assert_eq!(chunk.code, [OpCode::Unit as u8, OpCode::Return as u8]);
+/// assert!(chunk.constants.is_empty());
+/// ```
+pub fn eliminate_dead_code(chunk: &mut Chunk) {
+    let reachable = reachable(chunk);
+    if reachable.iter().all(|&reachable| reachable) {
+        return remove_unused_constants(chunk);
+    }
+    remove_instructions(chunk, &reachable);
+}
+
+/// Evaluate arithmetic on constant numbers ahead of time, so that `2 * 3 +
+/// 1` is compiled as if it were `7`. Arithmetic that would fail or overflow
+/// is left to happen when the program runs, so that it fails, or gives a big
+/// int, just as it would have.
+///
+/// An instruction that a jump lands on is never folded into the one before
+/// it, since the value it works on may come from somewhere else.
+///
+/// # Examples
+///
+/// ```
+/// use meow::{bytecode::{optimize::fold_constants, Chunk, OpCode}, span::Span, value::Value};
+///
+/// let mut chunk = Chunk::new();
+/// chunk.write_constant(Value::Int(2), Span::default());
+/// chunk.write_constant(Value::Int(3), Span::default());
+/// chunk.write_op(OpCode::Multiply, Span::default());
+/// chunk.write_op(OpCode::Negate, Span::default());
+/// chunk.write_op(OpCode::Return, Span::default());
+///
+/// fold_constants(&mut chunk);
+/// assert_eq!(chunk.code, [OpCode::Constant as u8, 0, 0, OpCode::Return as u8]);
+/// assert_eq!(chunk.constants, [Value::Int(-6)]);
+/// ```
+pub fn fold_constants(chunk: &mut Chunk) {
+    // Folding can leave constants next to each other that weren't before,
+    // so this repeats until there is nothing left to fold
+    loop {
+        let targets = jump_targets(chunk);
+        let mut ops = Vec::new();
+        let mut offset = 0;
+        while offset < chunk.code.len() {
+            let op = OpCode::from_byte(chunk.code[offset]).expect("invalid opcode");
+            ops.push((offset, op));
+            offset += 1 + op.operand_len();
+        }
+
+        let mut keep = vec![true; chunk.code.len()];
+        let mut folded = false;
+        let mut index = 0;
+        while index < ops.len() {
+            let window = &ops[index..ops.len().min(index + 3)];
+            let constant = |offset: usize| &chunk.constants[chunk.read_u16(offset + 1) as usize];
+            let (value, len) = match window {
+                [(first, OpCode::Constant), (second, OpCode::Constant), (last, op), ..]
+                    if !targets[*second] && !targets[*last] =>
+                {
+                    (binary(*op, constant(*first), constant(*second)), 3)
+                }
+                [(first, OpCode::Constant), (last, OpCode::Negate), ..] if !targets[*last] => {
+                    (negate(constant(*first)), 2)
+                }
+                _ => (None, 1),
+            };
+            let index_of = value.and_then(|value| chunk.add_constant(value));
+            match index_of {
+                Some(constant) => {
+                    let first = ops[index].0;
+                    chunk.code[first + 1..first + 3].copy_from_slice(&constant.to_be_bytes());
+                    for &(offset, _) in &ops[index + 1..index + len] {
+                        keep[offset] = false;
+                    }
+                    folded = true;
+                    index += len;
+                }
+                None => index += 1,
+            }
+        }
+
+        if !folded {
+            return remove_unused_constants(chunk);
+        }
+        remove_instructions(chunk, &keep);
+    }
+}
+
+/// Apply the arithmetic operator `op` to two numbers of the same type, or
+/// return `None` if it would fail or overflow.
+fn binary(op: OpCode, left: &Value, right: &Value) -> Option<Value> {
+    Some(match (left, right) {
+        (&Value::Int(a), &Value::Int(b)) => Value::Int(match op {
+            OpCode::Add => a.checked_add(b)?,
+            OpCode::Subtract => a.checked_sub(b)?,
+            OpCode::Multiply => a.checked_mul(b)?,
+            OpCode::Divide => a.checked_div(b)?,
+            OpCode::Remainder => a.checked_rem(b)?,
+            _ => return None,
+        }),
+        (&Value::Float(a), &Value::Float(b)) => Value::Float(match op {
+            OpCode::Add => a + b,
+            OpCode::Subtract => a - b,
+            OpCode::Multiply => a * b,
+            OpCode::Divide => a / b,
+            OpCode::Remainder => a % b,
+            _ => return None,
+        }),
+        _ => return None,
+    })
+}
+
+/// Negate a number, or return `None` if it would overflow.
+fn negate(value: &Value) -> Option<Value> {
+    match *value {
+        Value::Int(value) => value.checked_neg().map(Value::Int),
+        Value::Float(value) => Some(Value::Float(-value)),
+        _ => None,
+    }
+}
+
+/// Remove every instruction whose offset isn't marked in `keep`, rewriting
+/// jump offsets and the span table to match. No jump may land on a removed
+/// instruction.
+fn remove_instructions(chunk: &mut Chunk, keep: &[bool]) {
+    // Map every old offset to its offset once instructions are removed.
+    // Only offsets of kept instructions are meaningful.
+    let mut offsets = vec![0; chunk.code.len() + 1];
+    let mut len = 0;
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        let size = instruction_len(chunk, offset);
+        offsets[offset] = len;
+        if keep[offset] {
+            len += size;
+        }
+        offset += size;
+    }
+    offsets[chunk.code.len()] = len;
+
+    let mut code = Vec::with_capacity(len);
+    let mut spans = SpanTable::new();
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        let size = instruction_len(chunk, offset);
+        if keep[offset] {
+            let op = OpCode::from_byte(chunk.code[offset]).expect("invalid opcode");
+            if let Some(span) = chunk.spans.get(offset) {
+                spans.push(code.len(), span);
+            }
+
+            if op.is_jump() {
+                let target = jump_target(chunk, op, offset);
+                let start = offsets[offset] + 3;
+                let jump = if op == OpCode::Loop {
+                    start - offsets[target]
+                } else {
+                    offsets[target] - start
+                };
+                code.push(op as u8);
+                code.extend_from_slice(&(jump as u16).to_be_bytes());
+            } else {
+                code.extend_from_slice(&chunk.code[offset..offset + size]);
+            }
+        }
+        offset += size;
+    }
+
+    chunk.code = code;
+    if !chunk.spans.is_empty() {
+        chunk.spans = spans;
+    }
+    remove_unused_constants(chunk);
+}
+
+/// Return, for each byte of the chunk, whether it starts an instruction that
+/// can be executed.
+fn reachable(chunk: &Chunk) -> Vec<bool> {
+    let mut reachable = vec![false; chunk.code.len()];
+    let mut pending = vec![0];
+
+    while let Some(mut offset) = pending.pop() {
+        while offset < chunk.code.len() && !reachable[offset] {
+            reachable[offset] = true;
+            let op = OpCode::from_byte(chunk.code[offset]).expect("invalid opcode");
+            match op {
+                OpCode::Return | OpCode::Unmatched => break,
+                OpCode::Switch => {
+                    let entries = switch_table(chunk, offset).expect("switch table out of bounds");
+                    pending.extend(entries);
+                    break;
+                }
+                OpCode::Jump | OpCode::Loop => {
+                    offset = jump_target(chunk, op, offset);
+                    continue;
+                }
+                OpCode::JumpIfFalse => pending.push(jump_target(chunk, op, offset)),
+                _ => {}
+            }
+            offset += 1 + op.operand_len();
+        }
+    }
+
+    reachable
+}
+
+/// Return, for each byte of the chunk, whether a jump lands on it.
+fn jump_targets(chunk: &Chunk) -> Vec<bool> {
+    let mut targets = vec![false; chunk.code.len() + 1];
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        let op = OpCode::from_byte(chunk.code[offset]).expect("invalid opcode");
+        if op.is_jump() {
+            targets[jump_target(chunk, op, offset)] = true;
+        }
+        offset += 1 + op.operand_len();
+    }
+    targets
+}
+
+/// Drop constants no instruction refers to, renumbering the rest.
+fn remove_unused_constants(chunk: &mut Chunk) {
+    let operands = constant_operands(chunk);
+    let mut used = vec![false; chunk.constants.len()];
+    for &operand in &operands {
+        used[chunk.read_u16(operand) as usize] = true;
+    }
+    if used.iter().all(|&used| used) {
+        return;
+    }
+
+    let mut indices = vec![0; chunk.constants.len()];
+    let mut constants = Vec::new();
+    for (index, constant) in chunk.constants.drain(..).enumerate() {
+        if used[index] {
+            indices[index] = constants.len() as u16;
+            constants.push(constant);
+        }
+    }
+    chunk.constants = constants;
+
+    for operand in operands {
+        let index = indices[chunk.read_u16(operand) as usize];
+        chunk.code[operand..operand + 2].copy_from_slice(&index.to_be_bytes());
+    }
+}
+
+/// Return the offset of every operand holding a constant index.
+fn constant_operands(chunk: &Chunk) -> Vec<usize> {
+    let mut operands = Vec::new();
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        let op = OpCode::from_byte(chunk.code[offset]).expect("invalid opcode");
+        if op.has_constant() {
+            operands.push(offset + 1);
+        }
+        offset += 1 + op.operand_len();
+    }
+    operands
+}
+
+fn instruction_len(chunk: &Chunk, offset: usize) -> usize {
+    let op = OpCode::from_byte(chunk.code[offset]).expect("invalid opcode");
+    1 + op.operand_len()
+}
+
+/// Return the offset the jump instruction at `offset` lands on.
+fn jump_target(chunk: &Chunk, op: OpCode, offset: usize) -> usize {
+    let jump = chunk.read_u16(offset + 1) as usize;
+    if op == OpCode::Loop {
+        offset + 3 - jump
+    } else {
+        offset + 3 + jump
+    }
+}
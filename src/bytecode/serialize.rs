@@ -0,0 +1,304 @@
+//! Compiled functions can be saved to `.mwc` files, so that programs can be
+//! run again without lexing, parsing, or compiling them.
+//!
+//! A file starts with the [`MAGIC`] bytes and the format [`VERSION`],
+//! followed by the top-level function. Functions are encoded as their name,
+//! arity, whether they are generators, and chunk, with functions in the constant table encoded
+//! recursively, and span tables as their runs. All integers are big endian, and strings and lists are
+//! prefixed with their length as a `u32`.
+
+use super::{
+    spans::{SpanRun, SpanTable},
+    verify::verify,
+    Chunk, Function,
+};
+use crate::{errors::LoadError, span::Span, value::Value, vm::native::Module};
+use std::rc::Rc;
+#[cfg(feature = "fs")]
+use std::{fs, path::Path};
+
+/// The bytes every `.mwc` file starts with.
+pub const MAGIC: &[u8; 4] = b"MEOW";
+
+/// The version of the format, which also covers the numbering of opcodes.
+/// Files with any other version are rejected.
+pub const VERSION: u16 = 15;
+
+// Tags identifying the type of each constant
+const TAG_UNIT: u8 = 0;
+const TAG_BOOL: u8 = 1;
+const TAG_INT: u8 = 2;
+const TAG_FLOAT: u8 = 3;
+const TAG_CHAR: u8 = 4;
+const TAG_STR: u8 = 5;
+const TAG_FUNCTION: u8 = 6;
+const TAG_MODULE: u8 = 7;
+
+/// How deeply functions can be nested in each other's constants. The parser
+/// can't nest them any deeper, and decoding, verifying and dropping them
+/// recurse once per level.
+const MAX_DEPTH: usize = crate::parser::MAX_NESTING;
+
+/// Encode `function` into the `.mwc` format.
+///
+/// # Examples
+///
+/// ```
+/// use meow::{bytecode::serialize::{decode, encode}, compile};
+///
+/// let script = compile("let x = 1 + 2;").unwrap();
+/// assert_eq!(decode(&encode(&script)).unwrap(), script);
+/// ```
+pub fn encode(function: &Function) -> Vec<u8> {
+    let mut out = MAGIC.to_vec();
+    out.extend_from_slice(&VERSION.to_be_bytes());
+    encode_function(&mut out, function);
+    out
+}
+
+/// Decode a function from the `.mwc` format. The function is
+/// [verified](super::verify::verify) before it is returned, so it can't
+/// crash the VM.
+pub fn decode(bytes: &[u8]) -> Result<Function, LoadError> {
+    let mut reader = Reader::new(bytes);
+
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err(LoadError::BadMagic);
+    }
+    let version = reader.u16()?;
+    if version != VERSION {
+        return Err(LoadError::UnsupportedVersion(version));
+    }
+
+    let function = reader.function()?;
+    if !reader.is_done() {
+        return Err(LoadError::TrailingBytes);
+    }
+    // The program is run without arguments, and the verifier assumes the
+    // function's arguments are on the stack
+    if function.arity != 0 {
+        return Err(LoadError::Malformed("the program takes arguments"));
+    }
+    if function.generator {
+        return Err(LoadError::Malformed("the program is a generator"));
+    }
+    verify(&function)?;
+    Ok(function)
+}
+
+/// Write `function` to the file at `path` in the `.mwc` format.
+#[cfg(feature = "fs")]
+pub fn save(function: &Function, path: impl AsRef<Path>) -> Result<(), LoadError> {
+    Ok(fs::write(path, encode(function))?)
+}
+
+/// Read a function from the `.mwc` file at `path`.
+#[cfg(feature = "fs")]
+pub fn load(path: impl AsRef<Path>) -> Result<Function, LoadError> {
+    decode(&fs::read(path)?)
+}
+
+pub(crate) fn encode_u32(out: &mut Vec<u8>, value: usize) {
+    out.extend_from_slice(&(value as u32).to_be_bytes());
+}
+
+pub(crate) fn encode_str(out: &mut Vec<u8>, value: &str) {
+    encode_u32(out, value.len());
+    out.extend_from_slice(value.as_bytes());
+}
+
+pub(crate) fn encode_function(out: &mut Vec<u8>, function: &Function) {
+    encode_str(out, &function.name);
+    out.push(function.arity);
+    out.push(function.generator as u8);
+    encode_chunk(out, &function.chunk);
+}
+
+fn encode_chunk(out: &mut Vec<u8>, chunk: &Chunk) {
+    encode_u32(out, chunk.code.len());
+    out.extend_from_slice(&chunk.code);
+
+    encode_u32(out, chunk.spans.runs().len());
+    for run in chunk.spans.runs() {
+        for value in [run.offset, run.span.line, run.span.column, run.span.length] {
+            out.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+
+    encode_u32(out, chunk.constants.len());
+    for constant in &chunk.constants {
+        match constant {
+            Value::Unit => out.push(TAG_UNIT),
+            Value::Bool(value) => {
+                out.push(TAG_BOOL);
+                out.push(*value as u8);
+            }
+            Value::Int(value) => {
+                out.push(TAG_INT);
+                out.extend_from_slice(&value.to_be_bytes());
+            }
+            Value::Float(value) => {
+                out.push(TAG_FLOAT);
+                out.extend_from_slice(&value.to_bits().to_be_bytes());
+            }
+            Value::Char(value) => {
+                out.push(TAG_CHAR);
+                out.extend_from_slice(&(*value as u32).to_be_bytes());
+            }
+            Value::Str(value) => {
+                out.push(TAG_STR);
+                encode_str(out, value);
+            }
+            Value::Function(function) => {
+                out.push(TAG_FUNCTION);
+                encode_function(out, function);
+            }
+            Value::Module(module) => {
+                out.push(TAG_MODULE);
+                encode_str(out, module.name());
+            }
+            Value::BigInt(_)
+            | Value::Class(_)
+            | Value::List(_)
+            | Value::Instance(_)
+            | Value::BoundMethod(_)
+            | Value::Native(_)
+            | Value::Host(_)
+            | Value::Channel(_)
+            | Value::Generator(_)
+            | Value::Range(_) => unreachable!("runtime objects can't be constants"),
+        }
+    }
+}
+
+/// A cursor over the bytes being decoded.
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    /// How many functions the one being read is nested in.
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            offset: 0,
+            depth: 0,
+        }
+    }
+
+    /// Returns true once every byte has been read.
+    pub(crate) fn is_done(&self) -> bool {
+        self.offset == self.bytes.len()
+    }
+
+    pub(crate) fn take(&mut self, len: usize) -> Result<&'a [u8], LoadError> {
+        let end = self
+            .offset
+            .checked_add(len)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or(LoadError::UnexpectedEof)?;
+        let bytes = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    pub(crate) fn array<const N: usize>(&mut self) -> Result<[u8; N], LoadError> {
+        Ok(self
+            .take(N)?
+            .try_into()
+            .expect("took the wrong number of bytes"))
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, LoadError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, LoadError> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, LoadError> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+
+    pub(crate) fn len(&mut self) -> Result<usize, LoadError> {
+        Ok(self.u32()? as usize)
+    }
+
+    pub(crate) fn string(&mut self) -> Result<String, LoadError> {
+        let len = self.len()?;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| LoadError::InvalidUtf8)
+    }
+
+    pub(crate) fn function(&mut self) -> Result<Function, LoadError> {
+        if self.depth == MAX_DEPTH {
+            return Err(LoadError::Malformed("functions are nested too deeply"));
+        }
+        self.depth += 1;
+        let function = Function {
+            name: self.string()?,
+            arity: self.u8()?,
+            generator: self.u8()? != 0,
+            chunk: self.chunk()?,
+        };
+        self.depth -= 1;
+        Ok(function)
+    }
+
+    fn chunk(&mut self) -> Result<Chunk, LoadError> {
+        let len = self.len()?;
+        let code = self.take(len)?.to_vec();
+
+        let len = self.len()?;
+        let mut runs = Vec::new();
+        for _ in 0..len {
+            let offset = self.u32()?;
+            if offset as usize >= code.len() {
+                return Err(LoadError::Malformed("span table points outside the code"));
+            }
+            let span = Span::new(self.u32()?, self.u32()?, self.u32()?);
+            runs.push(SpanRun { offset, span });
+        }
+        let spans =
+            SpanTable::from_runs(runs).ok_or(LoadError::Malformed("span table isn't sorted"))?;
+
+        let len = self.len()?;
+        let mut constants = Vec::new();
+        for _ in 0..len {
+            constants.push(self.constant()?);
+        }
+
+        Ok(Chunk {
+            code,
+            constants,
+            spans,
+        })
+    }
+
+    fn constant(&mut self) -> Result<Value, LoadError> {
+        Ok(match self.u8()? {
+            TAG_UNIT => Value::Unit,
+            TAG_BOOL => Value::Bool(self.u8()? != 0),
+            TAG_INT => Value::Int(i64::from_be_bytes(self.array()?)),
+            TAG_FLOAT => Value::Float(f64::from_bits(u64::from_be_bytes(self.array()?))),
+            TAG_CHAR => Value::Char(
+                char::from_u32(self.u32()?).ok_or(LoadError::Malformed("invalid char constant"))?,
+            ),
+            TAG_STR => Value::Str(Rc::from(self.string()?)),
+            TAG_FUNCTION => Value::Function(Rc::new(self.function()?)),
+            TAG_MODULE => Value::Module(self.module()?),
+            tag => return Err(LoadError::UnknownConstant(tag)),
+        })
+    }
+
+    pub(crate) fn module(&mut self) -> Result<Module, LoadError> {
+        let name = self.string()?;
+        let module = Module::ALL
+            .iter()
+            .copied()
+            .find(|module| module.name() == name);
+        module.ok_or(LoadError::Malformed("unknown module"))
+    }
+}
@@ -0,0 +1,70 @@
+use crate::span::Span;
+
+/// A run of bytecode compiled from the same source span, starting at
+/// `offset` and lasting until the next run starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpanRun {
+    pub offset: u32,
+    pub span: Span,
+}
+
+/// The `SpanTable` maps bytecode offsets back to the source span they were
+/// compiled from. Consecutive instructions usually share a span, so it is
+/// stored run-length encoded, only recording the offsets at which the span
+/// changes.
+///
+/// The table is debug information, and can be stripped to make compiled
+/// programs smaller, at the cost of errors no longer pointing at the source.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SpanTable {
+    runs: Vec<SpanRun>,
+}
+
+impl SpanTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a table from existing runs, returning `None` if their offsets
+    /// aren't strictly increasing.
+    pub fn from_runs(runs: Vec<SpanRun>) -> Option<Self> {
+        if runs.windows(2).any(|pair| pair[0].offset >= pair[1].offset) {
+            return None;
+        }
+        Some(Self { runs })
+    }
+
+    /// Record that the byte at `offset` was compiled from `span`. Offsets
+    /// must be recorded in increasing order.
+    pub fn push(&mut self, offset: usize, span: Span) {
+        if self.runs.last().map(|run| run.span) == Some(span) {
+            return;
+        }
+        self.runs.push(SpanRun {
+            offset: offset as u32,
+            span,
+        });
+    }
+
+    /// Return the span the byte at `offset` was compiled from, or `None` if
+    /// debug information was stripped.
+    pub fn get(&self, offset: usize) -> Option<Span> {
+        let index = self
+            .runs
+            .partition_point(|run| run.offset as usize <= offset);
+        index.checked_sub(1).map(|index| self.runs[index].span)
+    }
+
+    pub fn runs(&self) -> &[SpanRun] {
+        &self.runs
+    }
+
+    /// Remove every span from the table.
+    pub fn strip(&mut self) {
+        self.runs = Vec::new();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.runs.is_empty()
+    }
+}
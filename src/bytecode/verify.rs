@@ -0,0 +1,190 @@
+//! The VM trusts the bytecode it runs: operands are used as indices without
+//! bounds checks, and values are popped without checking the stack is deep
+//! enough. The compiler never breaks these rules, but bytecode loaded from
+//! a `.mwc` file could have been corrupted or written by hand, so it is
+//! checked by [`verify`] first.
+//!
+//! Every instruction pushes and pops a fixed number of values, so the depth
+//! of the stack before each instruction can be found without running it.
+//! The same analysis lets the [`register`](crate::vm::register) backend
+//! turn stack positions into registers.
+
+use super::{Chunk, Function, OpCode};
+use crate::{
+    errors::VerifyError,
+    value::{CastType, Value},
+};
+
+/// Check that `function` and every function defined in it can run without
+/// crashing the VM. This means that:
+///
+/// - every instruction is a valid opcode with all of its operands,
+/// - constant indices are in bounds, and names are strings,
+/// - jumps land on an instruction within the chunk, and switch tables are
+///   made of jumps,
+/// - the stack depth before an instruction is the same along every path
+///   reaching it, and nothing pops more values than there are,
+/// - locals refer to slots that exist,
+/// - casts are to types that exist,
+/// - only generators yield, and
+/// - execution can't run past the end of the chunk.
+///
+/// # Examples
+///
+/// ```
+/// use meow::{bytecode::{verify::verify, OpCode}, compile};
+///
+/// let mut script = compile("let x = 1;").unwrap();
+/// assert!(verify(&script).is_ok());
+///
+/// script.chunk.code.insert(0, OpCode::Pop as u8);
+/// assert_eq!(verify(&script).unwrap_err().reason, "stack underflow");
+/// ```
+pub fn verify(function: &Function) -> Result<(), VerifyError> {
+    depths(function)?;
+    for constant in &function.chunk.constants {
+        if let Value::Function(function) = constant {
+            verify(function)?;
+        }
+    }
+    Ok(())
+}
+
+/// Find the stack depth before each instruction of `function`, counting the
+/// callee and its arguments, and the greatest depth reached. Unreachable
+/// instructions have no depth. Fails if the function doesn't pass
+/// [`verify`], ignoring the functions defined in it.
+pub(crate) fn depths(function: &Function) -> Result<(Vec<Option<u16>>, usize), VerifyError> {
+    let chunk = &function.chunk;
+    let error = |offset, reason| VerifyError {
+        function: function.name.clone(),
+        offset,
+        reason,
+    };
+
+    let starts = instruction_starts(chunk).map_err(|(offset, reason)| error(offset, reason))?;
+    let mut depths = vec![None; chunk.code.len()];
+    let mut max = function.arity as usize + 1;
+    let mut pending = vec![(0, function.arity as i32 + 1)];
+
+    while let Some((mut offset, mut depth)) = pending.pop() {
+        loop {
+            if offset >= chunk.code.len() {
+                return Err(error(offset, "execution runs past the end of the chunk"));
+            }
+            let depth_before = u16::try_from(depth).map_err(|_| error(offset, "stack too deep"))?;
+            match depths[offset] {
+                Some(seen) if seen == depth_before => break,
+                Some(_) => return Err(error(offset, "inconsistent stack depth")),
+                None => depths[offset] = Some(depth_before),
+            }
+
+            let op = OpCode::from_byte(chunk.code[offset]).expect("instructions were decoded");
+            let operand = || chunk.code[offset + 1] as i32;
+            if matches!(op, OpCode::GetLocal | OpCode::SetLocal | OpCode::Next)
+                && operand() >= depth
+            {
+                return Err(error(offset, "local slot out of range"));
+            }
+            if op == OpCode::Cast && CastType::from_byte(operand() as u8).is_none() {
+                return Err(error(offset, "unknown cast type"));
+            }
+            if op.has_constant() {
+                let index = chunk.read_u16(offset + 1) as usize;
+                match (op, chunk.constants.get(index)) {
+                    (_, None) => return Err(error(offset, "constant index out of range")),
+                    (OpCode::Constant, Some(_)) | (OpCode::Switch, Some(Value::Int(_))) => {}
+                    (OpCode::Switch, Some(_)) => {
+                        return Err(error(offset, "switch minimum is not an int"))
+                    }
+                    (_, Some(Value::Str(_))) => {}
+                    (_, Some(_)) => return Err(error(offset, "name is not a string")),
+                }
+            }
+
+            match op {
+                OpCode::Yield if !function.generator => {
+                    return Err(error(offset, "yield outside of a generator"))
+                }
+                // The returned value must sit above the frame's first slot
+                OpCode::Return | OpCode::Unmatched if depth < 2 => {
+                    return Err(error(offset, "stack underflow"))
+                }
+                OpCode::Return | OpCode::Unmatched => break,
+                _ => depth += op.stack_effect(chunk.code.get(offset + 1).copied().unwrap_or(0)),
+            }
+            if depth < 1 {
+                return Err(error(offset, "stack underflow"));
+            }
+            max = max.max(depth as usize);
+
+            if op == OpCode::Switch {
+                let entries = switch_table(chunk, offset)
+                    .filter(|entries| {
+                        entries.iter().all(|&entry| {
+                            starts.get(entry) == Some(&true)
+                                && chunk.code.get(entry) == Some(&(OpCode::Jump as u8))
+                        })
+                    })
+                    .ok_or_else(|| error(offset, "switch table entry is not a jump"))?;
+                pending.extend(entries.into_iter().map(|entry| (entry, depth)));
+                break;
+            } else if op.is_jump() {
+                let target = jump_target(chunk, op, offset)
+                    .filter(|&target| starts.get(target) == Some(&true))
+                    .ok_or_else(|| error(offset, "jump doesn't land on an instruction"))?;
+                match op {
+                    OpCode::JumpIfFalse => {
+                        pending.push((target, depth));
+                        offset += 3;
+                    }
+                    _ => offset = target,
+                }
+            } else {
+                offset += 1 + op.operand_len();
+            }
+        }
+    }
+
+    Ok((depths, max))
+}
+
+/// Return the offset the jump instruction at `offset` lands on, or `None`
+/// if it would land before the start of the chunk.
+pub(crate) fn jump_target(chunk: &Chunk, op: OpCode, offset: usize) -> Option<usize> {
+    let jump = chunk.read_u16(offset + 1) as usize;
+    if op == OpCode::Loop {
+        (offset + 3).checked_sub(jump)
+    } else {
+        Some(offset + 3 + jump)
+    }
+}
+
+/// Return the offset of every entry in the jump table of the `Switch` at
+/// `offset`, including the one for values outside of the table, or `None`
+/// if the table would run past the end of the chunk.
+pub(crate) fn switch_table(chunk: &Chunk, offset: usize) -> Option<Vec<usize>> {
+    let len = chunk.read_u16(offset + 3) as usize;
+    let table = offset + 5;
+    if table + 3 * len >= chunk.code.len() {
+        return None;
+    }
+    Some((0..=len).map(|index| table + 3 * index).collect())
+}
+
+/// Decode the chunk from the start, returning whether each offset (and the
+/// end of the chunk) is the start of an instruction.
+fn instruction_starts(chunk: &Chunk) -> Result<Vec<bool>, (usize, &'static str)> {
+    let mut starts = vec![false; chunk.code.len() + 1];
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        starts[offset] = true;
+        let op = OpCode::from_byte(chunk.code[offset]).ok_or((offset, "invalid opcode"))?;
+        if offset + op.operand_len() >= chunk.code.len() {
+            return Err((offset, "instruction is missing its operands"));
+        }
+        offset += 1 + op.operand_len();
+    }
+    starts[chunk.code.len()] = true;
+    Ok(starts)
+}
@@ -0,0 +1,124 @@
+//! A C interface to the interpreter, so that programs written in other
+//! languages can embed Meow. It is built with the `cdylib` feature, and
+//! declared for C in `include/meow.h`.
+//!
+//! A VM is created with `meow_new`, runs source code with `meow_eval`, and
+//! is freed with `meow_free`. Globals can be read back as strings with
+//! `meow_get_string`. When `meow_eval` fails, the reason can be read with
+//! `meow_last_error` until the next call to it.
+//!
+//! Every string passed in must be valid UTF-8 and end in a nul byte.
+
+use crate::{run, vm::Vm};
+use std::{
+    ffi::{c_char, c_int, CStr, CString},
+    panic::{self, AssertUnwindSafe},
+    ptr,
+};
+
+/// A VM created by `meow_new`, along with the error of the last call to
+/// `meow_eval`.
+pub struct MeowVm {
+    vm: Vm,
+    error: Option<CString>,
+}
+
+/// Create a VM, which must be freed with [`meow_free`].
+#[no_mangle]
+pub extern "C" fn meow_new() -> *mut MeowVm {
+    Box::into_raw(Box::new(MeowVm {
+        vm: Vm::new(),
+        error: None,
+    }))
+}
+
+/// Free a VM created by [`meow_new`]. Does nothing if `vm` is null.
+///
+/// # Safety
+///
+/// `vm` must have been returned by [`meow_new`], and not freed already.
+#[no_mangle]
+pub unsafe extern "C" fn meow_free(vm: *mut MeowVm) {
+    if !vm.is_null() {
+        drop(Box::from_raw(vm));
+    }
+}
+
+/// Run `source` on `vm`, returning 0 if it succeeds. Otherwise returns -1,
+/// and the error can be read with [`meow_last_error`].
+///
+/// # Safety
+///
+/// `vm` must be a live VM created by [`meow_new`], and `source` a
+/// nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn meow_eval(vm: *mut MeowVm, source: *const c_char) -> c_int {
+    let vm = &mut *vm;
+    vm.error = None;
+    let error = match CStr::from_ptr(source).to_str() {
+        Ok(source) => match panic::catch_unwind(AssertUnwindSafe(|| run(&mut vm.vm, source))) {
+            Ok(Ok(_)) => return 0,
+            Ok(Err(error)) => error.render(),
+            Err(_) => "the interpreter panicked".to_string(),
+        },
+        Err(_) => "the source code isn't valid UTF-8".to_string(),
+    };
+    vm.error = Some(c_string(error));
+    -1
+}
+
+/// Return the error of the last call to [`meow_eval`] on `vm`, or null if
+/// it succeeded. The string belongs to `vm`, and is only valid until the
+/// next call to [`meow_eval`] or [`meow_free`].
+///
+/// # Safety
+///
+/// `vm` must be a live VM created by [`meow_new`].
+#[no_mangle]
+pub unsafe extern "C" fn meow_last_error(vm: *const MeowVm) -> *const c_char {
+    match &(*vm).error {
+        Some(error) => error.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Return the value of the global `name` of `vm` as it would be printed, or
+/// null if it isn't defined. The string must be freed with
+/// [`meow_string_free`].
+///
+/// # Safety
+///
+/// `vm` must be a live VM created by [`meow_new`], and `name` a
+/// nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn meow_get_string(vm: *const MeowVm, name: *const c_char) -> *mut c_char {
+    let vm = &(*vm).vm;
+    let value = CStr::from_ptr(name)
+        .to_str()
+        .ok()
+        .and_then(|name| vm.global(name));
+    match value {
+        Some(value) => c_string(vm.heap().display(value).to_string()).into_raw(),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Free a string returned by [`meow_get_string`]. Does nothing if `string`
+/// is null.
+///
+/// # Safety
+///
+/// `string` must have been returned by [`meow_get_string`], and not freed
+/// already.
+#[no_mangle]
+pub unsafe extern "C" fn meow_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+/// Convert `string` for C, dropping any nul bytes it holds, since they
+/// would end it early.
+fn c_string(string: String) -> CString {
+    CString::new(string.replace('\0', "")).expect("nul bytes were removed")
+}
@@ -0,0 +1,35 @@
+//! The clock read by the profiler, the [timings](crate::vm::timings) and
+//! the `time` functions. It is the system's with the `clock` feature, which
+//! is on by default. Targets without a clock get one that never moves
+//! instead, so the profiler and timings measure no time at all, and
+//! `sleep`, `time.now` and `time.elapsed` fail.
+
+#[cfg(not(feature = "clock"))]
+use std::{ops::Sub, time::Duration};
+#[cfg(feature = "clock")]
+pub(crate) use web_time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// A reading of the clock that never moves.
+#[cfg(not(feature = "clock"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Instant;
+
+#[cfg(not(feature = "clock"))]
+impl Instant {
+    pub(crate) fn now() -> Self {
+        Instant
+    }
+
+    pub(crate) fn elapsed(&self) -> Duration {
+        Duration::ZERO
+    }
+}
+
+#[cfg(not(feature = "clock"))]
+impl Sub for Instant {
+    type Output = Duration;
+
+    fn sub(self, _: Instant) -> Duration {
+        Duration::ZERO
+    }
+}
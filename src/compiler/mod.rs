@@ -0,0 +1,1021 @@
+//! Compilation is the step after resolution. The [`Compiler`] walks the AST
+//! and lowers it into bytecode [`Function`]s, one per `fun` declaration plus
+//! one for the top level of the program.
+//!
+//! Variables are looked up through the [`SymbolTable`] built by the
+//! resolver. Top-level declarations are stored as globals, while parameters
+//! and block-scoped bindings live in stack slots of their function's call
+//! frame, assigned at compile time. Constructs the compiler can't lower yet
+//! are reported as diagnostics rather than silently miscompiled.
+
+use crate::{
+    bytecode::{optimize::eliminate_dead_code, Chunk, Function, OpCode},
+    diagnostics::Diagnostic,
+    parser::ast::{
+        BinOp, Block, Expr, FunDecl, Literal, MatchArm, Param, Pattern, Stmt, StrPart, UnaryOp,
+    },
+    resolver::{SymbolId, SymbolKind, SymbolTable},
+    span::Span,
+    value::{Range, Value},
+    vm::native::Module,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    rc::Rc,
+};
+use tracing::debug;
+
+/// A variable stored in a stack slot.
+struct Local {
+    /// The symbol stored in the slot, or `None` for the slot holding the
+    /// function being called.
+    symbol: Option<SymbolId>,
+    /// The depth of the scope the local was declared in.
+    depth: u32,
+    slot: u8,
+}
+
+/// The state of a single function being compiled.
+struct FunctionState {
+    function: Function,
+    /// The locals in scope, innermost last.
+    locals: Vec<Local>,
+    scope_depth: u32,
+    /// The number of values in the frame after the code emitted so far, so
+    /// that a local declared above temporaries, such as in a block passed as
+    /// an argument, gets the slot its value is actually in.
+    stack: i32,
+    /// The stack depth at each jump that hasn't been patched yet, by the
+    /// offset of its operand.
+    jumps: HashMap<usize, i32>,
+}
+
+impl FunctionState {
+    fn new(name: &str, arity: u8) -> Self {
+        Self {
+            function: Function {
+                name: name.to_string(),
+                arity,
+                generator: false,
+                chunk: Chunk::new(),
+            },
+            locals: vec![Local {
+                symbol: None,
+                depth: 0,
+                slot: 0,
+            }],
+            scope_depth: 0,
+            stack: 1,
+            jumps: HashMap::new(),
+        }
+    }
+
+    /// Return the stack slot holding `symbol`, if it is a local of this
+    /// function.
+    fn slot(&self, symbol: SymbolId) -> Option<u8> {
+        self.locals
+            .iter()
+            .rfind(|local| local.symbol == Some(symbol))
+            .map(|local| local.slot)
+    }
+}
+
+/// The fewest distinct ints a `match` needs before it is compiled to a jump
+/// table rather than a chain of tests.
+const MIN_SWITCH_CASES: usize = 4;
+
+/// The most entries a jump table can have.
+const MAX_SWITCH_LEN: usize = 256;
+
+/// The arm of a `match` that each int from `min` selects, if any, for a
+/// `match` that is compiled to a jump table.
+struct JumpTable {
+    min: i64,
+    arms: Vec<Option<usize>>,
+    /// The arm matching every value outside of the table, if there is one.
+    default: Option<usize>,
+}
+
+impl JumpTable {
+    /// Build a jump table for `arms`, if they are guardless int patterns,
+    /// optionally followed by a catch-all arm, and the ints they match are
+    /// dense enough to be worth it.
+    fn new(arms: &[MatchArm]) -> Option<JumpTable> {
+        let mut cases = BTreeMap::new();
+        let mut default = None;
+        for (index, arm) in arms.iter().enumerate() {
+            if arm.guard.is_some() {
+                return None;
+            }
+            if let Pattern::Wildcard { .. } | Pattern::Binding { .. } = arm.pattern {
+                default = Some(index);
+                break;
+            }
+            // An earlier arm takes precedence for an int matched twice
+            if !int_cases(&arm.pattern, &mut |value| {
+                cases.entry(value).or_insert(index);
+                cases.len() <= MAX_SWITCH_LEN
+            }) {
+                return None;
+            }
+        }
+
+        let (&min, _) = cases.first_key_value()?;
+        let (&max, _) = cases.last_key_value()?;
+        let len = max as i128 - min as i128 + 1;
+        if cases.len() < MIN_SWITCH_CASES
+            || len > MAX_SWITCH_LEN as i128
+            || len > 2 * cases.len() as i128
+        {
+            return None;
+        }
+
+        let arms = (0..len as i64)
+            .map(|offset| cases.get(&(min + offset)).copied().or(default))
+            .collect();
+        Some(JumpTable { min, arms, default })
+    }
+}
+
+/// Call `case` with every int matched by `pattern`, returning false if it
+/// matches anything else, or `case` returns false.
+fn int_cases(pattern: &Pattern, case: &mut impl FnMut(i64) -> bool) -> bool {
+    match pattern {
+        Pattern::Literal {
+            value: Literal::Int(value),
+            ..
+        } => case(*value),
+        Pattern::Range {
+            start,
+            end,
+            inclusive,
+            ..
+        } => {
+            let mut range = Range {
+                start: *start,
+                end: *end,
+                inclusive: *inclusive,
+            };
+            range.len().is_some_and(|len| len <= MAX_SWITCH_LEN as i64) && range.all(case)
+        }
+        Pattern::Or { patterns, .. } => patterns.iter().all(|pattern| int_cases(pattern, case)),
+        _ => false,
+    }
+}
+
+/// The `Compiler` struct lowers a resolved program into bytecode. It should
+/// typically be used through the top-level `compile()` function.
+pub struct Compiler<'t> {
+    table: &'t SymbolTable,
+    /// The functions being compiled, innermost last.
+    functions: Vec<FunctionState>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'t> Compiler<'t> {
+    pub fn new(table: &'t SymbolTable) -> Self {
+        Self {
+            table,
+            functions: vec![FunctionState::new("<script>", 0)],
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Compile a whole program into the function for its top level.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use meow::{compiler::Compiler, parse, resolve};
+    ///
+    /// let program = parse("1 + 2;").unwrap();
+    /// let (table, _) = resolve(&program);
+    /// let script = Compiler::new(&table).compile(&program).unwrap();
+    /// assert_eq!(script.name, "<script>");
+    /// ```
+    pub fn compile(mut self, program: &[Stmt]) -> Result<Function, Vec<Diagnostic>> {
+        for stmt in program {
+            self.stmt(stmt);
+        }
+
+        let end = program.last().map(|s| s.span()).unwrap_or_default();
+        self.emit(OpCode::Unit, end);
+        self.emit(OpCode::Return, end);
+
+        let mut script = self.functions.pop().expect("no function to compile into");
+        if self.diagnostics.is_empty() {
+            eliminate_dead_code(&mut script.function.chunk);
+            debug!(
+                bytes = script.function.chunk.code.len(),
+                "compiled the script"
+            );
+            Ok(script.function)
+        } else {
+            Err(self.diagnostics)
+        }
+    }
+
+    fn current(&mut self) -> &mut FunctionState {
+        self.functions
+            .last_mut()
+            .expect("no function to compile into")
+    }
+
+    fn chunk(&mut self) -> &mut Chunk {
+        &mut self.current().function.chunk
+    }
+
+    fn emit(&mut self, op: OpCode, span: Span) {
+        self.chunk().write_op(op, span);
+        self.current().stack += op.stack_effect(0);
+    }
+
+    fn emit_with_byte(&mut self, op: OpCode, byte: u8, span: Span) {
+        self.chunk().write_op(op, span);
+        self.chunk().write(byte, span);
+        self.current().stack += op.stack_effect(byte);
+    }
+
+    fn emit_with_u16(&mut self, op: OpCode, value: u16, span: Span) {
+        self.emit(op, span);
+        self.chunk().write_u16(value, span);
+    }
+
+    /// Add `value` to the current chunk's constant table, reporting an error
+    /// if it is full.
+    fn constant(&mut self, value: Value, span: Span) -> u16 {
+        match self.chunk().add_constant(value) {
+            Some(index) => index,
+            None => {
+                self.error("too many constants in one function", span);
+                0
+            }
+        }
+    }
+
+    fn error(&mut self, message: impl Into<String>, span: Span) {
+        self.diagnostics.push(Diagnostic::error(message, span));
+    }
+
+    /// Report a construct the compiler doesn't support yet.
+    fn unsupported(&mut self, what: &str, span: Span) {
+        self.error(format!("{} cannot be compiled yet", what), span);
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Let {
+                name, value, span, ..
+            } => {
+                match value {
+                    Some(value) => self.expr(value),
+                    None => self.emit(OpCode::Unit, *span),
+                }
+                self.define_variable(name, *span);
+            }
+            Stmt::Expr { expr, span } => {
+                self.expr(expr);
+                self.emit(OpCode::Pop, *span);
+            }
+            Stmt::Fun(fun) => {
+                self.function(fun, false);
+                self.define_variable(&fun.name, fun.span);
+            }
+            Stmt::Return { value, span } => {
+                match value {
+                    Some(value) => self.expr(value),
+                    None => self.emit(OpCode::Unit, *span),
+                }
+                self.emit(OpCode::Return, *span);
+            }
+            Stmt::Yield { value, span } => {
+                match value {
+                    Some(value) => self.expr(value),
+                    None => self.emit(OpCode::Unit, *span),
+                }
+                self.emit(OpCode::Yield, *span);
+                self.current().function.generator = true;
+            }
+            Stmt::Class {
+                name,
+                methods,
+                span,
+                ..
+            } => {
+                if methods.len() > u8::MAX as usize {
+                    return self.error("classes can't have more than 255 methods", *span);
+                }
+
+                let index = self.constant(Value::from(name.as_str()), *span);
+                self.emit_with_u16(OpCode::Constant, index, *span);
+                for method in methods {
+                    self.function(method, true);
+                }
+                self.emit_with_byte(OpCode::Class, methods.len() as u8, *span);
+                self.define_variable(name, *span);
+            }
+            Stmt::Spawn { callee, args, span } => self.call(callee, args, OpCode::Spawn, *span),
+            Stmt::While { cond, body, span } => {
+                let start = self.chunk().code.len();
+                self.expr(cond);
+                let exit = self.emit_jump(OpCode::JumpIfFalse, *span);
+                self.emit(OpCode::Pop, *span);
+                self.block(body);
+                self.emit(OpCode::Pop, *span);
+                self.emit_loop(start, *span);
+                self.patch_jump(exit, *span);
+                self.emit(OpCode::Pop, *span);
+            }
+            Stmt::For {
+                var,
+                iterable,
+                body,
+                span,
+            } => self.for_loop(var, iterable, body, *span),
+            Stmt::Import { path, span, .. } => match Module::from_path(path) {
+                Some(module) => {
+                    let index = self.constant(Value::Module(module), *span);
+                    self.emit_with_u16(OpCode::Constant, index, *span);
+                    self.define_variable(path.last().expect("import without a path"), *span);
+                }
+                None => self.error(format!("unknown module `{}`", path.join(".")), *span),
+            },
+        }
+    }
+
+    /// Bind the value on top of the stack to the variable `name` declared at
+    /// `span`. Inside a scope the value simply stays in its stack slot.
+    fn define_variable(&mut self, name: &str, span: Span) {
+        if self.current().scope_depth == 0 {
+            let name = self.constant(Value::from(name), span);
+            self.emit_with_u16(OpCode::DefineGlobal, name, span);
+        } else {
+            self.add_local(self.table.resolution(span), span);
+        }
+    }
+
+    /// Declare the value on top of the stack as a local holding `symbol`,
+    /// returning its slot.
+    fn add_local(&mut self, symbol: Option<SymbolId>, span: Span) -> u8 {
+        let Ok(slot) = u8::try_from(self.current().stack - 1) else {
+            self.error("too many local variables in one function", span);
+            return 0;
+        };
+
+        let depth = self.current().scope_depth;
+        self.current().locals.push(Local {
+            symbol,
+            depth,
+            slot,
+        });
+        slot
+    }
+
+    fn begin_scope(&mut self) {
+        self.current().scope_depth += 1;
+    }
+
+    /// Close the innermost scope, popping its locals off the stack. If
+    /// `keep_value` is true, the value on top of the stack is the result of
+    /// the scope and is kept in place of the first local.
+    fn end_scope(&mut self, keep_value: bool, span: Span) {
+        let state = self.current();
+        state.scope_depth -= 1;
+
+        let depth = state.scope_depth;
+        let first = state
+            .locals
+            .iter()
+            .rposition(|local| local.depth <= depth)
+            .map_or(0, |slot| slot + 1);
+        let Some(slot) = state.locals.get(first).map(|local| local.slot) else {
+            return;
+        };
+        let count = state.locals.len() - first;
+        state.locals.truncate(first);
+
+        if keep_value {
+            self.emit_with_byte(OpCode::SetLocal, slot, span);
+        }
+        self.emit_with_byte(OpCode::PopN, count as u8, span);
+    }
+
+    /// Emit a jump instruction with a placeholder offset, returning the
+    /// position of the offset for [`Compiler::patch_jump`].
+    fn emit_jump(&mut self, op: OpCode, span: Span) -> usize {
+        self.emit_with_u16(op, u16::MAX, span);
+        let offset = self.chunk().code.len() - 2;
+        let state = self.current();
+        state.jumps.insert(offset, state.stack);
+        offset
+    }
+
+    /// Point the jump whose offset is at `offset` to the next instruction.
+    /// The stack is as deep there as it was at the jump, which matters when
+    /// the instruction before is an unconditional jump.
+    fn patch_jump(&mut self, offset: usize, span: Span) {
+        let state = self.current();
+        if let Some(stack) = state.jumps.remove(&offset) {
+            state.stack = stack;
+        }
+
+        let jump = self.chunk().code.len() - offset - 2;
+        let jump = u16::try_from(jump).unwrap_or_else(|_| {
+            self.error("too much code to jump over", span);
+            0
+        });
+        self.chunk().code[offset..offset + 2].copy_from_slice(&jump.to_be_bytes());
+    }
+
+    /// Emit a jump back to the instruction at `start`.
+    fn emit_loop(&mut self, start: usize, span: Span) {
+        let jump = self.chunk().code.len() - start + 3;
+        let jump = u16::try_from(jump).unwrap_or_else(|_| {
+            self.error("loop body is too large", span);
+            0
+        });
+        self.emit_with_u16(OpCode::Loop, jump, span);
+    }
+
+    /// Compile a `for` loop. A range written in the loop is lowered into a
+    /// counting loop over two hidden locals holding the next value and the
+    /// bound, rather than being created.
+    fn for_loop(&mut self, var: &Param, iterable: &Expr, body: &Block, span: Span) {
+        let (start, end, inclusive) = match iterable {
+            Expr::Binary {
+                op: op @ (BinOp::Range | BinOp::RangeInclusive),
+                left,
+                right,
+                ..
+            } => (left, right, *op == BinOp::RangeInclusive),
+            _ => return self.iterate(var, iterable, body, span),
+        };
+
+        self.begin_scope();
+        self.expr(start);
+        let counter = self.add_local(None, span);
+        self.expr(end);
+        self.add_local(None, span);
+        // The bounds are checked like those of a range value
+        self.emit(OpCode::RangeBounds, span);
+
+        let loop_start = self.chunk().code.len();
+        self.emit_with_byte(OpCode::GetLocal, counter, span);
+        self.emit_with_byte(OpCode::GetLocal, counter + 1, span);
+        self.emit(
+            if inclusive {
+                OpCode::LessEqual
+            } else {
+                OpCode::Less
+            },
+            span,
+        );
+        let exit = self.emit_jump(OpCode::JumpIfFalse, span);
+        self.emit(OpCode::Pop, span);
+
+        // The loop variable is a copy, so assigning to it doesn't affect the
+        // iteration
+        self.begin_scope();
+        self.emit_with_byte(OpCode::GetLocal, counter, span);
+        self.add_local(self.table.resolution(var.span), var.span);
+        self.block(body);
+        self.emit(OpCode::Pop, span);
+        self.end_scope(false, span);
+
+        // An inclusive loop stops at its end before counting past it, which
+        // would overflow at the largest int
+        let last = inclusive.then(|| {
+            self.emit_with_byte(OpCode::GetLocal, counter, span);
+            self.emit_with_byte(OpCode::GetLocal, counter + 1, span);
+            self.emit(OpCode::NotEqual, span);
+            let last = self.emit_jump(OpCode::JumpIfFalse, span);
+            self.emit(OpCode::Pop, span);
+            last
+        });
+        self.emit_with_byte(OpCode::GetLocal, counter, span);
+        let one = self.constant(Value::Int(1), span);
+        self.emit_with_u16(OpCode::Constant, one, span);
+        self.emit(OpCode::Add, span);
+        self.emit_with_byte(OpCode::SetLocal, counter, span);
+        self.emit(OpCode::Pop, span);
+        self.emit_loop(loop_start, span);
+
+        self.patch_jump(exit, span);
+        if let Some(last) = last {
+            self.patch_jump(last, span);
+        }
+        self.emit(OpCode::Pop, span);
+        self.end_scope(false, span);
+    }
+
+    /// Compile a `for` loop over an iterator, such as a generator or a range
+    /// value, which is resumed for every value until it is exhausted.
+    fn iterate(&mut self, var: &Param, iterable: &Expr, body: &Block, span: Span) {
+        self.begin_scope();
+        self.expr(iterable);
+        let iterator = self.add_local(None, span);
+
+        let loop_start = self.chunk().code.len();
+        self.emit_with_byte(OpCode::Next, iterator, span);
+        let exit = self.emit_jump(OpCode::JumpIfFalse, span);
+        self.emit(OpCode::Pop, span);
+
+        // The value left below the flag becomes the loop variable
+        self.begin_scope();
+        self.add_local(self.table.resolution(var.span), var.span);
+        self.block(body);
+        self.emit(OpCode::Pop, span);
+        self.end_scope(false, span);
+        self.emit_loop(loop_start, span);
+
+        self.patch_jump(exit, span);
+        self.emit_with_byte(OpCode::PopN, 2, span);
+        self.end_scope(false, span);
+    }
+
+    /// Compile `fun` into a new function, and emit an instruction loading it.
+    fn function(&mut self, fun: &FunDecl, method: bool) {
+        if fun.params.len() > u8::MAX as usize {
+            self.error("functions can't have more than 255 parameters", fun.span);
+            return;
+        }
+
+        self.functions
+            .push(FunctionState::new(&fun.name, fun.params.len() as u8));
+        if method {
+            // Methods are called with their receiver in the callee's slot,
+            // which is the implicit `self` the resolver declares for them
+            self.current().locals[0].symbol = self.table.receiver(fun.span);
+        }
+        // Arguments are pushed by the caller, above the callee
+        self.begin_scope();
+        for param in &fun.params {
+            self.current().stack += 1;
+            self.add_local(self.table.resolution(param.span), param.span);
+        }
+
+        self.block(&fun.body);
+        self.emit(OpCode::Return, fun.body.span);
+
+        let mut function = self.functions.pop().expect("no function to compile into");
+        eliminate_dead_code(&mut function.function.chunk);
+        debug!(
+            name = fun.name,
+            bytes = function.function.chunk.code.len(),
+            "compiled a function"
+        );
+        let index = self.constant(Value::Function(Rc::new(function.function)), fun.span);
+        self.emit_with_u16(OpCode::Constant, index, fun.span);
+    }
+
+    /// Compile a block, leaving its value on the stack.
+    fn block(&mut self, block: &Block) {
+        self.begin_scope();
+        for stmt in &block.stmts {
+            self.stmt(stmt);
+        }
+
+        match &block.tail {
+            Some(tail) => self.expr(tail),
+            None => self.emit(OpCode::Unit, block.span),
+        }
+        self.end_scope(true, block.span);
+    }
+
+    /// Compile an expression, leaving its value on the stack.
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Literal { value, span } => self.literal(value, *span),
+            Expr::Ident { name, span } => self.variable(name, *span, OpCode::GetLocal),
+            Expr::Unary { op, expr, span } => {
+                self.expr(expr);
+                match op {
+                    UnaryOp::Minus => self.emit(OpCode::Negate, *span),
+                    UnaryOp::Bang => self.emit(OpCode::Not, *span),
+                }
+            }
+            Expr::Binary {
+                op,
+                left,
+                right,
+                span,
+            } => self.binary(*op, left, right, *span),
+            Expr::Cast { expr, ty, span } => {
+                self.expr(expr);
+                self.emit_with_byte(OpCode::Cast, *ty as u8, *span);
+            }
+            Expr::Assign {
+                target,
+                op,
+                value,
+                span,
+            } => match &**target {
+                Expr::Ident { name, span: target } => {
+                    if let Some(op) = op {
+                        self.variable(name, *target, OpCode::GetLocal);
+                        self.expr(value);
+                        self.binary_op(*op, *span);
+                    } else {
+                        self.expr(value);
+                    }
+                    self.variable(name, *target, OpCode::SetLocal);
+                }
+                Expr::Index { object, index, .. } if op.is_none() => {
+                    self.expr(object);
+                    self.expr(index);
+                    self.expr(value);
+                    self.emit(OpCode::SetIndex, *span);
+                }
+                Expr::Index { .. } => self.unsupported("compound assignment to indices", *span),
+                Expr::Field {
+                    object,
+                    name,
+                    span: target,
+                } => {
+                    let name = self.constant(Value::from(name.as_str()), *target);
+                    self.expr(object);
+                    if let Some(op) = op {
+                        self.emit(OpCode::Dup, *target);
+                        self.emit_with_u16(OpCode::GetField, name, *target);
+                        self.expr(value);
+                        self.binary_op(*op, *span);
+                    } else {
+                        self.expr(value);
+                    }
+                    self.emit_with_u16(OpCode::SetField, name, *span);
+                }
+                _ => self.error("invalid assignment target", *span),
+            },
+            Expr::Call { callee, args, span } => self.call(callee, args, OpCode::Call, *span),
+            Expr::Block(block) => self.block(block),
+            Expr::Field { object, name, span } => {
+                self.expr(object);
+                let name = self.constant(Value::from(name.as_str()), *span);
+                self.emit_with_u16(OpCode::GetField, name, *span);
+            }
+            Expr::Index {
+                object,
+                index,
+                span,
+            } => {
+                self.expr(object);
+                match &**index {
+                    // Indexing by a range takes a slice
+                    Expr::Binary {
+                        op: op @ (BinOp::Range | BinOp::RangeInclusive),
+                        left,
+                        right,
+                        ..
+                    } => {
+                        self.expr(left);
+                        self.expr(right);
+                        let inclusive = *op == BinOp::RangeInclusive;
+                        self.emit_with_byte(OpCode::Slice, inclusive as u8, *span);
+                    }
+                    index => {
+                        self.expr(index);
+                        self.emit(OpCode::GetIndex, *span);
+                    }
+                }
+            }
+            Expr::List { items, span } => {
+                if items.len() > u8::MAX as usize {
+                    self.error("list literals can't have more than 255 items", *span);
+                    return;
+                }
+                for item in items {
+                    self.expr(item);
+                }
+                self.emit_with_byte(OpCode::BuildList, items.len() as u8, *span);
+            }
+            Expr::Interpolation { parts, span } => {
+                if StrPart::exprs(parts).count() > u8::MAX as usize {
+                    self.error(
+                        "interpolated strings can't have more than 255 expressions",
+                        *span,
+                    );
+                    return;
+                }
+                let template = Value::from(StrPart::template(parts).as_str());
+                let index = self.constant(template, *span);
+                self.emit_with_u16(OpCode::Constant, index, *span);
+                for expr in StrPart::exprs(parts) {
+                    self.expr(expr);
+                }
+                let count = StrPart::exprs(parts).count();
+                self.emit_with_byte(OpCode::Format, count as u8, *span);
+            }
+            Expr::If {
+                cond,
+                then,
+                otherwise,
+                span,
+            } => {
+                self.expr(cond);
+                let else_jump = self.emit_jump(OpCode::JumpIfFalse, *span);
+                self.emit(OpCode::Pop, *span);
+                self.block(then);
+                let end_jump = self.emit_jump(OpCode::Jump, *span);
+
+                self.patch_jump(else_jump, *span);
+                self.emit(OpCode::Pop, *span);
+                match otherwise {
+                    Some(otherwise) => self.expr(otherwise),
+                    None => self.emit(OpCode::Unit, *span),
+                }
+                self.patch_jump(end_jump, *span);
+            }
+            Expr::Match {
+                scrutinee,
+                arms,
+                span,
+            } => self.match_expression(scrutinee, arms, *span),
+        }
+    }
+
+    /// Compile a `match`. The scrutinee is kept in a hidden local, which is
+    /// tested against each arm's pattern in turn, unless the arms match
+    /// enough ints to select the right one through a jump table.
+    fn match_expression(&mut self, scrutinee: &Expr, arms: &[MatchArm], span: Span) {
+        self.begin_scope();
+        self.expr(scrutinee);
+        let slot = self.add_local(None, span);
+
+        match JumpTable::new(arms) {
+            Some(table) => self.switch(arms, &table, slot, span),
+            None => self.match_arms(arms, slot, span),
+        }
+        self.end_scope(true, span);
+    }
+
+    /// Compile `arms` as a chain of tests of the value in `slot`.
+    fn match_arms(&mut self, arms: &[MatchArm], slot: u8, span: Span) {
+        let mut end_jumps = Vec::new();
+        for arm in arms {
+            let next_arm = if arm.pattern.is_irrefutable() {
+                None
+            } else {
+                self.pattern(&arm.pattern, slot);
+                let jump = self.emit_jump(OpCode::JumpIfFalse, arm.span);
+                self.emit(OpCode::Pop, arm.span);
+                Some(jump)
+            };
+
+            self.begin_scope();
+            let bindings = self.bind(&arm.pattern, slot);
+            let guard_failed = arm.guard.as_ref().map(|guard| {
+                self.expr(guard);
+                let jump = self.emit_jump(OpCode::JumpIfFalse, guard.span());
+                self.emit(OpCode::Pop, guard.span());
+                jump
+            });
+            self.expr(&arm.body);
+            self.end_scope(true, arm.span);
+            end_jumps.push(self.emit_jump(OpCode::Jump, arm.span));
+
+            // A failed guard leaves its condition above the bindings, and the
+            // pattern's condition is popped below
+            if let Some(jump) = guard_failed {
+                self.patch_jump(jump, arm.span);
+                let count = bindings + 1 - next_arm.is_some() as u8;
+                if count > 0 {
+                    self.emit_with_byte(OpCode::PopN, count, arm.span);
+                }
+            }
+            if let Some(jump) = next_arm {
+                self.patch_jump(jump, arm.span);
+                self.emit(OpCode::Pop, arm.span);
+            }
+        }
+
+        let exhaustive = arms
+            .iter()
+            .any(|arm| arm.guard.is_none() && arm.pattern.is_irrefutable());
+        if !exhaustive {
+            self.emit_with_byte(OpCode::GetLocal, slot, span);
+            self.emit(OpCode::Unmatched, span);
+        }
+        for jump in end_jumps {
+            self.patch_jump(jump, span);
+        }
+    }
+
+    /// Compile `arms` as a `Switch` on the value in `slot`, followed by a
+    /// table with a jump to the arm selected by each int in it.
+    fn switch(&mut self, arms: &[MatchArm], table: &JumpTable, slot: u8, span: Span) {
+        self.emit_with_byte(OpCode::GetLocal, slot, span);
+        let min = self.constant(Value::Int(table.min), span);
+        self.emit_with_u16(OpCode::Switch, min, span);
+        self.chunk().write_u16(table.arms.len() as u16, span);
+
+        // The last entry is for values outside of the table
+        let mut entries = vec![Vec::new(); arms.len()];
+        let mut unmatched = Vec::new();
+        for arm in table.arms.iter().chain([&table.default]) {
+            let jump = self.emit_jump(OpCode::Jump, span);
+            match arm {
+                Some(arm) => entries[*arm].push(jump),
+                None => unmatched.push(jump),
+            }
+        }
+
+        let mut end_jumps = Vec::new();
+        for (arm, entries) in arms.iter().zip(entries) {
+            for jump in entries {
+                self.patch_jump(jump, arm.span);
+            }
+            self.begin_scope();
+            self.bind(&arm.pattern, slot);
+            self.expr(&arm.body);
+            self.end_scope(true, arm.span);
+            end_jumps.push(self.emit_jump(OpCode::Jump, arm.span));
+        }
+
+        if !unmatched.is_empty() {
+            for jump in unmatched {
+                self.patch_jump(jump, span);
+            }
+            self.emit_with_byte(OpCode::GetLocal, slot, span);
+            self.emit(OpCode::Unmatched, span);
+        }
+        for jump in end_jumps {
+            self.patch_jump(jump, span);
+        }
+    }
+
+    /// Emit a test of the value in `slot` against `pattern`, which leaves a
+    /// bool on the stack.
+    fn pattern(&mut self, pattern: &Pattern, slot: u8) {
+        match pattern {
+            Pattern::Literal { value, span } => {
+                self.emit_with_byte(OpCode::GetLocal, slot, *span);
+                self.literal(value, *span);
+                self.emit(OpCode::Equal, *span);
+            }
+            Pattern::Range {
+                start,
+                end,
+                inclusive,
+                span,
+            } => {
+                self.emit_with_byte(OpCode::GetLocal, slot, *span);
+                self.literal(&Literal::Int(*start), *span);
+                self.literal(&Literal::Int(*end), *span);
+                self.emit_with_byte(OpCode::Range, *inclusive as u8, *span);
+                self.emit(OpCode::InRange, *span);
+            }
+            Pattern::Wildcard { span } | Pattern::Binding { span, .. } => {
+                self.emit(OpCode::True, *span)
+            }
+            Pattern::Or { patterns, span } => {
+                // Like `||`, an alternative is only tested if those before
+                // it didn't match
+                let mut end_jumps = Vec::new();
+                self.pattern(&patterns[0], slot);
+                for pattern in &patterns[1..] {
+                    let next = self.emit_jump(OpCode::JumpIfFalse, *span);
+                    end_jumps.push(self.emit_jump(OpCode::Jump, *span));
+                    self.patch_jump(next, *span);
+                    self.emit(OpCode::Pop, *span);
+                    self.pattern(pattern, slot);
+                }
+                for jump in end_jumps {
+                    self.patch_jump(jump, *span);
+                }
+            }
+        }
+    }
+
+    /// Declare the variable bound by `pattern`, if any, as a copy of the
+    /// value in `slot`. Returns the number of variables declared.
+    fn bind(&mut self, pattern: &Pattern, slot: u8) -> u8 {
+        match pattern {
+            Pattern::Binding { span, .. } => {
+                self.emit_with_byte(OpCode::GetLocal, slot, *span);
+                self.add_local(self.table.resolution(*span), *span);
+                1
+            }
+            _ => 0,
+        }
+    }
+
+    fn literal(&mut self, value: &Literal, span: Span) {
+        let value = match value {
+            Literal::Bool(true) => return self.emit(OpCode::True, span),
+            Literal::Bool(false) => return self.emit(OpCode::False, span),
+            Literal::Int(value) => Value::Int(*value),
+            Literal::Float(value) => Value::Float(*value),
+            Literal::Str(value) => Value::from(value.as_str()),
+            Literal::Char(value) => Value::Char(*value),
+        };
+        let index = self.constant(value, span);
+        self.emit_with_u16(OpCode::Constant, index, span);
+    }
+
+    /// Emit a read or write of the variable `name` referenced at `span`.
+    /// `local` is the instruction to use if it lives in a stack slot, and
+    /// its global counterpart is used otherwise.
+    fn variable(&mut self, name: &str, span: Span, local: OpCode) {
+        let symbol = self.table.resolution(span);
+        if symbol.is_some_and(|id| self.table.symbol(id).kind == SymbolKind::Method) {
+            return self.error(
+                format!("methods must be accessed through `self.{}`", name),
+                span,
+            );
+        }
+        let slot = symbol.and_then(|id| self.functions.last()?.slot(id));
+
+        if let Some(slot) = slot {
+            return self.emit_with_byte(local, slot, span);
+        }
+
+        let captured = symbol.is_some_and(|id| self.functions.iter().any(|f| f.slot(id).is_some()));
+        if captured {
+            return self.unsupported("closures capturing local variables", span);
+        }
+
+        let global = match local {
+            OpCode::GetLocal => OpCode::GetGlobal,
+            _ => OpCode::SetGlobal,
+        };
+        let name = self.constant(Value::from(name), span);
+        self.emit_with_u16(global, name, span);
+    }
+
+    /// Compile a call of `callee` with `args`, using `op` to either call it
+    /// or spawn a task calling it.
+    fn call(&mut self, callee: &Expr, args: &[Expr], op: OpCode, span: Span) {
+        self.expr(callee);
+        if args.len() > u8::MAX as usize {
+            self.error("calls can't have more than 255 arguments", span);
+            return;
+        }
+        for arg in args {
+            self.expr(arg);
+        }
+        self.emit_with_byte(op, args.len() as u8, span);
+    }
+
+    fn binary(&mut self, op: BinOp, left: &Expr, right: &Expr, span: Span) {
+        match op {
+            BinOp::And => {
+                self.expr(left);
+                let end = self.emit_jump(OpCode::JumpIfFalse, span);
+                self.emit(OpCode::Pop, span);
+                self.expr(right);
+                self.check_bool(right.span());
+                self.patch_jump(end, span);
+            }
+            BinOp::Or => {
+                self.expr(left);
+                let right_jump = self.emit_jump(OpCode::JumpIfFalse, span);
+                let end = self.emit_jump(OpCode::Jump, span);
+                self.patch_jump(right_jump, span);
+                self.emit(OpCode::Pop, span);
+                self.expr(right);
+                self.check_bool(right.span());
+                self.patch_jump(end, span);
+            }
+            BinOp::Range | BinOp::RangeInclusive => {
+                self.expr(left);
+                self.expr(right);
+                let inclusive = op == BinOp::RangeInclusive;
+                self.emit_with_byte(OpCode::Range, inclusive as u8, span);
+            }
+            _ => {
+                self.expr(left);
+                self.expr(right);
+                self.binary_op(op, span);
+            }
+        }
+    }
+
+    /// Check that the value on top of the stack is a bool, so that logical
+    /// operators always produce one. A jump to the next instruction checks
+    /// its condition like any other.
+    fn check_bool(&mut self, span: Span) {
+        let next = self.emit_jump(OpCode::JumpIfFalse, span);
+        self.patch_jump(next, span);
+    }
+
+    /// Emit the instruction for an arithmetic or comparison operator.
+    fn binary_op(&mut self, op: BinOp, span: Span) {
+        let op = match op {
+            BinOp::Plus => OpCode::Add,
+            BinOp::Minus => OpCode::Subtract,
+            BinOp::Star => OpCode::Multiply,
+            BinOp::Slash => OpCode::Divide,
+            BinOp::Percent => OpCode::Remainder,
+            BinOp::EqualEqual => OpCode::Equal,
+            BinOp::BangEqual => OpCode::NotEqual,
+            BinOp::Greater => OpCode::Greater,
+            BinOp::GreaterEqual => OpCode::GreaterEqual,
+            BinOp::Less => OpCode::Less,
+            BinOp::LessEqual => OpCode::LessEqual,
+            BinOp::And | BinOp::Or | BinOp::Range | BinOp::RangeInclusive => {
+                unreachable!("{:?} has no single instruction", op)
+            }
+        };
+        self.emit(op, span);
+    }
+}
@@ -0,0 +1,218 @@
+//! Project configuration, read from a `meow.toml` file at the root of the
+//! project. The file is found by looking in the current directory and then
+//! each directory above it, so commands work from anywhere in a project.
+//!
+//! ```toml
+//! [project]
+//! name = "hello"
+//! entry = "src/main.mw"
+//! source-dirs = ["src"]
+//! search-paths = ["../shared"]
+//!
+//! [lints]
+//! unused-variable = "deny"
+//!
+//! [format]
+//! indent = 4
+//! max-width = 100
+//!
+//! [build]
+//! optimize = true
+//! cache = true
+//! ```
+//!
+//! Every table and key is optional. Options given on the command line take
+//! precedence over the ones in the file.
+
+use crate::{
+    errors::ConfigError,
+    lint::{Lint, LintConfig, LintLevel},
+};
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// The name of the configuration file at the root of a project.
+pub const CONFIG_FILE: &str = "meow.toml";
+
+/// The directory at the root of a project that compiled modules are saved
+/// in, if they are cached.
+pub const CACHE_DIR: &str = ".meow-cache";
+
+/// The entry point of new projects.
+const MAIN: &str = "println(\"Hello, world!\");\n";
+
+/// The example test in new projects.
+const TEST: &str = "assert(1 + 1 == 2, \"addition works\");\n";
+
+/// The configuration of a project. Paths in it are relative to the
+/// directory the configuration file is in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    /// The directory the configuration file is in.
+    pub root: PathBuf,
+    /// The name of the project, if it has one.
+    pub name: Option<String>,
+    /// The file that is run when the project is run.
+    pub entry: PathBuf,
+    /// The directories holding the project's source files.
+    pub source_dirs: Vec<PathBuf>,
+    /// More directories to find modules in, after the source directories
+    /// and before the ones shared by every project.
+    pub search_paths: Vec<PathBuf>,
+    /// The level of each lint.
+    pub lints: LintConfig,
+    /// How source code is laid out when it is formatted.
+    pub format: FormatConfig,
+    /// Whether compiled programs are optimized.
+    pub optimize: bool,
+    /// Whether compiled modules are saved in [`CACHE_DIR`], so that they
+    /// are only compiled again once they change.
+    pub cache: bool,
+}
+
+/// Options for how source code is laid out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct FormatConfig {
+    /// The number of spaces each level of indentation is.
+    pub indent: usize,
+    /// The number of columns lines are kept within where possible.
+    pub max_width: usize,
+}
+
+impl Default for FormatConfig {
+    fn default() -> Self {
+        Self {
+            indent: 4,
+            max_width: 100,
+        }
+    }
+}
+
+/// The configuration file as it is written, before it is checked.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct File {
+    project: ProjectTable,
+    lints: BTreeMap<String, LintLevel>,
+    format: FormatConfig,
+    build: BuildTable,
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct ProjectTable {
+    name: Option<String>,
+    entry: PathBuf,
+    source_dirs: Vec<PathBuf>,
+    search_paths: Vec<PathBuf>,
+}
+
+impl Default for ProjectTable {
+    fn default() -> Self {
+        Self {
+            name: None,
+            entry: PathBuf::from("src/main.mw"),
+            source_dirs: vec![PathBuf::from("src")],
+            search_paths: Vec::new(),
+        }
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct BuildTable {
+    optimize: bool,
+    cache: bool,
+}
+
+impl Config {
+    /// Parse the configuration in `source`, for the project in the
+    /// directory `root`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use meow::{config::Config, lint::{Lint, LintLevel}};
+    /// use std::path::Path;
+    ///
+    /// let source = "[project]\nentry = \"app.mw\"\n\n[lints]\nshadowing = \"warn\"\n";
+    /// let config = Config::parse(source, Path::new("hello")).unwrap();
+    /// assert_eq!(config.entry, Path::new("hello/app.mw"));
+    /// assert_eq!(config.lints.level(Lint::Shadowing), LintLevel::Warn);
+    /// ```
+    pub fn parse(source: &str, root: &Path) -> Result<Config, ConfigError> {
+        let file: File = toml::from_str(source)?;
+
+        let mut lints = LintConfig::new();
+        for (name, level) in file.lints {
+            let lint = Lint::from_name(&name).ok_or(ConfigError::UnknownLint(name))?;
+            lints.set_level(lint, level);
+        }
+
+        Ok(Config {
+            root: root.to_path_buf(),
+            name: file.project.name,
+            entry: root.join(file.project.entry),
+            source_dirs: file
+                .project
+                .source_dirs
+                .into_iter()
+                .map(|dir| root.join(dir))
+                .collect(),
+            search_paths: file
+                .project
+                .search_paths
+                .into_iter()
+                .map(|dir| root.join(dir))
+                .collect(),
+            lints,
+            format: file.format,
+            optimize: file.build.optimize,
+            cache: file.build.cache,
+        })
+    }
+
+    /// Read the configuration file at `path`.
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let root = path.parent().unwrap_or_else(|| Path::new(""));
+        Config::parse(&fs::read_to_string(path)?, root)
+    }
+}
+
+/// Return the path of the configuration file of the project `start` is in,
+/// if it is in one.
+pub fn find(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|dir| dir.join(CONFIG_FILE))
+        .find(|path| path.is_file())
+}
+
+/// Create a new project in the directory `dir`, which mustn't exist yet,
+/// with a configuration file, a hello world program at `src/main.mw`, and
+/// an example test in `tests`. The project is named after the directory.
+pub fn create_project(dir: &Path) -> io::Result<()> {
+    let name = dir
+        .file_name()
+        .map_or_else(|| "main".into(), |name| name.to_string_lossy());
+    if let Some(parent) = dir.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::create_dir(dir)?;
+    fs::write(
+        dir.join(CONFIG_FILE),
+        format!(
+            "[project]\nname = {}\nentry = \"src/main.mw\"\nsource-dirs = [\"src\"]\n",
+            toml::Value::String(name.into_owned())
+        ),
+    )?;
+    fs::create_dir(dir.join("src"))?;
+    fs::write(dir.join("src").join("main.mw"), MAIN)?;
+    fs::create_dir(dir.join("tests"))?;
+    fs::write(dir.join("tests").join("main.mw"), TEST)
+}
@@ -0,0 +1,156 @@
+//! Diagnostics are the errors and warnings reported by every phase of Meow
+//! before execution. Each one carries a [`Span`] pointing at the offending
+//! code, and can be rendered alongside the source line it refers to.
+
+use crate::span::Span;
+#[cfg(feature = "color")]
+use ansi_term::Colour::{self, Blue, Red, Yellow};
+use std::fmt;
+#[cfg(not(feature = "color"))]
+use Colour::{Blue, Red, Yellow};
+
+/// The severity of a [`Diagnostic`]. Only errors prevent a program from
+/// running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Level::Error => write!(f, "error"),
+            Level::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A single message about the source code, such as a syntax error or an
+/// unresolved name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub level: Level,
+    pub message: String,
+    pub span: Span,
+    /// Extra lines of context rendered after the source line.
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    /// Create a new error diagnostic.
+    pub fn error(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            level: Level::Error,
+            message: message.into(),
+            span,
+            notes: Vec::new(),
+        }
+    }
+
+    /// Create a new warning diagnostic.
+    pub fn warning(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            level: Level::Warning,
+            message: message.into(),
+            span,
+            notes: Vec::new(),
+        }
+    }
+
+    /// Add a note to the diagnostic.
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// Returns true if this diagnostic is an error.
+    pub fn is_error(&self) -> bool {
+        self.level == Level::Error
+    }
+
+    /// Render the diagnostic along with the line of `source` it points to,
+    /// underlining the span with carets, followed by its notes. When `color`
+    /// is false, or the `color` feature is off, no ANSI escape codes are
+    /// emitted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use meow::{diagnostics::Diagnostic, span::Span};
+    ///
+    /// let diagnostic = Diagnostic::error("division by zero", Span::new(1, 9, 5))
+    ///     .with_note("in `<script>` at 1:9");
+    /// let rendered = diagnostic.render("let x = 1 / 0;", false);
+    /// assert!(rendered.ends_with("  |         ^^^^^\n  = note: in `<script>` at 1:9\n"));
+    /// ```
+    pub fn render(&self, source: &str, color: bool) -> String {
+        let level = match self.level {
+            Level::Error => bold(color, Red, "error"),
+            Level::Warning => bold(color, Yellow, "warning"),
+        };
+        let mut out = format!("{}: {}\n", level, self.message);
+
+        let line = match source
+            .lines()
+            .nth(self.span.line.saturating_sub(1) as usize)
+        {
+            Some(line) => line,
+            None => {
+                for note in &self.notes {
+                    out.push_str(&format!("= note: {}\n", note));
+                }
+                return out;
+            }
+        };
+        let number = self.span.line.to_string();
+        let gutter = " ".repeat(number.len());
+        let paint = |s: &str| bold(color, Blue, s);
+
+        let padding = " ".repeat(self.span.column.saturating_sub(1) as usize);
+        let available = (line.chars().count() as u32 + 1).saturating_sub(self.span.column);
+        let carets = "^".repeat(self.span.length.min(available).max(1) as usize);
+
+        out.push_str(&format!("{}{} {}\n", gutter, paint("-->"), self.span));
+        out.push_str(&format!("{} {}\n", gutter, paint("|")));
+        out.push_str(&format!("{} {} {}\n", paint(&number), paint("|"), line));
+        out.push_str(&format!(
+            "{} {} {}{}\n",
+            gutter,
+            paint("|"),
+            padding,
+            carets
+        ));
+        for note in &self.notes {
+            out.push_str(&format!("{} {} note: {}\n", gutter, paint("="), note));
+        }
+        out
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {}: {}", self.level, self.span, self.message)
+    }
+}
+
+/// Stand-ins for the colors diagnostics are painted in, which are never
+/// used without the `color` feature.
+#[cfg(not(feature = "color"))]
+#[derive(Clone, Copy)]
+enum Colour {
+    Blue,
+    Red,
+    Yellow,
+}
+
+/// Return `text` in bold `colour` if `color` is true, or as it is otherwise.
+fn bold(color: bool, colour: Colour, text: &str) -> String {
+    #[cfg(feature = "color")]
+    if color {
+        return colour.bold().paint(text).to_string();
+    }
+    #[cfg(not(feature = "color"))]
+    let _ = (color, colour);
+    text.to_string()
+}
@@ -0,0 +1,142 @@
+//! Documentation for a program, listing the functions and classes it
+//! declares at its top level, printed by `meow --doc` as Markdown or HTML.
+//!
+//! The `///` comments before a declaration are its documentation, and are
+//! included as they are written in Markdown, or as paragraphs in HTML.
+
+use crate::DocFormat;
+use meow::parser::ast::{FunDecl, Stmt};
+use std::fmt::Write;
+
+/// Return the signature of `fun`, such as `add(a, b)`.
+fn signature(fun: &FunDecl) -> String {
+    let params: Vec<_> = fun.params.iter().map(|param| param.name.as_str()).collect();
+    format!("{}({})", fun.name, params.join(", "))
+}
+
+/// Return `text` with the characters that are special in HTML escaped.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Write `doc` to `out` as HTML paragraphs, which blank lines separate.
+fn paragraphs(out: &mut String, doc: &str) {
+    for paragraph in doc
+        .split("\n\n")
+        .filter(|paragraph| !paragraph.trim().is_empty())
+    {
+        writeln!(out, "<p>{}</p>", escape(paragraph)).unwrap();
+    }
+}
+
+/// Render the documentation of `program`, the module called `name`.
+pub fn render(name: &str, program: &[Stmt], format: DocFormat) -> String {
+    let functions: Vec<_> = program
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Stmt::Fun(fun) => Some(fun),
+            _ => None,
+        })
+        .collect();
+    let classes: Vec<_> = program
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Stmt::Class {
+                name, methods, doc, ..
+            } => Some((name, methods, doc)),
+            _ => None,
+        })
+        .collect();
+
+    let mut out = String::new();
+    match format {
+        DocFormat::Markdown => {
+            writeln!(out, "# {}", name).unwrap();
+            if !functions.is_empty() {
+                writeln!(out, "\n## Functions").unwrap();
+                for fun in &functions {
+                    writeln!(out, "\n### `{}`", signature(fun)).unwrap();
+                    if let Some(doc) = &fun.doc {
+                        writeln!(out, "\n{}", doc).unwrap();
+                    }
+                }
+            }
+            if !classes.is_empty() {
+                writeln!(out, "\n## Classes").unwrap();
+                for (name, methods, doc) in &classes {
+                    writeln!(out, "\n### `{}`", name).unwrap();
+                    if let Some(doc) = doc {
+                        writeln!(out, "\n{}", doc).unwrap();
+                    }
+                    if !methods.is_empty() {
+                        out.push('\n');
+                    }
+                    for method in methods.iter() {
+                        write!(out, "- `{}`", signature(method)).unwrap();
+                        // The rest of the item is indented to keep it in
+                        // the list
+                        if let Some(doc) = &method.doc {
+                            write!(out, ": {}", doc.replace('\n', "\n  ")).unwrap();
+                        }
+                        out.push('\n');
+                    }
+                }
+            }
+        }
+        DocFormat::Html => {
+            let name = escape(name);
+            writeln!(out, "<!DOCTYPE html>").unwrap();
+            writeln!(out, "<html>").unwrap();
+            writeln!(
+                out,
+                "<head><meta charset=\"utf-8\"><title>{}</title></head>",
+                name
+            )
+            .unwrap();
+            writeln!(out, "<body>").unwrap();
+            writeln!(out, "<h1>{}</h1>", name).unwrap();
+            if !functions.is_empty() {
+                writeln!(out, "<h2>Functions</h2>").unwrap();
+                for fun in &functions {
+                    writeln!(out, "<h3><code>{}</code></h3>", escape(&signature(fun))).unwrap();
+                    if let Some(doc) = &fun.doc {
+                        paragraphs(&mut out, doc);
+                    }
+                }
+            }
+            if !classes.is_empty() {
+                writeln!(out, "<h2>Classes</h2>").unwrap();
+                for (name, methods, doc) in &classes {
+                    writeln!(out, "<h3><code>{}</code></h3>", escape(name)).unwrap();
+                    if let Some(doc) = doc {
+                        paragraphs(&mut out, doc);
+                    }
+                    if methods.is_empty() {
+                        continue;
+                    }
+                    writeln!(out, "<ul>").unwrap();
+                    for method in methods.iter() {
+                        let signature = escape(&signature(method));
+                        match &method.doc {
+                            Some(doc) => writeln!(
+                                out,
+                                "<li><code>{}</code>: {}</li>",
+                                signature,
+                                escape(doc)
+                            ),
+                            None => writeln!(out, "<li><code>{}</code></li>", signature),
+                        }
+                        .unwrap();
+                    }
+                    writeln!(out, "</ul>").unwrap();
+                }
+            }
+            writeln!(out, "</body>").unwrap();
+            writeln!(out, "</html>").unwrap();
+        }
+    }
+    out
+}
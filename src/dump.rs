@@ -0,0 +1,174 @@
+//! Dumps of what the interpreter makes of a program before running it, for
+//! working on the language and writing tools for it. Each dump is printed in
+//! a form for people to read, or as JSON for programs.
+
+use crate::FormatArg;
+use meow::{
+    diagnostics::Diagnostic,
+    lex,
+    lexer::token::{Token, TokenKind},
+    lint::Lint,
+    parser::ast::Stmt,
+};
+use std::{fmt::Write, iter, ops::Range};
+
+/// Return every token in `source` before the end of it, along with the range
+/// of bytes it covers.
+pub fn tokens(source: &str) -> Vec<(Token<'_>, Range<usize>)> {
+    // The byte offset of every char, and of the end of the source, since
+    // tokens are located by chars
+    let offsets: Vec<_> = source
+        .char_indices()
+        .map(|(offset, _)| offset)
+        .chain([source.len()])
+        .collect();
+    let line_starts: Vec<_> = iter::once(0)
+        .chain(
+            source
+                .chars()
+                .enumerate()
+                .filter(|(_, c)| *c == '\n')
+                .map(|(i, _)| i + 1),
+        )
+        .collect();
+
+    let mut tokens = Vec::new();
+    let mut lexer = lex(source);
+    loop {
+        let token = lexer.next_token();
+        if token.kind == TokenKind::Eof {
+            return tokens;
+        }
+        let first = line_starts[token.line as usize - 1] + token.column as usize - 1;
+        let range = offsets[first]..offsets[first + token.length as usize];
+        tokens.push((token, range));
+    }
+}
+
+/// Return the name of the kind of `token`, without the data it holds.
+fn kind_name(token: &Token) -> String {
+    let kind = format!("{:?}", token.kind);
+    match kind.find('(') {
+        Some(end) => kind[..end].to_string(),
+        None => kind,
+    }
+}
+
+/// Print the tokens in `source`, one per line with its location, kind and
+/// text, or as a JSON array of objects.
+pub fn print_tokens(source: &str, format: FormatArg) {
+    let tokens = tokens(source);
+    match format {
+        FormatArg::Text => {
+            for (token, range) in tokens {
+                let location = format!("{}:{}", token.line, token.column);
+                let mut line =
+                    format!("{:<8}{:<16}{}", location, kind_name(&token), &source[range]);
+                if let TokenKind::Error(message) = &token.kind {
+                    write!(line, "  ({})", message).unwrap();
+                }
+                println!("{}", line.trim_end());
+            }
+        }
+        FormatArg::Json => {
+            let mut out = String::from("[");
+            for (index, (token, range)) in tokens.iter().enumerate() {
+                out.push_str(if index == 0 { "\n  " } else { ",\n  " });
+                write!(
+                    out,
+                    "{{\"kind\": {}, \"lexeme\": {}, \"line\": {}, \"column\": {}, \"length\": {}",
+                    json_string(&kind_name(token)),
+                    json_string(&source[range.clone()]),
+                    token.line,
+                    token.column,
+                    token.length
+                )
+                .unwrap();
+                if let TokenKind::Error(message) = &token.kind {
+                    write!(out, ", \"error\": {}", json_string(message)).unwrap();
+                }
+                out.push('}');
+            }
+            if !tokens.is_empty() {
+                out.push('\n');
+            }
+            out.push(']');
+            println!("{}", out);
+        }
+    }
+}
+
+/// Print the syntax tree of `program`, indented to show its structure, or
+/// as JSON.
+pub fn print_ast(program: &[Stmt], format: FormatArg) {
+    match format {
+        FormatArg::Text => println!("{:#?}", program),
+        // Nothing in the tree fails to serialize
+        FormatArg::Json => println!("{}", serde_json::to_string_pretty(program).unwrap()),
+    }
+}
+
+/// Print `diagnostics`, along with the lint that found each one, if one
+/// did. As JSON, each is an object with its level, lint, message, location
+/// and notes.
+pub fn print_diagnostics(
+    source: &str,
+    diagnostics: &[(Option<Lint>, Diagnostic)],
+    format: FormatArg,
+    color: bool,
+) {
+    match format {
+        FormatArg::Text => {
+            for (_, diagnostic) in diagnostics {
+                eprintln!("{}", diagnostic.render(source, color));
+            }
+        }
+        FormatArg::Json => {
+            let mut out = String::from("[");
+            for (index, (lint, diagnostic)) in diagnostics.iter().enumerate() {
+                out.push_str(if index == 0 { "\n  " } else { ",\n  " });
+                let lint = lint.map_or("null".to_string(), |lint| json_string(lint.name()));
+                let notes: Vec<_> = diagnostic
+                    .notes
+                    .iter()
+                    .map(|note| json_string(note))
+                    .collect();
+                write!(
+                    out,
+                    "{{\"level\": \"{}\", \"lint\": {}, \"message\": {}, \"line\": {}, \"column\": {}, \"length\": {}, \"notes\": [{}]}}",
+                    diagnostic.level,
+                    lint,
+                    json_string(&diagnostic.message),
+                    diagnostic.span.line,
+                    diagnostic.span.column,
+                    diagnostic.span.length,
+                    notes.join(", ")
+                )
+                .unwrap();
+            }
+            if !diagnostics.is_empty() {
+                out.push('\n');
+            }
+            out.push(']');
+            println!("{}", out);
+        }
+    }
+}
+
+/// Return `string` as a JSON string literal.
+fn json_string(string: &str) -> String {
+    let mut out = String::from("\"");
+    for c in string.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -1,3 +1,4 @@
+use crate::{diagnostics::Diagnostic, span::Span};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -5,6 +6,224 @@ pub enum InterpreterError {
     #[error("File {0} not found")]
     FileNotFound(String),
 
+    /// The program failed to compile or run. `source_code` is kept so the
+    /// diagnostics can be rendered.
+    #[error("the program failed with {} error(s)", .diagnostics.len())]
+    Failed {
+        source_code: String,
+        diagnostics: Vec<Diagnostic>,
+    },
+
+    /// A compiled `.mwc` file couldn't be loaded.
+    #[error("cannot load {path}: {error}")]
+    Load {
+        path: String,
+        #[source]
+        error: LoadError,
+    },
+
+    /// A project's `meow.toml` couldn't be read.
+    #[cfg(feature = "fs")]
+    #[error("cannot read {path}: {error}")]
+    Config {
+        path: String,
+        #[source]
+        error: ConfigError,
+    },
+
+    /// A module that isn't loaded was [reloaded](crate::reload).
+    #[cfg(feature = "fs")]
+    #[error("module `{0}` isn't loaded")]
+    ModuleNotLoaded(String),
+
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
+
+impl InterpreterError {
+    /// Describe the error without color, rendering each diagnostic of a
+    /// program that failed along with the line it points to. This is what
+    /// embedders that can't print diagnostics themselves show.
+    pub fn render(&self) -> String {
+        match self {
+            InterpreterError::Failed {
+                source_code,
+                diagnostics,
+            } => diagnostics
+                .iter()
+                .map(|diagnostic| diagnostic.render(source_code, false))
+                .collect(),
+            error => error.to_string(),
+        }
+    }
+}
+
+/// Errors from reading a project's `meow.toml`.
+#[cfg(feature = "fs")]
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error(transparent)]
+    Syntax(#[from] toml::de::Error),
+
+    #[error("unknown lint `{0}`")]
+    UnknownLint(String),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// Errors from loading compiled `.mwc` files and VM snapshots.
+#[derive(Debug, Error)]
+pub enum LoadError {
+    #[error("not a compiled Meow file")]
+    BadMagic,
+
+    #[error("not a Meow VM snapshot")]
+    NotASnapshot,
+
+    #[error("compiled with unsupported format version {0}")]
+    UnsupportedVersion(u16),
+
+    #[error("unexpected end of file")]
+    UnexpectedEof,
+
+    #[error("unexpected bytes after the end of the program")]
+    TrailingBytes,
+
+    #[error("invalid UTF-8 in string")]
+    InvalidUtf8,
+
+    #[error("unknown constant type {0}")]
+    UnknownConstant(u8),
+
+    #[error("malformed file: {0}")]
+    Malformed(&'static str),
+
+    #[error("cannot restore a snapshot while a program is paused")]
+    Paused,
+
+    /// A snapshot holds a function the host hasn't registered.
+    #[error("unknown host function `{0}`")]
+    UnknownHostFunction(String),
+
+    /// A snapshot holds a class the host hasn't registered.
+    #[error("unknown host class `{0}`")]
+    UnknownHostClass(String),
+
+    #[error(transparent)]
+    Invalid(#[from] VerifyError),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// Why a value couldn't be converted to a Rust type with
+/// [`FromMeow`](crate::vm::convert::FromMeow).
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ConversionError {
+    #[error("expected {expected}, found {found}")]
+    Type {
+        expected: &'static str,
+        found: &'static str,
+    },
+
+    #[error("int is too big to fit in 64 bits")]
+    OutOfRange,
+}
+
+/// A reason bytecode could crash the VM, found by
+/// [`verify`](crate::bytecode::verify::verify).
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("invalid bytecode in `{function}` at offset {offset}: {reason}")]
+pub struct VerifyError {
+    pub function: String,
+    pub offset: usize,
+    pub reason: &'static str,
+}
+
+/// What caused a [`RuntimeError`], for hosts that handle some errors
+/// differently from others.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuntimeErrorKind {
+    /// The program did something invalid, such as dividing by zero.
+    Failed,
+    /// The program used up the VM's instruction budget. See
+    /// [`Vm::with_fuel`](crate::vm::Vm::with_fuel).
+    FuelExhausted,
+    /// The host stopped the program with an
+    /// [`InterruptHandle`](crate::vm::InterruptHandle).
+    Interrupted,
+    /// The program called `panic`, or an `assert` failed.
+    Panicked,
+    /// The program tried to do something the VM's
+    /// [`SandboxPolicy`](crate::vm::sandbox::SandboxPolicy) denies.
+    Denied,
+    /// The program had more objects on the heap than the VM's
+    /// [`SandboxPolicy`](crate::vm::sandbox::SandboxPolicy) allows.
+    MemoryExhausted,
+}
+
+/// An error raised while executing a program, pointing at the code that
+/// caused it when debug information is available.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{message}")]
+pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
+    pub message: String,
+    pub span: Option<Span>,
+    /// The calls in progress when the error happened, innermost first. This
+    /// is filled in once the error reaches [`Vm::run`](crate::vm::Vm::run).
+    pub trace: Vec<TraceFrame>,
+}
+
+/// A function that was running when a [`RuntimeError`] happened.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceFrame {
+    pub function: String,
+    /// The instruction the function was running: the call to the next frame
+    /// in, or the instruction that failed in the innermost frame.
+    pub span: Option<Span>,
+}
+
+/// The most frames a rendered trace shows before leaving out the middle.
+const MAX_TRACE_NOTES: usize = 10;
+
+impl RuntimeError {
+    pub fn new(message: impl Into<String>, span: Option<Span>) -> Self {
+        Self {
+            kind: RuntimeErrorKind::Failed,
+            message: message.into(),
+            span,
+            trace: Vec::new(),
+        }
+    }
+
+    /// Convert the error into a [`Diagnostic`] so it can be rendered like
+    /// any other error. The trace becomes one note per frame, with locations
+    /// prefixed by `path` if the program came from a file.
+    pub fn to_diagnostic(&self, path: Option<&str>) -> Diagnostic {
+        let mut diagnostic = Diagnostic::error(self.message.clone(), self.span.unwrap_or_default());
+
+        let location = |span: Option<Span>| match (path, span) {
+            (Some(path), Some(span)) => format!(" at {}:{}", path, span),
+            (None, Some(span)) => format!(" at {}", span),
+            (_, None) => String::new(),
+        };
+        let notes = self.trace.iter().enumerate().map(|(i, frame)| {
+            let prefix = if i == 0 { "in" } else { "called from" };
+            format!("{} `{}`{}", prefix, frame.function, location(frame.span))
+        });
+
+        let skipped = self.trace.len().saturating_sub(MAX_TRACE_NOTES);
+        if skipped == 0 {
+            diagnostic.notes.extend(notes);
+        } else {
+            let mut notes = notes;
+            let half = MAX_TRACE_NOTES / 2;
+            diagnostic.notes.extend(notes.by_ref().take(half));
+            diagnostic.notes.push(format!("... {} more calls", skipped));
+            diagnostic.notes.extend(notes.skip(skipped));
+        }
+        diagnostic
+    }
+}
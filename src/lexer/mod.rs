@@ -3,19 +3,22 @@
 //! consume on demand. Consumption is done through the `.next_token()` method
 //! on the `Lexer` struct.
 //!
-//! The lexer is fairly standard, iterating through a
-//! [`Peekable`](std::iter::Peekable) sequence of [`Chars`](std::str::Chars),
-//! matching known tokens to variants of the `TokenKind` enum and inserting the
-//! `Invalid` variant for any unknown ones. Errors are not emitted here, but
-//! rather passed to the parser for it to handle.
+//! The lexer is fairly standard, moving a cursor through the bytes of the
+//! source and matching known tokens to variants of the `TokenKind` enum,
+//! inserting the `Error` variant for any unknown ones. ASCII is read a byte at
+//! a time, and only other characters are decoded from UTF-8, which is what
+//! identifiers and strings are mostly written in. Errors are not emitted here,
+//! but rather passed to the parser for it to handle.
 
 pub mod token;
 
-use std::{iter::Peekable, str::Chars};
+use crate::diagnostics::Diagnostic;
+use std::borrow::Cow;
 use token::{
     Token,
     TokenKind::{self, *},
 };
+use tracing::trace;
 
 /// Returns true if the `c` matches Unicode's Pattern_White_Space. This does
 /// not include `\n`, however, because that is handled separately.
@@ -43,14 +46,52 @@ fn is_whitespace(c: char) -> bool {
     )
 }
 
+/// Returns true if a line of a multiline string holds nothing but
+/// whitespace.
+fn is_blank(line: &str) -> bool {
+    line.bytes()
+        .all(|byte| matches!(byte, b' ' | b'\t' | b'\r'))
+}
+
+/// Returns the indentation that the lines of a multiline string's text all
+/// start with. Blank lines don't count, other than the last, which holds
+/// the closing quotes.
+fn shared_indentation(text: &str) -> &str {
+    let count = text.split('\n').count();
+    text.split('\n')
+        .enumerate()
+        .filter(|&(index, line)| index + 1 == count || !is_blank(line))
+        .map(|(_, line)| &line[..line.len() - line.trim_start_matches([' ', '\t']).len()])
+        .reduce(|shared, indent| {
+            let length = shared
+                .bytes()
+                .zip(indent.bytes())
+                .take_while(|(a, b)| a == b)
+                .count();
+            &shared[..length]
+        })
+        .unwrap_or("")
+}
+
 /// The `Lexer` struct provides the first step of Meow's execution. It accepts
 /// a UTF-8 encoded string, and converts it into a stream of `Token`s for the
 /// parser to use to generate an AST.
 pub struct Lexer<'a> {
-    source: Peekable<Chars<'a>>,
+    source: &'a str,
+    /// The number of chars consumed, plus one.
     position: usize,
+    /// The byte offset of the next char in `source`.
+    offset: usize,
     line: u32,
     column: u32,
+    start_position: usize,
+    start_offset: usize,
+    start_line: u32,
+    start_column: u32,
+    /// For each `${` being lexed in an interpolated string, innermost last,
+    /// how many braces have been opened in its expression and not yet
+    /// closed. The `}` that closes none of them ends the expression.
+    interpolations: Vec<u32>,
 }
 
 impl<'a> Lexer<'a> {
@@ -71,10 +112,16 @@ impl<'a> Lexer<'a> {
     /// ```
     pub fn new(source: &'a str) -> Self {
         Self {
-            source: source.chars().peekable(),
+            source,
             position: 1,
+            offset: 0,
             line: 1,
             column: 1,
+            start_position: 1,
+            start_offset: 0,
+            start_line: 1,
+            start_column: 1,
+            interpolations: Vec::new(),
         }
     }
 
@@ -83,7 +130,7 @@ impl<'a> Lexer<'a> {
     fn advance(&mut self) -> Option<char> {
         self.position += 1;
         self.column += 1;
-        self.source.next()
+        self.next_char()
     }
 
     /// Move a single position and line forward in the lexer, and reset the
@@ -92,7 +139,27 @@ impl<'a> Lexer<'a> {
         self.position += 1;
         self.line += 1;
         self.column = 1;
-        self.source.next()
+        self.next_char()
+    }
+
+    /// Consume the next char in the source, moving `offset` past its bytes.
+    fn next_char(&mut self) -> Option<char> {
+        if self.at_end() {
+            return None;
+        }
+        let char = self.peek();
+        self.offset += char.len_utf8();
+        Some(char)
+    }
+
+    /// Return the char starting at byte `offset`, or `\0` past the end of the
+    /// source. Only chars outside ASCII are decoded.
+    fn char_at(&self, offset: usize) -> char {
+        match self.source.as_bytes().get(offset) {
+            None => '\0',
+            Some(&byte) if byte.is_ascii() => byte as char,
+            Some(_) => self.source[offset..].chars().next().unwrap(),
+        }
     }
 
     /// Advance tokens while being aware of newlines.
@@ -106,20 +173,36 @@ impl<'a> Lexer<'a> {
 
     /// Return the next char in the source without consuming it, or return `\0`
     /// if it is `None`.
-    fn peek(&mut self) -> char {
-        *self.source.peek().unwrap_or(&'\0')
+    fn peek(&self) -> char {
+        self.char_at(self.offset)
+    }
+
+    /// Return the char after the next one without consuming anything, or
+    /// return `\0` if it is `None`.
+    fn peek_next(&self) -> char {
+        self.char_at(self.offset + self.peek().len_utf8())
     }
 
     // Return true or false based on whether the lexer is at the end of the source code
-    fn at_end(&mut self) -> bool {
-        self.peek() == '\0'
+    fn at_end(&self) -> bool {
+        self.offset >= self.source.len()
     }
 
-    /// Given a `TokenKind`, create an `Token` with the `line` and `column`
-    /// data from the lexer.
-    fn create_token(&mut self, kind: TokenKind, length: u32) -> Token {
-        let position = self.position as u32 - length;
-        Token::new(kind, self.line, position)
+    /// Mark the current position as the start of the next token.
+    fn start_token(&mut self) {
+        self.start_position = self.position;
+        self.start_offset = self.offset;
+        self.start_line = self.line;
+        self.start_column = self.column;
+    }
+
+    /// Given a `TokenKind`, create an `Token` spanning from the start of the
+    /// current token up to the lexer's position.
+    fn create_token(&mut self, kind: TokenKind<'a>) -> Token<'a> {
+        let length = (self.position - self.start_position) as u32;
+        let token = Token::new(kind, self.start_line, self.start_column, length);
+        trace!(%token, "lexed");
+        token
     }
 
     /// Match the next token. If it's the expected character, generate a
@@ -127,229 +210,543 @@ impl<'a> Lexer<'a> {
     fn with_single_or_double(
         &mut self,
         expected_double: char,
-        single: TokenKind,
-        double: TokenKind,
-    ) -> Token {
+        single: TokenKind<'a>,
+        double: TokenKind<'a>,
+    ) -> Token<'a> {
         if self.peek() == expected_double {
-            let token = self.create_token(double, 2);
             self.advance();
-            token
+            self.create_token(double)
         } else {
-            self.create_token(single, 1)
+            self.create_token(single)
         }
     }
 
     /// Match the next token. If it's the expected character, generate a
     /// specified token. Otherwise, generate an Invalid token.
-    fn with_double(&mut self, expected: char, kind: TokenKind) -> Token {
+    fn with_double(&mut self, expected: char, kind: TokenKind<'a>) -> Token<'a> {
         let c = self.peek();
         if c == expected {
-            let token = self.create_token(kind, 1);
             self.advance();
-            token
+            self.create_token(kind)
         } else {
-            self.create_token(
-                Error(format!("Unknown character `{}` found in source", c)),
-                1,
-            )
+            self.create_token(Error(format!("Unknown character `{}` found in source", c)))
         }
     }
 
-    // Lexes a string
-    fn lex_string(&mut self) -> Token {
+    // Lexes a string, or the part of an interpolated one after the `}` that
+    // ends an expression in it if `continued` is true, up to its closing
+    // quote or next `${`
+    fn lex_string(&mut self, continued: bool) -> Token<'a> {
+        let start = self.offset;
+        // The text is sliced out of the source, until an escape means it
+        // has to be built up instead
+        let mut value: Option<String> = None;
+        let mut invalid = None;
+        // Strings can't span lines, which `"""` strings are for, so one left
+        // open ends with its line and the lines after it are lexed as code
+        while !matches!(self.peek(), '"' | '\n') && !self.at_end() {
+            if self.peek() == '$' && self.peek_next() == '{' {
+                let value = self.string_value(start, value);
+                self.advance();
+                self.advance();
+                self.interpolations.push(0);
+                return self.create_token(if continued {
+                    StrMiddle(value)
+                } else {
+                    StrStart(value)
+                });
+            }
+            if self.peek() == '\\' {
+                let (source, end) = (self.source, self.offset);
+                match self.lex_escape() {
+                    Ok(char) => value
+                        .get_or_insert_with(|| source[start..end].to_string())
+                        .push(char),
+                    // The rest of the string is still read, so that lexing
+                    // carries on after it, but only the first bad escape is
+                    // reported
+                    Err(token) => invalid = invalid.or(Some(token)),
+                }
+                continue;
+            }
+            let char = self.advance().unwrap();
+            if let Some(value) = &mut value {
+                value.push(char);
+            }
+        }
+
+        if self.peek() != '"' {
+            return self.create_token(Error(
+                "Unterminated string literal, expected closing quote before the end of the line"
+                    .to_string(),
+            ));
+        }
+
+        let value = self.string_value(start, value);
+        self.advance();
+        if let Some(token) = invalid {
+            return token;
+        }
+        self.create_token(if continued { StrEnd(value) } else { Str(value) })
+    }
+
+    // Lexes a `"""` string after its opening quotes. Its text starts on the
+    // line after them, and the indentation its lines share is removed. A
+    // last line holding only the closing quotes sets that indentation, and
+    // is left out along with the newline before it. Escapes work as in
+    // other strings, but `${` is kept as text rather than interpolated.
+    fn lex_multiline_string(&mut self) -> Token<'a> {
+        let opening_line = self.source[self.offset..].split('\n').next().unwrap();
+        let Some(close) = self.find_closing_quotes() else {
+            // There is no telling where the string was meant to end, so the
+            // error takes the rest of the source
+            while !self.at_end() {
+                self.newline_aware_advance();
+            }
+            return self.create_token(Error(
+                "Unterminated multiline string literal, expected closing `\"\"\"`".to_string(),
+            ));
+        };
+        if !is_blank(opening_line) {
+            while self.offset < close + 3 {
+                self.newline_aware_advance();
+            }
+            return self.create_token(Error(
+                "Expected a new line after the opening `\"\"\"` of a multiline string".to_string(),
+            ));
+        }
+        while self.newline_aware_advance() != Some('\n') {}
+
+        let text = &self.source[self.offset..close];
+        let indent = shared_indentation(text);
+        let count = text.split('\n').count();
         let mut value = String::new();
+        let mut invalid = None;
+        for (index, line) in text.split('\n').enumerate() {
+            let last = index + 1 == count;
+            if last && is_blank(line) {
+                break;
+            }
+            if index > 0 {
+                value.push('\n');
+            }
 
-        while self.peek() != '"' {
-            let char = self.newline_aware_advance();
-            value.push(char.unwrap());
+            // Blank lines can be indented less than the others
+            let skip = line
+                .bytes()
+                .zip(indent.bytes())
+                .take_while(|(a, b)| a == b)
+                .count();
+            for _ in 0..skip {
+                self.advance();
+            }
+            let line_end = self.offset + line.len() - skip;
+            while self.offset < line_end {
+                if self.peek() == '\\' {
+                    match self.lex_escape() {
+                        Ok(char) => value.push(char),
+                        Err(token) => invalid = invalid.or(Some(token)),
+                    }
+                } else {
+                    value.push(self.advance().unwrap());
+                }
+            }
+            if !last {
+                self.advance_line();
+            }
+        }
+        while self.offset < close + 3 {
+            self.advance();
+        }
+
+        match invalid {
+            Some(token) => token,
+            None => self.create_token(Str(Cow::Owned(value))),
         }
+    }
 
-        let length = value.len() as u32;
+    // Returns the byte offset of the `"""` that closes a multiline string,
+    // skipping over escaped chars
+    fn find_closing_quotes(&self) -> Option<usize> {
+        let bytes = self.source.as_bytes();
+        let mut offset = self.offset;
+        while offset < bytes.len() {
+            match bytes[offset] {
+                b'\\' => offset += 2,
+                b'"' if bytes[offset..].starts_with(b"\"\"\"") => return Some(offset),
+                _ => offset += 1,
+            }
+        }
+        None
+    }
 
-        if self.at_end() {
-            return self.create_token(Error("Unterminated string literal, expected closing quote, EOF (End of File) encountered".to_string()), length + 1);
+    // Returns the text of a string lexed from `start` up to the lexer's
+    // position, which is `value` if it had to be built up
+    fn string_value(&self, start: usize, value: Option<String>) -> Cow<'a, str> {
+        match value {
+            Some(value) => Cow::Owned(value),
+            None => Cow::Borrowed(&self.source[start..self.offset]),
         }
+    }
 
+    // Lexes an escape sequence in a string or char literal, starting at its
+    // backslash, returning the char it stands for. A bad escape gives an
+    // error token spanning just the escape.
+    fn lex_escape(&mut self) -> Result<char, Token<'a>> {
+        let (line, column, position) = (self.line, self.column, self.position);
         self.advance();
-        self.create_token(Str(value), length + 2)
+        let char = match self.peek() {
+            'u' => {
+                self.advance();
+                return self.lex_unicode_escape().map_err(|message| {
+                    let length = (self.position - position) as u32;
+                    Token::new(Error(message), line, column, length)
+                });
+            }
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            '\\' => '\\',
+            '\'' => '\'',
+            '"' => '"',
+            '$' => '$',
+            c => {
+                // A backslash at the end of a line or the source escapes
+                // nothing, and the newline or end is left for the literal to
+                // stop at
+                let message = if c == '\n' || self.at_end() {
+                    "Expected an escape sequence after `\\`".to_string()
+                } else {
+                    self.advance();
+                    format!("Unknown escape sequence `\\{}`", c)
+                };
+                let length = (self.position - position) as u32;
+                return Err(Token::new(Error(message), line, column, length));
+            }
+        };
+        self.advance();
+        Ok(char)
     }
 
-    // Lexes either an integer or a float
-    fn lex_number(&mut self, first_char: char) -> Token {
-        let mut is_integer = true;
+    // Lexes the rest of a `\u{...}` escape after its `u`, made of one to six
+    // hex digits, returning the char with that code point or a description
+    // of what is wrong with it
+    fn lex_unicode_escape(&mut self) -> Result<char, String> {
+        if self.peek() != '{' {
+            return Err("Expected `{` after `\\u` in unicode escape".to_string());
+        }
+        self.advance();
 
-        let mut value = String::from(first_char);
+        // Letters and digits are all taken as part of the escape, so that a
+        // bad one is reported along with the rest
+        let start = self.offset;
+        while self.peek().is_ascii_alphanumeric() {
+            self.advance();
+        }
+        let digits = &self.source[start..self.offset];
+        let closed = self.peek() == '}';
+        if closed {
+            self.advance();
+        }
 
-        if self.at_end() {
-            return self.create_token(TokenKind::Int(value), 1);
+        if let Some(c) = digits.chars().find(|c| !c.is_ascii_hexdigit()) {
+            return Err(format!("Invalid hex digit `{}` in unicode escape", c));
         }
+        if !closed {
+            return Err("Unterminated unicode escape, expected `}`".to_string());
+        }
+        if digits.is_empty() {
+            return Err("Unicode escape has no hex digits".to_string());
+        }
+        if digits.len() > 6 {
+            return Err("Unicode escape has more than 6 hex digits".to_string());
+        }
+        let code = u32::from_str_radix(digits, 16).unwrap();
+        char::from_u32(code).ok_or_else(|| match code {
+            0xD800..=0xDFFF => format!(
+                "Unicode escape `\\u{{{}}}` is a surrogate, not a char",
+                digits
+            ),
+            _ => format!("Unicode escape `\\u{{{}}}` is out of range", digits),
+        })
+    }
 
-        while self.peek().is_numeric() {
-            let char = self.advance();
-            value.push(char.unwrap());
+    // Lexes a `///` doc comment after its first slash, up to the end of its
+    // line
+    fn lex_doc_comment(&mut self) -> Token<'a> {
+        self.advance();
+        self.advance();
+        let start = self.offset;
+        while self.peek() != '\n' && !self.at_end() {
+            self.advance();
         }
 
-        if self.peek() == '.' {
+        // The space usually written after the slashes isn't part of the text
+        let value = &self.source[start..self.offset];
+        let text = value.strip_prefix(' ').unwrap_or(value);
+        self.create_token(DocComment(text.trim_end_matches('\r')))
+    }
+
+    // Returns the text of the number being lexed, without the underscores
+    // that can separate its digits
+    fn number_value(&self) -> Cow<'a, str> {
+        let value = &self.source[self.start_offset..self.offset];
+        if value.contains('_') {
+            Cow::Owned(value.replace('_', ""))
+        } else {
+            Cow::Borrowed(value)
+        }
+    }
+
+    // Lexes an integer after its `0x`, `0o` or `0b` prefix, keeping the
+    // prefix in its value
+    fn lex_radix_number(&mut self) -> Token<'a> {
+        let prefix = self.advance().unwrap();
+        let (radix, name) = match prefix {
+            'x' => (16, "Hexadecimal"),
+            'o' => (8, "Octal"),
+            _ => (2, "Binary"),
+        };
+
+        // Letters and digits after the prefix are all taken as part of the
+        // number, so that `0b12` is one malformed literal rather than two
+        let mut invalid = None;
+        let mut digits = 0;
+        while self.peek().is_ascii_alphanumeric() || self.peek() == '_' {
+            let char = self.advance().unwrap();
+            if char == '_' {
+                continue;
+            } else if !char.is_digit(radix) {
+                invalid = invalid.or(Some(char));
+            }
+            digits += 1;
+        }
+
+        if let Some(char) = invalid {
+            return self.create_token(Error(format!(
+                "{} literal contains invalid digit `{}`",
+                name, char
+            )));
+        }
+        if digits == 0 {
+            return self.create_token(Error(format!(
+                "{} literal has no digits after `0{}`",
+                name, prefix
+            )));
+        }
+        self.create_token(TokenKind::Int(self.number_value()))
+    }
+
+    // Consumes a run of digits, along with the underscores that can
+    // separate them
+    fn lex_digits(&mut self) {
+        while self.peek().is_numeric() || self.peek() == '_' {
+            self.advance();
+        }
+    }
+
+    // Lexes either an integer or a float, which has a fraction or an exponent
+    fn lex_number(&mut self, first_char: char) -> Token<'a> {
+        let mut is_integer = true;
+
+        if first_char == '0' && matches!(self.peek(), 'x' | 'o' | 'b') {
+            return self.lex_radix_number();
+        }
+
+        self.lex_digits();
+
+        // A dot only continues the number when a digit follows it, so that
+        // `0..10` lexes as a range
+        if self.peek() == '.' && self.peek_next().is_numeric() {
             // Set is_integer to false, since dot indicates that value is a decimal
             is_integer = false;
-            // Consume and add dot to value
-            value.push(self.advance().unwrap());
-            while self.peek().is_numeric() {
-                value.push(self.advance().unwrap())
-            }
+            self.advance();
+            self.lex_digits();
         }
 
-        let length = value.len() as u32;
+        // An exponent only continues the number when a digit follows it,
+        // after an optional sign
+        if matches!(self.peek(), 'e' | 'E') {
+            let mut digit = self.char_at(self.offset + 1);
+            if matches!(digit, '+' | '-') {
+                digit = self.char_at(self.offset + 2);
+            }
+            if digit.is_numeric() {
+                is_integer = false;
+                self.advance();
+                if matches!(self.peek(), '+' | '-') {
+                    self.advance();
+                }
+                self.lex_digits();
+            }
+        }
 
-        self.create_token(
-            if is_integer {
-                TokenKind::Int(value)
-            } else {
-                TokenKind::Float(value)
-            },
-            length,
-        )
+        let value = self.number_value();
+        self.create_token(if is_integer {
+            TokenKind::Int(value)
+        } else {
+            TokenKind::Float(value)
+        })
     }
 
     // Checks whether a given value matches the keyword
     fn get_keyword(
         &self,
-        value: &str,
+        value: &'a str,
         keyword: &str,
         length: usize,
-        token: TokenKind,
-    ) -> TokenKind {
+        token: TokenKind<'a>,
+    ) -> TokenKind<'a> {
         if value[length..] == keyword[length..] {
             token
         } else {
-            TokenKind::Ident(value.to_string())
+            TokenKind::Ident(value)
         }
     }
 
     // Use a state machine to single out Meow keywords
-    fn ident_type(&self, value: &str) -> TokenKind {
+    fn ident_type(&self, value: &'a str) -> TokenKind<'a> {
+        // Every keyword is ASCII, and the byte slices below would split the
+        // characters of other identifiers
+        if !value.is_ascii() {
+            return TokenKind::Ident(value);
+        }
+
         match &value[..1] {
+            "a" => self.get_keyword(value, "as", 1, TokenKind::As),
             "c" => self.get_keyword(value, "class", 1, TokenKind::Class),
             "e" => self.get_keyword(value, "else", 1, TokenKind::Else),
             "f" => {
                 if value.len() < 2 {
-                    TokenKind::Ident(value.to_string());
+                    return TokenKind::Ident(value);
                 }
 
                 match &value[1..2] {
                     "a" => self.get_keyword(value, "false", 2, TokenKind::False),
                     "o" => self.get_keyword(value, "for", 2, TokenKind::For),
                     "u" => self.get_keyword(value, "fun", 2, TokenKind::Fun),
-                    _ => TokenKind::Ident(value.to_string()),
+                    _ => TokenKind::Ident(value),
                 }
             }
             "i" => {
                 if value.len() < 2 {
-                    return TokenKind::Ident(value.to_string());
+                    return TokenKind::Ident(value);
                 }
 
                 match &value[1..2] {
-                    "f" => TokenKind::If,
+                    "f" => self.get_keyword(value, "if", 2, TokenKind::If),
+                    "n" => self.get_keyword(value, "in", 2, TokenKind::In),
                     "m" => {
                         if value.len() < 5 {
-                            return TokenKind::Ident(value.to_string());
+                            return TokenKind::Ident(value);
                         }
 
                         if &value[2..3] == "p" {
                             return match &value[3..4] {
                                 "o" => self.get_keyword(value, "import", 4, TokenKind::Import),
                                 "l" => self.get_keyword(value, "impls", 4, TokenKind::Impls),
-                                _ => TokenKind::Ident(value.to_string()),
+                                _ => TokenKind::Ident(value),
                             };
                         }
 
-                        TokenKind::Ident(value.to_string())
+                        TokenKind::Ident(value)
                     }
-                    _ => TokenKind::Ident(value.to_string()),
+                    _ => TokenKind::Ident(value),
                 }
             }
             "l" => self.get_keyword(value, "let", 1, TokenKind::Let),
             "m" => {
                 if value.len() < 2 {
-                    return TokenKind::Ident(value.to_string());
+                    return TokenKind::Ident(value);
                 }
 
                 match &value[1..2] {
                     "a" => self.get_keyword(value, "match", 2, TokenKind::Match),
                     "u" => self.get_keyword(value, "mut", 2, TokenKind::Mut),
-                    _ => TokenKind::Ident(value.to_string()),
+                    _ => TokenKind::Ident(value),
                 }
             }
+            "p" => self.get_keyword(value, "pub", 1, TokenKind::Pub),
             "r" => self.get_keyword(value, "return", 1, TokenKind::Return),
+            "s" => self.get_keyword(value, "spawn", 1, TokenKind::Spawn),
             "t" => {
                 if value.len() < 3 {
-                    return TokenKind::Ident(value.to_string());
+                    return TokenKind::Ident(value);
                 }
 
-                if !(&value[1..2] == "r") {
-                    return TokenKind::Ident(value.to_string());
+                if &value[1..2] != "r" {
+                    return TokenKind::Ident(value);
                 }
 
                 match &value[2..3] {
                     "u" => self.get_keyword(value, "true", 3, TokenKind::True),
                     "a" => self.get_keyword(value, "trait", 3, TokenKind::Trait),
-                    _ => TokenKind::Ident(value.to_string()),
+                    _ => TokenKind::Ident(value),
                 }
             }
-            "w" => return self.get_keyword(value, "while", 1, TokenKind::While),
-            _ => TokenKind::Ident(value.to_string()),
+            "w" => self.get_keyword(value, "while", 1, TokenKind::While),
+            "y" => self.get_keyword(value, "yield", 1, TokenKind::Yield),
+            _ => TokenKind::Ident(value),
         }
     }
 
     // Lexes identifiers and keywords
-    fn get_ident(&mut self, first_char: char) -> Token {
-        let mut value = String::from(first_char);
-
-        if self.at_end() {
-            return self.create_token(TokenKind::Ident(value), 1);
-        }
-
-        // Add to the eventual value as long as the next character is a valid identifer
+    fn get_ident(&mut self) -> Token<'a> {
+        // Take characters as long as the next one continues an identifier
         while unicode_xid::UnicodeXID::is_xid_continue(self.peek()) {
-            let char = self.advance();
-            value.push(char.unwrap());
+            self.advance();
         }
 
-        let token_type = self.ident_type(&value);
-        self.create_token(token_type, value.len() as u32)
+        let token_type = self.ident_type(&self.source[self.start_offset..self.offset]);
+        self.create_token(token_type)
     }
 
-    // Lexes a single char
-    fn lex_char(&mut self) -> Token {
-        // If at end, create an error token since there isn't a closing quote
-        if self.at_end() {
-            return self.create_token(
-                Error("Unterminated char literal, expected closing single quote".to_string()),
-                1,
-            );
-        }
+    // Lexes a single char, which may be written as an escape sequence
+    fn lex_char(&mut self) -> Token<'a> {
+        let unterminated = "Unterminated char literal, expected closing single quote";
 
-        let value = self.advance().unwrap();
+        // If at end or the line ends, create an error token since there isn't
+        // a closing quote
+        if self.at_end() || self.peek() == '\n' {
+            return self.create_token(Error(unterminated.to_string()));
+        }
 
-        // If the value is a closing quote, create an error token since empty char literals aren't allowed
-        if value == '\'' {
-            return self.create_token(
-                Error("Unterminated char literal, expected closing single quote".to_string()),
-                1,
-            );
+        // If the next char is the closing quote, create an error token since
+        // empty char literals aren't allowed
+        if self.peek() == '\'' {
+            self.advance();
+            return self.create_token(Error(
+                "Empty char literal, expected a character between the quotes".to_string(),
+            ));
         }
 
+        let value = if self.peek() == '\\' {
+            self.lex_escape()
+        } else {
+            Ok(self.advance().unwrap())
+        };
+
+        // Anything else before the closing quote is skipped along with it,
+        // up to the end of the line, so that it isn't lexed as code
+        let closed = self.peek() == '\'';
+        while !self.at_end() && !matches!(self.peek(), '\'' | '\n') {
+            self.advance();
+        }
         // If no closing quote is found, create an error token
         if self.peek() != '\'' {
-            return self.create_token(
-                Error("Unterminated char literal, expected closing single quote".to_string()),
-                1,
-            );
+            return self.create_token(Error(unterminated.to_string()));
         }
         // Consume closing quote
         self.advance();
 
-        self.create_token(Char(value), 1)
+        match value {
+            Err(token) => token,
+            Ok(_) if !closed => self.create_token(Error(
+                "Char literal contains more than one character, use a string instead".to_string(),
+            )),
+            Ok(value) => self.create_token(Char(value)),
+        }
     }
 
     /// Return the next `Token` for use in the parser. This is the method that
@@ -377,7 +774,11 @@ impl<'a> Lexer<'a> {
     ///     }
     /// }
     /// ```
-    pub fn next_token(&mut self) -> Token {
+    pub fn next_token(&mut self) -> Token<'a> {
+        while is_whitespace(self.peek()) {
+            self.newline_aware_advance();
+        }
+        self.start_token();
         let next = self.newline_aware_advance();
 
         if let Some(c) = next {
@@ -385,12 +786,12 @@ impl<'a> Lexer<'a> {
                 // range characters
                 '.' if self.peek() == '.' => {
                     self.advance();
-                    let mut token = self.create_token(Range, 2);
                     if self.peek() == '=' {
                         self.advance();
-                        token = self.create_token(RangeInclusive, 3);
+                        self.create_token(RangeInclusive)
+                    } else {
+                        self.create_token(Range)
                     }
-                    token
                 }
 
                 // literals
@@ -398,35 +799,62 @@ impl<'a> Lexer<'a> {
                 // identifiers and keywords
 
                 // simple single character tokens
-                '(' => self.create_token(OpenParen, 1),
-                ')' => self.create_token(CloseParen, 1),
-                '[' => self.create_token(OpenBracket, 1),
-                ']' => self.create_token(CloseBracket, 1),
-                '{' => self.create_token(OpenBrace, 1),
-                '}' => self.create_token(CloseBrace, 1),
-                ',' => self.create_token(Comma, 1),
-                '.' => self.create_token(Dot, 1),
-                ';' => self.create_token(Semicolon, 1),
+                '(' => self.create_token(OpenParen),
+                ')' => self.create_token(CloseParen),
+                '[' => self.create_token(OpenBracket),
+                ']' => self.create_token(CloseBracket),
+                '{' => {
+                    if let Some(depth) = self.interpolations.last_mut() {
+                        *depth += 1;
+                    }
+                    self.create_token(OpenBrace)
+                }
+                '}' => match self.interpolations.last_mut() {
+                    Some(0) => {
+                        self.interpolations.pop();
+                        self.lex_string(true)
+                    }
+                    Some(depth) => {
+                        *depth -= 1;
+                        self.create_token(CloseBrace)
+                    }
+                    None => self.create_token(CloseBrace),
+                },
+                ',' => self.create_token(Comma),
+                '.' => self.create_token(Dot),
+                ';' => self.create_token(Semicolon),
 
                 // simple double character tokens
                 '&' => self.with_double('&', And),
-                '|' => self.with_double('|', Or),
 
                 // simple single or double character tokens
+                '|' => self.with_single_or_double('|', Pipe, Or),
+                '=' if self.peek() == '>' => {
+                    self.advance();
+                    self.create_token(FatArrow)
+                }
                 '=' => self.with_single_or_double('=', Equal, EqualEqual),
                 '!' => self.with_single_or_double('=', Bang, BangEqual),
                 '>' => self.with_single_or_double('=', Greater, GreaterEqual),
                 '<' => self.with_single_or_double('=', Less, LessEqual),
                 '+' => self.with_single_or_double('=', Plus, PlusEqual),
+                '-' if self.peek() == '>' => {
+                    self.advance();
+                    self.create_token(Arrow)
+                }
                 '-' => self.with_single_or_double('=', Minus, MinusEqual),
                 '*' => self.with_single_or_double('=', Star, StarEqual),
+                '/' if self.peek() == '/' && self.peek_next() == '/' => self.lex_doc_comment(),
                 '/' => self.with_single_or_double('=', Slash, SlashEqual),
-
-                // whitespace
-                c if is_whitespace(c) => self.next_token(),
+                '%' => self.with_single_or_double('=', Percent, PercentEqual),
 
                 // String literals
-                '"' => self.lex_string(),
+                '"' if self.peek() == '"' && self.peek_next() == '"' => {
+                    self.advance();
+                    self.advance();
+                    self.lex_multiline_string()
+                }
+                '"' => self.lex_string(false),
 
                 // Chars (Characters)
                 '\'' => self.lex_char(),
@@ -435,14 +863,40 @@ impl<'a> Lexer<'a> {
                 '0'..='9' => self.lex_number(c),
 
                 // Identifiers
-                c if c == '_' || unicode_xid::UnicodeXID::is_xid_start(c) => self.get_ident(c),
+                c if c == '_' || unicode_xid::UnicodeXID::is_xid_start(c) => self.get_ident(),
 
-                c => self.create_token(
-                    Error(format!("Unknown character `{}` found in source", c)),
-                    1,
-                ),
+                c => self.create_token(Error(format!("Unknown character `{}` found in source", c))),
             };
         }
-        self.create_token(Eof, 0)
+        self.create_token(Eof)
+    }
+
+    /// Lex the whole of `source`, returning every token in it before the end,
+    /// and a [`Diagnostic`] in place of each `Error` token.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use meow::lexer::{Lexer, token::TokenKind};
+    ///
+    /// let (tokens, diagnostics) = Lexer::lex_all("let x = 1 $ 2;");
+    /// assert_eq!(tokens.len(), 6);
+    /// assert_eq!(tokens[1].kind, TokenKind::Ident("x"));
+    /// assert_eq!(diagnostics[0].message, "Unknown character `$` found in source");
+    /// assert_eq!(diagnostics[0].span.column, 11);
+    /// ```
+    pub fn lex_all(source: &'a str) -> (Vec<Token<'a>>, Vec<Diagnostic>) {
+        let mut lexer = Lexer::new(source);
+        let mut tokens = Vec::new();
+        let mut diagnostics = Vec::new();
+        loop {
+            let token = lexer.next_token();
+            let span = token.span();
+            match token.kind {
+                Eof => return (tokens, diagnostics),
+                Error(message) => diagnostics.push(Diagnostic::error(message, span)),
+                _ => tokens.push(token),
+            }
+        }
     }
 }
@@ -1,4 +1,5 @@
-use std::fmt;
+use crate::span::Span;
+use std::{borrow::Cow, fmt};
 
 /// The `TokenKind` enum contains every possible Token that the Meow lexer
 /// could return. This is not intended for use outside the lexer.
@@ -7,8 +8,12 @@ use std::fmt;
 /// struct because there is no reason to hold the content of simple tokens such
 /// as `OpenParen`. That will always be `(`, and the language uses that
 /// knowledge when needed.
-#[derive(Debug, PartialEq, Eq)]
-pub enum TokenKind {
+///
+/// That data borrows from the source wherever it is written there as is, so
+/// that lexing doesn't allocate for every identifier and literal. Only
+/// strings with escapes and numbers with underscores own theirs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenKind<'a> {
     // single char tokens
     OpenParen,
     CloseParen,
@@ -25,8 +30,13 @@ pub enum TokenKind {
     Or,
     Range,
     RangeInclusive,
+    /// `->`, which nothing is written with yet, but is kept for return
+    /// types rather than lexed as `-` and `>`.
+    Arrow,
+    FatArrow,
 
     // single or double char tokens
+    Pipe,
     Equal,
     EqualEqual,
     Bang,
@@ -43,19 +53,35 @@ pub enum TokenKind {
     StarEqual,
     Slash,
     SlashEqual,
+    Percent,
+    PercentEqual,
 
     // literals
-    Str(String),
+    /// The text of a string, with its escapes replaced by the chars they
+    /// stand for. For a `"""` string, that is after the indentation its
+    /// lines share is removed, so its value is the same as a `"` string
+    /// with the same text.
+    Str(Cow<'a, str>),
+    /// The text of an interpolated string up to its first `${`, such as
+    /// `"hello ${`. The tokens of the expression in it follow.
+    StrStart(Cow<'a, str>),
+    /// The text of an interpolated string between two of its expressions,
+    /// from a `}` up to the next `${`.
+    StrMiddle(Cow<'a, str>),
+    /// The text of an interpolated string after its last expression, from a
+    /// `}` up to the closing quote.
+    StrEnd(Cow<'a, str>),
     Char(char),
-    Int(String),
-    Float(String),
+    Int(Cow<'a, str>),
+    Float(Cow<'a, str>),
 
     // identifiers
-    Ident(String),
+    Ident(&'a str),
 
     // Keywords
     // `True` and `False` are considered boolean literals, but will be lexed as
     // as keywords for simplicity and ease of implementation
+    As,
     Class,
     Else,
     False,
@@ -63,41 +89,98 @@ pub enum TokenKind {
     Fun,
     If,
     Impls,
+    In,
     Import,
     Match,
     Mut,
+    Pub,
     Return,
+    Spawn,
     Trait,
     True,
     Let,
     While,
+    Yield,
+
+    /// A `///` comment, holding its text without the slashes. Consecutive
+    /// lines of them document the declaration that follows.
+    DocComment(&'a str),
 
     Error(String),
 
     Eof,
 }
 
+impl TokenKind<'_> {
+    /// Returns true for keywords, other than `true` and `false`, which are
+    /// literals.
+    pub fn is_keyword(&self) -> bool {
+        use TokenKind::*;
+        matches!(
+            self,
+            As | Class
+                | Else
+                | For
+                | Fun
+                | If
+                | Impls
+                | In
+                | Import
+                | Match
+                | Mut
+                | Pub
+                | Return
+                | Spawn
+                | Trait
+                | Let
+                | While
+                | Yield
+        )
+    }
+
+    /// Returns true for literals, including `true` and `false`.
+    pub fn is_literal(&self) -> bool {
+        use TokenKind::*;
+        matches!(self, Str(_) | Char(_) | Int(_) | Float(_) | True | False)
+    }
+}
+
 /// The `Token` struct stores the type of a single lexeme, as well as the line
-/// and column on which it starts. The end is not included, since that can be
-/// computed on demand as need be.
-#[derive(Debug, PartialEq, Eq)]
-pub struct Token {
-    pub kind: TokenKind,
+/// and column on which it starts and its length in characters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token<'a> {
+    pub kind: TokenKind<'a>,
     pub line: u32,
     pub column: u32,
+    pub length: u32,
 }
 
-impl fmt::Display for Token {
+// The parser keeps two tokens at a time, but the lexer makes one for every
+// lexeme, so tokens are kept from growing by accident
+#[cfg(target_pointer_width = "64")]
+const _: () = assert!(std::mem::size_of::<Token>() == 48);
+
+impl fmt::Display for Token<'_> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:?} at {}:{}", self.kind, self.line, self.column)
     }
 }
 
-impl Token {
+impl<'a> Token<'a> {
     /// Create a new token. This is only used in the interpreter by way of the
     /// various methods on the `Lexer` struct, but is available for testing
     /// purposes.
-    pub fn new(kind: TokenKind, line: u32, column: u32) -> Self {
-        Self { kind, line, column }
+    pub fn new(kind: TokenKind<'a>, line: u32, column: u32, length: u32) -> Self {
+        Self {
+            kind,
+            line,
+            column,
+            length,
+        }
+    }
+
+    /// Return the [`Span`] covering this token.
+    pub fn span(&self) -> Span {
+        Span::new(self.line, self.column, self.length)
     }
 }
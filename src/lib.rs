@@ -2,47 +2,698 @@
 //! interpreter in it's current state goes through the following primary
 //! phases.
 //!
-//! lexing -> ...
+//! lexing -> parsing -> resolution -> compilation -> execution
 //!
 //! Each of these phases may contain more specific steps, documented within
 //! their respective modules.
 
+pub mod bytecode;
+#[cfg(feature = "cdylib")]
+pub mod capi;
+mod clock;
+pub mod compiler;
+#[cfg(feature = "fs")]
+pub mod config;
+pub mod diagnostics;
 pub mod errors;
 pub mod lexer;
+pub mod lint;
+#[cfg(feature = "fs")]
+pub mod loader;
 pub mod parser;
+#[cfg(feature = "wasm")]
+pub mod playground;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod resolver;
+#[cfg(feature = "fs")]
+pub mod source;
+pub mod span;
+pub mod spec;
+pub mod value;
+pub mod vm;
+pub mod wasm;
 
 use anyhow::Result;
-use lexer::{token::TokenKind, Lexer};
-use std::{fs, path::Path};
+use bytecode::Function;
+use clock::Instant;
+use compiler::Compiler;
+use diagnostics::Diagnostic;
+use errors::InterpreterError;
+use lexer::token::{Token, TokenKind};
+use lexer::Lexer;
+use parser::{ast::Stmt, Parser};
+use resolver::{Resolver, SymbolTable};
+use std::time::Duration;
+use value::Value;
+use vm::{timings::Timings, Backend, Vm};
+use wasm::WasmCompiler;
+#[cfg(feature = "fs")]
+use {
+    bytecode::serialize,
+    config::{Config, CACHE_DIR, CONFIG_FILE},
+    errors::{ConfigError, LoadError},
+    loader::{Module, ModuleGraph},
+    std::{
+        collections::HashSet,
+        io, panic,
+        path::{Path, PathBuf},
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+    },
+};
 
 /// Create an instance of [`Lexer`](lexer::Lexer). This doesn't evaluate
 /// anything itself, but exists for testing and
-pub fn lex(source: &str) -> Lexer {
+pub fn lex(source: &str) -> Lexer<'_> {
     Lexer::new(source)
 }
 
-pub fn parse(source: &str) {
-    let mut lexer = lex(source);
+/// Lex the whole of `source` up front, returning its tokens and the errors
+/// in it. See [`Lexer::lex_all`].
+pub fn lex_all(source: &str) -> (Vec<Token<'_>>, Vec<Diagnostic>) {
+    Lexer::lex_all(source)
+}
+
+/// Parse `source` into a list of statements, or return every syntax error
+/// found in it.
+pub fn parse(source: &str) -> Result<Vec<Stmt>, Vec<Diagnostic>> {
+    Parser::new(source).parse()
+}
+
+/// Resolve the names in a parsed program, returning its [`SymbolTable`] and
+/// any errors found. See the [`resolver`] module for details.
+pub fn resolve(program: &[Stmt]) -> (SymbolTable, Vec<Diagnostic>) {
+    Resolver::new().resolve(program)
+}
+
+/// Run every phase up to compilation on `source`, returning the function for
+/// the top level of the program, or every error found along the way.
+pub fn compile(source: &str) -> Result<Function, Vec<Diagnostic>> {
+    let program = parse(source)?;
 
-    loop {
-        let next = lexer.next_token();
-        if next.kind == TokenKind::Eof {
-            break;
-        } else {
-            println!("{}", next);
+    let (table, diagnostics) = resolve(&program);
+    if diagnostics.iter().any(Diagnostic::is_error) {
+        return Err(diagnostics);
+    }
+
+    Compiler::new(&table).compile(&program)
+}
+
+/// Run every phase up to compilation on `source` without running it,
+/// returning every diagnostic found, warnings included. Compilation is
+/// skipped if an earlier phase found errors.
+///
+/// # Examples
+///
+/// ```
+/// use meow::check;
+///
+/// assert!(check("let x = 1; print(x);").is_empty());
+/// let diagnostics = check("let x = 1; x = 2; x = 3;");
+/// assert_eq!(diagnostics.len(), 2);
+/// ```
+pub fn check(source: &str) -> Vec<Diagnostic> {
+    let program = match parse(source) {
+        Ok(program) => program,
+        Err(diagnostics) => return diagnostics,
+    };
+
+    let (table, mut diagnostics) = resolve(&program);
+    if !diagnostics.iter().any(Diagnostic::is_error) {
+        if let Err(errors) = Compiler::new(&table).compile(&program) {
+            diagnostics.extend(errors);
         }
     }
+    diagnostics
 }
 
-pub fn run_from_file(path: &str) -> Result<()> {
+/// Run every phase up to resolution on `source`, then compile it into a
+/// WebAssembly module. See the [`wasm`] module for what can be compiled.
+pub fn compile_wasm(source: &str) -> Result<Vec<u8>, Vec<Diagnostic>> {
+    let program = parse(source)?;
+
+    let (table, diagnostics) = resolve(&program);
+    if diagnostics.iter().any(Diagnostic::is_error) {
+        return Err(diagnostics);
+    }
+
+    WasmCompiler::new(&table).compile(&program)
+}
+
+/// Read the file at `path` and run it on `vm`, as [`run`] does, along with
+/// the modules it imports from its directory and the
+/// [search paths](loader::search_paths). Files ending in `.mwc` are
+/// loaded as compiled bytecode, as written by
+/// [`serialize::save`](bytecode::serialize::save), and verified rather than
+/// compiled.
+///
+/// A directory, or the `meow.toml` of a project, is run as a project by
+/// [`run_project`]. A directory without a `meow.toml` is a project with the
+/// default configuration.
+#[cfg(feature = "fs")]
+pub fn run_from_file(vm: &mut Vm, path: &str) -> Result<Value, InterpreterError> {
     let filename = Path::new(path);
-    let contents = fs::read_to_string(filename)?;
+    if filename.extension() == Some("mwc".as_ref()) {
+        return run_compiled(vm, path);
+    }
+    if filename.is_dir() || filename.file_name() == Some(CONFIG_FILE.as_ref()) {
+        let file = match filename.is_dir() {
+            true => filename.join(CONFIG_FILE),
+            false => filename.to_path_buf(),
+        };
+        let config = match file.is_file() || !filename.is_dir() {
+            true => Config::load(&file),
+            false => Config::parse("", filename),
+        };
+        let config = config.map_err(|error| match error {
+            ConfigError::Io(error) if error.kind() == io::ErrorKind::NotFound => {
+                InterpreterError::FileNotFound(path.to_string())
+            }
+            error => InterpreterError::Config {
+                path: file.display().to_string(),
+                error,
+            },
+        })?;
+        return run_project(vm, &config);
+    }
+
+    let dir = filename.parent().unwrap_or_else(|| Path::new(""));
+    let mut dirs = vec![dir.to_path_buf()];
+    dirs.extend(loader::search_paths(&[]));
+    run_modules(vm, filename, &dirs)
+}
+
+/// Run the project configured by `config` on `vm`, starting from its entry
+/// point, and return the value of the entry. Every module imported from the
+/// project's source directories and search paths is compiled before any of
+/// them run, as the [`loader`] module describes. If the project caches compiled modules, the
+/// VM's [module cache](Vm::module_cache) saves them in the project.
+#[cfg(feature = "fs")]
+pub fn run_project(vm: &mut Vm, config: &Config) -> Result<Value, InterpreterError> {
+    if config.cache {
+        vm.module_cache().set_dir(Some(config.root.join(CACHE_DIR)));
+    }
+    let mut dirs = config.source_dirs.clone();
+    dirs.extend(loader::search_paths(&config.search_paths));
+    run_modules(vm, &config.entry, &dirs)
+}
 
-    run(&contents);
+/// Load the program starting from `entry` and the modules it imports from
+/// `source_dirs`, then run each module after the ones it imports.
+#[cfg(feature = "fs")]
+fn run_modules(
+    vm: &mut Vm,
+    entry: &Path,
+    source_dirs: &[PathBuf],
+) -> Result<Value, InterpreterError> {
+    let graph = timed(
+        vm,
+        |timings| &mut timings.parse,
+        |vm| ModuleGraph::load_cached(entry, source_dirs, vm.module_cache()),
+    )?;
+    let scripts = compile_modules(vm, &graph)?;
 
+    let mut value = Value::Unit;
+    for (module, script) in graph.modules.iter().zip(scripts) {
+        value = run_module(vm, module, &module.program, script)?;
+    }
+    Ok(value)
+}
+
+/// Reload the module called `module` of a project run on `vm` with
+/// `source` as its new source, so that programs can change while they run.
+/// The functions and classes the module defines are replaced, and existing
+/// instances of its classes get the new methods. Variables keep the values
+/// they have, unless they weren't defined by the module before, and the
+/// module's other top-level statements don't run again. Modules it imports
+/// that weren't loaded before are run first.
+///
+/// Values the program took from the module before it was reloaded, such
+/// as functions stored in variables, aren't replaced.
+#[cfg(feature = "fs")]
+pub fn reload(vm: &mut Vm, module: &str, source: &str) -> Result<(), InterpreterError> {
+    let Some(old) = vm.module_cache().modules().find(|old| old.name == module) else {
+        return Err(InterpreterError::ModuleNotLoaded(module.to_string()));
+    };
+    let old = old.clone();
+    let loaded: HashSet<u64> = vm
+        .module_cache()
+        .modules()
+        .map(|module| module.key)
+        .collect();
+    let graph = timed(
+        vm,
+        |timings| &mut timings.parse,
+        |vm| ModuleGraph::reload(module, source.to_string(), vm.module_cache()),
+    )?;
+    let (new, imports) = graph.modules.split_last().expect("reloaded module missing");
+
+    for import in imports
+        .iter()
+        .filter(|import| !loaded.contains(&import.key))
+    {
+        let script = compile_module(vm, import, Some(&import.path))?;
+        run_module(vm, import, &import.program, script)?;
+    }
+
+    let variables: HashSet<&str> = old
+        .program
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Stmt::Let { name, .. } => Some(name.as_str()),
+            _ => None,
+        })
+        .collect();
+    let program: Vec<Stmt> = new
+        .program
+        .iter()
+        .filter(|stmt| match stmt {
+            Stmt::Let { name, .. } => {
+                !variables.contains(name.as_str()) || vm.global(name).is_none()
+            }
+            Stmt::Fun(_) | Stmt::Class { .. } | Stmt::Import { .. } => true,
+            _ => false,
+        })
+        .cloned()
+        .collect();
+    let classes: Vec<_> = old
+        .program
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Stmt::Class { name, .. } => match vm.global(name) {
+                Some(Value::Class(class)) => Some((name, class.clone())),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+
+    let failed = |diagnostics| loader::failed(source, None, diagnostics);
+    let (table, diagnostics) = resolve(&program);
+    if diagnostics.iter().any(Diagnostic::is_error) {
+        return Err(failed(diagnostics));
+    }
+    let script = compile_program(vm, &program, &table).map_err(failed)?;
+    check_backend(vm, &script).map_err(failed)?;
+    run_module(vm, new, &program, script)?;
+
+    for (name, class) in classes {
+        if let Some(Value::Class(new)) = vm.global(name) {
+            let new = new.clone();
+            vm.replace_class(&class, &new);
+        }
+    }
     Ok(())
 }
 
-pub fn run(source: &str) {
-    parse(source)
+/// Compile every module of `graph` for the selected backend, in order. The
+/// modules that aren't in the VM's module cache are resolved and compiled
+/// in parallel, since none of them needs another's code until they run.
+#[cfg(feature = "fs")]
+fn compile_modules(vm: &mut Vm, graph: &ModuleGraph) -> Result<Vec<Function>, InterpreterError> {
+    // Only errors outside of the entry say which file they are in
+    let file = |index: usize| {
+        (index + 1 < graph.modules.len()).then_some(graph.modules[index].path.as_path())
+    };
+
+    let cached: Vec<_> = graph
+        .modules
+        .iter()
+        .map(|module| vm.module_cache().script(module.key))
+        .collect();
+    let uncached: Vec<_> = graph
+        .modules
+        .iter()
+        .zip(&cached)
+        .filter_map(|(module, script)| script.is_none().then_some(module))
+        .collect();
+    let mut compiled = compile_parallel(&uncached, vm.timings().is_some()).into_iter();
+
+    graph
+        .modules
+        .iter()
+        .zip(cached)
+        .enumerate()
+        .map(|(index, (module, script))| {
+            finish_module(vm, module, file(index), script, || {
+                compiled.next().expect("uncached module wasn't compiled")
+            })
+        })
+        .collect()
+}
+
+/// Compile `module`, read from `file`, for the selected backend, or take it
+/// from the VM's module cache if it was compiled before.
+#[cfg(feature = "fs")]
+fn compile_module(
+    vm: &mut Vm,
+    module: &Module,
+    file: Option<&Path>,
+) -> Result<Function, InterpreterError> {
+    let script = vm.module_cache().script(module.key);
+    let timing = vm.timings().is_some();
+    finish_module(vm, module, file, script, || compile_source(module, timing))
+}
+
+/// A module's script, or why it couldn't be compiled, along with how long
+/// compiling it took.
+#[cfg(feature = "fs")]
+type Compiled = (Result<Function, Vec<Diagnostic>>, Timings);
+
+/// Finish compiling `module`, read from `file`, taking its `script` from
+/// the module cache, or caching the one `compile` returns if it wasn't
+/// there, and check that it can run on the selected backend.
+#[cfg(feature = "fs")]
+fn finish_module(
+    vm: &mut Vm,
+    module: &Module,
+    file: Option<&Path>,
+    script: Option<Function>,
+    compile: impl FnOnce() -> Compiled,
+) -> Result<Function, InterpreterError> {
+    let failed = |diagnostics| loader::failed(&module.source, file, diagnostics);
+    let script = match script {
+        Some(script) => script,
+        None => {
+            let (script, timings) = compile();
+            if let Some(total) = vm.timings_mut() {
+                *total += timings;
+            }
+            let script = script.map_err(failed)?;
+            vm.module_cache().insert_script(module.key, &script);
+            script
+        }
+    };
+    check_backend(vm, &script).map_err(failed)?;
+    Ok(script)
+}
+
+/// Resolve and compile `module`, timing each phase if `timing`. This
+/// doesn't need a VM, so that modules can be compiled on other threads.
+#[cfg(feature = "fs")]
+fn compile_source(module: &Module, timing: bool) -> Compiled {
+    let mut timings = Timings::default();
+    if timing {
+        let start = Instant::now();
+        let mut lexer = lex(&module.source);
+        while lexer.next_token().kind != TokenKind::Eof {}
+        timings.lex = start.elapsed();
+    }
+
+    let start = Instant::now();
+    let (table, diagnostics) = resolve(&module.program);
+    timings.resolve = start.elapsed();
+    if diagnostics.iter().any(Diagnostic::is_error) {
+        return (Err(diagnostics), timings);
+    }
+
+    let start = Instant::now();
+    let script = Compiler::new(&table).compile(&module.program);
+    timings.compile = start.elapsed();
+    (script, timings)
+}
+
+/// The stack each compiler thread gets. The resolver and compiler recurse
+/// as deeply as programs nest, so they get as much as a main thread usually
+/// has.
+#[cfg(feature = "fs")]
+const COMPILER_STACK_SIZE: usize = 8 * 1024 * 1024;
+
+/// [Compile](compile_source) each of `modules` with a thread for each core,
+/// returning what each one compiled to, in order. Each module is timed on
+/// its own thread, so their timings can add up to more than the time that
+/// passed.
+///
+/// Scripts hold values that can't be sent between threads, so each thread
+/// sends its scripts back in the `.mwc` format.
+#[cfg(feature = "fs")]
+fn compile_parallel(modules: &[&Module], timing: bool) -> Vec<Compiled> {
+    let threads = thread::available_parallelism()
+        .map_or(1, usize::from)
+        .min(modules.len());
+    if threads <= 1 {
+        return modules
+            .iter()
+            .map(|module| compile_source(module, timing))
+            .collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let mut compiled: Vec<Option<Compiled>> = modules.iter().map(|_| None).collect();
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                let worker = || {
+                    let mut done = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(module) = modules.get(index) else {
+                            return done;
+                        };
+                        let (script, timings) = compile_source(module, timing);
+                        let bytes = script.map(|script| serialize::encode(&script));
+                        done.push((index, bytes, timings));
+                    }
+                };
+                thread::Builder::new()
+                    .stack_size(COMPILER_STACK_SIZE)
+                    .spawn_scoped(scope, worker)
+                    .expect("failed to start a compiler thread")
+            })
+            .collect();
+
+        for worker in workers {
+            let done = worker
+                .join()
+                .unwrap_or_else(|panic| panic::resume_unwind(panic));
+            for (index, bytes, timings) in done {
+                let script = bytes.map(|bytes| {
+                    serialize::decode(&bytes).expect("compiled scripts always decode")
+                });
+                compiled[index] = Some((script, timings));
+            }
+        }
+    });
+    compiled
+        .into_iter()
+        .map(|compiled| compiled.expect("module wasn't compiled"))
+        .collect()
+}
+
+/// Run `script`, compiled from `program`, the program of `module`.
+#[cfg(feature = "fs")]
+fn run_module(
+    vm: &mut Vm,
+    module: &Module,
+    program: &[Stmt],
+    script: Function,
+) -> Result<Value, InterpreterError> {
+    // Runtime errors say which file they are in through their trace
+    let path = module.path.to_string_lossy();
+    run_script(vm, script, program, Some(&path))
+        .map_err(|diagnostics| loader::failed(&module.source, None, diagnostics))
+}
+
+/// Load the compiled program at `path` and run it on `vm`.
+#[cfg(feature = "fs")]
+fn run_compiled(vm: &mut Vm, path: &str) -> Result<Value, InterpreterError> {
+    let script = serialize::load(path).map_err(|error| match error {
+        LoadError::Io(error) if error.kind() == io::ErrorKind::NotFound => {
+            InterpreterError::FileNotFound(path.to_string())
+        }
+        error => InterpreterError::Load {
+            path: path.to_string(),
+            error,
+        },
+    })?;
+    // There is no source to show, so errors are rendered with just their
+    // locations
+    timed(vm, |timings| &mut timings.execute, |vm| vm.run(script)).map_err(|error| {
+        InterpreterError::Failed {
+            source_code: String::new(),
+            diagnostics: vec![error.to_diagnostic(Some(path))],
+        }
+    })
+}
+
+/// Compile `source` and execute it on `vm`, returning the value of the
+/// program. With the [`Backend::Ast`] backend selected, the program is
+/// evaluated rather than run as bytecode, once it has passed the
+/// [`check`](vm::ast::check) for what that backend can't run.
+///
+/// # Examples
+///
+/// ```
+/// use meow::{run, value::Value, vm::Vm};
+///
+/// let mut vm = Vm::new();
+/// run(&mut vm, "let mut x = [1, 2]; x[0] = 3;").unwrap();
+/// assert!(run(&mut vm, "x[2];").is_err());
+/// assert_eq!(run(&mut vm, "let y = x[0] + x[1];").unwrap(), Value::Unit);
+/// assert_eq!(vm.global("y"), Some(&Value::Int(5)));
+/// ```
+pub fn run(vm: &mut Vm, source: &str) -> Result<Value, InterpreterError> {
+    execute(vm, source, None)
+}
+
+/// Run `source` on `vm` as an entry of an interactive session, as [`run`]
+/// does. An entry that is a single expression doesn't need a `;` after it,
+/// and gives back its value. Other entries give back `None`.
+///
+/// # Examples
+///
+/// ```
+/// use meow::{run_entry, value::Value, vm::Vm};
+///
+/// let mut vm = Vm::new();
+/// assert_eq!(run_entry(&mut vm, "let x = 20;").unwrap(), None);
+/// assert_eq!(run_entry(&mut vm, "x * 2 + 2").unwrap(), Some(Value::Int(42)));
+/// assert_eq!(run_entry(&mut vm, "x;").unwrap(), Some(Value::Int(20)));
+/// ```
+pub fn run_entry(vm: &mut Vm, source: &str) -> Result<Option<Value>, InterpreterError> {
+    let failed = |diagnostics| InterpreterError::Failed {
+        source_code: source.to_string(),
+        diagnostics,
+    };
+
+    // Adding the `;` at the end leaves the spans in the entry as they were
+    time_lexing(vm, source);
+    let mut program = timed(
+        vm,
+        |timings| &mut timings.parse,
+        |_| {
+            parse(source)
+                .or_else(|diagnostics| parse(&format!("{};", source)).map_err(|_| diagnostics))
+        },
+    )
+    .map_err(failed)?;
+    let (table, diagnostics) = timed(vm, |timings| &mut timings.resolve, |_| resolve(&program));
+    if diagnostics.iter().any(Diagnostic::is_error) {
+        return Err(failed(diagnostics));
+    }
+
+    // An expression's value is returned from the program, once the
+    // expression has been resolved where a `return` wouldn't be allowed
+    let expression = match program.as_mut_slice() {
+        [stmt @ Stmt::Expr { .. }] => {
+            if let Stmt::Expr { expr, span } = stmt.clone() {
+                *stmt = Stmt::Return {
+                    value: Some(expr),
+                    span,
+                };
+            }
+            true
+        }
+        _ => false,
+    };
+    let value = evaluate(vm, &program, &table, None).map_err(failed)?;
+    Ok(expression.then_some(value))
+}
+
+/// Run `source`, which was read from `path` if it came from a file.
+fn execute(vm: &mut Vm, source: &str, path: Option<&str>) -> Result<Value, InterpreterError> {
+    let failed = |diagnostics| InterpreterError::Failed {
+        source_code: source.to_string(),
+        diagnostics,
+    };
+
+    time_lexing(vm, source);
+    let program = timed(vm, |timings| &mut timings.parse, |_| parse(source)).map_err(failed)?;
+    let (table, diagnostics) = timed(vm, |timings| &mut timings.resolve, |_| resolve(&program));
+    if diagnostics.iter().any(Diagnostic::is_error) {
+        return Err(failed(diagnostics));
+    }
+    evaluate(vm, &program, &table, path).map_err(failed)
+}
+
+/// Run `phase` on `vm`, adding how long it takes to the timing `field`
+/// selects if `vm` is timing phases.
+fn timed<T>(
+    vm: &mut Vm,
+    field: fn(&mut Timings) -> &mut Duration,
+    phase: impl FnOnce(&mut Vm) -> T,
+) -> T {
+    let start = Instant::now();
+    let result = phase(vm);
+    if let Some(timings) = vm.timings_mut() {
+        *field(timings) += start.elapsed();
+    }
+    result
+}
+
+/// Lex `source` to time how long lexing takes, if `vm` is timing phases.
+/// The parser lexes as it goes, so this is the only way to tell lexing
+/// apart from parsing.
+fn time_lexing(vm: &mut Vm, source: &str) {
+    if vm.timings().is_some() {
+        timed(
+            vm,
+            |timings| &mut timings.lex,
+            |_| {
+                let mut lexer = lex(source);
+                while lexer.next_token().kind != TokenKind::Eof {}
+            },
+        );
+    }
+}
+
+/// Compile and run a resolved `program` on the selected backend, returning
+/// its value, or the diagnostics for why it couldn't run. With the
+/// [`Backend::Ast`] backend selected, the program is evaluated rather than
+/// run as bytecode, once it has passed the [`check`](vm::ast::check) for
+/// what that backend can't run.
+fn evaluate(
+    vm: &mut Vm,
+    program: &[Stmt],
+    table: &SymbolTable,
+    path: Option<&str>,
+) -> Result<Value, Vec<Diagnostic>> {
+    let script = compile_program(vm, program, table)?;
+    check_backend(vm, &script)?;
+    run_script(vm, script, program, path)
+}
+
+/// Compile a resolved `program`, timing how long it takes.
+fn compile_program(
+    vm: &mut Vm,
+    program: &[Stmt],
+    table: &SymbolTable,
+) -> Result<Function, Vec<Diagnostic>> {
+    timed(
+        vm,
+        |timings| &mut timings.compile,
+        |_| Compiler::new(table).compile(program),
+    )
+}
+
+/// Check that `script` can be run on the selected backend. With the AST
+/// backend the program is only compiled to be checked, so that it is
+/// rejected for the same reasons as on the other backends.
+fn check_backend(vm: &Vm, script: &Function) -> Result<(), Vec<Diagnostic>> {
+    if vm.backend() == Backend::Ast {
+        let diagnostics = vm::ast::check(script);
+        if !diagnostics.is_empty() {
+            return Err(diagnostics);
+        }
+    }
+    Ok(())
+}
+
+/// Run `script`, compiled from `program`, on the selected backend.
+fn run_script(
+    vm: &mut Vm,
+    script: Function,
+    program: &[Stmt],
+    path: Option<&str>,
+) -> Result<Value, Vec<Diagnostic>> {
+    let start = Instant::now();
+    let result = match vm.backend() {
+        Backend::Ast => vm.run_ast(program),
+        Backend::Stack | Backend::Register => vm.run(script),
+    };
+    if let Some(timings) = vm.timings_mut() {
+        timings.execute += start.elapsed();
+    }
+    result.map_err(|error| vec![error.to_diagnostic(path)])
 }
@@ -0,0 +1,435 @@
+//! Lints point out code that is allowed, but probably isn't what was meant,
+//! such as variables that are never used. They run on a resolved program
+//! without compiling it, so they are quick enough to run as code is edited.
+//!
+//! Each [`Lint`] has a [`LintLevel`] saying whether it is ignored, reported
+//! as a warning, or reported as an error, which a [`LintConfig`] can change.
+
+use crate::{
+    diagnostics::{Diagnostic, Level},
+    parser::ast::{Block, Expr, Stmt, StrPart},
+    resolver::{ScopeKind, Symbol, SymbolKind, SymbolTable},
+    span::Span,
+};
+use serde::Deserialize;
+use std::{fmt, ptr};
+
+/// A check for code that is probably a mistake, or hard to read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lint {
+    /// A variable or parameter that is never used. Names starting with `_`
+    /// are never reported.
+    UnusedVariable,
+    /// A variable declared with the same name as one already visible, which
+    /// it hides.
+    Shadowing,
+    /// Code after a `return`, which can never run.
+    UnreachableCode,
+    /// A name that doesn't follow the usual style: `snake_case` for
+    /// functions and variables, and `UpperCamelCase` for classes.
+    NamingStyle,
+}
+
+impl Lint {
+    pub const ALL: [Lint; 4] = [
+        Lint::UnusedVariable,
+        Lint::Shadowing,
+        Lint::UnreachableCode,
+        Lint::NamingStyle,
+    ];
+
+    /// Return the name the lint is selected by, such as `unused-variable`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Lint::UnusedVariable => "unused-variable",
+            Lint::Shadowing => "shadowing",
+            Lint::UnreachableCode => "unreachable-code",
+            Lint::NamingStyle => "naming-style",
+        }
+    }
+
+    /// Return the lint called `name`, if there is one.
+    pub fn from_name(name: &str) -> Option<Lint> {
+        Lint::ALL.into_iter().find(|lint| lint.name() == name)
+    }
+
+    /// Return the level the lint has unless it is configured otherwise.
+    /// Shadowing is a normal part of the language, so it is only reported
+    /// when asked for.
+    pub fn default_level(self) -> LintLevel {
+        match self {
+            Lint::Shadowing => LintLevel::Allow,
+            Lint::UnusedVariable | Lint::UnreachableCode | Lint::NamingStyle => LintLevel::Warn,
+        }
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// How a [`Lint`] is reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LintLevel {
+    /// Not at all.
+    Allow,
+    /// As a warning.
+    Warn,
+    /// As an error.
+    Deny,
+}
+
+/// The level of every lint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintConfig {
+    levels: [LintLevel; Lint::ALL.len()],
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        Self {
+            levels: Lint::ALL.map(Lint::default_level),
+        }
+    }
+}
+
+impl LintConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn level(&self, lint: Lint) -> LintLevel {
+        self.levels[lint as usize]
+    }
+
+    pub fn set_level(&mut self, lint: Lint, level: LintLevel) {
+        self.levels[lint as usize] = level;
+    }
+}
+
+/// Run every lint that isn't allowed by `config` over a resolved `program`,
+/// returning what each one found, in source order.
+///
+/// # Examples
+///
+/// ```
+/// use meow::{lint::{lint, Lint, LintConfig}, parse, resolve};
+///
+/// let program = parse("fun f(unused) { return 1; 2 }").unwrap();
+/// let (table, _) = resolve(&program);
+/// let found: Vec<_> = lint(&program, &table, &LintConfig::new())
+///     .into_iter()
+///     .map(|(lint, _)| lint)
+///     .collect();
+/// assert_eq!(found, [Lint::UnusedVariable, Lint::UnreachableCode]);
+/// ```
+pub fn lint(program: &[Stmt], table: &SymbolTable, config: &LintConfig) -> Vec<(Lint, Diagnostic)> {
+    let mut found = Vec::new();
+    for symbol in table.symbols() {
+        if let Some(diagnostic) = unused(table, symbol) {
+            found.push((Lint::UnusedVariable, diagnostic));
+        }
+        if let Some(diagnostic) = shadowing(table, symbol) {
+            found.push((Lint::Shadowing, diagnostic));
+        }
+        if let Some(diagnostic) = naming_style(symbol) {
+            found.push((Lint::NamingStyle, diagnostic));
+        }
+    }
+    let mut walker = Unreachable::default();
+    walker.stmts(program);
+    found.extend(
+        walker
+            .found
+            .into_iter()
+            .map(|diagnostic| (Lint::UnreachableCode, diagnostic)),
+    );
+
+    found.retain(|(lint, _)| config.level(*lint) != LintLevel::Allow);
+    for (lint, diagnostic) in &mut found {
+        let (level, setting) = match config.level(*lint) {
+            LintLevel::Deny => (Level::Error, "deny"),
+            LintLevel::Warn | LintLevel::Allow => (Level::Warning, "warn"),
+        };
+        diagnostic.level = level;
+        diagnostic
+            .notes
+            .push(format!("the `{}` lint is set to {}", lint, setting));
+    }
+    found.sort_by_key(|(_, diagnostic)| (diagnostic.span.line, diagnostic.span.column));
+    found
+}
+
+/// Returns true if `symbol` is a `self` parameter, which is declared for
+/// every method whether it is used or not.
+fn is_self(symbol: &Symbol) -> bool {
+    symbol.kind == SymbolKind::Parameter && symbol.name == "self"
+}
+
+/// Report `symbol` if it is a local variable or parameter that is never used.
+/// Globals may be used by code run later, so they are never reported.
+fn unused(table: &SymbolTable, symbol: &Symbol) -> Option<Diagnostic> {
+    let local = table.scope(symbol.scope).kind != ScopeKind::Global;
+    let kind = match symbol.kind {
+        SymbolKind::Variable { .. } => "variable",
+        SymbolKind::Parameter => "parameter",
+        _ => return None,
+    };
+    if !local || !symbol.references.is_empty() || symbol.name.starts_with('_') || is_self(symbol) {
+        return None;
+    }
+    Some(
+        Diagnostic::warning(format!("unused {} `{}`", kind, symbol.name), symbol.span).with_note(
+            format!(
+                "if this is on purpose, start the name with an underscore: `_{}`",
+                symbol.name
+            ),
+        ),
+    )
+}
+
+/// Report `symbol` if it is a variable that hides another symbol of the same
+/// name visible where it is declared.
+fn shadowing(table: &SymbolTable, symbol: &Symbol) -> Option<Diagnostic> {
+    if !matches!(
+        symbol.kind,
+        SymbolKind::Variable { .. } | SymbolKind::Parameter
+    ) || is_self(symbol)
+    {
+        return None;
+    }
+    let position = |span: Span| (span.line, span.column);
+
+    let mut scope = Some(symbol.scope);
+    while let Some(id) = scope {
+        let current = table.scope(id);
+        // Methods are looked up on instances rather than by name
+        if current.kind != ScopeKind::Class {
+            let hidden = table
+                .lookup(id, &symbol.name)
+                .map(|id| table.symbol(id))
+                .find(|other| {
+                    // Globals are visible everywhere inside functions and blocks,
+                    // while anything else is only visible after it is declared
+                    !ptr::eq(*other, symbol)
+                        && ((current.kind == ScopeKind::Global && id != symbol.scope)
+                            || position(other.span) < position(symbol.span))
+                });
+            if let Some(hidden) = hidden {
+                return Some(
+                    Diagnostic::warning(
+                        format!("`{}` shadows an earlier declaration", symbol.name),
+                        symbol.span,
+                    )
+                    .with_note(format!(
+                        "the earlier `{}` is at {}",
+                        hidden.name, hidden.span
+                    )),
+                );
+            }
+        }
+        scope = current.parent;
+    }
+    None
+}
+
+/// Report `symbol` if its name doesn't follow the style for its kind.
+fn naming_style(symbol: &Symbol) -> Option<Diagnostic> {
+    let name = &symbol.name;
+    let (kind, style, suggestion) = match symbol.kind {
+        SymbolKind::Class => {
+            let camel = to_upper_camel_case(name);
+            if camel == *name {
+                return None;
+            }
+            ("class", "an UpperCamelCase", camel)
+        }
+        SymbolKind::Variable { .. }
+        | SymbolKind::Parameter
+        | SymbolKind::Function
+        | SymbolKind::Method => {
+            let snake = to_snake_case(name);
+            if snake == *name {
+                return None;
+            }
+            let kind = match symbol.kind {
+                SymbolKind::Variable { .. } => "variable",
+                SymbolKind::Parameter => "parameter",
+                _ => "function",
+            };
+            (kind, "a snake_case", snake)
+        }
+        SymbolKind::Module => return None,
+    };
+    Some(Diagnostic::warning(
+        format!(
+            "{} `{}` should have {} name, such as `{}`",
+            kind, name, style, suggestion
+        ),
+        symbol.span,
+    ))
+}
+
+/// Convert `name` to `snake_case`, keeping leading underscores.
+fn to_snake_case(name: &str) -> String {
+    let trimmed = name.trim_start_matches('_');
+    let mut out = name[..name.len() - trimmed.len()].to_string();
+    let mut previous: Option<char> = None;
+    for c in trimmed.chars() {
+        if c.is_uppercase() {
+            if previous.is_some_and(|p| p.is_lowercase() || p.is_ascii_digit()) {
+                out.push('_');
+            }
+            out.extend(c.to_lowercase());
+        } else {
+            out.push(c);
+        }
+        previous = Some(c);
+    }
+    out
+}
+
+/// Convert `name` to `UpperCamelCase`, keeping leading underscores.
+fn to_upper_camel_case(name: &str) -> String {
+    let trimmed = name.trim_start_matches('_');
+    let mut out = name[..name.len() - trimmed.len()].to_string();
+    for word in trimmed.split('_').filter(|word| !word.is_empty()) {
+        let mut chars = word.chars();
+        if let Some(first) = chars.next() {
+            out.extend(first.to_uppercase());
+            out.extend(chars);
+        }
+    }
+    out
+}
+
+/// Finds the code that can't run because it comes after a `return` in the
+/// same block.
+#[derive(Default)]
+struct Unreachable {
+    found: Vec<Diagnostic>,
+}
+
+impl Unreachable {
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Let { value, .. } => self.option(value.as_ref()),
+            Stmt::Expr { expr, .. } => self.expr(expr),
+            Stmt::Fun(fun) => self.block(&fun.body),
+            Stmt::Class { methods, .. } => {
+                for method in methods {
+                    self.block(&method.body);
+                }
+            }
+            Stmt::Return { value, .. } | Stmt::Yield { value, .. } => self.option(value.as_ref()),
+            Stmt::Spawn { callee, args, .. } => {
+                self.expr(callee);
+                self.exprs(args);
+            }
+            Stmt::While { cond, body, .. } => {
+                self.expr(cond);
+                self.block(body);
+            }
+            Stmt::For { iterable, body, .. } => {
+                self.expr(iterable);
+                self.block(body);
+            }
+            Stmt::Import { .. } => {}
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        self.stmts(&block.stmts);
+        if let Some(tail) = &block.tail {
+            self.expr(tail);
+        }
+
+        let returned = block
+            .stmts
+            .iter()
+            .position(|stmt| matches!(stmt, Stmt::Return { .. }));
+        if let Some(index) = returned {
+            let next = block.stmts.get(index + 1).map(Stmt::span);
+            let next = next.or_else(|| block.tail.as_ref().map(|tail| tail.span()));
+            if let Some(span) = next {
+                self.found.push(
+                    Diagnostic::warning("unreachable code", span).with_note(format!(
+                        "it comes after the `return` at {}",
+                        block.stmts[index].span()
+                    )),
+                );
+            }
+        }
+    }
+
+    fn option(&mut self, expr: Option<&Expr>) {
+        if let Some(expr) = expr {
+            self.expr(expr);
+        }
+    }
+
+    fn exprs(&mut self, exprs: &[Expr]) {
+        for expr in exprs {
+            self.expr(expr);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Literal { .. } | Expr::Ident { .. } => {}
+            Expr::Unary { expr, .. } | Expr::Cast { expr, .. } => self.expr(expr),
+            Expr::Field { object, .. } => self.expr(object),
+            Expr::Binary { left, right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::Assign { target, value, .. } => {
+                self.expr(target);
+                self.expr(value);
+            }
+            Expr::Call { callee, args, .. } => {
+                self.expr(callee);
+                self.exprs(args);
+            }
+            Expr::Index { object, index, .. } => {
+                self.expr(object);
+                self.expr(index);
+            }
+            Expr::List { items, .. } => self.exprs(items),
+            Expr::Interpolation { parts, .. } => {
+                for expr in StrPart::exprs(parts) {
+                    self.expr(expr);
+                }
+            }
+            Expr::Block(block) => self.block(block),
+            Expr::If {
+                cond,
+                then,
+                otherwise,
+                ..
+            } => {
+                self.expr(cond);
+                self.block(then);
+                self.option(otherwise.as_deref());
+            }
+            Expr::Match {
+                scrutinee, arms, ..
+            } => {
+                self.expr(scrutinee);
+                for arm in arms {
+                    self.option(arm.guard.as_ref());
+                    self.expr(&arm.body);
+                }
+            }
+        }
+    }
+}
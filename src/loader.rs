@@ -0,0 +1,957 @@
+//! Loading programs made of more than one file. A file can import the other
+//! files of its project at its top level, naming them by their path with `.`
+//! between directories: `import shapes.circle;` loads `shapes/circle.mw`,
+//! looking next to the importing file first, then in each of the project's
+//! source directories, and then in the [search paths](search_paths) shared
+//! by every project, which hold the standard library.
+//!
+//! What a module defines at its top level and marks `pub` is reached through
+//! the last part of the name it is imported by, as in `circle.area(2.0)`.
+//! Definitions are renamed after their module, to `shapes.circle.area`, so
+//! that modules defining the same names don't clash. A module can also
+//! re-export the modules it imports with `pub import`, so that `import
+//! shapes; shapes.circle.area(2.0);` works if `shapes` has `pub import
+//! shapes.circle;`.
+//!
+//! The files a program imports, and the ones they import, make up its
+//! [`ModuleGraph`]. Every module in it is compiled before any of them run,
+//! and each runs once, before the modules that import it. A [`SymbolIndex`]
+//! finds where the names used in the modules are defined and used.
+
+use crate::{
+    bytecode::{
+        serialize::{self, encode},
+        Function,
+    },
+    diagnostics::Diagnostic,
+    errors::InterpreterError,
+    parse,
+    parser::ast::{Block, Expr, Stmt, StrPart},
+    resolve,
+    resolver::{ScopeId, Symbol, SymbolId, SymbolKind, SymbolTable},
+    source::Source,
+    span::Span,
+    vm::native,
+};
+use std::{
+    collections::HashMap,
+    env, fs,
+    hash::{Hash, Hasher},
+    io, iter,
+    path::{Path, PathBuf},
+};
+
+/// The extension of Meow source files.
+pub const EXTENSION: &str = "mw";
+
+/// The environment variable listing more directories to find modules in,
+/// separated as in `PATH`.
+pub const PATH_VAR: &str = "MEOW_PATH";
+
+/// The environment variable naming the directory the standard library is
+/// in, for builds of meow that have been moved from where they were built.
+pub const HOME_VAR: &str = "MEOW_HOME";
+
+/// Return the directory holding the modules of the standard library that
+/// are written in Meow, such as `std/strings.mw`. It is the one named by
+/// [`HOME_VAR`] if it is set, and otherwise the `lib` directory meow was
+/// built from.
+pub fn library_dir() -> PathBuf {
+    match env::var_os(HOME_VAR) {
+        Some(dir) => PathBuf::from(dir),
+        None => Path::new(env!("CARGO_MANIFEST_DIR")).join("lib"),
+    }
+}
+
+/// Return the directories to find modules in after a project's source
+/// directories: `configured` first, then the ones listed in [`PATH_VAR`],
+/// and last the [standard library](library_dir). Modules built into the VM,
+/// such as `std.math`, are found before any of them.
+pub fn search_paths(configured: &[PathBuf]) -> Vec<PathBuf> {
+    let mut dirs = configured.to_vec();
+    if let Some(paths) = env::var_os(PATH_VAR) {
+        dirs.extend(env::split_paths(&paths).filter(|dir| !dir.as_os_str().is_empty()));
+    }
+    dirs.push(library_dir());
+    dirs
+}
+
+/// A file of a program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Module {
+    /// The name the module is imported by, such as `shapes.circle`. The
+    /// entry is named after its file.
+    pub name: String,
+    pub path: PathBuf,
+    pub source: Source,
+    /// The parsed source, with its definitions renamed after the module,
+    /// and without the imports of other modules of the project, which have
+    /// been loaded.
+    pub program: Vec<Stmt>,
+    /// The names of the functions, classes and variables the module defines
+    /// at its top level and marks `pub`, which the modules importing it can
+    /// use.
+    pub exports: Vec<String>,
+    /// The names of the module's other top-level definitions, which only it
+    /// can use.
+    pub private: Vec<String>,
+    /// The modules imported with `pub import`, by the names they are reached
+    /// by through this module, along with their own names.
+    pub reexports: Vec<(String, String)>,
+    /// A hash of the module's source and name, and of the keys of the
+    /// modules it imports, which identifies it in a [`ModuleCache`].
+    pub key: u64,
+}
+
+/// The modules of a program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModuleGraph {
+    /// Every module, each after the ones it imports. The entry is last.
+    pub modules: Vec<Module>,
+}
+
+impl ModuleGraph {
+    /// Load the program whose entry is the file at `entry`, along with every
+    /// module it imports from `source_dirs`.
+    pub fn load(entry: &Path, source_dirs: &[PathBuf]) -> Result<ModuleGraph, InterpreterError> {
+        ModuleGraph::load_cached(entry, source_dirs, &mut ModuleCache::new())
+    }
+
+    /// Load the program as [`ModuleGraph::load`] does, reusing the modules
+    /// in `cache` that haven't changed, and adding the rest to it.
+    pub fn load_cached(
+        entry: &Path,
+        source_dirs: &[PathBuf],
+        cache: &mut ModuleCache,
+    ) -> Result<ModuleGraph, InterpreterError> {
+        let name = entry
+            .file_stem()
+            .map_or_else(|| "main".into(), |stem| stem.to_string_lossy());
+        cache.source_dirs = source_dirs.to_vec();
+        let mut loader = Loader {
+            source_dirs,
+            cache,
+            modules: Vec::new(),
+            loaded: HashMap::new(),
+            names: HashMap::new(),
+            loading: Vec::new(),
+            reloading: false,
+        };
+        loader.load(name.into_owned(), entry.to_path_buf())?;
+        Ok(ModuleGraph {
+            modules: loader.modules,
+        })
+    }
+
+    /// Load `source` as the new source of the module called `name`, which
+    /// was loaded into `cache` before, along with the modules it imports
+    /// from the source directories it was loaded with. The module is last
+    /// in the graph, and its file isn't read.
+    pub fn reload(
+        name: &str,
+        source: String,
+        cache: &mut ModuleCache,
+    ) -> Result<ModuleGraph, InterpreterError> {
+        let (path, entry) = cache
+            .modules
+            .values()
+            .find(|cached| cached.module.name == name)
+            .map(|cached| (cached.module.path.clone(), cached.entry))
+            .ok_or_else(|| InterpreterError::ModuleNotLoaded(name.to_string()))?;
+        let source_dirs = cache.source_dirs.clone();
+        let mut loader = Loader {
+            source_dirs: &source_dirs,
+            cache,
+            modules: Vec::new(),
+            loaded: HashMap::new(),
+            names: HashMap::new(),
+            loading: Vec::new(),
+            reloading: true,
+        };
+        loader.load_source(name.to_string(), path, source.into(), entry)?;
+        Ok(ModuleGraph {
+            modules: loader.modules,
+        })
+    }
+
+    /// Return the module that the program starts running from.
+    pub fn entry(&self) -> &Module {
+        self.modules.last().expect("module graph without an entry")
+    }
+}
+
+/// Modules loaded and compiled before, so that the ones that haven't
+/// changed aren't parsed and compiled again. A module is reused if its
+/// source and name, and the modules it imports, are the same as before.
+///
+/// Compiled modules can also be saved in a directory, so that they are
+/// reused by later processes too.
+#[derive(Debug, Default)]
+pub struct ModuleCache {
+    /// The module last loaded from each file, by its canonical path.
+    modules: HashMap<PathBuf, CachedModule>,
+    /// Compiled modules, by their keys.
+    scripts: HashMap<u64, Function>,
+    /// The directory compiled modules are saved in, if they are.
+    dir: Option<PathBuf>,
+    /// The source directories modules were last loaded from, which modules
+    /// that are reloaded import from.
+    source_dirs: Vec<PathBuf>,
+    hits: usize,
+}
+
+#[derive(Debug)]
+struct CachedModule {
+    /// The hash of the module's source.
+    hash: u64,
+    /// Whether the module was the entry of its program.
+    entry: bool,
+    /// The imports of other modules of the project, and where they are.
+    imports: Vec<(Vec<String>, Span)>,
+    module: Module,
+}
+
+impl ModuleCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Save compiled modules in `dir`, and look for them there, or only keep
+    /// them in memory if `dir` is `None`. The directory is created when the
+    /// first module is saved.
+    pub fn set_dir(&mut self, dir: Option<PathBuf>) {
+        self.dir = dir;
+    }
+
+    /// Return the number of times a module was reused rather than parsed or
+    /// compiled again.
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// Return the module last loaded from each file.
+    pub fn modules(&self) -> impl Iterator<Item = &Module> {
+        self.modules.values().map(|cached| &cached.module)
+    }
+
+    /// Return the compiled module whose key is `key`, if it is cached.
+    pub fn script(&mut self, key: u64) -> Option<Function> {
+        let script = match self.scripts.get(&key) {
+            Some(script) => script.clone(),
+            None => {
+                let file = self.dir.as_ref()?.join(format!("{:016x}.mwc", key));
+                let script = serialize::load(file).ok()?;
+                self.scripts.insert(key, script.clone());
+                script
+            }
+        };
+        self.hits += 1;
+        Some(script)
+    }
+
+    /// Cache `script`, the compiled module whose key is `key`.
+    pub fn insert_script(&mut self, key: u64, script: &Function) {
+        if let Some(dir) = &self.dir {
+            // A module that can't be saved is compiled again next time
+            let _ = fs::create_dir_all(dir)
+                .and_then(|_| fs::write(dir.join(format!("{:016x}.mwc", key)), encode(script)));
+        }
+        self.scripts.insert(key, script.clone());
+    }
+
+    /// Forget every cached module, in memory and on disk.
+    pub fn clear(&mut self) {
+        self.modules.clear();
+        self.scripts.clear();
+        if let Some(dir) = &self.dir {
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+}
+
+/// A place in one of the files of a program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub path: PathBuf,
+    pub span: Span,
+}
+
+/// The symbols of every module of a program, for finding where the names
+/// used in a module are defined and where definitions are used, across
+/// modules. Editors build go-to-definition and find-references on it.
+///
+/// What a module uses from the modules it imports is renamed by the
+/// loader, so it doesn't resolve in the module's own [`SymbolTable`], and is
+/// found by its name among the definitions of the other modules instead.
+#[derive(Debug, Clone)]
+pub struct SymbolIndex {
+    modules: Vec<IndexedModule>,
+}
+
+#[derive(Debug, Clone)]
+struct IndexedModule {
+    path: PathBuf,
+    canonical: PathBuf,
+    table: SymbolTable,
+    /// Whether the module's definitions were renamed after it, which they
+    /// are in every module but the entry.
+    renamed: bool,
+}
+
+impl SymbolIndex {
+    /// Resolve every module of `graph`.
+    pub fn new(graph: &ModuleGraph) -> Self {
+        let last = graph.modules.len().saturating_sub(1);
+        let modules = graph
+            .modules
+            .iter()
+            .enumerate()
+            .map(|(index, module)| IndexedModule {
+                path: module.path.clone(),
+                canonical: fs::canonicalize(&module.path).unwrap_or_else(|_| module.path.clone()),
+                table: resolve(&module.program).0,
+                renamed: index != last,
+            })
+            .collect();
+        Self { modules }
+    }
+
+    /// Return where the name at `line` and `column` of the file at `path`
+    /// is defined, if it is a name defined by the program.
+    pub fn definition(&self, path: &Path, line: u32, column: u32) -> Option<Location> {
+        let (module, id) = self.target(path, line, column)?;
+        Some(Location {
+            path: module.path.clone(),
+            span: module.table.symbol(id).span,
+        })
+    }
+
+    /// Return every use of what the name at `line` and `column` of the file
+    /// at `path` refers to, in every module, without its definition.
+    pub fn references(&self, path: &Path, line: u32, column: u32) -> Vec<Location> {
+        let Some((module, id)) = self.target(path, line, column) else {
+            return Vec::new();
+        };
+        let symbol = module.table.symbol(id);
+        let mut references: Vec<_> = symbol
+            .references
+            .iter()
+            .map(|&span| Location {
+                path: module.path.clone(),
+                span,
+            })
+            .collect();
+        if module.renamed && is_definition(symbol) {
+            for other in &self.modules {
+                references.extend(
+                    other
+                        .table
+                        .unresolved()
+                        .filter(|reference| reference.name == symbol.name)
+                        .map(|reference| Location {
+                            path: other.path.clone(),
+                            span: reference.span,
+                        }),
+                );
+            }
+        }
+        references
+    }
+
+    /// Return the module defining what the name at `line` and `column` of
+    /// the file at `path` refers to, and its symbol there.
+    fn target(&self, path: &Path, line: u32, column: u32) -> Option<(&IndexedModule, SymbolId)> {
+        let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let module = self
+            .modules
+            .iter()
+            .find(|module| module.canonical == canonical)?;
+        if let Some(id) = module.table.symbol_at(line, column) {
+            return Some((module, id));
+        }
+        let name = &module.table.reference_at(line, column)?.name;
+        self.modules
+            .iter()
+            .filter(|module| module.renamed)
+            .find_map(|module| {
+                let globals = &module.table.scopes()[0].symbols;
+                let id = globals.iter().copied().find(|&id| {
+                    let symbol = module.table.symbol(id);
+                    symbol.name == *name && is_definition(symbol)
+                })?;
+                Some((module, id))
+            })
+    }
+}
+
+/// The FNV-1a hash, which unlike the standard library's hashers is the same
+/// in every build, so that keys of cached modules can be saved.
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+/// Return the FNV-1a hash of `value`.
+fn hash(value: impl Hash) -> u64 {
+    let mut hasher = Fnv::default();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+struct Loader<'a> {
+    source_dirs: &'a [PathBuf],
+    cache: &'a mut ModuleCache,
+    modules: Vec<Module>,
+    /// The index of every module loaded so far, by its canonical path.
+    loaded: HashMap<PathBuf, usize>,
+    /// The canonical path of every module loaded so far, by its name.
+    names: HashMap<String, PathBuf>,
+    /// The canonical paths and names of the modules being loaded, each
+    /// imported by the one before it.
+    loading: Vec<(PathBuf, String)>,
+    /// Whether a module is being reloaded, in which case the modules it
+    /// imports that are cached keep the source they were loaded with.
+    reloading: bool,
+}
+
+impl Loader<'_> {
+    /// Load the module called `name` from `path`, along with the modules it
+    /// imports, returning its index.
+    fn load(&mut self, name: String, path: PathBuf) -> Result<usize, InterpreterError> {
+        let canonical = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+        let source = match self.cache.modules.get(&canonical) {
+            Some(cached) if self.reloading => cached.module.source.clone(),
+            _ => Source::read(&path).map_err(|error| match error.kind() {
+                io::ErrorKind::NotFound => {
+                    InterpreterError::FileNotFound(path.display().to_string())
+                }
+                _ => InterpreterError::UnexpectedError(error.into()),
+            })?,
+        };
+        let entry = self.loading.is_empty();
+        self.load_source(name, path, source, entry)
+    }
+
+    /// Load the module called `name` from `source`, which is the source of
+    /// the file at `path`, along with the modules it imports, returning its
+    /// index. `entry` is whether the module is the entry of its program.
+    fn load_source(
+        &mut self,
+        name: String,
+        path: PathBuf,
+        source: Source,
+        entry: bool,
+    ) -> Result<usize, InterpreterError> {
+        // Errors in modules other than the entry say which file they are in
+        let file = (!entry).then(|| path.clone());
+        let failed = |source: &str, diagnostics| failed(source, file.as_deref(), diagnostics);
+        let canonical = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+
+        // A module whose source hasn't changed imports the same modules, so
+        // it only needs to be parsed again if one of them has changed
+        let source_hash = hash(&*source);
+        let cached = self
+            .cache
+            .modules
+            .get(&canonical)
+            .filter(|cached| cached.hash == source_hash && cached.module.name == name);
+        let (program, imports) = match cached {
+            Some(cached) => (None, cached.imports.clone()),
+            None => {
+                let program = parse(&source).map_err(|diagnostics| failed(&source, diagnostics))?;
+                let imports = program
+                    .iter()
+                    .filter_map(|stmt| match stmt {
+                        Stmt::Import { path, span, .. }
+                            if native::Module::from_path(path).is_none() =>
+                        {
+                            Some((path.clone(), *span))
+                        }
+                        _ => None,
+                    })
+                    .collect();
+                (Some(program), imports)
+            }
+        };
+
+        self.loading.push((canonical.clone(), name.clone()));
+        // The entry is named after its file, so modules next to it aren't
+        // inside it
+        let package = match entry {
+            true => "",
+            false => name.rsplit_once('.').map_or("", |(package, _)| package),
+        };
+        let mut diagnostics = Vec::new();
+        let mut imported = HashMap::new();
+        for (import, span) in &imports {
+            let (import, span) = (import.as_slice(), *span);
+            let (module, file) = match self.find(&path, package, import) {
+                Some(found) => found,
+                None => {
+                    diagnostics.push(self.not_found(&path, import, span));
+                    continue;
+                }
+            };
+            let canonical = fs::canonicalize(&file).unwrap_or_else(|_| file.clone());
+            if let Some(&index) = self.loaded.get(&canonical) {
+                imported.insert(span, index);
+            } else if let Some(start) = self.loading.iter().position(|(path, _)| *path == canonical)
+            {
+                let cycle: Vec<_> = self.loading[start..]
+                    .iter()
+                    .map(|(_, name)| name.as_str())
+                    .chain([module.as_str()])
+                    .collect();
+                diagnostics.push(Diagnostic::error(
+                    format!("import cycle: {}", cycle.join(" -> ")),
+                    span,
+                ));
+            } else if let Some(other) = self.names.get(&module) {
+                diagnostics.push(
+                    Diagnostic::error(format!("there are two modules called `{}`", module), span)
+                        .with_note(format!("one is {}", other.display()))
+                        .with_note(format!("the other is {}", canonical.display())),
+                );
+            } else {
+                imported.insert(span, self.load(module, file)?);
+            }
+        }
+        self.loading.pop();
+        if !diagnostics.is_empty() {
+            return Err(failed(&source, diagnostics));
+        }
+
+        let key = hash((
+            env!("CARGO_PKG_VERSION"),
+            source_hash,
+            &name,
+            entry,
+            imports
+                .iter()
+                .map(|(_, span)| self.modules[imported[span]].key)
+                .collect::<Vec<_>>(),
+        ));
+        if let Some(cached) = self.cache.modules.get(&canonical) {
+            if cached.module.key == key {
+                self.cache.hits += 1;
+                let module = cached.module.clone();
+                return Ok(self.add(canonical, module));
+            }
+        }
+        let mut program = match program {
+            Some(program) => program,
+            None => parse(&source).map_err(|diagnostics| failed(&source, diagnostics))?,
+        };
+
+        let (table, diagnostics) = resolve(&program);
+        if diagnostics.iter().any(Diagnostic::is_error) {
+            return Err(failed(&source, diagnostics));
+        }
+        let definitions = table.scopes()[0]
+            .symbols
+            .iter()
+            .map(|&id| table.symbol(id))
+            .filter(|symbol| is_definition(symbol));
+        let (exports, private) = definitions.fold(
+            (Vec::new(), Vec::new()),
+            |(mut exports, mut private), symbol| {
+                match symbol.public {
+                    true => exports.push(symbol.name.clone()),
+                    false => private.push(symbol.name.clone()),
+                }
+                (exports, private)
+            },
+        );
+        let mut diagnostics = Vec::new();
+        let mut reexports = Vec::new();
+        for symbol in table.symbols() {
+            if symbol.kind != SymbolKind::Module || !symbol.public {
+                continue;
+            }
+            match imported.get(&symbol.span) {
+                Some(&index) => {
+                    reexports.push((symbol.name.clone(), self.modules[index].name.clone()))
+                }
+                // Modules built into the VM aren't renamed, so they can only
+                // be used where they are imported
+                None => diagnostics.push(Diagnostic::error(
+                    format!(
+                        "module `{}` is built into meow, so it can't be re-exported",
+                        symbol.name
+                    ),
+                    symbol.span,
+                )),
+            }
+        }
+        if !diagnostics.is_empty() {
+            return Err(failed(&source, diagnostics));
+        }
+        let mut renamer = Renamer {
+            table: &table,
+            prefix: (!entry).then_some(name.as_str()),
+            modules: &self.modules,
+            imports: table
+                .symbols()
+                .iter()
+                .enumerate()
+                .filter(|(_, symbol)| symbol.kind == SymbolKind::Module)
+                .filter_map(|(id, symbol)| {
+                    let index = imported.get(&symbol.span)?;
+                    Some((SymbolId(id), &self.modules[*index]))
+                })
+                .collect(),
+            diagnostics: Vec::new(),
+        };
+        for stmt in &mut program {
+            renamer.stmt(stmt);
+        }
+        if !renamer.diagnostics.is_empty() {
+            return Err(failed(&source, renamer.diagnostics));
+        }
+        program.retain(
+            |stmt| !matches!(stmt, Stmt::Import { span, .. } if imported.contains_key(span)),
+        );
+
+        let module = Module {
+            name,
+            path,
+            source,
+            program,
+            exports,
+            private,
+            reexports,
+            key,
+        };
+        self.cache.modules.insert(
+            canonical.clone(),
+            CachedModule {
+                hash: source_hash,
+                entry,
+                imports,
+                module: module.clone(),
+            },
+        );
+        Ok(self.add(canonical, module))
+    }
+
+    /// Add `module`, loaded from the file at `canonical`, returning its
+    /// index.
+    fn add(&mut self, canonical: PathBuf, module: Module) -> usize {
+        let index = self.modules.len();
+        self.loaded.insert(canonical.clone(), index);
+        self.names.insert(module.name.clone(), canonical);
+        self.modules.push(module);
+        index
+    }
+
+    /// Return the name and file of the module imported as `import` by the
+    /// module at `path` in `package`, if there is one. Modules next to the
+    /// importing one are found first, then the ones in the source
+    /// directories.
+    fn find(&self, path: &Path, package: &str, import: &[String]) -> Option<(String, PathBuf)> {
+        let relative = match package {
+            "" => import.join("."),
+            package => format!("{}.{}", package, import.join(".")),
+        };
+        self.candidates(path, import)
+            .zip(iter::once(relative).chain(iter::repeat(import.join("."))))
+            .map(|(file, name)| (name, file))
+            .find(|(_, file)| file.is_file())
+    }
+
+    /// Return every file the module imported as `import` by the module at
+    /// `path` could be in.
+    fn candidates<'p>(
+        &'p self,
+        path: &'p Path,
+        import: &'p [String],
+    ) -> impl Iterator<Item = PathBuf> + 'p {
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        iter::once(dir)
+            .chain(self.source_dirs.iter().map(PathBuf::as_path))
+            .map(move |dir| {
+                let mut file = dir.join(import.join("/"));
+                file.set_extension(EXTENSION);
+                file
+            })
+    }
+
+    fn not_found(&self, path: &Path, import: &[String], span: Span) -> Diagnostic {
+        let mut diagnostic =
+            Diagnostic::error(format!("cannot find module `{}`", import.join(".")), span);
+        let mut files: Vec<_> = self.candidates(path, import).collect();
+        files.dedup();
+        for file in files {
+            diagnostic = diagnostic.with_note(format!("there is no file {}", file.display()));
+        }
+        diagnostic
+    }
+}
+
+/// Returns true if `symbol` is a function, class or variable defined at the
+/// top level of a module, which other modules can use.
+fn is_definition(symbol: &Symbol) -> bool {
+    symbol.scope == ScopeId(0)
+        && matches!(
+            symbol.kind,
+            SymbolKind::Variable { .. } | SymbolKind::Function | SymbolKind::Class
+        )
+}
+
+/// Renames what a module defines at its top level after the module, so that
+/// the definitions of different modules don't clash, and replaces what it
+/// uses from the modules it imports with their renamed definitions.
+struct Renamer<'a> {
+    table: &'a SymbolTable,
+    /// The name of the module, if its definitions are renamed. The entry's
+    /// definitions keep their names.
+    prefix: Option<&'a str>,
+    /// Every module loaded so far, which includes the ones the imported
+    /// modules re-export.
+    modules: &'a [Module],
+    /// The modules of the project that are imported, by the symbols they
+    /// are imported as.
+    imports: HashMap<SymbolId, &'a Module>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Renamer<'a> {
+    /// Rename `name`, declared or used at `span`, if it is one of the
+    /// module's definitions.
+    fn rename(&self, name: &mut String, span: Span) {
+        let symbol = self.table.resolution(span).map(|id| self.table.symbol(id));
+        if let (Some(prefix), Some(symbol)) = (self.prefix, symbol) {
+            if is_definition(symbol) {
+                *name = format!("{}.{}", prefix, name);
+            }
+        }
+    }
+
+    /// Return the module the name at `span` imports, if it is one.
+    fn import(&self, span: Span) -> Option<&'a Module> {
+        self.imports.get(&self.table.resolution(span)?).copied()
+    }
+
+    /// Return the module `expr` refers to, if it is an imported module or
+    /// one re-exported by it, as in `shapes.circle`.
+    fn module(&self, expr: &Expr) -> Option<&'a Module> {
+        match expr {
+            Expr::Ident { span, .. } => self.import(*span),
+            Expr::Field { object, name, .. } => {
+                let module = self.module(object)?;
+                let (_, reexport) = module.reexports.iter().find(|(alias, _)| alias == name)?;
+                self.modules.iter().find(|module| module.name == *reexport)
+            }
+            _ => None,
+        }
+    }
+
+    fn stmt(&mut self, stmt: &mut Stmt) {
+        match stmt {
+            Stmt::Let {
+                name, value, span, ..
+            } => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+                self.rename(name, *span);
+            }
+            Stmt::Expr { expr, .. } => self.expr(expr),
+            Stmt::Fun(fun) => {
+                self.rename(&mut fun.name, fun.span);
+                self.block(&mut fun.body);
+            }
+            Stmt::Class {
+                name,
+                methods,
+                span,
+                ..
+            } => {
+                self.rename(name, *span);
+                for method in methods {
+                    self.block(&mut method.body);
+                }
+            }
+            Stmt::Return { value, .. } | Stmt::Yield { value, .. } => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+            }
+            Stmt::Spawn { callee, args, .. } => {
+                self.expr(callee);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            Stmt::While { cond, body, .. } => {
+                self.expr(cond);
+                self.block(body);
+            }
+            Stmt::For { iterable, body, .. } => {
+                self.expr(iterable);
+                self.block(body);
+            }
+            Stmt::Import { .. } => {}
+        }
+    }
+
+    fn block(&mut self, block: &mut Block) {
+        for stmt in &mut block.stmts {
+            self.stmt(stmt);
+        }
+        if let Some(tail) = &mut block.tail {
+            self.expr(tail);
+        }
+    }
+
+    fn expr(&mut self, expr: &mut Expr) {
+        if let Some(module) = self.module(expr) {
+            self.diagnostics.push(Diagnostic::error(
+                format!(
+                    "module `{}` can only be used to reach what it defines, as in `{}.name`",
+                    module.name,
+                    path(expr)
+                ),
+                expr.span(),
+            ));
+            return;
+        }
+
+        match expr {
+            Expr::Literal { .. } => {}
+            Expr::Ident { name, span } => self.rename(name, *span),
+            Expr::Field { object, name, span } => match self.module(object) {
+                Some(module) if module.exports.contains(name) => {
+                    *expr = Expr::Ident {
+                        name: format!("{}.{}", module.name, name),
+                        span: *span,
+                    };
+                }
+                Some(module) if module.private.contains(name) => self.diagnostics.push(
+                    Diagnostic::error(
+                        format!("`{}` is private to module `{}`", name, module.name),
+                        *span,
+                    )
+                    .with_note("it can be used by other modules if it is declared `pub`"),
+                ),
+                Some(module) => self.diagnostics.push(Diagnostic::error(
+                    format!("module `{}` doesn't define `{}`", module.name, name),
+                    *span,
+                )),
+                None => self.expr(object),
+            },
+            Expr::Assign { target, value, .. } => {
+                let module = match &**target {
+                    Expr::Field { object, .. } => self.module(object),
+                    _ => None,
+                };
+                match (module, &**target) {
+                    (Some(module), Expr::Field { name, span, .. }) => {
+                        let message = format!(
+                            "cannot assign to `{}` outside of module `{}`",
+                            name, module.name
+                        );
+                        self.diagnostics.push(Diagnostic::error(message, *span));
+                    }
+                    _ => self.expr(target),
+                }
+                self.expr(value);
+            }
+            Expr::Unary { expr, .. } | Expr::Cast { expr, .. } => self.expr(expr),
+            Expr::Binary { left, right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::Call { callee, args, .. } => {
+                self.expr(callee);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            Expr::Interpolation { parts, .. } => {
+                for part in parts {
+                    if let StrPart::Expr(expr) = part {
+                        self.expr(expr);
+                    }
+                }
+            }
+            Expr::Index { object, index, .. } => {
+                self.expr(object);
+                self.expr(index);
+            }
+            Expr::List { items, .. } => {
+                for item in items {
+                    self.expr(item);
+                }
+            }
+            Expr::Block(block) => self.block(block),
+            Expr::If {
+                cond,
+                then,
+                otherwise,
+                ..
+            } => {
+                self.expr(cond);
+                self.block(then);
+                if let Some(otherwise) = otherwise {
+                    self.expr(otherwise);
+                }
+            }
+            Expr::Match {
+                scrutinee, arms, ..
+            } => {
+                self.expr(scrutinee);
+                for arm in arms {
+                    if let Some(guard) = &mut arm.guard {
+                        self.expr(guard);
+                    }
+                    self.expr(&mut arm.body);
+                }
+            }
+        }
+    }
+}
+
+/// Return the path of the module `expr` refers to, as it is written, such as
+/// `shapes.circle`.
+fn path(expr: &Expr) -> String {
+    match expr {
+        Expr::Field { object, name, .. } => format!("{}.{}", path(object), name),
+        Expr::Ident { name, .. } => name.clone(),
+        _ => String::new(),
+    }
+}
+
+/// Return the error for `diagnostics` found in `source`. If it was read from
+/// `file`, the diagnostics are noted to be in it.
+pub(crate) fn failed(
+    source: &str,
+    file: Option<&Path>,
+    diagnostics: Vec<Diagnostic>,
+) -> InterpreterError {
+    InterpreterError::Failed {
+        source_code: source.to_string(),
+        diagnostics: match file {
+            Some(file) => diagnostics
+                .into_iter()
+                .map(|diagnostic| diagnostic.with_note(format!("in {}", file.display())))
+                .collect(),
+            None => diagnostics,
+        },
+    }
+}
@@ -5,39 +5,531 @@
 
 use ansi_term::Colour::Red;
 use anyhow::Result;
-use clap::Parser;
-use meow::{run, run_from_file};
-use std::process;
+use clap::{ArgEnum, Parser};
+use meow::{
+    bytecode::serialize,
+    check, compile, compile_wasm,
+    config::{self, Config},
+    errors::InterpreterError,
+    lint::{lint, Lint, LintLevel},
+    parse, resolve, run, run_from_file, run_project, spec,
+    vm::{heap::GcConfig, Backend, Vm},
+};
+use std::{
+    env, fs,
+    io::{self, IsTerminal, Read},
+    path::Path,
+    process,
+};
+use tracing_subscriber::EnvFilter;
+
+mod doc;
+mod dump;
+mod repl;
 
 #[derive(Parser)]
 #[clap(version)]
 struct Args {
-    /// the path to the file to execute
+    /// the path to the file to execute, or `-` to read the program from
+    /// standard input
     #[clap(short, long)]
     file: Option<String>,
 
     /// the string to execute
     #[clap(short, long)]
     string: Option<String>,
+
+    /// start the REPL, even in a project, where the project's entry point
+    /// is run when no file or string is given
+    #[clap(long, conflicts_with_all = &["file", "string"])]
+    repl: bool,
+
+    /// create a new project in the directory DIR, with a `meow.toml`, a
+    /// hello world program at `src/main.mw`, and a `tests` directory
+    #[clap(long, value_name = "DIR")]
+    new: Option<String>,
+
+    /// collect garbage at every allocation, to test the garbage collector
+    #[clap(long)]
+    gc_stress: bool,
+
+    /// count and time every instruction executed, and print a report after
+    /// the program finishes
+    #[clap(long)]
+    profile: bool,
+
+    /// print how long each phase of running the program took, how many
+    /// instructions it executed, and how much memory it used after it
+    /// finishes
+    #[clap(long, alias = "time")]
+    stats: bool,
+
+    /// print every instruction as it is executed, along with the stack
+    #[clap(long)]
+    trace: bool,
+
+    /// parse and analyze the program without running it, printing every
+    /// error found, and exit with a failure if there were any
+    #[clap(long)]
+    check: bool,
+
+    /// print the tokens the program is made of instead of running it
+    #[clap(long)]
+    tokens: bool,
+
+    /// print the syntax tree the program is parsed into instead of running
+    /// it
+    #[clap(long)]
+    ast: bool,
+
+    /// print documentation for the functions and classes the program
+    /// declares, with the `///` comments before them, as `markdown` or
+    /// `html`, instead of running it
+    #[clap(long, arg_enum, value_name = "FORMAT")]
+    doc: Option<DocFormat>,
+
+    /// run the lints over the program instead of running it, printing
+    /// what they find, and exit with a failure if there were errors
+    #[clap(long)]
+    lint: bool,
+
+    /// don't report this lint with `--lint`
+    #[clap(long, value_name = "LINT", parse(try_from_str = parse_lint))]
+    allow: Vec<Lint>,
+
+    /// report this lint as a warning with `--lint`
+    #[clap(long, value_name = "LINT", parse(try_from_str = parse_lint))]
+    warn: Vec<Lint>,
+
+    /// report this lint as an error with `--lint`
+    #[clap(long, value_name = "LINT", parse(try_from_str = parse_lint))]
+    deny: Vec<Lint>,
+
+    /// how to print tokens, syntax trees and lints: `text` to be read, or
+    /// `json`
+    #[clap(long, arg_enum, default_value = "text")]
+    format: FormatArg,
+
+    /// run the conformance tests in the `.mw` files in DIR and the
+    /// directories inside it, see `meow::spec`, printing each that fails,
+    /// and exit with a failure if any did
+    #[clap(long, value_name = "DIR")]
+    spec: Option<String>,
+
+    /// compile the program into a WebAssembly module at this path instead of
+    /// running it
+    #[clap(long, value_name = "OUTPUT")]
+    wasm: Option<String>,
+
+    /// compile the program to bytecode at this path instead of running it,
+    /// so that it can be run later with `--file OUTPUT.mwc`
+    #[clap(long, value_name = "OUTPUT")]
+    compile: Option<String>,
+
+    /// leave out the spans that errors in a program compiled with
+    /// `--compile` point at, making it smaller
+    #[clap(long, requires = "compile")]
+    strip_debug: bool,
+
+    /// optimize a program compiled with `--compile`
+    #[clap(short = 'O', long, requires = "compile")]
+    optimize: bool,
+
+    /// restore the globals saved in this snapshot before running the
+    /// program, if it exists, and save them to it afterwards
+    #[clap(long, value_name = "PATH")]
+    snapshot: Option<String>,
+
+    /// turn ints that overflow into big ints instead of failing
+    #[clap(long)]
+    big_ints: bool,
+
+    /// the virtual machine to execute programs with
+    #[clap(long, arg_enum, default_value = "stack")]
+    backend: BackendArg,
+
+    /// when to color errors and REPL input: `auto` colors them if stderr is
+    /// a terminal and the `NO_COLOR` environment variable isn't set
+    #[clap(long, arg_enum, default_value = "auto")]
+    color: ColorArg,
+
+    /// log what the interpreter is doing to stderr: `-v` for an overview of
+    /// each phase, `-vv` for every token and declaration. The `MEOW_LOG`
+    /// environment variable takes a filter such as `meow::vm=debug` instead
+    #[clap(short, long, parse(from_occurrences))]
+    verbose: u8,
+
+    /// arguments for the program, which it can read from the list `args`.
+    /// Everything after `--` is passed on as it is, so that arguments such
+    /// as `--input` aren't taken as options of meow's own
+    #[clap(value_name = "ARGS")]
+    args: Vec<String>,
+}
+
+#[derive(Clone, ArgEnum)]
+enum BackendArg {
+    Stack,
+    Register,
+    Ast,
+}
+
+#[derive(Clone, Copy, ArgEnum)]
+enum FormatArg {
+    Text,
+    Json,
+}
+
+#[derive(Clone, Copy, ArgEnum)]
+enum DocFormat {
+    Markdown,
+    Html,
+}
+
+#[derive(Clone, ArgEnum)]
+enum ColorArg {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorArg {
+    /// Returns true if output should be colored.
+    fn enabled(&self) -> bool {
+        match self {
+            ColorArg::Auto => io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none(),
+            ColorArg::Always => true,
+            ColorArg::Never => false,
+        }
+    }
 }
 
 fn main() -> Result<()> {
-    let args = Args::parse();
+    let mut args = Args::parse();
+    let color = args.color.enabled();
+    init_logging(args.verbose, color);
+    if let Some(dir) = &args.new {
+        if let Err(error) = config::create_project(Path::new(dir)) {
+            eprintln!("{}: {}: {}", label(color), dir, error);
+            process::exit(1);
+        }
+        println!("Created a new project in {}", dir);
+        return Ok(());
+    }
+    if let Some(dir) = &args.spec {
+        run_spec(Path::new(dir), color);
+    }
+    let config = load_config(color);
+    let mut vm = Vm::with_gc(GcConfig {
+        stress: args.gc_stress,
+        ..GcConfig::default()
+    });
+    vm.set_backend(match args.backend {
+        BackendArg::Stack => Backend::Stack,
+        BackendArg::Register => Backend::Register,
+        BackendArg::Ast => Backend::Ast,
+    });
+    vm.set_profiling(args.profile);
+    vm.set_timing(args.stats);
+    vm.set_big_ints(args.big_ints);
+    if args.trace {
+        vm.set_tracer(Some(Box::new(io::stderr())));
+    }
+    if let Some(path) = args
+        .snapshot
+        .as_deref()
+        .filter(|path| Path::new(path).exists())
+    {
+        if let Err(error) = vm.restore(&fs::read(path)?) {
+            eprintln!("{}: {}: {}", label(color), path, error);
+            process::exit(1);
+        }
+    }
+    // Arguments are given after restoring, so they replace any saved ones
+    vm.set_args(args.args);
 
     if args.file.is_some() && args.string.is_some() {
         eprintln!(
             "{}: please input either a file or a string, not both",
-            Red.paint("error")
+            label(color)
         );
         process::exit(1);
+    }
+    // Without a program, the project's entry point is used, and running it
+    // runs the whole project
+    let project = config
+        .as_ref()
+        .filter(|_| args.file.is_none() && args.string.is_none() && !args.repl);
+    if let Some(config) = project {
+        args.file = Some(config.entry.to_string_lossy().into());
+    }
+    if args.file.as_deref() == Some("-") {
+        let mut source = String::new();
+        io::stdin().read_to_string(&mut source)?;
+        args.file = None;
+        args.string = Some(source);
+    }
+
+    if args.check {
+        let source = read_source(args.string, args.file, color);
+        let diagnostics = check(&source);
+        let failed = diagnostics.iter().any(|diagnostic| diagnostic.is_error());
+        for diagnostic in diagnostics {
+            eprintln!("{}", diagnostic.render(&source, color));
+        }
+        if failed {
+            process::exit(1);
+        }
+    } else if args.tokens {
+        dump::print_tokens(&read_source(args.string, args.file, color), args.format);
+    } else if args.ast {
+        let source = read_source(args.string, args.file, color);
+        let program = parse(&source).unwrap_or_else(|diagnostics| {
+            report(
+                InterpreterError::Failed {
+                    source_code: source.clone(),
+                    diagnostics,
+                },
+                color,
+            )
+        });
+        dump::print_ast(&program, args.format);
+    } else if let Some(format) = args.doc {
+        // Programs given as a string are documented as `main`
+        let name = args
+            .file
+            .as_deref()
+            .and_then(|file| Path::new(file).file_stem())
+            .map_or_else(|| "main".to_string(), |stem| stem.to_string_lossy().into());
+        let source = read_source(args.string, args.file, color);
+        let program = parse(&source).unwrap_or_else(|diagnostics| {
+            report(
+                InterpreterError::Failed {
+                    source_code: source.clone(),
+                    diagnostics,
+                },
+                color,
+            )
+        });
+        print!("{}", doc::render(&name, &program, format));
+    } else if args.lint {
+        let mut lints_config = config.map(|config| config.lints).unwrap_or_default();
+        for (lints, level) in [
+            (&args.allow, LintLevel::Allow),
+            (&args.warn, LintLevel::Warn),
+            (&args.deny, LintLevel::Deny),
+        ] {
+            for &lint in lints {
+                lints_config.set_level(lint, level);
+            }
+        }
+        let source = read_source(args.string, args.file, color);
+        let mut found = Vec::new();
+        match parse(&source) {
+            Ok(program) => {
+                let (table, diagnostics) = resolve(&program);
+                found.extend(diagnostics.into_iter().map(|diagnostic| (None, diagnostic)));
+                let lints = lint(&program, &table, &lints_config);
+                found.extend(lints.into_iter().map(|(lint, found)| (Some(lint), found)));
+            }
+            Err(diagnostics) => {
+                found.extend(diagnostics.into_iter().map(|diagnostic| (None, diagnostic)))
+            }
+        }
+        let failed = found.iter().any(|(_, diagnostic)| diagnostic.is_error());
+        dump::print_diagnostics(&source, &found, args.format, color);
+        if failed {
+            process::exit(1);
+        }
+    } else if let Some(output) = args.wasm {
+        let source = read_source(args.string, args.file, color);
+        let module = compile_wasm(&source).unwrap_or_else(|diagnostics| {
+            report(
+                InterpreterError::Failed {
+                    source_code: source.clone(),
+                    diagnostics,
+                },
+                color,
+            )
+        });
+        fs::write(output, module)?;
+    } else if let Some(output) = args.compile {
+        let source = read_source(args.string, args.file, color);
+        let mut script = compile(&source).unwrap_or_else(|diagnostics| {
+            report(
+                InterpreterError::Failed {
+                    source_code: source.clone(),
+                    diagnostics,
+                },
+                color,
+            )
+        });
+        if args.optimize || config.is_some_and(|config| config.optimize) {
+            script.optimize();
+        }
+        if args.strip_debug {
+            script.strip_debug_info();
+        }
+        fs::write(output, serialize::encode(&script))?;
     } else if let Some(string) = args.string {
-        run(&string)
+        let result = run(&mut vm, &string);
+        print_profile(&vm);
+        print_stats(&vm);
+        save_snapshot(&vm, args.snapshot.as_deref())?;
+        result.unwrap_or_else(|error| report(error, color));
     } else if let Some(file) = args.file {
-        run_from_file(&file)?;
+        let result = match project {
+            Some(config) => run_project(&mut vm, config),
+            None => run_from_file(&mut vm, &file),
+        };
+        print_profile(&vm);
+        print_stats(&vm);
+        save_snapshot(&vm, args.snapshot.as_deref())?;
+        result.unwrap_or_else(|error| report(error, color));
     } else {
-        // add repl logic here
-        // run(input_or_whatever)
+        repl::run(&mut vm, color)?;
+        print_stats(&vm);
+        save_snapshot(&vm, args.snapshot.as_deref())?;
     }
 
     Ok(())
 }
+
+/// Return the lint called `name`, for parsing arguments.
+fn parse_lint(name: &str) -> Result<Lint, String> {
+    Lint::from_name(name).ok_or_else(|| {
+        let names: Vec<_> = Lint::ALL.iter().map(|lint| lint.name()).collect();
+        format!("unknown lint, expected one of: {}", names.join(", "))
+    })
+}
+
+/// Log events at the level chosen by `verbose`, or by the filter in the
+/// `MEOW_LOG` environment variable if it is set, to stderr.
+fn init_logging(verbose: u8, color: bool) {
+    let filter = EnvFilter::try_from_env("MEOW_LOG").unwrap_or_else(|_| {
+        EnvFilter::new(match verbose {
+            0 => "meow=warn",
+            1 => "meow=debug",
+            _ => "meow=trace",
+        })
+    });
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr)
+        .with_ansi(color)
+        .without_time()
+        .init();
+}
+
+/// Read the configuration of the project the current directory is in, if
+/// it is in one, exiting if it can't be read.
+fn load_config(color: bool) -> Option<Config> {
+    let path = config::find(&env::current_dir().ok()?)?;
+    let config = Config::load(&path).unwrap_or_else(|error| {
+        report(
+            InterpreterError::Config {
+                path: path.display().to_string(),
+                error,
+            },
+            color,
+        )
+    });
+    Some(config)
+}
+
+/// Return the source of the program, given as a string or the
+/// path of a file, exiting if there isn't one.
+fn read_source(string: Option<String>, file: Option<String>, color: bool) -> String {
+    match (string, file) {
+        (Some(string), _) => string,
+        (_, Some(file)) => fs::read_to_string(&file)
+            .unwrap_or_else(|_| report(InterpreterError::FileNotFound(file), color)),
+        _ => {
+            eprintln!("{}: there is no program to read", label(color));
+            process::exit(1);
+        }
+    }
+}
+
+/// Print the VM's profile, if profiling is on.
+fn print_profile(vm: &Vm) {
+    if let Some(profile) = vm.profile() {
+        eprintln!("{}", profile);
+    }
+}
+
+/// Print the VM's timings and statistics, if timing is on.
+fn print_stats(vm: &Vm) {
+    let Some(timings) = vm.timings() else {
+        return;
+    };
+    eprint!("{}", timings);
+    eprintln!("{:<16} {:>12}", "instructions", vm.instructions_executed());
+    eprintln!("{:<16} {:>12}", "collections", vm.heap().collections());
+    eprintln!("{:<16} {:>12}", "peak objects", vm.heap().peak_len());
+    if let Some(kib) = peak_memory() {
+        eprintln!("{:<16} {:>9} KiB", "peak memory", kib);
+    }
+}
+
+/// Return the most memory the process has had resident at once, in KiB, if
+/// the OS reports it.
+fn peak_memory() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Save the VM's state to `path`, if there is one.
+fn save_snapshot(vm: &Vm, path: Option<&str>) -> Result<()> {
+    if let Some(path) = path {
+        fs::write(path, vm.snapshot()?)?;
+    }
+    Ok(())
+}
+
+/// Run the conformance tests in `dir`, printing each fixture that fails,
+/// then exit, with a failure if any did.
+fn run_spec(dir: &Path, color: bool) -> ! {
+    let report = spec::run_suite(dir).unwrap_or_else(|error| {
+        eprintln!("{}: {}: {}", label(color), dir.display(), error);
+        process::exit(1);
+    });
+    for (path, failures) in &report.failures {
+        eprintln!("{}: {}", label(color), path.display());
+        for failure in failures {
+            eprintln!("    {}", failure);
+        }
+    }
+    println!("{} passed, {} failed", report.passed, report.failures.len());
+    process::exit(if report.failures.is_empty() { 0 } else { 1 });
+}
+
+/// Print `error` and exit.
+fn report(error: InterpreterError, color: bool) -> ! {
+    print_error(error, color);
+    process::exit(1)
+}
+
+/// Print `error`, rendering the diagnostics of a program that failed, in
+/// color if `color` is true.
+fn print_error(error: InterpreterError, color: bool) {
+    match error {
+        InterpreterError::Failed {
+            source_code,
+            diagnostics,
+        } => {
+            for diagnostic in diagnostics {
+                eprintln!("{}", diagnostic.render(&source_code, color));
+            }
+        }
+        error => eprintln!("{}: {}", label(color), error),
+    }
+}
+
+/// Return the label put before errors, in red if `color` is true.
+fn label(color: bool) -> String {
+    match color {
+        true => Red.paint("error").to_string(),
+        false => "error".to_string(),
+    }
+}
@@ -0,0 +1,398 @@
+//! The abstract syntax tree produced by the parser. Meow is expression
+//! oriented, so blocks and `if`s are expressions that evaluate to their final
+//! expression, while declarations and loops are statements.
+
+use crate::{span::Span, value::CastType};
+use serde::Serialize;
+
+/// A literal value written directly in the source.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum Literal {
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Char(char),
+    Bool(bool),
+}
+
+/// Binary operators, named after the tokens they are written with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum BinOp {
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    EqualEqual,
+    BangEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    And,
+    Or,
+    Range,
+    RangeInclusive,
+}
+
+impl BinOp {
+    /// Return the token the operator is written with.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinOp::Plus => "+",
+            BinOp::Minus => "-",
+            BinOp::Star => "*",
+            BinOp::Slash => "/",
+            BinOp::Percent => "%",
+            BinOp::EqualEqual => "==",
+            BinOp::BangEqual => "!=",
+            BinOp::Greater => ">",
+            BinOp::GreaterEqual => ">=",
+            BinOp::Less => "<",
+            BinOp::LessEqual => "<=",
+            BinOp::And => "&&",
+            BinOp::Or => "||",
+            BinOp::Range => "..",
+            BinOp::RangeInclusive => "..=",
+        }
+    }
+}
+
+/// Unary prefix operators.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum UnaryOp {
+    Minus,
+    Bang,
+}
+
+/// A braced sequence of statements, optionally ending in an expression
+/// without a trailing semicolon. That expression is the value of the block.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Block {
+    pub stmts: Vec<Stmt>,
+    pub tail: Option<Box<Expr>>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum Expr {
+    Literal {
+        value: Literal,
+        span: Span,
+    },
+    Ident {
+        name: String,
+        span: Span,
+    },
+    Unary {
+        op: UnaryOp,
+        expr: Box<Expr>,
+        span: Span,
+    },
+    Binary {
+        op: BinOp,
+        left: Box<Expr>,
+        right: Box<Expr>,
+        span: Span,
+    },
+    /// A conversion such as `x as float`.
+    Cast {
+        expr: Box<Expr>,
+        ty: CastType,
+        span: Span,
+    },
+    /// Assignment to an identifier, field, or index. Compound assignments
+    /// such as `+=` store the operator they apply.
+    Assign {
+        target: Box<Expr>,
+        op: Option<BinOp>,
+        value: Box<Expr>,
+        span: Span,
+    },
+    Call {
+        callee: Box<Expr>,
+        args: Vec<Expr>,
+        span: Span,
+    },
+    Field {
+        object: Box<Expr>,
+        name: String,
+        span: Span,
+    },
+    Index {
+        object: Box<Expr>,
+        index: Box<Expr>,
+        span: Span,
+    },
+    List {
+        items: Vec<Expr>,
+        span: Span,
+    },
+    /// A string with expressions interpolated into it, such as `"a ${x}"`,
+    /// whose value is its parts joined, the expressions formatted as
+    /// `format` does.
+    Interpolation {
+        parts: Vec<StrPart>,
+        span: Span,
+    },
+    Block(Box<Block>),
+    If {
+        cond: Box<Expr>,
+        then: Box<Block>,
+        otherwise: Option<Box<Expr>>,
+        span: Span,
+    },
+    /// A `match` on `scrutinee`, which evaluates the body of the first arm
+    /// whose pattern matches and whose guard, if any, is true.
+    Match {
+        scrutinee: Box<Expr>,
+        arms: Vec<MatchArm>,
+        span: Span,
+    },
+}
+
+impl Expr {
+    /// Return the span covering the whole expression.
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Literal { span, .. }
+            | Expr::Ident { span, .. }
+            | Expr::Unary { span, .. }
+            | Expr::Binary { span, .. }
+            | Expr::Cast { span, .. }
+            | Expr::Assign { span, .. }
+            | Expr::Call { span, .. }
+            | Expr::Field { span, .. }
+            | Expr::Index { span, .. }
+            | Expr::List { span, .. }
+            | Expr::Interpolation { span, .. }
+            | Expr::If { span, .. }
+            | Expr::Match { span, .. } => *span,
+            Expr::Block(block) => block.span,
+        }
+    }
+
+    /// Returns true for expressions ending in a block, which don't need a
+    /// semicolon when used as statements.
+    pub fn is_block_like(&self) -> bool {
+        matches!(self, Expr::Block(_) | Expr::If { .. } | Expr::Match { .. })
+    }
+}
+
+// A node is allocated for nearly every piece of syntax, so the largest
+// variants are boxed to keep the others small. Growing a node should be a
+// deliberate choice, so these fail the build when one changes size.
+#[cfg(target_pointer_width = "64")]
+const _: () = {
+    assert!(std::mem::size_of::<Expr>() == 48);
+    assert!(std::mem::size_of::<Stmt>() == 112);
+};
+
+/// A piece of an interpolated string, either text or the expression in a
+/// `${}`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum StrPart {
+    Text(String),
+    Expr(Expr),
+}
+
+impl StrPart {
+    /// Return the expressions among `parts`, in order.
+    pub fn exprs(parts: &[StrPart]) -> impl Iterator<Item = &Expr> {
+        parts.iter().filter_map(|part| match part {
+            StrPart::Expr(expr) => Some(expr),
+            StrPart::Text(_) => None,
+        })
+    }
+
+    /// Return the template `format` is given for an interpolated string
+    /// made of `parts`, with a `{}` for each expression and the braces in
+    /// the text doubled.
+    pub fn template(parts: &[StrPart]) -> String {
+        let mut template = String::new();
+        for part in parts {
+            match part {
+                StrPart::Text(text) => {
+                    template.push_str(&text.replace('{', "{{").replace('}', "}}"))
+                }
+                StrPart::Expr(_) => template.push_str("{}"),
+            }
+        }
+        template
+    }
+}
+
+/// A single `pattern if guard => body` arm of a `match`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub guard: Option<Expr>,
+    pub body: Expr,
+    pub span: Span,
+}
+
+/// The left hand side of a match arm.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum Pattern {
+    /// A literal, which matches any value equal to it by `==`.
+    Literal { value: Literal, span: Span },
+    /// An int range such as `1..10` or `1..=9`, which matches the ints it
+    /// contains and the floats equal to them.
+    Range {
+        start: i64,
+        end: i64,
+        inclusive: bool,
+        span: Span,
+    },
+    /// `_`, which matches anything.
+    Wildcard { span: Span },
+    /// A name, which matches anything and binds it for the guard and body.
+    Binding { name: String, span: Span },
+    /// Alternatives separated by `|`, which matches if any of them do.
+    Or { patterns: Vec<Pattern>, span: Span },
+}
+
+impl Pattern {
+    pub fn span(&self) -> Span {
+        match self {
+            Pattern::Literal { span, .. }
+            | Pattern::Range { span, .. }
+            | Pattern::Wildcard { span }
+            | Pattern::Binding { span, .. }
+            | Pattern::Or { span, .. } => *span,
+        }
+    }
+
+    /// Returns true if the pattern matches every value.
+    pub fn is_irrefutable(&self) -> bool {
+        match self {
+            Pattern::Wildcard { .. } | Pattern::Binding { .. } => true,
+            Pattern::Or { patterns, .. } => patterns.iter().any(Pattern::is_irrefutable),
+            Pattern::Literal { .. } | Pattern::Range { .. } => false,
+        }
+    }
+}
+
+/// A function parameter.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Param {
+    pub name: String,
+    pub span: Span,
+}
+
+/// A `fun` declaration, used both for free functions and class methods.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FunDecl {
+    pub name: String,
+    pub params: Vec<Param>,
+    pub body: Box<Block>,
+    /// The span of the function's name.
+    pub span: Span,
+    /// Whether the function is declared `pub`, so that the modules
+    /// importing it can use it. Methods never are.
+    pub public: bool,
+    /// The text of the doc comments before the function, a line each.
+    pub doc: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum Stmt {
+    Let {
+        name: String,
+        mutable: bool,
+        value: Option<Expr>,
+        /// The span of the bound name.
+        span: Span,
+        public: bool,
+        /// The text of the doc comments before the declaration, a line each.
+        doc: Option<String>,
+    },
+    Expr {
+        expr: Expr,
+        span: Span,
+    },
+    Fun(FunDecl),
+    Class {
+        name: String,
+        methods: Vec<FunDecl>,
+        /// The span of the class's name.
+        span: Span,
+        public: bool,
+        /// The text of the doc comments before the class, a line each.
+        doc: Option<String>,
+    },
+    Return {
+        value: Option<Expr>,
+        span: Span,
+    },
+    /// Produce a value from a generator, suspending it until the next value
+    /// is needed.
+    Yield {
+        value: Option<Expr>,
+        span: Span,
+    },
+    /// Start a new task running a call, without waiting for it to finish.
+    Spawn {
+        callee: Expr,
+        args: Vec<Expr>,
+        span: Span,
+    },
+    While {
+        cond: Expr,
+        body: Box<Block>,
+        span: Span,
+    },
+    For {
+        var: Param,
+        iterable: Expr,
+        body: Box<Block>,
+        span: Span,
+    },
+    /// Import a module. A `pub` import re-exports it, so that the modules
+    /// importing this one can reach it too.
+    Import {
+        path: Vec<String>,
+        span: Span,
+        public: bool,
+    },
+}
+
+impl Stmt {
+    /// Return the span associated with the statement.
+    pub fn span(&self) -> Span {
+        match self {
+            Stmt::Let { span, .. }
+            | Stmt::Expr { span, .. }
+            | Stmt::Fun(FunDecl { span, .. })
+            | Stmt::Class { span, .. }
+            | Stmt::Return { span, .. }
+            | Stmt::Yield { span, .. }
+            | Stmt::Spawn { span, .. }
+            | Stmt::While { span, .. }
+            | Stmt::For { span, .. }
+            | Stmt::Import { span, .. } => *span,
+        }
+    }
+
+    /// Return the text of the doc comments before the statement, if it is a
+    /// declaration that has any.
+    pub fn doc(&self) -> Option<&str> {
+        match self {
+            Stmt::Let { doc, .. } | Stmt::Fun(FunDecl { doc, .. }) | Stmt::Class { doc, .. } => {
+                doc.as_deref()
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns true if the statement is a declaration marked `pub`.
+    pub fn is_public(&self) -> bool {
+        match self {
+            Stmt::Let { public, .. }
+            | Stmt::Fun(FunDecl { public, .. })
+            | Stmt::Class { public, .. }
+            | Stmt::Import { public, .. } => *public,
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,1031 @@
+//! Parsing is the second step of Meow's execution. The [`Parser`] pulls
+//! tokens from the [`Lexer`] on demand and builds the AST defined in
+//! [`ast`].
+//!
+//! Statements are parsed by recursive descent, and expressions with a Pratt
+//! parser driven by the table in [`precedence`]. Errors, including the
+//! `Error` tokens produced by the lexer, are collected as
+//! [`Diagnostic`]s. After an error the parser skips ahead to the next
+//! statement boundary, so a single run reports as many problems as possible.
+//! Trees can be turned back into source code with [`unparse`].
+
+pub mod ast;
+pub mod precedence;
+pub mod unparse;
+
+use crate::{
+    diagnostics::Diagnostic,
+    lexer::{
+        token::{Token, TokenKind},
+        Lexer,
+    },
+    span::Span,
+    value::CastType,
+};
+use ast::{BinOp, Block, Expr, FunDecl, Literal, MatchArm, Param, Pattern, Stmt, StrPart, UnaryOp};
+use precedence::{get_precedence, Precedence};
+use std::mem;
+use tracing::{debug, trace};
+
+type ParseResult<T> = Result<T, Diagnostic>;
+
+/// How deeply expressions and blocks can be nested inside each other. The
+/// parser, and every pass after it, recurses once per level, so deeper
+/// programs would overflow the stack.
+pub const MAX_NESTING: usize = 256;
+
+/// Return the value of the int literal `text`, which is written in decimal
+/// or after a `0x`, `0o` or `0b` prefix, negated if `negative` is true.
+/// Returns `None` if it doesn't fit in an int.
+fn int_value(text: &str, negative: bool) -> Option<i64> {
+    let (radix, digits) = match text.get(..2) {
+        Some("0x") => (16, &text[2..]),
+        Some("0o") => (8, &text[2..]),
+        Some("0b") => (2, &text[2..]),
+        _ => (10, text),
+    };
+    let magnitude = u64::from_str_radix(digits, radix).ok()?;
+    if negative {
+        0i64.checked_sub_unsigned(magnitude)
+    } else {
+        i64::try_from(magnitude).ok()
+    }
+}
+
+/// An entry of a block, which is either a statement or the trailing
+/// expression that gives the block its value.
+enum BlockItem {
+    Stmt(Stmt),
+    Tail(Expr),
+}
+
+/// The `Parser` struct turns the token stream of a [`Lexer`] into a list of
+/// [`Stmt`]s.
+pub struct Parser<'a> {
+    lexer: Lexer<'a>,
+    current: Token<'a>,
+    previous: Token<'a>,
+    /// The text of the doc comments right before the current token, which
+    /// belongs to it if it starts a declaration.
+    doc: Option<String>,
+    diagnostics: Vec<Diagnostic>,
+    /// The number of expressions and blocks being parsed inside each other.
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    /// Create a new parser over `source`. This should typically be used
+    /// through the top-level `parse()` function.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use meow::parser::Parser;
+    ///
+    /// let program = Parser::new("let x = 1 + 2;").parse().unwrap();
+    /// assert_eq!(program.len(), 1);
+    /// ```
+    pub fn new(source: &'a str) -> Self {
+        let eof = Token::new(TokenKind::Eof, 1, 1, 0);
+        let mut parser = Self {
+            lexer: Lexer::new(source),
+            current: eof.clone(),
+            previous: eof,
+            doc: None,
+            diagnostics: Vec::new(),
+            depth: 0,
+        };
+        parser.advance();
+        parser
+    }
+
+    /// Parse the whole source, returning either the program or every
+    /// diagnostic encountered along the way.
+    pub fn parse(mut self) -> Result<Vec<Stmt>, Vec<Diagnostic>> {
+        let mut program = Vec::new();
+
+        while !self.check(&TokenKind::Eof) {
+            let start = self.current.span();
+            match self.declaration() {
+                Ok(stmt) => {
+                    trace!(span = %stmt.span(), "parsed a statement");
+                    program.push(stmt);
+                }
+                Err(diagnostic) => {
+                    debug!(
+                        span = %diagnostic.span,
+                        message = %diagnostic.message,
+                        "syntax error, skipping to the next statement"
+                    );
+                    self.diagnostics.push(diagnostic);
+                    self.synchronize();
+                    if self.current.span() == start {
+                        self.advance();
+                    }
+                }
+            }
+        }
+
+        debug!(
+            statements = program.len(),
+            errors = self.diagnostics.len(),
+            "parsed"
+        );
+        if self.diagnostics.is_empty() {
+            Ok(program)
+        } else {
+            Err(self.diagnostics)
+        }
+    }
+
+    /// Move to the next token, reporting any `Error` tokens from the lexer
+    /// and skipping over them.
+    fn advance(&mut self) {
+        let mut doc: Option<String> = None;
+        loop {
+            let next = self.lexer.next_token();
+            let span = next.span();
+            match next.kind {
+                TokenKind::Error(message) => {
+                    self.diagnostics.push(Diagnostic::error(message, span));
+                }
+                TokenKind::DocComment(line) => match &mut doc {
+                    Some(doc) => {
+                        doc.push('\n');
+                        doc.push_str(line);
+                    }
+                    None => doc = Some(line.to_string()),
+                },
+                kind => {
+                    let next = Token { kind, ..next };
+                    self.previous = mem::replace(&mut self.current, next);
+                    self.doc = doc;
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Returns true if the current token is of the given kind. Kinds carrying
+    /// data only compare their variant.
+    fn check(&self, kind: &TokenKind<'_>) -> bool {
+        mem::discriminant(&self.current.kind) == mem::discriminant(kind)
+    }
+
+    /// Consume the current token if it is of the given kind.
+    fn matches(&mut self, kind: &TokenKind<'_>) -> bool {
+        if self.check(kind) {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Consume the current token if it is of the given kind, or fail with
+    /// `message` otherwise.
+    fn expect(&mut self, kind: &TokenKind<'_>, message: &str) -> ParseResult<Token<'a>> {
+        if self.check(kind) {
+            self.advance();
+            Ok(self.previous.clone())
+        } else {
+            Err(self.error_at_current(message))
+        }
+    }
+
+    /// Consume an identifier, returning its name and span.
+    fn expect_ident(&mut self, message: &str) -> ParseResult<(String, Span)> {
+        match &self.current.kind {
+            TokenKind::Ident(name) => {
+                let name = name.to_string();
+                self.advance();
+                Ok((name, self.previous.span()))
+            }
+            _ => Err(self.error_at_current(message)),
+        }
+    }
+
+    /// Create an error pointing at the current token.
+    fn error_at_current(&self, message: &str) -> Diagnostic {
+        let found = match self.current.kind {
+            TokenKind::Eof => "end of file".to_string(),
+            ref kind => format!("{:?}", kind),
+        };
+        Diagnostic::error(format!("{}, found {}", message, found), self.current.span())
+    }
+
+    /// Run `parse` one level of nesting deeper, failing if that is more than
+    /// [`MAX_NESTING`] levels.
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> ParseResult<T>) -> ParseResult<T> {
+        if self.depth == MAX_NESTING {
+            return Err(Diagnostic::error(
+                "expression nested too deeply",
+                self.current.span(),
+            ));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// Skip tokens until the start of what looks like the next statement.
+    fn synchronize(&mut self) {
+        while !self.check(&TokenKind::Eof) {
+            if self.previous.kind == TokenKind::Semicolon {
+                return;
+            }
+
+            match self.current.kind {
+                TokenKind::Class
+                | TokenKind::Fun
+                | TokenKind::Let
+                | TokenKind::For
+                | TokenKind::If
+                | TokenKind::While
+                | TokenKind::Return
+                | TokenKind::Yield
+                | TokenKind::Spawn
+                | TokenKind::Import
+                | TokenKind::Pub
+                | TokenKind::CloseBrace => return,
+                _ => self.advance(),
+            }
+        }
+    }
+
+    /// Returns true if the current token starts a statement that can't be
+    /// parsed as an expression.
+    fn at_statement(&self) -> bool {
+        matches!(
+            self.current.kind,
+            TokenKind::Let
+                | TokenKind::Fun
+                | TokenKind::Class
+                | TokenKind::Import
+                | TokenKind::Pub
+                | TokenKind::Return
+                | TokenKind::Yield
+                | TokenKind::Spawn
+                | TokenKind::While
+                | TokenKind::For
+                | TokenKind::Trait
+        )
+    }
+
+    fn declaration(&mut self) -> ParseResult<Stmt> {
+        if self.at_statement() {
+            return self.statement();
+        }
+
+        let expr = self.expression()?;
+        self.expression_statement(expr)
+    }
+
+    /// Parse any statement other than an expression statement, along with
+    /// the doc comments before it if it is a declaration.
+    fn statement(&mut self) -> ParseResult<Stmt> {
+        let doc = self.doc.take();
+        let mut stmt = self.undocumented_statement()?;
+        if let Stmt::Let { doc: slot, .. }
+        | Stmt::Fun(FunDecl { doc: slot, .. })
+        | Stmt::Class { doc: slot, .. } = &mut stmt
+        {
+            *slot = doc;
+        }
+        Ok(stmt)
+    }
+
+    fn undocumented_statement(&mut self) -> ParseResult<Stmt> {
+        match self.current.kind {
+            TokenKind::Let => self.let_declaration(),
+            TokenKind::Fun => {
+                self.advance();
+                Ok(Stmt::Fun(self.function()?))
+            }
+            TokenKind::Class => self.class_declaration(),
+            TokenKind::Import => self.import(),
+            TokenKind::Pub => self.public_declaration(),
+            TokenKind::Return => self.return_statement(),
+            TokenKind::Yield => self.yield_statement(),
+            TokenKind::Spawn => self.spawn_statement(),
+            TokenKind::While => self.while_statement(),
+            TokenKind::For => self.for_statement(),
+            _ => Err(Diagnostic::error(
+                "trait declarations are not supported yet",
+                self.current.span(),
+            )),
+        }
+    }
+
+    /// Finish an expression statement, which needs a semicolon unless it ends
+    /// in a block.
+    fn expression_statement(&mut self, expr: Expr) -> ParseResult<Stmt> {
+        if expr.is_block_like() {
+            self.matches(&TokenKind::Semicolon);
+        } else {
+            self.expect(&TokenKind::Semicolon, "expected `;` after expression")?;
+        }
+
+        let span = expr.span();
+        Ok(Stmt::Expr { expr, span })
+    }
+
+    /// Parse a declaration marked `pub`.
+    fn public_declaration(&mut self) -> ParseResult<Stmt> {
+        self.advance();
+        let mut stmt = match self.current.kind {
+            TokenKind::Let => self.let_declaration()?,
+            TokenKind::Fun => {
+                self.advance();
+                Stmt::Fun(self.function()?)
+            }
+            TokenKind::Class => self.class_declaration()?,
+            TokenKind::Import => self.import()?,
+            _ => {
+                return Err(Diagnostic::error(
+                    "expected `let`, `fun`, `class` or `import` after `pub`",
+                    self.current.span(),
+                ))
+            }
+        };
+        match &mut stmt {
+            Stmt::Let { public, .. }
+            | Stmt::Fun(FunDecl { public, .. })
+            | Stmt::Class { public, .. }
+            | Stmt::Import { public, .. } => *public = true,
+            _ => unreachable!("only declarations can be public"),
+        }
+        Ok(stmt)
+    }
+
+    fn let_declaration(&mut self) -> ParseResult<Stmt> {
+        self.advance();
+        let mutable = self.matches(&TokenKind::Mut);
+        let (name, span) = self.expect_ident("expected variable name after `let`")?;
+
+        let value = if self.matches(&TokenKind::Equal) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+
+        self.expect(
+            &TokenKind::Semicolon,
+            "expected `;` after variable declaration",
+        )?;
+        Ok(Stmt::Let {
+            name,
+            mutable,
+            value,
+            span,
+            public: false,
+            doc: None,
+        })
+    }
+
+    /// Parse a function after its `fun` keyword.
+    fn function(&mut self) -> ParseResult<FunDecl> {
+        let (name, span) = self.expect_ident("expected function name")?;
+        self.expect(&TokenKind::OpenParen, "expected `(` after function name")?;
+
+        let mut params = Vec::new();
+        if !self.check(&TokenKind::CloseParen) {
+            loop {
+                let (name, span) = self.expect_ident("expected parameter name")?;
+                params.push(Param { name, span });
+                if !self.matches(&TokenKind::Comma) || self.check(&TokenKind::CloseParen) {
+                    break;
+                }
+            }
+        }
+        self.expect(&TokenKind::CloseParen, "expected `)` after parameters")?;
+
+        let body = self.block()?;
+        Ok(FunDecl {
+            name,
+            params,
+            body,
+            span,
+            public: false,
+            doc: None,
+        })
+    }
+
+    fn class_declaration(&mut self) -> ParseResult<Stmt> {
+        self.advance();
+        let (name, span) = self.expect_ident("expected class name")?;
+        self.expect(&TokenKind::OpenBrace, "expected `{` before class body")?;
+
+        let mut methods = Vec::new();
+        while !self.check(&TokenKind::CloseBrace) && !self.check(&TokenKind::Eof) {
+            let doc = self.doc.take();
+            self.expect(&TokenKind::Fun, "expected method declaration")?;
+            methods.push(FunDecl {
+                doc,
+                ..self.function()?
+            });
+        }
+        self.expect(&TokenKind::CloseBrace, "expected `}` after class body")?;
+
+        Ok(Stmt::Class {
+            name,
+            methods,
+            span,
+            public: false,
+            doc: None,
+        })
+    }
+
+    fn import(&mut self) -> ParseResult<Stmt> {
+        self.advance();
+        let start = self.previous.span();
+
+        let mut path = vec![self.expect_ident("expected module name after `import`")?.0];
+        while self.matches(&TokenKind::Dot) {
+            path.push(self.expect_ident("expected module name after `.`")?.0);
+        }
+        let end = self.previous.span();
+
+        self.expect(&TokenKind::Semicolon, "expected `;` after import")?;
+        Ok(Stmt::Import {
+            path,
+            span: start.to(end),
+            public: false,
+        })
+    }
+
+    fn return_statement(&mut self) -> ParseResult<Stmt> {
+        self.advance();
+        let mut span = self.previous.span();
+
+        let value = if self.check(&TokenKind::Semicolon) || self.check(&TokenKind::CloseBrace) {
+            None
+        } else {
+            let value = self.expression()?;
+            span = span.to(value.span());
+            Some(value)
+        };
+
+        if !self.check(&TokenKind::CloseBrace) {
+            self.expect(&TokenKind::Semicolon, "expected `;` after return value")?;
+        }
+        Ok(Stmt::Return { value, span })
+    }
+
+    fn yield_statement(&mut self) -> ParseResult<Stmt> {
+        self.advance();
+        let mut span = self.previous.span();
+
+        let value = if self.check(&TokenKind::Semicolon) || self.check(&TokenKind::CloseBrace) {
+            None
+        } else {
+            let value = self.expression()?;
+            span = span.to(value.span());
+            Some(value)
+        };
+
+        if !self.check(&TokenKind::CloseBrace) {
+            self.expect(&TokenKind::Semicolon, "expected `;` after yielded value")?;
+        }
+        Ok(Stmt::Yield { value, span })
+    }
+
+    fn spawn_statement(&mut self) -> ParseResult<Stmt> {
+        self.advance();
+        let start = self.previous.span();
+        let (callee, args, end) = match self.expression()? {
+            Expr::Call { callee, args, span } => (*callee, args, span),
+            expr => {
+                return Err(Diagnostic::error(
+                    "expected a function call after `spawn`",
+                    expr.span(),
+                ))
+            }
+        };
+
+        self.expect(&TokenKind::Semicolon, "expected `;` after spawned call")?;
+        Ok(Stmt::Spawn {
+            callee,
+            args,
+            span: start.to(end),
+        })
+    }
+
+    fn while_statement(&mut self) -> ParseResult<Stmt> {
+        self.advance();
+        let span = self.previous.span();
+        let cond = self.expression()?;
+        let body = self.block()?;
+        Ok(Stmt::While { cond, body, span })
+    }
+
+    fn for_statement(&mut self) -> ParseResult<Stmt> {
+        self.advance();
+        let span = self.previous.span();
+        let (name, var_span) = self.expect_ident("expected loop variable after `for`")?;
+        self.expect(&TokenKind::In, "expected `in` after loop variable")?;
+        let iterable = self.expression()?;
+        let body = self.block()?;
+        Ok(Stmt::For {
+            var: Param {
+                name,
+                span: var_span,
+            },
+            iterable,
+            body,
+            span,
+        })
+    }
+
+    /// Parse a braced block, recovering from errors in its statements so the
+    /// rest of the block is still checked.
+    fn block(&mut self) -> ParseResult<Box<Block>> {
+        let open = self.expect(&TokenKind::OpenBrace, "expected `{`")?;
+        self.block_body(open.span())
+    }
+
+    /// Parse the rest of a block whose opening brace is at `open`.
+    fn block_body(&mut self, open: Span) -> ParseResult<Box<Block>> {
+        self.nested(|parser| parser.block_items(open)).map(Box::new)
+    }
+
+    fn block_items(&mut self, open: Span) -> ParseResult<Block> {
+        let mut stmts = Vec::new();
+        let mut tail = None;
+        while !self.check(&TokenKind::CloseBrace) && !self.check(&TokenKind::Eof) {
+            let start = self.current.span();
+            match self.block_item() {
+                Ok(BlockItem::Stmt(stmt)) => stmts.push(stmt),
+                Ok(BlockItem::Tail(expr)) => tail = Some(Box::new(expr)),
+                Err(diagnostic) => {
+                    self.diagnostics.push(diagnostic);
+                    self.synchronize();
+                    if self.current.span() == start {
+                        self.advance();
+                    }
+                }
+            }
+        }
+
+        let close = self.expect(&TokenKind::CloseBrace, "expected `}` after block")?;
+        Ok(Block {
+            stmts,
+            tail,
+            span: open.to(close.span()),
+        })
+    }
+
+    fn block_item(&mut self) -> ParseResult<BlockItem> {
+        if self.at_statement() {
+            return Ok(BlockItem::Stmt(self.statement()?));
+        }
+
+        let expr = self.expression()?;
+        if self.check(&TokenKind::CloseBrace) {
+            Ok(BlockItem::Tail(expr))
+        } else {
+            Ok(BlockItem::Stmt(self.expression_statement(expr)?))
+        }
+    }
+
+    fn expression(&mut self) -> ParseResult<Expr> {
+        self.parse_precedence(Precedence::Assignment)
+    }
+
+    /// Parse an expression whose infix operators bind at least as tightly as
+    /// `precedence`.
+    fn parse_precedence(&mut self, precedence: Precedence) -> ParseResult<Expr> {
+        self.nested(|parser| {
+            parser.advance();
+            let mut expr = parser.prefix()?;
+
+            while precedence <= get_precedence(&parser.current.kind) {
+                parser.advance();
+                expr = parser.infix(expr)?;
+            }
+
+            Ok(expr)
+        })
+    }
+
+    /// Parse the expression starting with the previous token.
+    fn prefix(&mut self) -> ParseResult<Expr> {
+        let token = self.previous.clone();
+        let span = token.span();
+
+        match token.kind {
+            TokenKind::Int(_)
+            | TokenKind::Float(_)
+            | TokenKind::Str(_)
+            | TokenKind::Char(_)
+            | TokenKind::True
+            | TokenKind::False => Ok(Expr::Literal {
+                value: self.parse_literal(&token)?,
+                span,
+            }),
+            TokenKind::Ident(name) => Ok(Expr::Ident {
+                name: name.to_string(),
+                span,
+            }),
+            TokenKind::StrStart(text) => self.interpolation(&text, span),
+            TokenKind::OpenParen => {
+                let expr = self.expression()?;
+                self.expect(&TokenKind::CloseParen, "expected `)` after expression")?;
+                Ok(expr)
+            }
+            TokenKind::OpenBracket => {
+                let items = self.arguments(&TokenKind::CloseBracket)?;
+                Ok(Expr::List {
+                    items,
+                    span: span.to(self.previous.span()),
+                })
+            }
+            TokenKind::Minus | TokenKind::Bang => {
+                let op = if token.kind == TokenKind::Minus {
+                    UnaryOp::Minus
+                } else {
+                    UnaryOp::Bang
+                };
+                let expr = self.parse_precedence(Precedence::Unary)?;
+                Ok(Expr::Unary {
+                    op,
+                    span: span.to(expr.span()),
+                    expr: Box::new(expr),
+                })
+            }
+            TokenKind::OpenBrace => Ok(Expr::Block(self.block_body(span)?)),
+            TokenKind::If => self.if_expression(),
+            TokenKind::Match => self.match_expression(),
+            _ => Err(Diagnostic::error(
+                format!("expected expression, found {:?}", token.kind),
+                span,
+            )),
+        }
+    }
+
+    /// Parse the rest of an interpolated string, whose text up to its first
+    /// expression was `text`. Empty text between expressions is left out of
+    /// its parts.
+    fn interpolation(&mut self, text: &str, span: Span) -> ParseResult<Expr> {
+        let mut parts = vec![];
+        let push_text = |parts: &mut Vec<StrPart>, text: &str| {
+            if !text.is_empty() {
+                parts.push(StrPart::Text(text.to_string()));
+            }
+        };
+        push_text(&mut parts, text);
+        loop {
+            if let TokenKind::StrMiddle(_) | TokenKind::StrEnd(_) = self.current.kind {
+                return Err(Diagnostic::error(
+                    "expected an expression between `${` and `}`",
+                    self.current.span(),
+                ));
+            }
+            parts.push(StrPart::Expr(self.expression()?));
+            match &self.current.kind {
+                TokenKind::StrMiddle(text) => push_text(&mut parts, text),
+                TokenKind::StrEnd(text) => {
+                    push_text(&mut parts, text);
+                    self.advance();
+                    break;
+                }
+                _ => {
+                    return Err(self.error_at_current("expected `}` after interpolated expression"))
+                }
+            }
+            self.advance();
+        }
+
+        Ok(Expr::Interpolation {
+            parts,
+            span: span.to(self.previous.span()),
+        })
+    }
+
+    /// Parse the infix expression whose operator is the previous token.
+    fn infix(&mut self, left: Expr) -> ParseResult<Expr> {
+        let operator = self.previous.clone();
+        let precedence = get_precedence(&operator.kind);
+
+        let op = match operator.kind {
+            TokenKind::OpenParen => {
+                let args = self.arguments(&TokenKind::CloseParen)?;
+                return Ok(Expr::Call {
+                    span: left.span().to(self.previous.span()),
+                    callee: Box::new(left),
+                    args,
+                });
+            }
+            TokenKind::OpenBracket => {
+                let index = self.expression()?;
+                self.expect(&TokenKind::CloseBracket, "expected `]` after index")?;
+                return Ok(Expr::Index {
+                    span: left.span().to(self.previous.span()),
+                    object: Box::new(left),
+                    index: Box::new(index),
+                });
+            }
+            TokenKind::Dot => {
+                let (name, span) = self.expect_ident("expected field name after `.`")?;
+                return Ok(Expr::Field {
+                    span: left.span().to(span),
+                    object: Box::new(left),
+                    name,
+                });
+            }
+            TokenKind::As => {
+                let (name, span) = self.expect_ident("expected a type after `as`")?;
+                let ty = CastType::from_name(&name).ok_or_else(|| {
+                    Diagnostic::error(
+                        format!("cannot cast to `{}`, only to `int` or `float`", name),
+                        span,
+                    )
+                })?;
+                return Ok(Expr::Cast {
+                    span: left.span().to(span),
+                    expr: Box::new(left),
+                    ty,
+                });
+            }
+            TokenKind::Equal => return self.assignment(left, None),
+            TokenKind::PlusEqual => return self.assignment(left, Some(BinOp::Plus)),
+            TokenKind::MinusEqual => return self.assignment(left, Some(BinOp::Minus)),
+            TokenKind::StarEqual => return self.assignment(left, Some(BinOp::Star)),
+            TokenKind::SlashEqual => return self.assignment(left, Some(BinOp::Slash)),
+            TokenKind::PercentEqual => return self.assignment(left, Some(BinOp::Percent)),
+            TokenKind::Plus => BinOp::Plus,
+            TokenKind::Minus => BinOp::Minus,
+            TokenKind::Star => BinOp::Star,
+            TokenKind::Slash => BinOp::Slash,
+            TokenKind::Percent => BinOp::Percent,
+            TokenKind::EqualEqual => BinOp::EqualEqual,
+            TokenKind::BangEqual => BinOp::BangEqual,
+            TokenKind::Greater => BinOp::Greater,
+            TokenKind::GreaterEqual => BinOp::GreaterEqual,
+            TokenKind::Less => BinOp::Less,
+            TokenKind::LessEqual => BinOp::LessEqual,
+            TokenKind::And => BinOp::And,
+            TokenKind::Or => BinOp::Or,
+            TokenKind::Range => BinOp::Range,
+            TokenKind::RangeInclusive => BinOp::RangeInclusive,
+            _ => unreachable!("token without infix precedence: {:?}", operator.kind),
+        };
+
+        let right = self.parse_precedence(precedence.next())?;
+        Ok(Expr::Binary {
+            op,
+            span: left.span().to(right.span()),
+            left: Box::new(left),
+            right: Box::new(right),
+        })
+    }
+
+    /// Parse the right hand side of an assignment to `target`. Assignment is
+    /// right associative, so `a = b = c` assigns `c` to both.
+    fn assignment(&mut self, target: Expr, op: Option<BinOp>) -> ParseResult<Expr> {
+        if !matches!(
+            target,
+            Expr::Ident { .. } | Expr::Field { .. } | Expr::Index { .. }
+        ) {
+            return Err(Diagnostic::error(
+                "invalid assignment target",
+                target.span(),
+            ));
+        }
+
+        let value = self.parse_precedence(Precedence::Assignment)?;
+        Ok(Expr::Assign {
+            span: target.span().to(value.span()),
+            target: Box::new(target),
+            op,
+            value: Box::new(value),
+        })
+    }
+
+    /// Parse a comma separated list of expressions up to `close`, allowing a
+    /// trailing comma.
+    fn arguments(&mut self, close: &TokenKind) -> ParseResult<Vec<Expr>> {
+        let mut args = Vec::new();
+        while !self.check(close) {
+            args.push(self.expression()?);
+            if !self.matches(&TokenKind::Comma) {
+                break;
+            }
+        }
+        self.expect(close, &format!("expected {:?} after arguments", close))?;
+        Ok(args)
+    }
+
+    /// Parse an `if` expression after its keyword.
+    fn if_expression(&mut self) -> ParseResult<Expr> {
+        let span = self.previous.span();
+        let cond = self.expression()?;
+        let then = self.block()?;
+
+        let otherwise = if self.matches(&TokenKind::Else) {
+            if self.matches(&TokenKind::If) {
+                Some(Box::new(self.if_expression()?))
+            } else {
+                Some(Box::new(Expr::Block(self.block()?)))
+            }
+        } else {
+            None
+        };
+
+        Ok(Expr::If {
+            cond: Box::new(cond),
+            then,
+            otherwise,
+            span,
+        })
+    }
+
+    /// Parse a `match` expression after its keyword.
+    fn match_expression(&mut self) -> ParseResult<Expr> {
+        let span = self.previous.span();
+        let scrutinee = self.expression()?;
+        self.expect(&TokenKind::OpenBrace, "expected `{` after match value")?;
+
+        let mut arms = Vec::new();
+        while !self.check(&TokenKind::CloseBrace) && !self.check(&TokenKind::Eof) {
+            let pattern = self.pattern()?;
+            let guard = if self.matches(&TokenKind::If) {
+                Some(self.expression()?)
+            } else {
+                None
+            };
+            self.expect(&TokenKind::FatArrow, "expected `=>` after match pattern")?;
+            let body = self.expression()?;
+
+            arms.push(MatchArm {
+                span: pattern.span().to(body.span()),
+                pattern,
+                guard,
+                body,
+            });
+            if !self.matches(&TokenKind::Comma)
+                && !arms.last().is_some_and(|arm| arm.body.is_block_like())
+                && !self.check(&TokenKind::CloseBrace)
+            {
+                return Err(Diagnostic::error(
+                    "expected `,` after match arm",
+                    self.current.span(),
+                ));
+            }
+        }
+
+        let close = self.expect(&TokenKind::CloseBrace, "expected `}` after match arms")?;
+        Ok(Expr::Match {
+            scrutinee: Box::new(scrutinee),
+            arms,
+            span: span.to(close.span()),
+        })
+    }
+
+    /// Parse a match pattern, which may be several alternatives separated by
+    /// `|`.
+    fn pattern(&mut self) -> ParseResult<Pattern> {
+        let first = self.single_pattern()?;
+        if !self.check(&TokenKind::Pipe) {
+            return Ok(first);
+        }
+
+        let mut patterns = vec![first];
+        while self.matches(&TokenKind::Pipe) {
+            patterns.push(self.single_pattern()?);
+        }
+        if let Some(binding) = patterns
+            .iter()
+            .find(|pattern| matches!(pattern, Pattern::Binding { .. }))
+        {
+            return Err(Diagnostic::error(
+                "bindings cannot be used in `|` patterns",
+                binding.span(),
+            ));
+        }
+
+        Ok(Pattern::Or {
+            span: patterns[0].span().to(self.previous.span()),
+            patterns,
+        })
+    }
+
+    /// Parse a literal, range, wildcard, or binding pattern.
+    fn single_pattern(&mut self) -> ParseResult<Pattern> {
+        self.advance();
+        let token = self.previous.clone();
+        let span = token.span();
+
+        let value = match token.kind {
+            TokenKind::Ident("_") => return Ok(Pattern::Wildcard { span }),
+            TokenKind::Ident(name) => {
+                return Ok(Pattern::Binding {
+                    name: name.to_string(),
+                    span,
+                })
+            }
+            TokenKind::Minus => self.negative_literal()?,
+            TokenKind::Int(_)
+            | TokenKind::Float(_)
+            | TokenKind::Str(_)
+            | TokenKind::Char(_)
+            | TokenKind::True
+            | TokenKind::False => self.parse_literal(&token)?,
+            _ => {
+                return Err(Diagnostic::error(
+                    format!("expected a pattern, found {:?}", token.kind),
+                    span,
+                ))
+            }
+        };
+
+        let inclusive = match self.current.kind {
+            TokenKind::Range => false,
+            TokenKind::RangeInclusive => true,
+            _ => {
+                return Ok(Pattern::Literal {
+                    value,
+                    span: span.to(self.previous.span()),
+                })
+            }
+        };
+        let Literal::Int(start) = value else {
+            return Err(Diagnostic::error(
+                "range patterns must start with an int",
+                span,
+            ));
+        };
+        // Skip the range operator, then parse the end
+        self.advance();
+        self.advance();
+        let end = match self.previous.kind {
+            TokenKind::Minus => Some(self.negative_literal()?),
+            TokenKind::Int(_) => Some(self.parse_literal(&self.previous)?),
+            _ => None,
+        };
+        let Some(Literal::Int(end)) = end else {
+            return Err(Diagnostic::error(
+                "range patterns must end with an int",
+                self.previous.span(),
+            ));
+        };
+
+        Ok(Pattern::Range {
+            start,
+            end,
+            inclusive,
+            span: span.to(self.previous.span()),
+        })
+    }
+
+    /// Parse the number after a `-` in a pattern.
+    fn negative_literal(&mut self) -> ParseResult<Literal> {
+        let minus = self.previous.span();
+        self.advance();
+        let token = self.previous.clone();
+        let span = minus.to(token.span());
+
+        match token.kind {
+            TokenKind::Int(value) => int_value(&value, true)
+                .map(Literal::Int)
+                .ok_or_else(|| Diagnostic::error("integer literal is too large", span)),
+            TokenKind::Float(_) => match self.parse_literal(&token)? {
+                Literal::Float(value) => Ok(Literal::Float(-value)),
+                _ => unreachable!("float token parsed as another literal"),
+            },
+            _ => Err(Diagnostic::error(
+                "expected a number after `-` in a pattern",
+                span,
+            )),
+        }
+    }
+
+    /// Convert a literal token into its value.
+    fn parse_literal(&self, token: &Token<'_>) -> ParseResult<Literal> {
+        Ok(match &token.kind {
+            TokenKind::Int(value) => {
+                Literal::Int(int_value(value, false).ok_or_else(|| {
+                    Diagnostic::error("integer literal is too large", token.span())
+                })?)
+            }
+            TokenKind::Float(value) => {
+                let value: f64 = value
+                    .parse()
+                    .map_err(|_| Diagnostic::error("invalid float literal", token.span()))?;
+                // Exponents can be large enough to overflow
+                if value.is_infinite() {
+                    return Err(Diagnostic::error(
+                        "float literal is too large",
+                        token.span(),
+                    ));
+                }
+                Literal::Float(value)
+            }
+            TokenKind::Str(value) => Literal::Str(value.to_string()),
+            TokenKind::Char(value) => Literal::Char(*value),
+            TokenKind::True => Literal::Bool(true),
+            _ => Literal::Bool(false),
+        })
+    }
+}
@@ -0,0 +1,64 @@
+use crate::lexer::token::TokenKind;
+
+/// The binding power of infix operators, from loosest to tightest. The parser
+/// keeps consuming infix operators as long as their precedence is at least as
+/// high as the one it was asked to parse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Precedence {
+    None,
+    Assignment,
+    Range,
+    Or,
+    And,
+    Equality,
+    Comparison,
+    Term,
+    Factor,
+    Cast,
+    Unary,
+    Call,
+}
+
+impl Precedence {
+    /// Return the next tighter precedence level, used to make binary
+    /// operators left associative.
+    pub fn next(self) -> Precedence {
+        match self {
+            Precedence::None => Precedence::Assignment,
+            Precedence::Assignment => Precedence::Range,
+            Precedence::Range => Precedence::Or,
+            Precedence::Or => Precedence::And,
+            Precedence::And => Precedence::Equality,
+            Precedence::Equality => Precedence::Comparison,
+            Precedence::Comparison => Precedence::Term,
+            Precedence::Term => Precedence::Factor,
+            Precedence::Factor => Precedence::Cast,
+            Precedence::Cast => Precedence::Unary,
+            Precedence::Unary | Precedence::Call => Precedence::Call,
+        }
+    }
+}
+
+/// Return the precedence of `kind` when it appears in infix position.
+pub fn get_precedence(kind: &TokenKind) -> Precedence {
+    match kind {
+        TokenKind::Equal
+        | TokenKind::PlusEqual
+        | TokenKind::MinusEqual
+        | TokenKind::StarEqual
+        | TokenKind::SlashEqual
+        | TokenKind::PercentEqual => Precedence::Assignment,
+        TokenKind::Range | TokenKind::RangeInclusive => Precedence::Range,
+        TokenKind::Or => Precedence::Or,
+        TokenKind::And => Precedence::And,
+        TokenKind::EqualEqual | TokenKind::BangEqual => Precedence::Equality,
+        TokenKind::Greater | TokenKind::GreaterEqual | TokenKind::Less | TokenKind::LessEqual => {
+            Precedence::Comparison
+        }
+        TokenKind::Plus | TokenKind::Minus => Precedence::Term,
+        TokenKind::Star | TokenKind::Slash | TokenKind::Percent => Precedence::Factor,
+        TokenKind::As => Precedence::Cast,
+        TokenKind::OpenParen | TokenKind::OpenBracket | TokenKind::Dot => Precedence::Call,
+        _ => Precedence::None,
+    }
+}
@@ -0,0 +1,505 @@
+//! Turning a syntax tree back into source code, so that tools can change a
+//! program by changing its tree. [`to_source`] writes programs in a single
+//! style, with four spaces of indentation and a statement per line, and
+//! adds the parentheses that the precedence of operators needs. Parsing
+//! what it writes gives back the same tree, apart from spans.
+//!
+//! Strings and chars are written as they are, so ones holding quotes can't
+//! be written back.
+
+use super::{
+    ast::{BinOp, Block, Expr, FunDecl, Literal, MatchArm, Pattern, Stmt, StrPart, UnaryOp},
+    precedence::Precedence,
+};
+use std::fmt::Write;
+
+/// Write `program` as source code.
+///
+/// # Examples
+///
+/// ```
+/// use meow::{parse, parser::unparse::to_source};
+///
+/// let program = parse("let x=(1+2)*3; if x>1{print(x);}").unwrap();
+/// assert_eq!(to_source(&program), "let x = (1 + 2) * 3;\nif x > 1 {\n    print(x);\n}\n");
+/// ```
+pub fn to_source(program: &[Stmt]) -> String {
+    let mut unparser = Unparser {
+        out: String::new(),
+        indent: 0,
+    };
+    unparser.stmts(program, None);
+    unparser.out
+}
+
+/// Write `expr` as source code, as it would be written on its own.
+pub fn expr_to_source(expr: &Expr) -> String {
+    let mut unparser = Unparser {
+        out: String::new(),
+        indent: 0,
+    };
+    unparser.expr(expr, Precedence::Assignment);
+    unparser.out
+}
+
+struct Unparser {
+    out: String,
+    indent: usize,
+}
+
+impl Unparser {
+    /// Write a line for each statement, followed by `tail`, the expression
+    /// ending a block.
+    fn stmts(&mut self, stmts: &[Stmt], tail: Option<&Expr>) {
+        for (index, stmt) in stmts.iter().enumerate() {
+            self.line_start();
+            self.stmt(stmt);
+            if let Stmt::Expr { expr, .. } = stmt {
+                // Statements like `if` don't need a `;`, unless the next
+                // expression would continue them, or they end a block and
+                // would become its value
+                let next = match stmts.get(index + 1) {
+                    Some(Stmt::Expr { expr, .. }) => Some(expr),
+                    Some(_) => None,
+                    None => tail,
+                };
+                let ends_block = self.indent > 0 && index + 1 == stmts.len() && tail.is_none();
+                if !expr.is_block_like()
+                    || ends_block
+                    || next.is_some_and(|next| continues(next, Precedence::Assignment))
+                {
+                    self.out.push(';');
+                }
+            }
+            self.out.push('\n');
+        }
+        if let Some(tail) = tail {
+            self.line_start();
+            self.expr(tail, Precedence::Assignment);
+            self.out.push('\n');
+        }
+    }
+
+    fn line_start(&mut self) {
+        for _ in 0..self.indent {
+            self.out.push_str("    ");
+        }
+    }
+
+    /// Write `doc` as `///` comments, each followed by a new line at the
+    /// current indentation.
+    fn doc(&mut self, doc: Option<&str>) {
+        for line in doc.into_iter().flat_map(str::lines) {
+            if line.is_empty() {
+                self.out.push_str("///\n");
+            } else {
+                writeln!(self.out, "/// {}", line).unwrap();
+            }
+            self.line_start();
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        self.doc(stmt.doc());
+        if stmt.is_public() {
+            self.out.push_str("pub ");
+        }
+        match stmt {
+            Stmt::Let {
+                name,
+                mutable,
+                value,
+                ..
+            } => {
+                self.out
+                    .push_str(if *mutable { "let mut " } else { "let " });
+                self.out.push_str(name);
+                if let Some(value) = value {
+                    self.out.push_str(" = ");
+                    self.expr(value, Precedence::Assignment);
+                }
+                self.out.push(';');
+            }
+            Stmt::Expr { expr, .. } => self.expr(expr, Precedence::Assignment),
+            Stmt::Fun(fun) => self.function(fun),
+            Stmt::Class { name, methods, .. } => {
+                write!(self.out, "class {} {{", name).unwrap();
+                if methods.is_empty() {
+                    self.out.push('}');
+                    return;
+                }
+                self.out.push('\n');
+                self.indent += 1;
+                for method in methods {
+                    self.line_start();
+                    self.doc(method.doc.as_deref());
+                    self.function(method);
+                    self.out.push('\n');
+                }
+                self.indent -= 1;
+                self.line_start();
+                self.out.push('}');
+            }
+            Stmt::Return { value, .. } | Stmt::Yield { value, .. } => {
+                let keyword = match stmt {
+                    Stmt::Return { .. } => "return",
+                    _ => "yield",
+                };
+                self.out.push_str(keyword);
+                if let Some(value) = value {
+                    self.out.push(' ');
+                    self.expr(value, Precedence::Assignment);
+                }
+                self.out.push(';');
+            }
+            Stmt::Spawn { callee, args, .. } => {
+                self.out.push_str("spawn ");
+                self.expr(callee, Precedence::Call);
+                self.args('(', args, ')');
+                self.out.push(';');
+            }
+            Stmt::While { cond, body, .. } => {
+                self.out.push_str("while ");
+                self.expr(cond, Precedence::Assignment);
+                self.out.push(' ');
+                self.block(body);
+            }
+            Stmt::For {
+                var,
+                iterable,
+                body,
+                ..
+            } => {
+                write!(self.out, "for {} in ", var.name).unwrap();
+                self.expr(iterable, Precedence::Assignment);
+                self.out.push(' ');
+                self.block(body);
+            }
+            Stmt::Import { path, .. } => write!(self.out, "import {};", path.join(".")).unwrap(),
+        }
+    }
+
+    fn function(&mut self, fun: &FunDecl) {
+        let params: Vec<_> = fun.params.iter().map(|param| param.name.as_str()).collect();
+        write!(self.out, "fun {}({}) ", fun.name, params.join(", ")).unwrap();
+        self.block(&fun.body);
+    }
+
+    fn block(&mut self, block: &Block) {
+        if block.stmts.is_empty() && block.tail.is_none() {
+            self.out.push_str("{}");
+            return;
+        }
+        self.out.push_str("{\n");
+        self.indent += 1;
+        self.stmts(&block.stmts, block.tail.as_deref());
+        self.indent -= 1;
+        self.line_start();
+        self.out.push('}');
+    }
+
+    /// Write `expr`, in parentheses if its operators bind more loosely than
+    /// `precedence`, the least an operand where it is written needs.
+    fn expr(&mut self, expr: &Expr, precedence: Precedence) {
+        if binding(expr) < precedence {
+            self.out.push('(');
+            self.expr(expr, Precedence::None);
+            self.out.push(')');
+            return;
+        }
+
+        match expr {
+            Expr::Literal { value, .. } => self.literal(value),
+            Expr::Ident { name, .. } => self.out.push_str(name),
+            Expr::Unary { op, expr, .. } => {
+                self.out.push(match op {
+                    UnaryOp::Minus => '-',
+                    UnaryOp::Bang => '!',
+                });
+                // `--x` would read as a decrement
+                match starts_with_minus(expr) {
+                    true => self.expr(expr, Precedence::Call),
+                    false => self.expr(expr, Precedence::Unary),
+                }
+            }
+            Expr::Binary {
+                op, left, right, ..
+            } => {
+                let precedence = precedence_of(*op);
+                self.expr(left, precedence);
+                match op {
+                    BinOp::Range | BinOp::RangeInclusive => self.out.push_str(op.symbol()),
+                    op => write!(self.out, " {} ", op.symbol()).unwrap(),
+                }
+                self.expr(right, precedence.next());
+            }
+            Expr::Cast { expr, ty, .. } => {
+                self.expr(expr, Precedence::Cast);
+                write!(self.out, " as {}", ty.name()).unwrap();
+            }
+            Expr::Assign {
+                target, op, value, ..
+            } => {
+                self.expr(target, Precedence::Call);
+                match op {
+                    Some(op) => write!(self.out, " {}= ", op.symbol()).unwrap(),
+                    None => self.out.push_str(" = "),
+                }
+                self.expr(value, Precedence::Assignment);
+            }
+            Expr::Call { callee, args, .. } => {
+                self.expr(callee, Precedence::Call);
+                self.args('(', args, ')');
+            }
+            Expr::Field { object, name, .. } => {
+                self.expr(object, Precedence::Call);
+                write!(self.out, ".{}", name).unwrap();
+            }
+            Expr::Index { object, index, .. } => {
+                self.expr(object, Precedence::Call);
+                self.out.push('[');
+                self.expr(index, Precedence::Assignment);
+                self.out.push(']');
+            }
+            Expr::List { items, .. } => self.args('[', items, ']'),
+            Expr::Interpolation { parts, .. } => {
+                self.out.push('"');
+                for part in parts {
+                    match part {
+                        StrPart::Text(text) => self.string_text(text),
+                        StrPart::Expr(expr) => {
+                            self.out.push_str("${");
+                            self.expr(expr, Precedence::Assignment);
+                            self.out.push('}');
+                        }
+                    }
+                }
+                self.out.push('"');
+            }
+            Expr::Block(block) => self.block(block),
+            Expr::If {
+                cond,
+                then,
+                otherwise,
+                ..
+            } => {
+                self.out.push_str("if ");
+                self.expr(cond, Precedence::Assignment);
+                self.out.push(' ');
+                self.block(then);
+                match otherwise.as_deref() {
+                    Some(otherwise @ (Expr::If { .. } | Expr::Block(_))) => {
+                        self.out.push_str(" else ");
+                        self.expr(otherwise, Precedence::Assignment);
+                    }
+                    // Only blocks and `if`s can follow `else`
+                    Some(otherwise) => {
+                        self.out.push_str(" else {\n");
+                        self.indent += 1;
+                        self.stmts(&[], Some(otherwise));
+                        self.indent -= 1;
+                        self.line_start();
+                        self.out.push('}');
+                    }
+                    None => {}
+                }
+            }
+            Expr::Match {
+                scrutinee, arms, ..
+            } => {
+                self.out.push_str("match ");
+                self.expr(scrutinee, Precedence::Assignment);
+                self.out.push_str(" {\n");
+                self.indent += 1;
+                for arm in arms {
+                    self.line_start();
+                    self.arm(arm);
+                    self.out.push_str(",\n");
+                }
+                self.indent -= 1;
+                self.line_start();
+                self.out.push('}');
+            }
+        }
+    }
+
+    /// Write `items` separated by commas, between `open` and `close`.
+    fn args(&mut self, open: char, items: &[Expr], close: char) {
+        self.out.push(open);
+        for (index, item) in items.iter().enumerate() {
+            if index > 0 {
+                self.out.push_str(", ");
+            }
+            self.expr(item, Precedence::Assignment);
+        }
+        self.out.push(close);
+    }
+
+    fn arm(&mut self, arm: &MatchArm) {
+        self.pattern(&arm.pattern);
+        if let Some(guard) = &arm.guard {
+            self.out.push_str(" if ");
+            self.expr(guard, Precedence::Assignment);
+        }
+        self.out.push_str(" => ");
+        self.expr(&arm.body, Precedence::Assignment);
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Literal { value, .. } => self.literal(value),
+            Pattern::Range {
+                start,
+                end,
+                inclusive,
+                ..
+            } => {
+                let op = if *inclusive { "..=" } else { ".." };
+                write!(self.out, "{}{}{}", start, op, end).unwrap();
+            }
+            Pattern::Wildcard { .. } => self.out.push('_'),
+            Pattern::Binding { name, .. } => self.out.push_str(name),
+            Pattern::Or { patterns, .. } => {
+                for (index, pattern) in patterns.iter().enumerate() {
+                    if index > 0 {
+                        self.out.push_str(" | ");
+                    }
+                    self.pattern(pattern);
+                }
+            }
+        }
+    }
+
+    /// Write `c` in a literal quoted by `quote`, escaped if it has to be.
+    fn escaped(&mut self, c: char, quote: char) {
+        match c {
+            '\n' => self.out.push_str("\\n"),
+            '\t' => self.out.push_str("\\t"),
+            '\r' => self.out.push_str("\\r"),
+            '\0' => self.out.push_str("\\0"),
+            '\\' => self.out.push_str("\\\\"),
+            c if c == quote => {
+                self.out.push('\\');
+                self.out.push(c);
+            }
+            c if c.is_control() => write!(self.out, "\\u{{{:x}}}", c as u32).unwrap(),
+            c => self.out.push(c),
+        }
+    }
+
+    // Writes the text of a string, without its quotes
+    fn string_text(&mut self, text: &str) {
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            // Only a `$` before a `{` would start an interpolation
+            if c == '$' && chars.peek() == Some(&'{') {
+                self.out.push_str("\\$");
+            } else {
+                self.escaped(c, '"');
+            }
+        }
+    }
+
+    fn literal(&mut self, literal: &Literal) {
+        match literal {
+            // The lexer only reads positive ints, so the smallest one has to
+            // be computed
+            Literal::Int(i64::MIN) => write!(self.out, "(-{} - 1)", i64::MAX).unwrap(),
+            Literal::Int(value) => write!(self.out, "{}", value).unwrap(),
+            Literal::Float(value) if value.is_nan() => self.out.push_str("(0.0 / 0.0)"),
+            Literal::Float(value) if value.is_infinite() => {
+                let sign = if *value < 0.0 { "-" } else { "" };
+                write!(self.out, "({}1.0 / 0.0)", sign).unwrap();
+            }
+            // Very large and small floats are written with exponents, and
+            // the rest with a `.`, so that they are never read as ints
+            Literal::Float(value) => write!(self.out, "{:?}", value).unwrap(),
+            Literal::Str(value) => {
+                self.out.push('"');
+                self.string_text(value);
+                self.out.push('"');
+            }
+            Literal::Char(value) => {
+                self.out.push('\'');
+                self.escaped(*value, '\'');
+                self.out.push('\'');
+            }
+            Literal::Bool(value) => write!(self.out, "{}", value).unwrap(),
+        }
+    }
+}
+
+/// Return how tightly `expr` binds as it is written, which is how tightly
+/// its loosest operator outside of brackets does.
+fn binding(expr: &Expr) -> Precedence {
+    match expr {
+        Expr::Assign { .. } => Precedence::Assignment,
+        Expr::Binary { op, .. } => precedence_of(*op),
+        Expr::Cast { .. } => Precedence::Cast,
+        Expr::Unary { .. } => Precedence::Unary,
+        Expr::Literal { value, .. } if starts_with_minus(expr) => match value {
+            Literal::Int(i64::MIN) => Precedence::Call,
+            _ => Precedence::Unary,
+        },
+        _ => Precedence::Call,
+    }
+}
+
+fn precedence_of(op: BinOp) -> Precedence {
+    match op {
+        BinOp::Range | BinOp::RangeInclusive => Precedence::Range,
+        BinOp::Or => Precedence::Or,
+        BinOp::And => Precedence::And,
+        BinOp::EqualEqual | BinOp::BangEqual => Precedence::Equality,
+        BinOp::Greater | BinOp::GreaterEqual | BinOp::Less | BinOp::LessEqual => {
+            Precedence::Comparison
+        }
+        BinOp::Plus | BinOp::Minus => Precedence::Term,
+        BinOp::Star | BinOp::Slash | BinOp::Percent => Precedence::Factor,
+    }
+}
+
+/// Returns true if `expr` is written starting with a `-`.
+fn starts_with_minus(expr: &Expr) -> bool {
+    match expr {
+        Expr::Unary {
+            op: UnaryOp::Minus, ..
+        } => true,
+        Expr::Literal {
+            value: Literal::Int(value),
+            ..
+        } => *value < 0 && *value != i64::MIN,
+        Expr::Literal {
+            value: Literal::Float(value),
+            ..
+        } => value.is_sign_negative() && value.is_finite(),
+        _ => false,
+    }
+}
+
+/// Returns true if writing `expr` where it needs to bind at least as
+/// tightly as `precedence`, after an expression, would continue that
+/// expression rather than start a new one, as `(a)` would call it.
+fn continues(expr: &Expr, precedence: Precedence) -> bool {
+    if binding(expr) < precedence {
+        return true;
+    }
+    match expr {
+        Expr::Binary { op, left, .. } => continues(left, precedence_of(*op)),
+        Expr::Cast { expr, .. } => continues(expr, Precedence::Cast),
+        Expr::Assign { target: first, .. }
+        | Expr::Call { callee: first, .. }
+        | Expr::Field { object: first, .. }
+        | Expr::Index { object: first, .. } => continues(first, Precedence::Call),
+        Expr::List { .. } => true,
+        Expr::Literal {
+            value: Literal::Int(i64::MIN),
+            ..
+        } => true,
+        Expr::Literal {
+            value: Literal::Float(value),
+            ..
+        } if !value.is_finite() => true,
+        _ => starts_with_minus(expr),
+    }
+}
@@ -0,0 +1,118 @@
+//! The API of the web playground, built with the `wasm` feature. Compiled
+//! to WebAssembly with `wasm-bindgen`, it exports a single function to
+//! JavaScript:
+//!
+//! ```js
+//! const { output, diagnostics } = evaluate('println("Hello!");');
+//! ```
+//!
+//! Each evaluation runs on a fresh VM, capturing what the program prints
+//! with a [`MemoryIo`]. Programs have a limited amount of
+//! [fuel](Vm::with_fuel), so one that never stops can't hang the page.
+
+use crate::{
+    check,
+    diagnostics::{Diagnostic, Level},
+    errors::InterpreterError,
+    run,
+    span::Span,
+    vm::{io::MemoryIo, Vm},
+};
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+/// The most instructions a program run by the playground can execute.
+pub const FUEL: u64 = 10_000_000;
+
+/// What evaluating a program produced.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Evaluation {
+    /// What the program printed before it finished or failed.
+    pub output: String,
+    /// The errors and warnings found in the program, including the error
+    /// it failed with, if it did.
+    pub diagnostics: Vec<PlaygroundDiagnostic>,
+}
+
+/// A [`Diagnostic`] in the form given to JavaScript.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PlaygroundDiagnostic {
+    /// Either `"error"` or `"warning"`.
+    pub level: String,
+    pub message: String,
+    pub span: Span,
+    pub notes: Vec<String>,
+    /// The diagnostic rendered with the line it points to, as the command
+    /// line interface shows it.
+    pub rendered: String,
+}
+
+impl PlaygroundDiagnostic {
+    fn new(diagnostic: &Diagnostic, source: &str) -> Self {
+        Self {
+            level: match diagnostic.level {
+                Level::Error => "error",
+                Level::Warning => "warning",
+            }
+            .to_string(),
+            message: diagnostic.message.clone(),
+            span: diagnostic.span,
+            notes: diagnostic.notes.clone(),
+            rendered: diagnostic.render(source, false),
+        }
+    }
+}
+
+/// Check and run `source`, unless checking it finds errors.
+///
+/// # Examples
+///
+/// ```
+/// use meow::playground::evaluate;
+///
+/// let evaluation = evaluate("println(\"meow\"); println(1 / 0);");
+/// assert_eq!(evaluation.output, "meow\n");
+/// assert_eq!(evaluation.diagnostics[0].message, "division by zero");
+/// assert_eq!(evaluation.diagnostics[0].span.column, 26);
+/// ```
+pub fn evaluate(source: &str) -> Evaluation {
+    let mut diagnostics: Vec<_> = check(source)
+        .iter()
+        .map(|diagnostic| PlaygroundDiagnostic::new(diagnostic, source))
+        .collect();
+    let io = MemoryIo::new();
+    if diagnostics
+        .iter()
+        .all(|diagnostic| diagnostic.level != "error")
+    {
+        let mut vm = Vm::with_fuel(FUEL);
+        // Files are kept in memory, and there is nothing for `read_line`
+        vm.set_io(Box::new(io.clone()));
+        match run(&mut vm, source) {
+            Ok(_) => {}
+            Err(InterpreterError::Failed {
+                diagnostics: failed,
+                ..
+            }) => diagnostics.extend(
+                failed
+                    .iter()
+                    .map(|diagnostic| PlaygroundDiagnostic::new(diagnostic, source)),
+            ),
+            Err(error) => diagnostics.push(PlaygroundDiagnostic::new(
+                &Diagnostic::error(error.to_string(), Span::default()),
+                source,
+            )),
+        }
+    }
+    Evaluation {
+        output: io.output(),
+        diagnostics,
+    }
+}
+
+/// [`evaluate`] `source`, giving JavaScript an object with `output` and
+/// `diagnostics` properties.
+#[wasm_bindgen(js_name = evaluate)]
+pub fn evaluate_js(source: &str) -> Result<JsValue, JsValue> {
+    Ok(serde_wasm_bindgen::to_value(&evaluate(source))?)
+}
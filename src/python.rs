@@ -0,0 +1,198 @@
+//! Python bindings, built with the `python` feature as an extension module
+//! called `meow`, for example with [maturin](https://www.maturin.rs):
+//!
+//! ```python
+//! import meow
+//!
+//! meow.eval("1 + 2")  # 3
+//!
+//! vm = meow.Vm()
+//! vm.set("cats", ["Tom", "Felix"])
+//! vm.eval('let greeting = "Hi, " + cats[0];')
+//! vm.get("greeting")  # 'Hi, Tom'
+//! ```
+//!
+//! Code is run as entries of an interactive session are, with
+//! [`run_entry`], so an entry that is a single expression gives back its
+//! value. Failed programs raise `meow.MeowError`, holding the rendered
+//! diagnostics.
+//!
+//! Values are converted between the two languages with [`to_python`] and
+//! [`from_python`]. Lists become Python lists, and instances become dicts
+//! of their fields, as objects parsed from JSON do. The other values, such
+//! as functions, stay in the VM.
+
+use crate::{
+    run_entry,
+    value::Value,
+    vm::{bigint::BigInt, heap::ObjRef, Vm},
+};
+use pyo3::{
+    create_exception,
+    exceptions::{PyException, PyKeyError, PyTypeError, PyValueError},
+    prelude::*,
+    types::{PyBool, PyBytes, PyDict, PyFloat, PyInt, PyList, PyString},
+};
+use std::collections::HashMap;
+
+create_exception!(meow, MeowError, PyException, "A Meow program failed.");
+
+/// A VM whose globals are kept between calls to `eval`.
+#[pyclass(name = "Vm", unsendable)]
+pub struct PyVm {
+    vm: Vm,
+}
+
+#[pymethods]
+impl PyVm {
+    #[new]
+    fn new() -> Self {
+        Self { vm: Vm::new() }
+    }
+
+    /// Run `source`, returning the value of an expression, or `None`.
+    fn eval(&mut self, py: Python<'_>, source: &str) -> PyResult<Py<PyAny>> {
+        match run_entry(&mut self.vm, source) {
+            Ok(Some(value)) => to_python(py, &self.vm, &value),
+            Ok(None) => Ok(py.None()),
+            Err(error) => Err(MeowError::new_err(error.render())),
+        }
+    }
+
+    /// Return the value of the global `name`.
+    fn get(&self, py: Python<'_>, name: &str) -> PyResult<Py<PyAny>> {
+        match self.vm.global(name) {
+            Some(value) => to_python(py, &self.vm, value),
+            None => Err(PyKeyError::new_err(name.to_string())),
+        }
+    }
+
+    /// Define the global `name` as `value`.
+    fn set(&mut self, name: &str, value: &Bound<'_, PyAny>) -> PyResult<()> {
+        let value = from_python(&mut self.vm, value)?;
+        self.vm.set_global(name, value);
+        Ok(())
+    }
+}
+
+/// Run `source` on a new VM, returning the value of an expression, or
+/// `None`.
+#[pyfunction]
+fn eval(py: Python<'_>, source: &str) -> PyResult<Py<PyAny>> {
+    PyVm::new().eval(py, source)
+}
+
+/// The `meow` module.
+#[pymodule]
+pub fn meow(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyVm>()?;
+    module.add_function(wrap_pyfunction!(eval, module)?)?;
+    module.add("MeowError", module.py().get_type::<MeowError>())?;
+    Ok(())
+}
+
+/// Convert `value`, whose lists and instances are on the heap of `vm`, to
+/// a Python object. Big ints become Python ints, and chars become strings.
+/// Fails for values Python has no equivalent of, and for lists and
+/// instances that contain themselves.
+pub fn to_python(py: Python<'_>, vm: &Vm, value: &Value) -> PyResult<Py<PyAny>> {
+    convert(py, vm, value, &mut Vec::new())
+}
+
+/// Convert `value` as [`to_python`] does. `open` holds the lists and
+/// instances being converted around it.
+fn convert(py: Python<'_>, vm: &Vm, value: &Value, open: &mut Vec<ObjRef>) -> PyResult<Py<PyAny>> {
+    let object = match value {
+        Value::Unit => py.None(),
+        Value::Bool(value) => PyBool::new(py, *value).to_owned().into_any().unbind(),
+        Value::Int(value) => value.into_pyobject(py)?.into_any().unbind(),
+        // Python ints are unbounded, and can be parsed from the digits
+        Value::BigInt(_) => py.get_type::<PyInt>().call1((value.to_string(),))?.unbind(),
+        Value::Float(value) => PyFloat::new(py, *value).into_any().unbind(),
+        Value::Char(value) => PyString::new(py, &value.to_string()).into_any().unbind(),
+        Value::Str(value) => PyString::new(py, value).into_any().unbind(),
+        Value::List(obj) | Value::Instance(obj) if open.contains(obj) => {
+            return Err(PyValueError::new_err(format!(
+                "cannot convert a {} that contains itself",
+                value.type_name()
+            )))
+        }
+        Value::List(obj) => {
+            open.push(*obj);
+            let list = PyList::empty(py);
+            for item in vm.heap().list(*obj) {
+                list.append(convert(py, vm, item, open)?)?;
+            }
+            open.pop();
+            list.into_any().unbind()
+        }
+        Value::Instance(obj) => {
+            open.push(*obj);
+            let dict = PyDict::new(py);
+            for (name, field) in &vm.heap().instance(*obj).fields {
+                dict.set_item(&**name, convert(py, vm, field, open)?)?;
+            }
+            open.pop();
+            dict.into_any().unbind()
+        }
+        value => {
+            return Err(PyTypeError::new_err(format!(
+                "cannot convert a {} to a Python object",
+                value.type_name()
+            )))
+        }
+    };
+    Ok(object)
+}
+
+/// Convert the Python object `object` to a value of `vm`, allocating its
+/// lists and instances on the VM's heap. Dicts must have string keys.
+pub fn from_python(vm: &mut Vm, object: &Bound<'_, PyAny>) -> PyResult<Value> {
+    // Bools are ints in Python, so they are checked first
+    if object.is_none() {
+        Ok(Value::Unit)
+    } else if let Ok(value) = object.cast::<PyBool>() {
+        Ok(Value::Bool(value.is_true()))
+    } else if let Ok(value) = object.cast::<PyInt>() {
+        match value.extract() {
+            Ok(value) => Ok(Value::Int(value)),
+            Err(_) => big_int(value),
+        }
+    } else if let Ok(value) = object.cast::<PyFloat>() {
+        Ok(Value::Float(value.value()))
+    } else if let Ok(value) = object.cast::<PyString>() {
+        Ok(Value::from(&*value.to_cow()?))
+    } else if let Ok(list) = object.cast::<PyList>() {
+        let items = list
+            .iter()
+            .map(|item| from_python(vm, &item))
+            .collect::<PyResult<Vec<_>>>()?;
+        Ok(vm.to_value(items))
+    } else if let Ok(dict) = object.cast::<PyDict>() {
+        let mut fields = HashMap::new();
+        for (key, value) in dict.iter() {
+            fields.insert(key.extract::<String>()?, from_python(vm, &value)?);
+        }
+        Ok(vm.to_value(fields))
+    } else {
+        Err(PyTypeError::new_err(format!(
+            "cannot convert a Python {} to a Meow value",
+            object.get_type().name()?
+        )))
+    }
+}
+
+/// Convert a Python int too big for an ordinary int, by reading its
+/// magnitude 32 bits at a time.
+fn big_int(value: &Bound<'_, PyInt>) -> PyResult<Value> {
+    let magnitude = value.abs()?;
+    let bits: usize = magnitude.call_method0("bit_length")?.extract()?;
+    let bytes = magnitude.call_method1("to_bytes", (bits.div_ceil(32) * 4, "little"))?;
+    let digits = bytes
+        .cast::<PyBytes>()?
+        .as_bytes()
+        .chunks(4)
+        .map(|digit| u32::from_le_bytes(digit.try_into().expect("digits are 4 bytes")))
+        .collect();
+    Ok(BigInt::from_digits(value.lt(0)?, digits).into_value())
+}
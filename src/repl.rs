@@ -0,0 +1,176 @@
+//! The REPL (Read–Eval–Print Loop) started when `meow` is run without a file
+//! or a string. Every entry is run on the same VM, so it can use the globals
+//! defined by the entries before it, and the value of an entry that is a
+//! single expression is printed.
+//!
+//! Lines can be edited, and entries are saved to a history file in the home
+//! directory. An entry continues onto the next line while it has brackets
+//! that haven't been closed. With color on, keywords and literals are
+//! highlighted as they are typed.
+//!
+//! When standard input isn't a terminal, such as when entries are piped in
+//! from a script, they are read without prompts, history or colors, until
+//! the input ends.
+
+use crate::{dump, print_error};
+use ansi_term::{Colour, Style};
+use meow::{lex, lexer::token::TokenKind, run_entry, value::Value, vm::Vm};
+use rustyline::{
+    error::ReadlineError,
+    highlight::{CmdKind, Highlighter},
+    validate::{ValidationContext, ValidationResult, Validator},
+    Completer, Editor, Helper, Hinter,
+};
+use std::{
+    borrow::Cow,
+    env,
+    io::{self, IsTerminal},
+    path::PathBuf,
+};
+
+/// The name of the history file in the home directory.
+const HISTORY_FILE: &str = ".meow_history";
+
+/// Line editing support for entries.
+#[derive(Helper, Completer, Hinter)]
+struct EntryHelper {
+    /// Whether entries are highlighted.
+    color: bool,
+}
+
+impl Highlighter for EntryHelper {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        match self.color {
+            true => Cow::Owned(highlight(line)),
+            false => Cow::Borrowed(line),
+        }
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize, kind: CmdKind) -> bool {
+        // Moving the cursor doesn't change the highlighting
+        self.color && kind != CmdKind::MoveCursor
+    }
+}
+
+impl Validator for EntryHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        Ok(match unclosed(ctx.input()) {
+            true => ValidationResult::Incomplete,
+            false => ValidationResult::Valid(None),
+        })
+    }
+}
+
+/// Returns true if `source` opens more brackets than it closes, so the entry
+/// continues on the next line. Brackets in strings don't count.
+fn unclosed(source: &str) -> bool {
+    let mut lexer = lex(source);
+    let mut depth = 0;
+    loop {
+        match lexer.next_token().kind {
+            TokenKind::OpenParen | TokenKind::OpenBracket | TokenKind::OpenBrace => depth += 1,
+            TokenKind::CloseParen | TokenKind::CloseBracket | TokenKind::CloseBrace => depth -= 1,
+            TokenKind::Eof => return depth > 0,
+            _ => {}
+        }
+    }
+}
+
+/// Return `source` with its tokens colored by the kind of token they are.
+fn highlight(source: &str) -> String {
+    let mut out = String::new();
+    let mut end = 0;
+    for (token, range) in dump::tokens(source) {
+        let style = match &token.kind {
+            kind if kind.is_keyword() => Colour::Purple.bold(),
+            TokenKind::Str(_)
+            | TokenKind::StrStart(_)
+            | TokenKind::StrMiddle(_)
+            | TokenKind::StrEnd(_)
+            | TokenKind::Char(_) => Colour::Green.normal(),
+            kind if kind.is_literal() => Colour::Cyan.normal(),
+            TokenKind::Error(_) => Colour::Red.normal(),
+            TokenKind::DocComment(_) => Style::new().dimmed(),
+            _ => Style::new(),
+        };
+        out.push_str(&source[end..range.start]);
+        out.push_str(&style.paint(&source[range.clone()]).to_string());
+        end = range.end;
+    }
+    out.push_str(&source[end..]);
+    out
+}
+
+/// Return the path of the history file, if there is a home directory.
+fn history_path() -> Option<PathBuf> {
+    let home = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"))?;
+    Some(PathBuf::from(home).join(HISTORY_FILE))
+}
+
+/// Read entries and run them on `vm` until the input ends. Errors and
+/// entries are only colored if `color` is true.
+pub fn run(vm: &mut Vm, color: bool) -> rustyline::Result<()> {
+    if !io::stdin().is_terminal() {
+        return Ok(run_piped(vm)?);
+    }
+
+    let mut editor = Editor::new()?;
+    editor.set_helper(Some(EntryHelper { color }));
+    let history = history_path();
+    if let Some(path) = &history {
+        // There is no history the first time the REPL is used
+        let _ = editor.load_history(path);
+    }
+
+    loop {
+        let entry = match editor.readline(">> ") {
+            Ok(entry) => entry,
+            // Ctrl-C abandons the entry being typed
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(error) => return Err(error),
+        };
+        if entry.trim().is_empty() {
+            continue;
+        }
+        editor.add_history_entry(entry.as_str())?;
+
+        evaluate(vm, &entry, color);
+    }
+
+    if let Some(path) = &history {
+        // Losing the history isn't worth failing over
+        let _ = editor.save_history(path);
+    }
+    Ok(())
+}
+
+/// Read entries from standard input and run them on `vm` until the input
+/// ends. Lines are read one at a time, so that programs reading standard
+/// input get the lines after the entry that reads them.
+fn run_piped(vm: &mut Vm) -> io::Result<()> {
+    let mut entry = String::new();
+    loop {
+        let mut line = String::new();
+        let end = io::stdin().read_line(&mut line)? == 0;
+        entry.push_str(&line);
+        if end || !unclosed(&entry) {
+            if !entry.trim().is_empty() {
+                evaluate(vm, &entry, false);
+            }
+            entry.clear();
+        }
+        if end {
+            return Ok(());
+        }
+    }
+}
+
+/// Run `entry` on `vm`, printing its value, or the error it failed with.
+fn evaluate(vm: &mut Vm, entry: &str, color: bool) {
+    match run_entry(vm, entry) {
+        Ok(Some(Value::Unit)) | Ok(None) => {}
+        Ok(Some(value)) => println!("{}", vm.heap().display(&value)),
+        Err(error) => print_error(error, color),
+    }
+}
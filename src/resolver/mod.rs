@@ -0,0 +1,552 @@
+//! Resolution runs after parsing, and works out which declaration each name
+//! in the program refers to. The result is a [`SymbolTable`] that records
+//! every declaration, the scopes they live in, and every reference to them,
+//! which is what editor tooling needs to answer questions such as "where is
+//! this defined?" and "who uses this?".
+//!
+//! Top-level declarations are visible throughout the whole program, so
+//! functions may call each other regardless of the order they are declared
+//! in. Everything else is only visible after it is declared, and `let` may
+//! shadow an earlier binding of the same name. Names that don't resolve to
+//! any declaration are recorded as unresolved references rather than errors,
+//! since they may refer to builtins provided at runtime.
+
+use crate::{
+    diagnostics::Diagnostic,
+    parser::ast::{Block, Expr, FunDecl, Pattern, Stmt, StrPart},
+    span::Span,
+};
+use std::collections::HashMap;
+use tracing::{debug, trace};
+
+/// An index into [`SymbolTable::symbols`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SymbolId(pub usize);
+
+/// An index into [`SymbolTable::scopes`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ScopeId(pub usize);
+
+/// What kind of declaration introduced a [`Symbol`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Variable { mutable: bool },
+    Parameter,
+    Function,
+    Class,
+    Method,
+    Module,
+}
+
+/// What kind of syntax introduced a [`Scope`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScopeKind {
+    Global,
+    Function,
+    Class,
+    Block,
+}
+
+/// A single declaration in the program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// The span of the declared name. The implicit `self` of a method has
+    /// the span of the method, but isn't found at it, which is the method's.
+    pub span: Span,
+    pub scope: ScopeId,
+    /// Whether the declaration is marked `pub`, so that the modules
+    /// importing this one can use it.
+    pub public: bool,
+    /// The spans of every use of this symbol, in source order.
+    pub references: Vec<Span>,
+}
+
+/// A region of the program in which names can be declared.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scope {
+    pub kind: ScopeKind,
+    pub parent: Option<ScopeId>,
+    pub span: Span,
+    pub symbols: Vec<SymbolId>,
+}
+
+/// A use of a name, and the symbol it resolved to, if any.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    pub name: String,
+    pub span: Span,
+    pub symbol: Option<SymbolId>,
+}
+
+/// The output of the resolver. Symbols, scopes, and references are stored in
+/// the order they were encountered.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymbolTable {
+    symbols: Vec<Symbol>,
+    scopes: Vec<Scope>,
+    references: Vec<Reference>,
+    resolutions: HashMap<Span, SymbolId>,
+    /// The implicit `self` parameter of each method, by the method's span.
+    receivers: HashMap<Span, SymbolId>,
+}
+
+impl SymbolTable {
+    /// Every symbol declared in the program.
+    pub fn symbols(&self) -> &[Symbol] {
+        &self.symbols
+    }
+
+    /// Every scope in the program. The first one is always the global scope.
+    pub fn scopes(&self) -> &[Scope] {
+        &self.scopes
+    }
+
+    /// Every use of a name in the program, whether resolved or not.
+    pub fn references(&self) -> &[Reference] {
+        &self.references
+    }
+
+    pub fn symbol(&self, id: SymbolId) -> &Symbol {
+        &self.symbols[id.0]
+    }
+
+    pub fn scope(&self, id: ScopeId) -> &Scope {
+        &self.scopes[id.0]
+    }
+
+    /// Return the symbol referred to by the name at `span`. Declarations
+    /// resolve to themselves.
+    pub fn resolution(&self, span: Span) -> Option<SymbolId> {
+        self.resolutions.get(&span).copied()
+    }
+
+    /// Return the implicit `self` parameter of the method declared at
+    /// `span`.
+    pub fn receiver(&self, span: Span) -> Option<SymbolId> {
+        self.receivers.get(&span).copied()
+    }
+
+    /// Return the symbol declared or used at `line` and `column`, which is
+    /// what the name under an editor's cursor refers to.
+    pub fn symbol_at(&self, line: u32, column: u32) -> Option<SymbolId> {
+        self.resolutions
+            .iter()
+            .find(|(span, _)| span.contains(line, column))
+            .map(|(_, &id)| id)
+    }
+
+    /// Return the use of a name at `line` and `column`, whether it resolved
+    /// or not.
+    pub fn reference_at(&self, line: u32, column: u32) -> Option<&Reference> {
+        self.references
+            .iter()
+            .find(|reference| reference.span.contains(line, column))
+    }
+
+    /// Return the references that didn't resolve to any declaration.
+    pub fn unresolved(&self) -> impl Iterator<Item = &Reference> {
+        self.references.iter().filter(|r| r.symbol.is_none())
+    }
+
+    /// Find the symbols named `name` declared directly in `scope`.
+    pub fn lookup(&self, scope: ScopeId, name: &str) -> impl Iterator<Item = SymbolId> + '_ {
+        let name = name.to_string();
+        self.scope(scope)
+            .symbols
+            .iter()
+            .copied()
+            .filter(move |&id| self.symbol(id).name == name)
+    }
+}
+
+/// The kind of function currently being resolved, used to validate `return`
+/// and `self`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FunctionKind {
+    None,
+    Function,
+    Method,
+    Initializer,
+}
+
+/// The `Resolver` walks the AST and builds up a [`SymbolTable`]. It should
+/// typically be used through the top-level `resolve()` function.
+pub struct Resolver {
+    table: SymbolTable,
+    /// The scopes currently open, innermost last, with the names visible in
+    /// each one.
+    stack: Vec<(ScopeId, HashMap<String, SymbolId>)>,
+    function: FunctionKind,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        let mut resolver = Self {
+            table: SymbolTable::default(),
+            stack: Vec::new(),
+            function: FunctionKind::None,
+            diagnostics: Vec::new(),
+        };
+        resolver.begin_scope(ScopeKind::Global, Span::default());
+        resolver
+    }
+
+    /// Resolve a whole program, returning the symbol table and any errors
+    /// found along the way.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use meow::{parse, resolver::Resolver};
+    ///
+    /// let program = parse("let x = 1; x + 1;").unwrap();
+    /// let (table, diagnostics) = Resolver::new().resolve(&program);
+    /// assert!(diagnostics.is_empty());
+    /// assert_eq!(table.symbols()[0].references.len(), 1);
+    /// ```
+    pub fn resolve(mut self, program: &[Stmt]) -> (SymbolTable, Vec<Diagnostic>) {
+        // Top-level declarations are hoisted so they can be used anywhere
+        for stmt in program {
+            self.hoist(stmt);
+        }
+        for stmt in program {
+            self.stmt(stmt, true);
+        }
+
+        debug!(
+            symbols = self.table.symbols.len(),
+            unresolved = self.table.unresolved().count(),
+            errors = self.diagnostics.len(),
+            "resolved"
+        );
+        (self.table, self.diagnostics)
+    }
+
+    fn begin_scope(&mut self, kind: ScopeKind, span: Span) {
+        let id = ScopeId(self.table.scopes.len());
+        self.table.scopes.push(Scope {
+            kind,
+            parent: self.stack.last().map(|(id, _)| *id),
+            span,
+            symbols: Vec::new(),
+        });
+        self.stack.push((id, HashMap::new()));
+    }
+
+    fn end_scope(&mut self) {
+        self.stack.pop();
+    }
+
+    /// Declare `name` in the innermost scope, shadowing any earlier symbol of
+    /// the same name.
+    fn declare(&mut self, name: &str, kind: SymbolKind, span: Span) -> SymbolId {
+        let id = self.add_symbol(name, kind, span);
+        self.table.resolutions.insert(span, id);
+        id
+    }
+
+    /// Add a symbol to the innermost scope, without it being found at
+    /// `span`, where the name isn't written.
+    fn add_symbol(&mut self, name: &str, kind: SymbolKind, span: Span) -> SymbolId {
+        let id = SymbolId(self.table.symbols.len());
+        let (scope, names) = self.stack.last_mut().expect("resolver has no open scope");
+        trace!(name, ?kind, %span, "declared");
+
+        self.table.symbols.push(Symbol {
+            name: name.to_string(),
+            kind,
+            span,
+            scope: *scope,
+            public: false,
+            references: Vec::new(),
+        });
+        self.table.scopes[scope.0].symbols.push(id);
+        names.insert(name.to_string(), id);
+        id
+    }
+
+    /// Record a use of `name` at `span`, returning the symbol it refers to.
+    fn reference(&mut self, name: &str, span: Span) -> Option<SymbolId> {
+        let symbol = self
+            .stack
+            .iter()
+            .rev()
+            .find_map(|(_, names)| names.get(name).copied());
+
+        if let Some(id) = symbol {
+            self.table.symbols[id.0].references.push(span);
+            self.table.resolutions.insert(span, id);
+        }
+        self.table.references.push(Reference {
+            name: name.to_string(),
+            span,
+            symbol,
+        });
+        symbol
+    }
+
+    /// Declare a top-level statement before the program is resolved.
+    fn hoist(&mut self, stmt: &Stmt) {
+        let id = match stmt {
+            Stmt::Let {
+                name,
+                mutable,
+                span,
+                ..
+            } => self.declare(name, SymbolKind::Variable { mutable: *mutable }, *span),
+            Stmt::Fun(fun) => self.declare(&fun.name, SymbolKind::Function, fun.span),
+            Stmt::Class { name, span, .. } => self.declare(name, SymbolKind::Class, *span),
+            Stmt::Import { path, span, .. } => {
+                let name = path.last().expect("import without a path");
+                self.declare(name, SymbolKind::Module, *span)
+            }
+            _ => return,
+        };
+        self.table.symbols[id.0].public = stmt.is_public();
+    }
+
+    /// Resolve a statement. Statements at the top level have already been
+    /// declared by [`Resolver::hoist`].
+    fn stmt(&mut self, stmt: &Stmt, hoisted: bool) {
+        // Only what a module declares at its top level can be used by others
+        if !hoisted && stmt.is_public() {
+            self.diagnostics.push(Diagnostic::error(
+                "`pub` can only be used at the top level of a module",
+                stmt.span(),
+            ));
+        }
+
+        match stmt {
+            Stmt::Let {
+                name,
+                mutable,
+                value,
+                span,
+                ..
+            } => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+                if !hoisted {
+                    self.declare(name, SymbolKind::Variable { mutable: *mutable }, *span);
+                }
+            }
+            Stmt::Expr { expr, .. } => self.expr(expr),
+            Stmt::Fun(fun) => {
+                if !hoisted {
+                    self.declare(&fun.name, SymbolKind::Function, fun.span);
+                }
+                self.function(fun, FunctionKind::Function);
+            }
+            Stmt::Class {
+                name,
+                methods,
+                span,
+                ..
+            } => {
+                if !hoisted {
+                    self.declare(name, SymbolKind::Class, *span);
+                }
+
+                self.begin_scope(ScopeKind::Class, *span);
+                for method in methods {
+                    self.declare(&method.name, SymbolKind::Method, method.span);
+                }
+                for method in methods {
+                    let kind = if method.name == "init" {
+                        FunctionKind::Initializer
+                    } else {
+                        FunctionKind::Method
+                    };
+                    self.function(method, kind);
+                }
+                self.end_scope();
+            }
+            Stmt::Return { value, span } => {
+                if self.function == FunctionKind::None {
+                    self.diagnostics
+                        .push(Diagnostic::error("`return` outside of a function", *span));
+                }
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+            }
+            Stmt::Yield { value, span } => {
+                match self.function {
+                    FunctionKind::None => self
+                        .diagnostics
+                        .push(Diagnostic::error("`yield` outside of a function", *span)),
+                    FunctionKind::Initializer => self
+                        .diagnostics
+                        .push(Diagnostic::error("`yield` in an initializer", *span)),
+                    FunctionKind::Function | FunctionKind::Method => {}
+                }
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+            }
+            Stmt::Spawn { callee, args, .. } => {
+                self.expr(callee);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            Stmt::While { cond, body, .. } => {
+                self.expr(cond);
+                self.block(body);
+            }
+            Stmt::For {
+                var,
+                iterable,
+                body,
+                span,
+            } => {
+                self.expr(iterable);
+                self.begin_scope(ScopeKind::Block, *span);
+                self.declare(&var.name, SymbolKind::Variable { mutable: false }, var.span);
+                self.block(body);
+                self.end_scope();
+            }
+            Stmt::Import { path, span, .. } => {
+                if !hoisted {
+                    let name = path.last().expect("import without a path");
+                    self.declare(name, SymbolKind::Module, *span);
+                }
+            }
+        }
+    }
+
+    fn function(&mut self, fun: &FunDecl, kind: FunctionKind) {
+        let enclosing = self.function;
+        self.function = kind;
+
+        self.begin_scope(ScopeKind::Function, fun.span);
+        if matches!(kind, FunctionKind::Method | FunctionKind::Initializer) {
+            // The method's name already resolves to the method
+            let id = self.add_symbol("self", SymbolKind::Parameter, fun.span);
+            self.table.receivers.insert(fun.span, id);
+        }
+        for param in &fun.params {
+            self.declare(&param.name, SymbolKind::Parameter, param.span);
+        }
+        self.block(&fun.body);
+        self.end_scope();
+
+        self.function = enclosing;
+    }
+
+    fn block(&mut self, block: &Block) {
+        self.begin_scope(ScopeKind::Block, block.span);
+        for stmt in &block.stmts {
+            self.stmt(stmt, false);
+        }
+        if let Some(tail) = &block.tail {
+            self.expr(tail);
+        }
+        self.end_scope();
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Literal { .. } => {}
+            Expr::Ident { name, span } => {
+                if self.reference(name, *span).is_none() && name == "self" {
+                    self.diagnostics
+                        .push(Diagnostic::error("`self` outside of a method", *span));
+                }
+            }
+            Expr::Unary { expr, .. } | Expr::Cast { expr, .. } => self.expr(expr),
+            Expr::Binary { left, right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::Assign { target, value, .. } => {
+                self.expr(value);
+                if let Expr::Ident { name, span } = &**target {
+                    if let Some(id) = self.reference(name, *span) {
+                        self.check_assignable(id, *span);
+                    }
+                } else {
+                    self.expr(target);
+                }
+            }
+            Expr::Call { callee, args, .. } => {
+                self.expr(callee);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            Expr::Interpolation { parts, .. } => {
+                for expr in StrPart::exprs(parts) {
+                    self.expr(expr);
+                }
+            }
+            Expr::Field { object, .. } => self.expr(object),
+            Expr::Index { object, index, .. } => {
+                self.expr(object);
+                self.expr(index);
+            }
+            Expr::List { items, .. } => {
+                for item in items {
+                    self.expr(item);
+                }
+            }
+            Expr::Block(block) => self.block(block),
+            Expr::If {
+                cond,
+                then,
+                otherwise,
+                ..
+            } => {
+                self.expr(cond);
+                self.block(then);
+                if let Some(otherwise) = otherwise {
+                    self.expr(otherwise);
+                }
+            }
+            Expr::Match {
+                scrutinee, arms, ..
+            } => {
+                self.expr(scrutinee);
+                for arm in arms {
+                    self.begin_scope(ScopeKind::Block, arm.span);
+                    if let Pattern::Binding { name, span } = &arm.pattern {
+                        self.declare(name, SymbolKind::Variable { mutable: false }, *span);
+                    }
+                    if let Some(guard) = &arm.guard {
+                        self.expr(guard);
+                    }
+                    self.expr(&arm.body);
+                    self.end_scope();
+                }
+            }
+        }
+    }
+
+    /// Report an error if the symbol `id` can't be assigned to.
+    fn check_assignable(&mut self, id: SymbolId, span: Span) {
+        let symbol = self.table.symbol(id);
+        let message = match symbol.kind {
+            SymbolKind::Variable { mutable: false } => format!(
+                "cannot assign to immutable variable `{}`, consider declaring it with `let mut`",
+                symbol.name
+            ),
+            SymbolKind::Function | SymbolKind::Method => {
+                format!("cannot assign to function `{}`", symbol.name)
+            }
+            SymbolKind::Class => format!("cannot assign to class `{}`", symbol.name),
+            SymbolKind::Module => format!("cannot assign to module `{}`", symbol.name),
+            SymbolKind::Variable { mutable: true } | SymbolKind::Parameter => return,
+        };
+        self.diagnostics.push(Diagnostic::error(message, span));
+    }
+}
@@ -0,0 +1,158 @@
+//! The text of the source files the [`loader`](crate::loader) reads. With
+//! the `mmap` feature, large files are memory-mapped on Unix, so that they
+//! are lexed where the operating system keeps them rather than copied into
+//! memory first. Their UTF-8 is checked where it is, without a copy. Files
+//! that can't be mapped, such as pipes, are read as they are without it.
+//!
+//! A mapped file shouldn't be changed while a program loaded from it runs,
+//! as its source would change with it.
+
+use std::{fmt, fs, io, ops::Deref, path::Path, sync::Arc};
+
+/// The text of a source file, which is cheap to clone.
+#[derive(Clone)]
+pub struct Source(Arc<Text>);
+
+enum Text {
+    Read(String),
+    #[cfg(all(feature = "mmap", unix))]
+    Mapped(mapped::Mapping),
+}
+
+impl Source {
+    /// Read the file at `path`, which must be valid UTF-8, memory-mapping
+    /// it if it is large enough to be worth it.
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Source> {
+        let path = path.as_ref();
+        #[cfg(all(feature = "mmap", unix))]
+        if let Some(mapping) = mapped::Mapping::new(path)? {
+            return Ok(Source(Arc::new(Text::Mapped(mapping))));
+        }
+        fs::read_to_string(path).map(Source::from)
+    }
+
+    /// Returns true if the source is memory-mapped rather than read.
+    pub fn is_mapped(&self) -> bool {
+        !matches!(*self.0, Text::Read(_))
+    }
+}
+
+impl Deref for Source {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        match &*self.0 {
+            Text::Read(text) => text,
+            #[cfg(all(feature = "mmap", unix))]
+            Text::Mapped(mapping) => mapping.as_str(),
+        }
+    }
+}
+
+impl From<String> for Source {
+    fn from(text: String) -> Self {
+        Source(Arc::new(Text::Read(text)))
+    }
+}
+
+impl From<&str> for Source {
+    fn from(text: &str) -> Self {
+        Source::from(text.to_string())
+    }
+}
+
+impl PartialEq for Source {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl fmt::Debug for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self)
+    }
+}
+
+#[cfg(all(feature = "mmap", unix))]
+mod mapped {
+    use std::{fs::File, io, os::unix::io::AsRawFd, path::Path, ptr, slice, str};
+
+    /// Files smaller than this are read instead, as mapping them costs more
+    /// than copying them.
+    const MIN_LEN: u64 = 64 * 1024;
+
+    /// A file mapped into memory for reading, which is valid UTF-8.
+    pub(super) struct Mapping {
+        ptr: *mut libc::c_void,
+        len: usize,
+    }
+
+    // The mapping is never written to, and is only unmapped when dropped
+    unsafe impl Send for Mapping {}
+    unsafe impl Sync for Mapping {}
+
+    impl Mapping {
+        /// Map the file at `path`, or return `None` if it is too small or
+        /// isn't a file that can be mapped, so that it is read instead.
+        pub(super) fn new(path: &Path) -> io::Result<Option<Mapping>> {
+            let file = File::open(path)?;
+            let metadata = file.metadata()?;
+            if !metadata.is_file() || metadata.len() < MIN_LEN {
+                return Ok(None);
+            }
+            let Ok(len) = usize::try_from(metadata.len()) else {
+                return Ok(None);
+            };
+            // Safety: the file is open for reading, and the mapping is only
+            // ever read
+            let ptr = unsafe {
+                libc::mmap(
+                    ptr::null_mut(),
+                    len,
+                    libc::PROT_READ,
+                    libc::MAP_PRIVATE,
+                    file.as_raw_fd(),
+                    0,
+                )
+            };
+            if ptr == libc::MAP_FAILED {
+                return Ok(None);
+            }
+            let mapping = Mapping { ptr, len };
+            if str::from_utf8(mapping.bytes()).is_err() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "stream did not contain valid UTF-8",
+                ));
+            }
+            Ok(Some(mapping))
+        }
+
+        fn bytes(&self) -> &[u8] {
+            // Safety: the mapping is `len` bytes long, and stays mapped for
+            // as long as `self` lives
+            unsafe { slice::from_raw_parts(self.ptr as *const u8, self.len) }
+        }
+
+        pub(super) fn as_str(&self) -> &str {
+            // Safety: the bytes were checked to be UTF-8 when mapped
+            unsafe { str::from_utf8_unchecked(self.bytes()) }
+        }
+    }
+
+    impl Drop for Mapping {
+        fn drop(&mut self) {
+            // Safety: `ptr` and `len` are those of a mapping that nothing
+            // borrows anymore
+            unsafe {
+                libc::munmap(self.ptr, self.len);
+            }
+        }
+    }
+}
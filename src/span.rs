@@ -0,0 +1,51 @@
+//! Spans locate a piece of syntax within the original source. Every token,
+//! AST node, and diagnostic carries one so that errors can point back at the
+//! code that caused them.
+
+use serde::Serialize;
+use std::fmt;
+
+/// A `Span` stores the line and column on which a piece of syntax starts, as
+/// well as its length in characters. Spans never cross lines when rendered,
+/// so the length is only used to draw carets underneath the start line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize)]
+pub struct Span {
+    pub line: u32,
+    pub column: u32,
+    pub length: u32,
+}
+
+impl Span {
+    /// Create a new span. This is mostly used by the lexer, with the parser
+    /// combining existing spans through [`Span::to`].
+    pub fn new(line: u32, column: u32, length: u32) -> Self {
+        Self {
+            line,
+            column,
+            length,
+        }
+    }
+
+    /// Create a span covering both `self` and `other`. If `other` ends on a
+    /// different line, the result is cut off at the start of that line, since
+    /// spans are only ever rendered on a single line.
+    pub fn to(self, other: Span) -> Span {
+        if other.line == self.line && other.column >= self.column {
+            let end = (other.column + other.length).max(self.column + self.length);
+            Span::new(self.line, self.column, end - self.column)
+        } else {
+            self
+        }
+    }
+
+    /// Returns true if the given line and column fall within the span.
+    pub fn contains(&self, line: u32, column: u32) -> bool {
+        line == self.line && column >= self.column && column < self.column + self.length.max(1)
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
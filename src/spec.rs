@@ -0,0 +1,277 @@
+//! Conformance tests for the language. A fixture is a Meow program
+//! annotated with what the implementation should make of it: the tokens it
+//! lexes into, the diagnostics it gets, or what it prints when it runs.
+//! Fixtures are run by `cargo test` over `tests/spec`, and by `meow --spec
+//! DIR` over any directory.
+//!
+//! Annotations are written after `//~` and `//@`, which the language has no
+//! use for, and blanked out before the program is lexed, leaving every other
+//! token where it was.
+//!
+//! - `//~ ERROR message` expects an error on the annotation's line whose
+//!   message contains `message`, and `//~ WARNING message` a warning. Each
+//!   `^` after the `~` moves the expected line up one, so `//~^ ERROR`
+//!   points at the line above. Every diagnostic a fixture gets must be
+//!   expected, so a fixture without any expects none.
+//! - `//@ output: text` expects the program to print `text` and a newline.
+//!   Several are printed in order. Without any, the output isn't checked.
+//! - `//@ tokens: Let Ident Equal` expects the program to lex into tokens
+//!   of those kinds, named as in [`TokenKind`] without their contents.
+//!   Several are lexed in order.
+//!
+//! Programs without errors from [`check`] are run, and runtime errors are
+//! expected like any other.
+//!
+//! # Examples
+//!
+//! ```
+//! use meow::spec::run_fixture;
+//!
+//! let fixture = "
+//! let x = 1;
+//! x = 2; //~ ERROR cannot assign to immutable variable `x`
+//! ";
+//! assert!(run_fixture(fixture).is_empty());
+//!
+//! let fixture = "println(1 + 2); //@ output: 4";
+//! assert_eq!(run_fixture(fixture), ["expected the output \"4\\n\", got \"3\\n\""]);
+//! ```
+
+use crate::{
+    check,
+    diagnostics::{Diagnostic, Level},
+    errors::InterpreterError,
+    lex,
+    lexer::token::TokenKind,
+    run,
+    vm::{io::MemoryIo, Vm},
+};
+#[cfg(feature = "fs")]
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// The instructions a fixture can run before it is stopped, so that one
+/// that doesn't finish fails instead of hanging the suite.
+const FUEL: u64 = 10_000_000;
+
+/// A diagnostic a fixture expects.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpectedDiagnostic {
+    pub level: Level,
+    pub line: u32,
+    /// Text the diagnostic's message contains.
+    pub message: String,
+}
+
+/// What a fixture expects, read from its annotations.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Expectations {
+    /// The kinds of tokens the program lexes into, if they are checked.
+    pub tokens: Option<Vec<String>>,
+    pub diagnostics: Vec<ExpectedDiagnostic>,
+    /// What the program prints, if it is checked.
+    pub output: Option<String>,
+}
+
+impl Expectations {
+    /// Read the annotations in `fixture`, returning what it expects along
+    /// with its program, which is the fixture with the annotations blanked
+    /// out. Fails with a description of the first annotation that can't
+    /// be read.
+    pub fn parse(fixture: &str) -> Result<(Self, String), String> {
+        let mut expectations = Expectations::default();
+        let mut program = String::with_capacity(fixture.len());
+
+        for (index, line) in fixture.split_inclusive('\n').enumerate() {
+            let number = index as u32 + 1;
+            let start = match (line.find("//~"), line.find("//@")) {
+                (Some(a), Some(b)) => a.min(b),
+                (Some(start), None) | (None, Some(start)) => start,
+                (None, None) => {
+                    program.push_str(line);
+                    continue;
+                }
+            };
+            let (code, annotation) = line.split_at(start);
+            program.push_str(code);
+            program.extend(annotation.chars().map(|c| match c {
+                '\n' | '\r' => c,
+                _ => ' ',
+            }));
+
+            let annotation = annotation.trim_end();
+            let fail = |message: &str| Err(format!("line {}: {}", number, message));
+            if let Some(rest) = annotation.strip_prefix("//~") {
+                let up = rest.len() - rest.trim_start_matches('^').len();
+                let Some(line) = number.checked_sub(up as u32).filter(|&line| line > 0) else {
+                    return fail("the annotation points above the first line");
+                };
+                let rest = rest[up..].trim_start();
+                let (level, message) = if let Some(message) = rest.strip_prefix("ERROR") {
+                    (Level::Error, message)
+                } else if let Some(message) = rest.strip_prefix("WARNING") {
+                    (Level::Warning, message)
+                } else {
+                    return fail("expected `ERROR` or `WARNING` after `//~`");
+                };
+                expectations.diagnostics.push(ExpectedDiagnostic {
+                    level,
+                    line,
+                    message: message.trim().to_string(),
+                });
+            } else if let Some(rest) = annotation.strip_prefix("//@") {
+                match rest.trim_start().split_once(':') {
+                    Some(("output", text)) => {
+                        let output = expectations.output.get_or_insert_with(String::new);
+                        output.push_str(text.strip_prefix(' ').unwrap_or(text));
+                        output.push('\n');
+                    }
+                    Some(("tokens", kinds)) => expectations
+                        .tokens
+                        .get_or_insert_with(Vec::new)
+                        .extend(kinds.split_whitespace().map(str::to_string)),
+                    _ => return fail("expected `output:` or `tokens:` after `//@`"),
+                }
+            }
+        }
+        Ok((expectations, program))
+    }
+}
+
+/// Return the name of `kind`, without the data it holds.
+fn kind_name(kind: &TokenKind) -> String {
+    let name = format!("{:?}", kind);
+    match name.find('(') {
+        Some(end) => name[..end].to_string(),
+        None => name,
+    }
+}
+
+/// Run `fixture`, returning every way it differs from what its annotations
+/// expect, or nothing if it passes.
+pub fn run_fixture(fixture: &str) -> Vec<String> {
+    let (expectations, program) = match Expectations::parse(fixture) {
+        Ok(parsed) => parsed,
+        Err(error) => return vec![error],
+    };
+    let mut failures = Vec::new();
+
+    if let Some(expected) = &expectations.tokens {
+        let mut lexer = lex(&program);
+        let mut tokens = Vec::new();
+        loop {
+            let kind = lexer.next_token().kind;
+            if kind == TokenKind::Eof {
+                break;
+            }
+            tokens.push(kind_name(&kind));
+        }
+        if &tokens != expected {
+            failures.push(format!(
+                "expected the tokens `{}`, got `{}`",
+                expected.join(" "),
+                tokens.join(" ")
+            ));
+        }
+    }
+
+    let mut diagnostics = check(&program);
+    let mut output = None;
+    if !diagnostics.iter().any(Diagnostic::is_error) {
+        let io = MemoryIo::new();
+        let mut vm = Vm::with_fuel(FUEL);
+        vm.set_io(Box::new(io.clone()));
+        match run(&mut vm, &program) {
+            Ok(_) => {}
+            Err(InterpreterError::Failed {
+                diagnostics: errors,
+                ..
+            }) => diagnostics.extend(errors),
+            Err(error) => failures.push(format!("the program couldn't run: {}", error)),
+        }
+        output = Some(io.output());
+    }
+
+    let mut expected: Vec<_> = expectations.diagnostics.iter().map(Some).collect();
+    for diagnostic in &diagnostics {
+        let found = expected.iter_mut().find(|expected| {
+            expected.is_some_and(|expected| {
+                expected.level == diagnostic.level
+                    && expected.line == diagnostic.span.line
+                    && diagnostic.message.contains(&expected.message)
+            })
+        });
+        match found {
+            Some(found) => *found = None,
+            None => failures.push(format!(
+                "unexpected {} at {}: {}",
+                diagnostic.level, diagnostic.span, diagnostic.message
+            )),
+        }
+    }
+    for missing in expected.into_iter().flatten() {
+        failures.push(format!(
+            "expected {} on line {}: {}",
+            missing.level, missing.line, missing.message
+        ));
+    }
+
+    if let Some(expected) = &expectations.output {
+        match &output {
+            Some(output) if output == expected => {}
+            Some(output) => failures.push(format!(
+                "expected the output {:?}, got {:?}",
+                expected, output
+            )),
+            None => failures.push("expected output, but the program didn't run".to_string()),
+        }
+    }
+    failures
+}
+
+/// The fixtures of a suite run by [`run_suite`].
+#[cfg(feature = "fs")]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SuiteReport {
+    /// How many fixtures passed.
+    pub passed: usize,
+    /// The fixtures that failed, each with the ways it did.
+    pub failures: Vec<(PathBuf, Vec<String>)>,
+}
+
+/// Run every `.mw` fixture in `dir` and the directories inside it, in
+/// order of their paths.
+#[cfg(feature = "fs")]
+pub fn run_suite(dir: &Path) -> io::Result<SuiteReport> {
+    let mut paths = Vec::new();
+    collect_fixtures(dir, &mut paths)?;
+    paths.sort();
+
+    let mut report = SuiteReport::default();
+    for path in paths {
+        let failures = run_fixture(&fs::read_to_string(&path)?);
+        if failures.is_empty() {
+            report.passed += 1;
+        } else {
+            report.failures.push((path, failures));
+        }
+    }
+    Ok(report)
+}
+
+/// Add the paths of the fixtures in `dir` and the directories inside it to
+/// `paths`.
+#[cfg(feature = "fs")]
+fn collect_fixtures(dir: &Path, paths: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_fixtures(&path, paths)?;
+        } else if path.extension().is_some_and(|extension| extension == "mw") {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
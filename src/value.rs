@@ -0,0 +1,204 @@
+//! Runtime values. These are what Meow programs compute with, and what the
+//! constant table of a [`Chunk`](crate::bytecode::Chunk) stores.
+
+use crate::{
+    bytecode::Function,
+    vm::{
+        bigint::BigInt,
+        heap::{Class, ObjRef},
+        host::HostFunction,
+        native::{Module, Native},
+    },
+};
+use serde::Serialize;
+use std::{fmt, rc::Rc};
+
+/// A single Meow value. Values are cheap to clone, since strings are
+/// reference counted and mutable objects are handles to the VM's
+/// [`Heap`](crate::vm::heap::Heap).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// The value of expressions that don't produce anything, such as a block
+    /// without a trailing expression.
+    Unit,
+    Bool(bool),
+    Int(i64),
+    /// An int outside the range of `i64`, which only exists when
+    /// [`Vm::set_big_ints`](crate::vm::Vm::set_big_ints) is on.
+    BigInt(Rc<BigInt>),
+    Float(f64),
+    Char(char),
+    Str(Rc<str>),
+    Function(Rc<Function>),
+    Class(Rc<Class>),
+    List(ObjRef),
+    Instance(ObjRef),
+    BoundMethod(ObjRef),
+    /// A function built into the VM.
+    Native(Native),
+    /// A function registered by the host with
+    /// [`Vm::register_fn`](crate::vm::Vm::register_fn).
+    Host(Rc<HostFunction>),
+    Channel(ObjRef),
+    Generator(ObjRef),
+    /// A module built into the VM, loaded with `import`.
+    Module(Module),
+    Range(Range),
+}
+
+impl Value {
+    /// Return the name of the value's type, as used in error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Unit => "unit",
+            Value::Bool(_) => "bool",
+            Value::Int(_) | Value::BigInt(_) => "int",
+            Value::Float(_) => "float",
+            Value::Char(_) => "char",
+            Value::Str(_) => "string",
+            Value::Function(_) => "function",
+            Value::Class(_) => "class",
+            Value::List(_) => "list",
+            Value::Instance(_) => "instance",
+            Value::BoundMethod(_) => "method",
+            Value::Native(_) | Value::Host(_) => "function",
+            Value::Channel(_) => "channel",
+            Value::Generator(_) => "generator",
+            Value::Module(_) => "module",
+            Value::Range(_) => "range",
+        }
+    }
+
+    /// Return the value as an `i64` if it is an int, or a float equal to
+    /// one, which is how range patterns and jump tables in a `match` see it.
+    pub fn as_whole_int(&self) -> Option<i64> {
+        match *self {
+            Value::Int(value) => Some(value),
+            Value::Float(value)
+                if value.fract() == 0.0 && value >= i64::MIN as f64 && value < i64::MAX as f64 =>
+            {
+                Some(value as i64)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A range of ints, such as `0..10`. It is also an iterator over the ints
+/// it contains, in ascending order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Range {
+    pub start: i64,
+    pub end: i64,
+    /// Whether the range includes its end, as `0..=10` does.
+    pub inclusive: bool,
+}
+
+impl Range {
+    /// Return the number of ints in the range, or `None` if it doesn't fit
+    /// in an `i64`.
+    pub fn len(self) -> Option<i64> {
+        let len = self.end as i128 - self.start as i128 + self.inclusive as i128;
+        i64::try_from(len.max(0)).ok()
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.len() == Some(0)
+    }
+
+    /// Returns true if `value` is in the range.
+    pub fn contains(self, value: i64) -> bool {
+        self.start <= value
+            && match self.inclusive {
+                true => value <= self.end,
+                false => value < self.end,
+            }
+    }
+}
+
+impl Iterator for Range {
+    type Item = i64;
+
+    fn next(&mut self) -> Option<i64> {
+        if !self.contains(self.start) {
+            return None;
+        }
+        let next = self.start;
+        match next.checked_add(1) {
+            Some(start) => self.start = start,
+            // Only `..=i64::MAX` can end at the largest int
+            None => self.inclusive = false,
+        }
+        Some(next)
+    }
+}
+
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = if self.inclusive { "..=" } else { ".." };
+        write!(f, "{}{}{}", self.start, op, self.end)
+    }
+}
+
+/// A type that values can be converted to with `as`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[repr(u8)]
+pub enum CastType {
+    Int,
+    Float,
+}
+
+impl CastType {
+    /// Every cast type, ordered by the operand byte of
+    /// [`OpCode::Cast`](crate::bytecode::OpCode::Cast) that selects it.
+    pub const ALL: [CastType; 2] = [CastType::Int, CastType::Float];
+
+    /// Look a type up by the name it is written with.
+    pub fn from_name(name: &str) -> Option<CastType> {
+        Self::ALL.into_iter().find(|ty| ty.name() == name)
+    }
+
+    pub fn from_byte(byte: u8) -> Option<CastType> {
+        Self::ALL.get(byte as usize).copied()
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CastType::Int => "int",
+            CastType::Float => "float",
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::Str(Rc::from(value))
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Unit => write!(f, "()"),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Int(value) => write!(f, "{}", value),
+            Value::BigInt(value) => write!(f, "{}", value),
+            // Debug formatting keeps the `.0` on whole numbers
+            Value::Float(value) => write!(f, "{:?}", value),
+            Value::Char(value) => write!(f, "{}", value),
+            Value::Str(value) => write!(f, "{}", value),
+            Value::Function(function) => write!(f, "{}", function),
+            Value::Class(class) => write!(f, "<class {}>", class.name),
+            // Printing an object's contents needs the heap it lives on
+            Value::List(_) => write!(f, "<list>"),
+            Value::Instance(_) => write!(f, "<instance>"),
+            Value::BoundMethod(_) => write!(f, "<method>"),
+            Value::Native(native) => write!(f, "<native fun {}>", native.name()),
+            Value::Host(host) => write!(f, "<native fun {}>", host.name),
+            Value::Channel(_) => write!(f, "<channel>"),
+            Value::Generator(_) => write!(f, "<generator>"),
+            Value::Module(module) => write!(f, "<module {}>", module.name()),
+            Value::Range(range) => write!(f, "{}", range),
+        }
+    }
+}
@@ -0,0 +1,866 @@
+//! The tree-walking backend evaluates a program's syntax tree directly,
+//! without compiling it to bytecode first. Programs are run on it with
+//! [`Vm::run_ast`], or by selecting [`Backend::Ast`](super::Backend::Ast)
+//! before calling [`run`](crate::run).
+//!
+//! It shares everything else with the bytecode backends: values, globals,
+//! the heap, and the helpers implementing operators, fields and calls, so
+//! programs behave the same on every backend. Each call gets an
+//! `AstFrame` whose locals live in slots of the VM's stack, like those of
+//! a bytecode frame, and values being worked on are pushed above them, so
+//! the garbage collector finds them in the same place.
+//!
+//! Functions declared on this backend are [`Function`]s without any code,
+//! whose declarations are kept by the VM, so they can only be called here.
+//! A Rust call can't be suspended part way through, so generators and tasks
+//! aren't supported, and programs can't be paused with [`Vm::step`].
+//! [`run`](crate::run) compiles programs before evaluating them anyway, so
+//! that they get the same diagnostics as on the other backends, and
+//! [`check`] rejects those using generators or tasks up front.
+//!
+//! Evaluation recurses on the Rust stack for every call and nested
+//! expression, so the stack is grown as needed, and calls are limited by
+//! [`MAX_CALL_DEPTH`](super::MAX_CALL_DEPTH) like on the other backends.
+
+use super::{
+    heap::{Class, Object},
+    in_range,
+    native::Module,
+    observer::Instruction,
+    Prepared, RunResult, Vm,
+};
+use crate::{
+    bytecode::{Function, OpCode},
+    diagnostics::Diagnostic,
+    errors::RuntimeError,
+    parser::ast::{
+        BinOp, Block, Expr, FunDecl, Literal, MatchArm, Param, Pattern, Stmt, StrPart, UnaryOp,
+    },
+    span::Span,
+    value::{CastType, Range, Value},
+};
+use std::{collections::HashMap, rc::Rc};
+
+/// How much of the stack must be left before evaluating an expression.
+/// With less, evaluation continues on a newly allocated stack.
+const RED_ZONE: usize = 128 * 1024;
+
+/// The size of each stack allocated for evaluation.
+const STACK_SIZE: usize = 2 * 1024 * 1024;
+
+/// The declaration of a function declared on this backend.
+pub(super) struct Declaration {
+    /// The function value the declaration was turned into. Holding it keeps
+    /// its address, which declarations are looked up by, from being reused.
+    function: Rc<Function>,
+    decl: Rc<FunDecl>,
+    /// Whether the function is a method, which is called with its receiver
+    /// in the slot named `self`.
+    method: bool,
+}
+
+/// A variable stored in a stack slot.
+struct Local {
+    name: String,
+    slot: usize,
+}
+
+/// A call in progress on this backend.
+pub(super) struct AstFrame {
+    function: Rc<Function>,
+    /// The index of the frame's first stack slot, which holds the function
+    /// being called, or a method's receiver.
+    base: usize,
+    /// The locals in scope, innermost last.
+    locals: Vec<Local>,
+    /// The number of scopes open. Declarations outside of any scope are
+    /// globals.
+    depth: u32,
+    /// The call the frame is making, or the expression that failed.
+    span: Option<Span>,
+}
+
+impl AstFrame {
+    pub(super) fn function(&self) -> &Rc<Function> {
+        &self.function
+    }
+
+    pub(super) fn span(&self) -> Option<Span> {
+        self.span
+    }
+}
+
+/// Why evaluation stopped before reaching the end of an expression or
+/// statement.
+enum Unwind {
+    Error(RuntimeError),
+    /// A `return` is leaving the function it is in with a value.
+    Return(Value),
+}
+
+impl From<RuntimeError> for Unwind {
+    fn from(error: RuntimeError) -> Self {
+        Unwind::Error(error)
+    }
+}
+
+type EvalResult<T> = Result<T, Unwind>;
+
+impl Vm {
+    /// Evaluate a parsed program on the tree-walking backend, returning the
+    /// value it returns. Globals persist between calls, as they do with
+    /// [`Vm::run`], whichever backend is selected.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use meow::{parse, value::Value, vm::Vm};
+    ///
+    /// let program = parse("fun double(x) { x * 2 } let y = double(21);").unwrap();
+    /// let mut vm = Vm::new();
+    /// vm.run_ast(&program).unwrap();
+    /// assert_eq!(vm.global("y"), Some(&Value::Int(42)));
+    /// ```
+    pub fn run_ast(&mut self, program: &[Stmt]) -> RunResult<Value> {
+        self.stack.clear();
+        self.frames.clear();
+        self.register_frames.clear();
+        self.ast_frames.clear();
+        self.reset_tasks();
+
+        let script = Rc::new(Function {
+            name: "<script>".to_string(),
+            ..Function::default()
+        });
+        self.stack.push(Value::Function(script.clone()));
+        if self.observer.is_some() {
+            self.observe_call(&script, 0, 0);
+        }
+        self.ast_frames.push(AstFrame {
+            function: script,
+            base: 0,
+            locals: Vec::new(),
+            depth: 0,
+            span: None,
+        });
+
+        let result = match self.statements(program) {
+            Ok(()) => Ok(Value::Unit),
+            Err(Unwind::Return(value)) => Ok(value),
+            Err(Unwind::Error(error)) => Err(error),
+        };
+        if result.is_ok() {
+            self.ast_frames.clear();
+            self.stack.clear();
+        }
+        self.finish(result)
+    }
+
+    /// Tell the observer that the node at `span` is about to be evaluated.
+    #[cold]
+    fn observe_node(&mut self, span: Span) {
+        let function = &self.ast_frames.last().expect("no call frame").function;
+        if let Some(observer) = &mut self.observer {
+            observer.on_instruction(&Instruction {
+                function,
+                op: None,
+                span: Some(span),
+            });
+        }
+    }
+
+    fn ast_frame(&self) -> &AstFrame {
+        self.ast_frames.last().expect("no call frame")
+    }
+
+    fn ast_frame_mut(&mut self) -> &mut AstFrame {
+        self.ast_frames.last_mut().expect("no call frame")
+    }
+
+    /// Point an error that doesn't know where it happened at `span`.
+    fn locate(&mut self, unwind: Unwind, span: Span) -> Unwind {
+        match unwind {
+            Unwind::Error(mut error) if error.span.is_none() => {
+                error.span = Some(span);
+                self.ast_frame_mut().span = error.span;
+                Unwind::Error(error)
+            }
+            unwind => unwind,
+        }
+    }
+
+    fn statements(&mut self, stmts: &[Stmt]) -> EvalResult<()> {
+        for stmt in stmts {
+            self.exec(stmt)
+                .map_err(|unwind| self.locate(unwind, stmt.span()))?;
+        }
+        Ok(())
+    }
+
+    fn exec(&mut self, stmt: &Stmt) -> EvalResult<()> {
+        self.consume_fuel()?;
+        if self.observer.is_some() {
+            self.observe_node(stmt.span());
+        }
+        match stmt {
+            Stmt::Let { name, value, .. } => {
+                let value = match value {
+                    Some(value) => self.eval(value)?,
+                    None => Value::Unit,
+                };
+                self.define(name, value);
+            }
+            Stmt::Expr { expr, .. } => {
+                self.eval(expr)?;
+            }
+            Stmt::Fun(fun) => {
+                let function = self.declare(fun, false)?;
+                self.define(&fun.name, function);
+            }
+            Stmt::Class { name, methods, .. } => {
+                let methods = methods
+                    .iter()
+                    .map(|method| match self.declare(method, true)? {
+                        Value::Function(function) => Ok((method.name.clone(), function)),
+                        _ => unreachable!("declarations are functions"),
+                    })
+                    .collect::<RunResult<HashMap<_, _>>>()?;
+                let class = Class {
+                    name: name.clone(),
+                    methods,
+                    host: None,
+                };
+                self.define(name, Value::Class(Rc::new(class)));
+            }
+            Stmt::Return { value, .. } => {
+                let value = match value {
+                    Some(value) => self.eval(value)?,
+                    None => Value::Unit,
+                };
+                return Err(Unwind::Return(value));
+            }
+            Stmt::Yield { .. } => {
+                return Err(self.unsupported("generators").into());
+            }
+            Stmt::Spawn { .. } => return Err(self.unsupported("tasks").into()),
+            Stmt::While { cond, body, .. } => {
+                while self.condition(cond)? {
+                    self.eval_block(body)?;
+                }
+            }
+            Stmt::For {
+                var,
+                iterable,
+                body,
+                ..
+            } => {
+                // A range written in the loop is created like any other,
+                // which checks its bounds
+                return match self.eval(iterable)? {
+                    Value::Range(range) => self.iterate_range(var, range, body),
+                    Value::Generator(_) => Err(self.unsupported("generators").into()),
+                    value => Err(self
+                        .error(format!(
+                            "cannot iterate over a value of type {}",
+                            value.type_name()
+                        ))
+                        .into()),
+                };
+            }
+            Stmt::Import { path, .. } => {
+                let module = Module::from_path(path)
+                    .ok_or_else(|| self.error(format!("unknown module `{}`", path.join("."))))?;
+                self.define(
+                    path.last().expect("import without a path"),
+                    Value::Module(module),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Run `body` once for every int in `range`, which isn't written in the
+    /// loop itself.
+    fn iterate_range(&mut self, var: &Param, range: Range, body: &Block) -> EvalResult<()> {
+        for next in range {
+            self.scope(|vm| {
+                vm.define(&var.name, Value::Int(next));
+                vm.eval_block(body)
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Turn `fun` into a function value that calls it.
+    fn declare(&mut self, fun: &FunDecl, method: bool) -> RunResult<Value> {
+        if fun.params.len() > u8::MAX as usize {
+            return Err(self.error("functions can't have more than 255 parameters"));
+        }
+
+        let function = Rc::new(Function {
+            name: fun.name.clone(),
+            arity: fun.params.len() as u8,
+            ..Function::default()
+        });
+        self.declarations.insert(
+            Rc::as_ptr(&function),
+            Declaration {
+                function: function.clone(),
+                decl: Rc::new(fun.clone()),
+                method,
+            },
+        );
+        Ok(Value::Function(function))
+    }
+
+    /// Bind `value` to the variable `name`. Inside a scope it is pushed as a
+    /// new local, and otherwise it is a global.
+    fn define(&mut self, name: &str, value: Value) {
+        if self.ast_frame().depth == 0 {
+            let id = self.globals.intern(name);
+            self.globals.define(id, value);
+        } else {
+            let slot = self.stack.len();
+            self.push(value);
+            self.ast_frame_mut().locals.push(Local {
+                name: name.to_string(),
+                slot,
+            });
+        }
+    }
+
+    /// Return the stack slot of the local `name`, if one is in scope.
+    fn local(&self, name: &str) -> Option<usize> {
+        let frame = self.ast_frame();
+        let local = frame.locals.iter().rev().find(|local| local.name == name)?;
+        Some(local.slot)
+    }
+
+    fn read(&self, name: &str) -> RunResult<Value> {
+        match self.local(name) {
+            Some(slot) => Ok(self.stack[slot].clone()),
+            None => self
+                .globals
+                .lookup(name)
+                .cloned()
+                .ok_or_else(|| self.undefined(name)),
+        }
+    }
+
+    fn write(&mut self, name: &str, value: Value) -> RunResult<()> {
+        if let Some(slot) = self.local(name) {
+            self.stack[slot] = value;
+            return Ok(());
+        }
+        let id = self.globals.intern(name);
+        match self.globals.set(id, value) {
+            true => Ok(()),
+            false => Err(self.undefined(name)),
+        }
+    }
+
+    /// Run `f` in a new scope, dropping the locals it declares afterwards.
+    fn scope<T>(&mut self, f: impl FnOnce(&mut Self) -> EvalResult<T>) -> EvalResult<T> {
+        let height = self.stack.len();
+        let frame = self.ast_frame_mut();
+        let locals = frame.locals.len();
+        frame.depth += 1;
+
+        let result = f(self);
+        // Failed frames are left as they are for the trace, and cleared
+        // once the error reaches `run_ast`
+        if let Err(Unwind::Error(_)) = result {
+            return result;
+        }
+
+        let frame = self.ast_frame_mut();
+        frame.depth -= 1;
+        frame.locals.truncate(locals);
+        self.stack.truncate(height);
+        result
+    }
+
+    fn eval_block(&mut self, block: &Block) -> EvalResult<Value> {
+        self.scope(|vm| {
+            vm.statements(&block.stmts)?;
+            match &block.tail {
+                Some(tail) => vm.eval(tail),
+                None => Ok(Value::Unit),
+            }
+        })
+    }
+
+    /// Evaluate the condition of an `if`, `while` or logical operator,
+    /// which must be a bool.
+    fn condition(&mut self, cond: &Expr) -> EvalResult<bool> {
+        match self.eval(cond)? {
+            Value::Bool(value) => Ok(value),
+            value => Err(self
+                .error(format!(
+                    "expected a bool condition, found a value of type {}",
+                    value.type_name()
+                ))
+                .into()),
+        }
+    }
+
+    fn eval(&mut self, expr: &Expr) -> EvalResult<Value> {
+        stacker::maybe_grow(RED_ZONE, STACK_SIZE, || self.eval_expr(expr))
+    }
+
+    fn eval_expr(&mut self, expr: &Expr) -> EvalResult<Value> {
+        self.consume_fuel()?;
+        if self.observer.is_some() {
+            self.observe_node(expr.span());
+        }
+        // Each kind of expression is evaluated by a function of its own, to
+        // keep this one's stack frame small, since it recurses the most
+        let result = match expr {
+            Expr::Literal { value, .. } => Ok(literal(value)),
+            Expr::Ident { name, .. } => self.read(name).map_err(Unwind::Error),
+            Expr::Unary { op, expr, .. } => self.eval_unary(*op, expr),
+            Expr::Binary {
+                op, left, right, ..
+            } => self.eval_binary(*op, left, right),
+            Expr::Cast { expr, ty, .. } => self.eval_cast(expr, *ty),
+            Expr::Assign {
+                target, op, value, ..
+            } => self.assign(target, *op, value),
+            Expr::Call { callee, args, span } => self.eval_call(callee, args, *span),
+            Expr::Field { object, name, .. } => self.eval_field(object, name),
+            Expr::Index { object, index, .. } => self.eval_index(object, index),
+            Expr::List { items, .. } => self.eval_list(items),
+            Expr::Interpolation { parts, .. } => self.eval_interpolation(parts),
+            Expr::Block(block) => self.eval_block(block),
+            Expr::If {
+                cond,
+                then,
+                otherwise,
+                ..
+            } => self.eval_if(cond, then, otherwise.as_deref()),
+            Expr::Match {
+                scrutinee, arms, ..
+            } => self.eval_match(scrutinee, arms),
+        };
+        result.map_err(|unwind| self.locate(unwind, expr.span()))
+    }
+
+    fn eval_unary(&mut self, op: UnaryOp, expr: &Expr) -> EvalResult<Value> {
+        let value = self.eval(expr)?;
+        let op = match op {
+            UnaryOp::Minus => OpCode::Negate,
+            UnaryOp::Bang => OpCode::Not,
+        };
+        Ok(self.unary(op, value)?)
+    }
+
+    fn eval_cast(&mut self, expr: &Expr, ty: CastType) -> EvalResult<Value> {
+        let value = self.eval(expr)?;
+        Ok(self.cast(value, ty)?)
+    }
+
+    fn eval_field(&mut self, object: &Expr, name: &str) -> EvalResult<Value> {
+        // The object stays on the stack in case binding a method to it
+        // triggers a collection
+        let object = self.eval(object)?;
+        self.push(object.clone());
+        let value = self.get_field(object, name)?;
+        self.pop();
+        Ok(value)
+    }
+
+    fn eval_list(&mut self, items: &[Expr]) -> EvalResult<Value> {
+        let start = self.stack.len();
+        for item in items {
+            let item = self.eval(item)?;
+            self.push(item);
+        }
+        self.maybe_collect();
+        let items = self.stack.split_off(start);
+        Ok(Value::List(self.alloc(Object::List(items))))
+    }
+
+    fn eval_interpolation(&mut self, parts: &[StrPart]) -> EvalResult<Value> {
+        // The values stay on the stack, like a list's items, until the
+        // string is made
+        let start = self.stack.len();
+        self.push(Value::from(StrPart::template(parts).as_str()));
+        for expr in StrPart::exprs(parts) {
+            let value = self.eval(expr)?;
+            self.push(value);
+        }
+        let result = self.interpolate(&self.stack[start..]);
+        self.stack.truncate(start);
+        Ok(result?)
+    }
+
+    fn eval_if(
+        &mut self,
+        cond: &Expr,
+        then: &Block,
+        otherwise: Option<&Expr>,
+    ) -> EvalResult<Value> {
+        match (self.condition(cond)?, otherwise) {
+            (true, _) => self.eval_block(then),
+            (false, Some(otherwise)) => self.eval(otherwise),
+            (false, None) => Ok(Value::Unit),
+        }
+    }
+
+    fn eval_match(&mut self, scrutinee: &Expr, arms: &[MatchArm]) -> EvalResult<Value> {
+        // The value stays on the stack while the arms run, so it is rooted
+        let value = self.eval(scrutinee)?;
+        self.push(value.clone());
+        for arm in arms {
+            if !self.matches(&arm.pattern, &value)? {
+                continue;
+            }
+            let result = self.scope(|vm| {
+                if let Pattern::Binding { name, .. } = &arm.pattern {
+                    vm.define(name, value.clone());
+                }
+                if let Some(guard) = &arm.guard {
+                    if !vm.condition(guard)? {
+                        return Ok(None);
+                    }
+                }
+                vm.eval(&arm.body).map(Some)
+            })?;
+            if let Some(result) = result {
+                self.pop();
+                return Ok(result);
+            }
+        }
+        Err(self.unmatched(&value).into())
+    }
+
+    /// Returns true if `value` matches `pattern`, which compares literals
+    /// with `==`.
+    fn matches(&self, pattern: &Pattern, value: &Value) -> RunResult<bool> {
+        Ok(match pattern {
+            Pattern::Literal {
+                value: literal_value,
+                ..
+            } => {
+                let equal = self.binary(OpCode::Equal, value.clone(), literal(literal_value))?;
+                equal == Value::Bool(true)
+            }
+            Pattern::Range {
+                start,
+                end,
+                inclusive,
+                ..
+            } => {
+                let range = Range {
+                    start: *start,
+                    end: *end,
+                    inclusive: *inclusive,
+                };
+                in_range(value, &Value::Range(range))
+            }
+            Pattern::Wildcard { .. } | Pattern::Binding { .. } => true,
+            Pattern::Or { patterns, .. } => {
+                for pattern in patterns {
+                    if self.matches(pattern, value)? {
+                        return Ok(true);
+                    }
+                }
+                false
+            }
+        })
+    }
+
+    fn eval_binary(&mut self, op: BinOp, left: &Expr, right: &Expr) -> EvalResult<Value> {
+        match op {
+            // The right operand is only evaluated if the left one doesn't
+            // decide the result
+            BinOp::And | BinOp::Or => {
+                let decided = self.condition(left)? == (op == BinOp::Or);
+                match decided {
+                    true => Ok(Value::Bool(op == BinOp::Or)),
+                    false => Ok(Value::Bool(self.condition(right)?)),
+                }
+            }
+            BinOp::Range | BinOp::RangeInclusive => {
+                let start = self.eval(left)?;
+                self.push(start);
+                let end = self.eval(right)?;
+                let start = self.pop();
+                Ok(self.range(&start, &end, op == BinOp::RangeInclusive)?)
+            }
+            op => {
+                let left = self.eval(left)?;
+                self.push(left);
+                let right = self.eval(right)?;
+                let left = self.pop();
+                Ok(self.binary(opcode(op), left, right)?)
+            }
+        }
+    }
+
+    fn eval_index(&mut self, object: &Expr, index: &Expr) -> EvalResult<Value> {
+        let object = self.eval(object)?;
+        self.push(object);
+        let result = match index {
+            Expr::Binary {
+                op: op @ (BinOp::Range | BinOp::RangeInclusive),
+                left,
+                right,
+                ..
+            } => {
+                let start = self.eval(left)?;
+                self.push(start);
+                let end = self.eval(right)?;
+                self.push(end);
+                // The operands stay on the stack until the slice is taken,
+                // so that a sliced list is rooted during a collection
+                self.maybe_collect();
+                let end = self.pop();
+                let start = self.pop();
+                let object = self.peek(0).clone();
+                self.slice(object, &start, &end, *op == BinOp::RangeInclusive)?
+            }
+            index => {
+                let index = self.eval(index)?;
+                self.get_index(self.peek(0), &index)?
+            }
+        };
+        self.pop();
+        Ok(result)
+    }
+
+    fn assign(&mut self, target: &Expr, op: Option<BinOp>, value: &Expr) -> EvalResult<Value> {
+        // Compound assignments read the target before evaluating the value,
+        // which is kept on the stack in the meantime
+        let apply = |vm: &mut Self, current: Option<Value>| -> EvalResult<Value> {
+            let current = match (op, current) {
+                (Some(op), Some(current)) => Some((op, current)),
+                _ => None,
+            };
+            match current {
+                Some((op, current)) => {
+                    vm.push(current);
+                    let value = vm.eval(value)?;
+                    let current = vm.pop();
+                    Ok(vm.binary(opcode(op), current, value)?)
+                }
+                None => vm.eval(value),
+            }
+        };
+
+        match target {
+            Expr::Ident { name, .. } => {
+                let current = op.map(|_| self.read(name)).transpose()?;
+                let value = apply(self, current)?;
+                self.write(name, value.clone())?;
+                Ok(value)
+            }
+            Expr::Index { object, index, .. } => {
+                let object = self.eval(object)?;
+                self.push(object);
+                let index = self.eval(index)?;
+                self.push(index);
+                let current = op
+                    .map(|_| self.get_index(self.peek(1), self.peek(0)))
+                    .transpose()?;
+                let value = apply(self, current)?;
+                let index = self.pop();
+                let object = self.pop();
+                let (list, index) = self.index(&object, &index)?;
+                self.heap.list_mut(list)[index] = value.clone();
+                Ok(value)
+            }
+            Expr::Field { object, name, .. } => {
+                let object = self.eval(object)?;
+                self.push(object);
+                let current = op
+                    .map(|_| self.get_field(self.peek(0).clone(), name))
+                    .transpose()?;
+                let value = apply(self, current)?;
+                let object = self.pop();
+                self.set_field(object, Rc::from(name.as_str()), value.clone())?;
+                Ok(value)
+            }
+            _ => Err(self.error("invalid assignment target").into()),
+        }
+    }
+
+    fn eval_call(&mut self, callee: &Expr, args: &[Expr], span: Span) -> EvalResult<Value> {
+        if args.len() > u8::MAX as usize {
+            return Err(self
+                .error("calls can't have more than 255 arguments")
+                .into());
+        }
+        let base = self.stack.len();
+        let callee = self.eval(callee)?;
+        self.push(callee);
+        for arg in args {
+            let arg = self.eval(arg)?;
+            self.push(arg);
+        }
+
+        self.ast_frame_mut().span = Some(span);
+        let result = match self.prepare_call(base, args.len() as u8)? {
+            Prepared::Frame(function, initializer) => {
+                self.call_declared(function, base, initializer)?
+            }
+            Prepared::Done => self.stack[base].clone(),
+            // There are no other tasks to wait for
+            Prepared::Blocked if self.callback.is_some() => {
+                return Err(self.blocked_in_callback().into())
+            }
+            Prepared::Blocked => return Err(self.deadlock().into()),
+        };
+        self.stack.truncate(base);
+        Ok(result)
+    }
+
+    /// Call `function`, whose arguments follow stack slot `base`, in a new
+    /// frame.
+    fn call_declared(
+        &mut self,
+        function: Rc<Function>,
+        base: usize,
+        initializer: bool,
+    ) -> EvalResult<Value> {
+        let (function, decl, method) = match self.declarations.get(&Rc::as_ptr(&function)) {
+            Some(declaration) => (
+                declaration.function.clone(),
+                declaration.decl.clone(),
+                declaration.method,
+            ),
+            None => {
+                return Err(self
+                    .error(format!(
+                        "`{}` was compiled to bytecode, so it can't be called here",
+                        function.name
+                    ))
+                    .into())
+            }
+        };
+
+        let mut locals = Vec::with_capacity(decl.params.len() + 1);
+        if method {
+            locals.push(Local {
+                name: "self".to_string(),
+                slot: base,
+            });
+        }
+        locals.extend(decl.params.iter().enumerate().map(|(index, param)| Local {
+            name: param.name.clone(),
+            slot: base + 1 + index,
+        }));
+        self.ast_frames.push(AstFrame {
+            function,
+            base,
+            locals,
+            depth: 1,
+            span: None,
+        });
+
+        let result = match self.eval_block(&decl.body) {
+            Ok(value) | Err(Unwind::Return(value)) => value,
+            // The frame is left in place, so it appears in the trace
+            Err(error) => return Err(error),
+        };
+        let frame = self.ast_frames.pop().expect("no call frame");
+        Ok(match initializer {
+            true => self.stack[frame.base].clone(),
+            false => result,
+        })
+    }
+
+    /// Call `function` for [`Vm::call_value`], whose arguments follow stack
+    /// slot `base`.
+    pub(super) fn call_back_ast(
+        &mut self,
+        function: Rc<Function>,
+        base: usize,
+        initializer: bool,
+    ) -> RunResult<Value> {
+        match self.call_declared(function, base, initializer) {
+            Ok(value) => Ok(value),
+            Err(Unwind::Error(error)) => Err(error),
+            Err(Unwind::Return(_)) => unreachable!("calls catch their returns"),
+        }
+    }
+
+    fn unsupported(&self, what: &str) -> RuntimeError {
+        self.error(unsupported(what))
+    }
+}
+
+/// Check that `script`, the program as compiled for the other backends,
+/// doesn't use generators or tasks, which this backend can't run. Returns
+/// an error for every `yield` and `spawn` in it.
+///
+/// # Examples
+///
+/// ```
+/// use meow::{compile, vm::ast::check};
+///
+/// let script = compile("fun count() { yield 1; }\nlet x = 1;").unwrap();
+/// let diagnostics = check(&script);
+/// assert_eq!(diagnostics[0].message, "generators are not supported by the ast backend");
+/// assert_eq!(diagnostics[0].span.line, 1);
+/// ```
+pub fn check(script: &Function) -> Vec<Diagnostic> {
+    let chunk = &script.chunk;
+    let mut diagnostics = Vec::new();
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        let op = OpCode::from_byte(chunk.code[offset]).expect("the script was compiled");
+        let what = match op {
+            OpCode::Yield => Some("generators"),
+            OpCode::Spawn => Some("tasks"),
+            _ => None,
+        };
+        if let Some(what) = what {
+            let span = chunk.span_at(offset).unwrap_or_default();
+            diagnostics.push(Diagnostic::error(unsupported(what), span));
+        }
+        offset += 1 + op.operand_len();
+    }
+
+    for constant in &chunk.constants {
+        if let Value::Function(function) = constant {
+            diagnostics.extend(check(function));
+        }
+    }
+    diagnostics
+}
+
+fn unsupported(what: &str) -> String {
+    format!("{} are not supported by the ast backend", what)
+}
+
+fn literal(value: &Literal) -> Value {
+    match value {
+        Literal::Int(value) => Value::Int(*value),
+        Literal::Float(value) => Value::Float(*value),
+        Literal::Str(value) => Value::from(value.as_str()),
+        Literal::Char(value) => Value::Char(*value),
+        Literal::Bool(value) => Value::Bool(*value),
+    }
+}
+
+/// Return the instruction implementing an arithmetic or comparison
+/// operator.
+fn opcode(op: BinOp) -> OpCode {
+    match op {
+        BinOp::Plus => OpCode::Add,
+        BinOp::Minus => OpCode::Subtract,
+        BinOp::Star => OpCode::Multiply,
+        BinOp::Slash => OpCode::Divide,
+        BinOp::Percent => OpCode::Remainder,
+        BinOp::EqualEqual => OpCode::Equal,
+        BinOp::BangEqual => OpCode::NotEqual,
+        BinOp::Greater => OpCode::Greater,
+        BinOp::GreaterEqual => OpCode::GreaterEqual,
+        BinOp::Less => OpCode::Less,
+        BinOp::LessEqual => OpCode::LessEqual,
+        BinOp::And | BinOp::Or | BinOp::Range | BinOp::RangeInclusive => {
+            unreachable!("{:?} has no single instruction", op)
+        }
+    }
+}
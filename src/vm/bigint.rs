@@ -0,0 +1,362 @@
+//! Ints are 64 bits, and arithmetic that doesn't fit fails with an overflow
+//! error. When [`Vm::set_big_ints`](super::Vm::set_big_ints) is on, results
+//! that don't fit are [`BigInt`]s instead, which can be as large as memory
+//! allows.
+//!
+//! Big ints are only used for values outside the range of `i64`, so an int
+//! always has a single representation, and results that fit are turned back
+//! into ordinary ints by [`BigInt::into_value`].
+
+use crate::value::Value;
+use std::{cmp::Ordering, fmt, rc::Rc};
+
+/// An arbitrary precision integer, stored as a sign and a magnitude.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BigInt {
+    negative: bool,
+    /// The magnitude's 32 bit digits, least significant first, without
+    /// leading zeros. Zero has no digits.
+    digits: Vec<u32>,
+}
+
+impl BigInt {
+    /// Create an integer from its sign and the digits of its magnitude,
+    /// least significant first.
+    pub fn from_digits(negative: bool, digits: Vec<u32>) -> Self {
+        let mut int = Self { negative, digits };
+        int.normalize();
+        int
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    /// Return the digits of the magnitude, least significant first.
+    pub fn digits(&self) -> &[u32] {
+        &self.digits
+    }
+
+    /// Return the integer as an `i64`, if it fits.
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.digits.len() > 2 {
+            return None;
+        }
+        let magnitude = self
+            .digits
+            .iter()
+            .rev()
+            .fold(0u64, |acc, &digit| (acc << 32) | digit as u64);
+        if self.negative {
+            0i64.checked_sub_unsigned(magnitude)
+        } else {
+            i64::try_from(magnitude).ok()
+        }
+    }
+
+    /// Create an integer from a float with no fractional part.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `value` isn't finite.
+    pub fn from_f64(value: f64) -> Self {
+        assert!(value.is_finite(), "{} is not finite", value);
+        if value.abs() < 2f64.powi(63) {
+            return Self::from(value as i64);
+        }
+        // Larger floats are a 53 bit mantissa shifted left
+        let bits = value.to_bits();
+        let exponent = ((bits >> 52) & 0x7ff) as usize - 1075;
+        let mantissa = (bits & ((1 << 52) - 1)) | (1 << 52);
+        let digits = [mantissa as u32, (mantissa >> 32) as u32];
+        Self::from_digits(value < 0.0, shift_left_by(&digits, exponent))
+    }
+
+    /// Return the float nearest to the integer, which is infinite if it is
+    /// too large.
+    pub fn to_f64(&self) -> f64 {
+        // Zero has no digits, so it has no bits either
+        let bits =
+            self.digits.len() * 32 - self.digits.last().map_or(0, |d| d.leading_zeros()) as usize;
+        let magnitude = if bits <= 64 {
+            self.digits
+                .iter()
+                .rev()
+                .fold(0u64, |acc, &digit| (acc << 32) | digit as u64) as f64
+        } else {
+            // Keep the top 64 bits, and set the lowest if any of the rest
+            // are set, so that the conversion rounds as if they were kept
+            let shift = bits - 64;
+            let mut top = 0u64;
+            for bit in (shift..bits).rev() {
+                top = (top << 1) | self.bit(bit) as u64;
+            }
+            let sticky = (0..shift).any(|bit| self.bit(bit));
+            (top | sticky as u64) as f64 * 2f64.powi(shift as i32)
+        };
+        if self.negative {
+            -magnitude
+        } else {
+            magnitude
+        }
+    }
+
+    /// Compare the integer with `value` exactly. Returns `None` if `value`
+    /// is NaN.
+    pub fn cmp_f64(&self, value: f64) -> Option<Ordering> {
+        if value.is_nan() {
+            return None;
+        }
+        if value.is_infinite() {
+            return Some(if value > 0.0 {
+                Ordering::Less
+            } else {
+                Ordering::Greater
+            });
+        }
+        // Only the fractional part can tell an int from its whole part
+        let whole = value.trunc();
+        let ordering = self.cmp(&Self::from_f64(whole));
+        Some(ordering.then(whole.partial_cmp(&value)?))
+    }
+
+    fn bit(&self, index: usize) -> bool {
+        self.digits[index / 32] >> (index % 32) & 1 == 1
+    }
+
+    /// Turn the integer into a value, which is an ordinary int if it fits.
+    pub fn into_value(self) -> Value {
+        match self.to_i64() {
+            Some(value) => Value::Int(value),
+            None => Value::BigInt(Rc::new(self)),
+        }
+    }
+
+    pub fn add(&self, other: &Self) -> Self {
+        if self.negative == other.negative {
+            return Self::from_digits(self.negative, add(&self.digits, &other.digits));
+        }
+        match compare(&self.digits, &other.digits) {
+            Ordering::Less => Self::from_digits(other.negative, sub(&other.digits, &self.digits)),
+            _ => Self::from_digits(self.negative, sub(&self.digits, &other.digits)),
+        }
+    }
+
+    pub fn sub(&self, other: &Self) -> Self {
+        self.add(&other.neg())
+    }
+
+    pub fn mul(&self, other: &Self) -> Self {
+        Self::from_digits(
+            self.negative != other.negative,
+            mul(&self.digits, &other.digits),
+        )
+    }
+
+    /// Divide, rounding towards zero like `i64` division does.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `other` is zero.
+    pub fn div(&self, other: &Self) -> Self {
+        assert!(!other.digits.is_empty(), "division by zero");
+        Self::from_digits(
+            self.negative != other.negative,
+            div(&self.digits, &other.digits),
+        )
+    }
+
+    /// Return the remainder of dividing by `other`, which has the sign of
+    /// `self` like the remainder of `i64` division does.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `other` is zero.
+    pub fn rem(&self, other: &Self) -> Self {
+        self.sub(&self.div(other).mul(other))
+    }
+
+    pub fn neg(&self) -> Self {
+        Self::from_digits(!self.negative, self.digits.clone())
+    }
+
+    /// Remove leading zeros, and make sure zero isn't negative.
+    fn normalize(&mut self) {
+        trim(&mut self.digits);
+        if self.digits.is_empty() {
+            self.negative = false;
+        }
+    }
+}
+
+impl From<i64> for BigInt {
+    fn from(value: i64) -> Self {
+        let magnitude = value.unsigned_abs();
+        Self::from_digits(value < 0, vec![magnitude as u32, (magnitude >> 32) as u32])
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => compare(&self.digits, &other.digits),
+            (true, true) => compare(&other.digits, &self.digits),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Split the magnitude into base 10^9 chunks, least significant first
+        const CHUNK: u32 = 1_000_000_000;
+        let mut chunks = Vec::new();
+        let mut digits = self.digits.clone();
+        while !digits.is_empty() {
+            let (quotient, remainder) = div_small(&digits, CHUNK);
+            chunks.push(remainder);
+            digits = quotient;
+        }
+
+        if self.negative {
+            write!(f, "-")?;
+        }
+        match chunks.split_last() {
+            Some((first, rest)) => {
+                write!(f, "{}", first)?;
+                for chunk in rest.iter().rev() {
+                    write!(f, "{:09}", chunk)?;
+                }
+                Ok(())
+            }
+            None => write!(f, "0"),
+        }
+    }
+}
+
+fn trim(digits: &mut Vec<u32>) {
+    while digits.last() == Some(&0) {
+        digits.pop();
+    }
+}
+
+fn compare(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut sum = Vec::with_capacity(long.len() + 1);
+    let mut carry = 0;
+    for (index, &digit) in long.iter().enumerate() {
+        let total = digit as u64 + *short.get(index).unwrap_or(&0) as u64 + carry;
+        sum.push(total as u32);
+        carry = total >> 32;
+    }
+    sum.push(carry as u32);
+    sum
+}
+
+/// Subtract `b` from `a`, which must be at least as large.
+fn sub(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut difference = Vec::with_capacity(a.len());
+    let mut borrow = 0;
+    for (index, &digit) in a.iter().enumerate() {
+        let (total, under) =
+            (digit as u64).overflowing_sub(*b.get(index).unwrap_or(&0) as u64 + borrow);
+        difference.push(total as u32);
+        borrow = under as u64;
+    }
+    difference
+}
+
+fn mul(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut product = vec![0u32; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0u64;
+        for (j, &y) in b.iter().enumerate() {
+            let total = product[i + j] as u64 + x as u64 * y as u64 + carry;
+            product[i + j] = total as u32;
+            carry = total >> 32;
+        }
+        product[i + b.len()] = carry as u32;
+    }
+    product
+}
+
+/// Divide `a` by a single digit, returning the quotient and remainder.
+fn div_small(a: &[u32], divisor: u32) -> (Vec<u32>, u32) {
+    let mut quotient = vec![0; a.len()];
+    let mut remainder = 0u64;
+    for (index, &digit) in a.iter().enumerate().rev() {
+        let current = (remainder << 32) | digit as u64;
+        quotient[index] = (current / divisor as u64) as u32;
+        remainder = current % divisor as u64;
+    }
+    trim(&mut quotient);
+    (quotient, remainder as u32)
+}
+
+/// Divide `a` by `b`, which isn't zero, discarding the remainder.
+fn div(a: &[u32], b: &[u32]) -> Vec<u32> {
+    if let [divisor] = b {
+        return div_small(a, *divisor).0;
+    }
+
+    // Long division a bit at a time
+    let mut quotient = vec![0u32; a.len()];
+    let mut remainder: Vec<u32> = Vec::new();
+    for bit in (0..a.len() * 32).rev() {
+        shift_left(&mut remainder);
+        if a[bit / 32] >> (bit % 32) & 1 == 1 {
+            if remainder.is_empty() {
+                remainder.push(0);
+            }
+            remainder[0] |= 1;
+        }
+        if compare(&remainder, b) != Ordering::Less {
+            remainder = sub(&remainder, b);
+            trim(&mut remainder);
+            quotient[bit / 32] |= 1 << (bit % 32);
+        }
+    }
+    quotient
+}
+
+/// Multiply `digits` by two to the power of `bits`.
+fn shift_left_by(digits: &[u32], bits: usize) -> Vec<u32> {
+    let mut shifted = vec![0; bits / 32];
+    let offset = bits % 32;
+    let mut carry = 0;
+    for &digit in digits {
+        if offset == 0 {
+            shifted.push(digit);
+        } else {
+            shifted.push((digit << offset) | carry);
+            carry = digit >> (32 - offset);
+        }
+    }
+    shifted.push(carry);
+    shifted
+}
+
+fn shift_left(digits: &mut Vec<u32>) {
+    let mut carry = 0;
+    for digit in digits.iter_mut() {
+        let next = *digit >> 31;
+        *digit = (*digit << 1) | carry;
+        carry = next;
+    }
+    if carry != 0 {
+        digits.push(carry);
+    }
+}
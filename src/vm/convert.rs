@@ -0,0 +1,260 @@
+//! Conversions between Rust values and the values of programs, so that hosts
+//! can pass data in and out of the VM without matching on [`Value`] by
+//! hand. [`IntoMeow`] turns a Rust value into a [`Value`], allocating the
+//! lists and instances it needs on the VM's heap, and [`FromMeow`] does the
+//! opposite, checking that the value has the right type.
+//!
+//! `Vec`s become lists, and maps with string keys become instances of a
+//! class named `Object` with a field for each key, as JSON objects do in
+//! [`std.json`](super::json). `None` is the unit value `{}`.
+
+use super::{
+    heap::{Class, Instance, Object},
+    Vm,
+};
+use crate::{errors::ConversionError, value::Value};
+use std::{collections::HashMap, rc::Rc};
+
+/// A Rust type that can be turned into a [`Value`].
+pub trait IntoMeow {
+    /// Turn `self` into a value of `vm`. Lists and instances are allocated
+    /// on its heap, and are only kept alive once the value is stored in a
+    /// global or passed to the program.
+    fn into_meow(self, vm: &mut Vm) -> Value;
+}
+
+/// A Rust type that can be made from a [`Value`].
+pub trait FromMeow: Sized {
+    /// Convert `value`, whose lists and instances are on the heap of `vm`.
+    fn from_meow(value: &Value, vm: &Vm) -> Result<Self, ConversionError>;
+}
+
+impl Vm {
+    /// Turn `value` into a value of the VM, as [`IntoMeow`] does.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use meow::{compile, value::Value, vm::Vm};
+    ///
+    /// let mut vm = Vm::new();
+    /// let list = vm.to_value(vec![1, 2, 3]);
+    /// vm.set_global("numbers", list);
+    /// vm.run(compile("let total = numbers[0] + numbers[2];").unwrap()).unwrap();
+    /// assert_eq!(vm.global("total"), Some(&Value::Int(4)));
+    /// ```
+    pub fn to_value(&mut self, value: impl IntoMeow) -> Value {
+        value.into_meow(self)
+    }
+
+    /// Convert `value` to a Rust type, as [`FromMeow`] does.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use meow::{compile, vm::Vm};
+    ///
+    /// let mut vm = Vm::new();
+    /// vm.run(compile("let words = [\"a\", \"b\"];").unwrap()).unwrap();
+    /// let words: Vec<String> = vm.from_value(vm.global("words").unwrap()).unwrap();
+    /// assert_eq!(words, ["a", "b"]);
+    /// ```
+    pub fn from_value<T: FromMeow>(&self, value: &Value) -> Result<T, ConversionError> {
+        T::from_meow(value, self)
+    }
+}
+
+/// Return the error for `value` not being a `expected`.
+fn mismatch(expected: &'static str, value: &Value) -> ConversionError {
+    ConversionError::Type {
+        expected,
+        found: value.type_name(),
+    }
+}
+
+impl IntoMeow for Value {
+    fn into_meow(self, _: &mut Vm) -> Value {
+        self
+    }
+}
+
+impl FromMeow for Value {
+    fn from_meow(value: &Value, _: &Vm) -> Result<Self, ConversionError> {
+        Ok(value.clone())
+    }
+}
+
+impl IntoMeow for () {
+    fn into_meow(self, _: &mut Vm) -> Value {
+        Value::Unit
+    }
+}
+
+impl FromMeow for () {
+    fn from_meow(value: &Value, _: &Vm) -> Result<Self, ConversionError> {
+        match value {
+            Value::Unit => Ok(()),
+            value => Err(mismatch("unit", value)),
+        }
+    }
+}
+
+impl IntoMeow for bool {
+    fn into_meow(self, _: &mut Vm) -> Value {
+        Value::Bool(self)
+    }
+}
+
+impl FromMeow for bool {
+    fn from_meow(value: &Value, _: &Vm) -> Result<Self, ConversionError> {
+        match value {
+            Value::Bool(value) => Ok(*value),
+            value => Err(mismatch("bool", value)),
+        }
+    }
+}
+
+impl IntoMeow for i64 {
+    fn into_meow(self, _: &mut Vm) -> Value {
+        Value::Int(self)
+    }
+}
+
+impl FromMeow for i64 {
+    fn from_meow(value: &Value, _: &Vm) -> Result<Self, ConversionError> {
+        match value {
+            Value::Int(value) => Ok(*value),
+            // Big ints only hold values that don't fit in an `i64`
+            Value::BigInt(_) => Err(ConversionError::OutOfRange),
+            value => Err(mismatch("int", value)),
+        }
+    }
+}
+
+impl IntoMeow for f64 {
+    fn into_meow(self, _: &mut Vm) -> Value {
+        Value::Float(self)
+    }
+}
+
+/// Ints are converted to floats too, as they are by arithmetic mixing the
+/// two.
+impl FromMeow for f64 {
+    fn from_meow(value: &Value, _: &Vm) -> Result<Self, ConversionError> {
+        match value {
+            Value::Float(value) => Ok(*value),
+            Value::Int(value) => Ok(*value as f64),
+            value => Err(mismatch("float", value)),
+        }
+    }
+}
+
+impl IntoMeow for char {
+    fn into_meow(self, _: &mut Vm) -> Value {
+        Value::Char(self)
+    }
+}
+
+impl FromMeow for char {
+    fn from_meow(value: &Value, _: &Vm) -> Result<Self, ConversionError> {
+        match value {
+            Value::Char(value) => Ok(*value),
+            value => Err(mismatch("char", value)),
+        }
+    }
+}
+
+impl IntoMeow for &str {
+    fn into_meow(self, _: &mut Vm) -> Value {
+        Value::from(self)
+    }
+}
+
+impl IntoMeow for String {
+    fn into_meow(self, _: &mut Vm) -> Value {
+        Value::Str(self.into())
+    }
+}
+
+impl FromMeow for String {
+    fn from_meow(value: &Value, _: &Vm) -> Result<Self, ConversionError> {
+        match value {
+            Value::Str(value) => Ok(value.to_string()),
+            value => Err(mismatch("string", value)),
+        }
+    }
+}
+
+impl<T: IntoMeow> IntoMeow for Option<T> {
+    fn into_meow(self, vm: &mut Vm) -> Value {
+        match self {
+            Some(value) => value.into_meow(vm),
+            None => Value::Unit,
+        }
+    }
+}
+
+impl<T: FromMeow> FromMeow for Option<T> {
+    fn from_meow(value: &Value, vm: &Vm) -> Result<Self, ConversionError> {
+        match value {
+            Value::Unit => Ok(None),
+            value => T::from_meow(value, vm).map(Some),
+        }
+    }
+}
+
+impl<T: IntoMeow> IntoMeow for Vec<T> {
+    fn into_meow(self, vm: &mut Vm) -> Value {
+        let items = self.into_iter().map(|item| item.into_meow(vm)).collect();
+        Value::List(vm.alloc(Object::List(items)))
+    }
+}
+
+impl<T: FromMeow> FromMeow for Vec<T> {
+    fn from_meow(value: &Value, vm: &Vm) -> Result<Self, ConversionError> {
+        match value {
+            Value::List(obj) => vm
+                .heap
+                .list(*obj)
+                .iter()
+                .map(|item| T::from_meow(item, vm))
+                .collect(),
+            value => Err(mismatch("list", value)),
+        }
+    }
+}
+
+impl<T: IntoMeow> IntoMeow for HashMap<String, T> {
+    fn into_meow(self, vm: &mut Vm) -> Value {
+        let fields = self
+            .into_iter()
+            .map(|(key, value)| (Rc::from(key), value.into_meow(vm)))
+            .collect();
+        let class = Rc::new(Class {
+            name: "Object".to_string(),
+            methods: HashMap::new(),
+            host: None,
+        });
+        Value::Instance(vm.alloc(Object::Instance(Instance {
+            class,
+            fields,
+            data: None,
+        })))
+    }
+}
+
+/// An instance of any class is converted, with an entry for each field.
+impl<T: FromMeow> FromMeow for HashMap<String, T> {
+    fn from_meow(value: &Value, vm: &Vm) -> Result<Self, ConversionError> {
+        match value {
+            Value::Instance(obj) => vm
+                .heap
+                .instance(*obj)
+                .fields
+                .iter()
+                .map(|(key, value)| Ok((key.to_string(), T::from_meow(value, vm)?)))
+                .collect(),
+            value => Err(mismatch("instance", value)),
+        }
+    }
+}
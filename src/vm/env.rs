@@ -0,0 +1,80 @@
+//! The `std.env` and `std.os` modules read the process's environment through
+//! an [`Environment`], rather than directly, so that embedders can decide
+//! what programs have access to with
+//! [`Vm::set_environment`](super::Vm::set_environment).
+
+use std::{env, io};
+
+/// The environment variables and working directory available to a program.
+pub trait Environment {
+    /// Return the value of the environment variable `name`, if it is set.
+    fn var(&mut self, name: &str) -> Option<String>;
+
+    /// Return every environment variable, and its value.
+    fn vars(&mut self) -> Vec<(String, String)>;
+
+    /// Return the working directory.
+    fn current_dir(&mut self) -> io::Result<String>;
+
+    /// Change the working directory to `path`.
+    fn set_current_dir(&mut self, path: &str) -> io::Result<()>;
+}
+
+/// The environment of the process running the VM, which programs use by
+/// default. Variables whose names or values aren't valid UTF-8 are left
+/// out.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OsEnvironment;
+
+impl Environment for OsEnvironment {
+    fn var(&mut self, name: &str) -> Option<String> {
+        env::var(name).ok()
+    }
+
+    fn vars(&mut self) -> Vec<(String, String)> {
+        env::vars_os()
+            .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+            .collect()
+    }
+
+    fn current_dir(&mut self) -> io::Result<String> {
+        env::current_dir()?
+            .into_os_string()
+            .into_string()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "path is not valid UTF-8"))
+    }
+
+    fn set_current_dir(&mut self, path: &str) -> io::Result<()> {
+        env::set_current_dir(path)
+    }
+}
+
+/// An environment with no variables, which refuses access to the working
+/// directory, for running programs that shouldn't see the host.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoEnvironment;
+
+impl Environment for NoEnvironment {
+    fn var(&mut self, _name: &str) -> Option<String> {
+        None
+    }
+
+    fn vars(&mut self) -> Vec<(String, String)> {
+        Vec::new()
+    }
+
+    fn current_dir(&mut self) -> io::Result<String> {
+        Err(denied())
+    }
+
+    fn set_current_dir(&mut self, _path: &str) -> io::Result<()> {
+        Err(denied())
+    }
+}
+
+fn denied() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        "access to the working directory is disabled",
+    )
+}
@@ -0,0 +1,108 @@
+//! The `std.fs` module reads and writes files through a [`FileSystem`],
+//! rather than directly, so that embedders can decide what programs have
+//! access to with [`Vm::set_file_system`](super::Vm::set_file_system), or as
+//! part of a [`MeowIo`](super::io::MeowIo).
+
+use std::{cell::RefCell, collections::BTreeMap, io, rc::Rc};
+#[cfg(feature = "fs")]
+use std::{fs, path::Path};
+
+/// The files available to a program.
+pub trait FileSystem {
+    /// Read the whole file at `path`, which must be valid UTF-8.
+    fn read_to_string(&mut self, path: &str) -> io::Result<String>;
+
+    /// Replace the contents of the file at `path`, creating it if needed.
+    fn write(&mut self, path: &str, contents: &str) -> io::Result<()>;
+
+    /// Returns true if there is a file or directory at `path`.
+    fn exists(&mut self, path: &str) -> bool;
+}
+
+/// The file system of the operating system, which programs use by default
+/// with the `fs` feature.
+#[cfg(feature = "fs")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OsFileSystem;
+
+#[cfg(feature = "fs")]
+impl FileSystem for OsFileSystem {
+    fn read_to_string(&mut self, path: &str) -> io::Result<String> {
+        fs::read_to_string(path)
+    }
+
+    fn write(&mut self, path: &str, contents: &str) -> io::Result<()> {
+        fs::write(path, contents)
+    }
+
+    fn exists(&mut self, path: &str) -> bool {
+        Path::new(path).exists()
+    }
+}
+
+/// A file system with no files, which refuses to create any, for running
+/// programs that shouldn't have access to files.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoFileSystem;
+
+impl FileSystem for NoFileSystem {
+    fn read_to_string(&mut self, _path: &str) -> io::Result<String> {
+        Err(denied())
+    }
+
+    fn write(&mut self, _path: &str, _contents: &str) -> io::Result<()> {
+        Err(denied())
+    }
+
+    fn exists(&mut self, _path: &str) -> bool {
+        false
+    }
+}
+
+/// Files kept in memory, for testing programs that use files. Clones share
+/// the same files.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryFileSystem(Rc<RefCell<BTreeMap<String, String>>>);
+
+impl MemoryFileSystem {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create or replace the file at `path`.
+    pub fn insert(&self, path: &str, contents: &str) {
+        self.0
+            .borrow_mut()
+            .insert(path.to_string(), contents.to_string());
+    }
+
+    /// Return the contents of the file at `path`, if there is one.
+    pub fn get(&self, path: &str) -> Option<String> {
+        self.0.borrow().get(path).cloned()
+    }
+
+    /// Return the paths of every file, in order.
+    pub fn paths(&self) -> Vec<String> {
+        self.0.borrow().keys().cloned().collect()
+    }
+}
+
+impl FileSystem for MemoryFileSystem {
+    fn read_to_string(&mut self, path: &str) -> io::Result<String> {
+        self.get(path)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+    }
+
+    fn write(&mut self, path: &str, contents: &str) -> io::Result<()> {
+        self.insert(path, contents);
+        Ok(())
+    }
+
+    fn exists(&mut self, path: &str) -> bool {
+        self.0.borrow().contains_key(path)
+    }
+}
+
+fn denied() -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, "file access is disabled")
+}
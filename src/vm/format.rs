@@ -0,0 +1,241 @@
+//! Format strings, which `format` fills in with its arguments, as do
+//! `print` and `println` when they are given more than one argument. Each
+//! `{}` in the string is replaced by the next argument, and `{{` and `}}`
+//! stand for literal braces.
+//!
+//! A placeholder can also have a specifier after a colon, such as `{:>8.2}`,
+//! made up of an optional fill character and alignment (`<`, `^` or `>`),
+//! a `0` flag to pad numbers with zeros, a minimum width, and a precision.
+//! The precision is the number of decimal places for numbers, which are
+//! always written as floats when it is given, and the most characters to
+//! write for any other value. Numbers are aligned to the right by default,
+//! and other values to the left. Neither the width nor the precision can be
+//! more than [`MAX_FORMAT_WIDTH`].
+
+use super::{RunResult, Vm};
+use crate::value::Value;
+use std::{iter, mem};
+
+/// The largest width or precision a placeholder can have, so that a format
+/// string can't make `format` allocate without bound.
+pub const MAX_FORMAT_WIDTH: usize = 1024;
+
+/// A piece of a parsed format string.
+#[derive(Debug, Clone, PartialEq)]
+enum Piece {
+    Text(String),
+    Placeholder(Spec),
+}
+
+/// The specifier of a placeholder.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Spec {
+    fill: char,
+    align: Option<Align>,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+impl Default for Spec {
+    fn default() -> Self {
+        Spec {
+            fill: ' ',
+            align: None,
+            zero: false,
+            width: 0,
+            precision: None,
+        }
+    }
+}
+
+/// Split `template` into text and placeholders, describing what is wrong
+/// with it if it is invalid.
+fn parse(template: &str) -> Result<Vec<Piece>, String> {
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            }
+            '}' => return Err("unmatched `}` in format string".to_string()),
+            '{' => {
+                let mut placeholder = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => placeholder.push(c),
+                        None => return Err("unmatched `{` in format string".to_string()),
+                    }
+                }
+                let spec = match placeholder.strip_prefix(':') {
+                    Some(spec) => parse_spec(spec),
+                    None if placeholder.is_empty() => Some(Spec::default()),
+                    None => None,
+                };
+                let spec =
+                    spec.ok_or_else(|| format!("invalid format specifier `{{{}}}`", placeholder))?;
+                pieces.push(Piece::Text(mem::take(&mut text)));
+                pieces.push(Piece::Placeholder(spec));
+            }
+            c => text.push(c),
+        }
+    }
+    pieces.push(Piece::Text(text));
+    Ok(pieces)
+}
+
+/// Parse the part of a placeholder after the colon.
+fn parse_spec(spec: &str) -> Option<Spec> {
+    let align = |c| match c {
+        '<' => Some(Align::Left),
+        '^' => Some(Align::Center),
+        '>' => Some(Align::Right),
+        _ => None,
+    };
+
+    let mut result = Spec::default();
+    let mut rest = spec;
+    let mut chars = spec.chars();
+    match (chars.next(), chars.next()) {
+        (Some(fill), Some(c)) if align(c).is_some() => {
+            result.fill = fill;
+            result.align = align(c);
+            rest = chars.as_str();
+        }
+        (Some(c), _) if align(c).is_some() => {
+            result.align = align(c);
+            rest = &spec[1..];
+        }
+        _ => {}
+    }
+    if let Some(after) = rest.strip_prefix('0') {
+        result.zero = true;
+        rest = after;
+    }
+    let (width, precision) = match rest.split_once('.') {
+        Some((width, precision)) => (width, Some(precision)),
+        None => (rest, None),
+    };
+    if !width.is_empty() {
+        result.width = number(width)?;
+    }
+    if let Some(precision) = precision {
+        result.precision = Some(number(precision)?);
+    }
+    Some(result)
+}
+
+/// Parse a width or precision, which must be written with digits only and
+/// be at most [`MAX_FORMAT_WIDTH`].
+fn number(digits: &str) -> Option<usize> {
+    match digits.bytes().all(|digit| digit.is_ascii_digit()) {
+        true => digits.parse().ok().filter(|&n| n <= MAX_FORMAT_WIDTH),
+        false => None,
+    }
+}
+
+impl Vm {
+    /// Return an interpolated string, formatted from `values`, which are its
+    /// template followed by the values of its expressions.
+    pub(super) fn interpolate(&self, values: &[Value]) -> RunResult<Value> {
+        let Value::Str(template) = &values[0] else {
+            return Err(self.malformed("the template of an interpolated string isn't a string"));
+        };
+        Ok(Value::from(self.format(template, &values[1..])?.as_str()))
+    }
+
+    /// Fill in the placeholders of `template` with `args`, one for each.
+    pub(super) fn format(&self, template: &str, args: &[Value]) -> RunResult<String> {
+        let pieces = parse(template).map_err(|message| self.error(message))?;
+        let placeholders = pieces
+            .iter()
+            .filter(|piece| matches!(piece, Piece::Placeholder(_)))
+            .count();
+        if placeholders != args.len() {
+            return Err(self.error(format!(
+                "format string has {} placeholder{}, but {} argument{} given",
+                placeholders,
+                if placeholders == 1 { "" } else { "s" },
+                args.len(),
+                if args.len() == 1 { " was" } else { "s were" }
+            )));
+        }
+
+        let mut out = String::new();
+        let mut args = args.iter();
+        for piece in pieces {
+            match piece {
+                Piece::Text(text) => out.push_str(&text),
+                Piece::Placeholder(spec) => {
+                    let arg = args.next().expect("placeholders were counted");
+                    self.write_formatted(&mut out, arg, spec);
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    /// Write `value` to `out` as `spec` describes.
+    fn write_formatted(&self, out: &mut String, value: &Value, spec: Spec) {
+        let number = match value {
+            Value::Int(value) => Some(*value as f64),
+            Value::BigInt(value) => Some(value.to_f64()),
+            Value::Float(value) => Some(*value),
+            _ => None,
+        };
+        let text = match (number, spec.precision) {
+            (Some(number), Some(precision)) => format!("{:.*}", precision, number),
+            (None, Some(precision)) => self
+                .heap
+                .display(value)
+                .to_string()
+                .chars()
+                .take(precision)
+                .collect(),
+            (_, None) => self.heap.display(value).to_string(),
+        };
+
+        let len = text.chars().count();
+        let padding = spec.width.saturating_sub(len);
+        if spec.zero && number.is_some() {
+            // Zeros go between the sign and the digits
+            let (sign, digits) = match text.strip_prefix('-') {
+                Some(digits) => ("-", digits),
+                None => ("", text.as_str()),
+            };
+            out.push_str(sign);
+            out.extend(iter::repeat_n('0', padding));
+            out.push_str(digits);
+            return;
+        }
+
+        let align = match spec.align {
+            Some(align) => align,
+            None if number.is_some() => Align::Right,
+            None => Align::Left,
+        };
+        let (before, after) = match align {
+            Align::Left => (0, padding),
+            Align::Center => (padding / 2, padding - padding / 2),
+            Align::Right => (padding, 0),
+        };
+        out.extend(iter::repeat_n(spec.fill, before));
+        out.push_str(&text);
+        out.extend(iter::repeat_n(spec.fill, after));
+    }
+}
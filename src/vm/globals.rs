@@ -0,0 +1,77 @@
+use crate::value::Value;
+use std::{collections::HashMap, rc::Rc};
+
+/// An interned global name. Ids are only meaningful for the [`Globals`] that
+/// created them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NameId(u32);
+
+/// The `Globals` table stores the value of every global variable. Names are
+/// interned the first time they are seen, so each one is only hashed to find
+/// its id, and values are then stored in a flat list indexed by that id.
+#[derive(Debug, Clone, Default)]
+pub struct Globals {
+    ids: HashMap<Rc<str>, NameId>,
+    names: Vec<Rc<str>>,
+    values: Vec<Option<Value>>,
+}
+
+impl Globals {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the id of `name`, interning it if it hasn't been seen yet.
+    pub fn intern(&mut self, name: &str) -> NameId {
+        if let Some(id) = self.ids.get(name) {
+            return *id;
+        }
+
+        let id = NameId(self.names.len() as u32);
+        let name: Rc<str> = Rc::from(name);
+        self.ids.insert(name.clone(), id);
+        self.names.push(name);
+        self.values.push(None);
+        id
+    }
+
+    /// Return the name interned as `id`.
+    pub fn name(&self, id: NameId) -> &str {
+        &self.names[id.0 as usize]
+    }
+
+    /// Define the global `id`, replacing any previous value.
+    pub fn define(&mut self, id: NameId, value: Value) {
+        self.values[id.0 as usize] = Some(value);
+    }
+
+    /// Return the value of the global `id`, if it has been defined.
+    pub fn get(&self, id: NameId) -> Option<&Value> {
+        self.values[id.0 as usize].as_ref()
+    }
+
+    /// Assign to an existing global, returning false if it hasn't been
+    /// defined.
+    pub fn set(&mut self, id: NameId, value: Value) -> bool {
+        match &mut self.values[id.0 as usize] {
+            Some(slot) => {
+                *slot = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Look a global up by name.
+    pub fn lookup(&self, name: &str) -> Option<&Value> {
+        self.ids.get(name).and_then(|id| self.get(*id))
+    }
+
+    /// Iterate over every defined global and its value.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.names
+            .iter()
+            .zip(&self.values)
+            .filter_map(|(name, value)| Some((&**name, value.as_ref()?)))
+    }
+}
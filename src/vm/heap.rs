@@ -0,0 +1,554 @@
+//! Objects that can refer to other values, such as lists, live on the
+//! [`Heap`] and are referred to through [`ObjRef`] handles. Since objects can
+//! form cycles, they aren't reference counted, and are instead freed by a
+//! mark-sweep garbage collector.
+//!
+//! Collection starts from the roots given by the VM: every value on the stack
+//! and in a global. Reachable objects are marked, tracing through their
+//! contents with an explicit worklist, and every object left unmarked is then
+//! freed. Freed slots are reused by later allocations.
+//!
+//! Strings, functions and classes are immutable, so they can't form cycles,
+//! and stay reference counted.
+
+use super::{
+    host::{HostClass, HostData, HostFunction},
+    native::Native,
+    Backend,
+};
+use crate::{bytecode::Function, value::Value};
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    rc::Rc,
+};
+use tracing::debug;
+
+/// The deepest [`Heap::display`] writes objects nested in each other, so that
+/// formatting can't overflow the stack. Deeper objects are left out.
+pub const MAX_DISPLAY_DEPTH: usize = 256;
+
+/// A handle to an object on the [`Heap`]. Handles are only meaningful for the
+/// heap that created them, and only until the object is collected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObjRef(u32);
+
+impl ObjRef {
+    /// Return the index of the object's slot in the heap.
+    pub(super) fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    pub(super) fn from_index(index: usize) -> Self {
+        Self(index as u32)
+    }
+}
+
+/// A class created by a `class` declaration, or registered by the host.
+/// Classes can't be changed once they are created, so they are shared by
+/// their instances.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Class {
+    pub name: String,
+    pub methods: HashMap<String, Rc<Function>>,
+    /// How instances of a class registered with
+    /// [`Vm::register_class`](super::Vm::register_class) are created, and
+    /// its fields and methods written in Rust.
+    pub host: Option<Rc<HostClass>>,
+}
+
+impl Class {
+    /// Return the class's `init` method, which is called with the arguments
+    /// given when creating an instance.
+    pub fn initializer(&self) -> Option<&Rc<Function>> {
+        self.methods.get("init")
+    }
+}
+
+/// An instance of a class, with its own set of fields.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instance {
+    pub class: Rc<Class>,
+    pub fields: HashMap<Rc<str>, Value>,
+    /// The Rust value wrapped by an instance of a registered class.
+    pub data: Option<HostData>,
+}
+
+/// A method read from a field of a value, which remembers the value so it
+/// can be passed as `self` when the method is called.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoundMethod {
+    pub receiver: Value,
+    pub method: Method,
+}
+
+/// The function a [`BoundMethod`] calls.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Method {
+    /// A method declared in a class.
+    Function(Rc<Function>),
+    /// A method of a string, which is built into the VM.
+    Native(Native),
+    /// A method of a class registered by the host.
+    Host(Rc<HostFunction>),
+}
+
+impl Method {
+    pub fn name(&self) -> &str {
+        match self {
+            Method::Function(function) => &function.name,
+            Method::Native(native) => native.name(),
+            Method::Host(host) => &host.name,
+        }
+    }
+}
+
+/// A call of a generator function, which runs a step at a time as values
+/// are taken from it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Generator {
+    pub function: Rc<Function>,
+    /// The backend the generator was created on. Suspended frames are laid
+    /// out differently by each backend, so it can't be resumed on another.
+    pub backend: Backend,
+    pub state: GeneratorState,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeneratorState {
+    /// Waiting to continue from `ip`, with `slots` as its frame's stack
+    /// slots. A generator that hasn't started yet has its callee and
+    /// arguments as its slots.
+    Suspended { ip: usize, slots: Vec<Value> },
+    /// Running in a call frame, which holds its slots on the stack.
+    Running,
+    /// Finished by returning.
+    Done,
+}
+
+/// A heap allocated object.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Object {
+    List(Vec<Value>),
+    Instance(Instance),
+    BoundMethod(BoundMethod),
+    /// The values sent to a channel that haven't been received yet, oldest
+    /// first.
+    Channel(VecDeque<Value>),
+    Generator(Generator),
+}
+
+impl Object {
+    /// Call `f` with every value the object refers to.
+    pub(super) fn trace(&self, mut f: impl FnMut(&Value)) {
+        match self {
+            Object::List(items) => items.iter().for_each(&mut f),
+            Object::Instance(instance) => instance.fields.values().for_each(&mut f),
+            Object::BoundMethod(bound) => f(&bound.receiver),
+            Object::Channel(queue) => queue.iter().for_each(&mut f),
+            Object::Generator(generator) => {
+                if let GeneratorState::Suspended { slots, .. } = &generator.state {
+                    slots.iter().for_each(&mut f);
+                }
+            }
+        }
+    }
+}
+
+/// Settings for when the garbage collector runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GcConfig {
+    /// The number of live objects at which the first collection happens.
+    /// After each collection, the threshold is set to twice the number of
+    /// objects that survived it, but never below this.
+    pub threshold: usize,
+    /// Collect at every allocation, to find objects that the VM forgot to
+    /// root. This is very slow, and only meant for testing.
+    pub stress: bool,
+}
+
+impl Default for GcConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 1024,
+            stress: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Slot {
+    object: Object,
+    marked: bool,
+}
+
+/// The `Heap` owns every object allocated by a program.
+#[derive(Debug, Clone)]
+pub struct Heap {
+    slots: Vec<Option<Slot>>,
+    /// Indices of freed slots, reused before the heap grows.
+    free: Vec<u32>,
+    /// Objects reached during marking whose contents haven't been traced.
+    gray: Vec<ObjRef>,
+    live: usize,
+    /// The most objects that have been live at once.
+    peak: usize,
+    next_gc: usize,
+    collections: usize,
+    config: GcConfig,
+}
+
+impl Heap {
+    pub fn new(config: GcConfig) -> Self {
+        Self {
+            slots: Vec::new(),
+            free: Vec::new(),
+            gray: Vec::new(),
+            live: 0,
+            peak: 0,
+            next_gc: config.threshold,
+            collections: 0,
+            config,
+        }
+    }
+
+    /// Create a heap holding `objects`, so that the object at each index is
+    /// referred to by [`ObjRef::from_index`] of that index.
+    pub(super) fn from_objects(config: GcConfig, objects: Vec<Object>) -> Self {
+        let live = objects.len();
+        let slots = objects
+            .into_iter()
+            .map(|object| {
+                Some(Slot {
+                    object,
+                    marked: false,
+                })
+            })
+            .collect();
+        Self {
+            slots,
+            live,
+            peak: live,
+            next_gc: (live * 2).max(config.threshold),
+            ..Self::new(config)
+        }
+    }
+
+    /// Move `object` onto the heap. This never collects, so callers should
+    /// check [`Heap::should_collect`] first, while every value they still
+    /// need is rooted.
+    pub fn alloc(&mut self, object: Object) -> ObjRef {
+        self.live += 1;
+        self.peak = self.peak.max(self.live);
+        let slot = Some(Slot {
+            object,
+            marked: false,
+        });
+
+        match self.free.pop() {
+            Some(index) => {
+                self.slots[index as usize] = slot;
+                ObjRef(index)
+            }
+            None => {
+                self.slots.push(slot);
+                ObjRef(self.slots.len() as u32 - 1)
+            }
+        }
+    }
+
+    /// Return the object behind `obj`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the object has been collected.
+    pub fn get(&self, obj: ObjRef) -> &Object {
+        match &self.slots[obj.0 as usize] {
+            Some(slot) => &slot.object,
+            None => panic!("use of collected object {:?}", obj),
+        }
+    }
+
+    /// Return a mutable reference to the object behind `obj`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the object has been collected.
+    pub fn get_mut(&mut self, obj: ObjRef) -> &mut Object {
+        match &mut self.slots[obj.0 as usize] {
+            Some(slot) => &mut slot.object,
+            None => panic!("use of collected object {:?}", obj),
+        }
+    }
+
+    /// Return the items of the list behind `obj`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the object isn't a list.
+    pub fn list(&self, obj: ObjRef) -> &Vec<Value> {
+        match self.get(obj) {
+            Object::List(items) => items,
+            object => panic!("expected a list, found {:?}", object),
+        }
+    }
+
+    pub fn list_mut(&mut self, obj: ObjRef) -> &mut Vec<Value> {
+        match self.get_mut(obj) {
+            Object::List(items) => items,
+            object => panic!("expected a list, found {:?}", object),
+        }
+    }
+
+    /// Return the instance behind `obj`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the object isn't an instance.
+    pub fn instance(&self, obj: ObjRef) -> &Instance {
+        match self.get(obj) {
+            Object::Instance(instance) => instance,
+            object => panic!("expected an instance, found {:?}", object),
+        }
+    }
+
+    pub fn instance_mut(&mut self, obj: ObjRef) -> &mut Instance {
+        match self.get_mut(obj) {
+            Object::Instance(instance) => instance,
+            object => panic!("expected an instance, found {:?}", object),
+        }
+    }
+
+    /// Return the bound method behind `obj`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the object isn't a bound method.
+    pub fn bound_method(&self, obj: ObjRef) -> &BoundMethod {
+        match self.get(obj) {
+            Object::BoundMethod(bound) => bound,
+            object => panic!("expected a bound method, found {:?}", object),
+        }
+    }
+
+    /// Return the queue of the channel behind `obj`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the object isn't a channel.
+    pub fn channel_mut(&mut self, obj: ObjRef) -> &mut VecDeque<Value> {
+        match self.get_mut(obj) {
+            Object::Channel(queue) => queue,
+            object => panic!("expected a channel, found {:?}", object),
+        }
+    }
+
+    /// Return the generator behind `obj`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the object isn't a generator.
+    pub fn generator_mut(&mut self, obj: ObjRef) -> &mut Generator {
+        match self.get_mut(obj) {
+            Object::Generator(generator) => generator,
+            object => panic!("expected a generator, found {:?}", object),
+        }
+    }
+
+    /// Format `value` the way `print` shows it, including the contents of
+    /// the objects it refers to. Strings and chars inside lists and fields
+    /// are quoted, and the contents of an object that contains itself are
+    /// left out where it repeats, as in `[...]`. So are the contents of
+    /// objects nested more than [`MAX_DISPLAY_DEPTH`] deep.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use meow::{run, vm::Vm};
+    ///
+    /// let mut vm = Vm::new();
+    /// run(&mut vm, r#"let mut x = [1, "a", 'b', 2]; x[3] = x;"#).unwrap();
+    /// let x = vm.global("x").unwrap();
+    /// assert_eq!(vm.heap().display(x).to_string(), r#"[1, "a", 'b', [...]]"#);
+    /// ```
+    pub fn display<'h>(&'h self, value: &'h Value) -> Printed<'h> {
+        Printed { heap: self, value }
+    }
+
+    /// Write `value` as [`Heap::display`] does. `nested` is true inside an
+    /// object, and `open` holds the objects being written around it.
+    fn write(
+        &self,
+        f: &mut fmt::Formatter,
+        value: &Value,
+        nested: bool,
+        open: &mut Vec<ObjRef>,
+    ) -> fmt::Result {
+        let obj = match value {
+            Value::Str(value) if nested => return write!(f, "{:?}", value),
+            Value::Char(value) if nested => return write!(f, "{:?}", value),
+            Value::List(obj) | Value::Instance(obj) => *obj,
+            Value::BoundMethod(obj) => {
+                return write!(f, "<method {}>", self.bound_method(*obj).method.name())
+            }
+            Value::Generator(obj) => match self.get(*obj) {
+                Object::Generator(generator) => {
+                    return write!(f, "<generator {}>", generator.function.name)
+                }
+                object => panic!("expected a generator, found {:?}", object),
+            },
+            value => return write!(f, "{}", value),
+        };
+        let object = self.get(obj);
+        if open.len() == MAX_DISPLAY_DEPTH || open.contains(&obj) {
+            return match object {
+                Object::Instance(instance) => write!(f, "{} {{ ... }}", instance.class.name),
+                _ => write!(f, "[...]"),
+            };
+        }
+
+        open.push(obj);
+        match object {
+            Object::List(items) => {
+                write!(f, "[")?;
+                for (index, item) in items.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    self.write(f, item, true, open)?;
+                }
+                write!(f, "]")?;
+            }
+            Object::Instance(instance) => {
+                // Fields are sorted, so the output doesn't depend on the
+                // order of the hash map
+                let mut fields: Vec<_> = instance.fields.iter().collect();
+                fields.sort_by(|a, b| a.0.cmp(b.0));
+                write!(f, "{} {{", instance.class.name)?;
+                for (index, (name, value)) in fields.into_iter().enumerate() {
+                    write!(f, "{} {}: ", if index > 0 { "," } else { "" }, name)?;
+                    self.write(f, value, true, open)?;
+                }
+                write!(f, "{}}}", if instance.fields.is_empty() { "" } else { " " })?;
+            }
+            object => panic!("expected a list or an instance, found {:?}", object),
+        }
+        open.pop();
+        Ok(())
+    }
+
+    /// Iterate over every live object and its handle.
+    pub fn objects(&self) -> impl Iterator<Item = (ObjRef, &Object)> {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            let slot = slot.as_ref()?;
+            Some((ObjRef(index as u32), &slot.object))
+        })
+    }
+
+    /// Return the number of live objects.
+    pub fn len(&self) -> usize {
+        self.live
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.live == 0
+    }
+
+    /// Return the most objects that have been live at once.
+    pub fn peak_len(&self) -> usize {
+        self.peak
+    }
+
+    /// Return the number of collections that have run.
+    pub fn collections(&self) -> usize {
+        self.collections
+    }
+
+    pub fn config(&self) -> GcConfig {
+        self.config
+    }
+
+    /// Returns true if a collection should run before the next allocation.
+    pub fn should_collect(&self) -> bool {
+        self.config.stress || self.live >= self.next_gc
+    }
+
+    /// Free every object that isn't reachable from `roots`.
+    pub fn collect<'a>(&mut self, roots: impl IntoIterator<Item = &'a Value>) {
+        for root in roots {
+            self.mark(root);
+        }
+        self.trace();
+        let before = self.live;
+        self.sweep();
+
+        self.collections += 1;
+        self.next_gc = (self.live * 2).max(self.config.threshold);
+        debug!(
+            freed = before - self.live,
+            live = self.live,
+            next = self.next_gc,
+            "collected garbage"
+        );
+    }
+
+    fn mark(&mut self, value: &Value) {
+        let obj = match value {
+            Value::List(obj)
+            | Value::Instance(obj)
+            | Value::BoundMethod(obj)
+            | Value::Channel(obj)
+            | Value::Generator(obj) => *obj,
+            _ => return,
+        };
+        if let Some(slot) = &mut self.slots[obj.0 as usize] {
+            if !slot.marked {
+                slot.marked = true;
+                self.gray.push(obj);
+            }
+        }
+    }
+
+    fn trace(&mut self) {
+        let mut children = Vec::new();
+        while let Some(obj) = self.gray.pop() {
+            self.get(obj).trace(|value| children.push(value.clone()));
+            for child in children.drain(..) {
+                self.mark(&child);
+            }
+        }
+    }
+
+    fn sweep(&mut self) {
+        for (index, slot) in self.slots.iter_mut().enumerate() {
+            match slot {
+                Some(Slot { marked, .. }) if *marked => *marked = false,
+                Some(_) => {
+                    *slot = None;
+                    self.free.push(index as u32);
+                    self.live -= 1;
+                }
+                None => {}
+            }
+        }
+    }
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self::new(GcConfig::default())
+    }
+}
+
+/// A value formatted with the heap its objects live on, created by
+/// [`Heap::display`].
+pub struct Printed<'h> {
+    heap: &'h Heap,
+    value: &'h Value,
+}
+
+impl fmt::Display for Printed<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.heap.write(f, self.value, false, &mut Vec::new())
+    }
+}
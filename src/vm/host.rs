@@ -0,0 +1,600 @@
+//! Functions and types written in Rust that programs can use, so that hosts
+//! can give scripts capabilities of their own. A closure is registered as a
+//! global with [`Vm::register_fn`], and a type as a class with
+//! [`Vm::register_class`]. Arguments and results are converted with the
+//! traits in [`convert`](super::convert).
+//!
+//! Closures and methods can take up to eight arguments. Calls with the wrong
+//! number of arguments, or with arguments that can't be converted, fail
+//! before the closure runs. If it returns a [`Result`], an error fails the
+//! program with the error's message.
+//!
+//! The garbage collector only sees the values held by programs, so lists and
+//! instances the host keeps in Rust can be freed under it. A [`Handle`]
+//! keeps a value alive for as long as the host holds it.
+
+use super::{
+    convert::{FromMeow, IntoMeow},
+    heap::{Class, Instance, Object},
+    RunResult, Vm,
+};
+use crate::{errors::RuntimeError, value::Value};
+use std::{any::Any, cell::RefCell, collections::HashMap, fmt, marker::PhantomData, rc::Rc};
+
+/// A closure taking converted arguments, erased so that closures of any
+/// type can be stored the same way. Methods are given their receiver first.
+type Call = Box<dyn Fn(&mut Vm, &[Value]) -> RunResult<Value>>;
+
+/// A Rust function registered with [`Vm::register_fn`], or a method of a
+/// class registered with [`Vm::register_class`].
+pub struct HostFunction {
+    pub name: String,
+    /// The number of arguments the function takes, not counting the
+    /// receiver of a method.
+    pub arity: u8,
+    call: Call,
+}
+
+impl HostFunction {
+    /// Call the function with `args`, which there must be
+    /// [`arity`](HostFunction::arity) of after the receiver of a method.
+    pub(super) fn call(&self, vm: &mut Vm, args: &[Value]) -> RunResult<Value> {
+        (self.call)(vm, args)
+    }
+}
+
+impl fmt::Debug for HostFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HostFunction")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Host functions are only equal to themselves, since closures can't be
+/// compared.
+impl PartialEq for HostFunction {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+/// What a host function can return: a value that converts into a [`Value`],
+/// or a [`Result`] of one, whose error fails the program.
+pub trait HostResult {
+    fn into_result(self, vm: &mut Vm) -> Result<Value, String>;
+}
+
+impl<T: IntoMeow> HostResult for T {
+    fn into_result(self, vm: &mut Vm) -> Result<Value, String> {
+        Ok(self.into_meow(vm))
+    }
+}
+
+impl<T: IntoMeow, E: fmt::Display> HostResult for Result<T, E> {
+    fn into_result(self, vm: &mut Vm) -> Result<Value, String> {
+        self.map(|value| value.into_meow(vm))
+            .map_err(|error| error.to_string())
+    }
+}
+
+/// A Rust closure that can be called by programs, taking arguments of the
+/// types in the tuple `Args` and returning an `R`.
+pub trait IntoHostFunction<Args, R> {
+    /// The number of arguments the closure takes.
+    const ARITY: u8;
+
+    /// Convert `args` and call the closure with them, naming the function
+    /// `name` in errors.
+    fn call_with(&self, vm: &Vm, name: &str, args: &[Value]) -> RunResult<R>;
+}
+
+/// A Rust closure that can be called as a method of a class wrapping a `T`,
+/// taking the `T` and then arguments of the types in the tuple `Args`.
+pub trait IntoHostMethod<T, Args, R> {
+    /// The number of arguments the closure takes after the receiver.
+    const ARITY: u8;
+
+    /// Convert `args` and call the closure with `receiver` and them, naming
+    /// the method `name` in errors.
+    fn call_with(&self, vm: &Vm, name: &str, receiver: &mut T, args: &[Value]) -> RunResult<R>;
+}
+
+/// Convert the argument at `index` of a call to `name`.
+fn argument<T: FromMeow>(vm: &Vm, name: &str, args: &[Value], index: usize) -> RunResult<T> {
+    T::from_meow(&args[index], vm).map_err(|error| {
+        vm.error(format!(
+            "invalid argument {} to `{}`: {}",
+            index + 1,
+            name,
+            error
+        ))
+    })
+}
+
+macro_rules! into_host_function {
+    ($($arg:ident $index:tt),*) => {
+        impl<F, R, $($arg),*> IntoHostFunction<($($arg,)*), R> for F
+        where
+            F: Fn($($arg),*) -> R,
+            $($arg: FromMeow,)*
+        {
+            const ARITY: u8 = {
+                let args: &[usize] = &[$($index),*];
+                args.len() as u8
+            };
+
+            #[allow(unused_variables)]
+            fn call_with(&self, vm: &Vm, name: &str, args: &[Value]) -> RunResult<R> {
+                Ok(self($(argument::<$arg>(vm, name, args, $index)?),*))
+            }
+        }
+
+        impl<F, T, R, $($arg),*> IntoHostMethod<T, ($($arg,)*), R> for F
+        where
+            F: Fn(&mut T, $($arg),*) -> R,
+            $($arg: FromMeow,)*
+        {
+            const ARITY: u8 = {
+                let args: &[usize] = &[$($index),*];
+                args.len() as u8
+            };
+
+            #[allow(unused_variables)]
+            fn call_with(
+                &self,
+                vm: &Vm,
+                name: &str,
+                receiver: &mut T,
+                args: &[Value],
+            ) -> RunResult<R> {
+                Ok(self(receiver, $(argument::<$arg>(vm, name, args, $index)?),*))
+            }
+        }
+    };
+}
+
+into_host_function!();
+into_host_function!(A 0);
+into_host_function!(A 0, B 1);
+into_host_function!(A 0, B 1, C 2);
+into_host_function!(A 0, B 1, C 2, D 3);
+into_host_function!(A 0, B 1, C 2, D 3, E 4);
+into_host_function!(A 0, B 1, C 2, D 3, E 4, G 5);
+into_host_function!(A 0, B 1, C 2, D 3, E 4, G 5, H 6);
+into_host_function!(A 0, B 1, C 2, D 3, E 4, G 5, H 6, I 7);
+
+/// The Rust value wrapped by an instance of a host class, shared by every
+/// copy of the instance.
+#[derive(Clone)]
+pub struct HostData(Rc<RefCell<dyn Any>>);
+
+impl fmt::Debug for HostData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("HostData")
+    }
+}
+
+impl PartialEq for HostData {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+type Constructor = Box<dyn Fn(&mut Vm, &[Value]) -> RunResult<HostData>>;
+type Getter = Box<dyn Fn(&mut Vm, &dyn Any) -> Value>;
+type Setter = Box<dyn Fn(&Vm, &mut dyn Any, &Value) -> RunResult<()>>;
+
+/// The Rust side of a class registered with [`Vm::register_class`]: how its
+/// instances are created, and the fields and methods that reach the value
+/// they wrap.
+pub struct HostClass {
+    constructor: Option<(u8, Constructor)>,
+    getters: HashMap<String, Getter>,
+    setters: HashMap<String, Setter>,
+    methods: HashMap<String, Rc<HostFunction>>,
+}
+
+impl fmt::Debug for HostClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HostClass")
+            .field("methods", &self.methods)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Host classes are only equal to themselves, since closures can't be
+/// compared.
+impl PartialEq for HostClass {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+/// A description of a Rust type `T` to register as a class with
+/// [`Vm::register_class`].
+pub struct ClassBuilder<T> {
+    name: String,
+    class: HostClass,
+    wraps: PhantomData<T>,
+}
+
+impl<T: 'static> ClassBuilder<T> {
+    /// Describe the class `name`. Unless it is given a
+    /// [constructor](ClassBuilder::constructor), programs can only use the
+    /// instances the host creates with [`Vm::host_instance`].
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            class: HostClass {
+                constructor: None,
+                getters: HashMap::new(),
+                setters: HashMap::new(),
+                methods: HashMap::new(),
+            },
+            wraps: PhantomData,
+        }
+    }
+
+    /// Create instances when the class is called, wrapping the value
+    /// `function` returns for the arguments of the call.
+    pub fn constructor<Args, F>(mut self, function: F) -> Self
+    where
+        F: IntoHostFunction<Args, T> + 'static,
+    {
+        let name = self.name.clone();
+        let constructor: Constructor = Box::new(move |vm, args| {
+            let value = function.call_with(vm, &name, args)?;
+            Ok(HostData(Rc::new(RefCell::new(value))))
+        });
+        self.class.constructor = Some((F::ARITY, constructor));
+        self
+    }
+
+    /// Add the field `name`, whose value is read with `get`. It can only be
+    /// assigned to if it is given a [setter](ClassBuilder::setter) too.
+    pub fn getter<R, F>(mut self, name: &str, get: F) -> Self
+    where
+        R: IntoMeow,
+        F: Fn(&T) -> R + 'static,
+    {
+        self.class.getters.insert(
+            name.to_string(),
+            Box::new(move |vm, value| get(downcast_ref(value)).into_meow(vm)),
+        );
+        self
+    }
+
+    /// Let programs assign to the field `name`, calling `set` with the
+    /// assigned value.
+    pub fn setter<V, F>(mut self, name: &str, set: F) -> Self
+    where
+        V: FromMeow,
+        F: Fn(&mut T, V) + 'static,
+    {
+        let field = name.to_string();
+        let class = self.name.clone();
+        self.class.setters.insert(
+            name.to_string(),
+            Box::new(move |vm, value, assigned| {
+                let assigned = V::from_meow(assigned, vm).map_err(|error| {
+                    vm.error(format!(
+                        "invalid value for field `{}` of `{}`: {}",
+                        field, class, error
+                    ))
+                })?;
+                set(downcast_mut(value), assigned);
+                Ok(())
+            }),
+        );
+        self
+    }
+
+    /// Add the method `name`, which calls `function` with the value the
+    /// instance wraps and the arguments of the call.
+    pub fn method<Args, R, F>(mut self, name: &str, function: F) -> Self
+    where
+        R: HostResult,
+        F: IntoHostMethod<T, Args, R> + 'static,
+    {
+        let qualified = format!("{}.{}", self.name, name);
+        let method = HostFunction {
+            name: qualified.clone(),
+            arity: F::ARITY,
+            call: Box::new(move |vm, args| {
+                let data = vm
+                    .host_data(&args[0])
+                    .expect("host method bound to a value it doesn't belong to");
+                let result = {
+                    let mut receiver = data.0.try_borrow_mut().map_err(|_| {
+                        vm.error(format!(
+                            "`{}` called while its receiver is in use",
+                            qualified
+                        ))
+                    })?;
+                    function.call_with(vm, &qualified, downcast_mut(&mut *receiver), &args[1..])?
+                };
+                result.into_result(vm).map_err(|message| vm.error(message))
+            }),
+        };
+        self.class.methods.insert(name.to_string(), Rc::new(method));
+        self
+    }
+}
+
+fn downcast_ref<T: 'static>(value: &dyn Any) -> &T {
+    value
+        .downcast_ref()
+        .expect("host class instance wraps a value of another type")
+}
+
+fn downcast_mut<T: 'static>(value: &mut dyn Any) -> &mut T {
+    value
+        .downcast_mut()
+        .expect("host class instance wraps a value of another type")
+}
+
+/// A value kept alive by the garbage collector for as long as the host
+/// holds it, created with [`Vm::handle`].
+#[derive(Debug)]
+pub struct Handle {
+    value: Value,
+    id: u64,
+    roots: Rc<RefCell<Roots>>,
+}
+
+impl Handle {
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        self.roots.borrow_mut().values.remove(&self.id);
+    }
+}
+
+/// The values held by [`Handle`]s, which the garbage collector treats as
+/// roots.
+#[derive(Debug, Default)]
+pub(super) struct Roots {
+    pub(super) values: HashMap<u64, Value>,
+    next: u64,
+}
+
+impl Vm {
+    /// Define the global function `name`, which calls `function` with its
+    /// arguments converted to the types it takes, and converts its result
+    /// back. See the [`host`](self) module.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use meow::{compile, value::Value, vm::Vm};
+    ///
+    /// let mut vm = Vm::new();
+    /// vm.register_fn("shout", |word: String, times: i64| {
+    ///     word.to_uppercase().repeat(times as usize)
+    /// });
+    /// vm.run(compile("let s = shout(\"meow\", 2);").unwrap()).unwrap();
+    /// assert_eq!(vm.global("s"), Some(&Value::from("MEOWMEOW")));
+    ///
+    /// let error = vm.run(compile("shout(1, 2);").unwrap()).unwrap_err();
+    /// assert_eq!(
+    ///     error.message,
+    ///     "invalid argument 1 to `shout`: expected string, found int"
+    /// );
+    /// ```
+    pub fn register_fn<Args, R, F>(&mut self, name: &str, function: F)
+    where
+        R: HostResult,
+        F: IntoHostFunction<Args, R> + 'static,
+    {
+        let host_name = name.to_string();
+        let host = Rc::new(HostFunction {
+            name: name.to_string(),
+            arity: F::ARITY,
+            call: Box::new(move |vm, args| {
+                let result = function.call_with(vm, &host_name, args)?;
+                result.into_result(vm).map_err(|message| vm.error(message))
+            }),
+        });
+        self.hosts.insert(name.to_string(), host.clone());
+        self.set_global(name, Value::Host(host));
+    }
+
+    /// Define the global class described by `builder`, whose instances wrap
+    /// a Rust value. Besides the fields and methods the builder adds,
+    /// programs can give its instances fields of their own.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use meow::{compile, value::Value, vm::{host::ClassBuilder, Vm}};
+    ///
+    /// struct Counter {
+    ///     count: i64,
+    /// }
+    ///
+    /// let mut vm = Vm::new();
+    /// vm.register_class(
+    ///     ClassBuilder::new("Counter")
+    ///         .constructor(|count: i64| Counter { count })
+    ///         .getter("count", |counter: &Counter| counter.count)
+    ///         .method("add", |counter: &mut Counter, n: i64| counter.count += n),
+    /// );
+    /// let program = compile("let c = Counter(1); c.add(2); let n = c.count;").unwrap();
+    /// vm.run(program).unwrap();
+    /// assert_eq!(vm.global("n"), Some(&Value::Int(3)));
+    ///
+    /// let c = vm.global("c").unwrap().clone();
+    /// assert_eq!(vm.with_host_data(&c, |counter: &mut Counter| counter.count), Some(3));
+    /// ```
+    pub fn register_class<T: 'static>(&mut self, builder: ClassBuilder<T>) {
+        let class = Rc::new(Class {
+            name: builder.name.clone(),
+            methods: HashMap::new(),
+            host: Some(Rc::new(builder.class)),
+        });
+        self.host_classes
+            .insert(builder.name.clone(), class.clone());
+        self.set_global(&builder.name, Value::Class(class));
+    }
+
+    /// Create an instance of the registered class `class` wrapping `value`,
+    /// or return `None` if there is no such class.
+    ///
+    /// The instance is only kept alive once it is stored in a global, passed
+    /// to the program or held by a [`Handle`].
+    pub fn host_instance<T: 'static>(&mut self, class: &str, value: T) -> Option<Value> {
+        let class = self.host_classes.get(class)?.clone();
+        let instance = self.alloc(Object::Instance(Instance {
+            class,
+            fields: HashMap::new(),
+            data: Some(HostData(Rc::new(RefCell::new(value)))),
+        }));
+        Some(Value::Instance(instance))
+    }
+
+    /// Call `f` with the Rust value wrapped by `value`, if it is an instance
+    /// of a registered class wrapping a `T`.
+    pub fn with_host_data<T: 'static, R>(
+        &self,
+        value: &Value,
+        f: impl FnOnce(&mut T) -> R,
+    ) -> Option<R> {
+        let data = self.host_data(value)?;
+        let mut data = data.0.try_borrow_mut().ok()?;
+        data.downcast_mut().map(f)
+    }
+
+    /// Keep `value` alive until the returned handle is dropped, so that the
+    /// host can hold on to lists and instances between runs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use meow::{compile, value::Value, vm::Vm};
+    ///
+    /// let mut vm = Vm::new();
+    /// let list = vm.to_value(vec![1, 2]);
+    /// let handle = vm.handle(list);
+    /// vm.collect_garbage();
+    /// assert_eq!(vm.heap().display(handle.value()).to_string(), "[1, 2]");
+    /// ```
+    pub fn handle(&mut self, value: Value) -> Handle {
+        let mut roots = self.roots.borrow_mut();
+        let id = roots.next;
+        roots.next += 1;
+        roots.values.insert(id, value.clone());
+        Handle {
+            value,
+            id,
+            roots: self.roots.clone(),
+        }
+    }
+
+    /// Return the Rust value wrapped by `value`, if it is an instance of a
+    /// registered class.
+    fn host_data(&self, value: &Value) -> Option<HostData> {
+        match value {
+            Value::Instance(obj) => self.heap.instance(*obj).data.clone(),
+            _ => None,
+        }
+    }
+
+    /// Call `host` with the `argc` arguments following stack slot `base`,
+    /// leaving the result in `base`. If `host` is a method, the slot holds
+    /// its receiver.
+    pub(super) fn call_host(
+        &mut self,
+        host: &HostFunction,
+        base: usize,
+        argc: u8,
+        method: bool,
+    ) -> Result<(), RuntimeError> {
+        if host.arity != argc {
+            return Err(self.arity_mismatch(&host.name, host.arity, argc));
+        }
+        let start = if method { base } else { base + 1 };
+        let args = self.stack[start..=base + argc as usize].to_vec();
+        self.stack[base] = host.call(self, &args)?;
+        Ok(())
+    }
+
+    /// Create an instance of the registered class `class` with the `argc`
+    /// arguments following stack slot `base`, leaving it in `base`.
+    pub(super) fn construct_host(
+        &mut self,
+        class: Rc<Class>,
+        host: &HostClass,
+        base: usize,
+        argc: u8,
+    ) -> Result<(), RuntimeError> {
+        let (arity, constructor) = host.constructor.as_ref().ok_or_else(|| {
+            self.error(format!(
+                "`{}` instances can only be created by the host",
+                class.name
+            ))
+        })?;
+        if *arity != argc {
+            return Err(self.arity_mismatch(&class.name, *arity, argc));
+        }
+        let args = self.stack[base + 1..=base + argc as usize].to_vec();
+        let data = constructor(self, &args)?;
+        // The arguments are still on the stack, so they survive a collection
+        self.maybe_collect();
+        let instance = self.alloc(Object::Instance(Instance {
+            class,
+            fields: HashMap::new(),
+            data: Some(data),
+        }));
+        self.stack[base] = Value::Instance(instance);
+        Ok(())
+    }
+
+    /// Read the field `name` of `target`, an instance of a registered class,
+    /// returning its value or the method to bind. Returns `None` if the
+    /// class has neither.
+    pub(super) fn host_field(
+        &mut self,
+        target: &Value,
+        host: &HostClass,
+        name: &str,
+    ) -> Option<Result<Value, Rc<HostFunction>>> {
+        if let Some(get) = host.getters.get(name) {
+            let data = self.host_data(target)?;
+            let data = data.0.try_borrow().ok()?;
+            return Some(Ok(get(self, &*data)));
+        }
+        host.methods.get(name).cloned().map(Err)
+    }
+
+    /// Assign `value` to the field `name` of `target`, an instance of a
+    /// registered class. Returns `None` if the class doesn't define the
+    /// field, so that it can be stored on the instance instead.
+    pub(super) fn set_host_field(
+        &mut self,
+        target: &Value,
+        host: &HostClass,
+        name: &str,
+        value: &Value,
+    ) -> Option<RunResult<()>> {
+        match host.setters.get(name) {
+            Some(set) => {
+                let data = self.host_data(target)?;
+                let result = match data.0.try_borrow_mut() {
+                    Ok(mut data) => set(self, &mut *data, value),
+                    Err(_) => Err(self.error(format!("field `{}` is in use", name))),
+                };
+                Some(result)
+            }
+            None if host.getters.contains_key(name) || host.methods.contains_key(name) => Some(
+                Err(self.error(format!("field `{}` can't be assigned to", name))),
+            ),
+            None => None,
+        }
+    }
+}
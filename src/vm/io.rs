@@ -0,0 +1,174 @@
+//! Programs print, read lines and use files through a [`MeowIo`], rather
+//! than directly, so that hosts can capture what programs print, feed them
+//! input and decide which files they can use, with
+//! [`Vm::set_io`](super::Vm::set_io).
+//!
+//! By default, the VM uses a [`StdIo`], which prints to standard output and
+//! reads from standard input. Its parts can be replaced one at a time with
+//! [`Vm::set_output`](super::Vm::set_output),
+//! [`Vm::set_input`](super::Vm::set_input) and
+//! [`Vm::set_file_system`](super::Vm::set_file_system). A [`MemoryIo`] keeps
+//! everything in memory instead, for tests.
+
+#[cfg(feature = "fs")]
+use super::files::OsFileSystem;
+use super::files::{FileSystem, MemoryFileSystem, NoFileSystem};
+use std::{
+    cell::RefCell,
+    io::{self, BufRead, Write},
+    rc::Rc,
+};
+
+/// The input and output available to a program.
+pub trait MeowIo {
+    /// Write `text`, which `print` and `println` were called with.
+    fn print(&mut self, text: &str) -> io::Result<()>;
+
+    /// Read the next line for `read_line`, including the line break ending
+    /// it, or return `None` once there is nothing left to read.
+    fn read_line(&mut self) -> io::Result<Option<String>>;
+
+    /// Return the files the `std.fs` module reads and writes.
+    fn files(&mut self) -> &mut dyn FileSystem;
+}
+
+/// Input and output through the streams and file system given to it, which
+/// are those of the process by default. Without the `fs` feature, there are
+/// no files by default.
+pub struct StdIo {
+    /// Where `print` and `println` write to.
+    pub output: Box<dyn Write>,
+    /// Where `read_line` reads from, or `None` for standard input, which is
+    /// only locked while a line is read.
+    pub input: Option<Box<dyn BufRead>>,
+    pub files: Box<dyn FileSystem>,
+}
+
+impl Default for StdIo {
+    fn default() -> Self {
+        Self {
+            output: Box::new(io::stdout()),
+            input: None,
+            #[cfg(feature = "fs")]
+            files: Box::new(OsFileSystem),
+            #[cfg(not(feature = "fs"))]
+            files: Box::new(NoFileSystem),
+        }
+    }
+}
+
+impl MeowIo for StdIo {
+    fn print(&mut self, text: &str) -> io::Result<()> {
+        self.output.write_all(text.as_bytes())?;
+        self.output.flush()
+    }
+
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        let mut line = String::new();
+        let read = match &mut self.input {
+            Some(input) => input.read_line(&mut line)?,
+            None => io::stdin().lock().read_line(&mut line)?,
+        };
+        Ok((read > 0).then_some(line))
+    }
+
+    fn files(&mut self) -> &mut dyn FileSystem {
+        self.files.as_mut()
+    }
+}
+
+/// Input and output kept in memory, for testing programs. Clones share the
+/// same output, input and files, so the host can keep one to look at what
+/// the program did.
+///
+/// # Examples
+///
+/// ```
+/// use meow::{run, value::Value, vm::{io::MemoryIo, Vm}};
+///
+/// let io = MemoryIo::new()
+///     .with_input("Tom\nGinger\n")
+///     .with_file("cats.txt", "Felix");
+/// let mut vm = Vm::new();
+/// vm.set_io(Box::new(io.clone()));
+/// run(
+///     &mut vm,
+///     r#"import std.fs; println(read_line() + ", " + fs.read_to_string("cats.txt"));"#,
+/// )
+/// .unwrap();
+/// assert_eq!(io.output(), "Tom, Felix\n");
+/// assert_eq!(io.remaining_input(), "Ginger\n");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MemoryIo {
+    output: Rc<RefCell<String>>,
+    input: Rc<RefCell<String>>,
+    files: MemoryFileSystem,
+    /// Whether programs are refused access to files.
+    deny_files: bool,
+    no_files: NoFileSystem,
+}
+
+impl MemoryIo {
+    /// Create an I/O with no input and no files.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `input` to what `read_line` reads.
+    pub fn with_input(self, input: &str) -> Self {
+        self.input.borrow_mut().push_str(input);
+        self
+    }
+
+    /// Add a file at `path` holding `contents`.
+    pub fn with_file(self, path: &str, contents: &str) -> Self {
+        self.files.insert(path, contents);
+        self
+    }
+
+    /// Refuse programs access to files, as [`NoFileSystem`] does.
+    pub fn deny_files(mut self) -> Self {
+        self.deny_files = true;
+        self
+    }
+
+    /// Return everything printed so far.
+    pub fn output(&self) -> String {
+        self.output.borrow().clone()
+    }
+
+    /// Return the input that hasn't been read yet.
+    pub fn remaining_input(&self) -> String {
+        self.input.borrow().clone()
+    }
+
+    /// Return the files, including the ones programs wrote.
+    pub fn file_system(&self) -> &MemoryFileSystem {
+        &self.files
+    }
+}
+
+impl MeowIo for MemoryIo {
+    fn print(&mut self, text: &str) -> io::Result<()> {
+        self.output.borrow_mut().push_str(text);
+        Ok(())
+    }
+
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        let mut input = self.input.borrow_mut();
+        if input.is_empty() {
+            return Ok(None);
+        }
+        let end = input.find('\n').map_or(input.len(), |index| index + 1);
+        Ok(Some(input.drain(..end).collect()))
+    }
+
+    fn files(&mut self) -> &mut dyn FileSystem {
+        if self.deny_files {
+            &mut self.no_files
+        } else {
+            &mut self.files
+        }
+    }
+}
@@ -0,0 +1,74 @@
+//! A [`Vm`](super::Vm) and the values in it belong to the thread that
+//! created it, but a [`SharedProgram`] is `Send + Sync`. Servers compile a
+//! program once and share it, and every thread runs it on a VM of its own,
+//! called an isolate, with its own globals and heap. Isolates never lock
+//! each other, since they share nothing but the program's bytecode.
+//!
+//! # Examples
+//!
+//! ```
+//! use meow::{compile, value::Value, vm::{isolate::SharedProgram, Vm}};
+//!
+//! let program = SharedProgram::new(&compile("let total = 6 * 7;").unwrap()).unwrap();
+//! std::thread::scope(|scope| {
+//!     for _ in 0..4 {
+//!         scope.spawn(|| {
+//!             let mut vm = Vm::new();
+//!             vm.run_shared(&program).unwrap();
+//!             assert_eq!(vm.global("total"), Some(&Value::Int(42)));
+//!         });
+//!     }
+//! });
+//! ```
+
+use crate::{
+    bytecode::{serialize, Function},
+    errors::LoadError,
+};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+/// A compiled program that can be sent to and shared between threads. It
+/// holds the program in the [`.mwc`](serialize) format, which each VM
+/// decodes the first time it runs it, so cloning one is cheap.
+#[derive(Debug, Clone)]
+pub struct SharedProgram {
+    /// Tells programs apart in the VMs that decoded them.
+    id: u64,
+    bytes: Arc<[u8]>,
+}
+
+impl SharedProgram {
+    /// Share `script`, the top-level function of a program, failing if it
+    /// isn't one that [`serialize::decode`] accepts.
+    pub fn new(script: &Function) -> Result<Self, LoadError> {
+        Self::from_bytes(&serialize::encode(script))
+    }
+
+    /// Share the program in `bytes`, which are in the `.mwc` format.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, LoadError> {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+        serialize::decode(bytes)?;
+        Ok(Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            bytes: bytes.into(),
+        })
+    }
+
+    /// Return the program in the `.mwc` format.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub(crate) fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Decode the program for a VM to run.
+    pub(crate) fn decode(&self) -> Function {
+        serialize::decode(&self.bytes).expect("shared programs are decoded when they are created")
+    }
+}
@@ -0,0 +1,536 @@
+//! The JIT tier compiles hot functions to native code with Cranelift. It is
+//! only built with the `jit` cargo feature. Every call of a function is
+//! counted, and once a function has been called [`JIT_THRESHOLD`] times, its
+//! bytecode is translated to Cranelift IR and compiled.
+//!
+//! Only functions that work on ints and bools alone are compiled: the type
+//! of every stack slot is inferred from the arguments, which are assumed to
+//! be ints, and a function using any other type or any instruction with a
+//! side effect, such as a call or a global, stays interpreted. Since such a
+//! function can't affect anything but its result, native code that runs
+//! into something it doesn't handle, such as an overflow, a division by
+//! zero or an interrupt, gives up and lets the interpreter run the whole
+//! call again from the start, which then reports the error as usual.
+
+use crate::{
+    bytecode::{
+        verify::{depths, jump_target},
+        Function, OpCode,
+    },
+    value::Value,
+};
+use cranelift_codegen::{
+    entity::EntityRef,
+    ir::{condcodes::IntCC, types, AbiParam, Block, InstBuilder, MemFlags},
+};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, Module};
+use std::{collections::HashMap, mem, rc::Rc, sync::atomic::AtomicBool};
+use tracing::debug;
+
+/// The number of calls after which a function is compiled.
+pub const JIT_THRESHOLD: u32 = 1000;
+
+/// The type of the values in a stack slot, which compiled code keeps in an
+/// `i64`. Unit is 0, and bools are 0 or 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Type {
+    /// The function being called, in the frame's first slot, which can't
+    /// be used.
+    Callee,
+    Unit,
+    Bool,
+    Int,
+}
+
+/// Native code taking a pointer to the arguments, where to write the
+/// result, and the VM's interrupt flag. Returns whether it finished, rather
+/// than giving up.
+type Code = unsafe extern "C" fn(*const i64, *mut i64, *const AtomicBool) -> u8;
+
+struct Compiled {
+    code: Code,
+    result: Type,
+}
+
+enum Entry {
+    /// The number of times the function has been called so far.
+    Counting(u32),
+    Compiled(Compiled),
+    /// The function can't be compiled, so it isn't counted any more.
+    Interpreted,
+}
+
+/// The functions seen by the JIT, and the module holding their native code.
+pub(super) struct Jit {
+    /// Entries are keyed by address, and keep the function alive so that
+    /// the address can't be reused.
+    functions: HashMap<*const Function, (Rc<Function>, Entry)>,
+    /// The module is only created once the first function is compiled.
+    module: Option<JITModule>,
+    threshold: Option<u32>,
+}
+
+impl Default for Jit {
+    fn default() -> Self {
+        Self {
+            functions: HashMap::new(),
+            module: None,
+            threshold: Some(JIT_THRESHOLD),
+        }
+    }
+}
+
+impl Jit {
+    /// Set the number of calls after which functions are compiled, or turn
+    /// the JIT off with `None`.
+    pub(super) fn set_threshold(&mut self, threshold: Option<u32>) {
+        self.threshold = threshold;
+    }
+
+    /// Return the names of the functions that have been compiled.
+    pub(super) fn compiled(&self) -> Vec<&str> {
+        let mut names: Vec<_> = self
+            .functions
+            .values()
+            .filter(|(_, entry)| matches!(entry, Entry::Compiled(_)))
+            .map(|(function, _)| function.name.as_str())
+            .collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Count a call of `function` with `args`, and run it natively if it
+    /// has been compiled. Returns `None` if the interpreter should run the
+    /// call instead.
+    pub(super) fn call(
+        &mut self,
+        function: &Rc<Function>,
+        args: &[Value],
+        interrupt: &AtomicBool,
+    ) -> Option<Value> {
+        let threshold = self.threshold?;
+        let (_, entry) = self
+            .functions
+            .entry(Rc::as_ptr(function))
+            .or_insert_with(|| (function.clone(), Entry::Counting(0)));
+        if let Entry::Counting(calls) = entry {
+            *calls += 1;
+            if *calls < threshold {
+                return None;
+            }
+            *entry = match compile(self.module.get_or_insert_with(module), function) {
+                Some(compiled) => Entry::Compiled(compiled),
+                None => Entry::Interpreted,
+            };
+            debug!(
+                name = function.name,
+                compiled = matches!(entry, Entry::Compiled(_)),
+                "tried to compile a hot function"
+            );
+        }
+
+        let compiled = match entry {
+            Entry::Compiled(compiled) => compiled,
+            _ => return None,
+        };
+        let args = args
+            .iter()
+            .map(|arg| match arg {
+                Value::Int(value) => Some(*value),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()?;
+        let mut result = 0;
+        // Safety: the code was compiled for this function, which reads one
+        // int per argument, and only ever writes its result
+        let finished = unsafe { (compiled.code)(args.as_ptr(), &mut result, interrupt) };
+        if finished == 0 {
+            return None;
+        }
+        Some(match compiled.result {
+            Type::Int => Value::Int(result),
+            Type::Bool => Value::Bool(result != 0),
+            Type::Unit | Type::Callee => Value::Unit,
+        })
+    }
+}
+
+impl Drop for Jit {
+    fn drop(&mut self) {
+        if let Some(module) = self.module.take() {
+            // Safety: the only pointers to the module's code are in
+            // `functions`, which is dropped with it
+            unsafe { module.free_memory() };
+        }
+    }
+}
+
+fn module() -> JITModule {
+    let builder =
+        JITBuilder::new(default_libcall_names()).expect("the host is supported by cranelift");
+    JITModule::new(builder)
+}
+
+/// Infer the types on the stack before each instruction of `function`, and
+/// return the type of its result. Returns `None` if the function uses an
+/// instruction or a type the JIT doesn't handle, or the type of a slot
+/// depends on the path taken to an instruction.
+fn result_type(function: &Function) -> Option<Type> {
+    let chunk = &function.chunk;
+    let mut types: Vec<Option<Vec<Type>>> = vec![None; chunk.code.len()];
+    let mut result = None;
+    let mut params = vec![Type::Callee];
+    params.resize(function.arity as usize + 1, Type::Int);
+    let mut pending = vec![(0, params)];
+
+    while let Some((mut offset, mut stack)) = pending.pop() {
+        loop {
+            match &types[offset] {
+                Some(seen) if *seen == stack => break,
+                Some(_) => return None,
+                None => types[offset] = Some(stack.clone()),
+            }
+
+            let op = OpCode::from_byte(chunk.code[offset])?;
+            let operand = || chunk.code[offset + 1] as usize;
+            match op {
+                OpCode::Constant => match chunk.constants[chunk.read_u16(offset + 1) as usize] {
+                    Value::Int(_) => stack.push(Type::Int),
+                    _ => return None,
+                },
+                OpCode::Unit => stack.push(Type::Unit),
+                OpCode::True | OpCode::False => stack.push(Type::Bool),
+                OpCode::Pop => {
+                    stack.pop();
+                }
+                OpCode::PopN => stack.truncate(stack.len() - operand()),
+                OpCode::Dup => stack.push(*stack.last()?),
+                OpCode::GetLocal => match stack[operand()] {
+                    Type::Callee => return None,
+                    ty => stack.push(ty),
+                },
+                OpCode::SetLocal => stack[operand()] = *stack.last()?,
+                OpCode::Add
+                | OpCode::Subtract
+                | OpCode::Multiply
+                | OpCode::Divide
+                | OpCode::Remainder => {
+                    let right = stack.pop()?;
+                    if (*stack.last()?, right) != (Type::Int, Type::Int) {
+                        return None;
+                    }
+                }
+                OpCode::Negate | OpCode::Not => {
+                    let ty = if op == OpCode::Not {
+                        Type::Bool
+                    } else {
+                        Type::Int
+                    };
+                    if *stack.last()? != ty {
+                        return None;
+                    }
+                }
+                OpCode::Equal | OpCode::NotEqual => {
+                    let right = stack.pop()?;
+                    if stack.pop()? != right || right == Type::Callee {
+                        return None;
+                    }
+                    stack.push(Type::Bool);
+                }
+                OpCode::Greater | OpCode::GreaterEqual | OpCode::Less | OpCode::LessEqual => {
+                    let right = stack.pop()?;
+                    if (stack.pop()?, right) != (Type::Int, Type::Int) {
+                        return None;
+                    }
+                    stack.push(Type::Bool);
+                }
+                OpCode::RangeBounds => {
+                    if stack[stack.len().checked_sub(2)?..] != [Type::Int, Type::Int] {
+                        return None;
+                    }
+                }
+                OpCode::Jump | OpCode::Loop => {
+                    offset = jump_target(chunk, op, offset)?;
+                    continue;
+                }
+                OpCode::JumpIfFalse => {
+                    if *stack.last()? != Type::Bool {
+                        return None;
+                    }
+                    pending.push((jump_target(chunk, op, offset)?, stack.clone()));
+                }
+                OpCode::Return => {
+                    let ty = *stack.last()?;
+                    if result.replace(ty).is_some_and(|result| result != ty) {
+                        return None;
+                    }
+                    break;
+                }
+                _ => return None,
+            }
+            offset += 1 + op.operand_len();
+        }
+    }
+
+    result
+}
+
+/// Compile `function` into `module`, or return `None` if it can't be.
+fn compile(module: &mut JITModule, function: &Function) -> Option<Compiled> {
+    if function.generator || function.chunk.code.is_empty() {
+        return None;
+    }
+    let (depths, slots) = depths(function).ok()?;
+    let result = result_type(function)?;
+    let chunk = &function.chunk;
+
+    let mut context = module.make_context();
+    let pointer = module.target_config().pointer_type();
+    let signature = &mut context.func.signature;
+    signature.params = vec![AbiParam::new(pointer); 3];
+    signature.returns.push(AbiParam::new(types::I8));
+
+    let mut builder_context = FunctionBuilderContext::new();
+    let mut builder = FunctionBuilder::new(&mut context.func, &mut builder_context);
+    let slot = Variable::new;
+    for index in 0..slots {
+        builder.declare_var(slot(index), types::I64);
+    }
+
+    // Every instruction jumped to starts a block
+    let mut blocks: HashMap<usize, Block> = HashMap::new();
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        let op = OpCode::from_byte(chunk.code[offset])?;
+        if depths[offset].is_some() && op.is_jump() {
+            let target = jump_target(chunk, op, offset)?;
+            blocks
+                .entry(target)
+                .or_insert_with(|| builder.create_block());
+            if op == OpCode::JumpIfFalse {
+                blocks
+                    .entry(offset + 3)
+                    .or_insert_with(|| builder.create_block());
+            }
+        }
+        offset += 1 + op.operand_len();
+    }
+
+    let entry = builder.create_block();
+    let give_up = builder.create_block();
+    builder.append_block_params_for_function_params(entry);
+    builder.switch_to_block(entry);
+    let [args, out, interrupt] = builder.block_params(entry) else {
+        unreachable!("the signature has three parameters")
+    };
+    let (args, out, interrupt) = (*args, *out, *interrupt);
+    let zero = builder.ins().iconst(types::I64, 0);
+    for index in 0..slots {
+        let value = if (1..=function.arity as usize).contains(&index) {
+            let offset = 8 * (index as i32 - 1);
+            builder
+                .ins()
+                .load(types::I64, MemFlags::trusted(), args, offset)
+        } else {
+            zero
+        };
+        builder.def_var(slot(index), value);
+    }
+
+    // Whether the current block still needs an instruction ending it
+    let mut open = true;
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        let op = OpCode::from_byte(chunk.code[offset])?;
+        let next = offset + 1 + op.operand_len();
+        let depth = match depths[offset] {
+            Some(depth) => depth as usize,
+            None => {
+                offset = next;
+                continue;
+            }
+        };
+        if let Some(&block) = blocks.get(&offset) {
+            if open {
+                builder.ins().jump(block, &[]);
+            }
+            builder.switch_to_block(block);
+            open = true;
+        }
+
+        let operand = chunk.code.get(offset + 1).copied().unwrap_or(0) as usize;
+        let top = depth.wrapping_sub(1);
+        match op {
+            OpCode::Constant => {
+                let value = match chunk.constants[chunk.read_u16(offset + 1) as usize] {
+                    Value::Int(value) => value,
+                    _ => unreachable!("only int constants pass type inference"),
+                };
+                let value = builder.ins().iconst(types::I64, value);
+                builder.def_var(slot(depth), value);
+            }
+            OpCode::Unit | OpCode::True | OpCode::False => {
+                let value = builder
+                    .ins()
+                    .iconst(types::I64, (op == OpCode::True) as i64);
+                builder.def_var(slot(depth), value);
+            }
+            // Type inference has already checked the bounds are ints
+            OpCode::Pop | OpCode::PopN | OpCode::RangeBounds => {}
+            OpCode::Dup | OpCode::GetLocal => {
+                let src = if op == OpCode::Dup { top } else { operand };
+                let value = builder.use_var(slot(src));
+                builder.def_var(slot(depth), value);
+            }
+            OpCode::SetLocal => {
+                let value = builder.use_var(slot(top));
+                builder.def_var(slot(operand), value);
+            }
+            OpCode::Add
+            | OpCode::Subtract
+            | OpCode::Multiply
+            | OpCode::Divide
+            | OpCode::Remainder => {
+                let a = builder.use_var(slot(top - 1));
+                let b = builder.use_var(slot(top));
+                let value = match op {
+                    // The sum overflowed if its sign differs from both
+                    // operands' signs
+                    OpCode::Add => {
+                        let value = builder.ins().iadd(a, b);
+                        let left = builder.ins().bxor(a, value);
+                        let right = builder.ins().bxor(b, value);
+                        let both = builder.ins().band(left, right);
+                        let failed = builder.ins().icmp_imm(IntCC::SignedLessThan, both, 0);
+                        give_up_if(&mut builder, failed, give_up);
+                        value
+                    }
+                    // The difference overflowed if the operands' signs
+                    // differ, and its sign differs from the left one's
+                    OpCode::Subtract => {
+                        let value = builder.ins().isub(a, b);
+                        let operands = builder.ins().bxor(a, b);
+                        let left = builder.ins().bxor(a, value);
+                        let both = builder.ins().band(operands, left);
+                        let failed = builder.ins().icmp_imm(IntCC::SignedLessThan, both, 0);
+                        give_up_if(&mut builder, failed, give_up);
+                        value
+                    }
+                    // The product overflowed unless its high half only
+                    // extends the sign of the low half
+                    OpCode::Multiply => {
+                        let value = builder.ins().imul(a, b);
+                        let high = builder.ins().smulhi(a, b);
+                        let sign = builder.ins().sshr_imm(value, 63);
+                        let failed = builder.ins().icmp(IntCC::NotEqual, high, sign);
+                        give_up_if(&mut builder, failed, give_up);
+                        value
+                    }
+                    _ => {
+                        let by_zero = builder.ins().icmp_imm(IntCC::Equal, b, 0);
+                        let min = builder.ins().icmp_imm(IntCC::Equal, a, i64::MIN);
+                        let minus_one = builder.ins().icmp_imm(IntCC::Equal, b, -1);
+                        let overflow = builder.ins().band(min, minus_one);
+                        let failed = builder.ins().bor(by_zero, overflow);
+                        give_up_if(&mut builder, failed, give_up);
+                        match op {
+                            OpCode::Divide => builder.ins().sdiv(a, b),
+                            _ => builder.ins().srem(a, b),
+                        }
+                    }
+                };
+                builder.def_var(slot(top - 1), value);
+            }
+            OpCode::Negate => {
+                let value = builder.use_var(slot(top));
+                let failed = builder.ins().icmp_imm(IntCC::Equal, value, i64::MIN);
+                give_up_if(&mut builder, failed, give_up);
+                let value = builder.ins().ineg(value);
+                builder.def_var(slot(top), value);
+            }
+            OpCode::Not => {
+                let value = builder.use_var(slot(top));
+                let value = builder.ins().bxor_imm(value, 1);
+                builder.def_var(slot(top), value);
+            }
+            OpCode::Equal
+            | OpCode::NotEqual
+            | OpCode::Greater
+            | OpCode::GreaterEqual
+            | OpCode::Less
+            | OpCode::LessEqual => {
+                let condition = match op {
+                    OpCode::Equal => IntCC::Equal,
+                    OpCode::NotEqual => IntCC::NotEqual,
+                    OpCode::Greater => IntCC::SignedGreaterThan,
+                    OpCode::GreaterEqual => IntCC::SignedGreaterThanOrEqual,
+                    OpCode::Less => IntCC::SignedLessThan,
+                    _ => IntCC::SignedLessThanOrEqual,
+                };
+                let a = builder.use_var(slot(top - 1));
+                let b = builder.use_var(slot(top));
+                let value = builder.ins().icmp(condition, a, b);
+                let value = builder.ins().uextend(types::I64, value);
+                builder.def_var(slot(top - 1), value);
+            }
+            OpCode::Jump => {
+                builder
+                    .ins()
+                    .jump(blocks[&jump_target(chunk, op, offset)?], &[]);
+                open = false;
+            }
+            // Loops are where an interrupt is noticed, since code without
+            // them finishes soon enough anyway
+            OpCode::Loop => {
+                let flag = builder
+                    .ins()
+                    .atomic_load(types::I8, MemFlags::trusted(), interrupt);
+                let target = blocks[&jump_target(chunk, op, offset)?];
+                builder.ins().brif(flag, give_up, &[], target, &[]);
+                open = false;
+            }
+            OpCode::JumpIfFalse => {
+                let condition = builder.use_var(slot(top));
+                let target = blocks[&jump_target(chunk, op, offset)?];
+                builder
+                    .ins()
+                    .brif(condition, blocks[&next], &[], target, &[]);
+                open = false;
+            }
+            OpCode::Return => {
+                let value = builder.use_var(slot(top));
+                builder.ins().store(MemFlags::trusted(), value, out, 0);
+                let finished = builder.ins().iconst(types::I8, 1);
+                builder.ins().return_(&[finished]);
+                open = false;
+            }
+            _ => unreachable!("only supported instructions pass type inference"),
+        }
+        offset = next;
+    }
+
+    builder.switch_to_block(give_up);
+    let finished = builder.ins().iconst(types::I8, 0);
+    builder.ins().return_(&[finished]);
+    builder.seal_all_blocks();
+    builder.finalize();
+
+    let id = module
+        .declare_anonymous_function(&context.func.signature)
+        .ok()?;
+    module.define_function(id, &mut context).ok()?;
+    module.finalize_definitions().ok()?;
+    // Safety: the function was compiled with the signature of `Code`
+    let code = unsafe { mem::transmute::<*const u8, Code>(module.get_finalized_function(id)) };
+    Some(Compiled { code, result })
+}
+
+/// Branch to `give_up` if `failed` is true, and continue in a new block
+/// otherwise.
+fn give_up_if(builder: &mut FunctionBuilder, failed: cranelift_codegen::ir::Value, give_up: Block) {
+    let rest = builder.create_block();
+    builder.ins().brif(failed, give_up, &[], rest, &[]);
+    builder.switch_to_block(rest);
+}
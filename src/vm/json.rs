@@ -0,0 +1,425 @@
+//! The `std.json` module converts between values and JSON text. Arrays
+//! become lists, and objects become instances of a class named `Object`,
+//! with a field for each key. `null` is the unit value `{}`, and numbers
+//! are ints if they are written without a fraction or exponent and fit in
+//! 64 bits, and floats otherwise.
+
+use super::{
+    heap::{Class, Instance, ObjRef, Object},
+    RunResult, Vm,
+};
+use crate::value::Value;
+use std::{collections::HashMap, fmt::Write, rc::Rc};
+
+/// The deepest arrays and objects can be nested in JSON text, so that
+/// parsing and writing it can't overflow the stack.
+pub const MAX_JSON_DEPTH: usize = 128;
+
+/// A parsed JSON value, before it is turned into a [`Value`].
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum Json {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Array(Vec<Json>),
+    /// The entries of an object, in order. Later entries replace earlier
+    /// ones with the same key.
+    Object(Vec<(String, Json)>),
+}
+
+/// Parse `text` as a single JSON value, describing where it is invalid if
+/// it isn't.
+pub(super) fn parse(text: &str) -> Result<Json, String> {
+    let mut parser = Parser { text, offset: 0 };
+    let result = parser.value(0).and_then(|value| {
+        parser.skip_whitespace();
+        match parser.peek() {
+            Some(_) => Err("expected the end of the text"),
+            None => Ok(value),
+        }
+    });
+    result.map_err(|message| {
+        let before = &text[..parser.offset];
+        let line = before.matches('\n').count() + 1;
+        let column = before.chars().rev().take_while(|&c| c != '\n').count() + 1;
+        format!(
+            "invalid JSON at line {}, column {}: {}",
+            line, column, message
+        )
+    })
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    /// The byte offset of the next character.
+    offset: usize,
+}
+
+type ParseResult<T> = Result<T, &'static str>;
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.text[self.offset..].chars().next()
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.offset += c.len_utf8();
+        Some(c)
+    }
+
+    /// Consume `c` if it is next.
+    fn eat(&mut self, c: char) -> bool {
+        let next = self.peek() == Some(c);
+        if next {
+            self.offset += c.len_utf8();
+        }
+        next
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+            self.offset += 1;
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> ParseResult<Json> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') | Some('[') if depth == MAX_JSON_DEPTH => {
+                Err("arrays and objects are nested too deeply")
+            }
+            Some('{') => self.object(depth),
+            Some('[') => self.array(depth),
+            Some('"') => self.string().map(Json::Str),
+            Some('-' | '0'..='9') => self.number(),
+            Some(_) => self.keyword(),
+            None => Err("expected a value"),
+        }
+    }
+
+    fn keyword(&mut self) -> ParseResult<Json> {
+        for (keyword, value) in [
+            ("null", Json::Null),
+            ("true", Json::Bool(true)),
+            ("false", Json::Bool(false)),
+        ] {
+            if self.text[self.offset..].starts_with(keyword) {
+                self.offset += keyword.len();
+                return Ok(value);
+            }
+        }
+        Err("expected a value")
+    }
+
+    fn number(&mut self) -> ParseResult<Json> {
+        let start = self.offset;
+        self.eat('-');
+        if !self.eat('0') && !self.digits() {
+            return Err("expected a digit");
+        }
+        let mut int = true;
+        if self.eat('.') {
+            int = false;
+            if !self.digits() {
+                return Err("expected a digit after `.`");
+            }
+        }
+        if self.eat('e') || self.eat('E') {
+            int = false;
+            if !self.eat('+') {
+                self.eat('-');
+            }
+            if !self.digits() {
+                return Err("expected a digit in the exponent");
+            }
+        }
+
+        let number = &self.text[start..self.offset];
+        match number.parse() {
+            Ok(value) if int => Ok(Json::Int(value)),
+            // Ints too large for 64 bits are kept as floats
+            _ => Ok(Json::Float(number.parse().expect("invalid float syntax"))),
+        }
+    }
+
+    /// Consume a run of digits, returning whether there were any.
+    fn digits(&mut self) -> bool {
+        let start = self.offset;
+        while matches!(self.peek(), Some('0'..='9')) {
+            self.offset += 1;
+        }
+        self.offset > start
+    }
+
+    fn string(&mut self) -> ParseResult<String> {
+        self.advance();
+        let mut value = String::new();
+        loop {
+            match self.advance() {
+                Some('"') => return Ok(value),
+                Some('\\') => value.push(self.escape()?),
+                Some(c) if c < ' ' => return Err("control characters must be escaped"),
+                Some(c) => value.push(c),
+                None => return Err("unterminated string"),
+            }
+        }
+    }
+
+    fn escape(&mut self) -> ParseResult<char> {
+        Ok(match self.advance() {
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some('/') => '/',
+            Some('b') => '\u{8}',
+            Some('f') => '\u{c}',
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('u') => {
+                let high = self.hex()?;
+                if !(0xd800..0xdc00).contains(&high) {
+                    return char::from_u32(high).ok_or("unpaired surrogate in escape");
+                }
+                // Characters outside the basic plane are written as a
+                // surrogate pair
+                if !self.eat('\\') || !self.eat('u') {
+                    return Err("unpaired surrogate in escape");
+                }
+                let low = self.hex()?;
+                if !(0xdc00..0xe000).contains(&low) {
+                    return Err("unpaired surrogate in escape");
+                }
+                let c = 0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00);
+                char::from_u32(c).expect("surrogate pairs are valid characters")
+            }
+            _ => return Err("invalid escape"),
+        })
+    }
+
+    /// Parse the four hex digits of a `\u` escape.
+    fn hex(&mut self) -> ParseResult<u32> {
+        let digits = self.text[self.offset..]
+            .get(..4)
+            .filter(|digits| digits.chars().all(|c| c.is_ascii_hexdigit()))
+            .ok_or("expected four hex digits")?;
+        self.offset += 4;
+        Ok(u32::from_str_radix(digits, 16).expect("checked hex digits"))
+    }
+
+    fn array(&mut self, depth: usize) -> ParseResult<Json> {
+        self.advance();
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.eat(']') {
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value(depth + 1)?);
+            self.skip_whitespace();
+            if self.eat(']') {
+                return Ok(Json::Array(items));
+            }
+            if !self.eat(',') {
+                return Err("expected `,` or `]`");
+            }
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> ParseResult<Json> {
+        self.advance();
+        let mut entries = Vec::new();
+        self.skip_whitespace();
+        if self.eat('}') {
+            return Ok(Json::Object(entries));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('"') {
+                return Err("expected a string key");
+            }
+            let key = self.string()?;
+            self.skip_whitespace();
+            if !self.eat(':') {
+                return Err("expected `:`");
+            }
+            entries.push((key, self.value(depth + 1)?));
+            self.skip_whitespace();
+            if self.eat('}') {
+                return Ok(Json::Object(entries));
+            }
+            if !self.eat(',') {
+                return Err("expected `,` or `}`");
+            }
+        }
+    }
+}
+
+impl Vm {
+    /// Turn parsed JSON into a value, allocating its lists and objects.
+    pub(super) fn json_value(&mut self, json: Json) -> Value {
+        // Nothing is rooted while the value is built, so collect first
+        self.maybe_collect();
+        let class = Rc::new(Class {
+            name: "Object".to_string(),
+            methods: HashMap::new(),
+            host: None,
+        });
+        self.build_json(json, &class)
+    }
+
+    fn build_json(&mut self, json: Json, class: &Rc<Class>) -> Value {
+        match json {
+            Json::Null => Value::Unit,
+            Json::Bool(value) => Value::Bool(value),
+            Json::Int(value) => Value::Int(value),
+            Json::Float(value) => Value::Float(value),
+            Json::Str(value) => Value::from(value.as_str()),
+            Json::Array(items) => {
+                let items = items
+                    .into_iter()
+                    .map(|item| self.build_json(item, class))
+                    .collect();
+                Value::List(self.alloc(Object::List(items)))
+            }
+            Json::Object(entries) => {
+                let fields = entries
+                    .into_iter()
+                    .map(|(key, value)| (Rc::from(key), self.build_json(value, class)))
+                    .collect();
+                Value::Instance(self.alloc(Object::Instance(Instance {
+                    class: class.clone(),
+                    fields,
+                    data: None,
+                })))
+            }
+        }
+    }
+
+    /// Write `value` as JSON text, indented over several lines if `pretty`
+    /// is true. Fields of instances are sorted by name.
+    pub(super) fn stringify(&self, value: &Value, pretty: bool) -> RunResult<String> {
+        let mut out = String::new();
+        self.write_json(&mut out, value, pretty.then_some(0), &mut Vec::new())?;
+        Ok(out)
+    }
+
+    /// Write `value` to `out`. `indent` is the depth of the value if the
+    /// output is pretty, and `open` holds the objects being written around
+    /// it, so its length is how deeply the value is nested.
+    fn write_json(
+        &self,
+        out: &mut String,
+        value: &Value,
+        indent: Option<usize>,
+        open: &mut Vec<ObjRef>,
+    ) -> RunResult<()> {
+        let obj = match value {
+            Value::List(obj) | Value::Instance(obj) => *obj,
+            value => return self.write_scalar(out, value),
+        };
+        if open.contains(&obj) {
+            return Err(self.error("cannot convert a value that contains itself to JSON"));
+        }
+        if open.len() == MAX_JSON_DEPTH {
+            return Err(self.error(format!(
+                "cannot convert a value nested more than {} deep to JSON",
+                MAX_JSON_DEPTH
+            )));
+        }
+
+        open.push(obj);
+        let inner = indent.map(|depth| depth + 1);
+        match self.heap.get(obj) {
+            Object::List(items) => {
+                out.push('[');
+                for (index, item) in items.iter().enumerate() {
+                    separate(out, index, inner);
+                    self.write_json(out, item, inner, open)?;
+                }
+                close(out, !items.is_empty(), indent, ']');
+            }
+            Object::Instance(instance) => {
+                let mut fields: Vec<_> = instance.fields.iter().collect();
+                fields.sort_by(|a, b| a.0.cmp(b.0));
+                out.push('{');
+                for (index, (name, value)) in fields.iter().enumerate() {
+                    separate(out, index, inner);
+                    write_string(out, name);
+                    out.push_str(if indent.is_some() { ": " } else { ":" });
+                    self.write_json(out, value, inner, open)?;
+                }
+                close(out, !fields.is_empty(), indent, '}');
+            }
+            object => panic!("expected a list or an instance, found {:?}", object),
+        }
+        open.pop();
+        Ok(())
+    }
+}
+
+impl Vm {
+    /// Write a value that isn't a list or an instance to `out`.
+    fn write_scalar(&self, out: &mut String, value: &Value) -> RunResult<()> {
+        match value {
+            Value::Unit => out.push_str("null"),
+            Value::Bool(value) => out.push_str(if *value { "true" } else { "false" }),
+            Value::Int(value) => out.push_str(&value.to_string()),
+            Value::BigInt(value) => out.push_str(&value.to_string()),
+            // Debug formatting keeps the `.0` on whole numbers
+            Value::Float(value) if value.is_finite() => out.push_str(&format!("{:?}", value)),
+            Value::Float(value) => {
+                return Err(self.error(format!("cannot convert {:?} to JSON", value)))
+            }
+            Value::Char(value) => write_string(out, &value.to_string()),
+            Value::Str(value) => write_string(out, value),
+            value => {
+                return Err(self.error(format!(
+                    "cannot convert a value of type {} to JSON",
+                    value.type_name()
+                )))
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Start the item at `index` of an array or object whose items are at
+/// depth `indent`.
+fn separate(out: &mut String, index: usize, indent: Option<usize>) {
+    if index > 0 {
+        out.push(',');
+    }
+    if let Some(depth) = indent {
+        out.push('\n');
+        out.push_str(&"  ".repeat(depth));
+    }
+}
+
+/// End an array or object at depth `indent`, which has items if `items` is
+/// true.
+fn close(out: &mut String, items: bool, indent: Option<usize>, bracket: char) {
+    if let (true, Some(depth)) = (items, indent) {
+        out.push('\n');
+        out.push_str(&"  ".repeat(depth));
+    }
+    out.push(bracket);
+}
+
+fn write_string(out: &mut String, value: &str) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c < ' ' => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
@@ -0,0 +1,2296 @@
+//! Execution is the final step. The [`Vm`] is a stack machine running the
+//! bytecode produced by the compiler. A [`register`] machine backend that
+//! translates the same bytecode can be selected instead with
+//! [`Vm::set_backend`].
+//!
+//! Every call pushes a [`CallFrame`] whose stack slots start with the
+//! function being called, followed by its arguments. Globals are stored
+//! separately, in a [`Globals`] table keyed by interned names, and objects
+//! such as lists live on a garbage collected [`Heap`]. Programs can run
+//! several [`task`]s, each with its own stack and frames. Between runs, the
+//! globals and heap can be saved as a [`snapshot`]. Hosts can pass Rust
+//! values in and out of the VM with the traits in [`convert`], and decide
+//! where programs' input and output go with [`io`], limit what they can do
+//! with a [`sandbox`], and watch them run with an [`observer`]. Threads
+//! can share compiled programs with [`isolate`]s. The [`ast`] backend
+//! skips compilation, and evaluates the syntax tree directly instead.
+
+pub mod ast;
+pub mod bigint;
+pub mod convert;
+pub mod env;
+pub mod files;
+pub mod format;
+pub mod globals;
+pub mod heap;
+pub mod host;
+pub mod io;
+pub mod isolate;
+#[cfg(feature = "jit")]
+pub mod jit;
+pub mod json;
+pub mod native;
+pub mod observer;
+pub mod profile;
+pub mod register;
+pub mod sandbox;
+pub mod snapshot;
+pub mod task;
+pub mod timings;
+
+#[cfg(feature = "clock")]
+use crate::clock::Instant;
+#[cfg(feature = "fs")]
+use crate::loader::ModuleCache;
+use crate::{
+    bytecode::{Function, OpCode},
+    errors::{RuntimeError, RuntimeErrorKind, TraceFrame},
+    span::Span,
+    value::{CastType, Range, Value},
+};
+use ast::{AstFrame, Declaration};
+use bigint::BigInt;
+use env::{Environment, OsEnvironment};
+use files::FileSystem;
+use globals::Globals;
+use heap::{
+    BoundMethod, Class, GcConfig, Generator, GeneratorState, Heap, Instance, Method, ObjRef, Object,
+};
+use host::{HostFunction, Roots};
+use io::{MeowIo, StdIo};
+use isolate::SharedProgram;
+use native::Native;
+use observer::{Instruction, Observer};
+use profile::Profile;
+use register::{RegisterCode, RegisterFrame};
+use sandbox::{Capability, SandboxPolicy};
+use std::{
+    cell::RefCell,
+    cmp::Ordering,
+    collections::HashMap,
+    io::{BufRead, Write},
+    mem,
+    rc::Rc,
+    sync::{
+        atomic::{self, AtomicBool},
+        Arc,
+    },
+};
+use task::Scheduler;
+use timings::Timings;
+use tracing::debug;
+
+type RunResult<T> = Result<T, RuntimeError>;
+
+/// The most calls that can be in progress at once, including the script
+/// itself. Recursion deeper than this is almost certainly runaway, so it
+/// fails rather than using ever more memory.
+pub const MAX_CALL_DEPTH: usize = 1024;
+
+/// A handle that stops a VM's program from another thread. See
+/// [`Vm::interrupt_handle`].
+#[derive(Debug, Clone, Default)]
+pub struct InterruptHandle(Arc<AtomicBool>);
+
+impl InterruptHandle {
+    /// Make the running program fail with
+    /// [`RuntimeErrorKind::Interrupted`] before its next instruction, or
+    /// during a `sleep`. If no program is running, the next one fails
+    /// instead.
+    pub fn interrupt(&self) {
+        self.0.store(true, atomic::Ordering::Relaxed);
+    }
+}
+
+/// A single function invocation.
+#[derive(Debug, Clone)]
+struct CallFrame {
+    function: Rc<Function>,
+    /// The offset of the next instruction to execute.
+    ip: usize,
+    /// The index of the frame's first stack slot.
+    base: usize,
+    /// Whether the frame is running an `init` method, which returns the
+    /// instance being initialized instead of its own value.
+    initializer: bool,
+    /// The generator the frame is running, if it is one.
+    generator: Option<ObjRef>,
+}
+
+impl CallFrame {
+    #[inline]
+    fn read_byte(&mut self) -> u8 {
+        let byte = self.function.chunk.code[self.ip];
+        self.ip += 1;
+        byte
+    }
+
+    #[inline]
+    fn read_u16(&mut self) -> u16 {
+        let value = self.function.chunk.read_u16(self.ip);
+        self.ip += 2;
+        value
+    }
+
+    fn read_constant(&mut self) -> Value {
+        let index = self.read_u16() as usize;
+        self.function.chunk.constants[index].clone()
+    }
+
+    /// Read a constant holding a global's name.
+    fn read_name(&mut self) -> Rc<str> {
+        match self.read_constant() {
+            Value::Str(name) => name,
+            value => panic!("global name is not a string: {:?}", value),
+        }
+    }
+}
+
+/// The I/O of a VM.
+enum Io {
+    /// The default I/O, whose parts can be replaced one at a time.
+    Std(StdIo),
+    /// An I/O given to [`Vm::set_io`].
+    Custom(Box<dyn MeowIo>),
+}
+
+/// What happened when a call was prepared by [`Vm::prepare_call`].
+enum Prepared {
+    /// A frame should be pushed to run the function, which is an
+    /// initializer if the flag is set.
+    Frame(Rc<Function>, bool),
+    /// The call has completed, and left its result in the callee's slot.
+    Done,
+    /// The call can't complete until another task runs. It hasn't had any
+    /// effect, and should be retried once the task resumes.
+    Blocked,
+}
+
+/// A suspended generator that is being resumed.
+struct Resumed {
+    generator: ObjRef,
+    function: Rc<Function>,
+    ip: usize,
+    slots: Vec<Value>,
+}
+
+/// The state of a program after running it for a while with [`Vm::step`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    /// The program has stopped before its next instruction.
+    Running,
+    /// The program finished, returning this value.
+    Done(Value),
+}
+
+/// The way compiled programs are executed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// Run bytecode directly on the stack machine.
+    #[default]
+    Stack,
+    /// Translate bytecode for the [`register`] machine before running it.
+    Register,
+    /// Evaluate the syntax tree with the [`ast`] backend. Programs are run
+    /// with [`Vm::run_ast`] rather than compiled for [`Vm::run`].
+    Ast,
+}
+
+/// The `Vm` struct executes compiled programs. Globals persist between calls
+/// to [`Vm::run`], so a single VM can run several programs that build on
+/// each other. [`Native`] functions are defined as globals when the VM is
+/// created.
+pub struct Vm {
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    globals: Globals,
+    heap: Heap,
+    backend: Backend,
+    register_frames: Vec<RegisterFrame>,
+    /// Functions translated for the register backend, keyed by address.
+    /// Entries are kept for as long as the VM is.
+    lowered: HashMap<*const Function, Rc<RegisterCode>>,
+    ast_frames: Vec<AstFrame>,
+    /// Functions declared on the ast backend, keyed by address. Entries are
+    /// kept for as long as the VM is.
+    declarations: HashMap<*const Function, Declaration>,
+    /// The shared programs the VM has run, decoded, keyed by their id.
+    /// Entries are kept for as long as the VM is.
+    shared: HashMap<u64, Function>,
+    /// The number of instructions left to run, if execution is limited.
+    fuel: Option<u64>,
+    /// While [`Vm::step`] runs, `fuel` only holds the step's instructions,
+    /// and this holds the rest of the program's fuel.
+    beyond_step: Option<u64>,
+    interrupt: InterruptHandle,
+    /// When the VM was created, which `time.elapsed` counts from.
+    #[cfg(feature = "clock")]
+    created: Instant,
+    profile: Option<Profile>,
+    timings: Option<Timings>,
+    /// The number of instructions executed since the VM was created.
+    executed: u64,
+    /// Where executed instructions are written, if tracing is on.
+    tracer: Option<Box<dyn Write>>,
+    observer: Option<Box<dyn Observer>>,
+    /// What `print`, `read_line` and the `std.fs` module use.
+    io: Io,
+    /// The environment the `std.env` and `std.os` modules read.
+    env: Box<dyn Environment>,
+    sandbox: SandboxPolicy,
+    /// Whether the heap has grown past the sandbox's limit since the last
+    /// instruction started.
+    out_of_memory: bool,
+    scheduler: Scheduler,
+    /// The number of frames below the function a native function is calling
+    /// back with [`Vm::call_value`], if it is calling one.
+    callback: Option<usize>,
+    /// Whether the error being returned was raised by a function called back
+    /// by a native function, and already points at where it happened.
+    callback_failed: bool,
+    /// Whether ints that overflow become big ints rather than failing.
+    big_ints: bool,
+    /// The modules of projects run on the VM, kept so that running a
+    /// project again only compiles the modules that changed.
+    #[cfg(feature = "fs")]
+    modules: ModuleCache,
+    /// The functions registered by the host, by name, so that snapshots
+    /// holding them can be restored.
+    hosts: HashMap<String, Rc<HostFunction>>,
+    /// The classes registered by the host, by name, for the same reason.
+    host_classes: HashMap<String, Rc<Class>>,
+    /// The values held by the host's [`Handle`](host::Handle)s.
+    roots: Rc<RefCell<Roots>>,
+    #[cfg(feature = "jit")]
+    jit: jit::Jit,
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        let mut vm = Self {
+            stack: Vec::new(),
+            frames: Vec::new(),
+            globals: Globals::new(),
+            heap: Heap::default(),
+            backend: Backend::default(),
+            register_frames: Vec::new(),
+            lowered: HashMap::new(),
+            ast_frames: Vec::new(),
+            declarations: HashMap::new(),
+            shared: HashMap::new(),
+            fuel: None,
+            beyond_step: None,
+            interrupt: InterruptHandle::default(),
+            #[cfg(feature = "clock")]
+            created: Instant::now(),
+            profile: None,
+            timings: None,
+            executed: 0,
+            tracer: None,
+            observer: None,
+            io: Io::Std(StdIo::default()),
+            env: Box::new(OsEnvironment),
+            sandbox: SandboxPolicy::default(),
+            out_of_memory: false,
+            scheduler: Scheduler::default(),
+            callback: None,
+            callback_failed: false,
+            big_ints: false,
+            #[cfg(feature = "fs")]
+            modules: ModuleCache::new(),
+            hosts: HashMap::new(),
+            host_classes: HashMap::new(),
+            roots: Rc::default(),
+            #[cfg(feature = "jit")]
+            jit: jit::Jit::default(),
+        };
+        for native in Native::ALL
+            .iter()
+            .copied()
+            .filter(|native| native.is_global())
+        {
+            vm.set_global(native.name(), Value::Native(native));
+        }
+        vm
+    }
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a VM whose garbage collector uses `config`.
+    pub fn with_gc(config: GcConfig) -> Self {
+        Self {
+            heap: Heap::new(config),
+            ..Self::default()
+        }
+    }
+
+    /// Create a VM that stops programs once they have executed `fuel`
+    /// instructions in total, so untrusted programs can't run forever.
+    /// Programs that run out fail with [`RuntimeErrorKind::FuelExhausted`].
+    ///
+    /// The budget is shared by every call to [`Vm::run`]. Instructions are
+    /// counted on the selected backend, so the register backend usually
+    /// gets further with the same amount of fuel. Sleeping uses one unit of
+    /// fuel for every millisecond, and a sleep longer than the fuel left is
+    /// cut short, stopping the program once it wakes up.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use meow::{compile, errors::RuntimeErrorKind, vm::Vm};
+    ///
+    /// let mut vm = Vm::with_fuel(1000);
+    /// let error = vm.run(compile("while true { }").unwrap()).unwrap_err();
+    /// assert_eq!(error.kind, RuntimeErrorKind::FuelExhausted);
+    /// ```
+    pub fn with_fuel(fuel: u64) -> Self {
+        Self {
+            fuel: Some(fuel),
+            ..Self::default()
+        }
+    }
+
+    /// Return the number of instructions programs may still execute, or
+    /// `None` if execution isn't limited.
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    /// Replace the remaining instruction budget, or remove the limit with
+    /// `None`.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+
+    /// Return a handle that can stop the VM's programs from another thread.
+    /// Unlike fuel, this also stops programs in the middle of a `sleep`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use meow::{compile, errors::RuntimeErrorKind, vm::Vm};
+    /// use std::{thread, time::Duration};
+    ///
+    /// let mut vm = Vm::new();
+    /// let handle = vm.interrupt_handle();
+    /// thread::spawn(move || {
+    ///     thread::sleep(Duration::from_millis(10));
+    ///     handle.interrupt();
+    /// });
+    /// # #[cfg(feature = "clock")] {
+    /// let error = vm.run(compile("while true { sleep(1000); }").unwrap()).unwrap_err();
+    /// assert_eq!(error.kind, RuntimeErrorKind::Interrupted);
+    /// # }
+    /// ```
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+
+    /// Start or stop profiling. While profiling is on, every instruction
+    /// run is counted and timed in the VM's [`Profile`]. Turning it off
+    /// discards the profile.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use meow::{bytecode::OpCode, compile, vm::Vm};
+    ///
+    /// let mut vm = Vm::new();
+    /// vm.set_profiling(true);
+    /// vm.run(compile("let x = 1 + 2;").unwrap()).unwrap();
+    /// let add = vm.profile().unwrap().opcodes().into_iter().find(|(op, _)| *op == OpCode::Add);
+    /// assert_eq!(add.unwrap().1.count, 1);
+    /// ```
+    pub fn set_profiling(&mut self, enabled: bool) {
+        match (enabled, &self.profile) {
+            (true, None) => self.profile = Some(Profile::new()),
+            (false, _) => self.profile = None,
+            (true, Some(_)) => {}
+        }
+    }
+
+    /// Return the profile collected since profiling was turned on.
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /// Turn timing how long each phase of running a program takes on or
+    /// off. Turning it off discards the timings. Only programs run from
+    /// source, with [`run`](crate::run) and the functions like it, are
+    /// timed, since running a compiled program with [`Vm::run`] has just
+    /// one phase.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use meow::{run, vm::Vm};
+    ///
+    /// let mut vm = Vm::new();
+    /// vm.set_timing(true);
+    /// run(&mut vm, "let x = 1 + 2;").unwrap();
+    /// # #[cfg(feature = "clock")]
+    /// assert!(vm.timings().unwrap().total() > vm.timings().unwrap().lex);
+    /// ```
+    pub fn set_timing(&mut self, enabled: bool) {
+        match (enabled, &self.timings) {
+            (true, None) => self.timings = Some(Timings::default()),
+            (false, _) => self.timings = None,
+            (true, Some(_)) => {}
+        }
+    }
+
+    /// Return the timings collected since timing was turned on.
+    pub fn timings(&self) -> Option<&Timings> {
+        self.timings.as_ref()
+    }
+
+    pub(crate) fn timings_mut(&mut self) -> Option<&mut Timings> {
+        self.timings.as_mut()
+    }
+
+    /// Return the number of instructions executed since the VM was
+    /// created, on whichever backend ran them. Code compiled to native code
+    /// with the `jit` feature isn't counted.
+    pub fn instructions_executed(&self) -> u64 {
+        self.executed
+    }
+
+    /// Start tracing execution by writing every instruction to `output` as
+    /// it runs, after a line showing the current frame's stack slots, or
+    /// stop tracing with `None`. Write errors are ignored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use meow::{compile, vm::Vm};
+    /// use std::{cell::RefCell, io::{self, Write}, rc::Rc};
+    ///
+    /// #[derive(Clone, Default)]
+    /// struct Output(Rc<RefCell<Vec<u8>>>);
+    ///
+    /// impl Write for Output {
+    ///     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    ///         self.0.borrow_mut().write(buf)
+    ///     }
+    ///     fn flush(&mut self) -> io::Result<()> {
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// let output = Output::default();
+    /// let mut vm = Vm::new();
+    /// vm.set_tracer(Some(Box::new(output.clone())));
+    /// vm.run(compile("1 + 2;").unwrap()).unwrap();
+    ///
+    /// let trace = String::from_utf8(output.0.take()).unwrap();
+    /// assert!(trace.contains("[ <fun <script>> ][ 1 ][ 2 ]\n0006    | Add"));
+    /// ```
+    pub fn set_tracer(&mut self, output: Option<Box<dyn Write>>) {
+        self.tracer = output;
+    }
+
+    /// Install `observer` to be told about the calls, instructions,
+    /// allocations and errors of programs as they run, replacing any
+    /// observer installed before, or stop observing with `None`. See
+    /// [`Observer`] for an example.
+    pub fn set_observer(&mut self, observer: Option<Box<dyn Observer>>) {
+        self.observer = observer;
+    }
+
+    /// Send what programs print with `print` and `println` to `output`
+    /// instead of standard output. This replaces any I/O given to
+    /// [`Vm::set_io`], along with [`Vm::set_input`] and
+    /// [`Vm::set_file_system`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use meow::{run, vm::Vm};
+    /// use std::{cell::RefCell, io::{self, Write}, rc::Rc};
+    ///
+    /// #[derive(Clone, Default)]
+    /// struct Output(Rc<RefCell<Vec<u8>>>);
+    ///
+    /// impl Write for Output {
+    ///     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    ///         self.0.borrow_mut().write(buf)
+    ///     }
+    ///     fn flush(&mut self) -> io::Result<()> {
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// let output = Output::default();
+    /// let mut vm = Vm::new();
+    /// vm.set_output(Box::new(output.clone()));
+    /// run(&mut vm, r#"fun main() { println("Hello, world!"); } main();"#).unwrap();
+    /// assert_eq!(output.0.take(), b"Hello, world!\n");
+    /// ```
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.std_io().output = output;
+    }
+
+    /// Give programs the lines of `input` when they call `read_line`,
+    /// instead of standard input. Like [`Vm::set_output`], this replaces
+    /// any I/O given to [`Vm::set_io`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use meow::{run, value::Value, vm::Vm};
+    /// use std::io::Cursor;
+    ///
+    /// let mut vm = Vm::new();
+    /// vm.set_output(Box::new(Vec::new()));
+    /// vm.set_input(Box::new(Cursor::new("Tom\n")));
+    /// run(&mut vm, r#"let name = read_line("Name: ");"#).unwrap();
+    /// assert_eq!(vm.global("name"), Some(&Value::from("Tom")));
+    /// ```
+    pub fn set_input(&mut self, input: Box<dyn BufRead>) {
+        self.std_io().input = Some(input);
+    }
+
+    /// Define the global `args` as a list of `args`, so that programs can be
+    /// given arguments. Until this is called, `args` isn't defined.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use meow::{run, value::Value, vm::Vm};
+    ///
+    /// let mut vm = Vm::new();
+    /// vm.set_args(vec!["in.txt".to_string(), "out.txt".to_string()]);
+    /// run(&mut vm, "let output = args[1];").unwrap();
+    /// assert_eq!(vm.global("output"), Some(&Value::from("out.txt")));
+    /// ```
+    pub fn set_args(&mut self, args: Vec<String>) {
+        let args = args.iter().map(|arg| Value::from(arg.as_str())).collect();
+        self.maybe_collect();
+        let list = self.alloc(Object::List(args));
+        self.set_global("args", Value::List(list));
+    }
+
+    /// Give the `std.fs` module access to `files` instead of the operating
+    /// system's file system. Like [`Vm::set_output`], this replaces any I/O
+    /// given to [`Vm::set_io`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use meow::{compile, vm::{files::NoFileSystem, Vm}};
+    ///
+    /// let mut vm = Vm::new();
+    /// vm.set_file_system(Box::new(NoFileSystem));
+    /// let script = compile(r#"import std.fs; fs.read_to_string("secret.txt");"#).unwrap();
+    /// assert_eq!(
+    ///     vm.run(script).unwrap_err().message,
+    ///     "cannot read `secret.txt`: file access is disabled"
+    /// );
+    /// ```
+    pub fn set_file_system(&mut self, files: Box<dyn FileSystem>) {
+        self.std_io().files = files;
+    }
+
+    /// Use `io` for everything programs print, the lines they read and the
+    /// files they use. See the [`io`] module.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use meow::{compile, value::Value, vm::{io::MemoryIo, Vm}};
+    ///
+    /// let io = MemoryIo::new().deny_files();
+    /// let mut vm = Vm::new();
+    /// vm.set_io(Box::new(io.clone()));
+    /// vm.run(compile(r#"print("meow"); let line = read_line();"#).unwrap())
+    ///     .unwrap();
+    /// assert_eq!(io.output(), "meow");
+    /// assert_eq!(vm.global("line"), Some(&Value::Unit));
+    ///
+    /// let script = compile(r#"import std.fs; fs.write("a.txt", "");"#).unwrap();
+    /// assert_eq!(
+    ///     vm.run(script).unwrap_err().message,
+    ///     "cannot write `a.txt`: file access is disabled"
+    /// );
+    /// ```
+    pub fn set_io(&mut self, io: Box<dyn MeowIo>) {
+        self.io = Io::Custom(io);
+    }
+
+    /// Return the I/O programs use.
+    fn io(&mut self) -> &mut dyn MeowIo {
+        match &mut self.io {
+            Io::Std(io) => io,
+            Io::Custom(io) => io.as_mut(),
+        }
+    }
+
+    /// Return the default I/O, going back to it if another was given to
+    /// [`Vm::set_io`].
+    fn std_io(&mut self) -> &mut StdIo {
+        if let Io::Custom(_) = self.io {
+            self.io = Io::Std(StdIo::default());
+        }
+        match &mut self.io {
+            Io::Std(io) => io,
+            Io::Custom(_) => unreachable!("the default I/O was just restored"),
+        }
+    }
+
+    /// Give the `std.env` and `std.os` modules access to `env` instead of
+    /// the process's environment variables and working directory.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use meow::{compile, value::Value, vm::{env::NoEnvironment, Vm}};
+    ///
+    /// let mut vm = Vm::new();
+    /// vm.set_environment(Box::new(NoEnvironment));
+    /// let script = compile(r#"import std.env; let path = env.get("PATH");"#).unwrap();
+    /// vm.run(script).unwrap();
+    /// assert_eq!(vm.global("path"), Some(&Value::Unit));
+    /// ```
+    pub fn set_environment(&mut self, env: Box<dyn Environment>) {
+        self.env = env;
+    }
+
+    /// Limit what programs can do to what `policy` allows. If the policy
+    /// has fuel, it replaces the VM's remaining fuel. See [`SandboxPolicy`]
+    /// for an example.
+    pub fn set_sandbox(&mut self, policy: SandboxPolicy) {
+        if policy.fuel.is_some() {
+            self.fuel = policy.fuel;
+        }
+        self.sandbox = policy;
+    }
+
+    pub fn sandbox(&self) -> &SandboxPolicy {
+        &self.sandbox
+    }
+
+    /// Create the error for `native` using `capability`, which the sandbox
+    /// denies.
+    fn denied(&self, native: &str, capability: Capability) -> RuntimeError {
+        let what = match capability {
+            Capability::Files => "files",
+            Capability::Env => "the environment",
+            Capability::Subprocess => "other processes",
+            Capability::Network => "the network",
+        };
+        RuntimeError {
+            kind: RuntimeErrorKind::Denied,
+            ..self.error(format!(
+                "`{}` needs access to {}, which the sandbox denies",
+                native, what
+            ))
+        }
+    }
+
+    /// Choose what happens when int arithmetic overflows 64 bits. By default
+    /// it fails, and with `enabled`, the result becomes a
+    /// [big int](bigint::BigInt) instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use meow::{compile, vm::Vm};
+    ///
+    /// let source = "let x = 9223372036854775807 + 1;";
+    /// let mut vm = Vm::new();
+    /// let error = vm.run(compile(source).unwrap()).unwrap_err();
+    /// assert_eq!(error.message, "integer overflow: 9223372036854775807 + 1");
+    ///
+    /// vm.set_big_ints(true);
+    /// vm.run(compile(source).unwrap()).unwrap();
+    /// assert_eq!(vm.global("x").unwrap().to_string(), "9223372036854775808");
+    /// ```
+    pub fn set_big_ints(&mut self, enabled: bool) {
+        self.big_ints = enabled;
+    }
+
+    pub fn big_ints(&self) -> bool {
+        self.big_ints
+    }
+
+    /// Compile functions to native code once they have been called
+    /// `threshold` times, or never with `None`. The threshold starts at
+    /// [`JIT_THRESHOLD`](jit::JIT_THRESHOLD). Functions only run natively
+    /// while the VM isn't limited by fuel, profiled, traced or observed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use meow::{compile, value::Value, vm::Vm};
+    ///
+    /// let mut vm = Vm::new();
+    /// vm.set_jit_threshold(Some(10));
+    /// let source = "fun square(x) { x * x } let mut total = 0; for i in 0..100 { total += square(i); }";
+    /// vm.run(compile(source).unwrap()).unwrap();
+    /// assert_eq!(vm.global("total"), Some(&Value::Int(328350)));
+    /// assert_eq!(vm.jit_compiled(), ["square"]);
+    /// ```
+    #[cfg(feature = "jit")]
+    pub fn set_jit_threshold(&mut self, threshold: Option<u32>) {
+        self.jit.set_threshold(threshold);
+    }
+
+    /// Return the names of the functions compiled to native code, in
+    /// alphabetical order.
+    #[cfg(feature = "jit")]
+    pub fn jit_compiled(&self) -> Vec<&str> {
+        self.jit.compiled()
+    }
+
+    /// Select the backend used by later calls to [`Vm::run`].
+    pub fn set_backend(&mut self, backend: Backend) {
+        self.backend = backend;
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    /// Return the heap holding the objects created by programs.
+    pub fn heap(&self) -> &Heap {
+        &self.heap
+    }
+
+    /// Free every object that can no longer be reached from a global, the
+    /// stack of a task or a [`Handle`](host::Handle). This happens
+    /// automatically as programs allocate, so it only needs to be called to
+    /// free memory right away.
+    pub fn collect_garbage(&mut self) {
+        let roots = self.roots.borrow();
+        let globals = self.globals.iter().map(|(_, value)| value);
+        let tasks = self.scheduler.roots();
+        self.heap.collect(
+            self.stack
+                .iter()
+                .chain(globals)
+                .chain(tasks)
+                .chain(roots.values.values()),
+        );
+    }
+
+    /// Allocate `object` on the heap, telling the observer about it. Going
+    /// past the sandbox's memory limit fails the next instruction, since
+    /// this can't fail itself.
+    fn alloc(&mut self, object: Object) -> ObjRef {
+        let obj = self.heap.alloc(object);
+        if self
+            .sandbox
+            .max_objects
+            .is_some_and(|max| self.heap.len() > max)
+        {
+            self.out_of_memory = true;
+        }
+        if let Some(observer) = &mut self.observer {
+            observer.on_allocation(&self.heap, obj);
+        }
+        obj
+    }
+
+    /// Collect garbage if the heap has grown past its threshold. This must
+    /// be called before allocating, while every value still in use is on
+    /// the stack or in a global.
+    fn maybe_collect(&mut self) {
+        let at_limit = self
+            .sandbox
+            .max_objects
+            .is_some_and(|max| self.heap.len() >= max);
+        if self.heap.should_collect() || at_limit {
+            self.collect_garbage();
+        }
+    }
+
+    /// Return the cache of the modules of projects run on the VM.
+    #[cfg(feature = "fs")]
+    pub fn module_cache(&mut self) -> &mut ModuleCache {
+        &mut self.modules
+    }
+
+    pub fn globals(&self) -> &Globals {
+        &self.globals
+    }
+
+    /// Return the value of the global variable `name`, if it is defined.
+    pub fn global(&self, name: &str) -> Option<&Value> {
+        self.globals.lookup(name)
+    }
+
+    /// Define or overwrite the global variable `name`.
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        let id = self.globals.intern(name);
+        self.globals.define(id, value);
+    }
+
+    /// Make every instance of `old` an instance of `new`, so that they use
+    /// its methods.
+    #[cfg(feature = "fs")]
+    pub(crate) fn replace_class(&mut self, old: &Rc<Class>, new: &Rc<Class>) {
+        let instances: Vec<_> = self
+            .heap
+            .objects()
+            .filter_map(|(obj, object)| match object {
+                Object::Instance(instance) if Rc::ptr_eq(&instance.class, old) => Some(obj),
+                _ => None,
+            })
+            .collect();
+        for obj in instances {
+            if let Object::Instance(instance) = self.heap.get_mut(obj) {
+                instance.class = new.clone();
+            }
+        }
+    }
+
+    /// Execute the top-level function of a program, returning the value it
+    /// returns. If the program spawns tasks, this also waits for them to
+    /// finish, unless they are left waiting on a channel forever.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use meow::{compile, value::Value, vm::Vm};
+    ///
+    /// let mut vm = Vm::new();
+    /// vm.run(compile("let x = 6 * 7;").unwrap()).unwrap();
+    /// assert_eq!(vm.global("x"), Some(&Value::Int(42)));
+    /// ```
+    pub fn run(&mut self, script: Function) -> RunResult<Value> {
+        debug!(backend = ?self.backend, "running the program");
+        self.start(script)?;
+        let result = self.execute_program();
+        let result = self.finish(result);
+        debug!(failed = result.is_err(), "the program finished");
+        result
+    }
+
+    /// Run a program shared between threads, as [`Vm::run`] does. The
+    /// program is only decoded the first time the VM runs it. See
+    /// [`isolate`] for an example.
+    pub fn run_shared(&mut self, program: &SharedProgram) -> RunResult<Value> {
+        let script = self
+            .shared
+            .entry(program.id())
+            .or_insert_with(|| program.decode())
+            .clone();
+        self.run(script)
+    }
+
+    /// Load `script` to be run a few instructions at a time by [`Vm::step`],
+    /// abandoning any program that was already running. The backend that
+    /// is selected now is used until the program finishes.
+    pub fn start(&mut self, script: Function) -> RunResult<()> {
+        let script = Rc::new(script);
+        self.stack.clear();
+        self.frames.clear();
+        self.register_frames.clear();
+        self.ast_frames.clear();
+        self.reset_tasks();
+
+        self.stack.push(Value::Function(script.clone()));
+        if self.observer.is_some() {
+            self.observe_call(&script, 0, 0);
+        }
+        match self.backend {
+            Backend::Stack => {
+                self.frames.push(CallFrame {
+                    function: script,
+                    ip: 0,
+                    base: 0,
+                    initializer: false,
+                    generator: None,
+                });
+                Ok(())
+            }
+            Backend::Register => self
+                .push_register_frame(script, 0, false)
+                .map_err(|error| self.fail(error)),
+            Backend::Ast => Err(RuntimeError::new(
+                "the ast backend can't run bytecode, use `Vm::run_ast` instead",
+                None,
+            )),
+        }
+    }
+
+    /// Continue the program loaded by [`Vm::start`] for at most
+    /// `instructions` instructions, so that hosts can interleave it with
+    /// their own work. Once the program finishes or fails, another has to
+    /// be started before stepping again.
+    ///
+    /// Instructions are counted like [fuel](Vm::with_fuel), which also
+    /// still limits the program as a whole. A program can't be paused while
+    /// a native function such as `sort_by` is calling one of its functions,
+    /// so running out of instructions then is an error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use meow::{compile, value::Value, vm::{Step, Vm}};
+    ///
+    /// let mut vm = Vm::new();
+    /// vm.start(compile("let mut i = 0; while i < 100 { i += 1; }").unwrap()).unwrap();
+    /// let mut steps = 1;
+    /// while vm.step(50).unwrap() == Step::Running {
+    ///     steps += 1;
+    /// }
+    /// assert!(steps > 10);
+    /// assert_eq!(vm.global("i"), Some(&Value::Int(100)));
+    /// assert!(vm.step(50).is_err());
+    /// ```
+    pub fn step(&mut self, instructions: u64) -> RunResult<Step> {
+        if !self.is_paused() {
+            return Err(RuntimeError::new("there is no program to step", None));
+        }
+
+        let fuel = self.fuel;
+        let limit = fuel.map_or(instructions, |fuel| fuel.min(instructions));
+        self.fuel = Some(limit);
+        self.beyond_step = Some(fuel.map_or(u64::MAX, |fuel| fuel - limit));
+        let result = self.execute_program();
+        let left = self.fuel.unwrap_or(0);
+        let used = limit - left;
+        let beyond = self.beyond_step.take().unwrap_or(0);
+        self.fuel = fuel.map(|_| left + beyond);
+
+        match result {
+            // Running out of the instructions for this step rather than the
+            // program's own fuel pauses it before the next instruction,
+            // unless a native function was calling back into the program
+            Err(error)
+                if error.kind == RuntimeErrorKind::FuelExhausted
+                    && used == instructions
+                    && !self.callback_failed =>
+            {
+                if let Some(profile) = &mut self.profile {
+                    profile.stop();
+                }
+                Ok(Step::Running)
+            }
+            result => self.finish(result).map(Step::Done),
+        }
+    }
+
+    /// Returns true if a program started by [`Vm::start`] hasn't finished.
+    pub fn is_paused(&self) -> bool {
+        !self.frames.is_empty() || !self.register_frames.is_empty()
+    }
+
+    /// Continue the running program on the backend it was started on.
+    fn execute_program(&mut self) -> RunResult<Value> {
+        if self.register_frames.is_empty() {
+            self.execute()
+        } else {
+            self.execute_registers()
+        }
+    }
+
+    /// Clean up after the running program has finished with `result`.
+    fn finish(&mut self, result: RunResult<Value>) -> RunResult<Value> {
+        if let Some(profile) = &mut self.profile {
+            profile.stop();
+        }
+        let result = result.map_err(|error| self.fail(error));
+        // Tasks still waiting on a channel can never finish
+        self.reset_tasks();
+        result
+    }
+
+    /// Abandon the running program after `error`, which is given the calls
+    /// that were in progress.
+    fn fail(&mut self, mut error: RuntimeError) -> RuntimeError {
+        error.trace = self.trace();
+        if let Some(observer) = &mut self.observer {
+            observer.on_error(&error);
+        }
+        self.callback = None;
+        self.callback_failed = false;
+        self.stack.clear();
+        self.frames.clear();
+        self.register_frames.clear();
+        self.ast_frames.clear();
+        self.reset_tasks();
+        error
+    }
+
+    fn frame(&self) -> &CallFrame {
+        self.frames.last().expect("no call frame")
+    }
+
+    fn frame_mut(&mut self) -> &mut CallFrame {
+        self.frames.last_mut().expect("no call frame")
+    }
+
+    fn push(&mut self, value: Value) {
+        self.stack.push(value);
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("stack underflow")
+    }
+
+    fn peek(&self, distance: usize) -> &Value {
+        &self.stack[self.stack.len() - 1 - distance]
+    }
+
+    /// Return the calls in progress, innermost first.
+    fn trace(&self) -> Vec<TraceFrame> {
+        let frames = self.frames.iter().map(|frame| TraceFrame {
+            function: frame.function.name.clone(),
+            span: frame.function.chunk.span_at(frame.ip.saturating_sub(1)),
+        });
+        let register_frames = self.register_frames.iter().map(|frame| TraceFrame {
+            function: frame.function().name.clone(),
+            span: frame.current_span(),
+        });
+        let ast_frames = self.ast_frames.iter().map(|frame| TraceFrame {
+            function: frame.function().name.clone(),
+            span: frame.span(),
+        });
+        let mut trace: Vec<_> = frames.chain(register_frames).chain(ast_frames).collect();
+        trace.reverse();
+        trace
+    }
+
+    /// Create an error pointing at the instruction currently executing.
+    fn error(&self, message: impl Into<String>) -> RuntimeError {
+        RuntimeError::new(message, self.current_span())
+    }
+
+    /// The error raised when no arm of a match matches `value`.
+    fn unmatched(&self, value: &Value) -> RuntimeError {
+        self.error(format!("no match arm matches {}", self.heap.display(value)))
+    }
+
+    /// Return the span of the instruction currently executing.
+    fn current_span(&self) -> Option<Span> {
+        if let Some(frame) = self.register_frames.last() {
+            return frame.current_span();
+        }
+        let frame = self.frames.last()?;
+        frame.function.chunk.span_at(frame.ip.saturating_sub(1))
+    }
+
+    fn execute(&mut self) -> RunResult<Value> {
+        let mut frame = self.frame().clone();
+        self.dispatch(&mut frame).map_err(|mut error| {
+            if self.callback_failed {
+                return error;
+            }
+            // The stored frame is only updated on calls, so errors are
+            // pointed at the instruction that failed here
+            self.frame_mut().ip = frame.ip;
+            error.span = self.current_span();
+            error
+        })
+    }
+
+    /// Run instructions until the outermost frame returns. The current
+    /// frame is kept in `frame` rather than read from the frame stack for
+    /// every instruction.
+    fn dispatch(&mut self, frame: &mut CallFrame) -> RunResult<Value> {
+        loop {
+            self.consume_fuel()?;
+            if self.scheduler.slice == 0 {
+                self.frame_mut().ip = frame.ip;
+                self.preempt();
+                *frame = self.frame().clone();
+            }
+            self.scheduler.slice -= 1;
+            let byte = frame.read_byte();
+            let op = OpCode::from_byte(byte)
+                .ok_or_else(|| self.error(format!("invalid opcode {}", byte)))?;
+            if let Some(profile) = &mut self.profile {
+                profile.record(op, &frame.function);
+            }
+            if self.tracer.is_some() {
+                let instruction = frame.function.chunk.disassemble_instruction(frame.ip - 1).0;
+                self.trace_instruction(frame.base, &instruction);
+            }
+            if let Some(observer) = &mut self.observer {
+                observer.on_instruction(&Instruction {
+                    function: &frame.function,
+                    op: Some(op),
+                    span: frame.function.chunk.span_at(frame.ip - 1),
+                });
+            }
+
+            match op {
+                OpCode::Constant => {
+                    let value = frame.read_constant();
+                    self.push(value);
+                }
+                OpCode::Unit => self.push(Value::Unit),
+                OpCode::True => self.push(Value::Bool(true)),
+                OpCode::False => self.push(Value::Bool(false)),
+                OpCode::Pop => {
+                    self.pop();
+                }
+                OpCode::PopN => {
+                    let count = frame.read_byte() as usize;
+                    self.stack.truncate(self.stack.len() - count);
+                }
+                OpCode::Dup => self.push(self.peek(0).clone()),
+                OpCode::GetLocal => {
+                    let slot = frame.base + frame.read_byte() as usize;
+                    self.push(self.stack[slot].clone());
+                }
+                OpCode::SetLocal => {
+                    let slot = frame.base + frame.read_byte() as usize;
+                    self.stack[slot] = self.peek(0).clone();
+                }
+                OpCode::DefineGlobal => {
+                    let name = frame.read_name();
+                    let id = self.globals.intern(&name);
+                    let value = self.pop();
+                    self.globals.define(id, value);
+                }
+                OpCode::GetGlobal => {
+                    let name = frame.read_name();
+                    let id = self.globals.intern(&name);
+                    match self.globals.get(id) {
+                        Some(value) => {
+                            let value = value.clone();
+                            self.push(value);
+                        }
+                        None => return Err(self.undefined(&name)),
+                    }
+                }
+                OpCode::SetGlobal => {
+                    let name = frame.read_name();
+                    let id = self.globals.intern(&name);
+                    let value = self.peek(0).clone();
+                    if !self.globals.set(id, value) {
+                        return Err(self.undefined(&name));
+                    }
+                }
+                OpCode::Add
+                | OpCode::Subtract
+                | OpCode::Multiply
+                | OpCode::Divide
+                | OpCode::Remainder
+                | OpCode::Equal
+                | OpCode::NotEqual
+                | OpCode::Greater
+                | OpCode::GreaterEqual
+                | OpCode::Less
+                | OpCode::LessEqual => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    let result = self.binary(op, left, right)?;
+                    self.push(result);
+                }
+                OpCode::Negate | OpCode::Not => {
+                    let value = self.pop();
+                    let result = self.unary(op, value)?;
+                    self.push(result);
+                }
+                OpCode::Cast => {
+                    let ty = CastType::from_byte(frame.read_byte())
+                        .ok_or_else(|| self.malformed("unknown cast type"))?;
+                    let value = self.pop();
+                    let result = self.cast(value, ty)?;
+                    self.push(result);
+                }
+                OpCode::Format => {
+                    let start = self.stack.len() - frame.read_byte() as usize - 1;
+                    let result = self.interpolate(&self.stack[start..])?;
+                    self.stack.truncate(start);
+                    self.push(result);
+                }
+                OpCode::Jump => {
+                    let offset = frame.read_u16() as usize;
+                    frame.ip += offset;
+                }
+                OpCode::JumpIfFalse => {
+                    let offset = frame.read_u16() as usize;
+                    match self.peek(0) {
+                        Value::Bool(false) => frame.ip += offset,
+                        Value::Bool(true) => {}
+                        value => {
+                            return Err(self.error(format!(
+                                "expected a bool condition, found a value of type {}",
+                                value.type_name()
+                            )))
+                        }
+                    }
+                }
+                OpCode::Loop => {
+                    let offset = frame.read_u16() as usize;
+                    frame.ip -= offset;
+                }
+                OpCode::BuildList => {
+                    let len = frame.read_byte() as usize;
+                    // The items stay on the stack until the list is created,
+                    // so that they are rooted during a collection
+                    self.maybe_collect();
+                    let items = self.stack.split_off(self.stack.len() - len);
+                    let list = self.alloc(Object::List(items));
+                    self.push(Value::List(list));
+                }
+                OpCode::GetIndex => {
+                    let index = self.pop();
+                    let object = self.pop();
+                    let value = self.get_index(&object, &index)?;
+                    self.push(value);
+                }
+                OpCode::SetIndex => {
+                    let value = self.pop();
+                    let index = self.pop();
+                    let list = self.pop();
+                    let (items, index) = self.index(&list, &index)?;
+                    self.heap.list_mut(items)[index] = value.clone();
+                    self.push(value);
+                }
+                OpCode::Slice => {
+                    let inclusive = frame.read_byte() != 0;
+                    // The operands stay on the stack until the slice is
+                    // taken, so that a sliced list is rooted during a
+                    // collection
+                    self.maybe_collect();
+                    let end = self.pop();
+                    let start = self.pop();
+                    let object = self.pop();
+                    let result = self.slice(object, &start, &end, inclusive)?;
+                    self.push(result);
+                }
+                OpCode::Range => {
+                    let inclusive = frame.read_byte() != 0;
+                    let end = self.pop();
+                    let start = self.pop();
+                    let range = self.range(&start, &end, inclusive)?;
+                    self.push(range);
+                }
+                OpCode::RangeBounds => {
+                    let depth = self.stack.len();
+                    self.range_bound(&self.stack[depth - 2])?;
+                    self.range_bound(&self.stack[depth - 1])?;
+                }
+                OpCode::InRange => {
+                    let range = self.pop();
+                    let value = self.pop();
+                    self.push(Value::Bool(in_range(&value, &range)));
+                }
+                OpCode::Switch => {
+                    let min = frame.read_constant();
+                    let len = frame.read_u16() as usize;
+                    let value = self.pop();
+                    frame.ip += 3 * switch_index(&value, &min, len);
+                }
+                OpCode::Unmatched => {
+                    let value = self.pop();
+                    return Err(self.unmatched(&value));
+                }
+                OpCode::Class => {
+                    let count = frame.read_byte() as usize;
+                    let methods = self.stack.split_off(self.stack.len() - count);
+                    let name = self.pop();
+                    let class = class(name, methods).map_err(|message| self.malformed(message))?;
+                    self.push(class);
+                }
+                OpCode::GetField => {
+                    let name = frame.read_name();
+                    let value = self.get_field(self.peek(0).clone(), &name)?;
+                    self.pop();
+                    self.push(value);
+                }
+                OpCode::SetField => {
+                    let name = frame.read_name();
+                    let value = self.pop();
+                    let target = self.pop();
+                    self.set_field(target, name, value.clone())?;
+                    self.push(value);
+                }
+                OpCode::Call => {
+                    let argc = frame.read_byte();
+                    let base = self.stack.len() - argc as usize - 1;
+                    // Native functions can call back into the program, which
+                    // needs to know where this frame is
+                    self.frame_mut().ip = frame.ip;
+                    match self.prepare_call(base, argc)? {
+                        Prepared::Frame(function, initializer) => {
+                            *frame = CallFrame {
+                                function,
+                                ip: 0,
+                                base,
+                                initializer,
+                                generator: None,
+                            };
+                            self.frames.push(frame.clone());
+                        }
+                        Prepared::Done => self.stack.truncate(base + 1),
+                        Prepared::Blocked => {
+                            if let Some(result) = self.block()? {
+                                return Ok(result);
+                            }
+                            // Run the call again once the task resumes
+                            self.frame_mut().ip = frame.ip - 2;
+                            self.switch_task();
+                            *frame = self.frame().clone();
+                        }
+                    }
+                }
+                OpCode::Return => {
+                    let mut result = self.pop();
+                    self.frames.pop().expect("no call frame");
+                    if frame.initializer {
+                        result = self.stack[frame.base].clone();
+                    }
+                    self.stack.truncate(frame.base);
+                    if let Some(generator) = frame.generator {
+                        // The instruction that resumed the generator is told
+                        // it has finished instead
+                        self.heap.generator_mut(generator).state = GeneratorState::Done;
+                        self.push(Value::Unit);
+                        result = Value::Bool(false);
+                    }
+                    if self.callback == Some(self.frames.len()) {
+                        return Ok(result);
+                    }
+                    match self.frames.last() {
+                        Some(caller) => *frame = caller.clone(),
+                        None => match self.finish_task(result) {
+                            Some(result) => return Ok(result),
+                            None => {
+                                *frame = self.frame().clone();
+                                continue;
+                            }
+                        },
+                    }
+                    self.push(result);
+                }
+                OpCode::Spawn => {
+                    let argc = frame.read_byte();
+                    let base = self.stack.len() - argc as usize - 1;
+                    self.spawn(base, argc)?;
+                    self.stack.truncate(base);
+                }
+                OpCode::Next => {
+                    let slot = frame.base + frame.read_byte() as usize;
+                    if let Value::Range(range) = &mut self.stack[slot] {
+                        let next = range.next();
+                        self.push(next.map_or(Value::Unit, Value::Int));
+                        self.push(Value::Bool(next.is_some()));
+                        continue;
+                    }
+                    let iterator = self.stack[slot].clone();
+                    let Some(resumed) = self.resume_generator(&iterator)? else {
+                        self.push(Value::Unit);
+                        self.push(Value::Bool(false));
+                        continue;
+                    };
+                    self.frame_mut().ip = frame.ip;
+                    let base = self.stack.len();
+                    self.stack.extend(resumed.slots);
+                    *frame = CallFrame {
+                        function: resumed.function,
+                        ip: resumed.ip,
+                        base,
+                        initializer: false,
+                        generator: Some(resumed.generator),
+                    };
+                    self.frames.push(frame.clone());
+                }
+                OpCode::Yield => {
+                    let value = self.pop();
+                    let generator = frame
+                        .generator
+                        .ok_or_else(|| self.malformed("yield outside of a generator"))?;
+                    let slots = self.stack.split_off(frame.base);
+                    self.heap.generator_mut(generator).state = GeneratorState::Suspended {
+                        ip: frame.ip,
+                        slots,
+                    };
+                    self.frames.pop().expect("no call frame");
+                    *frame = self.frame().clone();
+                    self.push(value);
+                    self.push(Value::Bool(true));
+                }
+            }
+        }
+    }
+
+    /// Write the stack slots of the frame starting at `base`, followed by
+    /// the instruction about to run, to the tracer.
+    #[cold]
+    fn trace_instruction(&mut self, base: usize, instruction: &str) {
+        let Some(tracer) = &mut self.tracer else {
+            return;
+        };
+        let mut slots = String::from("          ");
+        for value in &self.stack[base..] {
+            slots.push_str(&format!("[ {} ]", value));
+        }
+        let _ = writeln!(tracer, "{}\n{}", slots, instruction);
+    }
+
+    /// Tell the observer that a call to `function`, with the `argc`
+    /// arguments following stack slot `base`, is starting.
+    #[cold]
+    fn observe_call(&mut self, function: &Function, base: usize, argc: u8) {
+        if let Some(observer) = &mut self.observer {
+            observer.on_enter_function(function, &self.stack[base + 1..=base + argc as usize]);
+        }
+    }
+
+    /// Use up the fuel for one instruction, failing if there is none left
+    /// or the program has been interrupted.
+    #[inline]
+    fn consume_fuel(&mut self) -> RunResult<()> {
+        self.check_interrupt()?;
+        if self.out_of_memory {
+            return Err(self.out_of_memory());
+        }
+        self.executed += 1;
+        match &mut self.fuel {
+            Some(0) => Err(self.out_of_fuel()),
+            Some(fuel) => {
+                *fuel -= 1;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Use up the fuel for sleeping `ms` milliseconds, one unit for each.
+    /// Returns how long the program may sleep, which is less than `ms` if
+    /// there isn't enough fuel left.
+    #[cfg(feature = "clock")]
+    fn consume_sleep_fuel(&mut self, ms: u64) -> u64 {
+        let Some(fuel) = self.fuel else {
+            return ms;
+        };
+        // While stepping, sleeping uses the program's fuel rather than the
+        // step's instructions where it can
+        let beyond = ms.min(self.beyond_step.unwrap_or(0));
+        let within = (ms - beyond).min(fuel);
+        if let Some(rest) = &mut self.beyond_step {
+            *rest -= beyond;
+        }
+        self.fuel = Some(fuel - within);
+        beyond + within
+    }
+
+    /// Fail if the program has been interrupted, clearing the interrupt so
+    /// that the next program can run.
+    #[inline]
+    fn check_interrupt(&self) -> RunResult<()> {
+        if self.interrupt.0.load(atomic::Ordering::Relaxed) {
+            self.interrupt.0.store(false, atomic::Ordering::Relaxed);
+            return Err(RuntimeError {
+                kind: RuntimeErrorKind::Interrupted,
+                ..self.error("the program was interrupted")
+            });
+        }
+        Ok(())
+    }
+
+    #[cold]
+    fn out_of_fuel(&self) -> RuntimeError {
+        RuntimeError {
+            kind: RuntimeErrorKind::FuelExhausted,
+            ..self.error("the program ran out of fuel")
+        }
+    }
+
+    #[cold]
+    fn out_of_memory(&mut self) -> RuntimeError {
+        self.out_of_memory = false;
+        let max = self.sandbox.max_objects.unwrap_or_default();
+        RuntimeError {
+            kind: RuntimeErrorKind::MemoryExhausted,
+            ..self.error(format!(
+                "the program used more than the {} objects the sandbox allows",
+                max
+            ))
+        }
+    }
+
+    #[cold]
+    fn malformed(&self, message: &str) -> RuntimeError {
+        self.error(format!("malformed bytecode: {}", message))
+    }
+
+    fn undefined(&self, name: &str) -> RuntimeError {
+        self.error(format!("undefined variable `{}`", name))
+    }
+
+    /// Read the item of a list, or the character of a string, at `index`.
+    /// Strings are indexed by character rather than by byte.
+    fn get_index(&self, object: &Value, index: &Value) -> RunResult<Value> {
+        match object {
+            Value::Str(string) => {
+                let position = self.position(index, "string")?;
+                position
+                    .and_then(|position| string.chars().nth(position))
+                    .map(Value::Char)
+                    .ok_or_else(|| self.out_of_bounds(index, "string", string.chars().count()))
+            }
+            object => {
+                let (list, index) = self.index(object, index)?;
+                Ok(self.heap.list(list)[index].clone())
+            }
+        }
+    }
+
+    /// Check that `list` can be indexed by `index`, returning the list and
+    /// the index as a `usize`.
+    fn index(&self, list: &Value, index: &Value) -> RunResult<(ObjRef, usize)> {
+        let list = match list {
+            Value::List(list) => *list,
+            Value::Str(_) => return Err(self.error("strings can't be changed")),
+            value => {
+                return Err(self.error(format!(
+                    "cannot index into a value of type {}",
+                    value.type_name()
+                )))
+            }
+        };
+        let position = self.position(index, "list")?;
+
+        let items = self.heap.list(list);
+        match position {
+            Some(position) if position < items.len() => Ok((list, position)),
+            _ => Err(self.out_of_bounds(index, "list", items.len())),
+        }
+    }
+
+    /// Return the list of the items of `object` between `start` and `end`,
+    /// or the string of its characters between them. The caller must have
+    /// rooted `object`, in case the new list triggers a collection.
+    fn slice(
+        &mut self,
+        object: Value,
+        start: &Value,
+        end: &Value,
+        inclusive: bool,
+    ) -> RunResult<Value> {
+        let (kind, len) = match &object {
+            Value::List(list) => ("list", self.heap.list(*list).len()),
+            Value::Str(string) => ("string", string.chars().count()),
+            value => {
+                return Err(self.error(format!(
+                    "cannot slice a value of type {}",
+                    value.type_name()
+                )))
+            }
+        };
+        let range = format!("{}{}{}", start, if inclusive { "..=" } else { ".." }, end);
+        let first = self.position(start, kind)?;
+        let last = self.position(end, kind)?;
+        let last = if inclusive {
+            last.and_then(|last| last.checked_add(1))
+        } else {
+            last
+        };
+        let (first, last) = match (first, last) {
+            (Some(first), Some(last)) if last <= len => (first, last),
+            _ => {
+                return Err(self.error(format!(
+                    "slice {} is out of bounds for a {} of length {}",
+                    range, kind, len
+                )))
+            }
+        };
+        if first > last {
+            return Err(self.error(format!("slice {} starts after it ends", range)));
+        }
+
+        Ok(match object {
+            Value::List(list) => {
+                let items = self.heap.list(list)[first..last].to_vec();
+                Value::List(self.alloc(Object::List(items)))
+            }
+            Value::Str(string) => {
+                let slice: String = string.chars().skip(first).take(last - first).collect();
+                Value::from(slice.as_str())
+            }
+            _ => unreachable!(),
+        })
+    }
+
+    /// Create the range from `start` to `end`, which must both be ints.
+    fn range(&self, start: &Value, end: &Value, inclusive: bool) -> RunResult<Value> {
+        Ok(Value::Range(Range {
+            start: self.range_bound(start)?,
+            end: self.range_bound(end)?,
+            inclusive,
+        }))
+    }
+
+    /// Check that `value` is an int that can bound a range, returning it.
+    fn range_bound(&self, value: &Value) -> RunResult<i64> {
+        match value {
+            Value::Int(value) => Ok(*value),
+            Value::BigInt(value) => Err(self.error(format!("range bound {} is too large", value))),
+            value => Err(self.error(format!(
+                "range bounds must be ints, found a value of type {}",
+                value.type_name()
+            ))),
+        }
+    }
+
+    /// Check that `index` is an int, returning it as a `usize` if it isn't
+    /// negative or too large for one.
+    fn position(&self, index: &Value, kind: &str) -> RunResult<Option<usize>> {
+        match index {
+            Value::Int(index) => Ok(usize::try_from(*index).ok()),
+            Value::BigInt(_) => Ok(None),
+            value => Err(self.error(format!(
+                "{} indices must be ints, found a value of type {}",
+                kind,
+                value.type_name()
+            ))),
+        }
+    }
+
+    fn out_of_bounds(&self, index: &Value, kind: &str, len: usize) -> RuntimeError {
+        self.error(format!(
+            "index {} is out of bounds for a {} of length {}",
+            index, kind, len
+        ))
+    }
+
+    /// Read the field or method `name` of `target`, which must still be
+    /// rooted. Methods are bound to the target, so that it is passed as
+    /// `self` when they are called.
+    fn get_field(&mut self, target: Value, name: &str) -> RunResult<Value> {
+        let obj = match target {
+            Value::Instance(obj) => obj,
+            Value::Str(_) | Value::List(_) | Value::Range(_) => {
+                let method = match Native::method(&target, name) {
+                    Some(native) => Method::Native(native),
+                    None => {
+                        return Err(self.error(format!(
+                            "{}s have no method `{}`",
+                            target.type_name(),
+                            name
+                        )))
+                    }
+                };
+                // The receiver is still on the stack, so it survives a
+                // collection
+                self.maybe_collect();
+                let bound = self.alloc(Object::BoundMethod(BoundMethod {
+                    receiver: target,
+                    method,
+                }));
+                return Ok(Value::BoundMethod(bound));
+            }
+            Value::Module(module) => {
+                return module.member(name).ok_or_else(|| {
+                    self.error(format!(
+                        "module `{}` has no member `{}`",
+                        module.name(),
+                        name
+                    ))
+                })
+            }
+            value => {
+                return Err(self.error(format!(
+                    "cannot access field `{}` on a value of type {}",
+                    name,
+                    value.type_name()
+                )))
+            }
+        };
+
+        let instance = self.heap.instance(obj);
+        if let Some(value) = instance.fields.get(name) {
+            return Ok(value.clone());
+        }
+        let class = instance.class.clone();
+        let method = match class.methods.get(name) {
+            Some(method) => Method::Function(method.clone()),
+            None => match class
+                .host
+                .as_ref()
+                .and_then(|host| self.host_field(&target, host, name))
+            {
+                Some(Ok(value)) => return Ok(value),
+                Some(Err(host)) => Method::Host(host),
+                None => {
+                    return Err(self.error(format!(
+                        "`{}` instance has no field or method `{}`",
+                        class.name, name
+                    )))
+                }
+            },
+        };
+
+        // The instance is still on the stack, so it survives a collection
+        self.maybe_collect();
+        let bound = self.alloc(Object::BoundMethod(BoundMethod {
+            receiver: Value::Instance(obj),
+            method,
+        }));
+        Ok(Value::BoundMethod(bound))
+    }
+
+    fn set_field(&mut self, target: Value, name: Rc<str>, value: Value) -> RunResult<()> {
+        match target {
+            Value::Instance(obj) => {
+                if let Some(host) = self.heap.instance(obj).class.host.clone() {
+                    if let Some(result) = self.set_host_field(&target, &host, &name, &value) {
+                        return result;
+                    }
+                }
+                self.heap.instance_mut(obj).fields.insert(name, value);
+                Ok(())
+            }
+            target => Err(self.error(format!(
+                "cannot set field `{}` on a value of type {}",
+                name,
+                target.type_name()
+            ))),
+        }
+    }
+
+    /// Prepare a call of the value in stack slot `base`, whose `argc`
+    /// arguments follow it.
+    fn prepare_call(&mut self, base: usize, argc: u8) -> RunResult<Prepared> {
+        let (function, initializer) = match self.stack[base].clone() {
+            Value::Function(function) => (function, false),
+            Value::BoundMethod(obj) => {
+                let bound = self.heap.bound_method(obj).clone();
+                self.stack[base] = bound.receiver;
+                match bound.method {
+                    Method::Function(function) => (function, false),
+                    Method::Native(native) => return self.prepare_native(native, base, argc),
+                    Method::Host(host) => {
+                        self.call_host(&host, base, argc, true)?;
+                        return Ok(Prepared::Done);
+                    }
+                }
+            }
+            Value::Class(class) => {
+                if let Some(host) = class.host.clone() {
+                    self.construct_host(class, &host, base, argc)?;
+                    return Ok(Prepared::Done);
+                }
+                // The arguments are still on the stack, so they survive a
+                // collection
+                self.maybe_collect();
+                let instance = self.alloc(Object::Instance(Instance {
+                    class: class.clone(),
+                    fields: HashMap::new(),
+                    data: None,
+                }));
+                self.stack[base] = Value::Instance(instance);
+
+                match class.initializer() {
+                    Some(init) => (init.clone(), true),
+                    None if argc == 0 => return Ok(Prepared::Done),
+                    None => {
+                        return Err(self.error(format!(
+                            "`{}` expects 0 arguments, but {} were given",
+                            class.name, argc
+                        )))
+                    }
+                }
+            }
+            Value::Native(native) => return self.prepare_native(native, base, argc),
+            Value::Host(host) => {
+                self.call_host(&host, base, argc, false)?;
+                return Ok(Prepared::Done);
+            }
+            value => {
+                return Err(self.error(format!("cannot call a value of type {}", value.type_name())))
+            }
+        };
+
+        if function.arity != argc {
+            return Err(self.arity_mismatch(&function.name, function.arity, argc));
+        }
+        if function.generator {
+            // The arguments are still on the stack, so they survive a
+            // collection
+            self.maybe_collect();
+            let slots = self.stack[base..=base + argc as usize].to_vec();
+            let generator = self.alloc(Object::Generator(Generator {
+                function,
+                backend: self.backend,
+                state: GeneratorState::Suspended { ip: 0, slots },
+            }));
+            self.stack[base] = Value::Generator(generator);
+            return Ok(Prepared::Done);
+        }
+        // Functions declared on the ast backend have no code to run
+        if function.chunk.code.is_empty() && self.ast_frames.is_empty() {
+            return Err(self.error(format!(
+                "`{}` was declared on the ast backend, so it can only be called there",
+                function.name
+            )));
+        }
+        if self.call_depth() >= MAX_CALL_DEPTH {
+            return Err(self.call_depth_exceeded(&function));
+        }
+        // Native code can't be limited by fuel, profiled, traced or observed
+        #[cfg(feature = "jit")]
+        if !initializer
+            && self.fuel.is_none()
+            && self.profile.is_none()
+            && self.tracer.is_none()
+            && self.observer.is_none()
+        {
+            let args = &self.stack[base + 1..=base + argc as usize];
+            if let Some(result) = self.jit.call(&function, args, &self.interrupt.0) {
+                self.stack[base] = result;
+                return Ok(Prepared::Done);
+            }
+        }
+        if self.observer.is_some() {
+            self.observe_call(&function, base, argc);
+        }
+        Ok(Prepared::Frame(function, initializer))
+    }
+
+    /// Call `native` with the `argc` arguments following stack slot `base`,
+    /// leaving the result in `base`.
+    fn prepare_native(&mut self, native: Native, base: usize, argc: u8) -> RunResult<Prepared> {
+        if native.is_variadic() && argc < native.arity() {
+            return Err(self.error(format!(
+                "`{}` expects at least {} argument{}, but {} were given",
+                native.name(),
+                native.arity(),
+                if native.arity() == 1 { "" } else { "s" },
+                argc
+            )));
+        }
+        if !native.is_variadic() && native.arity() != argc {
+            return Err(self.arity_mismatch(native.name(), native.arity(), argc));
+        }
+        Ok(match self.call_native(native, base, argc)? {
+            Some(result) => {
+                self.stack[base] = result;
+                Prepared::Done
+            }
+            None => Prepared::Blocked,
+        })
+    }
+
+    /// Call `callee` with `args` on behalf of a native function, running it
+    /// to completion on the current backend before returning its result.
+    /// Tasks aren't switched while it runs.
+    pub(super) fn call_value(&mut self, callee: Value, args: &[Value]) -> RunResult<Value> {
+        let base = self.stack.len();
+        self.stack.push(callee);
+        self.stack.extend_from_slice(args);
+        let (function, initializer) = match self.prepare_call(base, args.len() as u8)? {
+            Prepared::Frame(function, initializer) => (function, initializer),
+            Prepared::Done => {
+                let result = self.stack[base].clone();
+                self.stack.truncate(base);
+                return Ok(result);
+            }
+            Prepared::Blocked => return Err(self.blocked_in_callback()),
+        };
+
+        let result = if !self.ast_frames.is_empty() {
+            let outer = self.callback.replace(self.ast_frames.len());
+            let result = self.call_back_ast(function, base, initializer);
+            self.callback = outer;
+            result
+        } else if !self.register_frames.is_empty() {
+            let outer = self.callback.replace(self.register_frames.len());
+            let result = self
+                .push_register_frame(function, base, initializer)
+                .and_then(|()| self.execute_registers());
+            self.callback = outer;
+            result
+        } else {
+            let outer = self.callback.replace(self.frames.len());
+            self.frames.push(CallFrame {
+                function,
+                ip: 0,
+                base,
+                initializer,
+                generator: None,
+            });
+            let result = self.execute();
+            self.callback = outer;
+            result
+        };
+        match result {
+            Ok(result) => {
+                self.stack.truncate(base);
+                Ok(result)
+            }
+            Err(error) => {
+                // The frames are left in place, so they appear in the trace
+                self.callback_failed = true;
+                Err(error)
+            }
+        }
+    }
+
+    #[cold]
+    fn blocked_in_callback(&self) -> RuntimeError {
+        self.error("cannot wait for another task in a function called by a native function")
+    }
+
+    /// Prepare to resume `iterator` for the next value, marking it as
+    /// running. Returns `None` if it has already finished.
+    fn resume_generator(&mut self, iterator: &Value) -> RunResult<Option<Resumed>> {
+        let obj = match iterator {
+            Value::Generator(obj) => *obj,
+            value => {
+                return Err(self.error(format!(
+                    "cannot iterate over a value of type {}",
+                    value.type_name()
+                )))
+            }
+        };
+
+        let backend = self.backend;
+        let generator = self.heap.generator_mut(obj);
+        let function = generator.function.clone();
+        let state = match &generator.state {
+            _ if generator.backend != backend => {
+                Err("cannot resume a generator created on another backend")
+            }
+            GeneratorState::Running => Err("generator is already running"),
+            GeneratorState::Done => return Ok(None),
+            GeneratorState::Suspended { .. } => Ok(()),
+        };
+        state.map_err(|message| self.error(message))?;
+        if self.call_depth() >= MAX_CALL_DEPTH {
+            return Err(self.call_depth_exceeded(&function));
+        }
+
+        let generator = self.heap.generator_mut(obj);
+        match mem::replace(&mut generator.state, GeneratorState::Running) {
+            GeneratorState::Suspended { ip, slots } => Ok(Some(Resumed {
+                generator: obj,
+                function,
+                ip,
+                slots,
+            })),
+            _ => unreachable!("the generator is suspended"),
+        }
+    }
+
+    /// Return the number of calls in progress on any backend.
+    fn call_depth(&self) -> usize {
+        self.frames.len() + self.register_frames.len() + self.ast_frames.len()
+    }
+
+    fn arity_mismatch(&self, name: &str, arity: u8, argc: u8) -> RuntimeError {
+        self.error(format!(
+            "`{}` expects {} argument{}, but {} were given",
+            name,
+            arity,
+            if arity == 1 { "" } else { "s" },
+            argc
+        ))
+    }
+
+    /// Create the error for a call that would exceed [`MAX_CALL_DEPTH`],
+    /// listing the chain of calls that led to it. Repeated calls to the same
+    /// function are counted rather than listed, and the middle of a long
+    /// chain is left out.
+    #[cold]
+    fn call_depth_exceeded(&self, callee: &Function) -> RuntimeError {
+        let functions = self.frames.iter().map(|frame| &frame.function);
+        let functions = functions
+            .chain(self.register_frames.iter().map(|frame| frame.function()))
+            .chain(self.ast_frames.iter().map(|frame| frame.function()));
+
+        let mut calls: Vec<(&str, usize)> = Vec::new();
+        for name in functions
+            .map(|function| function.name.as_str())
+            .chain([callee.name.as_str()])
+        {
+            match calls.last_mut() {
+                Some((last, count)) if *last == name => *count += 1,
+                _ => calls.push((name, 1)),
+            }
+        }
+
+        let mut chain: Vec<_> = calls
+            .iter()
+            .map(|&(name, count)| match count {
+                1 => format!("`{}`", name),
+                count => format!("`{}` ({} calls)", name, count),
+            })
+            .collect();
+        if chain.len() > 8 {
+            chain.splice(4..chain.len() - 4, ["...".to_string()]);
+        }
+        self.error(format!(
+            "maximum call depth exceeded: {}",
+            chain.join(" -> ")
+        ))
+    }
+
+    /// Apply `-` or `!` to a value.
+    fn unary(&self, op: OpCode, value: Value) -> RunResult<Value> {
+        Ok(match (op, value) {
+            (OpCode::Negate, Value::Int(value)) => match value.checked_neg() {
+                Some(value) => Value::Int(value),
+                None => self.overflowed(BigInt::from(value).neg(), || format!("-({})", value))?,
+            },
+            (OpCode::Negate, Value::BigInt(value)) => value.neg().into_value(),
+            (OpCode::Negate, Value::Float(value)) => Value::Float(-value),
+            (OpCode::Not, Value::Bool(value)) => Value::Bool(!value),
+            (op, value) => {
+                let operator = if op == OpCode::Not {
+                    "apply `!` to"
+                } else {
+                    "negate"
+                };
+                return Err(self.error(format!(
+                    "cannot {} a value of type {}",
+                    operator,
+                    value.type_name()
+                )));
+            }
+        })
+    }
+
+    /// Apply an equality operator to any two values, or an arithmetic or
+    /// comparison operator to two numbers. Arithmetic on an int and a float
+    /// converts the int to the nearest float, while comparisons between
+    /// them are exact.
+    fn binary(&self, op: OpCode, left: Value, right: Value) -> RunResult<Value> {
+        Ok(match (left, right) {
+            (Value::Int(a), Value::Float(b)) => mixed(op, BigInt::from(a), b, false),
+            (Value::BigInt(a), Value::Float(b)) => mixed(op, (*a).clone(), b, false),
+            (Value::Float(a), Value::Int(b)) => mixed(op, BigInt::from(b), a, true),
+            (Value::Float(a), Value::BigInt(b)) => mixed(op, (*b).clone(), a, true),
+            (left, right) if op == OpCode::Equal => Value::Bool(left == right),
+            (left, right) if op == OpCode::NotEqual => Value::Bool(left != right),
+            (Value::Int(a), Value::Int(b)) => {
+                let result = match op {
+                    OpCode::Add => a.checked_add(b),
+                    OpCode::Subtract => a.checked_sub(b),
+                    OpCode::Multiply => a.checked_mul(b),
+                    OpCode::Divide if b == 0 => return Err(self.error("division by zero")),
+                    OpCode::Divide => a.checked_div(b),
+                    OpCode::Remainder if b == 0 => return Err(self.error("division by zero")),
+                    OpCode::Remainder => a.checked_rem(b),
+                    _ => return Ok(Value::Bool(compare(op, a, b))),
+                };
+                match result {
+                    Some(result) => Value::Int(result),
+                    None => self.big_binary(op, BigInt::from(a), BigInt::from(b))?,
+                }
+            }
+            (Value::BigInt(a), Value::BigInt(b)) => {
+                self.big_binary(op, (*a).clone(), (*b).clone())?
+            }
+            (Value::BigInt(a), Value::Int(b)) => {
+                self.big_binary(op, (*a).clone(), BigInt::from(b))?
+            }
+            (Value::Int(a), Value::BigInt(b)) => {
+                self.big_binary(op, BigInt::from(a), (*b).clone())?
+            }
+            (Value::Str(a), Value::Str(b)) if op == OpCode::Add => {
+                Value::from(format!("{}{}", a, b).as_str())
+            }
+            // Strings are compared by their characters, in order
+            (Value::Str(a), Value::Str(b)) if is_comparison(op) => Value::Bool(compare(op, a, b)),
+            (Value::Char(a), Value::Char(b)) if is_comparison(op) => Value::Bool(compare(op, a, b)),
+            (Value::Float(a), Value::Float(b)) => match op {
+                OpCode::Add => Value::Float(a + b),
+                OpCode::Subtract => Value::Float(a - b),
+                OpCode::Multiply => Value::Float(a * b),
+                OpCode::Divide => Value::Float(a / b),
+                OpCode::Remainder => Value::Float(a % b),
+                _ => Value::Bool(compare(op, a, b)),
+            },
+            (left, right) => {
+                return Err(self.error(format!(
+                    "unsupported operand types for `{}`: {} and {}",
+                    operator(op),
+                    left.type_name(),
+                    right.type_name()
+                )))
+            }
+        })
+    }
+}
+
+impl Vm {
+    /// Convert `value` with `as`. Floats are truncated towards zero when
+    /// they become ints.
+    fn cast(&self, value: Value, ty: CastType) -> RunResult<Value> {
+        Ok(match (value, ty) {
+            (value @ (Value::Int(_) | Value::BigInt(_)), CastType::Int) => value,
+            (Value::Bool(value), CastType::Int) => Value::Int(value as i64),
+            (Value::Char(value), CastType::Int) => Value::Int(value as i64),
+            (Value::Float(value), CastType::Int) if !value.is_finite() => {
+                return Err(self.error(format!("cannot cast {:?} to int", value)))
+            }
+            // The bounds are powers of two, so they are exact as floats
+            (Value::Float(value), CastType::Int)
+                if value >= i64::MIN as f64 && value < i64::MAX as f64 =>
+            {
+                Value::Int(value as i64)
+            }
+            (Value::Float(value), CastType::Int) => {
+                let int = BigInt::from_f64(value.trunc());
+                self.overflowed(int, || format!("{:?} as int", value))?
+            }
+            (Value::Int(value), CastType::Float) => Value::Float(value as f64),
+            (Value::BigInt(value), CastType::Float) => Value::Float(value.to_f64()),
+            (value @ Value::Float(_), CastType::Float) => value,
+            (value, ty) => {
+                return Err(self.error(format!(
+                    "cannot cast a value of type {} to {}",
+                    value.type_name(),
+                    ty.name()
+                )))
+            }
+        })
+    }
+
+    /// Apply an arithmetic or comparison operator to ints, at least one of
+    /// which doesn't fit in an `i64`.
+    fn big_binary(&self, op: OpCode, a: BigInt, b: BigInt) -> RunResult<Value> {
+        let result = match op {
+            OpCode::Add => a.add(&b),
+            OpCode::Subtract => a.sub(&b),
+            OpCode::Multiply => a.mul(&b),
+            OpCode::Divide if b == BigInt::from(0) => return Err(self.error("division by zero")),
+            OpCode::Divide => a.div(&b),
+            OpCode::Remainder if b == BigInt::from(0) => return Err(self.error("division by zero")),
+            OpCode::Remainder => a.rem(&b),
+            _ => return Ok(Value::Bool(compare(op, &a, &b))),
+        };
+        self.overflowed(result, || format!("{} {} {}", a, operator(op), b))
+    }
+
+    /// Return `result` as a value, failing if it doesn't fit in an `i64`
+    /// and big ints are off. The failed operation is described by
+    /// `operation`.
+    fn overflowed(&self, result: BigInt, operation: impl FnOnce() -> String) -> RunResult<Value> {
+        match result.into_value() {
+            Value::BigInt(_) if !self.big_ints => {
+                Err(self.error(format!("integer overflow: {}", operation())))
+            }
+            value => Ok(value),
+        }
+    }
+}
+
+/// Create a class named by the string `name`, with the functions in
+/// `methods`. The compiler always provides these, so other values mean the
+/// bytecode is malformed.
+fn class(name: Value, methods: Vec<Value>) -> Result<Value, &'static str> {
+    let methods = methods
+        .into_iter()
+        .map(|method| match method {
+            Value::Function(method) => Ok((method.name.clone(), method)),
+            _ => Err("method is not a function"),
+        })
+        .collect::<Result<_, _>>()?;
+    let name = match name {
+        Value::Str(name) => name.to_string(),
+        _ => return Err("class name is not a string"),
+    };
+    Ok(Value::Class(Rc::new(Class {
+        name,
+        methods,
+        host: None,
+    })))
+}
+
+fn is_comparison(op: OpCode) -> bool {
+    matches!(
+        op,
+        OpCode::Greater | OpCode::GreaterEqual | OpCode::Less | OpCode::LessEqual
+    )
+}
+
+/// Return the source operator an instruction was compiled from.
+fn operator(op: OpCode) -> &'static str {
+    match op {
+        OpCode::Add => "+",
+        OpCode::Subtract => "-",
+        OpCode::Multiply => "*",
+        OpCode::Divide => "/",
+        OpCode::Remainder => "%",
+        OpCode::Greater => ">",
+        OpCode::GreaterEqual => ">=",
+        OpCode::Less => "<",
+        OpCode::LessEqual => "<=",
+        _ => unreachable!("{} is not a binary operator", op),
+    }
+}
+
+/// Returns true if `range` is a range containing `value`, as a range
+/// pattern checks.
+fn in_range(value: &Value, range: &Value) -> bool {
+    match (value.as_whole_int(), range) {
+        (Some(value), Value::Range(range)) => range.contains(value),
+        _ => false,
+    }
+}
+
+/// Return the entry of a jump table of `len` entries starting at the int
+/// `min` that `value` selects, which is `len` for values outside of it.
+fn switch_index(value: &Value, min: &Value, len: usize) -> usize {
+    let (Some(value), Value::Int(min)) = (value.as_whole_int(), min) else {
+        return len;
+    };
+    match usize::try_from(value as i128 - *min as i128) {
+        Ok(index) if index < len => index,
+        _ => len,
+    }
+}
+
+/// Apply an arithmetic or comparison operator to an int and a float, which
+/// is the left operand if `reversed` is true.
+fn mixed(op: OpCode, int: BigInt, float: f64, reversed: bool) -> Value {
+    match op {
+        OpCode::Add | OpCode::Subtract | OpCode::Multiply | OpCode::Divide | OpCode::Remainder => {
+            let (a, b) = if reversed {
+                (float, int.to_f64())
+            } else {
+                (int.to_f64(), float)
+            };
+            Value::Float(match op {
+                OpCode::Add => a + b,
+                OpCode::Subtract => a - b,
+                OpCode::Multiply => a * b,
+                OpCode::Divide => a / b,
+                _ => a % b,
+            })
+        }
+        _ => {
+            let ordering = int.cmp_f64(float);
+            let ordering = if reversed {
+                ordering.map(Ordering::reverse)
+            } else {
+                ordering
+            };
+            Value::Bool(match op {
+                OpCode::Equal => ordering == Some(Ordering::Equal),
+                OpCode::NotEqual => ordering != Some(Ordering::Equal),
+                OpCode::Greater => ordering == Some(Ordering::Greater),
+                OpCode::GreaterEqual => {
+                    matches!(ordering, Some(Ordering::Greater | Ordering::Equal))
+                }
+                OpCode::Less => ordering == Some(Ordering::Less),
+                _ => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+            })
+        }
+    }
+}
+
+/// Apply a comparison instruction to two values.
+fn compare<T: PartialOrd>(op: OpCode, a: T, b: T) -> bool {
+    match op {
+        OpCode::Greater => a > b,
+        OpCode::GreaterEqual => a >= b,
+        OpCode::Less => a < b,
+        OpCode::LessEqual => a <= b,
+        _ => unreachable!("{} is not a comparison", op),
+    }
+}
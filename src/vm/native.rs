@@ -0,0 +1,1202 @@
+//! Native functions are built into the VM rather than compiled from Meow.
+//! Each one is defined as a global when a [`Vm`] is created, so programs
+//! call them like any other function. Methods of strings, lists and ranges,
+//! such as `"meow".len()`, are native functions too, which are bound to the
+//! value they are read from instead of being globals, as are the members of
+//! built-in [`Module`]s, which are loaded with `import`.
+
+use super::{
+    bigint::BigInt, heap::Object, json, sandbox::Capability, ObjRef, RunResult, RuntimeError,
+    RuntimeErrorKind, Vm,
+};
+#[cfg(feature = "clock")]
+use crate::clock::{Instant, SystemTime, UNIX_EPOCH};
+use crate::value::{CastType, Value};
+use std::{cmp::Ordering, collections::VecDeque, env, f64::consts::PI, mem, rc::Rc};
+#[cfg(feature = "clock")]
+use std::{thread, time::Duration};
+
+/// The longest `sleep` waits before checking whether the program has been
+/// interrupted.
+#[cfg(feature = "clock")]
+const SLEEP_SLICE: Duration = Duration::from_millis(10);
+
+/// How many lists deep values can be compared, since lists can contain
+/// themselves.
+const MAX_COMPARE_DEPTH: usize = 128;
+
+/// A function built into the VM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Native {
+    /// `channel()` creates a channel, which tasks use to pass values to each
+    /// other.
+    Channel,
+    /// `send(channel, value)` adds a value to a channel without waiting for
+    /// it to be received.
+    Send,
+    /// `receive(channel)` removes the oldest value from a channel, waiting
+    /// for another task to send one if it is empty.
+    Receive,
+    /// `print(value)` writes a value to the VM's output. Given more
+    /// arguments, as in `print("{} lives", 9)`, the first is a
+    /// [format string](super::format) that the rest fill in.
+    Print,
+    /// `println(value)` writes a value to the VM's output, followed by a
+    /// newline. It takes format strings as `print` does.
+    Println,
+    /// `read_line()` reads a line from the VM's input, without its line
+    /// ending, or returns `{}` at the end of the input. Given arguments, it
+    /// prints them first as a prompt, as `print` does.
+    ReadLine,
+    /// `format(template, ...)` returns a [format string](super::format)
+    /// with its placeholders filled in by the rest of the arguments.
+    Format,
+    /// `assert(condition)` stops the program with an error if a condition
+    /// is false. A message can follow the condition, which is a format
+    /// string if there are more arguments, as with `print`.
+    Assert,
+    /// `panic(message)` stops the program with an error. It takes format
+    /// strings as `print` does.
+    Panic,
+    /// `int(value)` converts a value to an int as `as int` does, or parses
+    /// a string of decimal digits.
+    Int,
+    /// `float(value)` converts a value to a float as `as float` does, or
+    /// parses a string.
+    Float,
+    /// `str(value)` returns a value as a string, written as `print` would.
+    Str,
+    /// `char(value)` returns the char with a code point, or the only char
+    /// in a string.
+    Char,
+    /// `type_of(value)` returns the name of a value's type, such as
+    /// `"int"`.
+    TypeOf,
+    /// `sort(list)` sorts a list in place, from the smallest value to the
+    /// largest. Numbers are ordered by value whatever their type, strings
+    /// and chars by code point, `false` before `true`, and lists by their
+    /// elements in turn. Like `==`, only a list is equal to itself, so
+    /// different lists with the same elements are put in an order of their
+    /// own. Values of other types, or of different types, can't be
+    /// compared.
+    Sort,
+    /// `sort_by(list, key)` sorts a list in place by the value a function
+    /// returns for each element, comparing them as `sort` does. Elements
+    /// with equal keys keep their order.
+    SortBy,
+    /// `string.len()` returns the number of characters in a string,
+    /// `list.len()` the number of elements in a list, and `range.len()` the
+    /// number of ints in a range.
+    Len,
+    /// `string.to_upper()` returns a string in uppercase.
+    ToUpper,
+    /// `string.to_lower()` returns a string in lowercase.
+    ToLower,
+    /// `string.trim()` returns a string without leading and trailing
+    /// whitespace.
+    Trim,
+    /// `string.split(separator)` returns a list of the parts of a string
+    /// between each occurrence of a separator.
+    Split,
+    /// `string.contains(pattern)` returns whether a string contains
+    /// another, and `range.contains(value)` whether a range contains an int.
+    Contains,
+    /// `string.replace(from, to)` returns a string with every occurrence of
+    /// one string replaced by another.
+    Replace,
+    /// `string.starts_with(prefix)` returns whether a string starts with
+    /// another.
+    StartsWith,
+    /// `math.sqrt(x)` returns the square root of a number.
+    Sqrt,
+    /// `math.pow(x, y)` returns `x` raised to the power of `y`, as a float.
+    Pow,
+    /// `math.abs(x)` returns the absolute value of a number.
+    Abs,
+    /// `math.floor(x)` returns the largest whole number no greater than a
+    /// number.
+    Floor,
+    /// `math.ceil(x)` returns the smallest whole number no less than a
+    /// number.
+    Ceil,
+    /// `math.min(list)` returns the smallest element of a list, comparing
+    /// them as `sort` does. Given more than one argument, as in
+    /// `math.min(a, b)`, it returns the smallest of them instead.
+    Min,
+    /// `math.max(list)` returns the largest element of a list, or the
+    /// largest argument if it is given more than one, as `math.min` does.
+    Max,
+    /// `math.sin(x)` returns the sine of an angle in radians.
+    Sin,
+    /// `math.cos(x)` returns the cosine of an angle in radians.
+    Cos,
+    /// `math.tan(x)` returns the tangent of an angle in radians.
+    Tan,
+    /// `math.asin(x)` returns the arcsine of a number, in radians.
+    Asin,
+    /// `math.acos(x)` returns the arccosine of a number, in radians.
+    Acos,
+    /// `math.atan(x)` returns the arctangent of a number, in radians.
+    Atan,
+    /// `fs.read_to_string(path)` returns the contents of a file.
+    ReadToString,
+    /// `fs.write(path, contents)` replaces the contents of a file, creating
+    /// it if needed.
+    Write,
+    /// `fs.exists(path)` returns whether there is a file at a path.
+    Exists,
+    /// `fs.lines(path)` returns a list of the lines in a file, without their
+    /// line endings.
+    Lines,
+    /// `sleep(ms)` waits for a number of milliseconds, or until the program
+    /// is interrupted.
+    Sleep,
+    /// `time.now()` returns the number of seconds since the Unix epoch, as a
+    /// float.
+    Now,
+    /// `time.elapsed()` returns the number of seconds since the VM was
+    /// created, as a float. Unlike `time.now()`, it never goes backwards.
+    Elapsed,
+    /// `json.parse(text)` returns the value a string of JSON describes.
+    Parse,
+    /// `json.stringify(value)` returns a value as a string of JSON.
+    Stringify,
+    /// `json.pretty(value)` returns a value as a string of JSON, indented
+    /// over several lines.
+    Pretty,
+    /// `env.get(name)` returns the value of an environment variable, or
+    /// `{}` if it isn't set.
+    Get,
+    /// `env.vars()` returns a list of `[name, value]` pairs for every
+    /// environment variable, sorted by name.
+    Vars,
+    /// `os.platform()` returns the name of the operating system, such as
+    /// `"linux"`.
+    Platform,
+    /// `os.cwd()` returns the working directory.
+    Cwd,
+    /// `os.set_cwd(path)` changes the working directory.
+    SetCwd,
+    /// `regex.is_match(pattern, text)` returns whether a regular expression
+    /// matches anywhere in a string.
+    #[cfg(feature = "regex")]
+    IsMatch,
+    /// `regex.find_all(pattern, text)` returns a list of every
+    /// non-overlapping match of a regular expression in a string.
+    #[cfg(feature = "regex")]
+    FindAll,
+    /// `regex.replace(pattern, text, replacement)` returns a string with
+    /// every match of a regular expression replaced, where `$1` or `$name`
+    /// in the replacement stands for a capture group.
+    #[cfg(feature = "regex")]
+    ReplaceMatches,
+}
+
+/// A module built into the VM, whose members are native functions and
+/// constants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Module {
+    /// `import std.math;` loads numeric functions, and the constant `pi`.
+    Math,
+    /// `import std.fs;` loads functions that read and write files, through
+    /// the VM's [`FileSystem`](super::files::FileSystem).
+    Fs,
+    /// `import std.time;` loads functions that read the time.
+    Time,
+    /// `import std.json;` loads functions that convert values to and from
+    /// JSON.
+    Json,
+    /// `import std.env;` loads functions that read environment variables,
+    /// through the VM's [`Environment`](super::env::Environment).
+    Env,
+    /// `import std.os;` loads functions about the operating system and the
+    /// working directory.
+    Os,
+    /// `import std.regex;` loads functions that match regular expressions.
+    /// It is only available when Meow is built with the `regex` feature.
+    #[cfg(feature = "regex")]
+    Regex,
+}
+
+impl Module {
+    /// Every built-in module.
+    pub const ALL: &'static [Module] = &[
+        Module::Math,
+        Module::Fs,
+        Module::Time,
+        Module::Json,
+        Module::Env,
+        Module::Os,
+        #[cfg(feature = "regex")]
+        Module::Regex,
+    ];
+
+    /// Look a module up by the path it is imported with.
+    pub fn from_path(path: &[String]) -> Option<Module> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|module| module.name().split('.').eq(path.iter().map(String::as_str)))
+    }
+
+    /// Return the path the module is imported with, such as `std.math`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Module::Math => "std.math",
+            Module::Fs => "std.fs",
+            Module::Time => "std.time",
+            Module::Json => "std.json",
+            Module::Env => "std.env",
+            Module::Os => "std.os",
+            #[cfg(feature = "regex")]
+            Module::Regex => "std.regex",
+        }
+    }
+
+    /// Return the member `name` of the module, if it has one.
+    pub fn member(self, name: &str) -> Option<Value> {
+        match (self, name) {
+            (Module::Math, "pi") => Some(Value::Float(PI)),
+            _ => Native::ALL
+                .iter()
+                .copied()
+                .find(|native| native.module() == Some(self) && native.name() == name)
+                .map(Value::Native),
+        }
+    }
+}
+
+impl Native {
+    /// Every native function.
+    pub const ALL: &'static [Native] = &[
+        Native::Channel,
+        Native::Send,
+        Native::Receive,
+        Native::Print,
+        Native::Println,
+        Native::ReadLine,
+        Native::Format,
+        Native::Assert,
+        Native::Panic,
+        Native::Int,
+        Native::Float,
+        Native::Str,
+        Native::Char,
+        Native::TypeOf,
+        Native::Sort,
+        Native::SortBy,
+        Native::Len,
+        Native::ToUpper,
+        Native::ToLower,
+        Native::Trim,
+        Native::Split,
+        Native::Contains,
+        Native::Replace,
+        Native::StartsWith,
+        Native::Sqrt,
+        Native::Pow,
+        Native::Abs,
+        Native::Floor,
+        Native::Ceil,
+        Native::Min,
+        Native::Max,
+        Native::Sin,
+        Native::Cos,
+        Native::Tan,
+        Native::Asin,
+        Native::Acos,
+        Native::Atan,
+        Native::ReadToString,
+        Native::Write,
+        Native::Exists,
+        Native::Lines,
+        Native::Sleep,
+        Native::Now,
+        Native::Elapsed,
+        Native::Parse,
+        Native::Stringify,
+        Native::Pretty,
+        Native::Get,
+        Native::Vars,
+        Native::Platform,
+        Native::Cwd,
+        Native::SetCwd,
+        #[cfg(feature = "regex")]
+        Native::IsMatch,
+        #[cfg(feature = "regex")]
+        Native::FindAll,
+        #[cfg(feature = "regex")]
+        Native::ReplaceMatches,
+    ];
+
+    /// Return the method `name` of `receiver`, if it is a string, list or
+    /// range with a method of that name.
+    pub fn method(receiver: &Value, name: &str) -> Option<Native> {
+        match receiver {
+            Value::Str(_) => Self::ALL
+                .iter()
+                .copied()
+                .find(|native| native.is_method() && native.name() == name),
+            Value::List(_) if name == "len" => Some(Native::Len),
+            Value::Range(_) => match name {
+                "len" => Some(Native::Len),
+                "contains" => Some(Native::Contains),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Returns true for methods of strings, lists and ranges, which aren't
+    /// defined as globals.
+    pub fn is_method(self) -> bool {
+        matches!(
+            self,
+            Native::Len
+                | Native::ToUpper
+                | Native::ToLower
+                | Native::Trim
+                | Native::Split
+                | Native::Contains
+                | Native::Replace
+                | Native::StartsWith
+        )
+    }
+
+    /// Return the module the function is a member of, if it isn't a global
+    /// or a method.
+    pub fn module(self) -> Option<Module> {
+        match self {
+            Native::Sqrt
+            | Native::Pow
+            | Native::Abs
+            | Native::Floor
+            | Native::Ceil
+            | Native::Min
+            | Native::Max
+            | Native::Sin
+            | Native::Cos
+            | Native::Tan
+            | Native::Asin
+            | Native::Acos
+            | Native::Atan => Some(Module::Math),
+            Native::ReadToString | Native::Write | Native::Exists | Native::Lines => {
+                Some(Module::Fs)
+            }
+            Native::Now | Native::Elapsed => Some(Module::Time),
+            Native::Parse | Native::Stringify | Native::Pretty => Some(Module::Json),
+            Native::Get | Native::Vars => Some(Module::Env),
+            Native::Platform | Native::Cwd | Native::SetCwd => Some(Module::Os),
+            #[cfg(feature = "regex")]
+            Native::IsMatch | Native::FindAll | Native::ReplaceMatches => Some(Module::Regex),
+            _ => None,
+        }
+    }
+
+    /// Return what the function needs the [sandbox](super::sandbox) to
+    /// allow, if anything.
+    pub fn capability(self) -> Option<Capability> {
+        match self {
+            Native::ReadToString | Native::Write | Native::Exists | Native::Lines => {
+                Some(Capability::Files)
+            }
+            Native::Get | Native::Vars | Native::Cwd | Native::SetCwd => Some(Capability::Env),
+            _ => None,
+        }
+    }
+
+    /// Returns true for functions that are defined as globals.
+    pub fn is_global(self) -> bool {
+        !self.is_method() && self.module().is_none()
+    }
+
+    /// Return the name of the global the function is stored in, or of the
+    /// method or module member if it is one.
+    pub fn name(self) -> &'static str {
+        match self {
+            Native::Channel => "channel",
+            Native::Send => "send",
+            Native::Receive => "receive",
+            Native::Print => "print",
+            Native::Println => "println",
+            Native::ReadLine => "read_line",
+            Native::Format => "format",
+            Native::Assert => "assert",
+            Native::Panic => "panic",
+            Native::Int => "int",
+            Native::Float => "float",
+            Native::Str => "str",
+            Native::Char => "char",
+            Native::TypeOf => "type_of",
+            Native::Sort => "sort",
+            Native::SortBy => "sort_by",
+            Native::Len => "len",
+            Native::ToUpper => "to_upper",
+            Native::ToLower => "to_lower",
+            Native::Trim => "trim",
+            Native::Split => "split",
+            Native::Contains => "contains",
+            Native::Replace => "replace",
+            Native::StartsWith => "starts_with",
+            Native::Sqrt => "sqrt",
+            Native::Pow => "pow",
+            Native::Abs => "abs",
+            Native::Floor => "floor",
+            Native::Ceil => "ceil",
+            Native::Min => "min",
+            Native::Max => "max",
+            Native::Sin => "sin",
+            Native::Cos => "cos",
+            Native::Tan => "tan",
+            Native::Asin => "asin",
+            Native::Acos => "acos",
+            Native::Atan => "atan",
+            Native::ReadToString => "read_to_string",
+            Native::Write => "write",
+            Native::Exists => "exists",
+            Native::Lines => "lines",
+            Native::Sleep => "sleep",
+            Native::Now => "now",
+            Native::Elapsed => "elapsed",
+            Native::Parse => "parse",
+            Native::Stringify => "stringify",
+            Native::Pretty => "pretty",
+            Native::Get => "get",
+            Native::Vars => "vars",
+            Native::Platform => "platform",
+            Native::Cwd => "cwd",
+            Native::SetCwd => "set_cwd",
+            #[cfg(feature = "regex")]
+            Native::IsMatch => "is_match",
+            #[cfg(feature = "regex")]
+            Native::FindAll => "find_all",
+            #[cfg(feature = "regex")]
+            Native::ReplaceMatches => "replace",
+        }
+    }
+
+    /// Return the name of the function, qualified by the path of its module
+    /// if it is a module member, such as `std.regex.replace`. Unlike
+    /// [`name`](Native::name), it is different for every native function.
+    pub fn path(self) -> String {
+        match self.module() {
+            Some(module) => format!("{}.{}", module.name(), self.name()),
+            None => self.name().to_string(),
+        }
+    }
+
+    /// Returns true for functions that take any number of arguments after
+    /// the first [`arity`](Native::arity).
+    pub fn is_variadic(self) -> bool {
+        matches!(
+            self,
+            Native::Print
+                | Native::Println
+                | Native::ReadLine
+                | Native::Format
+                | Native::Assert
+                | Native::Panic
+                | Native::Min
+                | Native::Max
+        )
+    }
+
+    /// Return the number of arguments the function takes, not counting
+    /// the receiver of a method, or the least it takes if it is variadic.
+    pub fn arity(self) -> u8 {
+        match self {
+            Native::Channel
+            | Native::ReadLine
+            | Native::Len
+            | Native::ToUpper
+            | Native::ToLower
+            | Native::Trim
+            | Native::Now
+            | Native::Elapsed
+            | Native::Vars
+            | Native::Platform
+            | Native::Cwd => 0,
+            Native::Receive
+            | Native::Print
+            | Native::Println
+            | Native::Format
+            | Native::Assert
+            | Native::Panic
+            | Native::Int
+            | Native::Float
+            | Native::Str
+            | Native::Char
+            | Native::TypeOf
+            | Native::Sort
+            | Native::Split
+            | Native::Contains
+            | Native::StartsWith
+            | Native::Sqrt
+            | Native::Abs
+            | Native::Floor
+            | Native::Ceil
+            | Native::Min
+            | Native::Max
+            | Native::Sin
+            | Native::Cos
+            | Native::Tan
+            | Native::Asin
+            | Native::Acos
+            | Native::Atan
+            | Native::ReadToString
+            | Native::Exists
+            | Native::Lines
+            | Native::Sleep
+            | Native::Parse
+            | Native::Stringify
+            | Native::Pretty
+            | Native::Get
+            | Native::SetCwd => 1,
+            Native::Send | Native::SortBy | Native::Replace | Native::Pow | Native::Write => 2,
+            #[cfg(feature = "regex")]
+            Native::IsMatch | Native::FindAll => 2,
+            #[cfg(feature = "regex")]
+            Native::ReplaceMatches => 3,
+        }
+    }
+}
+
+impl Vm {
+    /// Call `native` with the `argc` arguments following stack slot `base`,
+    /// which holds the receiver if it is a method. Returns `None` if the call
+    /// can't complete until another task runs, in which case it has had no
+    /// effect, and should be retried later.
+    pub(super) fn call_native(
+        &mut self,
+        native: Native,
+        base: usize,
+        argc: u8,
+    ) -> RunResult<Option<Value>> {
+        if let Some(capability) = native.capability() {
+            if !self.sandbox.allows(capability) {
+                return Err(self.denied(native.name(), capability));
+            }
+        }
+        let arg = |index: usize| &self.stack[base + 1 + index];
+        Ok(Some(match native {
+            Native::Channel => {
+                self.maybe_collect();
+                Value::Channel(self.alloc(Object::Channel(VecDeque::new())))
+            }
+            Native::Send => {
+                let channel = self.channel(native, arg(0))?;
+                let value = arg(1).clone();
+                self.heap.channel_mut(channel).push_back(value);
+                Value::Unit
+            }
+            Native::Receive => {
+                let channel = self.channel(native, arg(0))?;
+                match self.heap.channel_mut(channel).pop_front() {
+                    Some(value) => value,
+                    None => return Ok(None),
+                }
+            }
+            Native::Print | Native::Println => {
+                let mut text = self.message(native, self.args(base, argc))?;
+                if native == Native::Println {
+                    text.push('\n');
+                }
+                self.print(&text)?;
+                Value::Unit
+            }
+            Native::ReadLine => {
+                if argc > 0 {
+                    let prompt = self.message(native, self.args(base, argc))?;
+                    self.print(&prompt)?;
+                }
+                let read = self.io().read_line();
+                match read.map_err(|error| self.error(format!("cannot read a line: {}", error)))? {
+                    None => Value::Unit,
+                    Some(line) => {
+                        let line = line.strip_suffix('\n').unwrap_or(&line);
+                        Value::from(line.strip_suffix('\r').unwrap_or(line))
+                    }
+                }
+            }
+            Native::Format => {
+                let args = self.args(base, argc);
+                let template = self.string(native, &args[0])?;
+                Value::from(self.format(&template, &args[1..])?.as_str())
+            }
+            Native::Assert => match arg(0) {
+                Value::Bool(true) => Value::Unit,
+                Value::Bool(false) => {
+                    let message = match argc {
+                        1 => "assertion failed".to_string(),
+                        _ => format!(
+                            "assertion failed: {}",
+                            self.message(native, &self.args(base, argc)[1..])?
+                        ),
+                    };
+                    return Err(self.panicked(message));
+                }
+                value => return Err(self.expected(native, "a bool", value)),
+            },
+            Native::Panic => {
+                let message = self.message(native, self.args(base, argc))?;
+                return Err(self.panicked(message));
+            }
+            Native::Int => match arg(0).clone() {
+                Value::Str(text) => self.parse_int(&text)?,
+                value @ (Value::Int(_)
+                | Value::BigInt(_)
+                | Value::Float(_)
+                | Value::Bool(_)
+                | Value::Char(_)) => self.cast(value, CastType::Int)?,
+                value => return Err(self.unconvertible(&value, "int")),
+            },
+            Native::Float => match arg(0).clone() {
+                Value::Str(text) => match text.trim().parse() {
+                    Ok(value) => Value::Float(value),
+                    Err(_) => return Err(self.error(format!("cannot convert {:?} to float", text))),
+                },
+                value @ (Value::Int(_) | Value::BigInt(_) | Value::Float(_)) => {
+                    self.cast(value, CastType::Float)?
+                }
+                value => return Err(self.unconvertible(&value, "float")),
+            },
+            Native::Str => match arg(0) {
+                value @ Value::Str(_) => value.clone(),
+                value => Value::from(self.heap.display(value).to_string().as_str()),
+            },
+            Native::Char => match arg(0) {
+                value @ Value::Char(_) => value.clone(),
+                Value::Int(code) => match u32::try_from(*code).ok().and_then(char::from_u32) {
+                    Some(c) => Value::Char(c),
+                    None => return Err(self.error(format!("no char has the code point {}", code))),
+                },
+                Value::Str(text) => {
+                    let mut chars = text.chars();
+                    match (chars.next(), chars.next()) {
+                        (Some(c), None) => Value::Char(c),
+                        _ => return Err(self.error(format!("cannot convert {:?} to char", text))),
+                    }
+                }
+                value => return Err(self.unconvertible(value, "char")),
+            },
+            Native::TypeOf => Value::from(arg(0).type_name()),
+            Native::Sort => {
+                let list = self.list(native, arg(0))?;
+                let items = self.heap.list(list);
+                let sorted = self.sort_by_keys(items, items)?;
+                *self.heap.list_mut(list) = sorted;
+                Value::Unit
+            }
+            Native::SortBy => {
+                let list = self.list(native, arg(0))?;
+                let key = arg(1).clone();
+                let items = self.heap.list(list).clone();
+                let len = items.len();
+                // The items and their keys are kept on the stack while the
+                // key function runs, so that they survive a collection
+                let start = self.stack.len();
+                self.stack.extend(items);
+                for index in start..start + len {
+                    let item = self.stack[index].clone();
+                    let key = self.call_value(key.clone(), &[item])?;
+                    self.stack.push(key);
+                }
+                let (items, keys) = self.stack[start..].split_at(len);
+                let sorted = self.sort_by_keys(items, keys)?;
+                self.stack.truncate(start);
+                *self.heap.list_mut(list) = sorted;
+                Value::Unit
+            }
+            Native::Len => Value::Int(match &self.stack[base] {
+                Value::List(list) => self.heap.list(*list).len() as i64,
+                Value::Range(range) => range.len().ok_or_else(|| {
+                    self.error(format!("the length of {} is too large for an int", range))
+                })?,
+                _ => self.receiver(base).chars().count() as i64,
+            }),
+            Native::ToUpper => Value::from(self.receiver(base).to_uppercase().as_str()),
+            Native::ToLower => Value::from(self.receiver(base).to_lowercase().as_str()),
+            Native::Trim => Value::from(self.receiver(base).trim()),
+            Native::Split => {
+                let separator = self.string(native, arg(0))?;
+                if separator.is_empty() {
+                    return Err(self.error("cannot split on an empty string"));
+                }
+                let parts = self
+                    .receiver(base)
+                    .split(&*separator)
+                    .map(Value::from)
+                    .collect();
+                // The receiver and separator are still on the stack, so
+                // they survive a collection
+                self.maybe_collect();
+                Value::List(self.alloc(Object::List(parts)))
+            }
+            Native::Contains => {
+                if let Value::Range(range) = &self.stack[base] {
+                    return Ok(Some(Value::Bool(match arg(0) {
+                        Value::Int(value) => range.contains(*value),
+                        // Big ints are outside every range
+                        Value::BigInt(_) => false,
+                        value => return Err(self.expected(native, "an int", value)),
+                    })));
+                }
+                let pattern = self.string(native, arg(0))?;
+                Value::Bool(self.receiver(base).contains(&*pattern))
+            }
+            Native::Replace => {
+                let from = self.string(native, arg(0))?;
+                let to = self.string(native, arg(1))?;
+                Value::from(self.receiver(base).replace(&*from, &to).as_str())
+            }
+            Native::StartsWith => {
+                let prefix = self.string(native, arg(0))?;
+                Value::Bool(self.receiver(base).starts_with(&*prefix))
+            }
+            Native::Sqrt => Value::Float(self.number(native, arg(0))?.sqrt()),
+            Native::Pow => {
+                let x = self.number(native, arg(0))?;
+                Value::Float(x.powf(self.number(native, arg(1))?))
+            }
+            Native::Abs => match arg(0).clone() {
+                Value::Int(x) => match x.checked_abs() {
+                    Some(abs) => Value::Int(abs),
+                    None => self.overflowed(BigInt::from(x).neg(), || format!("abs({})", x))?,
+                },
+                Value::BigInt(x) if x.is_negative() => x.neg().into_value(),
+                value @ Value::BigInt(_) => value,
+                Value::Float(x) => Value::Float(x.abs()),
+                value => return Err(self.expected(native, "a number", &value)),
+            },
+            Native::Floor | Native::Ceil => match arg(0).clone() {
+                Value::Float(x) if native == Native::Floor => Value::Float(x.floor()),
+                Value::Float(x) => Value::Float(x.ceil()),
+                // Ints are already whole
+                value @ (Value::Int(_) | Value::BigInt(_)) => value,
+                value => return Err(self.expected(native, "a number", &value)),
+            },
+            Native::Min | Native::Max => {
+                let values = match self.args(base, argc) {
+                    [Value::List(list)] => self.heap.list(*list).as_slice(),
+                    [value] => return Err(self.expected(native, "a list", value)),
+                    values => values,
+                };
+                let (wanted, extreme) = match native {
+                    Native::Min => (Ordering::Less, "minimum"),
+                    _ => (Ordering::Greater, "maximum"),
+                };
+                let Some((mut best, rest)) = values.split_first() else {
+                    return Err(self.error(format!("cannot take the {} of an empty list", extreme)));
+                };
+                // The first value wins a tie
+                for value in rest {
+                    if self.ordering(value, best)? == wanted {
+                        best = value;
+                    }
+                }
+                best.clone()
+            }
+            Native::Sin => Value::Float(self.number(native, arg(0))?.sin()),
+            Native::Cos => Value::Float(self.number(native, arg(0))?.cos()),
+            Native::Tan => Value::Float(self.number(native, arg(0))?.tan()),
+            Native::Asin => Value::Float(self.number(native, arg(0))?.asin()),
+            Native::Acos => Value::Float(self.number(native, arg(0))?.acos()),
+            Native::Atan => Value::Float(self.number(native, arg(0))?.atan()),
+            Native::ReadToString => {
+                let path = self.file_path(native, arg(0))?;
+                Value::from(self.read_file(&path)?.as_str())
+            }
+            Native::Write => {
+                let path = self.file_path(native, arg(0))?;
+                let contents = self.string(native, arg(1))?;
+                self.io()
+                    .files()
+                    .write(&path, &contents)
+                    .map_err(|error| self.error(format!("cannot write `{}`: {}", path, error)))?;
+                Value::Unit
+            }
+            Native::Exists => {
+                let path = self.file_path(native, arg(0))?;
+                Value::Bool(self.io().files().exists(&path))
+            }
+            Native::Lines => {
+                let path = self.file_path(native, arg(0))?;
+                let contents = self.read_file(&path)?;
+                let lines = contents.lines().map(Value::from).collect();
+                // The path is still on the stack, so it survives a
+                // collection
+                self.maybe_collect();
+                Value::List(self.alloc(Object::List(lines)))
+            }
+            #[cfg(feature = "clock")]
+            Native::Sleep => {
+                let ms = match arg(0) {
+                    Value::Int(ms) if *ms >= 0 => *ms as u64,
+                    Value::Int(_) => return Err(self.error("cannot sleep for a negative time")),
+                    value => return Err(self.expected(native, "an int", value)),
+                };
+                let ms = self.consume_sleep_fuel(ms);
+                // Wake up regularly to check whether the program has been
+                // interrupted
+                let deadline = Instant::now() + Duration::from_millis(ms);
+                loop {
+                    self.check_interrupt()?;
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break;
+                    }
+                    thread::sleep((deadline - now).min(SLEEP_SLICE));
+                }
+                Value::Unit
+            }
+            #[cfg(feature = "clock")]
+            Native::Now => {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH);
+                // A clock set before 1970 is taken to be at the epoch
+                Value::Float(now.unwrap_or_default().as_secs_f64())
+            }
+            #[cfg(feature = "clock")]
+            Native::Elapsed => Value::Float(self.created.elapsed().as_secs_f64()),
+            #[cfg(not(feature = "clock"))]
+            Native::Sleep | Native::Now | Native::Elapsed => {
+                return Err(self.error(format!(
+                    "`{}` needs a clock, which this build of Meow doesn't have",
+                    native.name()
+                )))
+            }
+            Native::Parse => {
+                let text = self.string(native, arg(0))?;
+                let json = json::parse(&text).map_err(|message| self.error(message))?;
+                self.json_value(json)
+            }
+            Native::Stringify | Native::Pretty => {
+                let text = self.stringify(arg(0), native == Native::Pretty)?;
+                Value::from(text.as_str())
+            }
+            Native::Get => {
+                let name = self.string(native, arg(0))?;
+                match self.env.var(&name) {
+                    Some(value) => Value::from(value.as_str()),
+                    None => Value::Unit,
+                }
+            }
+            Native::Vars => {
+                let mut vars = self.env.vars();
+                vars.sort();
+                // Nothing is rooted while the pairs are built, so collect
+                // first
+                self.maybe_collect();
+                let pairs = vars
+                    .into_iter()
+                    .map(|(name, value)| {
+                        let pair = vec![Value::from(name.as_str()), Value::from(value.as_str())];
+                        Value::List(self.alloc(Object::List(pair)))
+                    })
+                    .collect();
+                Value::List(self.alloc(Object::List(pairs)))
+            }
+            Native::Platform => Value::from(env::consts::OS),
+            Native::Cwd => {
+                let dir = self.env.current_dir().map_err(|error| {
+                    self.error(format!("cannot read the working directory: {}", error))
+                })?;
+                Value::from(dir.as_str())
+            }
+            Native::SetCwd => {
+                let path = self.string(native, arg(0))?;
+                self.env.set_current_dir(&path).map_err(|error| {
+                    self.error(format!(
+                        "cannot change the working directory to `{}`: {}",
+                        path, error
+                    ))
+                })?;
+                Value::Unit
+            }
+            #[cfg(feature = "regex")]
+            Native::IsMatch => {
+                let text = self.string(native, arg(1))?;
+                Value::Bool(self.regex(native, arg(0))?.is_match(&text))
+            }
+            #[cfg(feature = "regex")]
+            Native::FindAll => {
+                let text = self.string(native, arg(1))?;
+                let matches = self
+                    .regex(native, arg(0))?
+                    .find_iter(&text)
+                    .map(|found| Value::from(found.as_str()))
+                    .collect();
+                // The pattern and text are still on the stack, so they
+                // survive a collection
+                self.maybe_collect();
+                Value::List(self.alloc(Object::List(matches)))
+            }
+            #[cfg(feature = "regex")]
+            Native::ReplaceMatches => {
+                let text = self.string(native, arg(1))?;
+                let replacement = self.string(native, arg(2))?;
+                let regex = self.regex(native, arg(0))?;
+                Value::from(regex.replace_all(&text, &*replacement).as_ref())
+            }
+        }))
+    }
+
+    /// Compare two values by the total ordering `sort`, `math.min` and
+    /// `math.max` use, failing if they can't be compared.
+    pub(super) fn ordering(&self, a: &Value, b: &Value) -> RunResult<Ordering> {
+        let ordering = self.ordering_within(a, b, 0)?;
+        // Lists are compared by identity with `==`, so ties between
+        // different lists are broken by where they live on the heap
+        Ok(match (a, b) {
+            (Value::List(a), Value::List(b)) => ordering.then(a.index().cmp(&b.index())),
+            _ => ordering,
+        })
+    }
+
+    /// Compare two values that are nested `depth` lists deep.
+    fn ordering_within(&self, a: &Value, b: &Value, depth: usize) -> RunResult<Ordering> {
+        Ok(match (a, b) {
+            (Value::Int(a), Value::Int(b)) => a.cmp(b),
+            (Value::Float(a), Value::Float(b)) => float_ordering(*a, *b),
+            (Value::Int(_) | Value::BigInt(_), Value::Int(_) | Value::BigInt(_)) => {
+                big(a).cmp(&big(b))
+            }
+            // NaN comes after every other number
+            (Value::Int(_) | Value::BigInt(_), Value::Float(b)) => {
+                big(a).cmp_f64(*b).unwrap_or(Ordering::Less)
+            }
+            (Value::Float(a), Value::Int(_) | Value::BigInt(_)) => big(b)
+                .cmp_f64(*a)
+                .map_or(Ordering::Greater, Ordering::reverse),
+            (Value::Str(a), Value::Str(b)) => a.cmp(b),
+            (Value::Char(a), Value::Char(b)) => a.cmp(b),
+            (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+            (Value::List(a), Value::List(b)) => {
+                if depth == MAX_COMPARE_DEPTH {
+                    return Err(self.error("lists are nested too deeply to compare"));
+                }
+                let (a, b) = (self.heap.list(*a), self.heap.list(*b));
+                for (a, b) in a.iter().zip(b) {
+                    match self.ordering_within(a, b, depth + 1)? {
+                        Ordering::Equal => {}
+                        ordering => return Ok(ordering),
+                    }
+                }
+                a.len().cmp(&b.len())
+            }
+            (a, b) => {
+                return Err(self.error(format!(
+                    "cannot compare a value of type {} with a value of type {}",
+                    a.type_name(),
+                    b.type_name()
+                )))
+            }
+        })
+    }
+
+    /// Return `items` sorted by the [ordering](Vm::ordering) of `keys`,
+    /// which holds the key of each item. Items with equal keys keep their
+    /// order.
+    fn sort_by_keys(&self, items: &[Value], keys: &[Value]) -> RunResult<Vec<Value>> {
+        let mut order: Vec<_> = (0..items.len()).collect();
+        self.merge_sort(&mut order, keys)?;
+        Ok(order
+            .into_iter()
+            .map(|index| items[index].clone())
+            .collect())
+    }
+
+    /// Sort `order`, which holds indices into `keys`, by the keys. The
+    /// standard library's sorts can't stop at a comparison that fails, so
+    /// this is a merge sort.
+    fn merge_sort(&self, order: &mut Vec<usize>, keys: &[Value]) -> RunResult<()> {
+        if order.len() < 2 {
+            return Ok(());
+        }
+        let mut right = order.split_off(order.len() / 2);
+        self.merge_sort(order, keys)?;
+        self.merge_sort(&mut right, keys)?;
+
+        let left = mem::take(order);
+        let (mut i, mut j) = (0, 0);
+        while i < left.len() && j < right.len() {
+            if self.ordering(&keys[left[i]], &keys[right[j]])? == Ordering::Greater {
+                order.push(right[j]);
+                j += 1;
+            } else {
+                order.push(left[i]);
+                i += 1;
+            }
+        }
+        order.extend_from_slice(&left[i..]);
+        order.extend_from_slice(&right[j..]);
+        Ok(())
+    }
+
+    /// Check that the argument `value` of `native` is a string, and compile
+    /// it as a regular expression.
+    #[cfg(feature = "regex")]
+    fn regex(&self, native: Native, value: &Value) -> RunResult<regex::Regex> {
+        let pattern = self.string(native, value)?;
+        regex::Regex::new(&pattern).map_err(|error| {
+            // The syntax errors span several lines, pointing at the pattern
+            let message = error.to_string();
+            let reason = message.lines().last().unwrap_or_default().trim();
+            let reason = reason.strip_prefix("error: ").unwrap_or(reason);
+            self.error(format!("invalid regex `{}`: {}", pattern, reason))
+        })
+    }
+
+    /// Write `text` to the VM's output.
+    fn print(&mut self, text: &str) -> RunResult<()> {
+        self.io()
+            .print(text)
+            .map_err(|error| self.error(format!("cannot print: {}", error)))
+    }
+
+    /// Return the `argc` arguments following stack slot `base`.
+    fn args(&self, base: usize, argc: u8) -> &[Value] {
+        &self.stack[base + 1..base + 1 + argc as usize]
+    }
+
+    /// Return the message a variadic function such as `print` writes: its
+    /// only argument, or a format string filled in with the rest.
+    fn message(&self, native: Native, args: &[Value]) -> RunResult<String> {
+        match args {
+            [value] => Ok(self.heap.display(value).to_string()),
+            [template, args @ ..] => {
+                let template = self.string(native, template)?;
+                self.format(&template, args)
+            }
+            [] => unreachable!("there is always a message"),
+        }
+    }
+
+    fn panicked(&self, message: String) -> RuntimeError {
+        RuntimeError {
+            kind: RuntimeErrorKind::Panicked,
+            ..self.error(message)
+        }
+    }
+
+    /// Parse a string of decimal digits, with an optional sign, as an int.
+    fn parse_int(&self, text: &str) -> RunResult<Value> {
+        let digits = text.trim();
+        if let Ok(value) = digits.parse() {
+            return Ok(Value::Int(value));
+        }
+        let (negative, unsigned) = match digits.strip_prefix('-') {
+            Some(unsigned) => (true, unsigned),
+            None => (false, digits.strip_prefix('+').unwrap_or(digits)),
+        };
+        if unsigned.is_empty() || !unsigned.bytes().all(|digit| digit.is_ascii_digit()) {
+            return Err(self.error(format!("cannot convert {:?} to int", text)));
+        }
+        // It only fails to parse as an `i64` because it is too large
+        let ten = BigInt::from(10);
+        let mut value = BigInt::from(0);
+        for digit in unsigned.bytes() {
+            value = value.mul(&ten).add(&BigInt::from((digit - b'0') as i64));
+        }
+        let value = if negative { value.neg() } else { value };
+        self.overflowed(value, || format!("int({:?})", text))
+    }
+
+    fn unconvertible(&self, value: &Value, ty: &str) -> RuntimeError {
+        self.error(format!(
+            "cannot convert a value of type {} to {}",
+            value.type_name(),
+            ty
+        ))
+    }
+
+    /// Check that the argument `value` of `native` is a path to a file the
+    /// sandbox allows programs to use, returning it.
+    fn file_path(&self, native: Native, value: &Value) -> RunResult<Rc<str>> {
+        let path = self.string(native, value)?;
+        if !self.sandbox.allows_path(&path) {
+            return Err(RuntimeError {
+                kind: RuntimeErrorKind::Denied,
+                ..self.error(format!(
+                    "`{}` can't use `{}`, which is outside the files the sandbox allows",
+                    native.name(),
+                    path
+                ))
+            });
+        }
+        Ok(path)
+    }
+
+    fn read_file(&mut self, path: &str) -> RunResult<String> {
+        self.io()
+            .files()
+            .read_to_string(path)
+            .map_err(|error| self.error(format!("cannot read `{}`: {}", path, error)))
+    }
+
+    /// Check that the argument `value` of `native` is a number, returning
+    /// the nearest float.
+    fn number(&self, native: Native, value: &Value) -> RunResult<f64> {
+        match value {
+            Value::Int(value) => Ok(*value as f64),
+            Value::BigInt(value) => Ok(value.to_f64()),
+            Value::Float(value) => Ok(*value),
+            value => Err(self.expected(native, "a number", value)),
+        }
+    }
+
+    /// Return the string a method was called on.
+    fn receiver(&self, base: usize) -> Rc<str> {
+        match &self.stack[base] {
+            Value::Str(string) => string.clone(),
+            value => panic!("expected a string receiver, found {:?}", value),
+        }
+    }
+
+    /// Check that the argument `value` of `native` is a string.
+    fn string(&self, native: Native, value: &Value) -> RunResult<Rc<str>> {
+        match value {
+            Value::Str(string) => Ok(string.clone()),
+            value => Err(self.expected(native, "a string", value)),
+        }
+    }
+
+    fn expected(&self, native: Native, expected: &str, value: &Value) -> RuntimeError {
+        self.error(format!(
+            "`{}` expects {}, found a value of type {}",
+            native.name(),
+            expected,
+            value.type_name()
+        ))
+    }
+
+    /// Check that the argument `value` of `native` is a list.
+    fn list(&self, native: Native, value: &Value) -> RunResult<ObjRef> {
+        match value {
+            Value::List(list) => Ok(*list),
+            value => Err(self.expected(native, "a list", value)),
+        }
+    }
+
+    /// Check that the argument `value` of `native` is a channel.
+    fn channel(&self, native: Native, value: &Value) -> RunResult<ObjRef> {
+        match value {
+            Value::Channel(channel) => Ok(*channel),
+            value => Err(self.expected(native, "a channel", value)),
+        }
+    }
+}
+
+/// Compare two floats, putting NaN after every other number.
+fn float_ordering(a: f64, b: f64) -> Ordering {
+    a.partial_cmp(&b)
+        .unwrap_or_else(|| a.is_nan().cmp(&b.is_nan()))
+}
+
+/// Return an int or big int as a big int.
+fn big(value: &Value) -> BigInt {
+    match value {
+        Value::Int(value) => BigInt::from(*value),
+        Value::BigInt(value) => (**value).clone(),
+        value => unreachable!("{:?} is not an int", value),
+    }
+}
@@ -0,0 +1,74 @@
+//! Observers let hosts watch programs run, to build profilers, debuggers
+//! and coverage tools on top of the VM. An [`Observer`] installed with
+//! [`Vm::set_observer`](super::Vm::set_observer) is told about every call,
+//! instruction, allocation and error, on whichever backend runs the
+//! program.
+//!
+//! Observing a program doesn't change what it does, but it is slower,
+//! since functions aren't compiled to native code while an observer is
+//! installed.
+
+use super::heap::{Heap, ObjRef};
+use crate::{
+    bytecode::{Function, OpCode},
+    errors::RuntimeError,
+    span::Span,
+    value::Value,
+};
+
+/// An instruction about to run.
+#[derive(Debug, Clone, Copy)]
+pub struct Instruction<'a> {
+    /// The function the instruction is part of.
+    pub function: &'a Function,
+    /// The opcode of the instruction. The register backend gives the opcode
+    /// its instruction was translated from. The ast backend has no
+    /// instructions, so it reports every statement and expression it
+    /// evaluates instead, without an opcode.
+    pub op: Option<OpCode>,
+    pub span: Option<Span>,
+}
+
+/// Callbacks for what happens while a program runs. Every method does
+/// nothing by default, so observers only implement the ones they need.
+///
+/// # Examples
+///
+/// ```
+/// use meow::{compile, vm::{observer::{Instruction, Observer}, Vm}};
+/// use std::{cell::RefCell, collections::BTreeSet, rc::Rc};
+///
+/// /// Records the lines that ran.
+/// #[derive(Clone, Default)]
+/// struct Coverage(Rc<RefCell<BTreeSet<u32>>>);
+///
+/// impl Observer for Coverage {
+///     fn on_instruction(&mut self, instruction: &Instruction) {
+///         if let Some(span) = instruction.span {
+///             self.0.borrow_mut().insert(span.line);
+///         }
+///     }
+/// }
+///
+/// let coverage = Coverage::default();
+/// let mut vm = Vm::new();
+/// vm.set_observer(Some(Box::new(coverage.clone())));
+/// vm.run(compile("let x = 1;\nif x > 1 {\n    print(x);\n}").unwrap()).unwrap();
+/// assert_eq!(*coverage.0.borrow(), BTreeSet::from([1, 2]));
+/// ```
+pub trait Observer {
+    /// Called when a call to `function` starts, with the arguments it was
+    /// given. This includes the top-level function of the program, and the
+    /// functions tasks are spawned with, but not native or host functions.
+    fn on_enter_function(&mut self, _function: &Function, _args: &[Value]) {}
+
+    /// Called before each instruction runs.
+    fn on_instruction(&mut self, _instruction: &Instruction) {}
+
+    /// Called when the program fails with `error`, which holds the calls
+    /// that were in progress.
+    fn on_error(&mut self, _error: &RuntimeError) {}
+
+    /// Called after `obj` is allocated on `heap`.
+    fn on_allocation(&mut self, _heap: &Heap, _obj: ObjRef) {}
+}
@@ -0,0 +1,137 @@
+//! The profiler counts how often each opcode runs and how long it takes, in
+//! total and for each function, to show where the VM spends its time.
+//!
+//! Every instruction is timed from when it starts until the next one does,
+//! so the time of a call instruction only covers setting up the call, and a
+//! function's time doesn't include the functions it calls. Reading the clock
+//! this often makes programs much slower while they are profiled, but the
+//! proportions stay useful.
+
+use crate::bytecode::{Function, OpCode};
+use crate::clock::Instant;
+use std::{cmp::Reverse, collections::HashMap, fmt, rc::Rc, time::Duration};
+
+/// How often something ran, and for how long.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Stats {
+    /// The number of instructions executed.
+    pub count: u64,
+    pub time: Duration,
+}
+
+/// The statistics collected while profiling. See [`Vm::set_profiling`].
+///
+/// [`Vm::set_profiling`]: super::Vm::set_profiling
+#[derive(Debug)]
+pub struct Profile {
+    opcodes: Vec<Stats>,
+    /// Keyed by address. Functions are kept alive by their entry, so their
+    /// addresses can't be reused by other functions.
+    functions: HashMap<*const Function, (Rc<Function>, Stats)>,
+    /// The instruction running, and when it started.
+    current: Option<(OpCode, *const Function, Instant)>,
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Self {
+            opcodes: vec![Stats::default(); OpCode::ALL.len()],
+            functions: HashMap::new(),
+            current: None,
+        }
+    }
+
+    /// Record that `op` is starting in `function`, ending the previous
+    /// instruction.
+    pub(super) fn record(&mut self, op: OpCode, function: &Rc<Function>) {
+        let now = Instant::now();
+        self.stop_at(now);
+
+        let key = Rc::as_ptr(function);
+        self.opcodes[op as usize].count += 1;
+        self.functions
+            .entry(key)
+            .or_insert_with(|| (function.clone(), Stats::default()))
+            .1
+            .count += 1;
+        self.current = Some((op, key, now));
+    }
+
+    /// End the instruction running, if there is one.
+    pub(super) fn stop(&mut self) {
+        self.stop_at(Instant::now());
+    }
+
+    fn stop_at(&mut self, now: Instant) {
+        if let Some((op, function, start)) = self.current.take() {
+            let time = now - start;
+            self.opcodes[op as usize].time += time;
+            if let Some((_, stats)) = self.functions.get_mut(&function) {
+                stats.time += time;
+            }
+        }
+    }
+
+    /// Return the statistics of every opcode that ran, slowest first.
+    pub fn opcodes(&self) -> Vec<(OpCode, Stats)> {
+        let mut opcodes: Vec<_> = OpCode::ALL
+            .iter()
+            .map(|&op| (op, self.opcodes[op as usize]))
+            .filter(|(_, stats)| stats.count > 0)
+            .collect();
+        opcodes.sort_by_key(|(_, stats)| Reverse(stats.time));
+        opcodes
+    }
+
+    /// Return the statistics of every function that ran, slowest first.
+    pub fn functions(&self) -> Vec<(Rc<Function>, Stats)> {
+        let mut functions: Vec<_> = self.functions.values().cloned().collect();
+        functions.sort_by_key(|(_, stats)| Reverse(stats.time));
+        functions
+    }
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for Profile {
+    /// Format the profile as two tables, one for opcodes and one for
+    /// functions.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let total: Duration = self.opcodes.iter().map(|stats| stats.time).sum();
+        let row = |f: &mut fmt::Formatter, name: &str, stats: Stats| {
+            let percent = if total.is_zero() {
+                0.0
+            } else {
+                stats.time.as_secs_f64() / total.as_secs_f64() * 100.0
+            };
+            writeln!(
+                f,
+                "{:<16} {:>12} {:>12.3?} {:>6.1}%",
+                name, stats.count, stats.time, percent
+            )
+        };
+
+        writeln!(
+            f,
+            "{:<16} {:>12} {:>12} {:>7}",
+            "opcode", "count", "time", "%"
+        )?;
+        for (op, stats) in self.opcodes() {
+            row(f, &op.to_string(), stats)?;
+        }
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:<16} {:>12} {:>12} {:>7}",
+            "function", "count", "time", "%"
+        )?;
+        for (function, stats) in self.functions() {
+            row(f, &function.name, stats)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,805 @@
+//! The register backend is an alternative to the stack machine, kept so the
+//! two designs can be compared. Both backends run the same compiled
+//! [`Function`]s: bytecode is the representation the compiler lowers to,
+//! and this backend translates it further into register [`Instr`]uctions
+//! the first time each function is called.
+//!
+//! The translation relies on the stack depth before every instruction being
+//! known at compile time. Each stack position becomes a register in the
+//! frame's window of the VM stack, so locals keep their slots, and pushes
+//! and pops turn into reads and writes of fixed registers. Instructions that
+//! only move values around, such as `Pop`, disappear entirely.
+
+use super::{
+    class, heap::GeneratorState, in_range, observer::Instruction, switch_index, ObjRef, Prepared,
+    RunResult, Vm,
+};
+use crate::{
+    bytecode::{
+        verify::{depths, jump_target},
+        Function, OpCode,
+    },
+    span::Span,
+    value::{CastType, Value},
+    vm::heap::Object,
+};
+use std::rc::Rc;
+
+/// The index of a register within its frame's window.
+pub type Register = u16;
+
+/// A register machine instruction. Jump targets are instruction indices,
+/// and constants and names are indices into the original chunk's constant
+/// table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instr {
+    LoadConstant {
+        dst: Register,
+        index: u16,
+    },
+    LoadUnit {
+        dst: Register,
+    },
+    LoadBool {
+        dst: Register,
+        value: bool,
+    },
+    Move {
+        dst: Register,
+        src: Register,
+    },
+    DefineGlobal {
+        src: Register,
+        name: u16,
+    },
+    GetGlobal {
+        dst: Register,
+        name: u16,
+    },
+    SetGlobal {
+        src: Register,
+        name: u16,
+    },
+    /// Apply the binary operator `op` to `left` and `right`.
+    Binary {
+        op: OpCode,
+        dst: Register,
+        left: Register,
+        right: Register,
+    },
+    /// Apply the unary operator `op` to `src`.
+    Unary {
+        op: OpCode,
+        dst: Register,
+        src: Register,
+    },
+    Cast {
+        dst: Register,
+        src: Register,
+        ty: CastType,
+    },
+    Jump {
+        target: u32,
+    },
+    JumpIfFalse {
+        cond: Register,
+        target: u32,
+    },
+    /// Format an interpolated string from the template in `start` and the
+    /// `argc` registers after it.
+    Format {
+        dst: Register,
+        start: Register,
+        argc: u8,
+    },
+    /// Create a list from the `len` registers starting at `start`.
+    BuildList {
+        dst: Register,
+        start: Register,
+        len: u8,
+    },
+    GetIndex {
+        dst: Register,
+        object: Register,
+        index: Register,
+    },
+    /// Store `value` in `list` at `index`, and copy it to `dst`.
+    SetIndex {
+        dst: Register,
+        list: Register,
+        index: Register,
+        value: Register,
+    },
+    /// Slice `object` from `start` to `end`, which is included if
+    /// `inclusive` is true.
+    Slice {
+        dst: Register,
+        object: Register,
+        start: Register,
+        end: Register,
+        inclusive: bool,
+    },
+    /// Create a range from `start` to `end`, which is included if
+    /// `inclusive` is true.
+    Range {
+        dst: Register,
+        start: Register,
+        end: Register,
+        inclusive: bool,
+    },
+    /// Fail unless `start` and `end` are ints that can bound a range.
+    RangeBounds {
+        start: Register,
+        end: Register,
+    },
+    /// Create a class named by the register before `start`, with the
+    /// `count` methods starting at `start`.
+    Class {
+        dst: Register,
+        start: Register,
+        count: u8,
+    },
+    GetField {
+        dst: Register,
+        object: Register,
+        name: u16,
+    },
+    /// Store `value` in the field `name` of `object`, and copy it to `dst`.
+    SetField {
+        dst: Register,
+        object: Register,
+        value: Register,
+        name: u16,
+    },
+    /// Call `callee` with the `argc` registers following it, storing the
+    /// result in `callee`.
+    Call {
+        callee: Register,
+        argc: u8,
+    },
+    Return {
+        src: Register,
+    },
+    /// Start a task calling `callee` with the `argc` registers following it.
+    Spawn {
+        callee: Register,
+        argc: u8,
+    },
+    /// Resume `iterator`, storing its next value in `dst` and whether it
+    /// produced one in the register after `dst`. A range in `iterator` is
+    /// replaced by the rest of it.
+    Next {
+        dst: Register,
+        iterator: Register,
+    },
+    Yield {
+        src: Register,
+    },
+    /// Store whether `value` is in `range` in `dst`.
+    InRange {
+        dst: Register,
+        value: Register,
+        range: Register,
+    },
+    /// Jump to the instruction at `table` plus the value in `src` minus the
+    /// int constant `min`, or to `table + len` if that is outside of
+    /// `0..len`.
+    Switch {
+        src: Register,
+        min: u16,
+        len: u16,
+        table: u32,
+    },
+    Unmatched {
+        src: Register,
+    },
+}
+
+/// A function translated for the register backend.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegisterCode {
+    /// The function the code was translated from, which holds its constants
+    /// and spans.
+    pub function: Rc<Function>,
+    pub code: Vec<Instr>,
+    /// The bytecode offset each instruction was translated from.
+    pub offsets: Vec<u32>,
+    /// The number of registers in the function's window.
+    pub registers: usize,
+}
+
+/// A single function invocation on the register backend.
+#[derive(Debug, Clone)]
+pub(super) struct RegisterFrame {
+    code: Rc<RegisterCode>,
+    ip: usize,
+    base: usize,
+    initializer: bool,
+    generator: Option<ObjRef>,
+}
+
+impl RegisterFrame {
+    pub(super) fn new(code: Rc<RegisterCode>, base: usize, initializer: bool) -> Self {
+        Self {
+            code,
+            ip: 0,
+            base,
+            initializer,
+            generator: None,
+        }
+    }
+
+    /// Return the function the frame is running.
+    pub(super) fn function(&self) -> &Rc<Function> {
+        &self.code.function
+    }
+
+    /// Return the span of the instruction currently executing.
+    pub(super) fn current_span(&self) -> Option<Span> {
+        let offset = self.code.offsets[self.ip.saturating_sub(1)];
+        self.code.function.chunk.span_at(offset as usize)
+    }
+}
+
+/// Translate `function` into register code. Returns an error if the
+/// function doesn't pass [`verify`](crate::bytecode::verify::verify), which
+/// the compiler's output always does.
+pub fn lower(function: Rc<Function>) -> Result<RegisterCode, &'static str> {
+    let chunk = &function.chunk;
+    let (depths, registers) = depths(&function).map_err(|error| error.reason)?;
+
+    let mut code = Vec::new();
+    let mut offsets = Vec::new();
+    // The index of the first instruction translated from each offset
+    let mut starts = vec![0; chunk.code.len() + 1];
+    let mut offset = 0;
+
+    while offset < chunk.code.len() {
+        let op = OpCode::from_byte(chunk.code[offset]).ok_or("invalid opcode")?;
+        starts[offset] = code.len() as u32;
+        let next = offset + 1 + op.operand_len();
+        // Unreachable code is dropped, and pops only change the stack depth,
+        // which is tracked statically
+        let depth = match depths[offset] {
+            Some(depth) if !matches!(op, OpCode::Pop | OpCode::PopN) => depth,
+            _ => {
+                offset = next;
+                continue;
+            }
+        };
+
+        let u8_operand = || chunk.code[offset + 1];
+        let u16_operand = || chunk.read_u16(offset + 1);
+        let top = depth.wrapping_sub(1);
+        let instr = match op {
+            OpCode::Constant => Instr::LoadConstant {
+                dst: depth,
+                index: u16_operand(),
+            },
+            OpCode::Unit => Instr::LoadUnit { dst: depth },
+            OpCode::True | OpCode::False => Instr::LoadBool {
+                dst: depth,
+                value: op == OpCode::True,
+            },
+            OpCode::Pop | OpCode::PopN => unreachable!("pops are skipped"),
+            OpCode::Dup => Instr::Move {
+                dst: depth,
+                src: top,
+            },
+            OpCode::GetLocal => Instr::Move {
+                dst: depth,
+                src: u8_operand() as Register,
+            },
+            OpCode::SetLocal => Instr::Move {
+                dst: u8_operand() as Register,
+                src: top,
+            },
+            OpCode::DefineGlobal => Instr::DefineGlobal {
+                src: top,
+                name: u16_operand(),
+            },
+            OpCode::GetGlobal => Instr::GetGlobal {
+                dst: depth,
+                name: u16_operand(),
+            },
+            OpCode::SetGlobal => Instr::SetGlobal {
+                src: top,
+                name: u16_operand(),
+            },
+            OpCode::Add
+            | OpCode::Subtract
+            | OpCode::Multiply
+            | OpCode::Divide
+            | OpCode::Remainder
+            | OpCode::Equal
+            | OpCode::NotEqual
+            | OpCode::Greater
+            | OpCode::GreaterEqual
+            | OpCode::Less
+            | OpCode::LessEqual => Instr::Binary {
+                op,
+                dst: depth - 2,
+                left: depth - 2,
+                right: top,
+            },
+            OpCode::Negate | OpCode::Not => Instr::Unary {
+                op,
+                dst: top,
+                src: top,
+            },
+            OpCode::Cast => Instr::Cast {
+                dst: top,
+                src: top,
+                ty: CastType::from_byte(u8_operand()).ok_or("unknown cast type")?,
+            },
+            OpCode::Format => {
+                let argc = u8_operand();
+                Instr::Format {
+                    dst: depth - argc as Register - 1,
+                    start: depth - argc as Register - 1,
+                    argc,
+                }
+            }
+            OpCode::Jump | OpCode::Loop => Instr::Jump {
+                target: jump_target(chunk, op, offset).ok_or("jump out of bounds")? as u32,
+            },
+            OpCode::JumpIfFalse => Instr::JumpIfFalse {
+                cond: top,
+                target: jump_target(chunk, op, offset).ok_or("jump out of bounds")? as u32,
+            },
+            OpCode::BuildList => {
+                let len = u8_operand();
+                Instr::BuildList {
+                    dst: depth - len as Register,
+                    start: depth - len as Register,
+                    len,
+                }
+            }
+            OpCode::GetIndex => Instr::GetIndex {
+                dst: depth - 2,
+                object: depth - 2,
+                index: top,
+            },
+            OpCode::SetIndex => Instr::SetIndex {
+                dst: depth - 3,
+                list: depth - 3,
+                index: depth - 2,
+                value: top,
+            },
+            OpCode::Slice => Instr::Slice {
+                dst: depth - 3,
+                object: depth - 3,
+                start: depth - 2,
+                end: top,
+                inclusive: u8_operand() != 0,
+            },
+            OpCode::Range => Instr::Range {
+                dst: depth - 2,
+                start: depth - 2,
+                end: top,
+                inclusive: u8_operand() != 0,
+            },
+            OpCode::RangeBounds => Instr::RangeBounds {
+                start: depth - 2,
+                end: top,
+            },
+            OpCode::Class => {
+                let count = u8_operand();
+                Instr::Class {
+                    dst: depth - count as Register - 1,
+                    start: depth - count as Register,
+                    count,
+                }
+            }
+            OpCode::GetField => Instr::GetField {
+                dst: top,
+                object: top,
+                name: u16_operand(),
+            },
+            OpCode::SetField => Instr::SetField {
+                dst: depth - 2,
+                object: depth - 2,
+                value: top,
+                name: u16_operand(),
+            },
+            OpCode::Call => {
+                let argc = u8_operand();
+                Instr::Call {
+                    callee: depth - argc as Register - 1,
+                    argc,
+                }
+            }
+            OpCode::Return => Instr::Return { src: top },
+            OpCode::Spawn => {
+                let argc = u8_operand();
+                Instr::Spawn {
+                    callee: depth - argc as Register - 1,
+                    argc,
+                }
+            }
+            OpCode::Next => Instr::Next {
+                dst: depth,
+                iterator: u8_operand() as Register,
+            },
+            OpCode::Yield => Instr::Yield { src: top },
+            OpCode::InRange => Instr::InRange {
+                dst: depth - 2,
+                value: depth - 2,
+                range: top,
+            },
+            OpCode::Switch => Instr::Switch {
+                src: top,
+                min: u16_operand(),
+                len: chunk.read_u16(offset + 3),
+                table: next as u32,
+            },
+            OpCode::Unmatched => Instr::Unmatched { src: top },
+        };
+        code.push(instr);
+        offsets.push(offset as u32);
+        offset = next;
+    }
+    starts[chunk.code.len()] = code.len() as u32;
+
+    // Jumps were translated with bytecode offsets as their targets
+    for instr in &mut code {
+        if let Instr::Jump { target }
+        | Instr::JumpIfFalse { target, .. }
+        | Instr::Switch { table: target, .. } = instr
+        {
+            *target = starts[*target as usize];
+        }
+    }
+
+    Ok(RegisterCode {
+        function,
+        code,
+        offsets,
+        registers,
+    })
+}
+
+impl Vm {
+    /// Return the register code for `function`, translating it the first
+    /// time it is called.
+    pub(super) fn lowered(&mut self, function: Rc<Function>) -> RunResult<Rc<RegisterCode>> {
+        // The cache keeps the function alive, so its address can't be reused
+        let key = Rc::as_ptr(&function);
+        if let Some(code) = self.lowered.get(&key) {
+            return Ok(code.clone());
+        }
+
+        let code = lower(function).map_err(|message| self.malformed(message))?;
+        let code = Rc::new(code);
+        self.lowered.insert(key, code.clone());
+        Ok(code)
+    }
+
+    /// Push a frame calling `function`, whose window starts at `base`.
+    pub(super) fn push_register_frame(
+        &mut self,
+        function: Rc<Function>,
+        base: usize,
+        initializer: bool,
+    ) -> RunResult<()> {
+        let code = self.lowered(function)?;
+        let len = base + code.registers;
+        self.stack.resize(len, Value::Unit);
+        self.register_frames
+            .push(RegisterFrame::new(code, base, initializer));
+        Ok(())
+    }
+
+    fn register_frame(&self) -> &RegisterFrame {
+        self.register_frames.last().expect("no call frame")
+    }
+
+    fn register_frame_mut(&mut self) -> &mut RegisterFrame {
+        self.register_frames.last_mut().expect("no call frame")
+    }
+
+    pub(super) fn execute_registers(&mut self) -> RunResult<Value> {
+        loop {
+            self.consume_fuel()?;
+            if self.scheduler.slice == 0 {
+                self.preempt();
+            }
+            self.scheduler.slice -= 1;
+            let frame = self.register_frame_mut();
+            let instr = frame.code.code[frame.ip];
+            frame.ip += 1;
+            let base = frame.base;
+            if self.profile.is_some() || self.observer.is_some() {
+                self.record_register_instruction();
+            }
+            if self.tracer.is_some() {
+                let ip = self.register_frame().ip - 1;
+                self.trace_instruction(base, &format!("{:04} {:?}", ip, instr));
+            }
+
+            let reg = |register: Register| base + register as usize;
+            match instr {
+                Instr::LoadConstant { dst, index } => {
+                    let chunk = &self.register_frame().code.function.chunk;
+                    self.stack[reg(dst)] = chunk.constants[index as usize].clone();
+                }
+                Instr::LoadUnit { dst } => self.stack[reg(dst)] = Value::Unit,
+                Instr::LoadBool { dst, value } => self.stack[reg(dst)] = Value::Bool(value),
+                Instr::Move { dst, src } => self.stack[reg(dst)] = self.stack[reg(src)].clone(),
+                Instr::DefineGlobal { src, name } => {
+                    let name = self.constant_name(name);
+                    let id = self.globals.intern(&name);
+                    self.globals.define(id, self.stack[reg(src)].clone());
+                }
+                Instr::GetGlobal { dst, name } => {
+                    let name = self.constant_name(name);
+                    let id = self.globals.intern(&name);
+                    match self.globals.get(id) {
+                        Some(value) => self.stack[reg(dst)] = value.clone(),
+                        None => return Err(self.undefined(&name)),
+                    }
+                }
+                Instr::SetGlobal { src, name } => {
+                    let name = self.constant_name(name);
+                    let id = self.globals.intern(&name);
+                    if !self.globals.set(id, self.stack[reg(src)].clone()) {
+                        return Err(self.undefined(&name));
+                    }
+                }
+                Instr::Binary {
+                    op,
+                    dst,
+                    left,
+                    right,
+                } => {
+                    let left = self.stack[reg(left)].clone();
+                    let right = self.stack[reg(right)].clone();
+                    self.stack[reg(dst)] = self.binary(op, left, right)?;
+                }
+                Instr::Unary { op, dst, src } => {
+                    let value = self.stack[reg(src)].clone();
+                    self.stack[reg(dst)] = self.unary(op, value)?;
+                }
+                Instr::Cast { dst, src, ty } => {
+                    let value = self.stack[reg(src)].clone();
+                    self.stack[reg(dst)] = self.cast(value, ty)?;
+                }
+                Instr::Jump { target } => self.register_frame_mut().ip = target as usize,
+                Instr::JumpIfFalse { cond, target } => match &self.stack[reg(cond)] {
+                    Value::Bool(false) => self.register_frame_mut().ip = target as usize,
+                    Value::Bool(true) => {}
+                    value => {
+                        return Err(self.error(format!(
+                            "expected a bool condition, found a value of type {}",
+                            value.type_name()
+                        )))
+                    }
+                },
+                Instr::InRange { dst, value, range } => {
+                    let result = in_range(&self.stack[reg(value)], &self.stack[reg(range)]);
+                    self.stack[reg(dst)] = Value::Bool(result);
+                }
+                Instr::Switch {
+                    src,
+                    min,
+                    len,
+                    table,
+                } => {
+                    // Every entry of the table is a jump, which translates
+                    // to a single instruction
+                    let chunk = &self.register_frame().code.function.chunk;
+                    let index = switch_index(
+                        &self.stack[reg(src)],
+                        &chunk.constants[min as usize],
+                        len as usize,
+                    );
+                    self.register_frame_mut().ip = table as usize + index;
+                }
+                Instr::Unmatched { src } => return Err(self.unmatched(&self.stack[reg(src)])),
+                Instr::Format { dst, start, argc } => {
+                    let start = reg(start);
+                    let value = self.interpolate(&self.stack[start..=start + argc as usize])?;
+                    self.stack[reg(dst)] = value;
+                }
+                Instr::BuildList { dst, start, len } => {
+                    self.maybe_collect();
+                    let start = reg(start);
+                    let items = self.stack[start..start + len as usize].to_vec();
+                    let list = self.alloc(Object::List(items));
+                    self.stack[reg(dst)] = Value::List(list);
+                }
+                Instr::GetIndex { dst, object, index } => {
+                    self.stack[reg(dst)] =
+                        self.get_index(&self.stack[reg(object)], &self.stack[reg(index)])?;
+                }
+                Instr::Slice {
+                    dst,
+                    object,
+                    start,
+                    end,
+                    inclusive,
+                } => {
+                    // The operands stay in their registers, so that a sliced
+                    // list is rooted during a collection
+                    self.maybe_collect();
+                    let object = self.stack[reg(object)].clone();
+                    let start = self.stack[reg(start)].clone();
+                    let end = self.stack[reg(end)].clone();
+                    self.stack[reg(dst)] = self.slice(object, &start, &end, inclusive)?;
+                }
+                Instr::Range {
+                    dst,
+                    start,
+                    end,
+                    inclusive,
+                } => {
+                    let start = &self.stack[reg(start)];
+                    let end = &self.stack[reg(end)];
+                    self.stack[reg(dst)] = self.range(start, end, inclusive)?;
+                }
+                Instr::RangeBounds { start, end } => {
+                    self.range_bound(&self.stack[reg(start)])?;
+                    self.range_bound(&self.stack[reg(end)])?;
+                }
+                Instr::SetIndex {
+                    dst,
+                    list,
+                    index,
+                    value,
+                } => {
+                    let (list, index) =
+                        self.index(&self.stack[reg(list)], &self.stack[reg(index)])?;
+                    let value = self.stack[reg(value)].clone();
+                    self.heap.list_mut(list)[index] = value.clone();
+                    self.stack[reg(dst)] = value;
+                }
+                Instr::Class { dst, start, count } => {
+                    let start = reg(start);
+                    let methods = self.stack[start..start + count as usize].to_vec();
+                    let name = self.stack[start - 1].clone();
+                    self.stack[reg(dst)] =
+                        class(name, methods).map_err(|message| self.malformed(message))?;
+                }
+                Instr::GetField { dst, object, name } => {
+                    let name = self.constant_name(name);
+                    let object = self.stack[reg(object)].clone();
+                    self.stack[reg(dst)] = self.get_field(object, &name)?;
+                }
+                Instr::SetField {
+                    dst,
+                    object,
+                    value,
+                    name,
+                } => {
+                    let name = self.constant_name(name);
+                    let object = self.stack[reg(object)].clone();
+                    let value = self.stack[reg(value)].clone();
+                    self.set_field(object, name, value.clone())?;
+                    self.stack[reg(dst)] = value;
+                }
+                Instr::Call { callee, argc } => {
+                    let callee = reg(callee);
+                    match self.prepare_call(callee, argc)? {
+                        Prepared::Frame(function, initializer) => {
+                            self.push_register_frame(function, callee, initializer)?;
+                        }
+                        Prepared::Done => {}
+                        Prepared::Blocked => {
+                            if let Some(result) = self.block()? {
+                                return Ok(result);
+                            }
+                            // Run the call again once the task resumes
+                            self.register_frame_mut().ip -= 1;
+                            self.switch_task();
+                        }
+                    }
+                }
+                Instr::Return { src } => {
+                    let frame = self.register_frames.pop().expect("no call frame");
+                    let result = if frame.initializer {
+                        self.stack[base].clone()
+                    } else {
+                        self.stack[reg(src)].clone()
+                    };
+                    if let Some(generator) = frame.generator {
+                        self.heap.generator_mut(generator).state = GeneratorState::Done;
+                        self.return_from_generator(base, Value::Unit, false);
+                        continue;
+                    }
+                    if self.callback == Some(self.register_frames.len()) {
+                        return Ok(result);
+                    }
+
+                    let Some(caller) = self.register_frames.last() else {
+                        self.stack.truncate(base);
+                        match self.finish_task(result) {
+                            Some(result) => return Ok(result),
+                            None => continue,
+                        }
+                    };
+                    let len = caller.base + caller.code.registers;
+                    self.stack.resize(len, Value::Unit);
+                    self.stack[base] = result;
+                }
+                Instr::Spawn { callee, argc } => self.spawn(reg(callee), argc)?,
+                Instr::Next { dst, iterator } => {
+                    if let Value::Range(range) = &mut self.stack[reg(iterator)] {
+                        let next = range.next();
+                        self.stack[reg(dst)] = next.map_or(Value::Unit, Value::Int);
+                        self.stack[reg(dst) + 1] = Value::Bool(next.is_some());
+                        continue;
+                    }
+                    let iterator = self.stack[reg(iterator)].clone();
+                    let Some(resumed) = self.resume_generator(&iterator)? else {
+                        self.stack[reg(dst)] = Value::Unit;
+                        self.stack[reg(dst) + 1] = Value::Bool(false);
+                        continue;
+                    };
+                    let code = self.lowered(resumed.function)?;
+                    let start = reg(dst);
+                    self.stack.truncate(start);
+                    self.stack.extend(resumed.slots);
+                    self.stack.resize(start + code.registers, Value::Unit);
+                    self.register_frames.push(RegisterFrame {
+                        code,
+                        ip: resumed.ip,
+                        base: start,
+                        initializer: false,
+                        generator: Some(resumed.generator),
+                    });
+                }
+                Instr::Yield { src } => {
+                    let value = self.stack[reg(src)].clone();
+                    let frame = self.register_frame();
+                    let (ip, registers) = (frame.ip, frame.code.registers);
+                    let generator = frame
+                        .generator
+                        .ok_or_else(|| self.malformed("yield outside of a generator"))?;
+                    let slots = self.stack[base..base + registers].to_vec();
+                    self.heap.generator_mut(generator).state =
+                        GeneratorState::Suspended { ip, slots };
+                    self.register_frames.pop();
+                    self.return_from_generator(base, value, true);
+                }
+            }
+        }
+    }
+
+    /// Once the frame of a generator whose window started at `base` has been
+    /// popped, pass `value`, and whether the generator produced it, to the
+    /// instruction that resumed the generator.
+    fn return_from_generator(&mut self, base: usize, value: Value, produced: bool) {
+        let caller = self.register_frame();
+        let len = caller.base + caller.code.registers;
+        self.stack.resize(len, Value::Unit);
+        self.stack[base] = value;
+        self.stack[base + 1] = Value::Bool(produced);
+    }
+
+    /// Record the instruction that just started in the profile, and tell
+    /// the observer about it, under the opcode it was translated from.
+    fn record_register_instruction(&mut self) {
+        let frame = self.register_frames.last().expect("no call frame");
+        let function = &frame.code.function;
+        let offset = frame.code.offsets[frame.ip - 1] as usize;
+        let op = OpCode::from_byte(function.chunk.code[offset]).expect("invalid opcode");
+        if let Some(profile) = &mut self.profile {
+            profile.record(op, function);
+        }
+        if let Some(observer) = &mut self.observer {
+            observer.on_instruction(&Instruction {
+                function,
+                op: Some(op),
+                span: frame.current_span(),
+            });
+        }
+    }
+
+    /// Read a constant holding a name from the current function.
+    fn constant_name(&self, index: u16) -> Rc<str> {
+        match &self.register_frame().code.function.chunk.constants[index as usize] {
+            Value::Str(name) => name.clone(),
+            value => panic!("name is not a string: {:?}", value),
+        }
+    }
+}
@@ -0,0 +1,162 @@
+//! A [`SandboxPolicy`] limits what programs can do, so that hosts can run
+//! code they don't trust. It is set with
+//! [`Vm::set_sandbox`](super::Vm::set_sandbox), and every native function
+//! checks it before touching files or the environment. Programs that break
+//! the policy fail with [`RuntimeErrorKind::Denied`], or
+//! [`RuntimeErrorKind::MemoryExhausted`] if they use too much memory.
+//!
+//! The policy works on top of the VM's [`FileSystem`](super::files) and
+//! [`Environment`](super::env), which decide what the allowed files and
+//! variables are.
+//!
+//! [`RuntimeErrorKind::Denied`]: crate::errors::RuntimeErrorKind::Denied
+//! [`RuntimeErrorKind::MemoryExhausted`]: crate::errors::RuntimeErrorKind::MemoryExhausted
+
+use std::path::{Component, Path, PathBuf};
+
+/// Something programs can be allowed or denied to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capability {
+    /// Reading and writing files, with the `std.fs` module.
+    Files,
+    /// Reading environment variables and using the working directory, with
+    /// the `std.env` and `std.os` modules.
+    Env,
+    /// Starting other processes.
+    Subprocess,
+    /// Using the network.
+    Network,
+}
+
+/// What programs run by a VM are allowed to do. The default policy allows
+/// everything, as a VM does without one.
+///
+/// No native function starts processes or uses the network, so those
+/// capabilities are for hosts to check with [`SandboxPolicy::allows`] before
+/// [registering](super::Vm::register_fn) functions that do.
+///
+/// # Examples
+///
+/// ```
+/// use meow::{compile, errors::RuntimeErrorKind, vm::{sandbox::SandboxPolicy, Vm}};
+///
+/// let mut vm = Vm::new();
+/// vm.set_sandbox(SandboxPolicy {
+///     file_roots: Some(vec!["data".into()]),
+///     max_objects: Some(1000),
+///     ..SandboxPolicy::strict()
+/// });
+///
+/// let mut run = |source| vm.run(compile(source).unwrap()).unwrap_err();
+/// let error = run("import std.fs; fs.read_to_string(\"../secrets.txt\");");
+/// assert_eq!(error.kind, RuntimeErrorKind::Denied);
+/// let error = run("import std.env; env.get(\"HOME\");");
+/// assert_eq!(error.message, "`get` needs access to the environment, which the sandbox denies");
+/// let error = run("let mut xs = []; while true { xs = [xs]; }");
+/// assert_eq!(error.kind, RuntimeErrorKind::MemoryExhausted);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SandboxPolicy {
+    /// The directories programs can read and write files in, including the
+    /// directories below them, or `None` to allow every file. Paths are
+    /// compared as written, after resolving `.` and `..`, so relative paths
+    /// are only allowed under relative roots.
+    pub file_roots: Option<Vec<PathBuf>>,
+    /// Whether programs can read environment variables and use the working
+    /// directory.
+    pub env: bool,
+    /// Whether programs can start other processes.
+    pub subprocess: bool,
+    /// Whether programs can use the network.
+    pub network: bool,
+    /// The most objects that can be on the heap at once, or `None` for no
+    /// limit. Garbage is collected before the limit is reached, and it is
+    /// checked before every instruction.
+    pub max_objects: Option<usize>,
+    /// The instruction budget given to the VM when the policy is set, as by
+    /// [`Vm::with_fuel`](super::Vm::with_fuel), or `None` to leave it as it
+    /// is.
+    pub fuel: Option<u64>,
+}
+
+impl Default for SandboxPolicy {
+    fn default() -> Self {
+        Self {
+            file_roots: None,
+            env: true,
+            subprocess: true,
+            network: true,
+            max_objects: None,
+            fuel: None,
+        }
+    }
+}
+
+impl SandboxPolicy {
+    /// Create a policy that denies every capability, but doesn't limit
+    /// memory or fuel.
+    pub fn strict() -> Self {
+        Self {
+            file_roots: Some(Vec::new()),
+            env: false,
+            subprocess: false,
+            network: false,
+            max_objects: None,
+            fuel: None,
+        }
+    }
+
+    /// Returns true if programs may use `capability` at all. Files are
+    /// allowed if there are any roots, though only the paths under them
+    /// are, see [`SandboxPolicy::allows_path`].
+    pub fn allows(&self, capability: Capability) -> bool {
+        match capability {
+            Capability::Files => self
+                .file_roots
+                .as_ref()
+                .is_none_or(|roots| !roots.is_empty()),
+            Capability::Env => self.env,
+            Capability::Subprocess => self.subprocess,
+            Capability::Network => self.network,
+        }
+    }
+
+    /// Returns true if programs may read and write the file at `path`.
+    pub fn allows_path(&self, path: &str) -> bool {
+        let Some(roots) = &self.file_roots else {
+            return true;
+        };
+        let path = normalize(Path::new(path));
+        roots.iter().any(|root| {
+            let root = normalize(root);
+            // Everything starts with the empty path, which `.` becomes, but
+            // absolute paths and paths leaving the directory aren't in it
+            if root.as_os_str().is_empty() {
+                path.is_relative() && !path.starts_with("..")
+            } else {
+                path.starts_with(&root)
+            }
+        })
+    }
+}
+
+/// Resolve the `.` and `..` in `path` without looking at the file system.
+/// A `..` that can't be resolved is kept at the start of a relative path,
+/// and dropped after the root of an absolute one.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normal.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normal.pop();
+                }
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                _ => normal.push(".."),
+            },
+            component => normal.push(component),
+        }
+    }
+    normal
+}
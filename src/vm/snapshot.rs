@@ -0,0 +1,604 @@
+//! A [`Vm`]'s state can be saved as a snapshot and restored later, so that
+//! embedded scripts can be checkpointed, and REPL sessions continued where
+//! they were left off.
+//!
+//! Snapshots hold the globals and the objects reachable from them, which is
+//! all of the VM's state between calls to [`Vm::run`]. A program paused by
+//! [`Vm::step`] has frames that refer to the heap as well, so snapshots
+//! can't be taken or restored until it finishes. Generators hold the state
+//! of their suspended frames, so they can still be resumed after a restore.
+//!
+//! A snapshot starts with the [`MAGIC`] bytes, the snapshot [`VERSION`] and
+//! the [`serialize::VERSION`] of the functions in it. It is followed by the
+//! objects reachable from the globals, numbered in order, and then by each
+//! global's name and value. Values are tagged like constants in `.mwc`
+//! files, and objects are referred to by their number. Functions and classes
+//! are written in full the first time they appear, and by their number after
+//! that, so values that shared them before still do.
+
+use super::{
+    bigint::BigInt,
+    heap::{BoundMethod, Class, Generator, GeneratorState, Heap, Instance, Method, ObjRef, Object},
+    host::HostFunction,
+    native::Native,
+    Backend, Globals, Vm,
+};
+use crate::{
+    bytecode::{
+        serialize::{self, encode_str, encode_u32, Reader},
+        verify::verify,
+        Function,
+    },
+    errors::{LoadError, RuntimeError},
+    value::{Range, Value},
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    rc::Rc,
+};
+
+/// The bytes every snapshot starts with.
+pub const MAGIC: &[u8; 4] = b"MWSN";
+
+/// The version of the snapshot format. Snapshots with any other version, or
+/// with functions in another version of the `.mwc` format, are rejected.
+pub const VERSION: u16 = 7;
+
+// Tags identifying the type of each value
+const TAG_UNIT: u8 = 0;
+const TAG_BOOL: u8 = 1;
+const TAG_INT: u8 = 2;
+const TAG_FLOAT: u8 = 3;
+const TAG_CHAR: u8 = 4;
+const TAG_STR: u8 = 5;
+const TAG_FUNCTION: u8 = 6;
+const TAG_FUNCTION_REF: u8 = 7;
+const TAG_CLASS: u8 = 8;
+const TAG_CLASS_REF: u8 = 9;
+const TAG_LIST: u8 = 10;
+const TAG_INSTANCE: u8 = 11;
+const TAG_BOUND_METHOD: u8 = 12;
+const TAG_NATIVE: u8 = 13;
+const TAG_CHANNEL: u8 = 14;
+const TAG_GENERATOR: u8 = 15;
+const TAG_BIG_INT: u8 = 16;
+const TAG_MODULE: u8 = 17;
+const TAG_RANGE: u8 = 18;
+const TAG_HOST: u8 = 19;
+const TAG_HOST_CLASS: u8 = 20;
+
+// Tags identifying the state of each generator
+const STATE_SUSPENDED: u8 = 0;
+const STATE_RUNNING: u8 = 1;
+const STATE_DONE: u8 = 2;
+
+impl Vm {
+    /// Save the VM's globals, and every object reachable from them, as a
+    /// snapshot that [`Vm::restore`] can load later. Settings such as the
+    /// backend and fuel aren't included. Fails if a program is
+    /// [paused](Vm::is_paused), since its frames can't be saved.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use meow::{compile, value::Value, vm::Vm};
+    ///
+    /// let mut vm = Vm::new();
+    /// vm.run(compile("let xs = [1, 2];").unwrap()).unwrap();
+    /// let snapshot = vm.snapshot().unwrap();
+    ///
+    /// let mut restored = Vm::new();
+    /// restored.restore(&snapshot).unwrap();
+    /// restored.run(compile("let n = xs[0] + xs[1];").unwrap()).unwrap();
+    /// assert_eq!(restored.global("n"), Some(&Value::Int(3)));
+    /// ```
+    pub fn snapshot(&self) -> Result<Vec<u8>, RuntimeError> {
+        if self.is_paused() {
+            return Err(RuntimeError::new(
+                "cannot take a snapshot while a program is paused",
+                None,
+            ));
+        }
+        let mut writer = Writer::new(&self.heap);
+        for (_, value) in self.globals.iter() {
+            writer.reach(value);
+        }
+        for &obj in &writer.order {
+            if let Object::Instance(instance) = self.heap.get(obj) {
+                if instance.data.is_some() {
+                    return Err(RuntimeError::new(
+                        format!(
+                            "cannot take a snapshot holding a `{}` instance, since it wraps a Rust value",
+                            instance.class.name
+                        ),
+                        None,
+                    ));
+                }
+            }
+        }
+
+        let mut out = MAGIC.to_vec();
+        out.extend_from_slice(&VERSION.to_be_bytes());
+        out.extend_from_slice(&serialize::VERSION.to_be_bytes());
+
+        encode_u32(&mut out, writer.order.len());
+        for index in 0..writer.order.len() {
+            let object = self.heap.get(writer.order[index]);
+            writer.object(&mut out, object);
+        }
+
+        let globals: Vec<_> = self.globals.iter().collect();
+        encode_u32(&mut out, globals.len());
+        for (name, value) in globals {
+            encode_str(&mut out, name);
+            writer.value(&mut out, value);
+        }
+        Ok(out)
+    }
+
+    /// Replace the VM's globals and heap with those saved in `snapshot` by
+    /// [`Vm::snapshot`]. The VM's settings are kept, and on error the VM is
+    /// left unchanged. Fails if a program is [paused](Vm::is_paused), since
+    /// its frames refer to the heap being replaced.
+    ///
+    /// Functions in the snapshot are
+    /// [verified](crate::bytecode::verify::verify), and references between
+    /// objects are checked, but the frames of suspended generators aren't,
+    /// so snapshots should only be restored from trusted sources.
+    pub fn restore(&mut self, snapshot: &[u8]) -> Result<(), LoadError> {
+        if self.is_paused() {
+            return Err(LoadError::Paused);
+        }
+        let mut reader = SnapshotReader {
+            reader: Reader::new(snapshot),
+            hosts: &self.hosts,
+            host_classes: &self.host_classes,
+            functions: Vec::new(),
+            classes: Vec::new(),
+        };
+
+        if reader.reader.take(MAGIC.len())? != MAGIC {
+            return Err(LoadError::NotASnapshot);
+        }
+        for version in [VERSION, serialize::VERSION] {
+            let found = reader.reader.u16()?;
+            if found != version {
+                return Err(LoadError::UnsupportedVersion(found));
+            }
+        }
+
+        let count = reader.reader.len()?;
+        let mut objects = Vec::new();
+        for _ in 0..count {
+            objects.push(reader.object()?);
+        }
+
+        let mut globals = Vec::new();
+        for _ in 0..reader.reader.len()? {
+            globals.push((reader.reader.string()?, reader.value()?));
+        }
+        if !reader.reader.is_done() {
+            return Err(LoadError::TrailingBytes);
+        }
+
+        let mut valid = globals.iter().all(|(_, value)| is_valid(&objects, value));
+        for object in &objects {
+            object.trace(|value| valid &= is_valid(&objects, value));
+        }
+        if !valid {
+            return Err(LoadError::Malformed("invalid object reference"));
+        }
+        for function in &reader.functions {
+            verify(function)?;
+        }
+
+        self.heap = Heap::from_objects(self.heap.config(), objects);
+        self.globals = Globals::new();
+        for (name, value) in globals {
+            self.set_global(&name, value);
+        }
+        Ok(())
+    }
+}
+
+/// Returns true unless `value` refers to an object that doesn't exist, or
+/// is of the wrong kind.
+fn is_valid(objects: &[Object], value: &Value) -> bool {
+    let object = |obj: &ObjRef| objects.get(obj.index());
+    match value {
+        Value::List(obj) => matches!(object(obj), Some(Object::List(_))),
+        Value::Instance(obj) => matches!(object(obj), Some(Object::Instance(_))),
+        Value::BoundMethod(obj) => matches!(object(obj), Some(Object::BoundMethod(_))),
+        Value::Channel(obj) => matches!(object(obj), Some(Object::Channel(_))),
+        Value::Generator(obj) => matches!(object(obj), Some(Object::Generator(_))),
+        _ => true,
+    }
+}
+
+/// Numbers the objects, functions and classes in a snapshot as it is
+/// written.
+struct Writer<'a> {
+    heap: &'a Heap,
+    /// Every reachable object, in the order they are numbered.
+    order: Vec<ObjRef>,
+    objects: HashMap<ObjRef, u32>,
+    functions: HashMap<*const Function, u32>,
+    classes: HashMap<*const Class, u32>,
+}
+
+impl<'a> Writer<'a> {
+    fn new(heap: &'a Heap) -> Self {
+        Self {
+            heap,
+            order: Vec::new(),
+            objects: HashMap::new(),
+            functions: HashMap::new(),
+            classes: HashMap::new(),
+        }
+    }
+
+    /// Number every object reachable from `value` that hasn't been numbered
+    /// yet.
+    fn reach(&mut self, value: &Value) {
+        let mut pending = vec![value.clone()];
+        while let Some(value) = pending.pop() {
+            let obj = match value {
+                Value::List(obj)
+                | Value::Instance(obj)
+                | Value::BoundMethod(obj)
+                | Value::Channel(obj)
+                | Value::Generator(obj) => obj,
+                _ => continue,
+            };
+            if self.objects.contains_key(&obj) {
+                continue;
+            }
+            self.objects.insert(obj, self.order.len() as u32);
+            self.order.push(obj);
+            self.heap
+                .get(obj)
+                .trace(|value| pending.push(value.clone()));
+        }
+    }
+
+    fn object(&mut self, out: &mut Vec<u8>, object: &Object) {
+        match object {
+            Object::List(items) => {
+                out.push(TAG_LIST);
+                self.values(out, items);
+            }
+            Object::Instance(instance) => {
+                out.push(TAG_INSTANCE);
+                self.class(out, &instance.class);
+                encode_u32(out, instance.fields.len());
+                for (name, value) in &instance.fields {
+                    encode_str(out, name);
+                    self.value(out, value);
+                }
+            }
+            Object::BoundMethod(bound) => {
+                out.push(TAG_BOUND_METHOD);
+                self.value(out, &bound.receiver);
+                match &bound.method {
+                    Method::Function(function) => self.function(out, function),
+                    Method::Native(native) => self.value(out, &Value::Native(*native)),
+                    Method::Host(_) => {
+                        unreachable!("host methods are bound to instances that wrap Rust values")
+                    }
+                }
+            }
+            Object::Channel(queue) => {
+                out.push(TAG_CHANNEL);
+                encode_u32(out, queue.len());
+                for value in queue {
+                    self.value(out, value);
+                }
+            }
+            Object::Generator(generator) => {
+                out.push(TAG_GENERATOR);
+                self.function(out, &generator.function);
+                out.push(match generator.backend {
+                    Backend::Stack => 0,
+                    Backend::Register => 1,
+                    Backend::Ast => 2,
+                });
+                match &generator.state {
+                    GeneratorState::Suspended { ip, slots } => {
+                        out.push(STATE_SUSPENDED);
+                        encode_u32(out, *ip);
+                        self.values(out, slots);
+                    }
+                    GeneratorState::Running => out.push(STATE_RUNNING),
+                    GeneratorState::Done => out.push(STATE_DONE),
+                }
+            }
+        }
+    }
+
+    fn values(&mut self, out: &mut Vec<u8>, values: &[Value]) {
+        encode_u32(out, values.len());
+        for value in values {
+            self.value(out, value);
+        }
+    }
+
+    fn value(&mut self, out: &mut Vec<u8>, value: &Value) {
+        let object = |out: &mut Vec<u8>, tag, obj| {
+            out.push(tag);
+            encode_u32(out, self.objects[obj] as usize);
+        };
+        match value {
+            Value::Unit => out.push(TAG_UNIT),
+            Value::Bool(value) => out.extend_from_slice(&[TAG_BOOL, *value as u8]),
+            Value::Int(value) => {
+                out.push(TAG_INT);
+                out.extend_from_slice(&value.to_be_bytes());
+            }
+            Value::BigInt(value) => {
+                out.extend_from_slice(&[TAG_BIG_INT, value.is_negative() as u8]);
+                encode_u32(out, value.digits().len());
+                for digit in value.digits() {
+                    out.extend_from_slice(&digit.to_be_bytes());
+                }
+            }
+            Value::Float(value) => {
+                out.push(TAG_FLOAT);
+                out.extend_from_slice(&value.to_bits().to_be_bytes());
+            }
+            Value::Char(value) => {
+                out.push(TAG_CHAR);
+                encode_u32(out, *value as usize);
+            }
+            Value::Str(value) => {
+                out.push(TAG_STR);
+                encode_str(out, value);
+            }
+            Value::Function(function) => self.function(out, function),
+            Value::Class(class) => self.class(out, class),
+            Value::List(obj) => object(out, TAG_LIST, obj),
+            Value::Instance(obj) => object(out, TAG_INSTANCE, obj),
+            Value::BoundMethod(obj) => object(out, TAG_BOUND_METHOD, obj),
+            Value::Channel(obj) => object(out, TAG_CHANNEL, obj),
+            Value::Generator(obj) => object(out, TAG_GENERATOR, obj),
+            Value::Native(native) => {
+                out.push(TAG_NATIVE);
+                encode_str(out, &native.path());
+            }
+            Value::Host(host) => {
+                out.push(TAG_HOST);
+                encode_str(out, &host.name);
+            }
+            Value::Module(module) => {
+                out.push(TAG_MODULE);
+                encode_str(out, module.name());
+            }
+            Value::Range(range) => {
+                out.push(TAG_RANGE);
+                out.extend_from_slice(&range.start.to_be_bytes());
+                out.extend_from_slice(&range.end.to_be_bytes());
+                out.push(range.inclusive as u8);
+            }
+        }
+    }
+
+    fn function(&mut self, out: &mut Vec<u8>, function: &Rc<Function>) {
+        if let Some(&index) = self.functions.get(&Rc::as_ptr(function)) {
+            out.push(TAG_FUNCTION_REF);
+            encode_u32(out, index as usize);
+        } else {
+            let index = self.functions.len() as u32;
+            self.functions.insert(Rc::as_ptr(function), index);
+            out.push(TAG_FUNCTION);
+            serialize::encode_function(out, function);
+        }
+    }
+
+    fn class(&mut self, out: &mut Vec<u8>, class: &Rc<Class>) {
+        if let Some(&index) = self.classes.get(&Rc::as_ptr(class)) {
+            out.push(TAG_CLASS_REF);
+            encode_u32(out, index as usize);
+            return;
+        }
+        let index = self.classes.len() as u32;
+        self.classes.insert(Rc::as_ptr(class), index);
+        if class.host.is_some() {
+            out.push(TAG_HOST_CLASS);
+            encode_str(out, &class.name);
+            return;
+        }
+        out.push(TAG_CLASS);
+        encode_str(out, &class.name);
+        encode_u32(out, class.methods.len());
+        for (name, method) in &class.methods {
+            encode_str(out, name);
+            self.function(out, method);
+        }
+    }
+}
+
+/// Reads a snapshot, keeping the functions and classes seen so far so that
+/// later references to them can be resolved.
+struct SnapshotReader<'a> {
+    reader: Reader<'a>,
+    /// The functions registered by the host of the VM being restored.
+    hosts: &'a HashMap<String, Rc<HostFunction>>,
+    /// The classes registered by the host of the VM being restored.
+    host_classes: &'a HashMap<String, Rc<Class>>,
+    functions: Vec<Rc<Function>>,
+    classes: Vec<Rc<Class>>,
+}
+
+impl SnapshotReader<'_> {
+    fn object(&mut self) -> Result<Object, LoadError> {
+        Ok(match self.reader.u8()? {
+            TAG_LIST => Object::List(self.values()?),
+            TAG_INSTANCE => {
+                let class = self.class()?;
+                if class.host.is_some() {
+                    return Err(LoadError::Malformed("instance of a host class"));
+                }
+                let mut fields = HashMap::new();
+                for _ in 0..self.reader.len()? {
+                    let name = Rc::from(self.reader.string()?);
+                    fields.insert(name, self.value()?);
+                }
+                Object::Instance(Instance {
+                    class,
+                    fields,
+                    data: None,
+                })
+            }
+            TAG_BOUND_METHOD => Object::BoundMethod(BoundMethod {
+                receiver: self.value()?,
+                method: match self.reader.u8()? {
+                    TAG_NATIVE => Method::Native(self.native()?),
+                    tag => Method::Function(self.tagged_function(tag)?),
+                },
+            }),
+            TAG_CHANNEL => Object::Channel(VecDeque::from(self.values()?)),
+            TAG_GENERATOR => Object::Generator(Generator {
+                function: self.function()?,
+                backend: match self.reader.u8()? {
+                    0 => Backend::Stack,
+                    1 => Backend::Register,
+                    2 => Backend::Ast,
+                    _ => return Err(LoadError::Malformed("unknown backend")),
+                },
+                state: match self.reader.u8()? {
+                    STATE_SUSPENDED => GeneratorState::Suspended {
+                        ip: self.reader.len()?,
+                        slots: self.values()?,
+                    },
+                    STATE_RUNNING => GeneratorState::Running,
+                    STATE_DONE => GeneratorState::Done,
+                    _ => return Err(LoadError::Malformed("unknown generator state")),
+                },
+            }),
+            _ => return Err(LoadError::Malformed("unknown object type")),
+        })
+    }
+
+    fn values(&mut self) -> Result<Vec<Value>, LoadError> {
+        let len = self.reader.len()?;
+        let mut values = Vec::new();
+        for _ in 0..len {
+            values.push(self.value()?);
+        }
+        Ok(values)
+    }
+
+    fn value(&mut self) -> Result<Value, LoadError> {
+        let tag = self.reader.u8()?;
+        let obj = |reader: &mut Reader| Ok::<_, LoadError>(ObjRef::from_index(reader.len()?));
+        Ok(match tag {
+            TAG_UNIT => Value::Unit,
+            TAG_BOOL => Value::Bool(self.reader.u8()? != 0),
+            TAG_INT => Value::Int(i64::from_be_bytes(self.reader.array()?)),
+            TAG_BIG_INT => {
+                let negative = self.reader.u8()? != 0;
+                let mut digits = Vec::new();
+                for _ in 0..self.reader.len()? {
+                    digits.push(self.reader.u32()?);
+                }
+                BigInt::from_digits(negative, digits).into_value()
+            }
+            TAG_FLOAT => Value::Float(f64::from_bits(u64::from_be_bytes(self.reader.array()?))),
+            TAG_CHAR => Value::Char(
+                char::from_u32(self.reader.u32()?).ok_or(LoadError::Malformed("invalid char"))?,
+            ),
+            TAG_STR => Value::Str(Rc::from(self.reader.string()?)),
+            TAG_FUNCTION | TAG_FUNCTION_REF => Value::Function(self.tagged_function(tag)?),
+            TAG_CLASS | TAG_CLASS_REF | TAG_HOST_CLASS => Value::Class(self.tagged_class(tag)?),
+            TAG_LIST => Value::List(obj(&mut self.reader)?),
+            TAG_INSTANCE => Value::Instance(obj(&mut self.reader)?),
+            TAG_BOUND_METHOD => Value::BoundMethod(obj(&mut self.reader)?),
+            TAG_CHANNEL => Value::Channel(obj(&mut self.reader)?),
+            TAG_GENERATOR => Value::Generator(obj(&mut self.reader)?),
+            TAG_NATIVE => Value::Native(self.native()?),
+            TAG_HOST => {
+                let name = self.reader.string()?;
+                let host = self.hosts.get(&name);
+                Value::Host(host.ok_or(LoadError::UnknownHostFunction(name))?.clone())
+            }
+            TAG_MODULE => Value::Module(self.reader.module()?),
+            TAG_RANGE => Value::Range(Range {
+                start: i64::from_be_bytes(self.reader.array()?),
+                end: i64::from_be_bytes(self.reader.array()?),
+                inclusive: self.reader.u8()? != 0,
+            }),
+            tag => return Err(LoadError::UnknownConstant(tag)),
+        })
+    }
+
+    fn native(&mut self) -> Result<Native, LoadError> {
+        let name = self.reader.string()?;
+        let native = Native::ALL
+            .iter()
+            .copied()
+            .find(|native| native.path() == name);
+        native.ok_or(LoadError::Malformed("unknown native function"))
+    }
+
+    fn function(&mut self) -> Result<Rc<Function>, LoadError> {
+        let tag = self.reader.u8()?;
+        self.tagged_function(tag)
+    }
+
+    fn tagged_function(&mut self, tag: u8) -> Result<Rc<Function>, LoadError> {
+        match tag {
+            TAG_FUNCTION => {
+                let function = Rc::new(self.reader.function()?);
+                self.functions.push(function.clone());
+                Ok(function)
+            }
+            TAG_FUNCTION_REF => {
+                let index = self.reader.len()?;
+                let function = self.functions.get(index);
+                function
+                    .cloned()
+                    .ok_or(LoadError::Malformed("invalid function reference"))
+            }
+            _ => Err(LoadError::Malformed("expected a function")),
+        }
+    }
+
+    fn class(&mut self) -> Result<Rc<Class>, LoadError> {
+        let tag = self.reader.u8()?;
+        self.tagged_class(tag)
+    }
+
+    fn tagged_class(&mut self, tag: u8) -> Result<Rc<Class>, LoadError> {
+        match tag {
+            TAG_CLASS => {
+                let name = self.reader.string()?;
+                let mut methods = HashMap::new();
+                for _ in 0..self.reader.len()? {
+                    let method = self.reader.string()?;
+                    methods.insert(method, self.function()?);
+                }
+                let class = Rc::new(Class {
+                    name,
+                    methods,
+                    host: None,
+                });
+                self.classes.push(class.clone());
+                Ok(class)
+            }
+            TAG_HOST_CLASS => {
+                let name = self.reader.string()?;
+                let class = self.host_classes.get(&name);
+                let class = class.ok_or(LoadError::UnknownHostClass(name))?.clone();
+                self.classes.push(class.clone());
+                Ok(class)
+            }
+            TAG_CLASS_REF => {
+                let index = self.reader.len()?;
+                let class = self.classes.get(index);
+                class
+                    .cloned()
+                    .ok_or(LoadError::Malformed("invalid class reference"))
+            }
+            _ => Err(LoadError::Malformed("expected a class")),
+        }
+    }
+}
@@ -0,0 +1,205 @@
+//! Tasks are lightweight threads of execution within a single [`Vm`]. The
+//! script runs as the main task, and `spawn` starts another task running a
+//! call. Tasks don't run in parallel: the VM switches between them, giving
+//! each a slice of [`TIME_SLICE`] instructions before moving on to the next,
+//! and switching early when one has to wait to receive from a channel.
+//!
+//! Each task has its own stack and call frames. Only the running task's are
+//! stored in the VM's fields, while suspended tasks wait in a queue, in the
+//! order they will resume. A program finishes once every task has finished,
+//! or once the main task has finished and every other task is waiting on a
+//! channel that nothing will send to.
+
+use super::{heap::Method, register::RegisterFrame, Backend, CallFrame, Prepared, RunResult, Vm};
+use crate::{errors::RuntimeError, value::Value};
+use std::{collections::VecDeque, mem};
+
+/// The number of instructions a task runs before the next one gets a turn.
+pub const TIME_SLICE: u32 = 256;
+
+/// A suspended task.
+#[derive(Debug)]
+struct Task {
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    register_frames: Vec<RegisterFrame>,
+    main: bool,
+}
+
+/// The scheduler's view of the tasks of a running program.
+#[derive(Debug, Default)]
+pub(super) struct Scheduler {
+    tasks: VecDeque<Task>,
+    /// Whether the running task is the main task.
+    main: bool,
+    /// The value the main task returned, if it has finished.
+    result: Option<Value>,
+    /// The number of instructions the running task has left in its slice.
+    pub(super) slice: u32,
+    /// The number of tasks in a row that have had to wait without doing
+    /// anything else since they resumed.
+    idle: usize,
+}
+
+impl Scheduler {
+    /// Return every value held by a suspended task, or returned by the main
+    /// task, so they can be used as roots by the garbage collector.
+    pub(super) fn roots(&self) -> impl Iterator<Item = &Value> {
+        let stacks = self.tasks.iter().flat_map(|task| &task.stack);
+        stacks.chain(&self.result)
+    }
+}
+
+impl Vm {
+    /// Discard every task, making the running one the main task.
+    pub(super) fn reset_tasks(&mut self) {
+        self.scheduler = Scheduler {
+            main: true,
+            slice: TIME_SLICE,
+            ..Scheduler::default()
+        };
+    }
+
+    /// Start a task calling the value in stack slot `base` with the `argc`
+    /// arguments following it. The values are left on the stack.
+    pub(super) fn spawn(&mut self, base: usize, argc: u8) -> RunResult<()> {
+        let native = match &self.stack[base] {
+            Value::Native(native) => Some(*native),
+            Value::Host(host) => {
+                return Err(self.error(format!("cannot spawn the native function `{}`", host.name)))
+            }
+            Value::BoundMethod(obj) => match &self.heap.bound_method(*obj).method {
+                Method::Native(native) => Some(*native),
+                Method::Host(host) => {
+                    return Err(
+                        self.error(format!("cannot spawn the native function `{}`", host.name))
+                    )
+                }
+                Method::Function(_) => None,
+            },
+            _ => None,
+        };
+        if let Some(native) = native {
+            return Err(self.error(format!(
+                "cannot spawn the native function `{}`",
+                native.name()
+            )));
+        }
+
+        let (function, initializer) = match self.prepare_call(base, argc)? {
+            Prepared::Frame(function, initializer) => (function, initializer),
+            // Creating an instance without an initializer is already done
+            Prepared::Done => return Ok(()),
+            Prepared::Blocked => unreachable!("only native functions wait"),
+        };
+        let mut task = Task {
+            stack: self.stack[base..=base + argc as usize].to_vec(),
+            frames: Vec::new(),
+            register_frames: Vec::new(),
+            main: false,
+        };
+        match self.backend {
+            Backend::Stack => task.frames.push(CallFrame {
+                function,
+                ip: 0,
+                base: 0,
+                initializer,
+                generator: None,
+            }),
+            Backend::Register => {
+                let code = self.lowered(function)?;
+                task.stack.resize(code.registers, Value::Unit);
+                task.register_frames
+                    .push(RegisterFrame::new(code, 0, initializer));
+            }
+            Backend::Ast => unreachable!("the ast backend doesn't spawn tasks"),
+        }
+        self.scheduler.tasks.push_back(task);
+        Ok(())
+    }
+
+    /// Switch to the next task once the running one has used up its slice.
+    /// The task keeps running while a native function is calling back into
+    /// it, since the native function can't be suspended.
+    pub(super) fn preempt(&mut self) {
+        self.scheduler.idle = 0;
+        if self.callback.is_some() {
+            self.scheduler.slice = TIME_SLICE;
+            return;
+        }
+        self.switch_task();
+    }
+
+    /// Suspend the running task, moving it to the back of the queue, and
+    /// resume the task at the front.
+    pub(super) fn switch_task(&mut self) {
+        let Some(next) = self.scheduler.tasks.pop_front() else {
+            self.scheduler.slice = TIME_SLICE;
+            return;
+        };
+        let task = Task {
+            stack: mem::take(&mut self.stack),
+            frames: mem::take(&mut self.frames),
+            register_frames: mem::take(&mut self.register_frames),
+            main: self.scheduler.main,
+        };
+        self.scheduler.tasks.push_back(task);
+        self.resume(next);
+    }
+
+    fn resume(&mut self, task: Task) {
+        self.stack = task.stack;
+        self.frames = task.frames;
+        self.register_frames = task.register_frames;
+        self.scheduler.main = task.main;
+        self.scheduler.slice = TIME_SLICE;
+    }
+
+    /// End the running task, which returned `result`, and resume the next
+    /// one. Returns the main task's result if there are no tasks left.
+    pub(super) fn finish_task(&mut self, result: Value) -> Option<Value> {
+        if self.scheduler.main {
+            self.scheduler.result = Some(result);
+        }
+        self.scheduler.idle = 0;
+        match self.scheduler.tasks.pop_front() {
+            Some(next) => {
+                self.resume(next);
+                None
+            }
+            None => self.scheduler.result.take(),
+        }
+    }
+
+    /// Note that the running task has to wait for another task before it
+    /// can continue, before it is suspended with [`Vm::switch_task`].
+    ///
+    /// Once every task has had to wait in a row without doing anything else,
+    /// none of them can ever continue. The program then finishes with the
+    /// main task's result if it has one, and fails otherwise. Waiting while
+    /// a native function is calling back into the task is an error.
+    pub(super) fn block(&mut self) -> RunResult<Option<Value>> {
+        if self.callback.is_some() {
+            return Err(self.blocked_in_callback());
+        }
+        // The task only ran the instruction it is waiting in
+        if self.scheduler.slice == TIME_SLICE - 1 {
+            self.scheduler.idle += 1;
+        } else {
+            self.scheduler.idle = 1;
+        }
+
+        if self.scheduler.idle <= self.scheduler.tasks.len() {
+            return Ok(None);
+        }
+        match self.scheduler.result.take() {
+            Some(result) => Ok(Some(result)),
+            None => Err(self.deadlock()),
+        }
+    }
+
+    #[cold]
+    pub(super) fn deadlock(&self) -> RuntimeError {
+        self.error("deadlock: every task is waiting to receive from a channel")
+    }
+}
@@ -0,0 +1,53 @@
+//! How long each phase of running a program takes, measured while timing is
+//! turned on with [`Vm::set_timing`](super::Vm::set_timing). Unlike the
+//! profiler, this only reads the clock between phases, so it doesn't slow
+//! programs down.
+
+use std::{fmt, ops::AddAssign, time::Duration};
+
+/// The time spent in each phase, added up over every program run while
+/// timing was on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Timings {
+    pub lex: Duration,
+    /// Parsing lexes the source again as it goes, so this includes the time
+    /// lexing takes.
+    pub parse: Duration,
+    pub resolve: Duration,
+    pub compile: Duration,
+    pub execute: Duration,
+}
+
+impl Timings {
+    /// Return the time spent in every phase together.
+    pub fn total(&self) -> Duration {
+        self.lex + self.parse + self.resolve + self.compile + self.execute
+    }
+}
+
+impl AddAssign for Timings {
+    fn add_assign(&mut self, other: Timings) {
+        self.lex += other.lex;
+        self.parse += other.parse;
+        self.resolve += other.resolve;
+        self.compile += other.compile;
+        self.execute += other.execute;
+    }
+}
+
+impl fmt::Display for Timings {
+    /// Format the timings as a table with a row for each phase.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (phase, time) in [
+            ("lex", self.lex),
+            ("parse", self.parse),
+            ("resolve", self.resolve),
+            ("compile", self.compile),
+            ("execute", self.execute),
+            ("total", self.total()),
+        ] {
+            writeln!(f, "{:<16} {:>12.3?}", phase, time)?;
+        }
+        Ok(())
+    }
+}
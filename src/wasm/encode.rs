@@ -0,0 +1,221 @@
+//! A minimal writer for the WebAssembly binary format, covering only what the
+//! [`WasmCompiler`](super::WasmCompiler) emits: functions over `i64`s,
+//! mutable `i64` globals, exports, and a start function.
+
+/// Instruction opcodes.
+pub(super) mod op {
+    pub const UNREACHABLE: u8 = 0x00;
+    pub const BLOCK: u8 = 0x02;
+    pub const LOOP: u8 = 0x03;
+    pub const IF: u8 = 0x04;
+    pub const ELSE: u8 = 0x05;
+    pub const END: u8 = 0x0b;
+    pub const BR: u8 = 0x0c;
+    pub const BR_IF: u8 = 0x0d;
+    pub const RETURN: u8 = 0x0f;
+    pub const CALL: u8 = 0x10;
+    pub const DROP: u8 = 0x1a;
+    pub const LOCAL_GET: u8 = 0x20;
+    pub const LOCAL_SET: u8 = 0x21;
+    pub const LOCAL_TEE: u8 = 0x22;
+    pub const GLOBAL_GET: u8 = 0x23;
+    pub const GLOBAL_SET: u8 = 0x24;
+    pub const I32_CONST: u8 = 0x41;
+    pub const I64_CONST: u8 = 0x42;
+    pub const I32_EQZ: u8 = 0x45;
+    pub const I64_EQZ: u8 = 0x50;
+    pub const I64_EQ: u8 = 0x51;
+    pub const I64_NE: u8 = 0x52;
+    pub const I64_LT_S: u8 = 0x53;
+    pub const I64_GT_S: u8 = 0x55;
+    pub const I64_LE_S: u8 = 0x57;
+    pub const I64_GE_S: u8 = 0x59;
+    pub const I64_ADD: u8 = 0x7c;
+    pub const I64_SUB: u8 = 0x7d;
+    pub const I64_MUL: u8 = 0x7e;
+    pub const I64_DIV_S: u8 = 0x7f;
+    pub const I64_REM_S: u8 = 0x81;
+    pub const I64_AND: u8 = 0x83;
+    pub const I64_XOR: u8 = 0x85;
+    pub const I32_WRAP_I64: u8 = 0xa7;
+    pub const I64_EXTEND_I32_U: u8 = 0xad;
+}
+
+/// The `i32` value type.
+pub(super) const I32: u8 = 0x7f;
+/// The `i64` value type.
+pub(super) const I64: u8 = 0x7e;
+/// The block type of blocks that produce no value.
+pub(super) const EMPTY: u8 = 0x40;
+
+const MAGIC: &[u8; 4] = b"\0asm";
+const VERSION: u32 = 1;
+
+// Section ids
+const TYPE: u8 = 1;
+const FUNCTION: u8 = 3;
+const GLOBAL: u8 = 6;
+const EXPORT: u8 = 7;
+const START: u8 = 8;
+const CODE: u8 = 10;
+
+// Export kinds
+const EXPORT_FUNCTION: u8 = 0;
+const EXPORT_GLOBAL: u8 = 3;
+
+/// Append `value` in the unsigned LEB128 encoding.
+pub(super) fn unsigned(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            return out.push(byte);
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// Append `value` in the signed LEB128 encoding.
+pub(super) fn signed(out: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        if done {
+            return out.push(byte);
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn name(out: &mut Vec<u8>, name: &str) {
+    unsigned(out, name.len() as u64);
+    out.extend_from_slice(name.as_bytes());
+}
+
+/// A function whose parameters are all `i64`s, returning an `i64` unless it
+/// is the start function.
+pub(super) struct Func {
+    pub params: u32,
+    pub result: bool,
+    /// The number of `i64` locals after the parameters.
+    pub locals: u32,
+    /// The function's body, without its final `end`.
+    pub code: Vec<u8>,
+}
+
+/// A module being built. Functions and globals are numbered in the order
+/// they are added.
+#[derive(Default)]
+pub(super) struct Module {
+    pub functions: Vec<Func>,
+    /// The number of mutable `i64` globals, all starting at zero.
+    pub globals: u32,
+    pub exports: Vec<(String, Export)>,
+    pub start: Option<u32>,
+}
+
+/// Something exported from a module, by index.
+pub(super) enum Export {
+    Function(u32),
+    Global(u32),
+}
+
+impl Module {
+    /// Encode the module in the binary format.
+    pub fn finish(self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.extend_from_slice(&VERSION.to_le_bytes());
+
+        // Each distinct signature gets one type
+        let mut types: Vec<(u32, bool)> = Vec::new();
+        let mut indices = Vec::new();
+        for func in &self.functions {
+            let signature = (func.params, func.result);
+            let index = types
+                .iter()
+                .position(|&other| other == signature)
+                .unwrap_or_else(|| {
+                    types.push(signature);
+                    types.len() - 1
+                });
+            indices.push(index as u64);
+        }
+
+        section(&mut out, TYPE, |out| {
+            unsigned(out, types.len() as u64);
+            for &(params, result) in &types {
+                out.push(0x60);
+                unsigned(out, params as u64);
+                out.extend(std::iter::repeat_n(I64, params as usize));
+                unsigned(out, result as u64);
+                if result {
+                    out.push(I64);
+                }
+            }
+        });
+
+        section(&mut out, FUNCTION, |out| {
+            unsigned(out, indices.len() as u64);
+            for &index in &indices {
+                unsigned(out, index);
+            }
+        });
+
+        if self.globals > 0 {
+            section(&mut out, GLOBAL, |out| {
+                unsigned(out, self.globals as u64);
+                for _ in 0..self.globals {
+                    out.extend_from_slice(&[I64, 1, op::I64_CONST, 0, op::END]);
+                }
+            });
+        }
+
+        section(&mut out, EXPORT, |out| {
+            unsigned(out, self.exports.len() as u64);
+            for (export, kind) in &self.exports {
+                name(out, export);
+                let (kind, index) = match kind {
+                    Export::Function(index) => (EXPORT_FUNCTION, index),
+                    Export::Global(index) => (EXPORT_GLOBAL, index),
+                };
+                out.push(kind);
+                unsigned(out, *index as u64);
+            }
+        });
+
+        if let Some(start) = self.start {
+            section(&mut out, START, |out| unsigned(out, start as u64));
+        }
+
+        section(&mut out, CODE, |out| {
+            unsigned(out, self.functions.len() as u64);
+            for func in &self.functions {
+                let mut body = Vec::new();
+                if func.locals > 0 {
+                    unsigned(&mut body, 1);
+                    unsigned(&mut body, func.locals as u64);
+                    body.push(I64);
+                } else {
+                    unsigned(&mut body, 0);
+                }
+                body.extend_from_slice(&func.code);
+                body.push(op::END);
+
+                unsigned(out, body.len() as u64);
+                out.extend_from_slice(&body);
+            }
+        });
+
+        out
+    }
+}
+
+/// Append a section, prefixed with its id and size.
+fn section(out: &mut Vec<u8>, id: u8, contents: impl FnOnce(&mut Vec<u8>)) {
+    let mut bytes = Vec::new();
+    contents(&mut bytes);
+    out.push(id);
+    unsigned(out, bytes.len() as u64);
+    out.extend_from_slice(&bytes);
+}
@@ -0,0 +1,893 @@
+//! Besides bytecode for the VM, programs can be compiled into standalone
+//! WebAssembly modules by the [`WasmCompiler`], so they can run in any wasm
+//! runtime without the interpreter.
+//!
+//! Only a numeric subset of Meow is supported so far: ints, bools, and unit,
+//! variables, arithmetic and comparisons, casts to int, blocks, `if`,
+//! `while`, `for` over ranges, and calls between top-level functions.
+//! Everything else is reported as a diagnostic.
+//!
+//! Every value is represented as an `i64`, with bools as 0 or 1 and unit as
+//! 0, so the types the VM checks at runtime are checked at compile time
+//! instead. The type of a function's parameters comes from the arguments it
+//! is called with, defaulting to int for functions that are never called,
+//! and its result type comes from its body. Since calls and bodies depend on
+//! each other, the program is compiled repeatedly until these types stop
+//! changing.
+//!
+//! Top-level functions are exported under their own names, and so are
+//! top-level variables, as mutable globals. The rest of the top level
+//! becomes the module's start function, which runs when it is instantiated.
+//! Where the VM fails, the module traps: on division by zero, and on int
+//! arithmetic that overflows, which is checked after every operation.
+
+mod encode;
+
+use crate::{
+    diagnostics::Diagnostic,
+    parser::ast::{BinOp, Block, Expr, FunDecl, Literal, Param, Stmt, UnaryOp},
+    resolver::{ScopeId, SymbolId, SymbolKind, SymbolTable},
+    span::Span,
+    value::CastType,
+};
+use encode::{op, Export, Func, Module, EMPTY, I32, I64};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+/// The most times a program is compiled while inferring types.
+const MAX_PASSES: usize = 8;
+
+/// The static type of an expression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Type {
+    Int,
+    Bool,
+    Unit,
+    /// A value whose type depends on which way the program went, such as an
+    /// `if` without an `else`. Such values can only be discarded or passed
+    /// along.
+    Mixed,
+    /// The type of an expression that already had an error reported, which
+    /// is accepted everywhere to avoid reporting it again.
+    Error,
+}
+
+impl Type {
+    /// Return the type of a value that is either `self` or `other`.
+    fn join(self, other: Type) -> Type {
+        match (self, other) {
+            (Type::Error, _) | (_, Type::Error) => Type::Error,
+            _ if self == other => self,
+            _ => Type::Mixed,
+        }
+    }
+
+    /// Returns true if a value of this type can be used where `expected` is.
+    fn is(self, expected: Type) -> bool {
+        self == expected || self == Type::Error
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Bool => write!(f, "bool"),
+            Type::Unit => write!(f, "unit"),
+            Type::Mixed => write!(f, "mixed"),
+            Type::Error => write!(f, "unknown"),
+        }
+    }
+}
+
+/// A top-level function, and the types it is compiled with.
+struct Signature {
+    index: u32,
+    params: Vec<Type>,
+    result: Type,
+}
+
+/// The types seen while compiling, used as the signatures of the next pass.
+#[derive(Default)]
+struct Observed {
+    params: HashMap<SymbolId, Vec<Option<Type>>>,
+    results: HashMap<SymbolId, Type>,
+    globals: HashMap<SymbolId, Type>,
+}
+
+/// The state of the function being compiled.
+struct FunctionState {
+    code: Vec<u8>,
+    /// The wasm local holding each variable.
+    locals: HashMap<SymbolId, (u32, Type)>,
+    params: u32,
+    /// The number of locals declared after the parameters.
+    extra_locals: u32,
+    /// The function's symbol, or `None` for the start function.
+    symbol: Option<SymbolId>,
+    /// The locals checked arithmetic keeps its operands and result in.
+    scratch: Option<[u32; 3]>,
+}
+
+impl FunctionState {
+    fn new(symbol: Option<SymbolId>, params: u32) -> Self {
+        Self {
+            code: Vec::new(),
+            locals: HashMap::new(),
+            params,
+            extra_locals: 0,
+            symbol,
+            scratch: None,
+        }
+    }
+
+    /// Declare a new local, returning its index.
+    fn add_local(&mut self) -> u32 {
+        self.extra_locals += 1;
+        self.params + self.extra_locals - 1
+    }
+
+    /// Return the locals for checked arithmetic, declaring them the first
+    /// time. Checks don't nest, so every check can share them.
+    fn scratch(&mut self) -> [u32; 3] {
+        match self.scratch {
+            Some(scratch) => scratch,
+            None => {
+                let scratch = [self.add_local(), self.add_local(), self.add_local()];
+                self.scratch = Some(scratch);
+                scratch
+            }
+        }
+    }
+}
+
+/// The `WasmCompiler` struct compiles a resolved program into a WebAssembly
+/// module. It should typically be used through the top-level
+/// `compile_wasm()` function.
+pub struct WasmCompiler<'t> {
+    table: &'t SymbolTable,
+    functions: HashMap<SymbolId, Signature>,
+    /// The index and type of each top-level variable.
+    globals: HashMap<SymbolId, (u32, Type)>,
+    observed: Observed,
+    current: FunctionState,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'t> WasmCompiler<'t> {
+    pub fn new(table: &'t SymbolTable) -> Self {
+        Self {
+            table,
+            functions: HashMap::new(),
+            globals: HashMap::new(),
+            observed: Observed::default(),
+            current: FunctionState::new(None, 0),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Compile a whole program into the bytes of a wasm module.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use meow::{parse, resolve, wasm::WasmCompiler};
+    ///
+    /// let program = parse("fun square(x) { x * x } let nine = square(3);").unwrap();
+    /// let (table, _) = resolve(&program);
+    /// let module = WasmCompiler::new(&table).compile(&program).unwrap();
+    /// assert_eq!(&module[..4], b"\0asm");
+    /// ```
+    pub fn compile(mut self, program: &[Stmt]) -> Result<Vec<u8>, Vec<Diagnostic>> {
+        let mut exports = Vec::new();
+        for stmt in program {
+            match stmt {
+                Stmt::Fun(fun) => {
+                    let Some(symbol) = self.table.resolution(fun.span) else {
+                        continue;
+                    };
+                    let index = self.functions.len() as u32;
+                    self.functions.insert(
+                        symbol,
+                        Signature {
+                            index,
+                            params: vec![Type::Int; fun.params.len()],
+                            result: Type::Int,
+                        },
+                    );
+                    exports.push((fun.name.clone(), Export::Function(index)));
+                }
+                Stmt::Let { name, span, .. } => {
+                    let Some(symbol) = self.table.resolution(*span) else {
+                        continue;
+                    };
+                    let index = self.globals.len() as u32;
+                    self.globals.insert(symbol, (index, Type::Unit));
+                    exports.push((name.clone(), Export::Global(index)));
+                }
+                _ => {}
+            }
+        }
+        // Later declarations shadow earlier ones with the same name
+        let mut names = HashSet::new();
+        exports.reverse();
+        exports.retain(|(name, _)| names.insert(name.clone()));
+        exports.reverse();
+
+        for _ in 0..MAX_PASSES {
+            // Errors only count once the types have settled
+            self.diagnostics.clear();
+            let functions = self.pass(program);
+            if !self.update_types() {
+                if !self.diagnostics.is_empty() {
+                    return Err(self.diagnostics);
+                }
+                let start = functions.len() as u32 - 1;
+                let module = Module {
+                    functions,
+                    globals: self.globals.len() as u32,
+                    exports,
+                    start: Some(start),
+                };
+                return Ok(module.finish());
+            }
+        }
+
+        let span = program.first().map(Stmt::span).unwrap_or_default();
+        Err(vec![Diagnostic::error(
+            "the types in this program couldn't be worked out",
+            span,
+        )])
+    }
+
+    /// Compile every function and the top level, using the types found by
+    /// the previous pass. The start function comes last.
+    fn pass(&mut self, program: &[Stmt]) -> Vec<Func> {
+        self.observed = Observed::default();
+
+        let mut functions = Vec::new();
+        for stmt in program {
+            if let Stmt::Fun(fun) = stmt {
+                functions.push(self.function(fun));
+            }
+        }
+
+        self.current = FunctionState::new(None, 0);
+        for stmt in program {
+            self.stmt(stmt);
+        }
+        let state = std::mem::replace(&mut self.current, FunctionState::new(None, 0));
+        functions.push(Func {
+            params: 0,
+            result: false,
+            locals: state.extra_locals,
+            code: state.code,
+        });
+        functions
+    }
+
+    /// Replace the types of functions and globals with those observed in the
+    /// last pass, returning whether any of them changed.
+    fn update_types(&mut self) -> bool {
+        let mut changed = false;
+        for (symbol, signature) in &mut self.functions {
+            if let Some(params) = self.observed.params.get(symbol) {
+                for (param, observed) in signature.params.iter_mut().zip(params) {
+                    let observed = observed.unwrap_or(Type::Int);
+                    changed |= *param != observed;
+                    *param = observed;
+                }
+            }
+            let result = self.observed.results.get(symbol).copied();
+            let result = result.unwrap_or(Type::Unit);
+            changed |= signature.result != result;
+            signature.result = result;
+        }
+        for (symbol, (_, ty)) in &mut self.globals {
+            let observed = self.observed.globals.get(symbol).copied();
+            let observed = observed.unwrap_or(Type::Unit);
+            changed |= *ty != observed;
+            *ty = observed;
+        }
+        changed
+    }
+
+    fn error(&mut self, message: impl Into<String>, span: Span) {
+        self.diagnostics.push(Diagnostic::error(message, span));
+    }
+
+    /// Report a construct that can't be compiled to wasm yet.
+    fn unsupported(&mut self, what: &str, span: Span) {
+        self.error(
+            format!("{} can't be compiled to WebAssembly yet", what),
+            span,
+        );
+    }
+
+    /// Check that an expression of type `found` has type `expected`.
+    fn expect(&mut self, expected: Type, found: Type, span: Span) {
+        if !found.is(expected) {
+            self.error(format!("expected {}, found {}", expected, found), span);
+        }
+    }
+
+    fn emit(&mut self, bytes: &[u8]) {
+        self.current.code.extend_from_slice(bytes);
+    }
+
+    /// Emit an instruction followed by an unsigned operand.
+    fn emit_with(&mut self, op: u8, operand: u32) {
+        self.current.code.push(op);
+        encode::unsigned(&mut self.current.code, operand as u64);
+    }
+
+    fn emit_int(&mut self, value: i64) {
+        self.current.code.push(op::I64_CONST);
+        encode::signed(&mut self.current.code, value);
+    }
+
+    /// Compile a top-level function.
+    fn function(&mut self, fun: &FunDecl) -> Func {
+        let symbol = self.table.resolution(fun.span);
+        let params = symbol
+            .and_then(|symbol| self.functions.get(&symbol))
+            .map(|signature| signature.params.clone())
+            .unwrap_or_default();
+
+        self.current = FunctionState::new(symbol, params.len() as u32);
+        for (index, (param, ty)) in fun.params.iter().zip(params).enumerate() {
+            if let Some(symbol) = self.table.resolution(param.span) {
+                self.current.locals.insert(symbol, (index as u32, ty));
+            }
+        }
+
+        let ty = self.block(&fun.body);
+        self.observe_result(ty);
+
+        let state = std::mem::replace(&mut self.current, FunctionState::new(None, 0));
+        Func {
+            params: state.params,
+            result: true,
+            locals: state.extra_locals,
+            code: state.code,
+        }
+    }
+
+    /// Record that the current function can return a value of type `ty`.
+    fn observe_result(&mut self, ty: Type) {
+        if let Some(symbol) = self.current.symbol {
+            let result = self.observed.results.entry(symbol).or_insert(ty);
+            *result = result.join(ty);
+        }
+    }
+
+    /// Return whether `symbol` is declared at the top level.
+    fn is_global(&self, symbol: SymbolId) -> bool {
+        self.table.symbol(symbol).scope == ScopeId(0)
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Let {
+                name, value, span, ..
+            } => {
+                let ty = match value {
+                    Some(value) => self.expr(value),
+                    None => {
+                        self.emit_int(0);
+                        Type::Unit
+                    }
+                };
+                let Some(symbol) = self.table.resolution(*span) else {
+                    return;
+                };
+                if let Some(&(index, _)) = self.globals.get(&symbol) {
+                    self.emit_with(op::GLOBAL_SET, index);
+                    match self.observed.globals.get(&symbol) {
+                        Some(&other) if other != ty => self.error(
+                            format!("`{}` is defined as both {} and {}", name, other, ty),
+                            *span,
+                        ),
+                        _ => {
+                            self.observed.globals.insert(symbol, ty);
+                        }
+                    }
+                } else {
+                    let local = self.current.add_local();
+                    self.emit_with(op::LOCAL_SET, local);
+                    self.current.locals.insert(symbol, (local, ty));
+                }
+            }
+            Stmt::Expr { expr, .. } => {
+                self.expr(expr);
+                self.emit(&[op::DROP]);
+            }
+            Stmt::Fun(fun) => {
+                // Top-level functions are compiled separately
+                let symbol = self.table.resolution(fun.span);
+                if !symbol.is_some_and(|symbol| self.is_global(symbol)) {
+                    self.unsupported("nested functions", fun.span);
+                }
+            }
+            Stmt::Return { value, .. } => {
+                let ty = match value {
+                    Some(value) => self.expr(value),
+                    None => {
+                        self.emit_int(0);
+                        Type::Unit
+                    }
+                };
+                if self.current.symbol.is_some() {
+                    self.observe_result(ty);
+                } else {
+                    // The start function doesn't return anything
+                    self.emit(&[op::DROP]);
+                }
+                self.emit(&[op::RETURN]);
+            }
+            Stmt::While { cond, body, .. } => {
+                self.emit(&[op::BLOCK, EMPTY, op::LOOP, EMPTY]);
+                let ty = self.expr(cond);
+                self.expect(Type::Bool, ty, cond.span());
+                self.emit(&[op::I32_WRAP_I64, op::I32_EQZ]);
+                self.emit_with(op::BR_IF, 1);
+                self.block(body);
+                self.emit(&[op::DROP]);
+                self.emit_with(op::BR, 0);
+                self.emit(&[op::END, op::END]);
+            }
+            Stmt::For {
+                var,
+                iterable,
+                body,
+                span,
+            } => self.for_loop(var, iterable, body, *span),
+            Stmt::Class { span, .. } => self.unsupported("classes", *span),
+            Stmt::Yield { span, .. } => self.unsupported("generators", *span),
+            Stmt::Spawn { span, .. } => self.unsupported("tasks", *span),
+            Stmt::Import { span, .. } => self.unsupported("imports", *span),
+        }
+    }
+
+    /// Compile a `for` loop over a range into a counting loop over two
+    /// hidden locals holding the next value and the bound.
+    fn for_loop(&mut self, var: &Param, iterable: &Expr, body: &Block, span: Span) {
+        let (start, end, inclusive) = match iterable {
+            Expr::Binary {
+                op: op @ (BinOp::Range | BinOp::RangeInclusive),
+                left,
+                right,
+                ..
+            } => (left, right, *op == BinOp::RangeInclusive),
+            _ => return self.unsupported("`for` loops over values other than ranges", span),
+        };
+
+        let counter = self.current.add_local();
+        let bound = self.current.add_local();
+        for (expr, local) in [(start, counter), (end, bound)] {
+            let ty = self.expr(expr);
+            self.expect(Type::Int, ty, expr.span());
+            self.emit_with(op::LOCAL_SET, local);
+        }
+
+        self.emit(&[op::BLOCK, EMPTY, op::LOOP, EMPTY]);
+        self.emit_with(op::LOCAL_GET, counter);
+        self.emit_with(op::LOCAL_GET, bound);
+        self.emit(&[if inclusive {
+            op::I64_GT_S
+        } else {
+            op::I64_GE_S
+        }]);
+        self.emit_with(op::BR_IF, 1);
+
+        // The loop variable is a copy, so assigning to it doesn't affect the
+        // iteration
+        let local = self.current.add_local();
+        self.emit_with(op::LOCAL_GET, counter);
+        self.emit_with(op::LOCAL_SET, local);
+        if let Some(symbol) = self.table.resolution(var.span) {
+            self.current.locals.insert(symbol, (local, Type::Int));
+        }
+        self.block(body);
+        self.emit(&[op::DROP]);
+
+        // The counter is below the bound, so it can only overflow if an
+        // inclusive loop has reached it
+        if inclusive {
+            self.emit_with(op::LOCAL_GET, counter);
+            self.emit_with(op::LOCAL_GET, bound);
+            self.emit(&[op::I64_EQ]);
+            self.emit_with(op::BR_IF, 1);
+        }
+        self.emit_with(op::LOCAL_GET, counter);
+        self.emit_int(1);
+        self.emit(&[op::I64_ADD]);
+        self.emit_with(op::LOCAL_SET, counter);
+        self.emit_with(op::BR, 0);
+        self.emit(&[op::END, op::END]);
+    }
+
+    /// Compile a block, leaving its value on the stack.
+    fn block(&mut self, block: &Block) -> Type {
+        for stmt in &block.stmts {
+            self.stmt(stmt);
+        }
+        match &block.tail {
+            Some(tail) => self.expr(tail),
+            None => {
+                self.emit_int(0);
+                Type::Unit
+            }
+        }
+    }
+
+    /// Compile an expression, leaving its value on the stack and returning
+    /// its type.
+    fn expr(&mut self, expr: &Expr) -> Type {
+        match expr {
+            Expr::Literal { value, span } => match value {
+                Literal::Int(value) => {
+                    self.emit_int(*value);
+                    Type::Int
+                }
+                Literal::Bool(value) => {
+                    self.emit_int(*value as i64);
+                    Type::Bool
+                }
+                Literal::Float(_) => self.unsupported_value("floats", *span),
+                Literal::Str(_) => self.unsupported_value("strings", *span),
+                Literal::Char(_) => self.unsupported_value("chars", *span),
+            },
+            Expr::Ident { name, span } => match self.variable(name, *span) {
+                Some((Variable::Local(index), ty)) => {
+                    self.emit_with(op::LOCAL_GET, index);
+                    ty
+                }
+                Some((Variable::Global(index), ty)) => {
+                    self.emit_with(op::GLOBAL_GET, index);
+                    ty
+                }
+                None => {
+                    self.emit_int(0);
+                    Type::Error
+                }
+            },
+            // Bools are already 0 or 1, so only the type changes
+            Expr::Cast { expr, ty, span } => match ty {
+                CastType::Int => {
+                    let ty = self.expr(expr);
+                    if !ty.is(Type::Int) && !ty.is(Type::Bool) {
+                        self.error(format!("cannot cast {} to int", ty), *span);
+                    }
+                    Type::Int
+                }
+                CastType::Float => self.unsupported_value("floats", *span),
+            },
+            Expr::Unary { op, expr, span } => match op {
+                UnaryOp::Minus => {
+                    self.emit_int(0);
+                    let ty = self.expr(expr);
+                    self.expect(Type::Int, ty, *span);
+                    self.checked(op::I64_SUB);
+                    Type::Int
+                }
+                UnaryOp::Bang => {
+                    let ty = self.expr(expr);
+                    self.expect(Type::Bool, ty, *span);
+                    self.emit(&[op::I64_EQZ, op::I64_EXTEND_I32_U]);
+                    Type::Bool
+                }
+            },
+            Expr::Binary {
+                op,
+                left,
+                right,
+                span,
+            } => self.binary(*op, left, right, *span),
+            Expr::Assign {
+                target,
+                op,
+                value,
+                span,
+            } => {
+                let Expr::Ident { name, span: target } = &**target else {
+                    return self.unsupported_value("assignments to fields and indices", *span);
+                };
+                let Some((variable, ty)) = self.variable(name, *target) else {
+                    self.expr(value);
+                    return Type::Error;
+                };
+                let found = match op {
+                    Some(op) => {
+                        self.emit_variable(op::LOCAL_GET, op::GLOBAL_GET, variable);
+                        let right = self.expr(value);
+                        self.binary_op(*op, ty, right, *span)
+                    }
+                    None => self.expr(value),
+                };
+                if !found.is(ty) {
+                    self.error(
+                        format!("can't assign {} to `{}`, which holds {}", found, name, ty),
+                        *span,
+                    );
+                }
+                match variable {
+                    Variable::Local(index) => self.emit_with(op::LOCAL_TEE, index),
+                    Variable::Global(index) => {
+                        self.emit_with(op::GLOBAL_SET, index);
+                        self.emit_with(op::GLOBAL_GET, index);
+                    }
+                }
+                ty
+            }
+            Expr::Call { callee, args, span } => self.call(callee, args, *span),
+            Expr::Block(block) => self.block(block),
+            Expr::If {
+                cond,
+                then,
+                otherwise,
+                ..
+            } => {
+                let ty = self.expr(cond);
+                self.expect(Type::Bool, ty, cond.span());
+                self.emit(&[op::I32_WRAP_I64, op::IF, I64]);
+                let then = self.block(then);
+                self.emit(&[op::ELSE]);
+                let otherwise = match otherwise {
+                    Some(otherwise) => self.expr(otherwise),
+                    None => {
+                        self.emit_int(0);
+                        Type::Unit
+                    }
+                };
+                self.emit(&[op::END]);
+                then.join(otherwise)
+            }
+            Expr::Field { span, .. } => self.unsupported_value("fields", *span),
+            Expr::Index { span, .. } => self.unsupported_value("indexing", *span),
+            Expr::List { span, .. } => self.unsupported_value("lists", *span),
+            Expr::Interpolation { span, .. } => self.unsupported_value("strings", *span),
+            Expr::Match { span, .. } => self.unsupported_value("match expressions", *span),
+        }
+    }
+
+    /// Report an unsupported expression, leaving a placeholder value on the
+    /// stack.
+    fn unsupported_value(&mut self, what: &str, span: Span) -> Type {
+        self.unsupported(what, span);
+        self.emit_int(0);
+        Type::Error
+    }
+
+    /// Find the variable `name` referenced at `span`, reporting an error if
+    /// it isn't a variable that can be compiled.
+    fn variable(&mut self, name: &str, span: Span) -> Option<(Variable, Type)> {
+        let Some(symbol) = self.table.resolution(span) else {
+            self.error(format!("`{}` isn't defined", name), span);
+            return None;
+        };
+        if let Some(&(index, ty)) = self.current.locals.get(&symbol) {
+            return Some((Variable::Local(index), ty));
+        }
+        if let Some(&(index, ty)) = self.globals.get(&symbol) {
+            return Some((Variable::Global(index), ty));
+        }
+        match self.table.symbol(symbol).kind {
+            SymbolKind::Function => self.unsupported("functions used as values", span),
+            _ if !self.is_global(symbol) => {
+                self.unsupported("closures capturing local variables", span)
+            }
+            _ => self.unsupported("values other than ints and bools", span),
+        }
+        None
+    }
+
+    fn emit_variable(&mut self, local: u8, global: u8, variable: Variable) {
+        match variable {
+            Variable::Local(index) => self.emit_with(local, index),
+            Variable::Global(index) => self.emit_with(global, index),
+        }
+    }
+
+    fn call(&mut self, callee: &Expr, args: &[Expr], span: Span) -> Type {
+        let function = match callee {
+            Expr::Ident { span, .. } => self.table.resolution(*span),
+            _ => None,
+        };
+        let Some((symbol, signature)) =
+            function.and_then(|symbol| Some((symbol, self.functions.get(&symbol)?)))
+        else {
+            return self.unsupported_value("calls to anything but top-level functions", span);
+        };
+        let (index, arity, result) = (signature.index, signature.params.len(), signature.result);
+
+        let name = &self.table.symbol(symbol).name;
+        if args.len() != arity {
+            let message = format!(
+                "`{}` expects {} argument{}, but {} were given",
+                name,
+                arity,
+                if arity == 1 { "" } else { "s" },
+                args.len()
+            );
+            self.error(message, span);
+            self.emit_int(0);
+            return Type::Error;
+        }
+
+        let observed = self
+            .observed
+            .params
+            .entry(symbol)
+            .or_insert_with(|| vec![None; arity])
+            .clone();
+        for (i, arg) in args.iter().enumerate() {
+            let ty = self.expr(arg);
+            match observed[i] {
+                Some(other) if !ty.is(other) && other != Type::Error => self.error(
+                    format!(
+                        "`{}` is called with both {} and {} as argument {}",
+                        name,
+                        other,
+                        ty,
+                        i + 1
+                    ),
+                    arg.span(),
+                ),
+                _ => {
+                    self.observed
+                        .params
+                        .get_mut(&symbol)
+                        .expect("params observed")[i] = Some(ty)
+                }
+            }
+        }
+        self.emit_with(op::CALL, index);
+        result
+    }
+
+    fn binary(&mut self, op: BinOp, left: &Expr, right: &Expr, span: Span) -> Type {
+        match op {
+            BinOp::And | BinOp::Or => {
+                let ty = self.expr(left);
+                self.expect(Type::Bool, ty, left.span());
+                self.emit(&[op::I32_WRAP_I64, op::IF, I64]);
+                if op == BinOp::Or {
+                    self.emit_int(1);
+                    self.emit(&[op::ELSE]);
+                }
+                let ty = self.expr(right);
+                self.expect(Type::Bool, ty, right.span());
+                if op == BinOp::And {
+                    self.emit(&[op::ELSE]);
+                    self.emit_int(0);
+                }
+                self.emit(&[op::END]);
+                Type::Bool
+            }
+            BinOp::Range | BinOp::RangeInclusive => self.unsupported_value("ranges", span),
+            _ => {
+                let left = self.expr(left);
+                let right = self.expr(right);
+                self.binary_op(op, left, right, span)
+            }
+        }
+    }
+
+    /// Emit the instruction for an arithmetic or comparison operator applied
+    /// to operands of types `left` and `right`, returning the result type.
+    fn binary_op(&mut self, op: BinOp, left: Type, right: Type, span: Span) -> Type {
+        let instruction = match op {
+            BinOp::Plus => op::I64_ADD,
+            BinOp::Minus => op::I64_SUB,
+            BinOp::Star => op::I64_MUL,
+            BinOp::Slash => op::I64_DIV_S,
+            BinOp::Percent => op::I64_REM_S,
+            BinOp::EqualEqual => op::I64_EQ,
+            BinOp::BangEqual => op::I64_NE,
+            BinOp::Greater => op::I64_GT_S,
+            BinOp::GreaterEqual => op::I64_GE_S,
+            BinOp::Less => op::I64_LT_S,
+            BinOp::LessEqual => op::I64_LE_S,
+            BinOp::And | BinOp::Or | BinOp::Range | BinOp::RangeInclusive => {
+                unreachable!("{:?} has no single instruction", op)
+            }
+        };
+
+        if matches!(op, BinOp::EqualEqual | BinOp::BangEqual) {
+            if left == Type::Mixed || right == Type::Mixed {
+                self.error(format!("can't compare {} and {}", left, right), span);
+            } else if !left.is(right) && !right.is(left) {
+                // Values of different types are never equal
+                self.emit(&[op::DROP, op::DROP]);
+                self.emit_int((op == BinOp::BangEqual) as i64);
+                return Type::Bool;
+            }
+            self.emit(&[instruction, op::I64_EXTEND_I32_U]);
+            return Type::Bool;
+        }
+
+        if !left.is(Type::Int) || !right.is(Type::Int) {
+            self.error(
+                format!(
+                    "unsupported operand types for `{}`: {} and {}",
+                    op.symbol(),
+                    left,
+                    right
+                ),
+                span,
+            );
+        }
+        if matches!(op, BinOp::Plus | BinOp::Minus | BinOp::Star) {
+            self.checked(instruction);
+            Type::Int
+        } else if matches!(op, BinOp::Slash | BinOp::Percent) {
+            self.emit(&[instruction]);
+            Type::Int
+        } else {
+            self.emit(&[instruction, op::I64_EXTEND_I32_U]);
+            Type::Bool
+        }
+    }
+
+    /// Emit `instruction`, which adds, subtracts or multiplies the two ints
+    /// on the stack, followed by a check that traps if it overflowed.
+    fn checked(&mut self, instruction: u8) {
+        let [left, right, result] = self.current.scratch();
+        self.emit_with(op::LOCAL_SET, right);
+        self.emit_with(op::LOCAL_TEE, left);
+        self.emit_with(op::LOCAL_GET, right);
+        self.emit(&[instruction]);
+        self.emit_with(op::LOCAL_SET, result);
+
+        match instruction {
+            // A sum overflowed if its sign differs from both operands'
+            // signs, and a difference if the operands' signs differ and its
+            // sign differs from the left one's
+            op::I64_ADD | op::I64_SUB => {
+                let pairs = if instruction == op::I64_ADD {
+                    [(left, result), (right, result)]
+                } else {
+                    [(left, right), (left, result)]
+                };
+                for (a, b) in pairs {
+                    self.emit_with(op::LOCAL_GET, a);
+                    self.emit_with(op::LOCAL_GET, b);
+                    self.emit(&[op::I64_XOR]);
+                }
+                self.emit(&[op::I64_AND]);
+                self.emit_int(0);
+                self.emit(&[op::I64_LT_S]);
+            }
+            // A product overflowed if dividing it by the left operand
+            // doesn't give the right one back. Dividing by -1 would trap
+            // itself, so that case is checked separately.
+            _ => {
+                self.emit_with(op::LOCAL_GET, left);
+                self.emit(&[op::I64_EQZ, op::IF, I32, op::I32_CONST, 0, op::ELSE]);
+                self.emit_with(op::LOCAL_GET, left);
+                self.emit_int(-1);
+                self.emit(&[op::I64_EQ, op::IF, I32]);
+                self.emit_with(op::LOCAL_GET, right);
+                self.emit_int(i64::MIN);
+                self.emit(&[op::I64_EQ, op::ELSE]);
+                self.emit_with(op::LOCAL_GET, result);
+                self.emit_with(op::LOCAL_GET, left);
+                self.emit(&[op::I64_DIV_S]);
+                self.emit_with(op::LOCAL_GET, right);
+                self.emit(&[op::I64_NE, op::END, op::END]);
+            }
+        }
+        self.emit(&[op::IF, EMPTY, op::UNREACHABLE, op::END]);
+        self.emit_with(op::LOCAL_GET, result);
+    }
+}
+
+/// Where a variable is stored.
+#[derive(Debug, Clone, Copy)]
+enum Variable {
+    Local(u32),
+    Global(u32),
+}
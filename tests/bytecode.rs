@@ -0,0 +1,364 @@
+#[cfg(feature = "fs")]
+use meow::{bytecode::serialize::save, errors::InterpreterError, run_from_file};
+use meow::{
+    bytecode::{
+        optimize::eliminate_dead_code,
+        serialize::{decode, encode, MAGIC},
+        verify::verify,
+        Chunk, Function, OpCode,
+    },
+    compile,
+    errors::LoadError,
+    span::Span,
+    value::Value,
+    vm::Vm,
+};
+#[cfg(feature = "fs")]
+use std::{env, fs, process};
+
+/// Returns true if the chunk of `function` has an `op` instruction.
+fn ops_contains(function: &Function, op: OpCode) -> bool {
+    let chunk = &function.chunk;
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        let next = OpCode::from_byte(chunk.code[offset]).unwrap();
+        if next == op {
+            return true;
+        }
+        offset += 1 + next.operand_len();
+    }
+    false
+}
+
+#[test]
+fn opcodes() {
+    // Every decodable byte round-trips to the same opcode
+    for byte in 0..=u8::MAX {
+        if let Some(op) = OpCode::from_byte(byte) {
+            assert_eq!(op as u8, byte);
+        }
+    }
+    assert_eq!(
+        OpCode::from_byte(OpCode::Return as u8),
+        Some(OpCode::Return)
+    );
+    assert_eq!(OpCode::from_byte(u8::MAX), None);
+}
+
+#[test]
+fn constants() {
+    let mut chunk = Chunk::new();
+    let span = Span::new(1, 1, 1);
+
+    assert_eq!(chunk.write_constant(Value::Int(1), span), Some(0));
+    assert_eq!(chunk.write_constant(Value::from("cat"), span), Some(1));
+    assert_eq!(chunk.write_constant(Value::Int(1), span), Some(0));
+    assert_eq!(chunk.write_constant(Value::Float(0.0), span), Some(2));
+    assert_eq!(chunk.write_constant(Value::Float(-0.0), span), Some(3));
+
+    assert_eq!(chunk.constants.len(), 4);
+    assert_eq!(chunk.code.len(), 15);
+    assert_eq!(chunk.read_u16(4), 1);
+}
+
+#[test]
+fn spans() {
+    let mut chunk = Chunk::new();
+    chunk.write_constant(Value::Int(1), Span::new(1, 5, 1));
+    chunk.write_op(OpCode::Negate, Span::new(1, 4, 1));
+    chunk.write_op(OpCode::Return, Span::new(2, 1, 6));
+
+    assert_eq!(chunk.span_at(2), Some(Span::new(1, 5, 1)));
+    assert_eq!(chunk.span_at(3), Some(Span::new(1, 4, 1)));
+    assert_eq!(chunk.span_at(4).unwrap().line, 2);
+
+    // Only changes of span are stored
+    assert_eq!(chunk.spans.runs().len(), 3);
+    assert_eq!(chunk.spans.runs()[1].offset, 3);
+}
+
+#[test]
+fn strip_debug_info() {
+    let mut script = compile(
+        "fun f() { 1 }
+f();",
+    )
+    .unwrap();
+    script.strip_debug_info();
+    assert_eq!(script.chunk.span_at(0), None);
+    match &script.chunk.constants[0] {
+        Value::Function(f) => assert!(f.chunk.spans.is_empty()),
+        value => panic!("expected function, found {:?}", value),
+    }
+
+    assert!(script.disassemble().contains("0000    ? Constant"));
+    assert_eq!(decode(&encode(&script)).unwrap(), script);
+}
+
+#[test]
+fn disassemble() {
+    let mut chunk = Chunk::new();
+    chunk.write_constant(Value::Float(1.0), Span::new(1, 1, 3));
+    chunk.write_op(OpCode::Negate, Span::new(1, 1, 1));
+    chunk.write_op(OpCode::Return, Span::new(2, 1, 6));
+
+    assert_eq!(
+        chunk.disassemble("test"),
+        "== test ==\n\
+         0000    1 Constant            0 '1.0'\n\
+         0003    | Negate\n\
+         0004    2 Return\n"
+    );
+}
+
+#[test]
+fn serialize() {
+    let script =
+        compile("import std.math;\nfun f(a) { a * 2.5 }\nlet c = 'c';\nf(\"meow\");").unwrap();
+    let bytes = encode(&script);
+    assert_eq!(&bytes[..4], MAGIC);
+    assert_eq!(decode(&bytes).unwrap(), script);
+
+    // Files are rejected rather than partially loaded
+    assert!(matches!(decode(b"MOO!\0\x01"), Err(LoadError::BadMagic)));
+    assert!(matches!(
+        decode(b"MEOW\0\x63"),
+        Err(LoadError::UnsupportedVersion(99))
+    ));
+    assert!(matches!(
+        decode(&bytes[..bytes.len() - 1]),
+        Err(LoadError::UnexpectedEof)
+    ));
+
+    let mut trailing = bytes.clone();
+    trailing.push(0);
+    assert!(matches!(decode(&trailing), Err(LoadError::TrailingBytes)));
+
+    // The program is run without arguments, so its arity must be zero. The
+    // arity and generator flag follow the magic, version and name
+    let arity = MAGIC.len() + 2 + 4 + script.name.len();
+    for (offset, message) in [
+        (arity, "malformed file: the program takes arguments"),
+        (arity + 1, "malformed file: the program is a generator"),
+    ] {
+        let mut corrupt = bytes.clone();
+        corrupt[offset] = 244;
+        assert_eq!(decode(&corrupt).unwrap_err().to_string(), message);
+    }
+
+    // Functions nested in each other's constants past what the parser allows
+    // are rejected before they can overflow the stack
+    let mut nested = MAGIC.to_vec();
+    nested.extend_from_slice(&bytes[4..6]);
+    for _ in 0..100_000 {
+        // An empty name, arity, generator flag, code and span table, then one
+        // constant, which is a function
+        nested.extend_from_slice(&[0; 14]);
+        nested.extend_from_slice(&[0, 0, 0, 1, 6]);
+    }
+    assert_eq!(
+        decode(&nested).unwrap_err().to_string(),
+        "malformed file: functions are nested too deeply"
+    );
+}
+
+#[cfg(feature = "fs")]
+#[test]
+fn compiled_files() {
+    // Compiled files are run by `run_from_file` without their source
+    let bytes = encode(&compile("let x = 6 * 7;").unwrap());
+    let path = env::temp_dir().join(format!("meow-serialize-{}.mwc", process::id()));
+    let path = path.to_str().unwrap();
+    save(&compile("let x = 6 * 7;").unwrap(), path).unwrap();
+    let mut vm = Vm::new();
+    run_from_file(&mut vm, path).unwrap();
+    assert_eq!(vm.global("x"), Some(&Value::Int(42)));
+
+    fs::write(path, &bytes[..bytes.len() - 1]).unwrap();
+    match run_from_file(&mut Vm::new(), path).unwrap_err() {
+        InterpreterError::Load { error, .. } => assert!(matches!(error, LoadError::UnexpectedEof)),
+        error => panic!("expected a load error, found {}", error),
+    }
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn dead_code() {
+    let mut chunk = Chunk::new();
+    let span = |line| Span::new(line, 1, 1);
+
+    // 0: Jump over the dead constant to the loop
+    chunk.write_op(OpCode::Jump, span(1));
+    chunk.write_u16(3, span(1));
+    chunk.write_constant(Value::Int(1), span(2));
+    // 6: Loop back to itself, with a dead return after it
+    chunk.write_constant(Value::Int(2), span(3));
+    chunk.write_op(OpCode::Pop, span(3));
+    chunk.write_op(OpCode::Loop, span(4));
+    chunk.write_u16(7, span(4));
+    chunk.write_op(OpCode::Return, span(5));
+
+    eliminate_dead_code(&mut chunk);
+    assert_eq!(
+        chunk.code,
+        [
+            OpCode::Jump as u8,
+            0,
+            0,
+            OpCode::Constant as u8,
+            0,
+            0,
+            OpCode::Pop as u8,
+            OpCode::Loop as u8,
+            0,
+            7
+        ]
+    );
+    assert_eq!(chunk.constants, vec![Value::Int(2)]);
+    assert_eq!(chunk.span_at(3), Some(span(3)));
+    assert_eq!(chunk.span_at(9), Some(span(4)));
+}
+
+#[test]
+fn constant_folding() {
+    let run = |source| {
+        let mut script = compile(source).unwrap();
+        script.optimize();
+        verify(&script).unwrap();
+        let mut vm = Vm::new();
+        let result = vm.run(script.clone()).map(|_| vm.global("x").cloned());
+        (script, result)
+    };
+
+    // The first constant is the name of `x`
+    let (script, result) = run("let x = -(2 * 3 + 1) * 2;");
+    assert_eq!(result.unwrap(), Some(Value::Int(-14)));
+    assert_eq!(script.chunk.constants[1..], [Value::Int(-14)]);
+    assert!(!ops_contains(&script, OpCode::Negate));
+    let (script, result) = run("let x = 1.5 / 0.5 - 1.0;");
+    assert_eq!(result.unwrap(), Some(Value::Float(2.0)));
+    assert_eq!(script.chunk.constants[1..], [Value::Float(2.0)]);
+
+    // Functions are optimized too
+    let (script, result) = run("fun f() { 1 - 2 } let x = f();");
+    assert_eq!(result.unwrap(), Some(Value::Int(-1)));
+    match &script.chunk.constants[0] {
+        Value::Function(f) => assert!(f.chunk.constants.contains(&Value::Int(-1))),
+        value => panic!("expected function, found {:?}", value),
+    }
+
+    // Values jumped to aren't folded with the ones before them
+    let (_, result) = run("let x = if true { 1 } else { 2 } + 3;");
+    assert_eq!(result.unwrap(), Some(Value::Int(4)));
+
+    // Arithmetic that fails is left to fail when it runs
+    let (_, result) = run("let x = 1 / 0;");
+    assert!(result.is_err());
+    let (script, result) = run("let x = 9223372036854775807 + 1;");
+    assert!(result.is_err());
+    assert!(ops_contains(&script, OpCode::Add));
+}
+
+#[test]
+fn verifier() {
+    // Everything the compiler produces passes
+    let source = "
+        class A { fun init(x) { self.x = [x]; } fun get() { self.x[0] } }
+        fun f(n) { let mut t = 0; for i in 0..n { if i > 2 && t < 10 { t += A(i).get(); } } t }
+        let mut n = 0; while n < 3 { n += f(n); }
+    ";
+    verify(&compile(source).unwrap()).unwrap();
+
+    let reason = |code: &[u8], constants: Vec<Value>| {
+        let mut function = Function::default();
+        function.chunk.code = code.to_vec();
+        function.chunk.constants = constants;
+        verify(&function).unwrap_err().reason
+    };
+    use OpCode::*;
+    assert_eq!(reason(&[255], vec![]), "invalid opcode");
+    assert_eq!(
+        reason(&[Constant as u8, 0], vec![]),
+        "instruction is missing its operands"
+    );
+    assert_eq!(
+        reason(&[Constant as u8, 0, 0, Return as u8], vec![]),
+        "constant index out of range"
+    );
+    assert_eq!(
+        reason(&[GetGlobal as u8, 0, 0, Return as u8], vec![Value::Int(1)]),
+        "name is not a string"
+    );
+    assert_eq!(
+        reason(&[Pop as u8, Unit as u8, Return as u8], vec![]),
+        "stack underflow"
+    );
+    assert_eq!(reason(&[Return as u8], vec![]), "stack underflow");
+    assert_eq!(
+        reason(&[GetLocal as u8, 1, Return as u8], vec![]),
+        "local slot out of range"
+    );
+    assert_eq!(
+        reason(&[Unit as u8], vec![]),
+        "execution runs past the end of the chunk"
+    );
+    assert_eq!(
+        reason(&[Jump as u8, 0, 1, GetLocal as u8, 0, Return as u8], vec![]),
+        "jump doesn't land on an instruction"
+    );
+    assert_eq!(
+        reason(&[Loop as u8, 0, 4], vec![]),
+        "jump doesn't land on an instruction"
+    );
+    assert_eq!(
+        reason(&[Unit as u8, Yield as u8, Unit as u8, Return as u8], vec![]),
+        "yield outside of a generator"
+    );
+    assert_eq!(
+        reason(&[Unit as u8, Cast as u8, 2, Return as u8], vec![]),
+        "unknown cast type"
+    );
+    // A switch is followed by a table of jumps, with one for values outside
+    // of it
+    let switch = [Unit as u8, Switch as u8, 0, 0, 0, 1];
+    let jump = [Jump as u8, 0, 0];
+    let table = [&switch[..], &jump, &jump, &[Unit as u8, Return as u8]].concat();
+    let mut function = Function::default();
+    function.chunk.code = table.clone();
+    function.chunk.constants = vec![Value::Int(0)];
+    verify(&function).unwrap();
+    assert_eq!(
+        reason(&table, vec![Value::from("a")]),
+        "switch minimum is not an int"
+    );
+    let short = [&switch[..], &jump, &[Unit as u8, Return as u8]].concat();
+    assert_eq!(
+        reason(&short, vec![Value::Int(0)]),
+        "switch table entry is not a jump"
+    );
+    assert_eq!(
+        reason(
+            &[&switch[..], &[Unit as u8, Return as u8]].concat(),
+            vec![Value::Int(0)]
+        ),
+        "switch table entry is not a jump"
+    );
+    assert_eq!(
+        reason(&[Unmatched as u8, Unit as u8, Return as u8], vec![]),
+        "stack underflow"
+    );
+    // The loop pushes a value every time around
+    assert_eq!(
+        reason(&[Unit as u8, Loop as u8, 0, 4], vec![]),
+        "inconsistent stack depth"
+    );
+
+    // Corrupt files are rejected when they are loaded
+    let mut script = compile("let x = 1;").unwrap();
+    script.chunk.code[1] = 9;
+    let error = decode(&encode(&script)).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "invalid bytecode in `<script>` at offset 0: constant index out of range"
+    );
+}
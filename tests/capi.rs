@@ -0,0 +1,73 @@
+#![cfg(feature = "cdylib")]
+
+use meow::capi::{
+    meow_eval, meow_free, meow_get_string, meow_last_error, meow_new, meow_string_free,
+};
+use std::{
+    ffi::{CStr, CString},
+    ptr,
+};
+
+/// Read the global `name` of `vm` through the C interface.
+unsafe fn get(vm: *const meow::capi::MeowVm, name: &str) -> Option<String> {
+    let name = CString::new(name).unwrap();
+    let value = meow_get_string(vm, name.as_ptr());
+    if value.is_null() {
+        return None;
+    }
+    let string = CStr::from_ptr(value).to_str().unwrap().to_string();
+    meow_string_free(value);
+    Some(string)
+}
+
+#[test]
+fn evaluating() {
+    unsafe {
+        let vm = meow_new();
+        let source = CString::new("let x = [1, \"a\"]; let s = \"meow\";").unwrap();
+        assert_eq!(meow_eval(vm, source.as_ptr()), 0);
+        assert!(meow_last_error(vm).is_null());
+        assert_eq!(get(vm, "x").as_deref(), Some("[1, \"a\"]"));
+        assert_eq!(get(vm, "s").as_deref(), Some("meow"));
+        assert_eq!(get(vm, "y"), None);
+
+        // Globals are kept between calls
+        let source = CString::new("let y = x[0] + 1;").unwrap();
+        assert_eq!(meow_eval(vm, source.as_ptr()), 0);
+        assert_eq!(get(vm, "y").as_deref(), Some("2"));
+
+        meow_free(vm);
+        meow_free(ptr::null_mut());
+        meow_string_free(ptr::null_mut());
+    }
+}
+
+#[test]
+fn errors() {
+    unsafe {
+        let vm = meow_new();
+        let error = |source: &str| {
+            let source = CString::new(source).unwrap();
+            assert_eq!(meow_eval(vm, source.as_ptr()), -1);
+            CStr::from_ptr(meow_last_error(vm))
+                .to_str()
+                .unwrap()
+                .to_string()
+        };
+
+        assert!(error("let x = 1 / 0;").starts_with("error: division by zero\n"));
+        assert!(error("let = 1;").contains("1 | let = 1;"));
+        let invalid = [0xff, 0];
+        assert_eq!(meow_eval(vm, invalid.as_ptr().cast()), -1);
+        assert_eq!(
+            CStr::from_ptr(meow_last_error(vm)).to_str(),
+            Ok("the source code isn't valid UTF-8")
+        );
+
+        // A successful call clears the error
+        let source = CString::new("1;").unwrap();
+        assert_eq!(meow_eval(vm, source.as_ptr()), 0);
+        assert!(meow_last_error(vm).is_null());
+        meow_free(vm);
+    }
+}
@@ -0,0 +1,286 @@
+use meow::{
+    bytecode::{
+        Chunk,
+        OpCode::{self, *},
+    },
+    check, compile,
+    value::Value,
+};
+
+/// Decode the instructions of `chunk`, dropping their operands.
+fn ops(chunk: &Chunk) -> Vec<OpCode> {
+    let mut ops = Vec::new();
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        let op = OpCode::from_byte(chunk.code[offset]).unwrap();
+        ops.push(op);
+        offset += 1 + op.operand_len();
+    }
+    ops
+}
+
+#[test]
+fn expressions() {
+    let script = compile("-(1 + 2) * 3 == 4;").unwrap();
+    assert_eq!(
+        ops(&script.chunk),
+        vec![
+            Constant, Constant, Add, Negate, Constant, Multiply, Constant, Equal, Pop, Unit, Return
+        ]
+    );
+}
+
+#[test]
+fn blocks() {
+    let script = compile("{ 1; 2 };").unwrap();
+    assert_eq!(
+        ops(&script.chunk),
+        vec![Constant, Pop, Constant, Pop, Unit, Return]
+    );
+}
+
+#[test]
+fn globals() {
+    let script = compile("let mut x = 1; x += 2; println(x);").unwrap();
+    assert_eq!(
+        ops(&script.chunk),
+        vec![
+            Constant,
+            DefineGlobal,
+            GetGlobal,
+            Constant,
+            Add,
+            SetGlobal,
+            Pop,
+            GetGlobal,
+            GetGlobal,
+            Call,
+            Pop,
+            Unit,
+            Return
+        ]
+    );
+}
+
+#[test]
+fn functions() {
+    let script = compile("fun add(a, b) { return a + b; }").unwrap();
+    assert_eq!(
+        ops(&script.chunk),
+        vec![Constant, DefineGlobal, Unit, Return]
+    );
+
+    let add = match &script.chunk.constants[0] {
+        Value::Function(function) => function.clone(),
+        value => panic!("expected function, found {:?}", value),
+    };
+    assert_eq!(add.name, "add");
+    assert_eq!(add.arity, 2);
+    assert_eq!(ops(&add.chunk), vec![GetLocal, GetLocal, Add, Return]);
+    // Parameters start after the slot holding the function itself
+    assert_eq!(add.chunk.code[1], 1);
+    assert_eq!(add.chunk.code[3], 2);
+}
+
+#[test]
+fn unsupported() {
+    let diagnostics = compile("import std.nope;\nfun f(x) { fun g() { x } }").unwrap_err();
+    assert_eq!(diagnostics.len(), 2);
+    assert_eq!(diagnostics[0].message, "unknown module `std.nope`");
+    assert!(diagnostics[1].message.contains("closures"));
+}
+
+#[test]
+fn match_tables() {
+    // Enough dense ints are matched through a jump table
+    let script = compile("let x = match 3 { 1 => 10, 2 | 3 => 20, 4..=6 => 30, n => n };").unwrap();
+    let table = ops(&script.chunk);
+    assert!(table.contains(&Switch));
+    assert!(!table.contains(&Equal));
+    let switch = script
+        .chunk
+        .code
+        .iter()
+        .position(|&byte| byte == Switch as u8)
+        .unwrap();
+    assert_eq!(script.chunk.read_u16(switch + 3), 6);
+
+    // Sparse ints, guards and other literals are tested one arm at a time
+    for source in [
+        "match 3 { 1 => 10, 100 => 20, 1000 => 30, 10000 => 40, _ => 50 };",
+        "match 3 { 1 => 10, 2 => 20, 3 if true => 30, 4 => 40, _ => 50 };",
+        "match 3 { 1 => 10, 2 => 20, 3 => 30, \"4\" => 40, _ => 50 };",
+    ] {
+        let chain = ops(&compile(source).unwrap().chunk);
+        assert!(!chain.contains(&Switch), "{}", source);
+        assert!(chain.contains(&Equal), "{}", source);
+    }
+
+    // A match with a catch-all arm can't fail
+    let total = ops(&compile("match 3 { 1..3 => 1, _ => 2 };").unwrap().chunk);
+    assert!(total.contains(&InRange));
+    assert!(!total.contains(&Unmatched));
+}
+
+#[test]
+fn resolver_errors() {
+    // Resolution errors stop compilation
+    let diagnostics = compile("let x = 1; x = 2;").unwrap_err();
+    assert!(diagnostics[0].message.contains("immutable"));
+}
+
+#[test]
+fn checking() {
+    let messages = |source| -> Vec<String> {
+        check(source)
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect()
+    };
+
+    // Every error of the phase that failed is reported
+    assert_eq!(
+        messages("let x = ; let y = ;"),
+        [
+            "expected expression, found Semicolon",
+            "expected expression, found Semicolon"
+        ]
+    );
+    assert_eq!(messages("let x = 1; x = 2; x = 3;").len(), 2);
+    assert!(messages("fun f(x) { fun g() { x } }")[0].contains("closures"));
+
+    // Errors at runtime aren't found, since nothing is run
+    assert!(messages("let x = 1 / 0;").is_empty());
+}
+
+#[test]
+fn locals() {
+    let script = compile("let x = { let a = 1; let b = a; b };").unwrap();
+    assert_eq!(
+        ops(&script.chunk),
+        vec![
+            Constant,
+            GetLocal,
+            GetLocal,
+            SetLocal,
+            PopN,
+            DefineGlobal,
+            Unit,
+            Return
+        ]
+    );
+    // `a` and `b` live in slots 1 and 2, and the block's value replaces `a`
+    assert_eq!(
+        &script.chunk.code[3..11],
+        &[
+            GetLocal as u8,
+            1,
+            GetLocal as u8,
+            2,
+            SetLocal as u8,
+            1,
+            PopN as u8,
+            2
+        ]
+    );
+}
+
+#[test]
+fn jumps() {
+    let script = compile("if true { 1 } else { 2 };").unwrap();
+    assert_eq!(
+        ops(&script.chunk),
+        vec![
+            True,
+            JumpIfFalse,
+            Pop,
+            Constant,
+            Jump,
+            Pop,
+            Constant,
+            Pop,
+            Unit,
+            Return
+        ]
+    );
+    // Both jumps land just past the code they skip
+    assert_eq!(&script.chunk.code[1..4], &[JumpIfFalse as u8, 0, 7]);
+    assert_eq!(&script.chunk.code[8..11], &[Jump as u8, 0, 4]);
+
+    let script = compile("while false { }").unwrap();
+    assert_eq!(
+        ops(&script.chunk),
+        vec![False, JumpIfFalse, Pop, Unit, Pop, Loop, Pop, Unit, Return]
+    );
+    assert_eq!(&script.chunk.code[7..10], &[Loop as u8, 0, 10]);
+
+    let script = compile("true && false || true;").unwrap();
+    assert_eq!(
+        ops(&script.chunk),
+        vec![
+            True,
+            JumpIfFalse,
+            Pop,
+            False,
+            JumpIfFalse,
+            JumpIfFalse,
+            Jump,
+            Pop,
+            True,
+            JumpIfFalse,
+            Pop,
+            Unit,
+            Return
+        ]
+    );
+    // Right operands are checked to be bools by a jump to the next
+    // instruction
+    assert_eq!(&script.chunk.code[6..9], &[JumpIfFalse as u8, 0, 0]);
+}
+
+#[test]
+fn classes() {
+    let script = compile("class A { fun f() { self.x += 1; } } A().f();").unwrap();
+    let method = match &script.chunk.constants[1] {
+        Value::Function(method) => method,
+        value => panic!("expected a method, found {:?}", value),
+    };
+    assert_eq!(
+        ops(&method.chunk),
+        vec![GetLocal, Dup, GetField, Constant, Add, SetField, Pop, Unit, Return]
+    );
+    // `self` is the receiver in slot 0
+    assert_eq!(method.chunk.code[1], 0);
+
+    let diagnostics = compile("class A { fun f() { g() } fun g() {} }").unwrap_err();
+    assert_eq!(
+        diagnostics[0].message,
+        "methods must be accessed through `self.g`"
+    );
+}
+
+#[test]
+fn dead_code() {
+    let script = compile("fun f(x) { if x { return 1; } else { return 2; } 3 }").unwrap();
+    let function = match &script.chunk.constants[0] {
+        Value::Function(function) => function,
+        value => panic!("expected a function, found {:?}", value),
+    };
+    // Nothing after the `if` can run, and the jump over the `else` branch
+    // is never reached either
+    assert_eq!(
+        ops(&function.chunk),
+        vec![
+            GetLocal,
+            JumpIfFalse,
+            Pop,
+            Constant,
+            Return,
+            Pop,
+            Constant,
+            Return
+        ]
+    );
+    assert_eq!(function.chunk.constants, vec![Value::Int(1), Value::Int(2)]);
+    assert_eq!(&function.chunk.code[2..5], &[JumpIfFalse as u8, 0, 5]);
+}
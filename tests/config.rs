@@ -0,0 +1,124 @@
+#![cfg(feature = "fs")]
+
+use meow::{
+    config::{create_project, find, Config, FormatConfig, CONFIG_FILE},
+    errors::ConfigError,
+    lint::{Lint, LintConfig, LintLevel},
+    run_from_file,
+    vm::Vm,
+};
+use std::{env, fs, io, path::Path, process};
+
+#[test]
+fn defaults() {
+    let config = Config::parse("", Path::new("project")).unwrap();
+    assert_eq!(config.root, Path::new("project"));
+    assert_eq!(config.name, None);
+    assert_eq!(config.entry, Path::new("project/src/main.mw"));
+    assert_eq!(config.source_dirs, [Path::new("project/src")]);
+    assert!(config.search_paths.is_empty());
+    assert_eq!(config.lints, LintConfig::new());
+    assert_eq!(config.format, FormatConfig::default());
+    assert!(!config.optimize);
+    assert!(!config.cache);
+}
+
+#[test]
+fn every_option() {
+    let source = r#"
+        [project]
+        name = "hello"
+        entry = "main.mw"
+        source-dirs = ["lib", "vendor"]
+        search-paths = ["/opt/meow"]
+
+        [lints]
+        unused-variable = "deny"
+        naming-style = "allow"
+
+        [format]
+        indent = 2
+        max-width = 80
+
+        [build]
+        optimize = true
+        cache = true
+    "#;
+    let config = Config::parse(source, Path::new("")).unwrap();
+    assert_eq!(config.name.as_deref(), Some("hello"));
+    assert_eq!(config.entry, Path::new("main.mw"));
+    assert_eq!(config.source_dirs, [Path::new("lib"), Path::new("vendor")]);
+    assert_eq!(config.search_paths, [Path::new("/opt/meow")]);
+    assert_eq!(config.lints.level(Lint::UnusedVariable), LintLevel::Deny);
+    assert_eq!(config.lints.level(Lint::NamingStyle), LintLevel::Allow);
+    assert_eq!(config.lints.level(Lint::UnreachableCode), LintLevel::Warn);
+    assert_eq!(
+        config.format,
+        FormatConfig {
+            indent: 2,
+            max_width: 80
+        }
+    );
+    assert!(config.optimize);
+    assert!(config.cache);
+}
+
+#[test]
+fn invalid() {
+    let error = |source| Config::parse(source, Path::new("")).unwrap_err();
+
+    assert!(matches!(
+        error("[lints]\nunused = \"deny\""),
+        ConfigError::UnknownLint(name) if name == "unused"
+    ));
+    assert!(matches!(
+        error("[lints]\nshadowing = \"forbid\""),
+        ConfigError::Syntax(_)
+    ));
+    assert!(matches!(
+        error("[project]\nentrypoint = \"main.mw\""),
+        ConfigError::Syntax(_)
+    ));
+    assert!(matches!(error("[build"), ConfigError::Syntax(_)));
+}
+
+#[test]
+fn finding() {
+    let root = env::temp_dir().join(format!("meow-config-{}", process::id()));
+    let nested = root.join("src").join("nested");
+    fs::create_dir_all(&nested).unwrap();
+    fs::write(root.join(CONFIG_FILE), "[project]\nname = \"found\"\n").unwrap();
+
+    let path = find(&nested).unwrap();
+    assert_eq!(path, root.join(CONFIG_FILE));
+    let config = Config::load(&path).unwrap();
+    assert_eq!(config.root, root);
+    assert_eq!(config.name.as_deref(), Some("found"));
+    assert_eq!(config.entry, root.join("src/main.mw"));
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn new_project() {
+    let dir = env::temp_dir().join(format!("meow-new-{}", process::id()));
+    create_project(&dir).unwrap();
+
+    let config = Config::load(&dir.join(CONFIG_FILE)).unwrap();
+    assert_eq!(
+        config.name.as_deref(),
+        Some(format!("meow-new-{}", process::id()).as_str())
+    );
+    assert_eq!(config.entry, dir.join("src/main.mw"));
+    assert!(dir.join("tests").is_dir());
+
+    let mut vm = Vm::new();
+    vm.set_output(Box::new(Vec::new()));
+    for path in [config.entry, dir.join("tests/main.mw")] {
+        run_from_file(&mut vm, path.to_str().unwrap()).unwrap();
+    }
+
+    let error = create_project(&dir).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,84 @@
+use meow::{
+    compile,
+    errors::ConversionError,
+    value::Value,
+    vm::{convert::IntoMeow, Vm},
+};
+use std::collections::HashMap;
+
+fn run(source: &str) -> Vm {
+    let mut vm = Vm::new();
+    vm.run(compile(source).unwrap()).unwrap();
+    vm
+}
+
+#[test]
+fn round_trips() {
+    let mut vm = Vm::new();
+    let mut scores = HashMap::new();
+    scores.insert("tabby".to_string(), vec![Some(1.5), None]);
+    let value = vm.to_value(scores.clone());
+    assert_eq!(value.type_name(), "instance");
+    assert_eq!(
+        vm.from_value::<HashMap<String, Vec<Option<f64>>>>(&value),
+        Ok(scores)
+    );
+
+    for (value, expected) in [
+        (vm.to_value(true), Value::Bool(true)),
+        (vm.to_value(7), Value::Int(7)),
+        (vm.to_value("meow"), Value::from("meow")),
+        (vm.to_value(String::from("purr")), Value::from("purr")),
+        (vm.to_value(None::<i64>), Value::Unit),
+        (vm.to_value('c'), Value::Char('c')),
+    ] {
+        assert_eq!(value, expected);
+    }
+}
+
+#[test]
+fn passing_values_to_programs() {
+    let mut vm = Vm::new();
+    let cat = HashMap::from([("name".to_string(), "Tom".into_meow(&mut vm))]);
+    let cat = vm.to_value(cat);
+    vm.set_global("cat", cat);
+    let numbers = vm.to_value(vec![vec![1, 2], vec![3]]);
+    vm.set_global("numbers", numbers);
+    vm.run(compile("let name = cat.name; let last = numbers[1][0];").unwrap())
+        .unwrap();
+    assert_eq!(
+        vm.from_value::<String>(vm.global("name").unwrap()),
+        Ok("Tom".into())
+    );
+    assert_eq!(vm.from_value::<i64>(vm.global("last").unwrap()), Ok(3));
+}
+
+#[test]
+fn reading_values_from_programs() {
+    let vm = run("let xs = [1, 2.5]; let n = 2; let nothing = {};");
+    let xs = vm.global("xs").unwrap();
+    assert_eq!(vm.from_value::<Vec<f64>>(xs), Ok(vec![1.0, 2.5]));
+    assert_eq!(
+        vm.from_value::<Vec<i64>>(xs),
+        Err(ConversionError::Type {
+            expected: "int",
+            found: "float"
+        })
+    );
+    assert_eq!(vm.from_value::<f64>(vm.global("n").unwrap()), Ok(2.0));
+    assert_eq!(
+        vm.from_value::<Option<i64>>(vm.global("nothing").unwrap()),
+        Ok(None)
+    );
+    assert_eq!(vm.from_value::<()>(vm.global("nothing").unwrap()), Ok(()));
+    assert!(vm.from_value::<String>(vm.global("n").unwrap()).is_err());
+
+    let mut vm = Vm::new();
+    vm.set_big_ints(true);
+    vm.run(compile("let big = 9223372036854775807 + 1;").unwrap())
+        .unwrap();
+    assert_eq!(
+        vm.from_value::<i64>(vm.global("big").unwrap()),
+        Err(ConversionError::OutOfRange)
+    );
+}
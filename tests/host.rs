@@ -0,0 +1,246 @@
+use meow::{
+    compile,
+    errors::LoadError,
+    run,
+    value::Value,
+    vm::{host::ClassBuilder, Backend, Vm},
+};
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+fn vm(backend: Backend) -> Vm {
+    let mut vm = Vm::new();
+    vm.set_backend(backend);
+    vm.register_fn("add", |a: i64, b: i64| a + b);
+    vm.register_fn("greeting", || "hello");
+    vm.register_fn("lengths", |words: Vec<String>| {
+        words
+            .into_iter()
+            .map(|word| (word.clone(), word.len() as i64))
+            .collect::<HashMap<_, _>>()
+    });
+    vm.register_fn("parse", |text: String| text.parse::<i64>());
+    vm
+}
+
+#[test]
+fn calling_host_functions() {
+    for backend in [Backend::Stack, Backend::Register, Backend::Ast] {
+        let mut vm = vm(backend);
+        run(
+            &mut vm,
+            "let sum = add(1, add(2, 3));
+            let word = greeting();
+            let n = lengths([\"a\", \"abc\"]).abc;
+            let parsed = parse(\"42\");
+            let f = add;
+            let kind = type_of(f);",
+        )
+        .unwrap();
+        assert_eq!(vm.global("sum"), Some(&Value::Int(6)));
+        assert_eq!(vm.global("word"), Some(&Value::from("hello")));
+        assert_eq!(vm.global("n"), Some(&Value::Int(3)));
+        assert_eq!(vm.global("parsed"), Some(&Value::Int(42)));
+        assert_eq!(vm.global("kind"), Some(&Value::from("function")));
+    }
+}
+
+#[test]
+fn invalid_calls() {
+    let mut vm = vm(Backend::Stack);
+    let mut error = |source: &str| vm.run(compile(source).unwrap()).unwrap_err().message;
+
+    assert_eq!(
+        error("add(1);"),
+        "`add` expects 2 arguments, but 1 were given"
+    );
+    assert_eq!(
+        error("add(1, \"2\");"),
+        "invalid argument 2 to `add`: expected int, found string"
+    );
+    assert_eq!(error("parse(\"cat\");"), "invalid digit found in string");
+    assert_eq!(
+        error("spawn add(1, 2);"),
+        "cannot spawn the native function `add`"
+    );
+}
+
+#[test]
+fn closures_keep_state() {
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let mut vm = Vm::new();
+    let sink = log.clone();
+    vm.register_fn("record", move |event: String| sink.borrow_mut().push(event));
+    vm.run(compile("for i in 0..3 { record(str(i)); }").unwrap())
+        .unwrap();
+    assert_eq!(*log.borrow(), ["0", "1", "2"]);
+}
+
+#[test]
+fn snapshots() {
+    let mut vm = Vm::new();
+    vm.register_fn("add", |a: i64, b: i64| a + b);
+    vm.run(compile("let f = add;").unwrap()).unwrap();
+    let snapshot = vm.snapshot().unwrap();
+
+    // Host functions are found by name in the VM the snapshot is restored in
+    let mut restored = Vm::new();
+    restored.register_fn("add", |a: i64, b: i64| a * b);
+    restored.restore(&snapshot).unwrap();
+    restored.run(compile("let x = f(2, 3);").unwrap()).unwrap();
+    assert_eq!(restored.global("x"), Some(&Value::Int(6)));
+
+    assert!(matches!(
+        Vm::new().restore(&snapshot),
+        Err(LoadError::UnknownHostFunction(name)) if name == "add"
+    ));
+}
+
+#[derive(Debug, PartialEq)]
+struct Point {
+    x: f64,
+    y: f64,
+}
+
+fn points(backend: Backend) -> Vm {
+    let mut vm = Vm::new();
+    vm.set_backend(backend);
+    vm.register_class(
+        ClassBuilder::new("Point")
+            .constructor(|x: f64, y: f64| Point { x, y })
+            .getter("x", |point: &Point| point.x)
+            .setter("x", |point: &mut Point, x: f64| point.x = x)
+            .getter("y", |point: &Point| point.y)
+            .method("length", |point: &mut Point| point.x.hypot(point.y))
+            .method("scale", |point: &mut Point, by: f64| {
+                if by < 0.0 {
+                    return Err("negative scale");
+                }
+                point.x *= by;
+                point.y *= by;
+                Ok(())
+            }),
+    );
+    vm.register_class(ClassBuilder::<Point>::new("Origin").getter("x", |point: &Point| point.x));
+    vm
+}
+
+#[test]
+fn host_classes() {
+    for backend in [Backend::Stack, Backend::Register, Backend::Ast] {
+        let mut vm = points(backend);
+        run(
+            &mut vm,
+            "let p = Point(3, 4);
+            let length = p.length();
+            p.scale(2);
+            p.x = p.x + 1;
+            p.label = \"p\";
+            let scale = p.scale;
+            scale(0.5);
+            let fields = [p.x, p.y, p.label];",
+        )
+        .unwrap();
+        assert_eq!(vm.global("length"), Some(&Value::Float(5.0)));
+        let fields: Vec<Value> = vm.from_value(vm.global("fields").unwrap()).unwrap();
+        assert_eq!(
+            fields,
+            [Value::Float(3.5), Value::Float(4.0), Value::from("p")]
+        );
+
+        // The host can reach the Rust value, and create instances itself
+        let p = vm.global("p").unwrap().clone();
+        assert_eq!(
+            vm.with_host_data(&p, |point: &mut Point| point.y),
+            Some(4.0)
+        );
+        assert_eq!(vm.with_host_data(&p, |_: &mut String| ()), None);
+        let origin = vm
+            .host_instance("Origin", Point { x: 0.0, y: 0.0 })
+            .unwrap();
+        vm.set_global("origin", origin);
+        run(&mut vm, "let ox = origin.x;").unwrap();
+        assert_eq!(vm.global("ox"), Some(&Value::Float(0.0)));
+    }
+}
+
+#[test]
+fn invalid_host_class_uses() {
+    let mut vm = points(Backend::Stack);
+    let mut error = |source: &str| vm.run(compile(source).unwrap()).unwrap_err().message;
+
+    assert_eq!(
+        error("Point(1);"),
+        "`Point` expects 2 arguments, but 1 were given"
+    );
+    assert_eq!(
+        error("Point(1, \"2\");"),
+        "invalid argument 2 to `Point`: expected float, found string"
+    );
+    assert_eq!(
+        error("Origin();"),
+        "`Origin` instances can only be created by the host"
+    );
+    assert_eq!(error("Point(1, 2).scale(-1);"), "negative scale");
+    assert_eq!(
+        error("Point(1, 2).length(1);"),
+        "`Point.length` expects 0 arguments, but 1 were given"
+    );
+    assert_eq!(
+        error("Point(1, 2).y = 1;"),
+        "field `y` can't be assigned to"
+    );
+    assert_eq!(
+        error("Point(1, 2).x = \"1\";"),
+        "invalid value for field `x` of `Point`: expected float, found string"
+    );
+    assert_eq!(
+        error("Point(1, 2).z;"),
+        "`Point` instance has no field or method `z`"
+    );
+    assert_eq!(
+        error("spawn Point(1, 2).length();"),
+        "cannot spawn the native function `Point.length`"
+    );
+}
+
+#[test]
+fn handles() {
+    let mut vm = points(Backend::Stack);
+    let point = vm.host_instance("Point", Point { x: 1.0, y: 2.0 }).unwrap();
+    let handle = vm.handle(point);
+    vm.to_value(vec![1, 2]);
+    assert_eq!(vm.heap().len(), 2);
+
+    // Values held by handles survive collections until they are dropped
+    vm.collect_garbage();
+    assert_eq!(vm.heap().len(), 1);
+    assert_eq!(
+        vm.with_host_data(handle.value(), |point: &mut Point| point.x),
+        Some(1.0)
+    );
+    drop(handle);
+    vm.collect_garbage();
+    assert!(vm.heap().is_empty());
+}
+
+#[test]
+fn host_class_snapshots() {
+    let mut vm = points(Backend::Stack);
+    run(&mut vm, "let mut p = Point(1, 2);").unwrap();
+    assert_eq!(
+        vm.snapshot().unwrap_err().message,
+        "cannot take a snapshot holding a `Point` instance, since it wraps a Rust value"
+    );
+
+    // Classes are found by name in the VM the snapshot is restored in
+    run(&mut vm, "p = 0; let c = Point;").unwrap();
+    let snapshot = vm.snapshot().unwrap();
+    let mut restored = points(Backend::Stack);
+    restored.restore(&snapshot).unwrap();
+    run(&mut restored, "let x = c(5, 6).x;").unwrap();
+    assert_eq!(restored.global("x"), Some(&Value::Float(5.0)));
+    assert!(matches!(
+        Vm::new().restore(&snapshot),
+        Err(LoadError::UnknownHostClass(name)) if name == "Point"
+    ));
+}
@@ -1,13 +1,11 @@
 use meow::{
-    lex,
+    lex, lex_all,
     lexer::token::{
         Token,
         TokenKind::{self, *},
     },
 };
 
-use unindent::unindent;
-
 fn test_tokens(input: &str, expected: &[TokenKind]) {
     let mut lexer = lex(input);
 
@@ -29,8 +27,8 @@ fn test_tokens(input: &str, expected: &[TokenKind]) {
 #[test]
 fn operators() {
     test_tokens(
-        r"( ) [ ] { } , . ; && || .. ..= = == ! != > >= < <= + += - -= * *= / /=",
-        &vec![
+        r"( ) [ ] { } , . ; && || .. ..= -> => | = == ! != > >= < <= + += - -= * *= / /= % %=",
+        &[
             OpenParen,
             CloseParen,
             OpenBracket,
@@ -44,6 +42,9 @@ fn operators() {
             Or,
             Range,
             RangeInclusive,
+            Arrow,
+            FatArrow,
+            Pipe,
             Equal,
             EqualEqual,
             Bang,
@@ -60,32 +61,115 @@ fn operators() {
             StarEqual,
             Slash,
             SlashEqual,
+            Percent,
+            PercentEqual,
         ],
-    )
+    );
+
+    // `->` is one token, even without spaces around it, but `- >` is two
+    test_tokens(
+        "a->b-1 - >=>",
+        &[
+            Ident("a"),
+            Arrow,
+            Ident("b"),
+            Minus,
+            Int("1".into()),
+            Minus,
+            GreaterEqual,
+            Greater,
+        ],
+    );
 }
 
 #[test]
 fn strings() {
     // Single line string
-    test_tokens("\"Hello, World\"", &vec![Str("Hello, World".to_string())]);
+    test_tokens("\"Hello, World\"", &[Str("Hello, World".into())]);
 
-    // Multiline string
+    // A string ends with its line, and the next one is lexed as code,
+    // even when it holds a string of its own
+    let mut lexer = lex("let s = \"meow;\nlet t = \"ok\";\nlet u = 1;");
+    let tokens: Vec<_> = std::iter::from_fn(|| Some(lexer.next_token()))
+        .take_while(|token| token.kind != Eof)
+        .map(|token| token.kind)
+        .collect();
+    assert_eq!(
+        tokens,
+        [
+            Let,
+            Ident("s"),
+            Equal,
+            Error(
+                "Unterminated string literal, expected closing quote before the end of the line"
+                    .into()
+            ),
+            Let,
+            Ident("t"),
+            Equal,
+            Str("ok".into()),
+            Semicolon,
+            Let,
+            Ident("u"),
+            Equal,
+            Int("1".into()),
+            Semicolon,
+        ]
+    );
+
+    let mut lexer = lex("\"meow");
+    assert!(matches!(lexer.next_token().kind, Error(_)));
+    assert_eq!(lexer.next_token().kind, Eof);
+}
+
+#[test]
+fn multiline_strings() {
+    // The indentation the lines share is removed, and a last line of just
+    // the closing quotes sets it without being part of the text
+    let source = "let s = \"\"\"\n      a\n        b\n\n      c \\t ${x}\n    \"\"\";\nx";
     test_tokens(
-        &unindent(
-            "\"
-    Hello, World
-    Foo, Bar
-    \"",
+        source,
+        &[
+            Let,
+            Ident("s"),
+            Equal,
+            Str("  a\n    b\n\n  c \t ${x}".into()),
+            Semicolon,
+            Ident("x"),
+        ],
+    );
+    let mut lexer = lex(source);
+    let tokens: Vec<_> = std::iter::from_fn(|| Some(lexer.next_token()))
+        .take_while(|token| token.kind != Eof)
+        .map(|token| (token.line, token.column))
+        .collect();
+    assert_eq!(tokens[4..], [(6, 8), (7, 1)]);
+
+    // Text on the last line is kept, and sets the indentation with the rest
+    test_tokens("\"\"\"\n  a\n b\"\"\"", &[Str(" a\nb".into())]);
+    test_tokens("\"\"\"\n\"\"\"", &[Str("".into())]);
+
+    for (source, message) in [
+        (
+            "\"\"\"a\"\"\"",
+            "Expected a new line after the opening `\"\"\"` of a multiline string",
         ),
-        &vec![Str("\nHello, World\nFoo, Bar\n".to_string())],
-    )
+        (
+            "\"\"\"\n a\n \"\"",
+            "Unterminated multiline string literal, expected closing `\"\"\"`",
+        ),
+    ] {
+        let mut lexer = lex(source);
+        assert_eq!(lexer.next_token().kind, Error(message.to_string()));
+        assert_eq!(lexer.next_token().kind, Eof);
+    }
 }
 
 #[test]
 fn chars() {
     test_tokens(
         "'a' 'b' 'c' 'd' 'e'",
-        &vec![Char('a'), Char('b'), Char('c'), Char('d'), Char('e')],
+        &[Char('a'), Char('b'), Char('c'), Char('d'), Char('e')],
     )
 }
 
@@ -94,45 +178,374 @@ fn numbers() {
     // Test integers
     test_tokens(
         "25 32 43",
-        &vec![
-            Int("25".to_string()),
-            Int("32".to_string()),
-            Int("43".to_string()),
-        ],
+        &[Int("25".into()), Int("32".into()), Int("43".into())],
     );
 
     // Test floats
     test_tokens(
         "3.14159 12.2",
-        &vec![Float("3.14159".to_string()), Float("12.2".to_string())],
+        &[Float("3.14159".into()), Float("12.2".into())],
     );
 
     // Test too many dots
+    test_tokens("4.2.1", &[Float("4.2".into()), Dot, Int("1".into())]);
+
+    // Test ranges between integers
+    test_tokens("0..10", &[Int("0".into()), Range, Int("10".into())]);
+
+    // Test integers in other bases, which keep their prefixes
     test_tokens(
-        "4.2.1",
-        &vec![Float("4.2".to_string()), Dot, Int("1".to_string())],
-    )
+        "0xFf 0o17 0b101 0x1..0b1",
+        &[
+            Int("0xFf".into()),
+            Int("0o17".into()),
+            Int("0b101".into()),
+            Int("0x1".into()),
+            Range,
+            Int("0b1".into()),
+        ],
+    );
+    // Test underscores between digits, which the values leave out
+    test_tokens(
+        "1_000_000 3.141_59 1__2_ 0xdead_beef 0b_1",
+        &[
+            Int("1000000".into()),
+            Float("3.14159".into()),
+            Int("12".into()),
+            Int("0xdeadbeef".into()),
+            Int("0b1".into()),
+        ],
+    );
+    test_tokens("1._5", &[Int("1".into()), Dot, Ident("_5")]);
+    // Test exponents, which make floats, though only when digits follow
+    test_tokens(
+        "1e9 2.5e-3 1E+6 1_0e1_0 2e x1",
+        &[
+            Float("1e9".into()),
+            Float("2.5e-3".into()),
+            Float("1E+6".into()),
+            Float("10e10".into()),
+            Int("2".into()),
+            Ident("e"),
+        ],
+    );
+    test_tokens("3e+x", &[Int("3".into()), Ident("e"), Plus]);
+
+    // The tokens still span the underscores, so errors point at all of them
+    assert_eq!(lex("1_000_000").next_token().length, 9);
+    test_tokens(
+        "0x 0o8 0b102;",
+        &[
+            Error("Hexadecimal literal has no digits after `0x`".to_string()),
+            Error("Octal literal contains invalid digit `8`".to_string()),
+            Error("Binary literal contains invalid digit `2`".to_string()),
+            Semicolon,
+        ],
+    );
 }
 
 #[test]
 fn identifiers() {
+    test_tokens("foo bar baz", &[Ident("foo"), Ident("bar"), Ident("baz")]);
+
+    // Identifiers aren't only ASCII, though keywords are
     test_tokens(
-        "foo bar baz",
-        &vec![
-            Ident("foo".to_string()),
-            Ident("bar".to_string()),
-            Ident("baz".to_string()),
-        ],
+        "é fé ié 日本",
+        &[Ident("é"), Ident("fé"), Ident("ié"), Ident("日本")],
     )
 }
 
+#[test]
+fn doc_comments() {
+    let mut lexer = lex("/// Adds.\r\n///\n///  Indented\nfun /// trailing");
+    let mut kinds = Vec::new();
+    loop {
+        let kind = lexer.next_token().kind;
+        if kind == Eof {
+            break;
+        }
+        kinds.push(kind);
+    }
+    assert_eq!(
+        kinds,
+        [
+            DocComment("Adds."),
+            DocComment(""),
+            DocComment(" Indented"),
+            Fun,
+            DocComment("trailing"),
+        ]
+    );
+
+    // Two slashes are still two divisions
+    test_tokens("a // b", &[Ident("a"), Slash, Slash]);
+}
+
+#[test]
+fn interpolation() {
+    let mut lexer = lex(r#""a ${x} b ${ {y} } $c" "${"{"}""#);
+    let mut kinds = Vec::new();
+    loop {
+        let kind = lexer.next_token().kind;
+        if kind == Eof {
+            break;
+        }
+        kinds.push(kind);
+    }
+    assert_eq!(
+        kinds,
+        [
+            StrStart("a ".into()),
+            Ident("x"),
+            StrMiddle(" b ".into()),
+            OpenBrace,
+            Ident("y"),
+            CloseBrace,
+            StrEnd(" $c".into()),
+            StrStart("".into()),
+            Str("{".into()),
+            StrEnd("".into()),
+        ]
+    );
+}
+
+#[test]
+fn escapes() {
+    test_tokens(
+        r#""a\tb\n\\ \"q\" \${x}" '\n' '\'' '"' '\0' '\\'"#,
+        &[
+            Str("a\tb\n\\ \"q\" ${x}".into()),
+            Char('\n'),
+            Char('\''),
+            Char('"'),
+            Char('\0'),
+            Char('\\'),
+        ],
+    );
+
+    test_tokens(
+        r#""\u{1F431} \u{00e9}" '\u{41}' '\u{10FFFF}'"#,
+        &[
+            Str("\u{1F431} \u{e9}".into()),
+            Char('A'),
+            Char('\u{10FFFF}'),
+        ],
+    );
+
+    // Bad escapes are reported where they are, and the rest of the literal
+    // is skipped
+    let mut lexer = lex(r#""a \q \w" 'b' '\x' ''"#);
+    let errors: Vec<_> = std::iter::from_fn(|| Some(lexer.next_token()))
+        .take_while(|token| token.kind != Eof)
+        .map(|token| (token.kind, token.column, token.length))
+        .collect();
+    assert_eq!(
+        errors,
+        [
+            (Error("Unknown escape sequence `\\q`".to_string()), 4, 2),
+            (Char('b'), 11, 3),
+            (Error("Unknown escape sequence `\\x`".to_string()), 16, 2),
+            (
+                Error("Empty char literal, expected a character between the quotes".to_string()),
+                20,
+                2
+            ),
+        ]
+    );
+
+    for (source, message, length) in [
+        (r#""\u41""#, "Expected `{` after `\\u` in unicode escape", 2),
+        (r#""\u{4g}""#, "Invalid hex digit `g` in unicode escape", 6),
+        (r#""\u{41""#, "Unterminated unicode escape, expected `}`", 5),
+        (r#""\u{}""#, "Unicode escape has no hex digits", 4),
+        (
+            r#""\u{0000041}""#,
+            "Unicode escape has more than 6 hex digits",
+            11,
+        ),
+        (
+            r#""\u{dfff}""#,
+            "Unicode escape `\\u{dfff}` is a surrogate, not a char",
+            8,
+        ),
+        (
+            r#"'\u{110000}'"#,
+            "Unicode escape `\\u{110000}` is out of range",
+            10,
+        ),
+    ] {
+        let token = lex(source).next_token();
+        assert_eq!(token.kind, Error(message.to_string()), "{}", source);
+        assert_eq!((token.column, token.length), (2, length), "{}", source);
+    }
+}
+
 #[test]
 fn keywords() {
     test_tokens(
-        "class else false for fun if impls import match mut return trait true let while",
-        &vec![
-            Class, Else, False, For, Fun, If, Impls, Import, Match, Mut, Return, Trait, True, Let,
-            While,
+        "as class else false for fun if impls import in match mut pub return spawn trait true let while yield",
+        &[
+            As, Class, Else, False, For, Fun, If, Impls, Import, In, Match, Mut, Pub, Return, Spawn,
+            Trait, True, Let, While, Yield,
         ],
     )
 }
+
+#[test]
+fn classification() {
+    let mut lexer = lex("let x = true + 1.5 + \"a\" + 'b' + y;");
+    let mut kinds = Vec::new();
+    loop {
+        let kind = lexer.next_token().kind;
+        if kind == Eof {
+            break;
+        }
+        kinds.push((kind.is_keyword(), kind.is_literal()));
+    }
+    let (keyword, literal, other) = ((true, false), (false, true), (false, false));
+    assert_eq!(
+        kinds,
+        [
+            keyword, other, other, literal, other, literal, other, literal, other, literal, other,
+            other, other
+        ]
+    );
+}
+
+#[test]
+fn positions() {
+    let mut lexer = lex("let x = \"cat\";\n  x");
+
+    let expected = [
+        (1, 1, 3),
+        (1, 5, 1),
+        (1, 7, 1),
+        (1, 9, 5),
+        (1, 14, 1),
+        (2, 3, 1),
+    ];
+    for (line, column, length) in expected {
+        let token = lexer.next_token();
+        assert_eq!(
+            (token.line, token.column, token.length),
+            (line, column, length)
+        );
+    }
+}
+
+#[test]
+fn borrowed_payloads() {
+    use std::borrow::Cow;
+
+    // Text written as is in the source is borrowed from it, and only text
+    // that had to be rewritten is owned
+    let source = "\"plain\" \"esc\\n\" 1_000 2.5 \"a ${x} b\"";
+    let mut lexer = lex(source);
+    let mut borrowed = Vec::new();
+    loop {
+        let token = lexer.next_token();
+        borrowed.push(match token.kind {
+            // Identifiers are always slices of the source
+            Ident(_) => continue,
+            Str(text) | StrStart(text) | StrEnd(text) | Int(text) | Float(text) => {
+                matches!(text, Cow::Borrowed(_))
+            }
+            Eof => break,
+            kind => panic!("unexpected token {:?}", kind),
+        });
+    }
+    assert_eq!(borrowed, [true, false, false, true, true, true]);
+}
+
+#[test]
+fn multibyte_source() {
+    // Columns and lengths count chars, not the bytes they are encoded in
+    let mut lexer = lex("\"日本\" é\t\u{2028}x");
+    let tokens: Vec<_> = std::iter::from_fn(|| Some(lexer.next_token()))
+        .take_while(|token| token.kind != Eof)
+        .map(|token| (token.kind, token.column, token.length))
+        .collect();
+    assert_eq!(
+        tokens,
+        [
+            (Str("日本".into()), 1, 4),
+            (Ident("é"), 6, 1),
+            (Ident("x"), 9, 1)
+        ]
+    );
+
+    // A NUL char is part of the source, not the end of it
+    test_tokens("\"a\0b\" c", &[Str("a\0b".into()), Ident("c")]);
+}
+
+#[test]
+fn recovery() {
+    // Each broken literal is one error token, and lexing carries on after
+    // the rest of its line or its closing quote
+    let mut lexer = lex("'ab' x\n'\\qz' y\n'c\nz \"open\nw");
+    let tokens: Vec<_> = std::iter::from_fn(|| Some(lexer.next_token()))
+        .take_while(|token| token.kind != Eof)
+        .map(|token| (token.kind, token.line, token.column, token.length))
+        .collect();
+    assert_eq!(
+        tokens,
+        [
+            (
+                Error("Char literal contains more than one character, use a string instead".into()),
+                1,
+                1,
+                4
+            ),
+            (Ident("x"), 1, 6, 1),
+            (Error("Unknown escape sequence `\\q`".into()), 2, 2, 2),
+            (Ident("y"), 2, 7, 1),
+            (
+                Error("Unterminated char literal, expected closing single quote".into()),
+                3,
+                1,
+                2
+            ),
+            (Ident("z"), 4, 1, 1),
+            (
+                Error("Unterminated string literal, expected closing quote before the end of the line".into()),
+                4,
+                3,
+                5
+            ),
+            (Ident("w"), 5, 1, 1),
+        ]
+    );
+}
+
+#[test]
+fn all_at_once() {
+    let (tokens, diagnostics) = lex_all("/// Doc.\nlet s = 'ab' + \"x\";\n#");
+    let kinds: Vec<_> = tokens.into_iter().map(|token| token.kind).collect();
+    assert_eq!(
+        kinds,
+        [
+            DocComment("Doc."),
+            Let,
+            Ident("s"),
+            Equal,
+            Plus,
+            Str("x".into()),
+            Semicolon
+        ]
+    );
+    let errors: Vec<_> = diagnostics
+        .iter()
+        .map(|diagnostic| (diagnostic.message.as_str(), diagnostic.span.to_string()))
+        .collect();
+    assert_eq!(
+        errors,
+        [
+            (
+                "Char literal contains more than one character, use a string instead",
+                "2:9".to_string()
+            ),
+            ("Unknown character `#` found in source", "3:1".to_string()),
+        ]
+    );
+    assert!(diagnostics.iter().all(|diagnostic| diagnostic.is_error()));
+}
@@ -0,0 +1,134 @@
+use meow::{
+    diagnostics::Level,
+    lint::{lint, Lint, LintConfig, LintLevel},
+    parse, resolve,
+};
+
+/// Lint `source` with `config`, returning each lint found with its message.
+fn found_with(source: &str, config: &LintConfig) -> Vec<(Lint, String)> {
+    let program = parse(source).unwrap();
+    let (table, diagnostics) = resolve(&program);
+    assert!(diagnostics.is_empty());
+    lint(&program, &table, config)
+        .into_iter()
+        .map(|(lint, diagnostic)| (lint, diagnostic.message))
+        .collect()
+}
+
+fn found(source: &str) -> Vec<(Lint, String)> {
+    found_with(source, &LintConfig::new())
+}
+
+#[test]
+fn names() {
+    for lint in Lint::ALL {
+        assert_eq!(Lint::from_name(lint.name()), Some(lint));
+    }
+    assert_eq!(Lint::from_name("unused"), None);
+}
+
+#[test]
+fn unused_variables() {
+    assert_eq!(
+        found("fun f(a, b, _c) { let d = a; let _e = 1; for i in 0..3 { } match 1 { n => 2 } }"),
+        [
+            (Lint::UnusedVariable, "unused parameter `b`".to_string()),
+            (Lint::UnusedVariable, "unused variable `d`".to_string()),
+            (Lint::UnusedVariable, "unused variable `i`".to_string()),
+            (Lint::UnusedVariable, "unused variable `n`".to_string()),
+        ]
+    );
+    // Globals and `self` aren't reported, and assigning counts as a use
+    assert!(
+        found("let x = 1; class A { fun get() { 1 } } fun f() { let mut y = 1; y = 2; }")
+            .is_empty()
+    );
+}
+
+#[test]
+fn shadowing() {
+    let mut config = LintConfig::new();
+    assert_eq!(config.level(Lint::Shadowing), LintLevel::Allow);
+    let source =
+        "let x = 1; let x = 2; fun f(x) { let y = x; let y = y; { let z = y; z; } let z = 1; z }";
+    assert!(found(source).is_empty());
+
+    config.set_level(Lint::Shadowing, LintLevel::Warn);
+    let messages: Vec<_> = found_with(source, &config)
+        .into_iter()
+        .map(|(_, message)| message)
+        .collect();
+    // The parameter shadows the global, while a variable declared in a
+    // block that has ended isn't visible anymore
+    assert_eq!(
+        messages,
+        [
+            "`x` shadows an earlier declaration",
+            "`x` shadows an earlier declaration",
+            "`y` shadows an earlier declaration"
+        ]
+    );
+}
+
+#[test]
+fn unreachable_code() {
+    assert_eq!(
+        found("fun f() { if true { return 1; 2 } return 3; let a = 4; a }"),
+        [
+            (Lint::UnreachableCode, "unreachable code".to_string()),
+            (Lint::UnreachableCode, "unreachable code".to_string()),
+        ]
+    );
+    assert!(found("fun f() { return 1; }").is_empty());
+}
+
+#[test]
+fn naming_style() {
+    assert_eq!(
+        found("fun doThing(someArg) { someArg } class my_class { fun Get() { 1 } } let URL2x = 1;"),
+        [
+            (
+                Lint::NamingStyle,
+                "function `doThing` should have a snake_case name, such as `do_thing`".to_string()
+            ),
+            (
+                Lint::NamingStyle,
+                "parameter `someArg` should have a snake_case name, such as `some_arg`".to_string()
+            ),
+            (
+                Lint::NamingStyle,
+                "class `my_class` should have an UpperCamelCase name, such as `MyClass`"
+                    .to_string()
+            ),
+            (
+                Lint::NamingStyle,
+                "function `Get` should have a snake_case name, such as `get`".to_string()
+            ),
+            (
+                Lint::NamingStyle,
+                "variable `URL2x` should have a snake_case name, such as `url2x`".to_string()
+            ),
+        ]
+    );
+    assert!(found("fun to_string(_a1) { 1 } class Point2D { } let __x = 1;").is_empty());
+}
+
+#[test]
+fn levels() {
+    let source = "fun f(a) { return 1; 2 }";
+    let mut config = LintConfig::new();
+    config.set_level(Lint::UnusedVariable, LintLevel::Deny);
+    config.set_level(Lint::UnreachableCode, LintLevel::Allow);
+
+    let program = parse(source).unwrap();
+    let (table, _) = resolve(&program);
+    let found = lint(&program, &table, &config);
+    assert_eq!(found.len(), 1);
+    let (lint, diagnostic) = &found[0];
+    assert_eq!(*lint, Lint::UnusedVariable);
+    assert_eq!(diagnostic.level, Level::Error);
+    assert_eq!(
+        diagnostic.notes.last().unwrap(),
+        "the `unused-variable` lint is set to deny"
+    );
+}
@@ -0,0 +1,540 @@
+#![cfg(feature = "fs")]
+
+use meow::{
+    config::Config,
+    errors::InterpreterError,
+    loader::{search_paths, Location, ModuleGraph, SymbolIndex, PATH_VAR},
+    reload, run, run_from_file, run_project,
+    source::Source,
+    span::Span,
+    value::Value,
+    vm::{Backend, Vm},
+};
+use std::{env, fs, io, path::PathBuf, process, slice};
+
+/// Create a directory named after `name` holding `files`, each given as its
+/// path in the directory and its contents.
+fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let root = env::temp_dir().join(format!("meow-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&root);
+    for (path, contents) in files {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+    root
+}
+
+fn messages(error: InterpreterError) -> Vec<String> {
+    match error {
+        InterpreterError::Failed { diagnostics, .. } => diagnostics
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect(),
+        error => panic!("unexpected error: {}", error),
+    }
+}
+
+#[test]
+fn running_a_project() {
+    let root = project(
+        "project",
+        &[
+            (
+                "src/main.mw",
+                "import shapes.square; import count; let total = square.area(3) + count.count;",
+            ),
+            (
+                "src/shapes/square.mw",
+                "import count; pub fun area(side) { return side * side; }",
+            ),
+            ("src/count.mw", "pub let mut count = 0; count = count + 1;"),
+        ],
+    );
+
+    for backend in [Backend::Stack, Backend::Register, Backend::Ast] {
+        let mut vm = Vm::new();
+        vm.set_backend(backend);
+        run_from_file(&mut vm, root.to_str().unwrap()).unwrap();
+        // The module imported twice only runs once
+        assert_eq!(vm.global("total"), Some(&Value::Int(10)));
+    }
+
+    // A `meow.toml` can change where modules are found
+    fs::rename(root.join("src/shapes"), root.join("shapes")).unwrap();
+    fs::write(
+        root.join("meow.toml"),
+        "[project]\nsource-dirs = [\"src\", \".\"]\n",
+    )
+    .unwrap();
+    let mut vm = Vm::new();
+    run_from_file(&mut vm, root.join("meow.toml").to_str().unwrap()).unwrap();
+    assert_eq!(
+        vm.global("shapes.square.area").map(Value::type_name),
+        Some("function")
+    );
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn module_order() {
+    let root = project(
+        "order",
+        &[
+            ("main.mw", "import b; import a;"),
+            ("a.mw", "import c;"),
+            ("b.mw", "import c;"),
+            ("c.mw", ""),
+        ],
+    );
+
+    let graph = ModuleGraph::load(&root.join("main.mw"), slice::from_ref(&root)).unwrap();
+    let names: Vec<_> = graph
+        .modules
+        .iter()
+        .map(|module| module.name.as_str())
+        .collect();
+    assert_eq!(names, ["c", "b", "a", "main"]);
+    assert_eq!(graph.entry().path, root.join("main.mw"));
+    assert!(graph.entry().program.is_empty());
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn parallel_compilation() {
+    let modules: Vec<_> = (0..16)
+        .map(|i| (format!("m{}.mw", i), format!("pub fun value() {{ {} }}", i)))
+        .collect();
+    let imports: String = (0..16).map(|i| format!("import m{};", i)).collect();
+    let sum: Vec<_> = (0..16).map(|i| format!("m{}.value()", i)).collect();
+    let main = format!("{} let total = {};", imports, sum.join(" + "));
+    let mut files: Vec<_> = modules
+        .iter()
+        .map(|(path, source)| (path.as_str(), source.as_str()))
+        .collect();
+    files.push(("main.mw", &main));
+    let root = project("parallel", &files);
+    let entry = root.join("main.mw");
+
+    let mut vm = Vm::new();
+    run_from_file(&mut vm, entry.to_str().unwrap()).unwrap();
+    assert_eq!(vm.global("total"), Some(&Value::Int(120)));
+
+    // However the modules are spread over threads, the error reported is
+    // the one in the first module to fail
+    fs::write(root.join("m3.mw"), "let a = 1; a = 2;").unwrap();
+    fs::write(root.join("m9.mw"), "let b = 1; b = 2;").unwrap();
+    let error = run_from_file(&mut Vm::new(), entry.to_str().unwrap()).unwrap_err();
+    assert_eq!(
+        messages(error),
+        ["cannot assign to immutable variable `a`, consider declaring it with `let mut`"]
+    );
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn invalid_imports() {
+    let root = project(
+        "invalid",
+        &[
+            ("main.mw", "import std.math; import a; import missing;"),
+            ("a.mw", "import b;"),
+            ("b.mw", "import a;"),
+        ],
+    );
+    let load = |file: &str| ModuleGraph::load(&root.join(file), slice::from_ref(&root));
+
+    assert_eq!(
+        messages(load("main.mw").unwrap_err()),
+        ["import cycle: a -> b -> a"]
+    );
+    fs::write(root.join("b.mw"), "").unwrap();
+    assert_eq!(
+        messages(load("main.mw").unwrap_err()),
+        ["cannot find module `missing`"]
+    );
+    assert!(matches!(
+        load("nothing.mw").unwrap_err(),
+        InterpreterError::FileNotFound(_)
+    ));
+    assert!(matches!(
+        run_from_file(&mut Vm::new(), root.to_str().unwrap()).unwrap_err(),
+        InterpreterError::FileNotFound(_)
+    ));
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn namespaces() {
+    let root = project(
+        "namespaces",
+        &[
+            (
+                "main.mw",
+                "import a; import b.c; let x = a.x + c.x; let y = c.twice();",
+            ),
+            ("a.mw", "pub let x = 1;"),
+            // `d` is found next to `c` before the source directory
+            (
+                "b/c.mw",
+                "import d; pub let x = 2; pub fun twice() { return x * d.x; }",
+            ),
+            ("b/d.mw", "pub let x = 2;"),
+            ("d.mw", "pub let x = 3;"),
+        ],
+    );
+
+    let mut vm = Vm::new();
+    run_from_file(&mut vm, root.join("main.mw").to_str().unwrap()).unwrap();
+    assert_eq!(vm.global("x"), Some(&Value::Int(3)));
+    assert_eq!(vm.global("y"), Some(&Value::Int(4)));
+    assert_eq!(vm.global("a.x"), Some(&Value::Int(1)));
+    assert_eq!(vm.global("b.d.x"), Some(&Value::Int(2)));
+    assert_eq!(vm.global("d.x"), None);
+
+    let graph = ModuleGraph::load(&root.join("main.mw"), slice::from_ref(&root)).unwrap();
+    assert_eq!(graph.modules[0].exports, ["x"]);
+    assert_eq!(graph.modules[2].exports, ["x", "twice"]);
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn invalid_uses() {
+    let root = project(
+        "uses",
+        &[(
+            "a.mw",
+            "pub let mut x = 1; let y = 2; fun f() { return x; }",
+        )],
+    );
+    let errors = |source: &str| {
+        fs::write(root.join("main.mw"), source).unwrap();
+        messages(ModuleGraph::load(&root.join("main.mw"), slice::from_ref(&root)).unwrap_err())
+    };
+
+    assert_eq!(
+        errors("import a; print(a);"),
+        ["module `a` can only be used to reach what it defines, as in `a.name`"]
+    );
+    assert_eq!(errors("import a; a.z;"), ["module `a` doesn't define `z`"]);
+    assert_eq!(errors("import a; a.y;"), ["`y` is private to module `a`"]);
+    assert_eq!(errors("import a; a.f();"), ["`f` is private to module `a`"]);
+    assert_eq!(
+        errors("fun f() { pub let x = 1; }"),
+        ["`pub` can only be used at the top level of a module"]
+    );
+    assert_eq!(
+        errors("pub import std.math;"),
+        ["module `math` is built into meow, so it can't be re-exported"]
+    );
+    assert_eq!(
+        errors("import a; a.x = 2;"),
+        ["cannot assign to `x` outside of module `a`"]
+    );
+    // A local variable can shadow a module
+    fs::write(root.join("main.mw"), "import a; fun f(a) { return a.x; }").unwrap();
+    assert!(ModuleGraph::load(&root.join("main.mw"), slice::from_ref(&root)).is_ok());
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn reexports() {
+    let root = project(
+        "reexports",
+        &[
+            (
+                "main.mw",
+                "import shapes; let area = shapes.square.area(2) + shapes.unit;",
+            ),
+            (
+                "shapes.mw",
+                "pub import shapes.square; import shapes.circle; pub let unit = circle.unit;",
+            ),
+            ("shapes/square.mw", "pub fun area(side) { side * side }"),
+            ("shapes/circle.mw", "pub let unit = 1;"),
+        ],
+    );
+    let main = root.join("main.mw");
+
+    let mut vm = Vm::new();
+    run_from_file(&mut vm, main.to_str().unwrap()).unwrap();
+    assert_eq!(vm.global("area"), Some(&Value::Int(5)));
+    let graph = ModuleGraph::load(&main, slice::from_ref(&root)).unwrap();
+    assert_eq!(
+        graph.modules[2].reexports,
+        [("square".to_string(), "shapes.square".to_string())]
+    );
+
+    // Only modules imported with `pub import` can be reached
+    let errors = |source: &str| {
+        fs::write(&main, source).unwrap();
+        messages(ModuleGraph::load(&main, slice::from_ref(&root)).unwrap_err())
+    };
+    assert_eq!(
+        errors("import shapes; shapes.circle.unit;"),
+        ["module `shapes` doesn't define `circle`"]
+    );
+    assert_eq!(
+        errors("import shapes; let s = shapes.square;"),
+        ["module `shapes.square` can only be used to reach what it defines, as in `shapes.square.name`"]
+    );
+    assert_eq!(
+        errors("import shapes; shapes.square.side;"),
+        ["module `shapes.square` doesn't define `side`"]
+    );
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn caching() {
+    let root = project(
+        "cache",
+        &[
+            ("main.mw", "import a; let x = a.f();"),
+            ("a.mw", "import b; pub fun f() { return b.x; }"),
+            ("b.mw", "pub let x = 1;"),
+            ("c.mw", "pub let x = 2;"),
+        ],
+    );
+    let main = root.join("main.mw");
+    let main = main.to_str().unwrap();
+
+    let mut vm = Vm::new();
+    run_from_file(&mut vm, main).unwrap();
+    assert_eq!(vm.module_cache().hits(), 0);
+    // Every module is loaded and compiled from the cache
+    run_from_file(&mut vm, main).unwrap();
+    assert_eq!(vm.module_cache().hits(), 6);
+
+    // Changing a module compiles it again, along with the ones importing it
+    fs::write(root.join("a.mw"), "import c; pub fun f() { return c.x; }").unwrap();
+    run_from_file(&mut vm, main).unwrap();
+    assert_eq!(vm.module_cache().hits(), 6);
+    assert_eq!(vm.global("x"), Some(&Value::Int(2)));
+
+    // Compiled modules saved on disk are used by other VMs
+    let dir = root.join("cache");
+    let mut vm = Vm::new();
+    vm.module_cache().set_dir(Some(dir.clone()));
+    run_from_file(&mut vm, main).unwrap();
+    assert_eq!(vm.module_cache().hits(), 0);
+    let mut vm = Vm::new();
+    vm.module_cache().set_dir(Some(dir.clone()));
+    run_from_file(&mut vm, main).unwrap();
+    assert_eq!(vm.module_cache().hits(), 3);
+    assert_eq!(vm.global("x"), Some(&Value::Int(2)));
+    vm.module_cache().clear();
+    assert!(!dir.exists());
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn reloading() {
+    let root = project(
+        "reload",
+        &[
+            (
+                "main.mw",
+                "import game; let tom = game.Cat(\"Tom\"); game.bump(); game.bump(); \
+                 fun tick() { game.bump(); return tom.speak(); }",
+            ),
+            (
+                "game.mw",
+                "pub let mut score = 0; pub fun bump() { score = score + 1; } \
+                 pub class Cat { fun init(name) { self.name = name; } \
+                 fun speak() { return self.name + \" meows\"; } }",
+            ),
+            ("extra.mw", "pub let mut loaded = 0; loaded = loaded + 1;"),
+        ],
+    );
+    let main = root.join("main.mw");
+
+    for backend in [Backend::Stack, Backend::Register, Backend::Ast] {
+        let mut vm = Vm::new();
+        vm.set_backend(backend);
+        run_from_file(&mut vm, main.to_str().unwrap()).unwrap();
+        assert_eq!(vm.global("game.score"), Some(&Value::Int(2)));
+
+        // Functions and classes are replaced, but variables keep their values,
+        // and the rest of the module doesn't run again
+        let source = "import extra; pub let mut score = 100; pub let lives = 9; \
+                      pub fun bump() { score = score + 10 * extra.loaded; } \
+                      pub class Cat { fun init(name) { self.name = name; } \
+                      fun speak() { return self.name + \" purrs\"; } } score = 50;";
+        reload(&mut vm, "game", source).unwrap();
+        assert_eq!(vm.global("game.score"), Some(&Value::Int(2)));
+        assert_eq!(vm.global("game.lives"), Some(&Value::Int(9)));
+        assert_eq!(vm.global("extra.loaded"), Some(&Value::Int(1)));
+        run(&mut vm, "let said = tick();").unwrap();
+        assert_eq!(vm.global("game.score"), Some(&Value::Int(12)));
+        assert_eq!(vm.global("said"), Some(&Value::from("Tom purrs")));
+
+        // The entry can be reloaded too, and modules already loaded don't
+        // run again
+        let source = "import game; import extra; \
+                      fun tick() { game.bump(); return extra.loaded; }";
+        reload(&mut vm, "main", source).unwrap();
+        run(&mut vm, "let loaded = tick();").unwrap();
+        assert_eq!(vm.global("loaded"), Some(&Value::Int(1)));
+        assert_eq!(vm.global("game.score"), Some(&Value::Int(22)));
+
+        // A module that fails to reload is left as it was
+        assert_eq!(
+            messages(reload(&mut vm, "game", "pub fun bump( {}").unwrap_err()),
+            [
+                "expected parameter name, found OpenBrace",
+                "expected expression, found CloseBrace"
+            ]
+        );
+        run(&mut vm, "tick();").unwrap();
+        assert_eq!(vm.global("game.score"), Some(&Value::Int(32)));
+        assert!(matches!(
+            reload(&mut vm, "dog", ""),
+            Err(InterpreterError::ModuleNotLoaded(name)) if name == "dog"
+        ));
+    }
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn navigation() {
+    let root = project(
+        "navigate",
+        &[
+            (
+                "main.mw",
+                "import shapes.square;\nlet side = 3;\nlet area = square.area(side) + side;",
+            ),
+            (
+                "shapes/square.mw",
+                "pub fun area(side) {\n    return side * side;\n}\nfun twice() { area(1); }",
+            ),
+        ],
+    );
+    let main = root.join("main.mw");
+    let square = root.join("shapes/square.mw");
+    let graph = ModuleGraph::load(&main, &[]).unwrap();
+    let index = SymbolIndex::new(&graph);
+    let at = |path: &PathBuf, line, column, length| Location {
+        path: path.clone(),
+        span: Span::new(line, column, length),
+    };
+
+    // Names used in one module lead to their definitions in another
+    let definition = index.definition(&main, 3, 20).unwrap();
+    assert_eq!(
+        fs::canonicalize(&definition.path).unwrap(),
+        fs::canonicalize(&square).unwrap()
+    );
+    assert_eq!(definition.span, Span::new(1, 9, 4));
+    assert_eq!(index.definition(&main, 3, 32), Some(at(&main, 2, 5, 4)));
+    assert_eq!(
+        index.definition(&square, 2, 12),
+        Some(at(&definition.path, 1, 14, 4))
+    );
+    // Builtins and places without names have no definition
+    assert_eq!(index.definition(&main, 1, 1), None);
+
+    // References are found in every module
+    let mut references: Vec<_> = index
+        .references(&definition.path, 1, 10)
+        .into_iter()
+        .map(|location| (location.path.ends_with("main.mw"), location.span))
+        .collect();
+    references.sort_by_key(|(in_main, span)| (*in_main, span.line, span.column));
+    assert_eq!(
+        references,
+        [(false, Span::new(4, 15, 4)), (true, Span::new(3, 12, 11))]
+    );
+    assert_eq!(
+        index.references(&main, 2, 5),
+        [at(&main, 3, 24, 4), at(&main, 3, 32, 4)]
+    );
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn search_paths_are_shared() {
+    let root = project(
+        "search",
+        &[
+            (
+                "app/main.mw",
+                "import std.strings; import util; import std.math; \
+                 let line = strings.join([util.name, math.floor(1.5)], \", \");",
+            ),
+            ("shared/util.mw", "pub let name = \"shared\";"),
+            ("vendor/util.mw", "pub let name = \"vendor\";"),
+        ],
+    );
+
+    // The standard library is found wherever the program is
+    let main = root.join("app/main.mw");
+    fs::write(root.join("app/util.mw"), "pub let name = \"local\";").unwrap();
+    let mut vm = Vm::new();
+    run_from_file(&mut vm, main.to_str().unwrap()).unwrap();
+    assert_eq!(vm.global("line"), Some(&Value::from("local, 1.0")));
+    fs::remove_file(root.join("app/util.mw")).unwrap();
+
+    // Directories configured by a project come before `MEOW_PATH`
+    env::set_var(PATH_VAR, root.join("shared"));
+    assert!(search_paths(&[]).contains(&root.join("shared")));
+    let mut vm = Vm::new();
+    run_from_file(&mut vm, main.to_str().unwrap()).unwrap();
+    assert_eq!(vm.global("line"), Some(&Value::from("shared, 1.0")));
+    let config = Config::parse(
+        "[project]\nentry = \"app/main.mw\"\nsearch-paths = [\"vendor\"]\n",
+        &root,
+    )
+    .unwrap();
+    let mut vm = Vm::new();
+    run_project(&mut vm, &config).unwrap();
+    assert_eq!(vm.global("line"), Some(&Value::from("vendor, 1.0")));
+    env::remove_var(PATH_VAR);
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn large_sources() {
+    // Large enough to be memory-mapped with the `mmap` feature
+    let lines = "count = count + 1;\n".repeat(5000);
+    let main = format!("import count; pub let mut count = 0;\n{}", lines);
+    let root = project(
+        "large",
+        &[
+            ("main.mw", &main),
+            ("count.mw", &format!("pub let mut count = 0;\n{}", lines)),
+            ("small.mw", "let x = 1;"),
+        ],
+    );
+
+    let mut vm = Vm::new();
+    run_from_file(&mut vm, root.join("main.mw").to_str().unwrap()).unwrap();
+    assert_eq!(vm.global("count"), Some(&Value::Int(5000)));
+    assert_eq!(vm.global("count.count"), Some(&Value::Int(5000)));
+
+    let source = Source::read(root.join("main.mw")).unwrap();
+    assert_eq!(&*source, main);
+    assert_eq!(source.is_mapped(), cfg!(all(feature = "mmap", unix)));
+    assert!(!Source::read(root.join("small.mw")).unwrap().is_mapped());
+
+    // Sources are checked to be UTF-8 whether they are mapped or not
+    let mut invalid = main.into_bytes();
+    invalid[100] = 0xff;
+    fs::write(root.join("invalid.mw"), invalid).unwrap();
+    let error = Source::read(root.join("invalid.mw")).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+    fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,537 @@
+use meow::{
+    parse,
+    parser::{
+        ast::{BinOp, Expr, Literal, Pattern, Stmt, StrPart, UnaryOp},
+        unparse::{expr_to_source, to_source},
+        MAX_NESTING,
+    },
+    span::Span,
+    value::CastType,
+};
+
+/// Parse `input` and return the expression of its single expression
+/// statement.
+fn parse_expr(input: &str) -> Expr {
+    let mut program = parse(input).unwrap();
+    assert_eq!(program.len(), 1);
+    match program.remove(0) {
+        Stmt::Expr { expr, .. } => expr,
+        stmt => panic!("expected expression statement, found {:?}", stmt),
+    }
+}
+
+fn int(expr: &Expr) -> i64 {
+    match expr {
+        Expr::Literal {
+            value: Literal::Int(value),
+            ..
+        } => *value,
+        expr => panic!("expected integer literal, found {:?}", expr),
+    }
+}
+
+#[test]
+fn precedence() {
+    match parse_expr("1 + 2 * 3;") {
+        Expr::Binary {
+            op: BinOp::Plus,
+            left,
+            right,
+            ..
+        } => {
+            assert_eq!(int(&left), 1);
+            assert!(matches!(
+                *right,
+                Expr::Binary {
+                    op: BinOp::Star,
+                    ..
+                }
+            ));
+        }
+        expr => panic!("unexpected expression {:?}", expr),
+    }
+
+    // Binary operators are left associative
+    match parse_expr("1 - 2 - 3;") {
+        Expr::Binary {
+            op: BinOp::Minus,
+            left,
+            right,
+            ..
+        } => {
+            assert!(matches!(
+                *left,
+                Expr::Binary {
+                    op: BinOp::Minus,
+                    ..
+                }
+            ));
+            assert_eq!(int(&right), 3);
+        }
+        expr => panic!("unexpected expression {:?}", expr),
+    }
+
+    // `%` binds as tightly as `*` and `/`
+    match parse_expr("1 + 2 % 3 * 4;") {
+        Expr::Binary {
+            op: BinOp::Plus,
+            right,
+            ..
+        } => match *right {
+            Expr::Binary {
+                op: BinOp::Star,
+                left,
+                ..
+            } => assert!(matches!(
+                *left,
+                Expr::Binary {
+                    op: BinOp::Percent,
+                    ..
+                }
+            )),
+            expr => panic!("unexpected expression {:?}", expr),
+        },
+        expr => panic!("unexpected expression {:?}", expr),
+    }
+}
+
+#[test]
+fn casts() {
+    // Casts bind tighter than binary operators, but looser than unary ones
+    match parse_expr("1 * -2 as float;") {
+        Expr::Binary {
+            op: BinOp::Star,
+            right,
+            ..
+        } => match *right {
+            Expr::Cast {
+                expr,
+                ty: CastType::Float,
+                ..
+            } => assert!(matches!(
+                *expr,
+                Expr::Unary {
+                    op: UnaryOp::Minus,
+                    ..
+                }
+            )),
+            expr => panic!("unexpected expression {:?}", expr),
+        },
+        expr => panic!("unexpected expression {:?}", expr),
+    }
+    assert!(matches!(
+        parse_expr("x as float as int;"),
+        Expr::Cast {
+            ty: CastType::Int,
+            ..
+        }
+    ));
+
+    let diagnostics = parse("x as string;").unwrap_err();
+    assert_eq!(
+        diagnostics[0].message,
+        "cannot cast to `string`, only to `int` or `float`"
+    );
+    assert_eq!(diagnostics[0].span.column, 6);
+}
+
+#[test]
+fn assignment() {
+    // Assignment is right associative
+    match parse_expr("a = b = 1;") {
+        Expr::Assign {
+            value, op: None, ..
+        } => {
+            assert!(matches!(*value, Expr::Assign { .. }))
+        }
+        expr => panic!("unexpected expression {:?}", expr),
+    }
+
+    assert!(matches!(
+        parse_expr("a.b += 1;"),
+        Expr::Assign {
+            op: Some(BinOp::Plus),
+            ..
+        }
+    ));
+
+    assert!(parse("1 + 2 = 3;").is_err());
+}
+
+#[test]
+fn postfix() {
+    match parse_expr("foo.bar(1, 2)[0];") {
+        Expr::Index { object, .. } => match *object {
+            Expr::Call { callee, args, .. } => {
+                assert_eq!(args.len(), 2);
+                assert!(matches!(*callee, Expr::Field { .. }));
+            }
+            expr => panic!("unexpected expression {:?}", expr),
+        },
+        expr => panic!("unexpected expression {:?}", expr),
+    }
+}
+
+#[test]
+fn blocks() {
+    match parse_expr("if x { 1 } else if y { 2 } else { let z = 3; z }") {
+        Expr::If {
+            then, otherwise, ..
+        } => {
+            assert_eq!(int(then.tail.as_ref().unwrap()), 1);
+            match *otherwise.unwrap() {
+                Expr::If {
+                    otherwise: Some(otherwise),
+                    ..
+                } => match *otherwise {
+                    Expr::Block(block) => {
+                        assert_eq!(block.stmts.len(), 1);
+                        assert!(block.tail.is_some());
+                    }
+                    expr => panic!("unexpected expression {:?}", expr),
+                },
+                expr => panic!("unexpected expression {:?}", expr),
+            }
+        }
+        expr => panic!("unexpected expression {:?}", expr),
+    }
+}
+
+#[test]
+fn statements() {
+    let program = parse(
+        "import std.math;
+        let mut x = [1, 2, 3];
+        fun add(a, b) { return a + b; }
+        class Cat { fun meow(self) { println(\"meow\"); } }
+        while x { x = false; }
+        for i in 0..10 { println(i); }
+        spawn worker(x, 1);",
+    )
+    .unwrap();
+
+    assert!(matches!(&program[0], Stmt::Import { path, .. } if path.len() == 2));
+    assert!(matches!(program[1], Stmt::Let { mutable: true, .. }));
+    assert!(matches!(&program[2], Stmt::Fun(fun) if fun.params.len() == 2));
+    assert!(matches!(&program[3], Stmt::Class { methods, .. } if methods.len() == 1));
+    assert!(matches!(program[4], Stmt::While { .. }));
+    assert!(matches!(program[5], Stmt::For { .. }));
+    assert!(matches!(&program[6], Stmt::Spawn { args, .. } if args.len() == 2));
+    assert!(!program.iter().any(Stmt::is_public));
+}
+
+#[test]
+fn public_declarations() {
+    let program = parse(
+        "pub import shapes.circle;
+        pub let mut x = 1;
+        pub fun f() {}
+        pub class Cat {}
+        let y = 2;",
+    )
+    .unwrap();
+
+    assert!(matches!(&program[0], Stmt::Import { public: true, .. }));
+    assert!(matches!(
+        program[1],
+        Stmt::Let {
+            public: true,
+            mutable: true,
+            ..
+        }
+    ));
+    assert!(matches!(&program[2], Stmt::Fun(fun) if fun.public));
+    assert!(matches!(program[3], Stmt::Class { public: true, .. }));
+    assert!(!program[4].is_public());
+
+    let diagnostics = parse("pub 1;").unwrap_err();
+    assert_eq!(
+        diagnostics[0].message,
+        "expected `let`, `fun`, `class` or `import` after `pub`"
+    );
+}
+
+#[test]
+fn doc_comments() {
+    let program = parse(
+        "/// The answer.
+        pub let x = 42;
+        /// Adds two numbers.
+        ///
+        /// Both are ints.
+        fun add(a, b) { a + b }
+        /// A cat.
+        class Cat {
+            /// Makes a sound.
+            fun speak() {}
+            fun sleep() {}
+        }
+        /// Not a declaration.
+        add(1, 2);
+        fun undocumented() {}",
+    )
+    .unwrap();
+
+    assert_eq!(program[0].doc(), Some("The answer."));
+    assert_eq!(
+        program[1].doc(),
+        Some("Adds two numbers.\n\nBoth are ints.")
+    );
+    assert_eq!(program[2].doc(), Some("A cat."));
+    match &program[2] {
+        Stmt::Class { methods, .. } => {
+            assert_eq!(methods[0].doc.as_deref(), Some("Makes a sound."));
+            assert_eq!(methods[1].doc, None);
+        }
+        stmt => panic!("expected a class, got {:?}", stmt),
+    }
+    assert_eq!(program[3].doc(), None);
+    assert_eq!(program[4].doc(), None);
+}
+
+#[test]
+fn interpolation() {
+    match parse_expr(r#""{a} ${a} b ${c + 1}";"#) {
+        Expr::Interpolation { parts, .. } => {
+            assert!(matches!(
+                &parts[..],
+                [
+                    StrPart::Text(first),
+                    StrPart::Expr(Expr::Ident { .. }),
+                    StrPart::Text(second),
+                    StrPart::Expr(Expr::Binary { .. }),
+                ] if first == "{a} " && second == " b "
+            ));
+            assert_eq!(StrPart::template(&parts), "{{a}} {} b {}");
+        }
+        expr => panic!("expected an interpolation, got {:?}", expr),
+    }
+
+    for (source, message) in [
+        (r#""${}";"#, "expected an expression between `${` and `}`"),
+        (
+            r#""${a b}";"#,
+            "expected `}` after interpolated expression, found Ident(\"b\")",
+        ),
+    ] {
+        assert_eq!(parse(source).unwrap_err()[0].message, message, "{}", source);
+    }
+}
+
+#[test]
+fn match_expressions() {
+    match parse_expr("match x { 0 => 1, -1 | 2.5 => 2, 3..=9 if big => { 3 } n => n, _ => 4 }") {
+        Expr::Match {
+            scrutinee, arms, ..
+        } => {
+            assert!(matches!(*scrutinee, Expr::Ident { ref name, .. } if name == "x"));
+            assert_eq!(arms.len(), 5);
+            assert!(matches!(
+                arms[0].pattern,
+                Pattern::Literal {
+                    value: Literal::Int(0),
+                    ..
+                }
+            ));
+            match &arms[1].pattern {
+                Pattern::Or { patterns, .. } => {
+                    assert!(matches!(
+                        patterns[..],
+                        [
+                            Pattern::Literal {
+                                value: Literal::Int(-1),
+                                ..
+                            },
+                            Pattern::Literal {
+                                value: Literal::Float(_),
+                                ..
+                            }
+                        ]
+                    ));
+                }
+                pattern => panic!("expected alternatives, found {:?}", pattern),
+            }
+            assert!(matches!(
+                arms[2].pattern,
+                Pattern::Range {
+                    start: 3,
+                    end: 9,
+                    inclusive: true,
+                    ..
+                }
+            ));
+            assert!(arms[2].guard.is_some());
+            assert!(matches!(&arms[3].pattern, Pattern::Binding { name, .. } if name == "n"));
+            assert!(matches!(arms[4].pattern, Pattern::Wildcard { .. }));
+        }
+        expr => panic!("expected match, found {:?}", expr),
+    }
+
+    // A match is block-like, so it needs no semicolon as a statement
+    assert!(parse("match 1 { _ => 2 } let x = 1;").is_ok());
+
+    for (source, message) in [
+        (
+            "match 1 { 1 2 }",
+            "expected `=>` after match pattern, found Int(\"2\")",
+        ),
+        ("match 1 { 1 => 1 2 => 2 }", "expected `,` after match arm"),
+        (
+            "match 1 { 1 | n => 1 }",
+            "bindings cannot be used in `|` patterns",
+        ),
+        (
+            "match 1 { 1.5..2 => 1 }",
+            "range patterns must start with an int",
+        ),
+        (
+            "match 1 { 1..x => 1 }",
+            "range patterns must end with an int",
+        ),
+        (
+            "match 1 { [1] => 1 }",
+            "expected a pattern, found OpenBracket",
+        ),
+    ] {
+        assert_eq!(parse(source).unwrap_err()[0].message, message, "{}", source);
+    }
+}
+
+#[test]
+fn errors() {
+    // Every broken statement is reported, not just the first
+    let diagnostics = parse("let = 1;\nlet x = ;\nlet y = 2;").unwrap_err();
+    assert_eq!(diagnostics.len(), 2);
+    assert_eq!(diagnostics[0].span.line, 1);
+    assert_eq!(diagnostics[1].span.line, 2);
+
+    // Lexer errors are reported by the parser
+    let diagnostics = parse("let x = 1 $ 2;").unwrap_err();
+    assert!(diagnostics[0].message.contains("Unknown character"));
+    assert_eq!(diagnostics[0].span.column, 11);
+
+    let diagnostics = parse("spawn 1 + 2;").unwrap_err();
+    assert_eq!(
+        diagnostics[0].message,
+        "expected a function call after `spawn`"
+    );
+
+    // Recovering from a statement that fails before it consumes anything
+    // still moves on, rather than reporting it forever
+    let diagnostics = parse("{ x; trait }").unwrap_err();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(
+        diagnostics[0].message,
+        "trait declarations are not supported yet"
+    );
+}
+
+#[test]
+fn nesting() {
+    let nested = |depth| format!("{}1{};", "(".repeat(depth), ")".repeat(depth));
+    assert!(parse(&nested(MAX_NESTING - 1)).is_ok());
+
+    let diagnostics = parse(&nested(5000)).unwrap_err();
+    assert_eq!(diagnostics[0].message, "expression nested too deeply");
+    let diagnostics = parse(&format!("let x = {}1;", "-".repeat(5000))).unwrap_err();
+    assert_eq!(diagnostics[0].message, "expression nested too deeply");
+    let diagnostics = parse(&format!("{}{}", "{".repeat(5000), "}".repeat(5000))).unwrap_err();
+    assert_eq!(diagnostics[0].message, "expression nested too deeply");
+}
+
+#[test]
+fn json() {
+    let program = parse("let x = -1 as float;").unwrap();
+    let json = serde_json::to_value(&program).unwrap();
+    let cast = &json[0]["Let"]["value"]["Cast"];
+    assert_eq!(cast["ty"], "Float");
+    assert_eq!(cast["expr"]["Unary"]["op"], "Minus");
+    assert_eq!(cast["expr"]["Unary"]["expr"]["Literal"]["value"]["Int"], 1);
+    assert_eq!(
+        cast["span"],
+        serde_json::json!({"line": 1, "column": 9, "length": 11})
+    );
+}
+
+#[test]
+fn unparsing() {
+    // Writing a program and parsing it again gives back the same program,
+    // which is written the same way again
+    let programs = [
+        "let x = (1 + 2) * 3 - -4 / (5 - 6);",
+        "let y = !(a && b) || c == (d < e);",
+        "a = b = c; a += 1; xs[0].name = 2 as float as int;",
+        "a %= 2; let r = (1 + 2) % 3 * 4 % -5;",
+        "let r = (0..10)..=-1; let s = f(a..b, -(1 + 2))[0]();",
+        "pub let mut count = -0.5; let big = 100000000000000000000000.0; let tiny = 0.000001;",
+        "let huge = 1.5e300; let small = 2e-300; let whole = 1e15;",
+        "pub fun add(a, b) { let c = a + b; c } fun empty() {}",
+        "pub class Cat { fun init(name) { self.name = name; } fun speak() { return self.name; } }",
+        "class Empty {}",
+        "while i < 10 { i += 1; } for x in xs { print(x); }",
+        "fun gen() { yield 1; yield; return; } spawn worker(1, 2);",
+        "pub import shapes.circle; import std.fs;",
+        "let v = if a { 1 } else if b { 2 } else { 3 };",
+        "if a { f(); }; (g)(); if b { 1 }; [1, 2]; { 1 }; -1; if c {} d;",
+        "fun f() { if a { 1 } }",
+        "fun f() { if a { 1 }; }",
+        "fun f() { if a { 1 }; -1 }",
+        "let m = match x { 1 | 2 => 'a', -3..=5 if x > 4 => { 'b' }, n => n, _ => 'c', };",
+        "let s = \"cat\"; let t = true; let u = false;",
+        r#"let s = "\t\"q\"\n\\ \${x} $ {}"; let c = '\''; let d = '"'; let e = '\0';"#,
+        r#"let s = "\u{1b}[1m\u{1F431}"; let c = '\u{7f}'; let d = '\u{e9}';"#,
+        r#"let s = "a ${x + 1} {b}\n$${"q ${f(y)}"}"; let t = "${x}";"#,
+        "/// Adds.\n///\n/// Twice.\npub fun add(a, b) { a + b } class Cat { /// Meows.\n fun speak() {} }",
+    ];
+    for source in programs {
+        let program = parse(source).unwrap();
+        let written = to_source(&program);
+        let reparsed = parse(&written).unwrap_or_else(|errors| {
+            panic!(
+                "{} was written as {}, failing with {:?}",
+                source, written, errors
+            )
+        });
+        assert_eq!(to_source(&reparsed), written);
+        assert_eq!(strip_spans(&reparsed), strip_spans(&program), "{}", written);
+    }
+
+    assert_eq!(
+        to_source(&parse("fun f(a,b){let c=a*(b+1);if c>1{return c;}c}").unwrap()),
+        "fun f(a, b) {\n    let c = a * (b + 1);\n    if c > 1 {\n        return c;\n    }\n    c\n}\n"
+    );
+    let expr = Expr::Binary {
+        op: BinOp::Minus,
+        left: Box::new(Expr::Literal {
+            value: Literal::Int(i64::MIN),
+            span: Span::default(),
+        }),
+        right: Box::new(Expr::Literal {
+            value: Literal::Float(f64::NAN),
+            span: Span::default(),
+        }),
+        span: Span::default(),
+    };
+    assert_eq!(
+        expr_to_source(&expr),
+        "(-9223372036854775807 - 1) - (0.0 / 0.0)"
+    );
+}
+
+/// Return the JSON form of `program` without its spans, to compare trees
+/// parsed from different sources.
+fn strip_spans(program: &[Stmt]) -> serde_json::Value {
+    fn strip(value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::Object(fields) => {
+                fields.remove("span");
+                fields.values_mut().for_each(strip);
+            }
+            serde_json::Value::Array(items) => items.iter_mut().for_each(strip),
+            _ => {}
+        }
+    }
+    let mut value = serde_json::to_value(program).unwrap();
+    strip(&mut value);
+    value
+}
@@ -0,0 +1,34 @@
+#![cfg(feature = "wasm")]
+
+use meow::playground::{evaluate, FUEL};
+
+#[test]
+fn capturing_output() {
+    let evaluation = evaluate("print(\"a\"); println([1, 2]); let line = read_line();");
+    assert_eq!(evaluation.output, "a[1, 2]\n");
+    assert!(evaluation.diagnostics.is_empty());
+}
+
+#[test]
+fn diagnostics() {
+    // Programs with errors aren't run
+    let evaluation = evaluate("println(1); let = 2;");
+    assert_eq!(evaluation.output, "");
+    let diagnostic = &evaluation.diagnostics[0];
+    assert_eq!(diagnostic.level, "error");
+    assert_eq!((diagnostic.span.line, diagnostic.span.column), (1, 17));
+    assert!(diagnostic.rendered.contains("1 | println(1); let = 2;"));
+
+    // Output printed before a program fails is kept
+    let evaluation = evaluate("println(1); [][0];");
+    assert_eq!(evaluation.output, "1\n");
+    assert_eq!(evaluation.diagnostics.len(), 1);
+    assert_eq!(evaluation.diagnostics[0].level, "error");
+}
+
+#[test]
+fn runaway_programs_stop() {
+    let evaluation = evaluate("let mut i = 0; while true { i = i + 1; }");
+    assert_eq!(evaluation.diagnostics.len(), 1);
+    assert!(evaluation.diagnostics[0].message.contains("fuel"), "{FUEL}");
+}
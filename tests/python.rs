@@ -0,0 +1,119 @@
+#![cfg(feature = "python")]
+
+use meow::{
+    python::{self, from_python, to_python},
+    run,
+    value::Value,
+    vm::Vm,
+};
+use pyo3::{
+    prelude::*,
+    types::{PyDict, PyList},
+    wrap_pymodule,
+};
+
+#[test]
+fn converting_values() {
+    Python::initialize();
+    Python::attach(|py| {
+        let mut vm = Vm::new();
+        vm.set_big_ints(true);
+        run(
+            &mut vm,
+            "fun nothing() { }
+            class Cat { fun init() {
+                self.name = \"Tom\"; self.lives = 9; self.alive = true; self.owner = nothing();
+                self.ages = [1.5, 9223372036854775807 * 2];
+            } }
+            let cat = Cat();",
+        )
+        .unwrap();
+        let cat = to_python(py, &vm, vm.global("cat").unwrap()).unwrap();
+        let cat = cat.bind(py).cast::<PyDict>().unwrap();
+        let get = |name: &str| cat.get_item(name).unwrap().unwrap();
+        assert_eq!(get("name").extract::<String>().unwrap(), "Tom");
+        assert_eq!(get("lives").extract::<i64>().unwrap(), 9);
+        assert!(get("alive").extract::<bool>().unwrap());
+        assert!(get("owner").is_none());
+        let ages = get("ages");
+        let ages = ages.cast::<PyList>().unwrap();
+        assert_eq!(ages.get_item(0).unwrap().extract::<f64>().unwrap(), 1.5);
+        assert_eq!(
+            ages.get_item(1).unwrap().str().unwrap().to_string(),
+            "18446744073709551614"
+        );
+
+        // Converting back gives an equal value
+        let value = from_python(&mut vm, cat.as_any()).unwrap();
+        vm.set_global("copy", value);
+        run(
+            &mut vm,
+            "let same = copy.name == cat.name && copy.ages[1] == cat.ages[1] && copy.owner == nothing();",
+        )
+        .unwrap();
+        assert_eq!(vm.global("same"), Some(&Value::Bool(true)));
+    });
+}
+
+#[test]
+fn unconvertible_values() {
+    Python::initialize();
+    Python::attach(|py| {
+        let mut vm = Vm::new();
+        run(&mut vm, "fun f() { } let mut l = [1]; l[0] = l;").unwrap();
+        let error = |name: &str| {
+            to_python(py, &vm, vm.global(name).unwrap())
+                .unwrap_err()
+                .value(py)
+                .to_string()
+        };
+        assert_eq!(error("f"), "cannot convert a function to a Python object");
+        assert_eq!(error("l"), "cannot convert a list that contains itself");
+
+        let object = py.eval(c"(1, 2)", None, None).unwrap();
+        assert_eq!(
+            from_python(&mut vm, &object)
+                .unwrap_err()
+                .value(py)
+                .to_string(),
+            "cannot convert a Python tuple to a Meow value"
+        );
+    });
+}
+
+#[test]
+fn python_module() {
+    Python::initialize();
+    Python::attach(|py| {
+        let globals = PyDict::new(py);
+        globals
+            .set_item("meow", wrap_pymodule!(python::meow)(py))
+            .unwrap();
+        py.run(
+            cr#"
+assert meow.eval("1 + 2") == 3
+assert meow.eval("let x = 1;") is None
+
+vm = meow.Vm()
+vm.set("cats", ["Tom", "Felix"])
+vm.eval('let greeting = "Hi, " + cats[0];')
+assert vm.get("greeting") == "Hi, Tom"
+assert vm.eval("greeting") == "Hi, Tom"
+
+try:
+    vm.get("dogs")
+    assert False
+except KeyError:
+    pass
+try:
+    vm.eval("1 / 0;")
+    assert False
+except meow.MeowError as error:
+    assert "division by zero" in str(error)
+"#,
+            Some(&globals),
+            None,
+        )
+        .unwrap();
+    });
+}
@@ -0,0 +1,132 @@
+use meow::{
+    parse, resolve,
+    resolver::{ScopeKind, SymbolKind, SymbolTable},
+};
+
+fn resolve_source(input: &str) -> SymbolTable {
+    let program = parse(input).unwrap();
+    let (table, diagnostics) = resolve(&program);
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    table
+}
+
+#[test]
+fn references() {
+    let table = resolve_source("let x = 1;\nfun f(y) { x + y }\nf(x);");
+
+    let x = &table.symbols()[0];
+    assert_eq!(x.name, "x");
+    assert_eq!(x.references.len(), 2);
+
+    let f = &table.symbols()[1];
+    assert_eq!(f.kind, SymbolKind::Function);
+    assert_eq!(f.references.len(), 1);
+    assert_eq!(f.references[0].line, 3);
+
+    let y = table.symbols().iter().find(|s| s.name == "y").unwrap();
+    assert_eq!(y.kind, SymbolKind::Parameter);
+    assert_eq!(table.scope(y.scope).kind, ScopeKind::Function);
+}
+
+#[test]
+fn hoisting() {
+    // Top-level functions can be used before their declaration
+    let table = resolve_source("fun a() { b() }\nfun b() { a() }");
+    assert!(table.unresolved().next().is_none());
+
+    // Locals can't
+    let table = resolve_source("fun a() { x; let x = 1; }");
+    assert_eq!(table.unresolved().count(), 1);
+}
+
+#[test]
+fn shadowing() {
+    let table = resolve_source("let x = 1;\n{ let x = x; x; }");
+
+    let reference = table.references().last().unwrap();
+    let inner = table.symbol(table.resolution(reference.span).unwrap());
+    assert_eq!(inner.span.line, 2);
+    assert_eq!(table.scope(inner.scope).kind, ScopeKind::Block);
+
+    // The initializer refers to the outer `x`
+    assert_eq!(table.symbols()[0].references.len(), 1);
+}
+
+#[test]
+fn unresolved() {
+    let table = resolve_source("println(\"hi\");");
+    let names: Vec<_> = table.unresolved().map(|r| r.name.as_str()).collect();
+    assert_eq!(names, vec!["println"]);
+}
+
+#[test]
+fn interpolation() {
+    // Only the expressions in an interpolated string are references, and
+    // not the `format` it is formatted with
+    let table = resolve_source("let format = 1;\nlet s = \"a ${format} b\";");
+    let format = table.symbol_at(1, 5).unwrap();
+    assert_eq!(table.symbol(format).references.len(), 1);
+    assert_eq!(table.symbol_at(2, 9), None);
+    assert_eq!(table.symbol_at(2, 14), Some(format));
+    assert!(table.unresolved().next().is_none());
+}
+
+#[test]
+fn errors() {
+    let program = parse("let x = 1;\nx = 2;\nreturn;\nself;").unwrap();
+    let (_, diagnostics) = resolve(&program);
+    assert_eq!(diagnostics.len(), 3);
+    assert!(diagnostics[0].message.contains("immutable"));
+    assert!(diagnostics[1].message.contains("`return`"));
+    assert!(diagnostics[2].message.contains("`self`"));
+}
+
+#[test]
+fn visibility() {
+    let table = resolve_source("pub fun f() {}\nlet x = 1;\npub import shapes;");
+    let public: Vec<_> = table
+        .symbols()
+        .iter()
+        .filter(|symbol| symbol.public)
+        .map(|symbol| symbol.name.as_str())
+        .collect();
+    assert_eq!(public, ["f", "shapes"]);
+
+    let program = parse("fun f() { pub let x = 1; }").unwrap();
+    let (_, diagnostics) = resolve(&program);
+    assert_eq!(
+        diagnostics[0].message,
+        "`pub` can only be used at the top level of a module"
+    );
+}
+
+#[test]
+fn positions() {
+    let table = resolve_source("let x = 1;\nprintln(x + x);");
+
+    let x = table.symbol_at(1, 5).unwrap();
+    assert_eq!(table.symbol(x).name, "x");
+    assert_eq!(table.symbol_at(2, 13), Some(x));
+    assert_eq!(table.symbol_at(2, 11), None);
+
+    // Builtins are used, but not declared
+    assert_eq!(table.symbol_at(2, 1), None);
+    let println = table.reference_at(2, 3).unwrap();
+    assert_eq!((println.name.as_str(), println.symbol), ("println", None));
+    assert_eq!(table.reference_at(2, 9).unwrap().symbol, Some(x));
+}
+
+#[test]
+fn methods() {
+    let table = resolve_source("class Cat {\n    fun speak() { return self; }\n}");
+
+    let speak = table.symbol_at(2, 9).unwrap();
+    assert_eq!(table.symbol(speak).name, "speak");
+    assert_eq!(table.symbol(speak).kind, SymbolKind::Method);
+
+    // `self` is declared for the method, but not where its name is written
+    let receiver = table.receiver(table.symbol(speak).span).unwrap();
+    assert_eq!(table.symbol(receiver).name, "self");
+    assert_eq!(table.symbol(receiver).kind, SymbolKind::Parameter);
+    assert_eq!(table.reference_at(2, 26).unwrap().symbol, Some(receiver));
+}
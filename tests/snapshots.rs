@@ -0,0 +1,104 @@
+//! Snapshot tests over the programs in `tests/snapshots`. Each `.mw` file
+//! there is checked, and what the interpreter makes of it, its diagnostics
+//! rendered as the `meow` command prints them and its syntax tree as `--ast`
+//! dumps it, is compared with the `.snap` file of the same name.
+//!
+//! To add a case, drop a `.mw` file in the directory and run the tests,
+//! which write a new snapshot next to it for review. When a change alters
+//! existing snapshots, the tests fail and write what they got to `.snap.new`
+//! files instead, and `MEOW_UPDATE_SNAPSHOTS=1 cargo test --test snapshots`
+//! accepts every change at once.
+
+use meow::{check, parse};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+/// Describe what the interpreter makes of `source`: every diagnostic
+/// [`check`] finds, then the syntax tree if it parses.
+fn snapshot(source: &str) -> String {
+    let mut out = String::from("-- diagnostics --\n");
+    for diagnostic in check(source) {
+        out.push_str(&diagnostic.render(source, false));
+    }
+    if let Ok(program) = parse(source) {
+        out.push_str("\n-- ast --\n");
+        out.push_str(&format!("{:#?}\n", program));
+    }
+    out
+}
+
+/// Return every `.mw` file in the corpus, in order.
+fn corpus() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots");
+    let mut paths: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "mw"))
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// Describe the first line that differs between `expected` and `actual`.
+fn first_difference(expected: &str, actual: &str) -> String {
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    for line in 1.. {
+        match (expected_lines.next(), actual_lines.next()) {
+            (Some(expected), Some(actual)) if expected == actual => {}
+            (None, None) => break,
+            (expected, actual) => {
+                return format!(
+                    "line {}: expected {:?}, got {:?}",
+                    line,
+                    expected.unwrap_or("the end"),
+                    actual.unwrap_or("the end")
+                )
+            }
+        }
+    }
+    "only in whitespace at the end".to_string()
+}
+
+#[test]
+fn snapshots() {
+    let update = env::var_os("MEOW_UPDATE_SNAPSHOTS").is_some_and(|value| value == "1");
+    let corpus = corpus();
+    assert!(!corpus.is_empty(), "the snapshot corpus is missing");
+
+    let mut failures = Vec::new();
+    for path in corpus {
+        let source = fs::read_to_string(&path).unwrap();
+        let actual = snapshot(&source);
+        let snap = path.with_extension("snap");
+        let new = path.with_extension("snap.new");
+
+        match fs::read_to_string(&snap) {
+            Ok(expected) if expected == actual => {
+                let _ = fs::remove_file(&new);
+            }
+            Ok(_) | Err(_) if update => {
+                fs::write(&snap, &actual).unwrap();
+                let _ = fs::remove_file(&new);
+            }
+            Ok(expected) => {
+                fs::write(&new, &actual).unwrap();
+                failures.push(format!(
+                    "{} changed, see {} ({})",
+                    snap.display(),
+                    new.display(),
+                    first_difference(&expected, &actual)
+                ));
+            }
+            // New cases are written straight away, but still fail so that
+            // they are looked at before they are committed
+            Err(_) => {
+                fs::write(&snap, &actual).unwrap();
+                failures.push(format!("{} is new, review it", snap.display()));
+            }
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
@@ -0,0 +1,50 @@
+#![cfg(feature = "fs")]
+
+use meow::spec::{run_fixture, run_suite};
+use std::path::Path;
+
+#[test]
+fn conformance() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/spec");
+    let report = run_suite(&dir).unwrap();
+    let failures: Vec<_> = report
+        .failures
+        .iter()
+        .map(|(path, failures)| format!("{}:\n  {}", path.display(), failures.join("\n  ")))
+        .collect();
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+    assert!(report.passed > 0);
+}
+
+#[test]
+fn annotations() {
+    // Fixtures fail for every way they differ from their annotations
+    assert_eq!(
+        run_fixture("let x = 1;\nx = 2;\nprint(x); //~ ERROR undefined\n//@ output: 1"),
+        [
+            "unexpected error at 2:1: cannot assign to immutable variable `x`, consider declaring it with `let mut`",
+            "expected error on line 3: undefined",
+            "expected output, but the program didn't run",
+        ]
+    );
+    assert_eq!(
+        run_fixture("let x = 1; //@ tokens: Let Ident Equal Float Semicolon"),
+        ["expected the tokens `Let Ident Equal Float Semicolon`, got `Let Ident Equal Int Semicolon`"]
+    );
+
+    // Annotations are blanked out, leaving the tokens around them in place
+    assert!(run_fixture("let x = 1 $ 2; //~ ERROR `$`\n//~^ ERROR expected `;`").is_empty());
+
+    // Annotations that can't be read fail the fixture
+    assert_eq!(
+        run_fixture("//~^ ERROR nothing"),
+        ["line 1: the annotation points above the first line"]
+    );
+    assert_eq!(
+        run_fixture("1; //~ NOTE nothing"),
+        ["line 1: expected `ERROR` or `WARNING` after `//~`"]
+    );
+
+    // Programs that don't finish are stopped
+    assert_eq!(run_fixture("while true { }").len(), 1);
+}
@@ -0,0 +1,2794 @@
+use meow::{
+    bytecode::{Function, OpCode},
+    compile,
+    errors::{InterpreterError, LoadError, RuntimeError, RuntimeErrorKind, TraceFrame},
+    parse,
+    parser::MAX_NESTING,
+    run_entry,
+    value::Value,
+    vm::{
+        env::Environment,
+        files::{FileSystem, MemoryFileSystem},
+        format::MAX_FORMAT_WIDTH,
+        heap::{GcConfig, Heap, ObjRef, MAX_DISPLAY_DEPTH},
+        io::MemoryIo,
+        isolate::SharedProgram,
+        json::MAX_JSON_DEPTH,
+        observer::{Instruction, Observer},
+        register::{lower, Instr},
+        sandbox::{Capability, SandboxPolicy},
+        Backend, Step, Vm, MAX_CALL_DEPTH,
+    },
+};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    io::{self, Write},
+    rc::Rc,
+};
+
+/// Run `input` on a new VM, returning the VM so its globals can be checked.
+/// The program is run on the register and ast backends too, which must
+/// agree, unless the ast backend can't run it.
+fn run(input: &str) -> Vm {
+    let mut vm = Vm::new();
+    let result = vm.run(compile(input).unwrap()).unwrap();
+
+    let mut registers = Vm::new();
+    registers.set_backend(Backend::Register);
+    assert_eq!(registers.run(compile(input).unwrap()).unwrap(), result);
+    assert_eq!(
+        registers.globals().iter().collect::<Vec<_>>(),
+        vm.globals().iter().collect::<Vec<_>>()
+    );
+
+    // Functions and objects differ between the backends, so values are
+    // compared by how they print
+    let mut ast = Vm::new();
+    ast.set_backend(Backend::Ast);
+    if let Some(ast_result) = run_ast(&mut ast, input).unwrap() {
+        assert_eq!(
+            ast.heap().display(&ast_result).to_string(),
+            vm.heap().display(&result).to_string()
+        );
+        assert_eq!(printed_globals(&ast), printed_globals(&vm));
+    }
+    vm
+}
+
+fn run_err(input: &str) -> RuntimeError {
+    let error = Vm::new().run(compile(input).unwrap()).unwrap_err();
+
+    let mut registers = Vm::new();
+    registers.set_backend(Backend::Register);
+    assert_eq!(registers.run(compile(input).unwrap()).unwrap_err(), error);
+
+    if let Err(message) = run_ast(&mut Vm::new(), input) {
+        assert_eq!(message, error.message);
+    }
+    error
+}
+
+/// Run `input` on the ast backend, returning the value of the program, or
+/// `None` if the backend rejects it for using generators or tasks, or the
+/// message of the error it failed with.
+fn run_ast(vm: &mut Vm, input: &str) -> Result<Option<Value>, String> {
+    vm.set_backend(Backend::Ast);
+    match meow::run(vm, input) {
+        Ok(value) => Ok(Some(value)),
+        Err(InterpreterError::Failed { diagnostics, .. })
+            if diagnostics.iter().all(|diagnostic| {
+                diagnostic
+                    .message
+                    .ends_with("not supported by the ast backend")
+            }) =>
+        {
+            Ok(None)
+        }
+        Err(InterpreterError::Failed { diagnostics, .. }) => Err(diagnostics[0].message.clone()),
+        Err(error) => panic!("expected a runtime error, found {}", error),
+    }
+}
+
+/// Return the name of every global of `vm` and how its value prints, in
+/// alphabetical order.
+fn printed_globals(vm: &Vm) -> Vec<(String, String)> {
+    let mut globals: Vec<_> = vm
+        .globals()
+        .iter()
+        .map(|(name, value)| (name.to_string(), vm.heap().display(value).to_string()))
+        .collect();
+    globals.sort();
+    globals
+}
+
+#[test]
+fn arithmetic() {
+    let vm = run("let a = 1 + 2 * 3; let b = -(7.5 / 2.5); let c = 3 >= 2; let d = !(1 == 1.5);");
+    assert_eq!(vm.global("a"), Some(&Value::Int(7)));
+    assert_eq!(vm.global("b"), Some(&Value::Float(-3.0)));
+    assert_eq!(vm.global("c"), Some(&Value::Bool(true)));
+    assert_eq!(vm.global("d"), Some(&Value::Bool(true)));
+
+    // Remainders have the sign of the dividend, like division rounds
+    // towards zero
+    let vm = run("
+        let ints = 7 % 3 + -7 % 3 * 10 + 7 % -3 * 100;
+        let floats = 7.5 % 2;
+        let mut compound = 10;
+        compound %= 4;
+        let precedence = 1 + 10 % 4 * 3;
+    ");
+    assert_eq!(vm.global("ints"), Some(&Value::Int(91)));
+    assert_eq!(vm.global("floats"), Some(&Value::Float(1.5)));
+    assert_eq!(vm.global("compound"), Some(&Value::Int(2)));
+    assert_eq!(vm.global("precedence"), Some(&Value::Int(7)));
+
+    assert_eq!(run_err("1 / 0;").message, "division by zero");
+    assert_eq!(run_err("1 % 0;").message, "division by zero");
+    assert_eq!(
+        run_err("\"a\" * 2.5;").message,
+        "unsupported operand types for `*`: string and float"
+    );
+}
+
+#[test]
+fn numbers() {
+    // Ints and floats mix in arithmetic, which gives floats, and compare
+    // exactly, without rounding the int
+    let vm = run("
+        let sum = 1 + 0.5;
+        let quotient = 7 / 2.0;
+        let ints = 7 / 2;
+        let equal = 1 == 1.0 && 2.0 == 2 && 1 != 1.5;
+        let ordered = 1 < 1.5 && -1 > -1.5 && 2 >= 2.0 && 9007199254740993 > 9007199254740992.0;
+        let unordered = { let nan = 0.0 / 0.0; !(1 < nan) && !(1 >= nan) && 1 != nan };
+        let zero_sum = 0 + 0.5;
+        let zero_product = 0.5 * 0;
+        let zero_ordered = 0 < 0.5 && -0.5 < 0 && 0 == 0.0 && 0 == -0.0 && 0.0 <= 0;
+    ");
+    assert_eq!(vm.global("sum"), Some(&Value::Float(1.5)));
+    assert_eq!(vm.global("quotient"), Some(&Value::Float(3.5)));
+    assert_eq!(vm.global("ints"), Some(&Value::Int(3)));
+    assert_eq!(vm.global("equal"), Some(&Value::Bool(true)));
+    assert_eq!(vm.global("ordered"), Some(&Value::Bool(true)));
+    assert_eq!(vm.global("unordered"), Some(&Value::Bool(true)));
+    assert_eq!(vm.global("zero_sum"), Some(&Value::Float(0.5)));
+    assert_eq!(vm.global("zero_product"), Some(&Value::Float(0.0)));
+    assert_eq!(vm.global("zero_ordered"), Some(&Value::Bool(true)));
+
+    // Ints can be written in hexadecimal, octal and binary, floats with
+    // exponents, and digits can be separated by underscores
+    let vm = run("
+        let hex = 0x7FFFFFFFFFFFFFFF - 0x7fffffffffffff00 + 0xff;
+        let octal = 0o17;
+        let binary = 0b10_10;
+        let separated = 1_000_000 + 0.000_5;
+        let scientific = 1e3 + 2.5e-1 + 1E+1;
+        let matched = match -0x10 { -16 => true, _ => false };
+    ");
+    assert_eq!(vm.global("hex"), Some(&Value::Int(510)));
+    assert_eq!(vm.global("octal"), Some(&Value::Int(15)));
+    assert_eq!(vm.global("binary"), Some(&Value::Int(10)));
+    assert_eq!(vm.global("separated"), Some(&Value::Float(1_000_000.000_5)));
+    assert_eq!(vm.global("scientific"), Some(&Value::Float(1010.25)));
+    assert_eq!(vm.global("matched"), Some(&Value::Bool(true)));
+
+    // Casts truncate floats towards zero
+    let vm = run("
+        let a = 2.9 as int;
+        let b = -2.9 as int;
+        let c = 3 as float;
+        let d = true as int + 'a' as int;
+        let e = 1.5 as float;
+        let f = 10 / 4 as float;
+    ");
+    assert_eq!(vm.global("a"), Some(&Value::Int(2)));
+    assert_eq!(vm.global("b"), Some(&Value::Int(-2)));
+    assert_eq!(vm.global("c"), Some(&Value::Float(3.0)));
+    assert_eq!(vm.global("d"), Some(&Value::Int(98)));
+    assert_eq!(vm.global("e"), Some(&Value::Float(1.5)));
+    assert_eq!(vm.global("f"), Some(&Value::Float(2.5)));
+
+    assert_eq!(
+        run_err("\"1\" as int;").message,
+        "cannot cast a value of type string to int"
+    );
+    assert_eq!(
+        run_err("'a' as float;").message,
+        "cannot cast a value of type char to float"
+    );
+    assert_eq!(
+        run_err("(0.0 / 0.0) as int;").message,
+        "cannot cast NaN to int"
+    );
+    assert_eq!(
+        run_err("10000000000000000000.0 as int;").message,
+        "integer overflow: 1e19 as int"
+    );
+
+    // Big ints convert to and from floats too
+    let mut vm = Vm::new();
+    vm.set_big_ints(true);
+    vm.run(
+        compile("let big = 10000000000000000000.0 as int; let back = big as float; let more = big > back;")
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        vm.global("big").unwrap().to_string(),
+        "10000000000000000000"
+    );
+    assert_eq!(vm.global("back"), Some(&Value::Float(1e19)));
+    assert_eq!(vm.global("more"), Some(&Value::Bool(false)));
+}
+
+#[test]
+fn integer_overflow() {
+    let max = "let max = 9223372036854775807; let min = -max - 1;";
+    for (source, operation) in [
+        ("max + 1;", "9223372036854775807 + 1"),
+        ("min - 1;", "-9223372036854775808 - 1"),
+        ("max * 2;", "9223372036854775807 * 2"),
+        ("min / -1;", "-9223372036854775808 / -1"),
+        ("-min;", "-(-9223372036854775808)"),
+    ] {
+        assert_eq!(
+            run_err(&format!("{} {}", max, source)).message,
+            format!("integer overflow: {}", operation)
+        );
+    }
+
+    // With big ints on, results that don't fit are promoted, and turned
+    // back into ints once they fit again
+    let source = "
+        let max = 9223372036854775807;
+        let min = -max - 1;
+        fun factorial(n) { if n == 0 { 1 } else { n * factorial(n - 1) } }
+        let big = factorial(30);
+        let back = big / factorial(29);
+        let quotient = big / (max + 2);
+        let negated = -(min - 1);
+        let fits = min - 1 + 1;
+        let ordered = big > max && -big < min && big == factorial(30) && big != -big;
+        let difference = (max + 1) - (max + 1);
+        let remainder = big % (max + 2);
+        let unchanged = min % -1;
+    ";
+    for backend in [Backend::Stack, Backend::Register] {
+        let mut vm = Vm::new();
+        vm.set_backend(backend);
+        vm.set_big_ints(true);
+        vm.run(compile(source).unwrap()).unwrap();
+        let global = |name| vm.global(name).unwrap().to_string();
+        assert_eq!(global("big"), "265252859812191058636308480000000");
+        assert_eq!(vm.global("back"), Some(&Value::Int(30)));
+        assert_eq!(global("quotient"), "28758772686637");
+        assert_eq!(global("negated"), "9223372036854775809");
+        assert_eq!(global("remainder"), "458764309234835667");
+        assert_eq!(vm.global("unchanged"), Some(&Value::Int(0)));
+        assert_eq!(vm.global("fits"), Some(&Value::Int(i64::MIN)));
+        assert_eq!(vm.global("ordered"), Some(&Value::Bool(true)));
+        assert_eq!(vm.global("difference"), Some(&Value::Int(0)));
+        assert_eq!(vm.global("big").unwrap().type_name(), "int");
+
+        // Big ints survive snapshots, and still can't be made once big ints
+        // are turned off
+        let mut restored = Vm::new();
+        restored.set_backend(backend);
+        restored.restore(&vm.snapshot().unwrap()).unwrap();
+        assert_eq!(restored.global("big"), vm.global("big"));
+        restored
+            .run(compile("let one = big / big;").unwrap())
+            .unwrap();
+        assert_eq!(restored.global("one"), Some(&Value::Int(1)));
+        let error = restored.run(compile("big * 2;").unwrap()).unwrap_err();
+        assert_eq!(
+            error.message,
+            "integer overflow: 265252859812191058636308480000000 * 2"
+        );
+        let error = restored.run(compile("[1][big];").unwrap()).unwrap_err();
+        assert_eq!(
+            error.message,
+            "index 265252859812191058636308480000000 is out of bounds for a list of length 1"
+        );
+    }
+}
+
+#[test]
+fn globals() {
+    let vm = run("let mut x = 1; x += 2; let y = x * 2;");
+    assert_eq!(vm.global("x"), Some(&Value::Int(3)));
+    assert_eq!(vm.global("y"), Some(&Value::Int(6)));
+
+    // Globals persist between runs, and can be defined by the host
+    let mut vm = Vm::new();
+    vm.set_global("z", Value::Int(10));
+    vm.run(compile("let w = z + 1;").unwrap()).unwrap();
+    vm.run(compile("let v = w + 1;").unwrap()).unwrap();
+    assert_eq!(vm.global("v"), Some(&Value::Int(12)));
+}
+
+#[test]
+fn undefined_globals() {
+    let error = run_err("let a = 1;\nlet b = a + missing;");
+    assert_eq!(error.message, "undefined variable `missing`");
+    let span = error.span.unwrap();
+    assert_eq!((span.line, span.column, span.length), (2, 13, 7));
+
+    let error = run_err("missing = 1;");
+    assert_eq!(error.message, "undefined variable `missing`");
+}
+
+#[test]
+fn functions() {
+    let vm = run("fun add(a, b) { a + b }\nfun twice(x) { return add(x, x); }\nlet r = twice(21);");
+    assert_eq!(vm.global("r"), Some(&Value::Int(42)));
+
+    let error = run_err("fun f(a) { a }\nf(1, 2);");
+    assert_eq!(error.message, "`f` expects 1 argument, but 2 were given");
+    assert_eq!(error.span.unwrap().line, 2);
+
+    let error = run_err("let x = 1;\nx();");
+    assert_eq!(error.message, "cannot call a value of type int");
+}
+
+#[test]
+fn call_depth() {
+    let error = run_err("fun down(n) { down(n + 1) }\ndown(0);");
+    assert_eq!(
+        error.message,
+        "maximum call depth exceeded: `<script>` -> `down` (1024 calls)"
+    );
+    assert_eq!(error.span.unwrap().line, 1);
+
+    // Long chains of calls to different functions are shortened
+    let error = run_err("fun a() { b() } fun b() { a() } a();");
+    assert_eq!(
+        error.message,
+        "maximum call depth exceeded: `<script>` -> `a` -> `b` -> `a` -> ... -> `a` -> `b` -> `a` -> `b`"
+    );
+
+    // Deep recursion that stops in time is fine
+    let vm = run("fun depth(n) { if n == 0 { 0 } else { 1 + depth(n - 1) } } let d = depth(1000);");
+    assert_eq!(vm.global("d"), Some(&Value::Int(1000)));
+}
+
+#[test]
+fn traces() {
+    let error = run_err("fun inner(x) {\n  x / 0\n}\nfun outer() { inner(1) }\nouter();");
+    let trace: Vec<_> = error
+        .trace
+        .iter()
+        .map(|TraceFrame { function, span }| {
+            let span = span.unwrap();
+            (function.as_str(), span.line, span.column)
+        })
+        .collect();
+    assert_eq!(
+        trace,
+        vec![("inner", 2, 3), ("outer", 4, 15), ("<script>", 5, 1)]
+    );
+
+    let diagnostic = error.to_diagnostic(Some("main.meow"));
+    assert_eq!(
+        diagnostic.notes,
+        vec![
+            "in `inner` at main.meow:2:3",
+            "called from `outer` at main.meow:4:15",
+            "called from `<script>` at main.meow:5:1",
+        ]
+    );
+
+    // Long traces leave out their middle
+    let error = run_err("fun down(n) { down(n + 1) } down(0);");
+    assert_eq!(error.trace.len(), 1024);
+    let notes = error.to_diagnostic(None).notes;
+    assert_eq!(notes.len(), 11);
+    assert_eq!(notes[5], "... 1014 more calls");
+    assert_eq!(notes[10], "called from `<script>` at 1:29");
+}
+
+#[test]
+fn type_errors() {
+    let error = run_err("1 + true;");
+    assert_eq!(
+        error.message,
+        "unsupported operand types for `+`: int and bool"
+    );
+    assert_eq!(
+        run_err("-true;").message,
+        "cannot negate a value of type bool"
+    );
+}
+
+#[test]
+fn locals() {
+    let vm = run("let x = { let a = 1; let mut b = a + 1; b *= 10; { let a = 5; b + a } };");
+    assert_eq!(vm.global("x"), Some(&Value::Int(25)));
+
+    let vm = run("fun f(n) { let double = n * 2; { let n = double; n + 1 } }\nlet r = f(4);");
+    assert_eq!(vm.global("r"), Some(&Value::Int(9)));
+
+    // Locals declared above temporaries, such as earlier list items, get
+    // the slot their value is in
+    let vm = run("fun f() { [1, 2, { let x = 5; x + 1 }] }\nlet a = f()[2]; let b = [7, { let y = 3; y }][1];");
+    assert_eq!(vm.global("a"), Some(&Value::Int(6)));
+    assert_eq!(vm.global("b"), Some(&Value::Int(3)));
+}
+
+#[test]
+fn control_flow() {
+    let vm = run("let a = if 1 > 2 { 1 } else if 2 > 1 { 2 } else { 3 }; let b = if false { 1 };");
+    assert_eq!(vm.global("a"), Some(&Value::Int(2)));
+    assert_eq!(vm.global("b"), Some(&Value::Unit));
+
+    let vm = run("let mut n = 0; let mut i = 0; while i < 10 { i += 1; n += i; }");
+    assert_eq!(vm.global("n"), Some(&Value::Int(55)));
+
+    let vm =
+        run("let mut n = 0; for i in 0..10 { n += i; } let mut m = 0; for i in 1..=4 { m += i; }");
+    assert_eq!(vm.global("n"), Some(&Value::Int(45)));
+    assert_eq!(vm.global("m"), Some(&Value::Int(10)));
+
+    // Functions can loop over their locals
+    let vm = run(
+        "fun sum(n) { let mut total = 0; for i in 0..n { total += i; } total } let s = sum(5);",
+    );
+    assert_eq!(vm.global("s"), Some(&Value::Int(10)));
+
+    assert_eq!(
+        run_err("if 1 { }").message,
+        "expected a bool condition, found a value of type int"
+    );
+}
+
+#[test]
+fn matching() {
+    let source = "
+        fun dense(n) {
+            match n {
+                1 | 2 => \"small\",
+                3..=5 => \"medium\",
+                6..8 => \"large\",
+                9 => \"nine\",
+                other => other,
+            }
+        }
+        fun sparse(n) {
+            match n {
+                -1 => \"minus one\",
+                1000 => \"thousand\",
+                \"a\" | 'b' => \"letter\",
+                true | false => \"bool\",
+                x if x < 0 => \"negative\",
+                _ => \"unknown\",
+            }
+        }
+        println([dense(0), dense(2), dense(3.0), dense(5), dense(7), dense(9), dense(8), dense(2.5), dense(\"s\")]);
+        println([sparse(-1), sparse(1000), sparse(-5), sparse(\"a\"), sparse('b'), sparse(true), sparse(1)]);
+        println([1, match 2 { 1 => 1, n if n > 1 => { let d = n * 2; d }, _ => 0 }]);
+        println([match 2.5 { 0 => \"zero\", _ => \"other\" }, match 0.0 { 0 => \"zero\", _ => \"other\" }]);
+    ";
+    assert_eq!(
+        printed(source),
+        "[0, \"small\", \"medium\", \"medium\", \"large\", \"nine\", 8, 2.5, \"s\"]\n\
+         [\"minus one\", \"thousand\", \"negative\", \"letter\", \"letter\", \"bool\", \"unknown\"]\n\
+         [1, 4]\n\
+         [\"other\", \"zero\"]\n"
+    );
+
+    let error = run_err("let x = 7;\nmatch x { 1 => 1, 2 => 2, 3 => 3, 4 => 4 };");
+    assert_eq!(error.message, "no match arm matches 7");
+    assert_eq!(error.span.unwrap().line, 2);
+    assert_eq!(
+        run_err("match \"a\" { \"b\" => 1, s if s == \"c\" => 2 };").message,
+        "no match arm matches a"
+    );
+}
+
+#[test]
+fn short_circuit() {
+    // The right operand isn't evaluated if the left decides the result
+    let vm = run("let a = false && undefined; let b = true || undefined; let c = true && 1 < 2;");
+    assert_eq!(vm.global("a"), Some(&Value::Bool(false)));
+    assert_eq!(vm.global("b"), Some(&Value::Bool(true)));
+    assert_eq!(vm.global("c"), Some(&Value::Bool(true)));
+
+    // Both operands must be bools, so the result always is one
+    for (source, ty) in [
+        ("true && [1];", "list"),
+        ("false || 3;", "int"),
+        ("1 && 2;", "int"),
+    ] {
+        assert_eq!(
+            run_err(source).message,
+            format!("expected a bool condition, found a value of type {}", ty)
+        );
+    }
+    let error = run_err("let xs = [1];\nlet a = true &&\n  xs;");
+    assert_eq!(
+        error.message,
+        "expected a bool condition, found a value of type list"
+    );
+    assert_eq!(error.span.unwrap().line, 3);
+    let mut ast = Vm::new();
+    ast.set_backend(Backend::Ast);
+    assert!(meow::run(&mut ast, "let a = false || 3;").is_err());
+}
+
+#[test]
+fn lists() {
+    let vm = run("let mut a = [1, [2, 3]]; a[1][0] = a[0] + 10; let b = a[1][0]; let c = [];");
+    assert_eq!(vm.global("b"), Some(&Value::Int(11)));
+    assert_eq!(vm.heap().len(), 3);
+
+    assert_eq!(
+        run_err("[1][1];").message,
+        "index 1 is out of bounds for a list of length 1"
+    );
+    assert_eq!(
+        run_err("[1][-1];").message,
+        "index -1 is out of bounds for a list of length 1"
+    );
+    assert_eq!(
+        run_err("[1][true];").message,
+        "list indices must be ints, found a value of type bool"
+    );
+    assert_eq!(
+        run_err("1[0];").message,
+        "cannot index into a value of type int"
+    );
+}
+
+#[test]
+fn strings() {
+    let vm = run(r#"
+        let joined = "meo" + "w";
+        let ordered = "apple" < "banana" && "b" > "abc" && "cat" <= "cat" && "" < "a";
+        let equal = "cat" == "ca" + "t" && "cat" != "dog";
+        let chars = 'a' < 'b' && 'z' >= 'z';
+    "#);
+    assert_eq!(vm.global("joined"), Some(&Value::from("meow")));
+    assert_eq!(vm.global("ordered"), Some(&Value::Bool(true)));
+    assert_eq!(vm.global("equal"), Some(&Value::Bool(true)));
+    assert_eq!(vm.global("chars"), Some(&Value::Bool(true)));
+
+    // Strings and chars can contain escape sequences
+    let vm = run(r#"
+        let escaped = "a\tb\n\"c\" \\ \${d}";
+        let quote = '\'';
+        let newline = '\n';
+        let cat = "\u{1F431}\u{2764}";
+        let accent = '\u{E9}';
+    "#);
+    assert_eq!(
+        vm.global("escaped"),
+        Some(&Value::from("a\tb\n\"c\" \\ ${d}"))
+    );
+    assert_eq!(vm.global("quote"), Some(&Value::Char('\'')));
+    assert_eq!(vm.global("newline"), Some(&Value::Char('\n')));
+    assert_eq!(vm.global("cat"), Some(&Value::from("\u{1F431}\u{2764}")));
+    assert_eq!(vm.global("accent"), Some(&Value::Char('é')));
+
+    // Strings are indexed and sliced by character, not by byte
+    let vm = run(r#"
+        let s = "héllo";
+        let first = s[0];
+        let second = s[1];
+        let middle = s[1..4];
+        let end = s[1..=4];
+        let empty = s[5..5];
+    "#);
+    assert_eq!(vm.global("first"), Some(&Value::Char('h')));
+    assert_eq!(vm.global("second"), Some(&Value::Char('é')));
+    assert_eq!(vm.global("middle"), Some(&Value::from("éll")));
+    assert_eq!(vm.global("end"), Some(&Value::from("éllo")));
+    assert_eq!(vm.global("empty"), Some(&Value::from("")));
+
+    // Slicing a list makes a new list
+    let vm = run("let a = [1, 2, 3]; let b = a[1..3]; let c = a[0..2][1];");
+    assert_eq!(vm.global("c"), Some(&Value::Int(2)));
+
+    assert_eq!(
+        run_err(r#""héllo"[5];"#).message,
+        "index 5 is out of bounds for a string of length 5"
+    );
+    assert_eq!(
+        run_err(r#""abc"[1..4];"#).message,
+        "slice 1..4 is out of bounds for a string of length 3"
+    );
+    assert_eq!(
+        run_err("[1, 2][2..1];").message,
+        "slice 2..1 starts after it ends"
+    );
+    assert_eq!(
+        run_err(r#""abc"["a"];"#).message,
+        "string indices must be ints, found a value of type string"
+    );
+    assert_eq!(
+        run_err(r#"let s = "abc"; s[0] = 'b';"#).message,
+        "strings can't be changed"
+    );
+    assert_eq!(
+        run_err("1[0..1];").message,
+        "cannot slice a value of type int"
+    );
+    assert_eq!(
+        run_err(r#""a" - "b";"#).message,
+        "unsupported operand types for `-`: string and string"
+    );
+    assert_eq!(
+        run_err(r#""a" + 1;"#).message,
+        "unsupported operand types for `+`: string and int"
+    );
+}
+
+/// Output shared with a VM, so that tests can read what it printed.
+#[derive(Clone, Default)]
+struct Output(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Run `source` on every backend, returning what it printed, which must be
+/// the same on each.
+fn printed(source: &str) -> String {
+    let outputs: Vec<_> = [Backend::Stack, Backend::Register, Backend::Ast]
+        .into_iter()
+        .map(|backend| {
+            let output = Output::default();
+            let mut vm = Vm::new();
+            vm.set_backend(backend);
+            vm.set_output(Box::new(output.clone()));
+            meow::run(&mut vm, source).unwrap();
+            String::from_utf8(output.0.take()).unwrap()
+        })
+        .collect();
+    assert!(outputs.iter().all(|output| *output == outputs[0]));
+    outputs[0].clone()
+}
+
+#[test]
+fn deep_nesting() {
+    // Programs as deeply nested as the parser allows run on every backend
+    let depth = MAX_NESTING - 2;
+    let source = format!(
+        "println({}1{}); println({}{});",
+        "(".repeat(depth),
+        ")".repeat(depth),
+        "[".repeat(depth),
+        "]".repeat(depth)
+    );
+    assert_eq!(
+        printed(&source),
+        format!("1\n{}{}\n", "[".repeat(depth), "]".repeat(depth))
+    );
+}
+
+#[test]
+fn tracing() {
+    // Every instruction is traced after the values in its frame's slots
+    let source = "fun f(a) { a * 2 }\nlet x = f(3);";
+    let output = Output::default();
+    let mut vm = Vm::new();
+    vm.set_tracer(Some(Box::new(output.clone())));
+    vm.run(compile(source).unwrap()).unwrap();
+    let trace = String::from_utf8(output.0.take()).unwrap();
+    let expected = [
+        "[ <fun <script>> ]",
+        "0000    1 Constant            0 '<fun f>'",
+        "[ <fun <script>> ][ <fun f> ]",
+        "0003    | DefineGlobal        1 'f'",
+        "[ <fun <script>> ]",
+        "0006    2 GetGlobal           1 'f'",
+        "[ <fun <script>> ][ <fun f> ]",
+        "0009    | Constant            2 '3'",
+        "[ <fun <script>> ][ <fun f> ][ 3 ]",
+        "0012    | Call                1",
+        "[ <fun f> ][ 3 ]",
+        "0000    1 GetLocal            1",
+        "[ <fun f> ][ 3 ][ 3 ]",
+        "0002    | Constant            0 '2'",
+        "[ <fun f> ][ 3 ][ 3 ][ 2 ]",
+        "0005    | Multiply",
+        "[ <fun f> ][ 3 ][ 6 ]",
+        "0006    | Return",
+        "[ <fun <script>> ][ 6 ]",
+        "0014    | DefineGlobal        3 'x'",
+        "[ <fun <script>> ]",
+        "0017    | Unit",
+        "[ <fun <script>> ][ () ]",
+        "0018    | Return",
+    ];
+    let lines: Vec<_> = trace.lines().map(str::trim).collect();
+    assert_eq!(lines, expected);
+
+    // The register backend traces its own instructions and registers
+    let output = Output::default();
+    let mut vm = Vm::new();
+    vm.set_backend(Backend::Register);
+    vm.set_tracer(Some(Box::new(output.clone())));
+    vm.run(compile(source).unwrap()).unwrap();
+    let trace = String::from_utf8(output.0.take()).unwrap();
+    assert!(
+        trace.contains("[ <fun <script>> ][ <fun f> ][ 3 ]\n0004 Call { callee: 1, argc: 1 }\n")
+    );
+    assert!(trace.contains("0002 Binary { op: Multiply, dst: 2, left: 2, right: 3 }\n"));
+
+    // Nothing is traced once the tracer is removed
+    vm.set_tracer(None);
+    vm.run(compile("let y = 1;").unwrap()).unwrap();
+    assert!(output.0.borrow().is_empty());
+}
+
+#[test]
+fn printing() {
+    assert_eq!(
+        printed(r#"print("Hello, "); println("world!"); println(1); println(2.0);"#),
+        "Hello, world!\n1\n2.0\n"
+    );
+    assert_eq!(
+        printed(r#"println([1, "a", 'b', [true, {}]]); println('c');"#),
+        "[1, \"a\", 'b', [true, ()]]\nc\n"
+    );
+    assert_eq!(
+        printed(
+            "
+            class Point { fun init(x, y) { self.x = x; self.y = y; } fun norm() { 0 } }
+            class Empty {}
+            let p = Point(1, [2]);
+            println(p);
+            println(Empty());
+            println(p.norm);
+            println(Point);
+            fun f() { }
+            println(f);
+            println(println);
+            "
+        ),
+        "Point { x: 1, y: [2] }\nEmpty {}\n<method norm>\n<class Point>\n<fun f>\n<native fun println>\n"
+    );
+    assert_eq!(
+        printed("let mut a = [1]; let b = [a]; a[0] = b; println(a);"),
+        "[[[...]]]\n"
+    );
+
+    // Deeply nested objects are cut off rather than overflowing the stack
+    let output = printed("let mut x = [1]; for i in 0..100000 { x = [x]; } println(x);");
+    let depth = MAX_DISPLAY_DEPTH;
+    assert_eq!(
+        output,
+        format!("{}[...]{}\n", "[".repeat(depth), "]".repeat(depth))
+    );
+}
+
+#[test]
+fn entries() {
+    for backend in [Backend::Stack, Backend::Register, Backend::Ast] {
+        let mut vm = Vm::new();
+        vm.set_backend(backend);
+        assert_eq!(run_entry(&mut vm, "fun double(x) { x * 2 }").unwrap(), None);
+        assert_eq!(run_entry(&mut vm, "let x = double(4); x").unwrap(), None);
+        assert_eq!(
+            run_entry(&mut vm, "double(x) + 1").unwrap(),
+            Some(Value::Int(17))
+        );
+        assert_eq!(run_entry(&mut vm, "println(x)").unwrap(), Some(Value::Unit));
+
+        // Errors are reported at the columns they were typed at
+        let message = |error| match error {
+            Err(InterpreterError::Failed { diagnostics, .. }) => {
+                let span = diagnostics[0].span;
+                (diagnostics[0].message.clone(), span.column)
+            }
+            result => panic!("expected an error, found {:?}", result),
+        };
+        assert_eq!(
+            message(run_entry(&mut vm, "1 + y")),
+            ("undefined variable `y`".to_string(), 5)
+        );
+        assert_eq!(
+            message(run_entry(&mut vm, "let z = ;")),
+            ("expected expression, found Semicolon".to_string(), 9)
+        );
+    }
+}
+
+#[test]
+fn reading_lines() {
+    let source = r#"
+        let name = read_line("Name {}: ", 1);
+        let second = read_line();
+        let done = read_line();
+        println("Hi, {}! {} {}", name, second, done);
+    "#;
+    for backend in [Backend::Stack, Backend::Register, Backend::Ast] {
+        let output = Output::default();
+        let mut vm = Vm::new();
+        vm.set_backend(backend);
+        vm.set_output(Box::new(output.clone()));
+        vm.set_input(Box::new(io::Cursor::new("Tom\r\nGinger\n")));
+        meow::run(&mut vm, source).unwrap();
+        assert_eq!(
+            String::from_utf8(output.0.take()).unwrap(),
+            "Name 1: Hi, Tom! Ginger ()\n"
+        );
+    }
+
+    let mut vm = Vm::new();
+    vm.set_input(Box::new(io::Cursor::new(vec![0xff, b'\n'])));
+    let error = vm.run(compile("read_line();").unwrap()).unwrap_err();
+    assert_eq!(
+        error.message,
+        "cannot read a line: stream did not contain valid UTF-8"
+    );
+}
+
+#[test]
+fn memory_io() {
+    let source = r#"
+        import std.fs;
+        let name = read_line("Name: ");
+        fs.write("name.txt", name);
+        println(fs.read_to_string("cats.txt") + fs.read_to_string("name.txt"));
+        let done = read_line();
+    "#;
+    for backend in [Backend::Stack, Backend::Register, Backend::Ast] {
+        let io = MemoryIo::new()
+            .with_input("Tom\r\n")
+            .with_file("cats.txt", "Felix, ");
+        let mut vm = Vm::new();
+        vm.set_backend(backend);
+        vm.set_io(Box::new(io.clone()));
+        meow::run(&mut vm, source).unwrap();
+        assert_eq!(io.output(), "Name: Felix, Tom\n");
+        assert_eq!(vm.global("done"), Some(&Value::Unit));
+        assert_eq!(io.file_system().paths(), ["cats.txt", "name.txt"]);
+        assert_eq!(io.file_system().get("name.txt").as_deref(), Some("Tom"));
+    }
+
+    // Setting one part of the I/O goes back to the default for the others
+    let io = MemoryIo::new()
+        .with_input("Tom\n")
+        .with_file("cats.txt", "Felix")
+        .deny_files();
+    let mut vm = Vm::new();
+    vm.set_io(Box::new(io.clone()));
+    let script = compile("import std.fs; fs.read_to_string(\"cats.txt\");").unwrap();
+    assert_eq!(
+        vm.run(script).unwrap_err().message,
+        "cannot read `cats.txt`: file access is disabled"
+    );
+    let output = Output::default();
+    vm.set_output(Box::new(output.clone()));
+    vm.set_input(Box::new(io::Cursor::new("Ginger\n")));
+    meow::run(&mut vm, "println(read_line());").unwrap();
+    assert_eq!(output.0.take(), b"Ginger\n");
+    assert_eq!(io.remaining_input(), "Tom\n");
+}
+
+#[test]
+fn formatting() {
+    let vm = run(r#"
+        let plain = format("{} has {} lives", "Tom", 9);
+        let braces = format("{{}} {}", [1, 2]);
+        let widths = format("[{:5}] [{:5}] [{:<5}] [{:^7}] [{:*>4}]", "ab", 42, 7, "mid", 1);
+        let precise = format("{:.2} {:.1} {:8.3} {:.3}", 3.14159, 2, -1.5, "meow!");
+        let zeros = format("{:05} {:06.2}", -42, 3.5);
+        let none = format("no placeholders");
+    "#);
+    assert_eq!(vm.global("plain"), Some(&Value::from("Tom has 9 lives")));
+    assert_eq!(vm.global("braces"), Some(&Value::from("{} [1, 2]")));
+    assert_eq!(
+        vm.global("widths"),
+        Some(&Value::from("[ab   ] [   42] [7    ] [  mid  ] [***1]"))
+    );
+    assert_eq!(
+        vm.global("precise"),
+        Some(&Value::from("3.14 2.0   -1.500 meo"))
+    );
+    assert_eq!(vm.global("zeros"), Some(&Value::from("-0042 003.50")));
+    assert_eq!(vm.global("none"), Some(&Value::from("no placeholders")));
+
+    // Interpolated strings are formatted, with their braces kept as they are
+    let vm = run(r#"
+        let name = "Tom";
+        let greeting = "hello ${name}!";
+        let sum = "${1} + ${1 + 1} = ${[1, 2][0] + 2} {}";
+        let nested = "${ "<${ if true { name } else { "" } }>" }";
+    "#);
+    assert_eq!(vm.global("greeting"), Some(&Value::from("hello Tom!")));
+    assert_eq!(vm.global("sum"), Some(&Value::from("1 + 2 = 3 {}")));
+    assert_eq!(vm.global("nested"), Some(&Value::from("<Tom>")));
+
+    // Interpolation doesn't go through the `format` global, so bindings of
+    // that name don't change it
+    let vm = run(r#"
+        let format = 3;
+        let shadowed = "a ${format} b";
+        fun f(format) { "x ${1} y ${format + 1}" }
+        let parameter = f(10);
+    "#);
+    assert_eq!(vm.global("shadowed"), Some(&Value::from("a 3 b")));
+    assert_eq!(vm.global("parameter"), Some(&Value::from("x 1 y 11")));
+
+    // Only `print` and `println` calls with more than one argument format
+    assert_eq!(
+        printed(r#"println("{} + {} = {:.1}", 1, 2, 3); print("{}"); println("!", );"#),
+        "1 + 2 = 3.0\n{}!\n"
+    );
+
+    // Widths and precisions are limited, so formatting can't use up memory
+    let vm = run(&format!(
+        r#"let padded = format("{{:{0}}}", 1).len(); let digits = format("{{:.{0}}}", 1.5).len();"#,
+        MAX_FORMAT_WIDTH
+    ));
+    assert_eq!(
+        vm.global("padded"),
+        Some(&Value::Int(MAX_FORMAT_WIDTH as i64))
+    );
+    assert_eq!(
+        vm.global("digits"),
+        Some(&Value::Int(MAX_FORMAT_WIDTH as i64 + 2))
+    );
+
+    for (source, message) in [
+        (
+            r#"format("{} {}", 1);"#,
+            "format string has 2 placeholders, but 1 argument was given",
+        ),
+        (
+            r#"format("{}", 1, 2);"#,
+            "format string has 1 placeholder, but 2 arguments were given",
+        ),
+        (r#"format("{");"#, "unmatched `{` in format string"),
+        (r#"format("}");"#, "unmatched `}` in format string"),
+        (r#"format("{:x}", 1);"#, "invalid format specifier `{:x}`"),
+        (r#"format("{0}", 1);"#, "invalid format specifier `{0}`"),
+        (
+            r#"format("{:.99999999999}", 1.0);"#,
+            "invalid format specifier `{:.99999999999}`",
+        ),
+        (
+            r#"format("{:99999999999}", 1);"#,
+            "invalid format specifier `{:99999999999}`",
+        ),
+        (
+            r#"format("{:1025}", 1);"#,
+            "invalid format specifier `{:1025}`",
+        ),
+        (
+            "format(1);",
+            "`format` expects a string, found a value of type int",
+        ),
+        (
+            "format();",
+            "`format` expects at least 1 argument, but 0 were given",
+        ),
+        (
+            "println();",
+            "`println` expects at least 1 argument, but 0 were given",
+        ),
+    ] {
+        assert_eq!(run_err(source).message, message);
+    }
+}
+
+#[test]
+fn garbage_collection() {
+    // Unreachable lists are freed, including ones that refer to each other
+    let mut vm = run("let mut a = [1]; let mut b = [a]; a[0] = b; let c = [[2]]; a = 0; b = 0;");
+    assert_eq!(vm.heap().len(), 4);
+    vm.collect_garbage();
+    assert_eq!(vm.heap().len(), 2);
+
+    // Freed slots are reused
+    vm.run(compile("let d = [3];").unwrap()).unwrap();
+    assert_eq!(vm.heap().len(), 3);
+
+    // Collections run once the threshold is reached
+    let mut vm = Vm::with_gc(GcConfig {
+        threshold: 8,
+        stress: false,
+    });
+    let source = "let mut n = 0; while n < 100 { let x = [n]; n += x[0] - n + 1; }";
+    vm.run(compile(source).unwrap()).unwrap();
+    assert!(vm.heap().collections() > 0);
+    assert!(vm.heap().len() <= 8);
+
+    // Stress mode collects at every allocation, so anything that isn't
+    // rooted while an allocation happens would be freed too early
+    let mut vm = Vm::with_gc(GcConfig {
+        stress: true,
+        ..GcConfig::default()
+    });
+    let source = "fun pair(a, b) { [a, [b, [a]]] } let mut p = pair([1], [2]); let q = [p[0][0], p[1][1][0][0], [], []];";
+    vm.run(compile(source).unwrap()).unwrap();
+    assert_eq!(vm.heap().collections(), 8);
+    vm.run(compile("let r = q[0] + q[1];").unwrap()).unwrap();
+    assert_eq!(vm.global("r"), Some(&Value::Int(2)));
+}
+
+#[test]
+fn classes() {
+    let source = "
+        class Counter {
+            fun init(start) { self.count = start; return 0; }
+            fun add(n) { self.count += n; self }
+            fun get() { self.count }
+        }
+        let c = Counter(1);
+        let a = c.add(2).add(3).get();
+        let get = c.get;
+        c.count = 10;
+        let b = get();
+
+        class Empty {}
+        let e = Empty();
+        e.x = 1;
+        let x = e.x;
+    ";
+    let vm = run(source);
+    assert_eq!(vm.global("a"), Some(&Value::Int(6)));
+    assert_eq!(vm.global("b"), Some(&Value::Int(10)));
+    assert_eq!(vm.global("x"), Some(&Value::Int(1)));
+    assert!(matches!(vm.global("c"), Some(Value::Instance(_))));
+
+    assert_eq!(
+        run_err("class A {} A().x;").message,
+        "`A` instance has no field or method `x`"
+    );
+    assert_eq!(
+        run_err("class A {} A(1);").message,
+        "`A` expects 0 arguments, but 1 were given"
+    );
+    assert_eq!(
+        run_err("class A { fun init(x) {} } A();").message,
+        "`init` expects 1 argument, but 0 were given"
+    );
+    assert_eq!(
+        run_err("1.x;").message,
+        "cannot access field `x` on a value of type int"
+    );
+    assert_eq!(
+        run_err("let a = [1]; a.x = 1;").message,
+        "cannot set field `x` on a value of type list"
+    );
+}
+
+#[test]
+fn instance_garbage_collection() {
+    // Instances and bound methods are traced through their fields and
+    // receivers, so cycles between them are collected too
+    let source = "
+        class Node { fun init() { self.next = self; } fun me() { self } }
+        let mut n = Node();
+        let m = n.me;
+        n = 0;
+    ";
+    let mut vm = run(source);
+    vm.collect_garbage();
+    assert_eq!(vm.heap().len(), 2);
+    vm.run(compile("let o = m();").unwrap()).unwrap();
+    assert!(matches!(vm.global("o"), Some(Value::Instance(_))));
+}
+
+#[test]
+fn register_backend() {
+    // Stress the collector, so anything the register machine forgets to keep
+    // in its window is freed too early
+    let mut vm = Vm::with_gc(GcConfig {
+        stress: true,
+        ..GcConfig::default()
+    });
+    vm.set_backend(Backend::Register);
+    let source = "
+        class Pair { fun init(a, b) { self.a = a; self.b = b; } fun sum() { self.a[0] + self.b[0] } }
+        fun make(n) { Pair([n], [n * 2]) }
+        let mut total = 0;
+        for i in 0..10 { total += make(i).sum(); }
+        fun id(x) { x }
+        let deep = [id(1), [2, [3, id(4)]], 5];
+        let four = deep[1][1][1];
+    ";
+    vm.run(compile(source).unwrap()).unwrap();
+    assert_eq!(vm.global("total"), Some(&Value::Int(135)));
+    assert_eq!(vm.global("four"), Some(&Value::Int(4)));
+}
+
+#[test]
+fn ast_backend() {
+    let source = "
+        class Pair { fun init(a, b) { self.a = a; self.b = b; } fun sum() { self.a[0] + self.b[0] } }
+        fun make(n) { Pair([n], [n * 2]) }
+        let mut total = 0;
+        for i in 0..10 { total += make(i).sum(); }
+        fun fib(n) { if n < 2 { return n; } fib(n - 1) + fib(n - 2) }
+        let f = fib(15);
+        let mut i = 0;
+        let mut list = [0, 0, 0];
+        while i < 3 { list[i] = i * i; i += 1; }
+        let last = list[2];
+        let block = { let x = 1; let x = x + 1; x };
+        let word = \"meow\"[1..=2] + \"!\";
+        fun sign(n) { let s = if n < 0 { return -1; } else { 1 }; s }
+        let signs = [sign(-5), sign(5)];
+        let negative = signs[0];
+    ";
+    // The collector is stressed, so values the evaluator forgets to root
+    // are freed too early
+    let mut vm = Vm::with_gc(GcConfig {
+        stress: true,
+        ..GcConfig::default()
+    });
+    vm.run_ast(&parse(source).unwrap()).unwrap();
+    let compiled = run(source);
+    for name in ["total", "f", "i", "last", "block", "word", "negative"] {
+        assert_eq!(vm.global(name), compiled.global(name), "{}", name);
+    }
+    assert_eq!(vm.global("total"), Some(&Value::Int(135)));
+    assert_eq!(vm.global("x"), None);
+
+    // Selecting the backend makes `run` evaluate programs without
+    // compiling them
+    let mut vm = Vm::new();
+    vm.set_backend(Backend::Ast);
+    meow::run(&mut vm, "let y = 6 * 7;").unwrap();
+    assert_eq!(vm.global("y"), Some(&Value::Int(42)));
+    assert!(vm.run(compile("1;").unwrap()).is_err());
+}
+
+#[test]
+fn ast_backend_errors() {
+    let run_err = |source: &str| Vm::new().run_ast(&parse(source).unwrap()).unwrap_err();
+
+    let error = run_err("fun inner(x) {\n  x / 0\n}\nfun outer() { inner(1) }\nouter();");
+    assert_eq!(error.message, "division by zero");
+    let trace: Vec<_> = error
+        .trace
+        .iter()
+        .map(|TraceFrame { function, span }| {
+            let span = span.unwrap();
+            (function.as_str(), span.line, span.column)
+        })
+        .collect();
+    assert_eq!(
+        trace,
+        vec![("inner", 2, 3), ("outer", 4, 15), ("<script>", 5, 1)]
+    );
+
+    let error = run_err("fun down(n) { down(n + 1) } down(0);");
+    assert_eq!(
+        error.message,
+        "maximum call depth exceeded: `<script>` -> `down` (1024 calls)"
+    );
+    assert_eq!(error.trace.len(), MAX_CALL_DEPTH);
+
+    assert_eq!(
+        run_err("fun gen() { yield 1; } for x in gen() { }").message,
+        "generators are not supported by the ast backend"
+    );
+    assert_eq!(
+        run_err("fun f() { } spawn f();").message,
+        "tasks are not supported by the ast backend"
+    );
+    assert_eq!(
+        run_err("let c = channel(); receive(c);").message,
+        "deadlock: every task is waiting to receive from a channel"
+    );
+
+    // Programs are checked like they are for the other backends
+    let mut vm = Vm::new();
+    assert_eq!(
+        run_ast(
+            &mut vm,
+            "fun outer() { let x = 1; fun inner() { x } inner() } outer();"
+        ),
+        Err("closures capturing local variables cannot be compiled yet".to_string())
+    );
+    // Deep recursion grows the stack rather than overflowing it
+    run_ast(
+        &mut vm,
+        "fun down(n) { if n == 0 { 0 } else { down(n - 1) + 1 } } let depth = down(1000);",
+    )
+    .unwrap();
+    assert_eq!(vm.global("depth"), Some(&Value::Int(1000)));
+
+    // Functions only run on the backend they were declared on
+    let mut vm = Vm::new();
+    vm.run(compile("fun compiled() { 1 }").unwrap()).unwrap();
+    vm.run_ast(&parse("fun declared() { 1 }").unwrap()).unwrap();
+    assert_eq!(
+        vm.run_ast(&parse("compiled();").unwrap())
+            .unwrap_err()
+            .message,
+        "`compiled` was compiled to bytecode, so it can't be called here"
+    );
+    assert_eq!(
+        vm.run(compile("declared();").unwrap()).unwrap_err().message,
+        "`declared` was declared on the ast backend, so it can only be called there"
+    );
+}
+
+#[test]
+fn register_lowering() {
+    let code = lower(Rc::new(compile("let x = { let a = 1; a + 2 };").unwrap())).unwrap();
+    assert_eq!(
+        code.code,
+        vec![
+            Instr::LoadConstant { dst: 1, index: 0 },
+            Instr::Move { dst: 2, src: 1 },
+            Instr::LoadConstant { dst: 3, index: 1 },
+            Instr::Binary {
+                op: OpCode::Add,
+                dst: 2,
+                left: 2,
+                right: 3
+            },
+            Instr::Move { dst: 1, src: 2 },
+            Instr::DefineGlobal { src: 1, name: 2 },
+            Instr::LoadUnit { dst: 1 },
+            Instr::Return { src: 1 },
+        ]
+    );
+    assert_eq!(code.registers, 4);
+}
+
+#[cfg(feature = "clock")]
+#[test]
+fn fuel() {
+    for backend in [Backend::Stack, Backend::Register] {
+        let mut vm = Vm::with_fuel(10_000);
+        vm.set_backend(backend);
+        let error = vm
+            .run(compile("let mut n = 0;\nwhile true { n += 1; }").unwrap())
+            .unwrap_err();
+        assert_eq!(error.kind, RuntimeErrorKind::FuelExhausted);
+        assert_eq!(error.span.unwrap().line, 2);
+        assert_eq!(vm.fuel(), Some(0));
+
+        // The VM can be refuelled once the runaway program has been stopped
+        vm.set_fuel(Some(100));
+        vm.run(compile("n = 1;").unwrap()).unwrap();
+        assert_eq!(vm.global("n"), Some(&Value::Int(1)));
+        assert!(vm.fuel().unwrap() < 100);
+
+        // Sleeping uses fuel for every millisecond, and is cut short when
+        // it runs out
+        vm.set_fuel(Some(50));
+        let error = vm
+            .run(compile("while true { sleep(10); }").unwrap())
+            .unwrap_err();
+        assert_eq!(error.kind, RuntimeErrorKind::FuelExhausted);
+        vm.set_fuel(Some(20));
+        let error = vm
+            .run(compile("sleep(1000000000); n = 2;").unwrap())
+            .unwrap_err();
+        assert_eq!(error.kind, RuntimeErrorKind::FuelExhausted);
+        assert_eq!(vm.global("n"), Some(&Value::Int(1)));
+    }
+
+    assert_eq!(run_err("1 / 0;").kind, RuntimeErrorKind::Failed);
+}
+
+#[test]
+fn profiling() {
+    for backend in [Backend::Stack, Backend::Register] {
+        let mut vm = Vm::new();
+        vm.set_backend(backend);
+        vm.set_profiling(true);
+        let source = "fun double(x) { x * 2 } let mut n = 0; for i in 0..3 { n += double(i); }";
+        vm.run(compile(source).unwrap()).unwrap();
+
+        let profile = vm.profile().unwrap();
+        let count = |op| {
+            let stats = profile
+                .opcodes()
+                .into_iter()
+                .find(|(other, _)| *other == op);
+            stats.map_or(0, |(_, stats)| stats.count)
+        };
+        assert_eq!(count(OpCode::Multiply), 3);
+        assert_eq!(count(OpCode::Call), 3);
+        assert_eq!(count(OpCode::Divide), 0);
+
+        let functions = profile.functions();
+        let double = functions.iter().find(|(f, _)| f.name == "double").unwrap();
+        // Each call runs `GetLocal`, `Constant`, `Multiply` and `Return`
+        assert_eq!(double.1.count, 12);
+        assert!(profile.to_string().contains("Multiply"));
+    }
+
+    // Profiling is off by default
+    assert!(Vm::new().profile().is_none());
+}
+
+#[derive(Clone, Default)]
+struct Events(Rc<RefCell<Vec<String>>>);
+
+impl Observer for Events {
+    fn on_enter_function(&mut self, function: &Function, args: &[Value]) {
+        let args: Vec<_> = args.iter().map(Value::to_string).collect();
+        let event = format!("enter {}({})", function.name, args.join(", "));
+        self.0.borrow_mut().push(event);
+    }
+
+    fn on_instruction(&mut self, instruction: &Instruction) {
+        if instruction.op == Some(OpCode::Multiply) {
+            self.0.borrow_mut().push("multiply".to_string());
+        }
+    }
+
+    fn on_error(&mut self, error: &RuntimeError) {
+        let event = format!("error {} in {}", error.message, error.trace[0].function);
+        self.0.borrow_mut().push(event);
+    }
+
+    fn on_allocation(&mut self, heap: &Heap, obj: ObjRef) {
+        let event = format!("allocate {}", heap.display(&Value::List(obj)));
+        self.0.borrow_mut().push(event);
+    }
+}
+
+#[test]
+fn observers() {
+    let source = "fun double(x) { [x * 2] } let a = double(1); let b = a[0] / 0;";
+    for backend in [Backend::Stack, Backend::Register, Backend::Ast] {
+        let events = Events::default();
+        let mut vm = Vm::new();
+        vm.set_backend(backend);
+        vm.set_observer(Some(Box::new(events.clone())));
+        if backend == Backend::Ast {
+            vm.run_ast(&parse(source).unwrap()).unwrap_err();
+        } else {
+            vm.run(compile(source).unwrap()).unwrap_err();
+        }
+        let multiply = (backend != Backend::Ast).then_some("multiply");
+        let expected: Vec<_> = ["enter <script>()", "enter double(1)"]
+            .into_iter()
+            .chain(multiply)
+            .chain(["allocate [2]", "error division by zero in <script>"])
+            .collect();
+        assert_eq!(*events.0.borrow(), expected);
+    }
+
+    // The ast backend reports every statement and expression it evaluates
+    let lines = Rc::new(RefCell::new(Vec::new()));
+    struct Lines(Rc<RefCell<Vec<u32>>>);
+    impl Observer for Lines {
+        fn on_instruction(&mut self, instruction: &Instruction) {
+            assert!(instruction.op.is_none());
+            self.0.borrow_mut().push(instruction.span.unwrap().line);
+        }
+    }
+    let mut vm = Vm::new();
+    vm.set_observer(Some(Box::new(Lines(lines.clone()))));
+    vm.run_ast(&parse("let x = 1;\nlet y = x + 2;").unwrap())
+        .unwrap();
+    assert_eq!(*lines.borrow(), [1, 1, 2, 2, 2, 2]);
+}
+
+#[cfg(feature = "clock")]
+#[test]
+fn timing() {
+    let mut vm = Vm::new();
+    vm.set_timing(true);
+    let source = "let mut xs = []; for i in 0..10 { xs = [xs, i]; }";
+    meow::run(&mut vm, source).unwrap();
+    let timings = *vm.timings().unwrap();
+    assert!(!timings.parse.is_zero());
+    assert!(!timings.execute.is_zero());
+    assert_eq!(
+        timings.total(),
+        timings.lex + timings.parse + timings.resolve + timings.compile + timings.execute
+    );
+    assert!(timings.to_string().contains("resolve"));
+    assert!(vm.heap().peak_len() >= 10);
+
+    // Timings add up over every program run, and a program that fails to
+    // parse is only timed up to parsing
+    assert!(meow::run(&mut vm, "let x = ;").is_err());
+    let more = *vm.timings().unwrap();
+    assert!(more.parse > timings.parse);
+    assert_eq!(more.execute, timings.execute);
+
+    vm.set_timing(false);
+    assert!(vm.timings().is_none());
+    assert!(Vm::new().timings().is_none());
+}
+
+#[test]
+fn instructions_executed() {
+    for backend in [Backend::Stack, Backend::Register, Backend::Ast] {
+        let mut vm = Vm::new();
+        vm.set_backend(backend);
+        assert_eq!(vm.instructions_executed(), 0);
+        meow::run(&mut vm, "let mut n = 0; for i in 0..100 { n += i; }").unwrap();
+        let executed = vm.instructions_executed();
+        assert!(executed >= 100, "{:?} executed {}", backend, executed);
+        meow::run(&mut vm, "n += 1;").unwrap();
+        assert!(vm.instructions_executed() > executed);
+    }
+}
+
+#[test]
+fn tasks() {
+    // Tasks take turns, and pass values through channels
+    let vm = run("
+        fun producer(ch, n) { for i in 0..n { send(ch, [i]); } send(ch, [-1]); }
+        fun consumer(ch, out) {
+            let mut total = 0;
+            let mut item = receive(ch)[0];
+            while item != -1 { total += item; item = receive(ch)[0]; }
+            send(out, total);
+        }
+        let ch = channel();
+        let out = channel();
+        spawn consumer(ch, out);
+        spawn producer(ch, 1000);
+        let total = receive(out);
+    ");
+    assert_eq!(vm.global("total"), Some(&Value::Int(499500)));
+
+    // Long-running tasks are preempted, and finish after the script does
+    let vm = run("
+        let counts = [0, 0];
+        fun count(i) { for _ in 0..1000 { counts[i] = counts[i] + 1; } }
+        spawn count(0);
+        spawn count(1);
+        let early = counts[0] + counts[1];
+        let ch = channel();
+        fun wait(ch) { receive(ch); }
+        spawn wait(ch);
+    ");
+    assert_eq!(vm.global("early"), Some(&Value::Int(0)));
+    let counts = match vm.global("counts") {
+        Some(Value::List(list)) => vm.heap().list(*list).clone(),
+        value => panic!("expected a list, found {:?}", value),
+    };
+    assert_eq!(counts, [Value::Int(1000), Value::Int(1000)]);
+
+    // Values held by suspended tasks and channels survive collections
+    let mut vm = Vm::with_gc(GcConfig {
+        stress: true,
+        ..GcConfig::default()
+    });
+    vm.run(
+        compile(
+            "
+        fun relay(from, to) { let item = [receive(from)]; send(to, item); }
+        let a = channel();
+        let b = channel();
+        spawn relay(a, b);
+        send(a, [1, 2]);
+        let x = receive(b)[0][1];
+    ",
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(vm.global("x"), Some(&Value::Int(2)));
+
+    assert_eq!(
+        run_err("let ch = channel(); receive(ch);").message,
+        "deadlock: every task is waiting to receive from a channel"
+    );
+    assert_eq!(
+        run_err("fun f(x) { x + 1 } spawn f(true);").message,
+        "unsupported operand types for `+`: bool and int"
+    );
+    assert_eq!(
+        run_err("spawn send(channel(), 1);").message,
+        "cannot spawn the native function `send`"
+    );
+    assert_eq!(
+        run_err("send(1, 2);").message,
+        "`send` expects a channel, found a value of type int"
+    );
+}
+
+#[test]
+fn generators() {
+    // Generators can be nested and can be methods, and stay exhausted once
+    // they finish
+    let vm = run("
+        fun count(n) { let mut i = 0; while i < n { yield i; i += 1; } }
+        let mut total = 0;
+        for x in count(5) { total += x; }
+
+        fun pairs() { for i in 0..3 { for j in count(i) { yield i * 10 + j; } } }
+        let mut seen = [];
+        for pair in pairs() { seen = [seen, pair]; }
+        class Cat { fun init(lives) { self.lives = lives; } fun each() { yield self.lives; yield 0; } }
+        let mut lives = 0;
+        for n in Cat(9).each() { lives += n; }
+
+        let g = count(2);
+        let mut first = 0;
+        for x in g { first += 1; }
+        let mut second = 0;
+        for x in g { second += 1; }
+        let kind = count(1);
+    ");
+    assert_eq!(vm.global("total"), Some(&Value::Int(10)));
+    assert_eq!(vm.global("lives"), Some(&Value::Int(9)));
+    assert_eq!(vm.global("first"), Some(&Value::Int(2)));
+    assert_eq!(vm.global("second"), Some(&Value::Int(0)));
+    assert_eq!(vm.global("kind").unwrap().type_name(), "generator");
+
+    let mut pairs = Vec::new();
+    let mut seen = vm.global("seen").unwrap().clone();
+    while let Value::List(list) = seen {
+        match vm.heap().list(list).as_slice() {
+            [rest, Value::Int(pair)] => {
+                pairs.push(*pair);
+                seen = rest.clone();
+            }
+            _ => break,
+        }
+    }
+    pairs.reverse();
+    assert_eq!(pairs, [10, 20, 21]);
+
+    // The slots of suspended generators survive collections
+    let mut vm = Vm::with_gc(GcConfig {
+        stress: true,
+        ..GcConfig::default()
+    });
+    vm.run(
+        compile(
+            "
+        fun nest() { let items = [1]; yield [[items]]; yield [[items]]; }
+        let mut n = 0;
+        for x in nest() { n += x[0][0][0]; }
+    ",
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(vm.global("n"), Some(&Value::Int(2)));
+
+    assert_eq!(
+        run_err("for x in 5 { }").message,
+        "cannot iterate over a value of type int"
+    );
+    assert_eq!(
+        run_err("fun me() { for x in g { } yield 1; } let g = me(); for x in g { }").message,
+        "generator is already running"
+    );
+    assert!(compile("yield 1;").unwrap_err()[0]
+        .message
+        .contains("`yield` outside of a function"));
+}
+
+#[test]
+fn conversions() {
+    let vm = run(r#"
+        let ints = [int("42"), int(" -7 "), int("+3"), int(2.9), int(true), int('a'), int(5)];
+        let floats = [float("2.5"), float("1e3"), float(2), float(0.5)];
+        let strings = [str(12), str(1.0), str([1, "a"]), str("meow"), str({})];
+        let chars = [char(97), char("z"), char('c')];
+        let types = [type_of(1), type_of(1.5), type_of("a"), type_of([]), type_of(0..2), type_of(type_of)];
+    "#);
+    let list = |name| {
+        let Some(Value::List(list)) = vm.global(name) else {
+            panic!("expected a list");
+        };
+        vm.heap().list(*list).clone()
+    };
+    assert_eq!(
+        list("ints"),
+        [42, -7, 3, 2, 1, 97, 5].map(Value::Int).to_vec()
+    );
+    assert_eq!(
+        list("floats"),
+        [2.5, 1000.0, 2.0, 0.5].map(Value::Float).to_vec()
+    );
+    assert_eq!(
+        list("strings"),
+        ["12", "1.0", "[1, \"a\"]", "meow", "()"]
+            .map(Value::from)
+            .to_vec()
+    );
+    assert_eq!(list("chars"), ['a', 'z', 'c'].map(Value::Char).to_vec());
+    assert_eq!(
+        list("types"),
+        ["int", "float", "string", "list", "range", "function"]
+            .map(Value::from)
+            .to_vec()
+    );
+
+    for (source, message) in [
+        (r#"int("12a");"#, r#"cannot convert "12a" to int"#),
+        (r#"int("");"#, r#"cannot convert "" to int"#),
+        ("int([]);", "cannot convert a value of type list to int"),
+        (
+            r#"int("99999999999999999999");"#,
+            r#"integer overflow: int("99999999999999999999")"#,
+        ),
+        (r#"float("meow");"#, r#"cannot convert "meow" to float"#),
+        (
+            "float(true);",
+            "cannot convert a value of type bool to float",
+        ),
+        ("char(-1);", "no char has the code point -1"),
+        ("char(55296);", "no char has the code point 55296"),
+        (r#"char("ab");"#, r#"cannot convert "ab" to char"#),
+        ("char(1.5);", "cannot convert a value of type float to char"),
+    ] {
+        assert_eq!(run_err(source).message, message);
+    }
+
+    // Strings too long for an `i64` become big ints when they are on
+    let mut vm = Vm::new();
+    vm.set_big_ints(true);
+    vm.run(compile(r#"let big = str(int("-99999999999999999999") - 1);"#).unwrap())
+        .unwrap();
+    assert_eq!(
+        vm.global("big"),
+        Some(&Value::from("-100000000000000000000"))
+    );
+}
+
+#[test]
+fn assertions() {
+    let vm = run("assert(1 < 2); assert(true, \"unused\"); let reached = true;");
+    assert_eq!(vm.global("reached"), Some(&Value::Bool(true)));
+
+    let error = run_err("let lives = 9;\nassert(lives == 8);");
+    assert_eq!(error.message, "assertion failed");
+    assert_eq!(error.kind, RuntimeErrorKind::Panicked);
+    assert_eq!(error.span.unwrap().line, 2);
+    assert_eq!(
+        run_err("assert(false, \"cats are liquid\");").message,
+        "assertion failed: cats are liquid"
+    );
+    assert_eq!(
+        run_err("let lives = 8; assert(lives == 9, \"expected {} lives, found {}\", 9, lives);")
+            .message,
+        "assertion failed: expected 9 lives, found 8"
+    );
+    assert_eq!(
+        run_err("assert(1);").message,
+        "`assert` expects a bool, found a value of type int"
+    );
+
+    let error = run_err("fun land() {\n  panic(\"fell on {} paws\", 3);\n}\nland();");
+    assert_eq!(error.message, "fell on 3 paws");
+    assert_eq!(error.kind, RuntimeErrorKind::Panicked);
+    assert_eq!(error.span.unwrap().line, 2);
+    assert_eq!(run_err("panic([1, 2]);").message, "[1, 2]");
+
+    // The AST backend points at the call too
+    let mut vm = Vm::new();
+    vm.set_backend(Backend::Ast);
+    match meow::run(&mut vm, "let x = 1;\nassert(x == 2, \"x is {}\", x);").unwrap_err() {
+        InterpreterError::Failed { diagnostics, .. } => {
+            assert_eq!(diagnostics[0].message, "assertion failed: x is 1");
+            assert_eq!(diagnostics[0].span.line, 2);
+        }
+        error => panic!("expected a runtime error, found {}", error),
+    }
+}
+
+#[test]
+fn sorting() {
+    let source = "
+        import std.math;
+        let xs = [3, 1.5, -2, 10, 1];
+        sort(xs);
+        println(xs);
+        fun length(word) { word.len() }
+        let words = [\"pear\", \"fig\", \"apple\", \"kiwi\"];
+        sort_by(words, length);
+        println(words);
+        let pairs = [[2, \"b\"], [1, \"z\"], [2, \"a\"], [1]];
+        sort(pairs);
+        println(pairs);
+        let flags = [true, false, true];
+        sort(flags);
+        println(flags);
+        let mut calls = 0;
+        fun negated(x) { calls += 1; -x }
+        let countdown = [1, 2, 3];
+        sort_by(countdown, negated);
+        println(\"{} after {} calls\", countdown, calls);
+        let signed = [-3, 1, -2];
+        sort_by(signed, math.abs);
+        println(signed);
+        let nan = [0.0 / 0.0, 2, 1];
+        sort(nan);
+        println(nan);
+        println(\"{} {} {}\", math.min([4, 2.5, 8]), math.max(\"b\", \"c\", \"a\"), math.max([[1, 2], [1, 3]]));
+        let a = [1];
+        let b = [1];
+        println(\"{} {} {}\", a == b, math.min(a, a) == a, math.min(a, b) == math.max(a, b));
+    ";
+    assert_eq!(
+        printed(source),
+        "[-2, 1, 1.5, 3, 10]\n\
+         [\"fig\", \"pear\", \"kiwi\", \"apple\"]\n\
+         [[1], [1, \"z\"], [2, \"a\"], [2, \"b\"]]\n\
+         [false, true, true]\n\
+         [3, 2, 1] after 3 calls\n\
+         [1, -2, -3]\n\
+         [1, 2, NaN]\n\
+         2.5 c [1, 3]\n\
+         false true false\n"
+    );
+
+    assert_eq!(
+        run_err("sort([1, \"a\"]);").message,
+        "cannot compare a value of type int with a value of type string"
+    );
+    assert_eq!(
+        run_err("sort(5);").message,
+        "`sort` expects a list, found a value of type int"
+    );
+    assert_eq!(
+        run_err("let mut xs = [1]; xs[0] = xs; sort([xs, xs]);").message,
+        "lists are nested too deeply to compare"
+    );
+    assert_eq!(
+        run_err("import std.math; math.min([]);").message,
+        "cannot take the minimum of an empty list"
+    );
+    assert_eq!(
+        run_err("import std.math; math.max(1);").message,
+        "`max` expects a list, found a value of type int"
+    );
+    assert_eq!(
+        run_err("import std.math; math.min();").message,
+        "`min` expects at least 1 argument, but 0 were given"
+    );
+    assert_eq!(
+        run_err("let c = channel(); fun wait(x) { receive(c) } sort_by([1], wait);").message,
+        "cannot wait for another task in a function called by a native function"
+    );
+
+    // Errors in the key function point at where they happened
+    let error = run_err("fun key(x) {\n  x.len()\n}\nsort_by([1], key);");
+    assert_eq!(error.span.unwrap().line, 2);
+    let lines: Vec<_> = error
+        .trace
+        .iter()
+        .map(|frame| (frame.function.as_str(), frame.span.unwrap().line))
+        .collect();
+    assert_eq!(lines, [("key", 2), ("<script>", 4)]);
+
+    // Programs can't be paused while a native function calls them
+    let mut vm = Vm::new();
+    let source = "
+        fun key(x) { let mut i = 0; while i < 100 { i += 1; } x }
+        sort_by([2, 1], key);
+    ";
+    vm.start(compile(source).unwrap()).unwrap();
+    let error = vm.step(50).unwrap_err();
+    assert_eq!(error.kind, RuntimeErrorKind::FuelExhausted);
+}
+
+#[test]
+fn ranges() {
+    let source = "
+        let digits = 0..10;
+        let len = digits.len();
+        let inclusive = (1..=10).len();
+        let empty = (5..2).len();
+        let has_nine = digits.contains(9);
+        let has_ten = digits.contains(10);
+        let mut total = 0;
+        for i in digits { total += i; }
+        let mut again = 0;
+        for i in digits { again += 1; }
+        let kind = digits;
+    ";
+    let vm = run(source);
+    assert_eq!(vm.global("len"), Some(&Value::Int(10)));
+    assert_eq!(vm.global("inclusive"), Some(&Value::Int(10)));
+    assert_eq!(vm.global("empty"), Some(&Value::Int(0)));
+    assert_eq!(vm.global("has_nine"), Some(&Value::Bool(true)));
+    assert_eq!(vm.global("has_ten"), Some(&Value::Bool(false)));
+    assert_eq!(vm.global("total"), Some(&Value::Int(45)));
+    // Iterating over a range doesn't use it up
+    assert_eq!(vm.global("again"), Some(&Value::Int(10)));
+    assert_eq!(vm.global("kind").unwrap().to_string(), "0..10");
+
+    let mut ast = Vm::new();
+    ast.set_backend(Backend::Ast);
+    meow::run(&mut ast, source).unwrap();
+    assert_eq!(ast.global("total"), Some(&Value::Int(45)));
+    assert_eq!(ast.global("again"), Some(&Value::Int(10)));
+    assert_eq!(ast.global("has_nine"), Some(&Value::Bool(true)));
+
+    // Ranges ending at the largest int stop there
+    let vm = run("
+        let top = 9223372036854775806..=9223372036854775807;
+        let mut count = 0;
+        for i in top { count += 1; }
+    ");
+    assert_eq!(vm.global("count"), Some(&Value::Int(2)));
+    // And so do ranges written in the loop, which count without creating
+    // the range
+    let vm = run("
+        let mut count = 0;
+        for i in 9223372036854775805..=9223372036854775807 { count += 1; }
+        let mut total = 0;
+        for i in -2..=-2 { total += i; }
+    ");
+    assert_eq!(vm.global("count"), Some(&Value::Int(3)));
+    assert_eq!(vm.global("total"), Some(&Value::Int(-2)));
+
+    assert_eq!(
+        run_err("0.5..2;").message,
+        "range bounds must be ints, found a value of type float"
+    );
+    assert_eq!(
+        run_err("for i in 0..2.5 { }").message,
+        "range bounds must be ints, found a value of type float"
+    );
+    assert_eq!(
+        run_err("for c in \"a\"..=\"c\" { }").message,
+        "range bounds must be ints, found a value of type string"
+    );
+    assert_eq!(
+        run_err("(0..1).contains(\"a\");").message,
+        "`contains` expects an int, found a value of type string"
+    );
+    assert_eq!(
+        run_err("(0..1).trim();").message,
+        "ranges have no method `trim`"
+    );
+}
+
+#[test]
+fn snapshots() {
+    // Restored objects keep their cycles and sharing, and generators can be
+    // resumed, on either backend
+    let setup = "
+        class Cat { fun init(name) { self.name = name; } fun greet() { return self.name; } }
+        let cat = Cat(\"Tom\");
+        let mut xs = [1, cat];
+        xs[0] = xs;
+        let same = [xs, xs];
+        let greet = cat.greet;
+        let shout = \"meow\".to_upper;
+        import std.math;
+        let c = channel();
+        send(c, 5);
+        fun count(n) { let mut i = 0; while i < n { yield i; i += 1; } }
+        let g = count(4);
+    ";
+    let resume = "
+        let mut total = 0;
+        for x in g { total += x; }
+        let name = greet();
+        let shouted = shout();
+        let root = math.sqrt(4);
+        let received = receive(c);
+        same[0][0][1].name = \"Felix\";
+        let renamed = same[1][1].name;
+    ";
+    for backend in [Backend::Stack, Backend::Register] {
+        let mut vm = Vm::new();
+        vm.set_backend(backend);
+        vm.run(compile(setup).unwrap()).unwrap();
+        let snapshot = vm.snapshot().unwrap();
+
+        let mut restored = Vm::with_gc(GcConfig {
+            stress: true,
+            ..GcConfig::default()
+        });
+        restored.set_backend(backend);
+        restored.run(compile("let old = 1;").unwrap()).unwrap();
+        restored.restore(&snapshot).unwrap();
+        assert_eq!(restored.global("old"), None);
+        restored.run(compile(resume).unwrap()).unwrap();
+        assert_eq!(restored.global("total"), Some(&Value::Int(6)));
+        assert_eq!(restored.global("name"), Some(&Value::Str(Rc::from("Tom"))));
+        assert_eq!(restored.global("shouted"), Some(&Value::from("MEOW")));
+        assert_eq!(restored.global("root"), Some(&Value::Float(2.0)));
+        assert_eq!(restored.global("received"), Some(&Value::Int(5)));
+        assert_eq!(
+            restored.global("renamed"),
+            Some(&Value::Str(Rc::from("Felix")))
+        );
+    }
+
+    // Garbage isn't saved
+    let mut vm = Vm::new();
+    vm.run(compile("let mut xs = [1]; xs = [2];").unwrap())
+        .unwrap();
+    let mut restored = Vm::new();
+    restored.restore(&vm.snapshot().unwrap()).unwrap();
+    assert_eq!(restored.heap().len(), 1);
+
+    // Bad snapshots are rejected, leaving the VM as it was
+    let snapshot = vm.snapshot().unwrap();
+    let mut vm = Vm::new();
+    vm.run(compile("let x = 1;").unwrap()).unwrap();
+    assert!(matches!(
+        vm.restore(b"MEOW\0\0"),
+        Err(LoadError::NotASnapshot)
+    ));
+    assert!(matches!(
+        vm.restore(&snapshot[..snapshot.len() - 1]),
+        Err(LoadError::UnexpectedEof)
+    ));
+    let mut future = snapshot.clone();
+    future[5] += 1;
+    assert!(matches!(
+        vm.restore(&future),
+        Err(LoadError::UnsupportedVersion(8))
+    ));
+    let mut dangling = snapshot.clone();
+    let last = dangling.len() - 1;
+    dangling[last] = 7;
+    assert_eq!(
+        vm.restore(&dangling).unwrap_err().to_string(),
+        "malformed file: invalid object reference"
+    );
+    assert_eq!(vm.global("x"), Some(&Value::Int(1)));
+}
+
+#[test]
+fn stepping() {
+    // Stepping through a program a few instructions at a time, across tasks
+    // and generators, gets the same result as running it
+    let source = "
+        fun count(n) { let mut i = 0; while i < n { yield i; i += 1; } }
+        fun worker(c) { for x in count(10) { send(c, x); } }
+        let c = channel();
+        spawn worker(c);
+        let mut total = 0;
+        for i in 0..10 { total += receive(c); }
+        total;
+    ";
+    for backend in [Backend::Stack, Backend::Register] {
+        let mut vm = Vm::new();
+        vm.set_backend(backend);
+        vm.start(compile(source).unwrap()).unwrap();
+        assert_eq!(vm.step(0).unwrap(), Step::Running);
+        assert!(vm.is_paused());
+
+        // The paused program's frames can't be saved, and restoring would
+        // replace the heap they refer to
+        assert_eq!(
+            vm.snapshot().unwrap_err().message,
+            "cannot take a snapshot while a program is paused"
+        );
+        let snapshot = Vm::new().snapshot().unwrap();
+        assert!(matches!(vm.restore(&snapshot), Err(LoadError::Paused)));
+
+        let mut steps = 0;
+        let result = loop {
+            steps += 1;
+            if let Step::Done(result) = vm.step(3).unwrap() {
+                break result;
+            }
+        };
+        assert!(steps > 50);
+        assert_eq!(vm.global("total"), Some(&Value::Int(45)));
+        assert!(!vm.is_paused());
+        vm.restore(&vm.snapshot().unwrap()).unwrap();
+        assert_eq!(vm.run(compile(source).unwrap()).unwrap(), result);
+        assert_eq!(
+            vm.step(3).unwrap_err().message,
+            "there is no program to step"
+        );
+
+        // Fuel still limits the program as a whole
+        vm.set_fuel(Some(10));
+        vm.start(compile("while true { }").unwrap()).unwrap();
+        assert_eq!(vm.step(4).unwrap(), Step::Running);
+        assert_eq!(vm.fuel(), Some(6));
+        assert_eq!(vm.step(6).unwrap(), Step::Running);
+        let error = vm.step(4).unwrap_err();
+        assert_eq!(error.kind, RuntimeErrorKind::FuelExhausted);
+        assert_eq!(error.trace.len(), 1);
+        vm.set_fuel(None);
+
+        // Sleeping uses the program's fuel, not the step's instructions
+        #[cfg(feature = "clock")]
+        {
+            vm.start(compile("sleep(5); let x = 1;").unwrap()).unwrap();
+            assert!(matches!(vm.step(10).unwrap(), Step::Done(_)));
+            vm.set_fuel(Some(20));
+            vm.start(compile("sleep(5); let y = 1;").unwrap()).unwrap();
+            assert!(matches!(vm.step(10).unwrap(), Step::Done(_)));
+            assert!(vm.fuel().unwrap() < 15);
+            vm.set_fuel(None);
+        }
+
+        // Errors end the program
+        vm.start(compile("fun f() { return 1 / 0; }\nf();").unwrap())
+            .unwrap();
+        let error = vm.step(100).unwrap_err();
+        assert_eq!(error.message, "division by zero");
+        assert_eq!(error.trace.len(), 2);
+        assert!(vm.step(100).is_err());
+    }
+}
+
+#[test]
+fn string_methods() {
+    let vm = run(r#"
+        let s = "  Héllo, World  ";
+        let len = s.len();
+        let upper = s.trim().to_upper();
+        let lower = s.trim().to_lower();
+        let found = s.contains("World") && !s.contains("world");
+        let starts = "meow".starts_with("me") && !"meow".starts_with("ow");
+        let replaced = "a-b-c".replace("-", "+");
+        let method = "abc".len;
+        let bound = method();
+    "#);
+    assert_eq!(vm.global("len"), Some(&Value::Int(16)));
+    assert_eq!(vm.global("upper"), Some(&Value::from("HÉLLO, WORLD")));
+    assert_eq!(vm.global("lower"), Some(&Value::from("héllo, world")));
+    assert_eq!(vm.global("found"), Some(&Value::Bool(true)));
+    assert_eq!(vm.global("starts"), Some(&Value::Bool(true)));
+    assert_eq!(vm.global("replaced"), Some(&Value::from("a+b+c")));
+    assert_eq!(vm.global("bound"), Some(&Value::Int(3)));
+
+    assert_eq!(
+        printed(
+            r#"println("a,b,,c".split(",")); println("meow".to_upper); println("".split(","));"#
+        ),
+        "[\"a\", \"b\", \"\", \"c\"]\n<method to_upper>\n[\"\"]\n"
+    );
+
+    // Methods aren't globals
+    assert_eq!(run_err("len;").message, "undefined variable `len`");
+    assert_eq!(
+        run_err(r#""abc".size();"#).message,
+        "strings have no method `size`"
+    );
+    assert_eq!(
+        run_err(r#""abc".contains(1);"#).message,
+        "`contains` expects a string, found a value of type int"
+    );
+    assert_eq!(
+        run_err(r#""abc".replace("a");"#).message,
+        "`replace` expects 2 arguments, but 1 were given"
+    );
+    assert_eq!(
+        run_err(r#""abc".split("");"#).message,
+        "cannot split on an empty string"
+    );
+}
+
+#[test]
+fn math_module() {
+    let vm = run("
+        import std.math;
+        let root = math.sqrt(16);
+        let power = math.pow(2, 10);
+        let abs = math.abs(-3) + math.abs(-1.5);
+        let floor = math.floor(2.7);
+        let ceil = math.ceil(-2.7);
+        let whole = math.floor(3);
+        let min = math.min(3, 2.5);
+        let max = math.max(\"a\", \"b\");
+        let tie = math.max(1, 1.0);
+        let trig = math.sin(0) + math.cos(0) + math.tan(0) + math.atan(0);
+        let inverse = math.asin(1) * 2 == math.pi && math.acos(1) == 0.0;
+        fun area(r) { import std.math; math.pi * r * r }
+        let circle = area(1) == math.pi;
+    ");
+    assert_eq!(vm.global("root"), Some(&Value::Float(4.0)));
+    assert_eq!(vm.global("power"), Some(&Value::Float(1024.0)));
+    assert_eq!(vm.global("abs"), Some(&Value::Float(4.5)));
+    assert_eq!(vm.global("floor"), Some(&Value::Float(2.0)));
+    assert_eq!(vm.global("ceil"), Some(&Value::Float(-2.0)));
+    assert_eq!(vm.global("whole"), Some(&Value::Int(3)));
+    assert_eq!(vm.global("min"), Some(&Value::Float(2.5)));
+    assert_eq!(vm.global("max"), Some(&Value::from("b")));
+    assert_eq!(vm.global("tie"), Some(&Value::Int(1)));
+    assert_eq!(vm.global("trig"), Some(&Value::Float(1.0)));
+    assert_eq!(vm.global("inverse"), Some(&Value::Bool(true)));
+    assert_eq!(vm.global("circle"), Some(&Value::Bool(true)));
+
+    assert_eq!(
+        printed("import std.math; println(math); println(math.sqrt); println(math.pi);"),
+        "<module std.math>\n<native fun sqrt>\n3.141592653589793\n"
+    );
+
+    assert_eq!(
+        run_err("import std.math; math.tau;").message,
+        "module `std.math` has no member `tau`"
+    );
+    assert_eq!(
+        run_err("import std.math; math.sqrt(\"4\");").message,
+        "`sqrt` expects a number, found a value of type string"
+    );
+    assert_eq!(
+        run_err("import std.math; math.min(1, \"1\");").message,
+        "cannot compare a value of type string with a value of type int"
+    );
+    assert_eq!(
+        run_err("import std.math; math.abs(-9223372036854775807 - 1);").message,
+        "integer overflow: abs(-9223372036854775808)"
+    );
+    assert_eq!(run_err("sqrt(4);").message, "undefined variable `sqrt`");
+}
+
+#[test]
+fn fs_module() {
+    #[derive(Clone, Default)]
+    struct Files(Rc<RefCell<HashMap<String, String>>>);
+
+    impl FileSystem for Files {
+        fn read_to_string(&mut self, path: &str) -> io::Result<String> {
+            let files = self.0.borrow();
+            let file = files.get(path).ok_or(io::ErrorKind::NotFound)?;
+            Ok(file.clone())
+        }
+        fn write(&mut self, path: &str, contents: &str) -> io::Result<()> {
+            self.0.borrow_mut().insert(path.into(), contents.into());
+            Ok(())
+        }
+        fn exists(&mut self, path: &str) -> bool {
+            self.0.borrow().contains_key(path)
+        }
+    }
+
+    let cats = "Tom\r\nFelix\n\nGarfield";
+    let source = r#"
+        import std.fs;
+        let before = fs.exists("cats.txt");
+        fs.write("cats.txt", "Tom\r\nFelix\n\nGarfield");
+        let after = fs.exists("cats.txt");
+        let contents = fs.read_to_string("cats.txt");
+        let lines = fs.lines("cats.txt");
+        let count = lines.len();
+        let mut names = "";
+        for i in 0..count { names = names + lines[i] + ";"; }
+    "#;
+    for backend in [Backend::Stack, Backend::Register, Backend::Ast] {
+        let files = Files::default();
+        let mut vm = Vm::new();
+        vm.set_backend(backend);
+        vm.set_file_system(Box::new(files.clone()));
+        meow::run(&mut vm, source).unwrap();
+        assert_eq!(vm.global("before"), Some(&Value::Bool(false)));
+        assert_eq!(vm.global("after"), Some(&Value::Bool(true)));
+        assert_eq!(vm.global("contents"), Some(&Value::from(cats)));
+        assert_eq!(vm.global("count"), Some(&Value::Int(4)));
+        assert_eq!(
+            vm.global("names"),
+            Some(&Value::from("Tom;Felix;;Garfield;"))
+        );
+        assert_eq!(files.0.borrow().len(), 1);
+
+        let mut error = |source| match meow::run(&mut vm, source).unwrap_err() {
+            InterpreterError::Failed { diagnostics, .. } => diagnostics[0].message.clone(),
+            error => panic!("expected a runtime error, found {}", error),
+        };
+        assert_eq!(
+            error(r#"fs.read_to_string("dogs.txt");"#),
+            "cannot read `dogs.txt`: entity not found"
+        );
+        assert_eq!(
+            error(r#"fs.write("dogs.txt", 1);"#),
+            "`write` expects a string, found a value of type int"
+        );
+    }
+
+    // Programs use the real file system by default, if there is one
+    #[cfg(feature = "fs")]
+    {
+        let path = std::env::temp_dir().join(format!("meow-fs-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap().replace('\\', "/");
+        let mut vm = Vm::new();
+        meow::run(
+            &mut vm,
+            &format!(
+                r#"import std.fs; fs.write("{0}", "meow"); let read = fs.read_to_string("{0}");"#,
+                path
+            ),
+        )
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(vm.global("read"), Some(&Value::from("meow")));
+    }
+    #[cfg(not(feature = "fs"))]
+    assert_eq!(
+        run_err(r#"import std.fs; fs.read_to_string("cats.txt");"#).message,
+        "cannot read `cats.txt`: file access is disabled"
+    );
+}
+
+#[cfg(feature = "clock")]
+#[test]
+fn time_module() {
+    // The times differ between backends, so this only runs on one
+    let mut vm = Vm::new();
+    let source = "
+        import std.time;
+        let now = time.now();
+        let start = time.elapsed();
+        sleep(20);
+        let slept = time.elapsed() - start;
+    ";
+    vm.run(compile(source).unwrap()).unwrap();
+    assert!(matches!(vm.global("now"), Some(Value::Float(now)) if *now > 1_600_000_000.0));
+    assert!(matches!(vm.global("slept"), Some(Value::Float(slept)) if *slept >= 0.02));
+
+    assert_eq!(
+        run_err("sleep(-1);").message,
+        "cannot sleep for a negative time"
+    );
+    assert_eq!(
+        run_err("sleep(1.5);").message,
+        "`sleep` expects an int, found a value of type float"
+    );
+}
+
+#[cfg(not(feature = "clock"))]
+#[test]
+fn no_clock() {
+    assert_eq!(
+        run_err("import std.time; time.now();").message,
+        "`now` needs a clock, which this build of Meow doesn't have"
+    );
+    assert_eq!(
+        run_err("sleep(1);").message,
+        "`sleep` needs a clock, which this build of Meow doesn't have"
+    );
+}
+
+#[cfg(feature = "clock")]
+#[test]
+fn interrupts() {
+    for (backend, source) in [
+        (Backend::Stack, "sleep(10000);"),
+        (Backend::Register, "while true { }"),
+        (Backend::Ast, "fun wait() { sleep(10000); } wait();"),
+    ] {
+        let mut vm = Vm::new();
+        vm.set_backend(backend);
+        let handle = vm.interrupt_handle();
+        let interrupter = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            handle.interrupt();
+        });
+        match meow::run(&mut vm, source).unwrap_err() {
+            InterpreterError::Failed { diagnostics, .. } => {
+                assert_eq!(diagnostics[0].message, "the program was interrupted")
+            }
+            error => panic!("expected a runtime error, found {}", error),
+        }
+        interrupter.join().unwrap();
+
+        // The interrupt only stops the program that was running
+        meow::run(&mut vm, "let after = 1;").unwrap();
+        assert_eq!(vm.global("after"), Some(&Value::Int(1)));
+    }
+
+    // Interrupting an idle VM stops its next program
+    let mut vm = Vm::new();
+    vm.interrupt_handle().interrupt();
+    let error = vm.run(compile("let x = 1;").unwrap()).unwrap_err();
+    assert_eq!(error.kind, RuntimeErrorKind::Interrupted);
+    vm.run(compile("let x = 1;").unwrap()).unwrap();
+    assert_eq!(vm.global("x"), Some(&Value::Int(1)));
+}
+
+#[test]
+fn json_module() {
+    // String literals can't contain quotes, so the JSON is passed in as
+    // globals
+    let run_json = |texts: &[&str], source: &str| {
+        let mut results = Vec::new();
+        for backend in [Backend::Stack, Backend::Register, Backend::Ast] {
+            let mut vm = Vm::new();
+            vm.set_backend(backend);
+            for (index, text) in texts.iter().enumerate() {
+                vm.set_global(&format!("text{}", index), Value::from(*text));
+            }
+            let source = format!("import std.json; {}", source);
+            results.push(match meow::run(&mut vm, &source) {
+                Ok(_) => Ok(vm),
+                Err(InterpreterError::Failed { diagnostics, .. }) => {
+                    Err(diagnostics[0].message.clone())
+                }
+                Err(error) => panic!("expected a runtime error, found {}", error),
+            });
+        }
+        results
+    };
+
+    let cat = r#"{"name": "Tom", "age": 3, "weight": 4.5, "toys": ["ball", null, true], "name": "Felix"}"#;
+    let numbers = r#"[12345678901234567890, -0, 1e2, "é😺\n\"\\\/"]"#;
+    let source = "
+        let cat = json.parse(text0);
+        let name = cat.name;
+        let age = cat.age;
+        let weight = cat.weight;
+        let toy = cat.toys[0];
+        let nothing = cat.toys[1];
+        let numbers = json.parse(text1);
+        let huge = numbers[0];
+        let zero = numbers[1];
+        let exponent = numbers[2];
+        let escaped = numbers[3];
+        let compact = json.stringify(cat);
+        let roundtrip = json.stringify(json.parse(compact)) == compact;
+        let escapes = json.stringify(escaped);
+        let scalars = json.stringify([{}, 'c', -1.0, text2, []]);
+    ";
+    for vm in run_json(&[cat, numbers, "tab\t"], source) {
+        let vm = vm.unwrap();
+        assert_eq!(vm.global("name"), Some(&Value::from("Felix")));
+        assert_eq!(vm.global("age"), Some(&Value::Int(3)));
+        assert_eq!(vm.global("weight"), Some(&Value::Float(4.5)));
+        assert_eq!(vm.global("toy"), Some(&Value::from("ball")));
+        assert_eq!(vm.global("nothing"), Some(&Value::Unit));
+        assert_eq!(
+            vm.global("huge"),
+            Some(&Value::Float(12345678901234567890.0))
+        );
+        assert_eq!(vm.global("zero"), Some(&Value::Int(0)));
+        assert_eq!(vm.global("exponent"), Some(&Value::Float(100.0)));
+        assert_eq!(vm.global("escaped"), Some(&Value::from("é😺\n\"\\/")));
+        assert_eq!(
+            vm.global("compact"),
+            Some(&Value::from(
+                r#"{"age":3,"name":"Felix","toys":["ball",null,true],"weight":4.5}"#
+            ))
+        );
+        assert_eq!(vm.global("roundtrip"), Some(&Value::Bool(true)));
+        assert_eq!(vm.global("escapes"), Some(&Value::from(r#""é😺\n\"\\/""#)));
+        assert_eq!(
+            vm.global("scalars"),
+            Some(&Value::from(r#"[null,"c",-1.0,"tab\t",[]]"#))
+        );
+    }
+
+    assert_eq!(
+        printed(
+            "
+            import std.json;
+            class Point { fun init(x, y) { self.x = x; self.y = y; } }
+            println(json.pretty([Point(1, [2, 3]), [], json.parse(json.stringify(Point(1, 2)))]));
+            "
+        ),
+        "[\n  {\n    \"x\": 1,\n    \"y\": [\n      2,\n      3\n    ]\n  },\n  [],\n  {\n    \"x\": 1,\n    \"y\": 2\n  }\n]\n"
+    );
+    assert_eq!(
+        printed("import std.json; println(json.parse(json.stringify([{}, 1])));"),
+        "[(), 1]\n"
+    );
+
+    let parse_error = |text: &str| {
+        let messages: Vec<_> = run_json(&[text], "json.parse(text0);")
+            .into_iter()
+            .map(|result| match result {
+                Ok(_) => panic!("expected {:?} to be invalid", text),
+                Err(message) => message,
+            })
+            .collect();
+        assert!(messages.iter().all(|message| *message == messages[0]));
+        messages[0].clone()
+    };
+    assert_eq!(
+        parse_error("[1, 2"),
+        "invalid JSON at line 1, column 6: expected `,` or `]`"
+    );
+    assert_eq!(
+        parse_error("{\n  \"a\" 1}"),
+        "invalid JSON at line 2, column 7: expected `:`"
+    );
+    assert_eq!(
+        parse_error("01"),
+        "invalid JSON at line 1, column 2: expected the end of the text"
+    );
+    assert_eq!(
+        parse_error(r#""\ud83d""#),
+        "invalid JSON at line 1, column 8: unpaired surrogate in escape"
+    );
+    assert_eq!(
+        parse_error(&"[".repeat(MAX_JSON_DEPTH + 1)),
+        format!(
+            "invalid JSON at line 1, column {}: arrays and objects are nested too deeply",
+            MAX_JSON_DEPTH + 1
+        )
+    );
+    assert_eq!(
+        run_err("import std.json; fun f() { } json.stringify([f]);").message,
+        "cannot convert a value of type function to JSON"
+    );
+    assert_eq!(
+        run_err("import std.json; let mut a = [1]; a[0] = a; json.stringify(a);").message,
+        "cannot convert a value that contains itself to JSON"
+    );
+    let nested = |depth| {
+        format!(
+            "import std.json; let mut x = 1; for i in 0..{} {{ x = [x]; }} json.stringify(x);",
+            depth
+        )
+    };
+    run(&nested(MAX_JSON_DEPTH));
+    assert_eq!(
+        run_err(&nested(100_000)).message,
+        format!(
+            "cannot convert a value nested more than {} deep to JSON",
+            MAX_JSON_DEPTH
+        )
+    );
+    assert_eq!(
+        run_err("import std.json; json.stringify(0.0 / 0.0);").message,
+        "cannot convert NaN to JSON"
+    );
+}
+
+#[test]
+fn env_and_os_modules() {
+    struct Env {
+        vars: Vec<(String, String)>,
+        dir: String,
+    }
+
+    impl Environment for Env {
+        fn var(&mut self, name: &str) -> Option<String> {
+            let var = self.vars.iter().find(|(var, _)| var == name);
+            var.map(|(_, value)| value.clone())
+        }
+        fn vars(&mut self) -> Vec<(String, String)> {
+            self.vars.clone()
+        }
+        fn current_dir(&mut self) -> io::Result<String> {
+            Ok(self.dir.clone())
+        }
+        fn set_current_dir(&mut self, path: &str) -> io::Result<()> {
+            if path == "missing" {
+                return Err(io::ErrorKind::NotFound.into());
+            }
+            self.dir = path.to_string();
+            Ok(())
+        }
+    }
+
+    let source = r#"
+        import std.env;
+        import std.os;
+        let home = env.get("HOME");
+        let unset = env.get("UNSET");
+        let vars = env.vars();
+        let first = vars[0][0] + "=" + vars[0][1];
+        let platform = os.platform();
+        let before = os.cwd();
+        os.set_cwd("/tmp");
+        let after = os.cwd();
+    "#;
+    for backend in [Backend::Stack, Backend::Register, Backend::Ast] {
+        let mut vm = Vm::new();
+        vm.set_backend(backend);
+        vm.set_environment(Box::new(Env {
+            vars: vec![
+                ("USER".to_string(), "tom".to_string()),
+                ("HOME".to_string(), "/home/tom".to_string()),
+            ],
+            dir: "/home/tom".to_string(),
+        }));
+        meow::run(&mut vm, source).unwrap();
+        assert_eq!(vm.global("home"), Some(&Value::from("/home/tom")));
+        assert_eq!(vm.global("unset"), Some(&Value::Unit));
+        assert_eq!(vm.global("first"), Some(&Value::from("HOME=/home/tom")));
+        assert_eq!(
+            vm.global("platform"),
+            Some(&Value::from(std::env::consts::OS))
+        );
+        assert_eq!(vm.global("before"), Some(&Value::from("/home/tom")));
+        assert_eq!(vm.global("after"), Some(&Value::from("/tmp")));
+
+        match meow::run(&mut vm, r#"os.set_cwd("missing");"#).unwrap_err() {
+            InterpreterError::Failed { diagnostics, .. } => assert_eq!(
+                diagnostics[0].message,
+                "cannot change the working directory to `missing`: entity not found"
+            ),
+            error => panic!("expected a runtime error, found {}", error),
+        }
+    }
+
+    // Programs see the real environment by default
+    let vm = run("import std.os; let cwd = os.cwd();");
+    let cwd = std::env::current_dir().unwrap();
+    assert_eq!(vm.global("cwd"), Some(&Value::from(cwd.to_str().unwrap())));
+}
+
+#[test]
+fn sandbox() {
+    let policy = SandboxPolicy::default();
+    assert!(policy.allows(Capability::Files) && policy.allows(Capability::Network));
+    assert!(policy.allows_path("/etc/passwd"));
+    let policy = SandboxPolicy {
+        file_roots: Some(vec!["data".into(), "/srv/cats".into()]),
+        ..SandboxPolicy::strict()
+    };
+    assert!(policy.allows(Capability::Files) && !policy.allows(Capability::Env));
+    assert!(!policy.allows(Capability::Subprocess) && !policy.allows(Capability::Network));
+    for path in [
+        "data/a.txt",
+        "./data/b/../c.txt",
+        "/srv/cats/tom",
+        "/srv/dogs/../cats",
+    ] {
+        assert!(policy.allows_path(path), "{}", path);
+    }
+    for path in [
+        "a.txt",
+        "data/../a.txt",
+        "../data/a.txt",
+        "/srv/cats/../dogs",
+        "/data",
+    ] {
+        assert!(!policy.allows_path(path), "{}", path);
+    }
+    let policy = SandboxPolicy {
+        file_roots: Some(vec![".".into()]),
+        ..SandboxPolicy::strict()
+    };
+    assert!(policy.allows_path("a.txt") && !policy.allows_path("../a.txt"));
+    assert!(!policy.allows_path("/a.txt"));
+
+    for backend in [Backend::Stack, Backend::Register, Backend::Ast] {
+        let files = MemoryFileSystem::new();
+        files.insert("data/cats.txt", "Tom");
+        files.insert("secrets.txt", "hunter2");
+        let mut vm = Vm::new();
+        vm.set_backend(backend);
+        vm.set_file_system(Box::new(files.clone()));
+        vm.set_sandbox(SandboxPolicy {
+            file_roots: Some(vec!["data".into()]),
+            ..SandboxPolicy::strict()
+        });
+        let source = r#"
+            import std.fs;
+            import std.env;
+            import std.os;
+            let cats = fs.read_to_string("data/cats.txt");
+            fs.write("data/dogs.txt", "Rex");
+            let exists = fs.exists("data/dogs.txt");
+            let platform = os.platform();
+        "#;
+        meow::run(&mut vm, source).unwrap();
+        assert_eq!(vm.global("cats"), Some(&Value::from("Tom")));
+        assert_eq!(vm.global("exists"), Some(&Value::Bool(true)));
+
+        let failure = |vm: &mut Vm, source: &str| match meow::run(vm, source).unwrap_err() {
+            InterpreterError::Failed { diagnostics, .. } => diagnostics[0].message.clone(),
+            error => panic!("expected a runtime error, found {}", error),
+        };
+        assert_eq!(
+            failure(&mut vm, r#"fs.read_to_string("data/../secrets.txt");"#),
+            "`read_to_string` can't use `data/../secrets.txt`, which is outside the files the sandbox allows"
+        );
+        assert_eq!(
+            failure(&mut vm, r#"fs.write("secrets.txt", "");"#),
+            "`write` can't use `secrets.txt`, which is outside the files the sandbox allows"
+        );
+        assert_eq!(files.get("secrets.txt").as_deref(), Some("hunter2"));
+        assert_eq!(
+            failure(&mut vm, "env.vars();"),
+            "`vars` needs access to the environment, which the sandbox denies"
+        );
+        assert_eq!(
+            failure(&mut vm, r#"os.set_cwd("/");"#),
+            "`set_cwd` needs access to the environment, which the sandbox denies"
+        );
+
+        vm.set_sandbox(SandboxPolicy::strict());
+        assert_eq!(
+            failure(&mut vm, r#"fs.exists("data/cats.txt");"#),
+            "`exists` needs access to files, which the sandbox denies"
+        );
+
+        // The memory ceiling counts live objects, so garbage doesn't hit it
+        vm.set_sandbox(SandboxPolicy {
+            max_objects: Some(100),
+            ..SandboxPolicy::default()
+        });
+        meow::run(&mut vm, "for i in 0..1000 { let garbage = [i]; }").unwrap();
+        assert_eq!(
+            failure(&mut vm, "let mut xs = []; for i in 0..1000 { xs = [xs]; }"),
+            "the program used more than the 100 objects the sandbox allows"
+        );
+
+        vm.set_sandbox(SandboxPolicy {
+            fuel: Some(100),
+            ..SandboxPolicy::default()
+        });
+        assert_eq!(vm.fuel(), Some(100));
+        assert_eq!(
+            failure(&mut vm, "while true { }"),
+            "the program ran out of fuel"
+        );
+    }
+}
+
+#[test]
+fn isolates() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<SharedProgram>();
+
+    let source = r#"
+        fun fib(n) { if n < 2 { return n; } return fib(n - 1) + fib(n - 2); }
+        let result = fib(seed);
+    "#;
+    let program = SharedProgram::new(&compile(source).unwrap()).unwrap();
+    let handles: Vec<_> = (0..4)
+        .map(|seed| {
+            let program = program.clone();
+            std::thread::spawn(move || {
+                // Each isolate has its own globals, and runs the program
+                // more than once
+                let mut vm = Vm::new();
+                vm.set_global("seed", Value::Int(seed + 10));
+                (0..3)
+                    .map(|_| {
+                        vm.run_shared(&program).unwrap();
+                        vm.from_value::<i64>(vm.global("result").unwrap()).unwrap()
+                    })
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect();
+    for (handle, expected) in handles.into_iter().zip([55, 89, 144, 233]) {
+        assert_eq!(handle.join().unwrap(), vec![expected; 3]);
+    }
+
+    assert!(matches!(
+        SharedProgram::from_bytes(b"MOEW"),
+        Err(LoadError::BadMagic)
+    ));
+    let shared = SharedProgram::from_bytes(program.bytes()).unwrap();
+    assert_eq!(shared.bytes(), program.bytes());
+}
+
+#[test]
+fn args() {
+    assert_eq!(run_err("args;").message, "undefined variable `args`");
+
+    for backend in [Backend::Stack, Backend::Register, Backend::Ast] {
+        let mut vm = Vm::new();
+        vm.set_backend(backend);
+        vm.set_args(vec!["--verbose".to_string(), "cats.txt".to_string()]);
+        meow::run(&mut vm, "let count = args.len(); let path = args[1];").unwrap();
+        assert_eq!(vm.global("count"), Some(&Value::Int(2)));
+        assert_eq!(vm.global("path"), Some(&Value::from("cats.txt")));
+    }
+}
+
+#[test]
+#[cfg(feature = "regex")]
+fn regex_module() {
+    let vm = run(r#"
+        import std.regex;
+        let matches = regex.is_match("^[a-z]+$", "meow");
+        let misses = regex.is_match("^[a-z]+$", "Meow!");
+        let numbers = regex.find_all("\\d+", "9 lives, 4 paws and 1 tail");
+        let count = numbers.len();
+        let first = numbers[0];
+        let none = regex.find_all("\\d+", "meow").len();
+        let swapped = regex.replace("(\\w+)@(\\w+)", "tom@home", "$2@$1");
+        let unchanged = regex.replace("z", "meow", "-");
+    "#);
+    assert_eq!(vm.global("matches"), Some(&Value::Bool(true)));
+    assert_eq!(vm.global("misses"), Some(&Value::Bool(false)));
+    assert_eq!(vm.global("count"), Some(&Value::Int(3)));
+    assert_eq!(vm.global("first"), Some(&Value::from("9")));
+    assert_eq!(vm.global("none"), Some(&Value::Int(0)));
+    assert_eq!(vm.global("swapped"), Some(&Value::from("home@tom")));
+    assert_eq!(vm.global("unchanged"), Some(&Value::from("meow")));
+
+    assert_eq!(
+        run_err(r#"import std.regex; regex.is_match("a(", "a");"#).message,
+        "invalid regex `a(`: unclosed group"
+    );
+    assert_eq!(
+        run_err(r#"import std.regex; regex.find_all("a", 1);"#).message,
+        "`find_all` expects a string, found a value of type int"
+    );
+
+    // The module's `replace` is restored as itself, not the string method
+    let mut vm = run(r#"import std.regex; let replace = regex.replace;"#);
+    let snapshot = vm.snapshot().unwrap();
+    vm.restore(&snapshot).unwrap();
+    meow::run(&mut vm, r#"let swapped = replace("o", "meow", "0");"#).unwrap();
+    assert_eq!(vm.global("swapped"), Some(&Value::from("me0w")));
+}
+
+#[test]
+#[cfg(feature = "jit")]
+fn jit() {
+    let source = "
+        fun collatz(n) {
+            let mut steps = 0;
+            while n != 1 {
+                if n / 2 * 2 == n { n = n / 2; } else { n = 3 * n + 1; }
+                steps += 1;
+            }
+            steps
+        }
+        fun even(n) { n / 2 * 2 == n }
+        fun fib(n) { if n < 2 { n } else { fib(n - 1) + fib(n - 2) } }
+        fun sum(n) { let mut total = 0; for i in 1..=n { total += i; } total }
+        let mut total = 0;
+        let mut evens = 0;
+        let mut sums = 0;
+        for i in 1..200 {
+            total += collatz(i);
+            if even(i) { evens += 1; }
+            sums += sum(i);
+        }
+        let fibs = fib(15);
+    ";
+    for backend in [Backend::Stack, Backend::Register] {
+        let mut vm = Vm::new();
+        vm.set_backend(backend);
+        vm.set_jit_threshold(Some(10));
+        vm.run(compile(source).unwrap()).unwrap();
+        assert_eq!(vm.global("total"), Some(&Value::Int(8392)));
+        assert_eq!(vm.global("evens"), Some(&Value::Int(99)));
+        assert_eq!(vm.global("fibs"), Some(&Value::Int(610)));
+        assert_eq!(vm.global("sums"), Some(&Value::Int(1333300)));
+        // Calling a global can't be compiled
+        assert_eq!(vm.jit_compiled(), ["collatz", "even", "sum"]);
+
+        // Compiled code gives up on anything it doesn't handle, and the
+        // interpreter runs the call instead
+        let calls = "fun f(a, b) { a * b - a / b + a % b } for i in 1..20 { f(i, i); }";
+        meow::run(&mut vm, calls).unwrap();
+        assert_eq!(vm.jit_compiled(), ["collatz", "even", "f", "sum"]);
+        meow::run(&mut vm, "let float = f(2.5, 2);").unwrap();
+        assert_eq!(vm.global("float"), Some(&Value::Float(4.25)));
+        let error = vm.run(compile("f(1, 0);").unwrap()).unwrap_err();
+        assert_eq!(error.message, "division by zero");
+        let error = vm
+            .run(compile("f(9223372036854775807, 2);").unwrap())
+            .unwrap_err();
+        assert_eq!(error.message, "integer overflow: 9223372036854775807 * 2");
+        vm.set_big_ints(true);
+        meow::run(&mut vm, "let big = f(9223372036854775807, 2);").unwrap();
+        assert_eq!(
+            vm.global("big").unwrap().to_string(),
+            "13835058055282163712"
+        );
+
+        // A compiled loop still stops when the program is interrupted
+        meow::run(
+            &mut vm,
+            "fun spin(n) { while n > 0 { } n } for i in 0..20 { spin(0); }",
+        )
+        .unwrap();
+        assert!(vm.jit_compiled().contains(&"spin"));
+        let handle = vm.interrupt_handle();
+        let interrupter = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            handle.interrupt();
+        });
+        let error = vm.run(compile("spin(1);").unwrap()).unwrap_err();
+        assert_eq!(error.kind, RuntimeErrorKind::Interrupted);
+        interrupter.join().unwrap();
+    }
+
+    // Native code isn't limited by fuel, so functions aren't run natively
+    // while it is
+    let mut vm = Vm::with_fuel(1_000_000);
+    vm.set_jit_threshold(Some(10));
+    vm.run(compile(source).unwrap()).unwrap();
+    assert!(vm.jit_compiled().is_empty());
+
+    let mut vm = Vm::new();
+    vm.set_jit_threshold(None);
+    vm.run(compile(source).unwrap()).unwrap();
+    assert!(vm.jit_compiled().is_empty());
+}
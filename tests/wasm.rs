@@ -0,0 +1,185 @@
+use meow::compile_wasm;
+use wasmi::{core::TrapCode, Engine, Instance, Linker, Module, Store, Val};
+use wasmparser::Validator;
+
+/// Compile `source`, returning the messages of its diagnostics.
+fn errors(source: &str) -> Vec<String> {
+    compile_wasm(source)
+        .unwrap_err()
+        .into_iter()
+        .map(|diagnostic| diagnostic.message)
+        .collect()
+}
+
+/// A compiled module, instantiated with its top level run.
+struct Program {
+    store: Store<()>,
+    instance: Instance,
+}
+
+impl Program {
+    /// Compile `source`, validate the module, and instantiate it.
+    fn new(source: &str) -> Self {
+        let module = compile_wasm(source).unwrap();
+        Validator::new().validate_all(&module).unwrap();
+
+        let engine = Engine::default();
+        let module = Module::new(&engine, &module).unwrap();
+        let mut store = Store::new(&engine, ());
+        let instance = Linker::new(&engine)
+            .instantiate(&mut store, &module)
+            .unwrap()
+            .start(&mut store)
+            .unwrap();
+        Self { store, instance }
+    }
+
+    /// Return the value of the exported global `name`.
+    fn global(&self, name: &str) -> i64 {
+        let global = self.instance.get_global(&self.store, name).unwrap();
+        global.get(&self.store).i64().unwrap()
+    }
+
+    /// Call the exported function `name` with `args`, returning its result
+    /// or the trap it ran into.
+    fn call(&mut self, name: &str, args: &[i64]) -> Result<i64, TrapCode> {
+        let function = self.instance.get_func(&self.store, name).unwrap();
+        let args: Vec<Val> = args.iter().map(|&arg| Val::I64(arg)).collect();
+        let mut result = [Val::I64(0)];
+        function
+            .call(&mut self.store, &args, &mut result)
+            .map_err(|error| error.as_trap_code().unwrap())?;
+        Ok(result[0].i64().unwrap())
+    }
+}
+
+#[test]
+fn module() {
+    let mut program = Program::new(
+        "fun fib(n) { if n < 2 { n } else { fib(n - 1) + fib(n - 2) } }
+let mut total = 0;
+for i in 0..10 { if i > 2 && i != 5 { total += fib(i); } }
+let done = total > 100;",
+    );
+
+    // Functions and globals are exported by name, and the top level runs
+    // when the module is instantiated
+    assert_eq!(program.global("total"), 81);
+    assert_eq!(program.global("done"), 0);
+    assert_eq!(program.call("fib", &[20]), Ok(6765));
+}
+
+#[test]
+fn programs_run() {
+    let mut program = Program::new(
+        "fun gcd(a, b) { while b != 0 { let t = b; b = a - a / b * b; a = t; } a }
+fun sign(x) { if x < 0 { -1 } else if x == 0 { 0 } else { 1 } }
+fun sum(n) { let mut total = 0; for i in 1..=n { total += i; } total }
+fun even(n) { n / 2 * 2 == n }
+let g = gcd(1071, 462);
+let flags = (true as int) * 10 + (!even(3)) as int;",
+    );
+    assert_eq!(program.global("g"), 21);
+    assert_eq!(program.global("flags"), 11);
+    assert_eq!(program.call("sign", &[-7]), Ok(-1));
+    assert_eq!(program.call("sign", &[0]), Ok(0));
+    assert_eq!(program.call("sum", &[100]), Ok(5050));
+    assert_eq!(program.call("even", &[10]), Ok(1));
+
+    // Division by zero traps, like it fails in the VM
+    let mut program = Program::new("fun div(a, b) { a / b } fun rem(a, b) { a % b }");
+    assert_eq!(program.call("rem", &[-7, 3]), Ok(-1));
+    assert_eq!(program.call("rem", &[i64::MIN, -1]), Ok(0));
+    assert_eq!(
+        program.call("rem", &[1, 0]),
+        Err(TrapCode::IntegerDivisionByZero)
+    );
+    assert_eq!(program.call("div", &[7, 2]), Ok(3));
+    assert_eq!(program.call("div", &[-7, 2]), Ok(-3));
+    assert_eq!(
+        program.call("div", &[1, 0]),
+        Err(TrapCode::IntegerDivisionByZero)
+    );
+    assert_eq!(
+        program.call("div", &[i64::MIN, -1]),
+        Err(TrapCode::IntegerOverflow)
+    );
+}
+
+#[test]
+fn errors_are_reported() {
+    assert_eq!(
+        errors("let x = 1 + true;"),
+        ["unsupported operand types for `+`: int and bool"]
+    );
+    assert_eq!(
+        errors("fun f(a) { a } let x = f(1, 2);"),
+        ["`f` expects 1 argument, but 2 were given"]
+    );
+    assert_eq!(
+        errors("let s = \"cat\"; let f = 1.5;"),
+        [
+            "strings can't be compiled to WebAssembly yet",
+            "floats can't be compiled to WebAssembly yet"
+        ]
+    );
+    assert_eq!(
+        errors("let x = (1 < 2) as int + 1; let y = {} as int; let z = 1 as float;"),
+        [
+            "cannot cast unit to int",
+            "floats can't be compiled to WebAssembly yet"
+        ]
+    );
+    // Parameter types are inferred from every call
+    assert_eq!(
+        errors("fun f(a) { a + 1 } f(1); f(true);"),
+        ["`f` is called with both int and bool as argument 1"]
+    );
+}
+
+#[test]
+fn overflow_traps() {
+    let mut program = Program::new(
+        "fun add(a, b) { a + b }
+fun sub(a, b) { a - b }
+fun mul(a, b) { a * b }
+fun neg(a) { -a }
+fun bump(a) { a += 1; a }
+fun count(a) { let mut n = 0; for i in a..=9223372036854775807 { n += 1; } n }",
+    );
+    let max = i64::MAX;
+    let min = i64::MIN;
+    let overflow = Err(TrapCode::UnreachableCodeReached);
+
+    assert_eq!(program.call("add", &[max - 1, 1]), Ok(max));
+    assert_eq!(program.call("add", &[min, -1]), overflow);
+    assert_eq!(program.call("add", &[max, 1]), overflow);
+    assert_eq!(program.call("sub", &[min + 1, 1]), Ok(min));
+    assert_eq!(program.call("sub", &[min, 1]), overflow);
+    assert_eq!(program.call("sub", &[0, min]), overflow);
+    assert_eq!(program.call("sub", &[-1, min]), Ok(max));
+    assert_eq!(program.call("mul", &[max, -1]), Ok(-max));
+    assert_eq!(program.call("mul", &[0, min]), Ok(0));
+    assert_eq!(program.call("mul", &[-1, min]), overflow);
+    assert_eq!(program.call("mul", &[min, -1]), overflow);
+    assert_eq!(program.call("mul", &[1 << 32, 1 << 31]), overflow);
+    assert_eq!(program.call("mul", &[-(1 << 32), 1 << 31]), Ok(min));
+    assert_eq!(program.call("neg", &[min + 1]), Ok(max));
+    assert_eq!(program.call("neg", &[min]), overflow);
+    assert_eq!(program.call("bump", &[max]), overflow);
+
+    // Counting up to the largest int stops there rather than wrapping
+    assert_eq!(program.call("count", &[max - 2]), Ok(3));
+
+    // The top level traps too, so the module can't be instantiated
+    let module = compile_wasm("let x = 9223372036854775807 + 1;").unwrap();
+    let engine = Engine::default();
+    let module = Module::new(&engine, &module).unwrap();
+    let mut store = Store::new(&engine, ());
+    let error = Linker::new(&engine)
+        .instantiate(&mut store, &module)
+        .unwrap()
+        .start(&mut store)
+        .unwrap_err();
+    assert_eq!(error.as_trap_code(), Some(TrapCode::UnreachableCodeReached));
+}